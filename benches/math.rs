@@ -0,0 +1,170 @@
+//! Hot-path math benchmarks
+//!
+//! Run with `cargo bench`. Each group sweeps reserves from small pools up to
+//! u64-sized reserves, since the cost of the wide-integer paths depends on
+//! operand magnitude.
+use {
+    criterion::{BenchmarkId, Criterion, criterion_group, criterion_main},
+    curve::{
+        curve::{
+            CurveCalculator, FeeConfig, FeeRate, Fees, Reserves, RoundDirection, SwapParams,
+            Token0Amount, Token1Amount, TradeDirection,
+        },
+        router::{self, Hop},
+        state::PoolState,
+    },
+    std::hint::black_box,
+};
+
+const RESERVES: [(&str, u128); 4] = [
+    ("1e6", 1_000_000),
+    ("1e12", 1_000_000_000_000),
+    ("1e18", 1_000_000_000_000_000_000),
+    ("u64::MAX", u64::MAX as u128),
+];
+
+const TRADE_FEE_RATE: u64 = 2_500;
+const PROTOCOL_FEE_RATE: u64 = 120_000;
+
+fn fee_config() -> FeeConfig {
+    FeeConfig::new(
+        FeeRate::checked(TRADE_FEE_RATE).unwrap(),
+        FeeRate::checked(PROTOCOL_FEE_RATE).unwrap(),
+    )
+}
+
+fn swap_quotes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("swap");
+    let fee_config = fee_config();
+    for (name, reserve) in RESERVES {
+        // a 0.1% trade against a balanced pool
+        let params = SwapParams::new(
+            TradeDirection::ZeroForOne,
+            reserve / 1_000,
+            Reserves::new(Token0Amount(reserve), Token1Amount(reserve)),
+            fee_config,
+        );
+        group.bench_with_input(
+            BenchmarkId::new("base_input", name),
+            &params,
+            |b, params| b.iter(|| CurveCalculator::swap_base_input(black_box(params))),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("base_output", name),
+            &params,
+            |b, params| b.iter(|| CurveCalculator::swap_base_output(black_box(params))),
+        );
+    }
+    group.finish();
+}
+
+fn lp_conversions(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lp_tokens_to_trading_tokens");
+    for (name, reserve) in RESERVES {
+        let lp_token_supply = reserve;
+        let lp_token_amount = lp_token_supply / 3;
+        for round_direction in [RoundDirection::Floor, RoundDirection::Ceiling] {
+            let id = BenchmarkId::new(format!("{round_direction:?}"), name);
+            group.bench_with_input(id, &reserve, |b, &reserve| {
+                b.iter(|| {
+                    CurveCalculator::lp_tokens_to_trading_tokens(
+                        black_box(lp_token_amount),
+                        black_box(lp_token_supply),
+                        black_box(reserve),
+                        black_box(reserve / 2),
+                        round_direction,
+                    )
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+fn fees(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fees");
+    for (name, amount) in RESERVES {
+        group.bench_with_input(
+            BenchmarkId::new("trading_fee", name),
+            &amount,
+            |b, &amount| b.iter(|| Fees::trading_fee(black_box(amount), black_box(TRADE_FEE_RATE))),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("protocol_fee", name),
+            &amount,
+            |b, &amount| {
+                b.iter(|| Fees::protocol_fee(black_box(amount), black_box(PROTOCOL_FEE_RATE)))
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("calculate_pre_fee_amount", name),
+            &amount,
+            |b, &amount| {
+                b.iter(|| {
+                    Fees::calculate_pre_fee_amount(black_box(amount), black_box(TRADE_FEE_RATE))
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn routes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("quote_route_exact_in");
+    for (name, reserve) in RESERVES {
+        let hop = Hop {
+            reserves: Reserves::new(Token0Amount(reserve), Token1Amount(reserve)),
+            fee_config: fee_config(),
+            trade_direction: TradeDirection::ZeroForOne,
+        };
+        for hops in [2, 3] {
+            let route = vec![hop; hops];
+            group.bench_with_input(
+                BenchmarkId::new(format!("{hops}_hops"), name),
+                &route,
+                |b, route| {
+                    b.iter(|| router::quote_route_exact_in(black_box(route), reserve / 1_000))
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+fn split_orders(c: &mut Criterion) {
+    let mut group = c.benchmark_group("split_order");
+    for (name, reserve) in RESERVES {
+        // three pools of the pair, each half as deep as the last
+        let pools: Vec<_> = [reserve, reserve / 2, reserve / 4]
+            .into_iter()
+            .map(|reserve| PoolState {
+                fee_config: fee_config(),
+                token_0_reserve: reserve as u64,
+                token_1_reserve: reserve as u64,
+                ..PoolState::default()
+            })
+            .collect();
+        let total_amount_in = (reserve / 1_000) as u64;
+        group.bench_with_input(BenchmarkId::new("3_pools", name), &pools, |b, pools| {
+            b.iter(|| {
+                router::split_order(
+                    0,
+                    TradeDirection::ZeroForOne,
+                    black_box(pools),
+                    black_box(total_amount_in),
+                )
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    swap_quotes,
+    lp_conversions,
+    fees,
+    routes,
+    split_orders
+);
+criterion_main!(benches);
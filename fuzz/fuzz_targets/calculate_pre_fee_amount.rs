@@ -0,0 +1,33 @@
+#![no_main]
+
+use {
+    arbitrary::Arbitrary,
+    curve::curve::{FEE_RATE_DENOMINATOR_VALUE, Fees},
+    libfuzzer_sys::fuzz_target,
+};
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    post_fee_amount: u128,
+    trade_fee_rate: u64,
+}
+
+fuzz_target!(|input: Input| {
+    let Some(pre_fee_amount) =
+        Fees::calculate_pre_fee_amount(input.post_fee_amount, input.trade_fee_rate)
+    else {
+        return;
+    };
+    if input.trade_fee_rate >= FEE_RATE_DENOMINATOR_VALUE {
+        return;
+    }
+    let Some(trade_fee) = Fees::trading_fee(pre_fee_amount, input.trade_fee_rate) else {
+        return;
+    };
+    // charging the fee on the grossed-up amount leaves at least the net amount
+    assert!(pre_fee_amount >= input.post_fee_amount);
+    assert!(
+        pre_fee_amount - trade_fee >= input.post_fee_amount,
+        "{input:?}"
+    );
+});
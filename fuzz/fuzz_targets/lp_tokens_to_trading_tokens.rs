@@ -0,0 +1,62 @@
+#![no_main]
+
+use {
+    arbitrary::Arbitrary,
+    curve::{
+        curve::{CurveCalculator, RoundDirection},
+        utils::U256,
+    },
+    libfuzzer_sys::fuzz_target,
+};
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    lp_token_amount: u128,
+    lp_token_supply: u128,
+    swap_token_0_amount: u128,
+    swap_token_1_amount: u128,
+    round_up: bool,
+}
+
+fuzz_target!(|input: Input| {
+    let round_direction = if input.round_up {
+        RoundDirection::Ceiling
+    } else {
+        RoundDirection::Floor
+    };
+    let Some(result) = CurveCalculator::lp_tokens_to_trading_tokens(
+        input.lp_token_amount,
+        input.lp_token_supply,
+        input.swap_token_0_amount,
+        input.swap_token_1_amount,
+        round_direction,
+    ) else {
+        return;
+    };
+    // the pool never pays out more than the LP share of each reserve, and
+    // never charges more than one unit above it; amounts worth less than one
+    // unit are returned as zero when rounding up, for the caller to reject
+    for (amount, reserve) in [
+        (result.token_0_amount, input.swap_token_0_amount),
+        (result.token_1_amount, input.swap_token_1_amount),
+    ] {
+        let exact_numerator = U256::from(input.lp_token_amount) * U256::from(reserve);
+        let scaled = U256::from(amount) * U256::from(input.lp_token_supply);
+        match round_direction {
+            RoundDirection::Floor => assert!(scaled <= exact_numerator, "{input:?}"),
+            RoundDirection::Ceiling if amount == 0 => {
+                assert!(
+                    exact_numerator < U256::from(input.lp_token_supply),
+                    "{input:?}"
+                )
+            }
+            RoundDirection::Ceiling => {
+                assert!(scaled >= exact_numerator, "{input:?}");
+                assert!(
+                    scaled < exact_numerator + U256::from(input.lp_token_supply),
+                    "{input:?}"
+                );
+            }
+        }
+    }
+});
@@ -0,0 +1,61 @@
+#![no_main]
+
+use {
+    arbitrary::Arbitrary,
+    curve::{
+        curve::{CurveCalculator, FeeConfig, FeeRate, Reserves, SwapParams, TradeDirection},
+        utils::U256,
+    },
+    libfuzzer_sys::fuzz_target,
+};
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    source_amount: u128,
+    swap_source_amount: u128,
+    swap_destination_amount: u128,
+    trade_fee_rate: u64,
+    protocol_fee_rate: u64,
+}
+
+fuzz_target!(|input: Input| {
+    let Input {
+        source_amount,
+        swap_source_amount,
+        swap_destination_amount,
+        trade_fee_rate,
+        protocol_fee_rate,
+    } = input;
+    // the strict math unwraps instead of returning None on these overflows;
+    // empty pools are rejected before it runs
+    if source_amount.checked_mul(swap_destination_amount).is_none()
+        || swap_source_amount.checked_add(source_amount).is_none()
+    {
+        return;
+    }
+    let (Some(trade_fee_rate), Some(protocol_fee_rate)) = (
+        FeeRate::checked(trade_fee_rate),
+        FeeRate::checked(protocol_fee_rate),
+    ) else {
+        return;
+    };
+    let params = SwapParams::new(
+        TradeDirection::ZeroForOne,
+        source_amount,
+        Reserves::from_source_and_destination(
+            TradeDirection::ZeroForOne,
+            swap_source_amount,
+            swap_destination_amount,
+        ),
+        FeeConfig::new(trade_fee_rate, protocol_fee_rate),
+    );
+    let Some(result) = CurveCalculator::swap_base_input(&params) else {
+        return;
+    };
+    assert_eq!(result.source_amount_swapped, source_amount);
+    assert!(result.destination_amount_swapped <= swap_destination_amount);
+    let before = U256::from(swap_source_amount) * U256::from(swap_destination_amount);
+    let after =
+        U256::from(result.new_swap_source_amount) * U256::from(result.new_swap_destination_amount);
+    assert!(after >= before, "invariant decreased: {input:?}");
+});
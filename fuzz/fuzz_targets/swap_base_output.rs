@@ -0,0 +1,74 @@
+#![no_main]
+
+use {
+    arbitrary::Arbitrary,
+    curve::{
+        curve::{
+            CurveCalculator, CurveError, FeeConfig, FeeRate, MAX_TRADE_FEE_RATE, Reserves,
+            SwapParams, TradeDirection,
+        },
+        utils::U256,
+    },
+    libfuzzer_sys::fuzz_target,
+};
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    destination_amount: u128,
+    swap_source_amount: u128,
+    swap_destination_amount: u128,
+    trade_fee_rate: u64,
+    protocol_fee_rate: u64,
+}
+
+fuzz_target!(|input: Input| {
+    let Input {
+        destination_amount,
+        swap_source_amount,
+        swap_destination_amount,
+        trade_fee_rate,
+        protocol_fee_rate,
+    } = input;
+    let (Some(trade_fee_rate), Some(protocol_fee_rate)) = (
+        FeeRate::checked(trade_fee_rate),
+        FeeRate::checked(protocol_fee_rate),
+    ) else {
+        return;
+    };
+    let params = SwapParams::new(
+        TradeDirection::ZeroForOne,
+        destination_amount,
+        Reserves::from_source_and_destination(
+            TradeDirection::ZeroForOne,
+            swap_source_amount,
+            swap_destination_amount,
+        ),
+        FeeConfig::new(trade_fee_rate, protocol_fee_rate),
+    );
+    // exact-out quotes are total: out-of-range inputs are errors, not panics
+    let result = CurveCalculator::try_swap_base_output(&params);
+    if trade_fee_rate > MAX_TRADE_FEE_RATE {
+        assert_eq!(result, Err(CurveError::FeeConfigInvalid), "{input:?}");
+        return;
+    }
+    if destination_amount == 0 {
+        assert_eq!(result, Err(CurveError::ZeroTradeAmount), "{input:?}");
+        return;
+    }
+    if swap_source_amount == 0 || swap_destination_amount == 0 {
+        assert_eq!(result, Err(CurveError::EmptyPool), "{input:?}");
+        return;
+    }
+    if destination_amount >= swap_destination_amount {
+        assert_eq!(result, Err(CurveError::InsufficientLiquidity), "{input:?}");
+        return;
+    }
+    let Ok(result) = result else {
+        return;
+    };
+    assert_eq!(result.destination_amount_swapped, destination_amount);
+    let before = U256::from(swap_source_amount) * U256::from(swap_destination_amount);
+    let after =
+        U256::from(result.new_swap_source_amount) * U256::from(result.new_swap_destination_amount);
+    assert!(after >= before, "invariant decreased: {input:?}");
+});
@@ -0,0 +1,178 @@
+//! Optimal arbitrage
+//!
+//! Solves for the trade that moves a constant product pool's marginal price,
+//! net of the trade fee, to a target. With `x` and `y` the source and
+//! destination reserves and `γ = 1 - fee`, an exact-input trade of `dx` has
+//! marginal output `γ * x * y / (x + γ * dx)^2`, so the input reaching a
+//! marginal price `p` is `(sqrt(γ * x * y / p) - x) / γ`.
+use crate::{
+    curve::{
+        amount::Reserves,
+        calculator::TradeDirection,
+        fees::{FEE_RATE_DENOMINATOR_VALUE, FeeConfig},
+    },
+    utils::{IntegerSquareRoot, PriceQ64, Q64_RESOLUTION, U256, price::to_u128},
+};
+
+/// A trade moving a pool to a target price
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OptimalTrade {
+    /// Direction to trade in
+    pub trade_direction: TradeDirection,
+    /// Amount of source token to pay in, including fees
+    pub source_amount: u128,
+}
+
+/// The exact-input trade that moves the post-fee marginal price of token 0,
+/// quoted in token 1, as close to `target_price` as possible without
+/// passing it.
+///
+/// Selling token 0 lowers the price received for the next unit of token 0,
+/// buying it raises the price paid, and the fee keeps a band around the spot
+/// price where neither trade is worth making. Returns `None` if the target
+/// is already inside that band, if a reserve or the target is zero, if the
+/// trade fee is 100%, or if an intermediate value overflows.
+pub fn optimal_trade_to_price(
+    reserves: Reserves,
+    fee_config: FeeConfig,
+    target_price: PriceQ64,
+) -> Option<OptimalTrade> {
+    let token_0 = U256::from(reserves.token_0.get());
+    let token_1 = U256::from(reserves.token_1.get());
+    let target = U256::from(target_price.raw());
+    let denominator = U256::from(FEE_RATE_DENOMINATOR_VALUE);
+    let net_of_fee = denominator.checked_sub(U256::from(fee_config.trade_fee_rate.get()))?;
+    if token_0.is_zero() || token_1.is_zero() || target.is_zero() || net_of_fee.is_zero() {
+        return None;
+    }
+    let invariant = token_0.checked_mul(token_1)?.checked_mul(net_of_fee)?;
+
+    // selling token 0 until y' = p * x' with x' = x + γ * dx, ie.
+    // x' = sqrt(γ * x * y / p)
+    let source_after =
+        ((invariant << Q64_RESOLUTION) / denominator.checked_mul(target)?).sqrt_floor();
+    let (trade_direction, source_before, source_after) = if source_after > token_0 {
+        (TradeDirection::ZeroForOne, token_0, source_after)
+    } else {
+        // buying token 0 until y' = p * x' with y' = y + γ * dy, ie.
+        // y' = sqrt(p * γ * x * y)
+        let source_after =
+            (invariant.checked_mul(target)? / (denominator << Q64_RESOLUTION)).sqrt_floor();
+        if source_after <= token_1 {
+            return None;
+        }
+        (TradeDirection::OneForZero, token_1, source_after)
+    };
+    let source_amount = (source_after - source_before).checked_mul(denominator)? / net_of_fee;
+    Some(OptimalTrade {
+        trade_direction,
+        source_amount: to_u128(source_amount)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::curve::{calculator::CurveCalculator, fees::FeeRate},
+        proptest::prelude::*,
+    };
+
+    #[test]
+    fn trade_to_price_without_fees() {
+        let pool = Reserves::from_raw(1_000_000, 1_000_000);
+        let fee_config = FeeConfig::default();
+        assert_eq!(
+            optimal_trade_to_price(pool, fee_config, PriceQ64::from_ratio(1, 4).unwrap()),
+            Some(OptimalTrade {
+                trade_direction: TradeDirection::ZeroForOne,
+                source_amount: 1_000_000,
+            })
+        );
+        assert_eq!(
+            optimal_trade_to_price(pool, fee_config, PriceQ64::from_integer(4)),
+            Some(OptimalTrade {
+                trade_direction: TradeDirection::OneForZero,
+                source_amount: 1_000_000,
+            })
+        );
+        assert_eq!(
+            optimal_trade_to_price(pool, fee_config, PriceQ64::ONE),
+            None
+        );
+        assert_eq!(
+            optimal_trade_to_price(pool, fee_config, PriceQ64::ZERO),
+            None
+        );
+    }
+
+    #[test]
+    fn no_trade_inside_fee_band() {
+        let pool = Reserves::from_raw(1_000_000, 1_000_000);
+        let fee_config = FeeConfig::new(FeeRate::from_percent(1).unwrap(), FeeRate::ZERO);
+        // within 1% of the spot price neither direction pays for the fee
+        let inside = PriceQ64::from_ratio(995, 1_000).unwrap();
+        assert_eq!(optimal_trade_to_price(pool, fee_config, inside), None);
+        let inside = PriceQ64::from_ratio(1_005, 1_000).unwrap();
+        assert_eq!(optimal_trade_to_price(pool, fee_config, inside), None);
+        let all_fees = FeeConfig::new(FeeRate::MAX, FeeRate::ZERO);
+        assert_eq!(
+            optimal_trade_to_price(pool, all_fees, PriceQ64::from_integer(2)),
+            None
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn trade_reaches_target_without_passing_it(
+            token_0 in 1_000..1u128 << 48,
+            token_1 in 1_000..1u128 << 48,
+            target_bps in 1..100_000u64,
+            trade_fee_rate in 0..100_000u64,
+        ) {
+            let pool = Reserves::from_raw(token_0, token_1);
+            let fee_config = FeeConfig::new(FeeRate::checked(trade_fee_rate).unwrap(), FeeRate::ZERO);
+            let spot = PriceQ64::from_reserves(token_0, token_1).unwrap();
+            let target = PriceQ64::from_raw((U256::from(spot.raw()) * target_bps / 10_000).as_u128());
+            prop_assume!(target != PriceQ64::ZERO);
+            if let Some(trade) = optimal_trade_to_price(pool, fee_config, target) {
+                let result = CurveCalculator::swap_exact_in(
+                    trade.trade_direction,
+                    trade.source_amount,
+                    pool,
+                    fee_config,
+                )
+                .unwrap();
+                // the curve's reserves after the trade, leaving the fee out
+                let net_source = result.new_swap_source_amount - result.trade_fee;
+                let destination = result.new_swap_destination_amount;
+                let net = U256::from(FEE_RATE_DENOMINATOR_VALUE - trade_fee_rate);
+                let denominator = U256::from(FEE_RATE_DENOMINATOR_VALUE);
+                let target = U256::from(target.raw());
+                // marginal price of token 0 after the trade, as a fraction
+                // of the Q64 target
+                let (price_numerator, price_denominator) = match trade.trade_direction {
+                    TradeDirection::ZeroForOne => (
+                        U256::from(destination) * net,
+                        U256::from(net_source) * denominator,
+                    ),
+                    TradeDirection::OneForZero => (
+                        U256::from(net_source) * denominator,
+                        U256::from(destination) * net,
+                    ),
+                };
+                let price = (price_numerator << Q64_RESOLUTION) / price_denominator;
+                match trade.trade_direction {
+                    TradeDirection::ZeroForOne => {
+                        prop_assert!(price >= target * 9_999 / 10_000);
+                        prop_assert!(price <= target * 10_100 / 10_000);
+                    }
+                    TradeDirection::OneForZero => {
+                        prop_assert!(price <= target * 10_001 / 10_000);
+                        prop_assert!(price >= target * 9_900 / 10_000);
+                    }
+                }
+            }
+        }
+    }
+}
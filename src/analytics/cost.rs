@@ -0,0 +1,118 @@
+//! Execution cost attribution
+//!
+//! Splits the shortfall of a swap against the pre-trade spot price into what
+//! the trader paid in fees, what the curve's slope cost them, and what was
+//! lost to rounding, each valued in the destination token.
+use crate::{
+    curve::calculator::{RoundDirection, TradeDirection},
+    quote::SwapQuote,
+};
+
+/// Where the gap between spot and realized execution of a swap went, in
+/// destination token
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CostBreakdown {
+    /// Output the whole input would receive at the pre-trade spot price
+    pub spot_amount_out: u128,
+    /// `spot_amount_out - amount_out`, the total cost of execution
+    pub total_cost: u128,
+    /// The trade fee, including the protocol's share, valued at spot
+    pub fee_cost: u128,
+    /// Source tokens retained by rounding, valued at spot
+    pub rounding_cost: u128,
+    /// The rest of the total cost, due to the trade moving the price along
+    /// the curve
+    pub price_impact_cost: u128,
+}
+
+/// Attribute the execution cost of `quote`.
+///
+/// Amounts are valued at `quote.price_before`, rounded down. Price impact is
+/// the remainder, so the three components sum to `total_cost`; should the
+/// rounding of the spot valuation leave less than the fee and rounding
+/// costs, the price impact is zero. Returns `None` if the spot price is zero
+/// or a valuation overflows.
+pub fn cost_breakdown(quote: &SwapQuote) -> Option<CostBreakdown> {
+    let at_spot = |amount| match quote.trade_direction {
+        TradeDirection::ZeroForOne => quote.price_before.mul_amount(amount, RoundDirection::Floor),
+        TradeDirection::OneForZero => quote.price_before.div_amount(amount, RoundDirection::Floor),
+    };
+    let spot_amount_out = at_spot(quote.amount_in)?;
+    let total_cost = spot_amount_out.saturating_sub(quote.amount_out);
+    let fee_cost = at_spot(quote.trade_fee)?;
+    let rounding_cost = at_spot(quote.rounding_dust)?;
+    Some(CostBreakdown {
+        spot_amount_out,
+        total_cost,
+        fee_cost,
+        rounding_cost,
+        price_impact_cost: total_cost
+            .saturating_sub(fee_cost)
+            .saturating_sub(rounding_cost),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            curve::{
+                amount::{Reserves, Token0Amount, Token1Amount},
+                fees::{FeeConfig, FeeRate},
+            },
+            quote::{quote_exact_in, quote_exact_out},
+        },
+        proptest::prelude::*,
+    };
+
+    #[test]
+    fn attribute_exact_in_cost() {
+        let reserves = Reserves::new(Token0Amount(1_000_000), Token1Amount(4_000_000));
+        let fee_config = FeeConfig::new(FeeRate::from_bps(30).unwrap(), FeeRate::ZERO);
+        let quote =
+            quote_exact_in(TradeDirection::ZeroForOne, 10_000, reserves, fee_config, 0).unwrap();
+        let cost = cost_breakdown(&quote).unwrap();
+        assert_eq!(cost.spot_amount_out, 40_000);
+        assert_eq!(cost.total_cost, 40_000 - quote.amount_out);
+        assert_eq!(cost.fee_cost, 120);
+        assert_eq!(cost.rounding_cost, 4 * quote.rounding_dust);
+        assert_eq!(
+            cost.fee_cost + cost.rounding_cost + cost.price_impact_cost,
+            cost.total_cost
+        );
+        // about 1% of the output for a trade of 1% of the reserve
+        assert!((390..=400).contains(&cost.price_impact_cost));
+
+        // the other direction values costs by dividing by the price
+        let quote =
+            quote_exact_out(TradeDirection::OneForZero, 1_000, reserves, fee_config, 0).unwrap();
+        let cost = cost_breakdown(&quote).unwrap();
+        assert_eq!(cost.spot_amount_out, quote.amount_in / 4);
+        assert_eq!(cost.fee_cost, quote.trade_fee / 4);
+    }
+
+    proptest! {
+        #[test]
+        fn components_sum_to_total(
+            reserve_0 in 1_000u128..u64::MAX as u128,
+            reserve_1 in 1_000u128..u64::MAX as u128,
+            amount in 1u128..u32::MAX as u128,
+            trade_fee_bps in 0u16..1_000,
+        ) {
+            let reserves = Reserves::new(Token0Amount(reserve_0), Token1Amount(reserve_1));
+            let fee_config = FeeConfig::new(FeeRate::from_bps(trade_fee_bps).unwrap(), FeeRate::ZERO);
+            let quote = quote_exact_in(TradeDirection::ZeroForOne, amount, reserves, fee_config, 0)
+                .unwrap();
+            let cost = cost_breakdown(&quote).unwrap();
+            let explained = cost.fee_cost + cost.rounding_cost;
+            if explained <= cost.total_cost {
+                prop_assert_eq!(explained + cost.price_impact_cost, cost.total_cost);
+            } else {
+                prop_assert_eq!(cost.price_impact_cost, 0);
+            }
+            prop_assert!(cost.fee_cost <= cost.total_cost + 1);
+            prop_assert_eq!(cost.total_cost, cost.spot_amount_out.saturating_sub(quote.amount_out));
+        }
+    }
+}
@@ -0,0 +1,268 @@
+//! Liquidity depth
+//!
+//! Samples the output of a pool along a ladder of input sizes, for charting
+//! slippage and for routers estimating how much flow a pool can absorb, and
+//! measures the `±X%` depth reported by listing and risk dashboards.
+use {
+    crate::{
+        curve::{
+            amount::Reserves,
+            calculator::{CurveCalculator, TradeDirection},
+            fees::FeeConfig,
+        },
+        utils::{IntegerSquareRoot, U256, price::to_u128},
+    },
+    alloc::vec::Vec,
+    borsh::{BorshDeserialize, BorshSerialize},
+};
+
+/// Denominator for `DepthCurveConfig::max_input_bps`
+pub const DEPTH_BPS_DENOMINATOR: u128 = 10_000;
+
+/// Shape of the input ladder sampled by `depth_curve`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DepthCurveConfig {
+    /// Number of evenly spaced inputs to sample
+    pub points: u16,
+    /// Largest input, in basis points of the source reserve
+    pub max_input_bps: u16,
+}
+
+impl Default for DepthCurveConfig {
+    /// 50 points up to 10% of the source reserve
+    fn default() -> Self {
+        Self {
+            points: 50,
+            max_input_bps: 1_000,
+        }
+    }
+}
+
+/// Output of the pool for one input size
+#[derive(Clone, Copy, Debug, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct DepthPoint {
+    /// Amount of source token paid in, including fees
+    pub amount_in: u128,
+    /// Amount of destination token received
+    pub amount_out: u128,
+}
+
+/// Amount out as a function of amount in, sampled in increasing order of
+/// input
+#[derive(Clone, Debug, Default, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct DepthCurve {
+    /// Sampled quotes, one per ladder step
+    pub points: Vec<DepthPoint>,
+}
+
+/// Quote exact-input swaps in `trade_direction` for each input on the ladder
+/// described by `config`.
+///
+/// Inputs are `source_reserve * max_input_bps * i / (10_000 * points)` for
+/// `i` in `1..=points`, rounded down. Returns `None` if any quote fails.
+pub fn depth_curve(
+    trade_direction: TradeDirection,
+    reserves: Reserves,
+    fee_config: FeeConfig,
+    config: DepthCurveConfig,
+) -> Option<DepthCurve> {
+    let (swap_source_amount, _) = reserves.source_and_destination(trade_direction);
+    let max_input =
+        swap_source_amount.checked_mul(config.max_input_bps.into())? / DEPTH_BPS_DENOMINATOR;
+    let steps = u128::from(config.points);
+    let points = (1..=steps)
+        .map(|step| {
+            let amount_in = max_input.checked_mul(step)? / steps;
+            let result =
+                CurveCalculator::swap_exact_in(trade_direction, amount_in, reserves, fee_config)?;
+            Some(DepthPoint {
+                amount_in,
+                amount_out: result.destination_amount_swapped,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(DepthCurve { points })
+}
+
+/// Amounts that move the spot price of token 0 by a given number of basis
+/// points
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MarketDepth {
+    /// Token 0 that can be bought before its price rises by the given bps,
+    /// rounded down
+    pub token_0_bought: u128,
+    /// Token 1 paid for `token_0_bought`, before fees, rounded up
+    pub token_1_paid: u128,
+    /// Token 0 that can be sold before its price falls by the given bps,
+    /// rounded down
+    pub token_0_sold: u128,
+    /// Token 1 received for `token_0_sold`, before fees, rounded down
+    pub token_1_received: u128,
+}
+
+// sqrt(reserve^2 * numerator / denominator), the reserve after the price
+// moves by a factor of denominator / numerator or its inverse
+fn scaled_reserve(
+    reserve: u128,
+    numerator: u128,
+    denominator: u128,
+    ceiling: bool,
+) -> Option<u128> {
+    let squared = U256::from(reserve)
+        .checked_mul(U256::from(reserve))?
+        .checked_mul(U256::from(numerator))?;
+    let denominator = U256::from(denominator);
+    let reserve = if ceiling {
+        ((squared + denominator - 1) / denominator).sqrt_ceil()
+    } else {
+        (squared / denominator).sqrt_floor()
+    };
+    to_u128(reserve)
+}
+
+/// Depth of the pool within `bps` basis points of the spot price of token 0,
+/// computed in closed form from the curve.
+///
+/// With `p = reserve_1 / reserve_0` moving to `p * (1 ± bps / 10_000)`, the
+/// reserves on the curve become `reserve_0 / sqrt(1 ± bps / 10_000)` and
+/// `reserve_1 * sqrt(1 ± bps / 10_000)`. Fees are excluded, so the amounts
+/// are the spot-price movement of the curve alone; each is rounded so the
+/// price moves by at most `bps`.
+///
+/// Returns `None` if a reserve is empty, `bps` is 10_000 or more (the price
+/// cannot fall by 100%), or an intermediate value overflows.
+pub fn depth_at(reserves: Reserves, bps: u16) -> Option<MarketDepth> {
+    let (reserve_0, reserve_1) = (reserves.token_0.get(), reserves.token_1.get());
+    let bps = u128::from(bps);
+    if reserve_0 == 0 || reserve_1 == 0 || bps >= DEPTH_BPS_DENOMINATOR {
+        return None;
+    }
+    let up = DEPTH_BPS_DENOMINATOR + bps;
+    let down = DEPTH_BPS_DENOMINATOR - bps;
+    Some(MarketDepth {
+        token_0_bought: reserve_0 - scaled_reserve(reserve_0, DEPTH_BPS_DENOMINATOR, up, true)?,
+        token_1_paid: scaled_reserve(reserve_1, up, DEPTH_BPS_DENOMINATOR, true)? - reserve_1,
+        token_0_sold: scaled_reserve(reserve_0, DEPTH_BPS_DENOMINATOR, down, false)? - reserve_0,
+        token_1_received: reserve_1 - scaled_reserve(reserve_1, down, DEPTH_BPS_DENOMINATOR, true)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            curve::{
+                amount::{Token0Amount, Token1Amount},
+                fees::FeeRate,
+            },
+            utils::PriceQ64,
+        },
+        proptest::prelude::*,
+    };
+
+    #[test]
+    fn default_ladder() {
+        let reserves = Reserves::new(Token0Amount(1_000_000), Token1Amount(4_000_000));
+        let fee_config = FeeConfig::new(FeeRate::from_bps(30).unwrap(), FeeRate::ZERO);
+        let curve = depth_curve(
+            TradeDirection::ZeroForOne,
+            reserves,
+            fee_config,
+            DepthCurveConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(curve.points.len(), 50);
+        assert_eq!(curve.points[0].amount_in, 2_000);
+        assert_eq!(curve.points[49].amount_in, 100_000);
+        // the average price worsens with size
+        for pair in curve.points.windows(2) {
+            assert!(pair[0].amount_out < pair[1].amount_out);
+            assert!(
+                pair[0].amount_out * pair[1].amount_in >= pair[1].amount_out * pair[0].amount_in
+            );
+        }
+        let bytes = borsh::to_vec(&curve).unwrap();
+        assert_eq!(DepthCurve::try_from_slice(&bytes).unwrap(), curve);
+
+        let reverse = depth_curve(
+            TradeDirection::OneForZero,
+            reserves,
+            fee_config,
+            DepthCurveConfig {
+                points: 4,
+                max_input_bps: 10_000,
+            },
+        )
+        .unwrap();
+        let amounts_in: Vec<_> = reverse.points.iter().map(|point| point.amount_in).collect();
+        assert_eq!(amounts_in, [1_000_000, 2_000_000, 3_000_000, 4_000_000]);
+        assert!(
+            reverse
+                .points
+                .iter()
+                .all(|point| point.amount_out < 1_000_000)
+        );
+    }
+
+    #[test]
+    fn empty_ladder() {
+        let reserves = Reserves::new(Token0Amount(1_000), Token1Amount(1_000));
+        let config = DepthCurveConfig {
+            points: 0,
+            ..DepthCurveConfig::default()
+        };
+        let curve = depth_curve(
+            TradeDirection::ZeroForOne,
+            reserves,
+            FeeConfig::default(),
+            config,
+        );
+        assert_eq!(curve, Some(DepthCurve::default()));
+    }
+
+    #[test]
+    fn depth_around_spot_price() {
+        let reserves = Reserves::new(Token0Amount(1_000_000), Token1Amount(4_000_000));
+        let depth = depth_at(reserves, 200).unwrap();
+        // 1e6 * (1 - 1 / sqrt(1.02)) and 1e6 * (1 / sqrt(0.98) - 1)
+        assert_eq!(depth.token_0_bought, 9_852);
+        assert_eq!(depth.token_0_sold, 10_152);
+        assert_eq!(depth.token_1_paid, 39_802);
+        assert_eq!(depth.token_1_received, 40_202);
+        assert_eq!(depth_at(reserves, 0).unwrap().token_0_sold, 0);
+        assert_eq!(depth_at(reserves, 10_000), None);
+        let empty = Reserves::new(Token0Amount(0), Token1Amount(4_000_000));
+        assert_eq!(depth_at(empty, 200), None);
+    }
+
+    proptest! {
+        #[test]
+        fn depth_moves_price_by_at_most_bps(
+            reserve_0 in 1_000u128..u64::MAX as u128,
+            reserve_1 in 1_000u128..u64::MAX as u128,
+            bps in 1u16..10_000,
+        ) {
+            let reserves = Reserves::new(Token0Amount(reserve_0), Token1Amount(reserve_1));
+            let depth = depth_at(reserves, bps).unwrap();
+            let spot = PriceQ64::from_reserves(reserve_0, reserve_1).unwrap();
+            let bps = u128::from(bps);
+
+            let bought = PriceQ64::from_reserves(
+                reserve_0 - depth.token_0_bought,
+                reserve_1 + depth.token_1_paid,
+            ).unwrap();
+            let ceiling = spot.raw() / DEPTH_BPS_DENOMINATOR * (DEPTH_BPS_DENOMINATOR + bps);
+            prop_assert!(bought >= spot);
+            prop_assert!(bought.raw() <= ceiling + spot.raw() / DEPTH_BPS_DENOMINATOR * 2);
+
+            let sold = PriceQ64::from_reserves(
+                reserve_0 + depth.token_0_sold,
+                reserve_1 - depth.token_1_received,
+            ).unwrap();
+            prop_assert!(sold <= spot);
+            prop_assert!(U256::from(depth.token_1_received) * U256::from(reserve_0)
+                <= U256::from(depth.token_0_sold) * U256::from(reserve_1));
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! LP yield
+//!
+//! Annualized yields are simple, not compounded: the yield over a window is
+//! scaled by the number of such windows in a 365 day year.
+use crate::{
+    curve::{amount::LpAmount, calculator::CurveCalculator, fees::FeeRate},
+    state::PoolState,
+    utils::{U256, price::to_u128},
+};
+
+/// Seconds in a 365 day year
+pub const SECONDS_PER_YEAR: u64 = 365 * 24 * 60 * 60;
+
+/// Denominator for yields expressed in basis points
+pub const YIELD_BPS_DENOMINATOR: u128 = 10_000;
+
+fn annualize_bps(numerator: U256, denominator: U256, window_duration: u64) -> Option<u128> {
+    let denominator = denominator.checked_mul(U256::from(window_duration))?;
+    if denominator.is_zero() {
+        return None;
+    }
+    let numerator = numerator
+        .checked_mul(U256::from(YIELD_BPS_DENOMINATOR))?
+        .checked_mul(U256::from(SECONDS_PER_YEAR))?;
+    to_u128(numerator / denominator)
+}
+
+/// Estimated LP APY in basis points, rounded down, from `volume_window`
+/// traded over `window_duration` seconds in a pool worth `pool_tvl`.
+///
+/// Volume and TVL must be denominated in the same token. The estimate is
+/// gross of the protocol's share of the trade fee. Returns `None` if the TVL
+/// or duration is zero.
+pub fn estimate_apy(
+    volume_window: u128,
+    trade_fee_rate: FeeRate,
+    pool_tvl: u128,
+    window_duration: u64,
+) -> Option<u128> {
+    let fees = trade_fee_rate.mul_floor(volume_window)?;
+    annualize_bps(U256::from(fees), U256::from(pool_tvl), window_duration)
+}
+
+fn liquidity_per_lp_q64(state: &PoolState) -> Option<U256> {
+    let lp_supply = LpAmount::from(state.lp_supply);
+    CurveCalculator::value_per_lp_token(state.reserves(), lp_supply).map(U256::from)
+}
+
+/// Realized LP fee APY in basis points, rounded down, between two snapshots
+/// of the same pool taken `window_duration` seconds apart.
+///
+/// Measures the growth of `sqrt(token_0 * token_1)` per LP token, which
+/// swaps increase only through fees and deposits and withdrawals leave
+/// unchanged, so the result is independent of price moves and liquidity
+/// flows. Returns `None` if either snapshot has no LP supply or the
+/// duration is zero, and `Some(0)` if the value per LP token decreased.
+pub fn realized_fee_yield(
+    before: &PoolState,
+    after: &PoolState,
+    window_duration: u64,
+) -> Option<u128> {
+    let value_before = liquidity_per_lp_q64(before)?;
+    let value_after = liquidity_per_lp_q64(after)?;
+    annualize_bps(
+        value_after.saturating_sub(value_before),
+        value_before,
+        window_duration,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::curve::{fees::FeeConfig, swap_params::SwapParams},
+    };
+
+    #[test]
+    fn estimate_apy_from_volume() {
+        let fee = FeeRate::from_bps(30).unwrap();
+        // a day of volume equal to the TVL earns 30 bps
+        let day = 24 * 60 * 60;
+        assert_eq!(estimate_apy(1_000_000, fee, 1_000_000, day), Some(30 * 365));
+        assert_eq!(
+            estimate_apy(1_000_000, fee, 1_000_000, SECONDS_PER_YEAR),
+            Some(30)
+        );
+        assert_eq!(estimate_apy(1_000_000, fee, 0, day), None);
+        assert_eq!(estimate_apy(1_000_000, fee, 1_000_000, 0), None);
+        assert_eq!(estimate_apy(0, fee, 1_000_000, day), Some(0));
+    }
+
+    #[test]
+    fn realized_yield_from_snapshots() {
+        let fee_config = FeeConfig::new(FeeRate::from_bps(30).unwrap(), FeeRate::ZERO);
+        let before = PoolState {
+            fee_config,
+            token_0_reserve: 1_000_000_000,
+            token_1_reserve: 1_000_000_000,
+            lp_supply: 1_000_000_000,
+            ..PoolState::default()
+        };
+        assert_eq!(realized_fee_yield(&before, &before, 1), Some(0));
+
+        // trade the pool's depth in token 0, then back in token 1
+        let mut after = before;
+        let result = CurveCalculator::swap_base_input(
+            &SwapParams::from_raw_parts(1_000_000_000, 1_000_000_000, 1_000_000_000, 3_000, 0)
+                .unwrap(),
+        )
+        .unwrap();
+        after.token_0_reserve = result.new_swap_source_amount as u64;
+        after.token_1_reserve = result.new_swap_destination_amount as u64;
+        let result = CurveCalculator::swap_base_input(
+            &SwapParams::from_raw_parts(
+                after.token_1_reserve as u128,
+                after.token_1_reserve as u128,
+                after.token_0_reserve as u128,
+                3_000,
+                0,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        after.token_1_reserve = result.new_swap_source_amount as u64;
+        after.token_0_reserve = result.new_swap_destination_amount as u64;
+        let window_yield = realized_fee_yield(&before, &after, SECONDS_PER_YEAR).unwrap();
+        // each fee, 30 bps of the pool depth, grows sqrt(k) by about 7.5 bps
+        assert_eq!(window_yield, 15);
+
+        // doubling the liquidity does not change the value per LP token
+        let deposited = PoolState {
+            token_0_reserve: after.token_0_reserve * 2,
+            token_1_reserve: after.token_1_reserve * 2,
+            lp_supply: after.lp_supply * 2,
+            ..after
+        };
+        assert_eq!(
+            realized_fee_yield(&before, &deposited, SECONDS_PER_YEAR),
+            Some(window_yield)
+        );
+        let empty = PoolState {
+            lp_supply: 0,
+            ..before
+        };
+        assert_eq!(realized_fee_yield(&empty, &after, SECONDS_PER_YEAR), None);
+    }
+}
@@ -0,0 +1,146 @@
+//! Impermanent loss
+//!
+//! The loss of a constant product LP position relative to holding the
+//! deposited tokens, ignoring fees. For a price move by a factor `r` it is
+//! `1 - 2 * sqrt(r) / (1 + r)`, independent of the position size.
+use crate::{
+    curve::amount::Reserves,
+    utils::{IntegerSquareRoot, PriceQ64, U256, price::to_u128},
+};
+
+/// Denominator for losses expressed in basis points
+pub const IMPERMANENT_LOSS_BPS_DENOMINATOR: u128 = 10_000;
+
+/// Impermanent loss of a position, valued in token 1 at the current price
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PositionImpermanentLoss {
+    /// Amount of token 0 the position holds now
+    pub token_0_amount: u128,
+    /// Amount of token 1 the position holds now
+    pub token_1_amount: u128,
+    /// Value of the originally deposited tokens, had they been held
+    pub hold_value: u128,
+    /// Value of the position's current holdings
+    pub position_value: u128,
+    /// `hold_value - position_value`
+    pub loss: u128,
+    /// `loss / hold_value` in basis points, rounded down
+    pub loss_bps: u128,
+}
+
+/// Impermanent loss in basis points for a move from `initial_price` to
+/// `current_price`, rounded down.
+///
+/// Returns `None` if either price is zero.
+pub fn impermanent_loss(initial_price: PriceQ64, current_price: PriceQ64) -> Option<u128> {
+    if initial_price == PriceQ64::ZERO || current_price == PriceQ64::ZERO {
+        return None;
+    }
+    let initial_price = U256::from(initial_price.raw());
+    let current_price = U256::from(current_price.raw());
+    // 1 - 2 * sqrt(p0 * p1) / (p0 + p1), both terms scaled by Q64
+    let geometric = (initial_price * current_price).sqrt_ceil() * 2;
+    let arithmetic = initial_price + current_price;
+    let loss = arithmetic.saturating_sub(geometric) * U256::from(IMPERMANENT_LOSS_BPS_DENOMINATOR)
+        / arithmetic;
+    to_u128(loss)
+}
+
+/// Impermanent loss of a position that deposited `deposit_token_0_amount`
+/// and `deposit_token_1_amount`, now that the pool holds
+/// `current_reserves`.
+///
+/// The position's liquidity `sqrt(d0 * d1)` is re-spread along the curve at
+/// the current price `r1 / r0`. Amounts are rounded down, so the reported
+/// loss never understates the position's value by more than a unit per
+/// rounding. Returns `None` if a reserve is zero or a value overflows u128.
+pub fn position_impermanent_loss(
+    deposit_token_0_amount: u128,
+    deposit_token_1_amount: u128,
+    current_reserves: Reserves,
+) -> Option<PositionImpermanentLoss> {
+    let reserve_0 = U256::from(current_reserves.token_0.get());
+    let reserve_1 = U256::from(current_reserves.token_1.get());
+    if reserve_0.is_zero() || reserve_1.is_zero() {
+        return None;
+    }
+    let liquidity_squared = U256::from(deposit_token_0_amount) * U256::from(deposit_token_1_amount);
+    // holdings on the curve through the current price: h0 = sqrt(L^2 / p),
+    // h1 = sqrt(L^2 * p) with p = r1 / r0
+    let token_0_amount = (liquidity_squared.checked_mul(reserve_0)? / reserve_1).sqrt_floor();
+    let token_1_amount = (liquidity_squared.checked_mul(reserve_1)? / reserve_0).sqrt_floor();
+    let in_token_1 = |amount_0: U256| {
+        amount_0
+            .checked_mul(reserve_1)
+            .map(|value| value / reserve_0)
+    };
+    let hold_value = in_token_1(U256::from(deposit_token_0_amount))? + deposit_token_1_amount;
+    let position_value = in_token_1(token_0_amount)? + token_1_amount;
+    let loss = hold_value.saturating_sub(position_value);
+    let loss_bps = if hold_value.is_zero() {
+        U256::zero()
+    } else {
+        loss * U256::from(IMPERMANENT_LOSS_BPS_DENOMINATOR) / hold_value
+    };
+    Some(PositionImpermanentLoss {
+        token_0_amount: to_u128(token_0_amount)?,
+        token_1_amount: to_u128(token_1_amount)?,
+        hold_value: to_u128(hold_value)?,
+        position_value: to_u128(position_value)?,
+        loss: to_u128(loss)?,
+        loss_bps: to_u128(loss_bps)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::curve::amount::{Token0Amount, Token1Amount},
+    };
+
+    #[test]
+    fn price_impermanent_loss() {
+        let one = PriceQ64::ONE;
+        assert_eq!(impermanent_loss(one, one), Some(0));
+        // 1 - 2 * 2 / 5
+        assert_eq!(
+            impermanent_loss(one, PriceQ64::from_integer(4)),
+            Some(2_000)
+        );
+        assert_eq!(
+            impermanent_loss(PriceQ64::from_integer(4), one),
+            Some(2_000)
+        );
+        // 1 - 2 * sqrt(2) / 3 = 5.72%
+        assert_eq!(impermanent_loss(one, PriceQ64::from_integer(2)), Some(571));
+        assert_eq!(impermanent_loss(PriceQ64::ZERO, one), None);
+        let extreme = impermanent_loss(PriceQ64::from_raw(1), PriceQ64::from_raw(u128::MAX));
+        assert_eq!(extreme, Some(9_999));
+    }
+
+    #[test]
+    fn position_loss_matches_price_loss() {
+        // deposited at price 1, price is now 4
+        let reserves = Reserves::new(Token0Amount(50), Token1Amount(200));
+        let loss = position_impermanent_loss(100, 100, reserves).unwrap();
+        assert_eq!(
+            loss,
+            PositionImpermanentLoss {
+                token_0_amount: 50,
+                token_1_amount: 200,
+                hold_value: 500,
+                position_value: 400,
+                loss: 100,
+                loss_bps: 2_000,
+            }
+        );
+        let reserves = Reserves::new(Token0Amount(100), Token1Amount(100));
+        assert_eq!(
+            position_impermanent_loss(100, 100, reserves).map(|loss| loss.loss),
+            Some(0)
+        );
+        let empty = Reserves::new(Token0Amount(0), Token1Amount(100));
+        assert_eq!(position_impermanent_loss(100, 100, empty), None);
+    }
+}
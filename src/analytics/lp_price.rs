@@ -0,0 +1,224 @@
+//! LP token fair value
+//!
+//! Valuing LP tokens at the pool's own reserves lets anyone inflate them with
+//! a large swap inside one transaction. Lending protocols instead value the
+//! pool at external prices `p0` and `p1`: a constant product pool arbitraged
+//! to those prices holds `sqrt(k * p1 / p0)` of token 0 and
+//! `sqrt(k * p0 / p1)` of token 1, worth `2 * sqrt(k * p0 * p1)` in total.
+//! A swap can only increase `k`, so manipulating the reserves cannot raise
+//! the price by more than the fees paid.
+//!
+//! StableSwap pools are valued through their invariant `D` instead, which is
+//! what the pool holds in total once balanced at the peg. Like `k`, a swap
+//! can only increase `D`.
+use crate::{
+    curve::{
+        amount::{LpAmount, Reserves},
+        calculator::CurveCalculator,
+        stable::StableSwapCurve,
+    },
+    utils::{IntegerSquareRoot, PriceQ64, Q64_RESOLUTION, U256, price::to_u128},
+};
+
+/// Fair value of one LP token out of `lp_supply`, given external prices of
+/// each token in a common numeraire, rounded down.
+///
+/// Computes `2 * sqrt(k * price_0 * price_1) / lp_supply`. Returns `None` if
+/// the LP supply is zero or the value overflows.
+pub fn lp_token_price(
+    reserves: Reserves,
+    lp_supply: LpAmount,
+    price_0: PriceQ64,
+    price_1: PriceQ64,
+) -> Option<PriceQ64> {
+    if lp_supply.get() == 0 {
+        return None;
+    }
+    // both square roots in Q64, so their product is in Q128
+    let invariant = CurveCalculator::pool_value(reserves).invariant;
+    let value = invariant
+        .checked_mul(U256::one() << (2 * Q64_RESOLUTION))?
+        .sqrt_floor();
+    let price = (U256::from(price_0.raw()) * U256::from(price_1.raw())).sqrt_floor();
+    let total = value.checked_mul(price)? >> (Q64_RESOLUTION - 1);
+    to_u128(total / U256::from(lp_supply.get())).map(PriceQ64::from_raw)
+}
+
+/// Fair value of one LP token of a StableSwap pool with amplification `amp`,
+/// scaled by `AMP_PRECISION`, out of `lp_supply`, given external prices of
+/// each token in a common numeraire, rounded down.
+///
+/// Computes `D * min(price_0, price_1) / lp_supply`: if either token loses
+/// its peg, arbitrage leaves the pool holding mostly that token, so `D` is
+/// valued at the cheaper of the two. Returns `None` if the LP supply is zero,
+/// `D` cannot be computed or the value overflows.
+pub fn stable_lp_token_price(
+    amp: u64,
+    reserves: Reserves,
+    lp_supply: LpAmount,
+    price_0: PriceQ64,
+    price_1: PriceQ64,
+) -> Option<PriceQ64> {
+    if lp_supply.get() == 0 {
+        return None;
+    }
+    let d = StableSwapCurve::compute_d(amp, reserves)?;
+    let total = U256::from(d).checked_mul(U256::from(price_0.min(price_1).raw()))?;
+    to_u128(total / U256::from(lp_supply.get())).map(PriceQ64::from_raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::curve::{
+            amount::{Token0Amount, Token1Amount},
+            calculator::TradeDirection,
+            fees::{FeeConfig, FeeRate},
+            stable::{AMP_PRECISION, AmpRamp},
+        },
+        proptest::prelude::*,
+    };
+
+    #[test]
+    fn price_balanced_pool() {
+        // at the pool's own price the fair value is the reserves' value
+        let reserves = Reserves::new(Token0Amount(100), Token1Amount(400));
+        let price = lp_token_price(
+            reserves,
+            LpAmount(10),
+            PriceQ64::from_integer(4),
+            PriceQ64::ONE,
+        );
+        assert_eq!(price, Some(PriceQ64::from_integer(80)));
+        // quoted in token 0 instead
+        let price = lp_token_price(
+            reserves,
+            LpAmount(10),
+            PriceQ64::ONE,
+            PriceQ64::from_ratio(1, 4).unwrap(),
+        );
+        assert_eq!(price, Some(PriceQ64::from_integer(20)));
+        assert_eq!(
+            lp_token_price(reserves, LpAmount(0), PriceQ64::ONE, PriceQ64::ONE),
+            None
+        );
+    }
+
+    #[test]
+    fn swaps_cannot_inflate_price() {
+        let reserves = Reserves::new(Token0Amount(1_000_000), Token1Amount(1_000_000));
+        let fee_config = FeeConfig::new(FeeRate::from_bps(30).unwrap(), FeeRate::ZERO);
+        let fair = |reserves| {
+            lp_token_price(reserves, LpAmount(1_000), PriceQ64::ONE, PriceQ64::ONE).unwrap()
+        };
+        let result = CurveCalculator::swap_exact_in(
+            TradeDirection::ZeroForOne,
+            9_000_000,
+            reserves,
+            fee_config,
+        )
+        .unwrap();
+        let skewed = Reserves::new(
+            Token0Amount(result.new_swap_source_amount),
+            Token1Amount(result.new_swap_destination_amount),
+        );
+        // valued at the skewed reserves, the pool would look 5x richer
+        assert!(skewed.token_0.get() + skewed.token_1.get() > 5 * 2_000_000);
+        let before = fair(reserves);
+        let after = fair(skewed);
+        assert_eq!(before, PriceQ64::from_integer(2_000));
+        assert!(after >= before);
+        assert!(after < PriceQ64::from_integer(2_050));
+    }
+
+    #[test]
+    fn price_stable_pool() {
+        let amp = 100 * AMP_PRECISION;
+        let reserves = Reserves::new(Token0Amount(1_000_000), Token1Amount(1_000_000));
+        let price = |reserves, price_1| {
+            stable_lp_token_price(amp, reserves, LpAmount(1_000), PriceQ64::ONE, price_1)
+        };
+        // balanced at the peg, D is the sum of the reserves
+        assert_eq!(
+            price(reserves, PriceQ64::ONE),
+            Some(PriceQ64::from_integer(2_000))
+        );
+        // a depegged token drags the whole pool down to its price
+        assert_eq!(
+            price(reserves, PriceQ64::from_ratio(1, 2).unwrap()),
+            Some(PriceQ64::from_integer(1_000))
+        );
+        assert_eq!(
+            stable_lp_token_price(amp, reserves, LpAmount(0), PriceQ64::ONE, PriceQ64::ONE),
+            None
+        );
+        assert_eq!(
+            stable_lp_token_price(0, reserves, LpAmount(1), PriceQ64::ONE, PriceQ64::ONE),
+            None
+        );
+
+        // a swap can only raise D by the fees it pays
+        let fee_config = FeeConfig::new(FeeRate::from_bps(4).unwrap(), FeeRate::ZERO);
+        let result = StableSwapCurve::swap_exact_in(
+            &AmpRamp::new(100).unwrap(),
+            0,
+            TradeDirection::ZeroForOne,
+            900_000,
+            reserves,
+            fee_config,
+        )
+        .unwrap();
+        let skewed = Reserves::new(
+            Token0Amount(result.new_swap_source_amount),
+            Token1Amount(result.new_swap_destination_amount),
+        );
+        let after = price(skewed, PriceQ64::ONE).unwrap();
+        assert!(after >= PriceQ64::from_integer(2_000));
+        assert!(after < PriceQ64::from_integer(2_001));
+    }
+
+    proptest! {
+        #[test]
+        fn fair_value_never_exceeds_reserve_value(
+            token_0 in 1u128..1 << 40,
+            token_1 in 1u128..1 << 40,
+            price_0 in 1u64..1 << 20,
+            price_1 in 1u64..1 << 20,
+        ) {
+            // by AM-GM, 2 * sqrt(x * p0 * y * p1) <= x * p0 + y * p1
+            let reserves = Reserves::new(Token0Amount(token_0), Token1Amount(token_1));
+            let fair = lp_token_price(
+                reserves,
+                LpAmount(1),
+                PriceQ64::from_integer(price_0),
+                PriceQ64::from_integer(price_1),
+            )
+            .unwrap();
+            let spot_value = token_0 * u128::from(price_0) + token_1 * u128::from(price_1);
+            prop_assert!(fair.to_integer_floor() <= spot_value);
+        }
+
+        #[test]
+        fn stable_fair_value_never_exceeds_reserve_value(
+            amp in AMP_PRECISION..1_000 * AMP_PRECISION,
+            token_0 in 1u128..1 << 40,
+            token_1 in 1u128..1 << 40,
+            price_0 in 1u64..1 << 20,
+            price_1 in 1u64..1 << 20,
+        ) {
+            // D is at most x + y, valued here at the lower price
+            let reserves = Reserves::new(Token0Amount(token_0), Token1Amount(token_1));
+            let fair = stable_lp_token_price(
+                amp,
+                reserves,
+                LpAmount(1),
+                PriceQ64::from_integer(price_0),
+                PriceQ64::from_integer(price_1),
+            )
+            .unwrap();
+            let spot_value = token_0 * u128::from(price_0) + token_1 * u128::from(price_1);
+            prop_assert!(fair.to_integer_floor() <= spot_value);
+        }
+    }
+}
@@ -0,0 +1,17 @@
+//! Pool analytics
+//!
+//! Off-chain metrics derived from the exact curve math, so dashboards and
+//! risk tooling built on this crate report consistent numbers.
+pub mod arbitrage;
+pub mod cost;
+pub mod depth;
+pub mod fee_yield;
+pub mod impermanent_loss;
+pub mod lp_price;
+pub mod position;
+pub mod price_curve;
+
+pub use {
+    arbitrage::*, cost::*, depth::*, fee_yield::*, impermanent_loss::*, lp_price::*, position::*,
+    price_curve::*,
+};
@@ -0,0 +1,160 @@
+//! LP position P&L
+//!
+//! A `Position` remembers the pool as it was when the LP tokens were minted,
+//! and splits the change in their value since into impermanent loss and fees
+//! earned, all valued in token 1 at the current pool price:
+//! `current_value = hold_value - loss + fees_earned`, up to rounding.
+use crate::{
+    analytics::impermanent_loss::{PositionImpermanentLoss, position_impermanent_loss},
+    curve::{
+        amount::{LpAmount, Reserves, TokenAmounts},
+        calculator::{CurveCalculator, RoundDirection},
+    },
+    sim::PoolSnapshot,
+    utils::{U256, price::to_u128},
+};
+
+/// LP tokens and the pool they were minted from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Position {
+    /// LP tokens held
+    pub lp_amount: LpAmount,
+    /// The pool when the position was opened
+    pub entry: PoolSnapshot,
+}
+
+fn reserves(snapshot: &PoolSnapshot) -> Reserves {
+    Reserves::new(snapshot.token_0_reserve, snapshot.token_1_reserve)
+}
+
+impl Position {
+    pub const fn new(lp_amount: LpAmount, entry: PoolSnapshot) -> Self {
+        Self { lp_amount, entry }
+    }
+
+    // Tokens the position redeems for in `snapshot`, rounded down
+    fn amounts(&self, snapshot: &PoolSnapshot) -> Option<TokenAmounts> {
+        CurveCalculator::lp_to_token_amounts(
+            self.lp_amount,
+            snapshot.lp_supply,
+            reserves(snapshot),
+            RoundDirection::Floor,
+        )
+    }
+
+    /// Tokens the position would redeem for at entry, ie. what holding
+    /// instead would have kept
+    pub fn entry_amounts(&self) -> Option<TokenAmounts> {
+        self.amounts(&self.entry)
+    }
+
+    /// Tokens the position redeems for in `current`, rounded down
+    pub fn current_amounts(&self, current: &PoolSnapshot) -> Option<TokenAmounts> {
+        self.amounts(current)
+    }
+
+    /// Value of the position in `current`, in token 1 at the current pool
+    /// price, rounded down.
+    ///
+    /// Returns `None` if the pool has no LP supply or an empty reserve.
+    pub fn current_value(&self, current: &PoolSnapshot) -> Option<u128> {
+        let amounts = self.current_amounts(current)?;
+        let reserve_0 = U256::from(current.token_0_reserve.get());
+        if reserve_0.is_zero() {
+            return None;
+        }
+        let value_0 = U256::from(amounts.token_0.get()) * U256::from(current.token_1_reserve.get())
+            / reserve_0;
+        to_u128(value_0.checked_add(U256::from(amounts.token_1.get()))?)
+    }
+
+    /// Impermanent loss against holding the entry amounts, ignoring fees.
+    ///
+    /// Returns `None` if either pool has no LP supply or an empty reserve.
+    pub fn il_vs_hold(&self, current: &PoolSnapshot) -> Option<PositionImpermanentLoss> {
+        let entry = self.entry_amounts()?;
+        position_impermanent_loss(entry.token_0.get(), entry.token_1.get(), reserves(current))
+    }
+
+    /// Fees earned since entry, in token 1 at the current pool price: the
+    /// position's value beyond what its entry liquidity is worth now.
+    ///
+    /// Zero if the position is worth less, eg. through rounding. Returns
+    /// `None` if either pool has no LP supply or an empty reserve.
+    pub fn fees_earned(&self, current: &PoolSnapshot) -> Option<u128> {
+        let loss = self.il_vs_hold(current)?;
+        Some(
+            self.current_value(current)?
+                .saturating_sub(loss.position_value),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            curve::{
+                amount::{Token0Amount, Token1Amount},
+                calculator::TradeDirection,
+                fees::{FeeConfig, FeeRate},
+            },
+            sim::Pool,
+        },
+    };
+
+    fn pool(trade_fee_bps: u16) -> Pool {
+        Pool::new(
+            Reserves::new(Token0Amount(1_000_000_000), Token1Amount(1_000_000_000)),
+            LpAmount(1_000_000_000),
+            FeeConfig::new(FeeRate::from_bps(trade_fee_bps).unwrap(), FeeRate::ZERO),
+        )
+    }
+
+    #[test]
+    fn untouched_position_keeps_its_value() {
+        let pool = pool(30);
+        let snapshot = PoolSnapshot::from(&pool);
+        let position = Position::new(LpAmount(100_000_000), snapshot);
+        assert_eq!(position.current_value(&snapshot), Some(200_000_000));
+        assert_eq!(position.fees_earned(&snapshot), Some(0));
+        let loss = position.il_vs_hold(&snapshot).unwrap();
+        assert_eq!(loss.loss, 0);
+        assert_eq!(loss.hold_value, 200_000_000);
+    }
+
+    #[test]
+    fn split_pnl_into_loss_and_fees() {
+        for trade_fee_bps in [0, 30] {
+            let mut pool = pool(trade_fee_bps);
+            let position = Position::new(LpAmount(100_000_000), PoolSnapshot::from(&pool));
+            pool.apply_swap(TradeDirection::ZeroForOne, 1_000_000_000)
+                .unwrap();
+            pool.apply_swap(TradeDirection::OneForZero, 200_000_000)
+                .unwrap();
+            let current = PoolSnapshot::from(&pool);
+
+            let value = position.current_value(&current).unwrap();
+            let loss = position.il_vs_hold(&current).unwrap();
+            let fees = position.fees_earned(&current).unwrap();
+            assert!(loss.loss_bps > 0);
+            assert!(value < loss.hold_value);
+            // value = hold - loss + fees, to within rounding
+            assert!((loss.hold_value - loss.loss + fees).abs_diff(value) <= 2);
+            if trade_fee_bps == 0 {
+                assert!(fees <= 2);
+            } else {
+                // a tenth of 0.3% of the volume, valued in token 1
+                assert!(fees > 100_000);
+            }
+        }
+    }
+
+    #[test]
+    fn empty_pool_has_no_value() {
+        let position = Position::new(LpAmount(1), PoolSnapshot::default());
+        assert_eq!(position.current_value(&PoolSnapshot::default()), None);
+        assert_eq!(position.fees_earned(&PoolSnapshot::default()), None);
+    }
+}
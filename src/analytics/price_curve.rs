@@ -0,0 +1,153 @@
+//! Price curve export
+//!
+//! Samples the spot price a pool is left at after trades of increasing size
+//! in both directions, as plain data that analytics pipelines and notebooks
+//! can load without reimplementing the curve. With the `serde` feature the
+//! samples serialize with serde; amounts and prices are integers, prices as
+//! raw Q64.64.
+use {
+    crate::{
+        analytics::depth::{DEPTH_BPS_DENOMINATOR, DepthCurveConfig},
+        curve::{amount::Reserves, calculator::TradeDirection, fees::FeeConfig},
+        quote::ladder,
+        utils::PriceQ64,
+    },
+    alloc::vec::Vec,
+};
+
+/// The pool after one trade size
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PriceCurvePoint {
+    /// Amount of source token paid in, including fees
+    pub amount_in: u128,
+    /// Amount of destination token received
+    pub amount_out: u128,
+    /// Spot price of token 0 in token 1 after the trade, in Q64.64
+    pub price_after_q64: u128,
+}
+
+/// Price against trade size for a pool, in both directions
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PriceCurveSample {
+    /// Token 0 reserve sampled
+    pub token_0_reserve: u128,
+    /// Token 1 reserve sampled
+    pub token_1_reserve: u128,
+    /// Trade fee rate, in millionths
+    pub trade_fee_rate: u64,
+    /// Protocol's share of the trade fee, in millionths
+    pub protocol_fee_rate: u64,
+    /// Spot price of token 0 in token 1 before any trade, in Q64.64
+    pub spot_price_q64: u128,
+    /// Selling token 0 for token 1, in increasing size; the price falls
+    pub zero_for_one: Vec<PriceCurvePoint>,
+    /// Selling token 1 for token 0, in increasing size; the price rises
+    pub one_for_zero: Vec<PriceCurvePoint>,
+}
+
+fn sample_direction(
+    trade_direction: TradeDirection,
+    reserves: Reserves,
+    fee_config: FeeConfig,
+    config: DepthCurveConfig,
+) -> Option<Vec<PriceCurvePoint>> {
+    let (swap_source_amount, _) = reserves.source_and_destination(trade_direction);
+    let max_input =
+        swap_source_amount.checked_mul(config.max_input_bps.into())? / DEPTH_BPS_DENOMINATOR;
+    let steps = u128::from(config.points);
+    let amounts = (1..=steps)
+        .map(|step| Some(max_input.checked_mul(step)? / steps))
+        .collect::<Option<Vec<_>>>()?;
+    ladder(trade_direction, reserves, fee_config, &amounts, 0)
+        .into_iter()
+        .map(|quote| {
+            let quote = quote?;
+            Some(PriceCurvePoint {
+                amount_in: quote.amount_in,
+                amount_out: quote.amount_out,
+                price_after_q64: quote.price_after.raw(),
+            })
+        })
+        .collect()
+}
+
+/// Sample the price curve of a pool in both directions, on the input ladder
+/// of `config` as `depth_curve` uses.
+///
+/// Returns `None` if a reserve is empty or any trade on the ladder fails.
+pub fn sample_price_curve(
+    reserves: Reserves,
+    fee_config: FeeConfig,
+    config: DepthCurveConfig,
+) -> Option<PriceCurveSample> {
+    let (token_0_reserve, token_1_reserve) = (reserves.token_0.get(), reserves.token_1.get());
+    if token_0_reserve == 0 || token_1_reserve == 0 {
+        return None;
+    }
+    let spot_price = PriceQ64::from_reserves(token_0_reserve, token_1_reserve)?;
+    Some(PriceCurveSample {
+        token_0_reserve,
+        token_1_reserve,
+        trade_fee_rate: fee_config.trade_fee_rate.get(),
+        protocol_fee_rate: fee_config.protocol_fee_rate.get(),
+        spot_price_q64: spot_price.raw(),
+        zero_for_one: sample_direction(TradeDirection::ZeroForOne, reserves, fee_config, config)?,
+        one_for_zero: sample_direction(TradeDirection::OneForZero, reserves, fee_config, config)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::curve::{
+            amount::{Token0Amount, Token1Amount},
+            fees::FeeRate,
+        },
+    };
+
+    fn sample() -> PriceCurveSample {
+        let reserves = Reserves::new(Token0Amount(1_000_000), Token1Amount(4_000_000));
+        let fee_config = FeeConfig::new(FeeRate::from_bps(30).unwrap(), FeeRate::ZERO);
+        let config = DepthCurveConfig {
+            points: 10,
+            max_input_bps: 5_000,
+        };
+        sample_price_curve(reserves, fee_config, config).unwrap()
+    }
+
+    #[test]
+    fn price_moves_away_from_spot() {
+        let sample = sample();
+        assert_eq!(sample.zero_for_one.len(), 10);
+        assert_eq!(sample.one_for_zero[9].amount_in, 2_000_000);
+        assert_eq!(sample.trade_fee_rate, 3_000);
+        let mut price = sample.spot_price_q64;
+        for point in &sample.zero_for_one {
+            assert!(point.price_after_q64 < price);
+            price = point.price_after_q64;
+        }
+        let mut price = sample.spot_price_q64;
+        for point in &sample.one_for_zero {
+            assert!(point.price_after_q64 > price);
+            price = point.price_after_q64;
+        }
+        let empty = Reserves::new(Token0Amount(0), Token1Amount(1));
+        assert_eq!(
+            sample_price_curve(empty, FeeConfig::default(), DepthCurveConfig::default()),
+            None
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let sample = sample();
+        let json = serde_json::to_string(&sample).unwrap();
+        assert!(json.contains("\"zero_for_one\":[{\"amount_in\":50000,"));
+        let decoded: PriceCurveSample = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, sample);
+    }
+}
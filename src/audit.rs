@@ -0,0 +1,358 @@
+//! Invariant audit checks
+//!
+//! Re-verifies completed operations from their observable effects: the pool
+//! reserves before and after, and the result the calculator reported. Meant
+//! for debug builds of on-chain processors and for indexers validating
+//! historical swaps, so the checks use only U256 arithmetic and never panic.
+use {
+    crate::{
+        curve::{
+            amount::{LpAmount, Reserves},
+            calculator::SwapResult,
+        },
+        utils::U256,
+    },
+    core::fmt,
+};
+
+/// Why an operation failed an audit check
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuditError {
+    /// The reserves did not move by the amounts in the result
+    ReservesMismatch,
+    /// A fee exceeds the amount it was charged on
+    FeeExceedsAmount,
+    /// The constant product `token_0 * token_1` decreased
+    InvariantDecreased,
+    /// The constant product grew by less than the fees left to LPs
+    InvariantGrowthBelowFees,
+    /// The value of one LP token decreased
+    LpValueDecreased,
+}
+
+impl fmt::Display for AuditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            AuditError::ReservesMismatch => "reserves do not match the reported amounts",
+            AuditError::FeeExceedsAmount => "fee exceeds the amount it was charged on",
+            AuditError::InvariantDecreased => "pool invariant decreased",
+            AuditError::InvariantGrowthBelowFees => "pool invariant grew by less than the LP fees",
+            AuditError::LpValueDecreased => "LP token value decreased",
+        };
+        f.write_str(message)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for AuditError {}
+
+fn invariant(reserves: Reserves) -> U256 {
+    U256::from(reserves.token_0.get()) * U256::from(reserves.token_1.get())
+}
+
+// (source before, destination before, source after, destination after),
+// taking the reserve that grew as the source
+fn orient(before: Reserves, after: Reserves) -> (u128, u128, u128, u128) {
+    if after.token_0 >= before.token_0 {
+        (
+            before.token_0.get(),
+            before.token_1.get(),
+            after.token_0.get(),
+            after.token_1.get(),
+        )
+    } else {
+        (
+            before.token_1.get(),
+            before.token_0.get(),
+            after.token_1.get(),
+            after.token_0.get(),
+        )
+    }
+}
+
+/// Verify a swap that moved the pool from `before` to `after` and reported
+/// `result`.
+///
+/// The trade direction is inferred from which reserve grew. Checks that the
+/// reserves moved by exactly the swapped amounts, that the fees are bounded
+/// by the input, and that the constant product did not decrease.
+pub fn verify_swap(
+    before: Reserves,
+    after: Reserves,
+    result: &SwapResult,
+) -> Result<(), AuditError> {
+    let (source_before, destination_before, source_after, destination_after) =
+        orient(before, after);
+    let moved_as_reported = source_after == result.new_swap_source_amount
+        && destination_after == result.new_swap_destination_amount
+        && source_before.checked_add(result.source_amount_swapped) == Some(source_after)
+        && destination_before
+            .checked_sub(result.destination_amount_swapped)
+            .and_then(|amount| amount.checked_sub(result.destination_protocol_fee))
+            == Some(destination_after);
+    if !moved_as_reported {
+        return Err(AuditError::ReservesMismatch);
+    }
+    if result.trade_fee > result.source_amount_swapped || result.protocol_fee > result.trade_fee {
+        return Err(AuditError::FeeExceedsAmount);
+    }
+    if invariant(after) < invariant(before) {
+        return Err(AuditError::InvariantDecreased);
+    }
+    Ok(())
+}
+
+/// Verify a swap as `verify_swap` does, and that the constant product grew
+/// by at least the fees left to LPs.
+///
+/// Excluding the source protocol fee, which is not the LPs', the curve
+/// keeps `k` and the LP fees are added on top, so `k` must grow by at least
+/// each LP fee times the other reserve after the swap. Under
+/// `ProtocolFeeMode::Split` the pool buys the destination protocol fee with
+/// part of the source fee, at no better than the post-swap price, so it is
+/// deducted at that price. This is strictly stronger than `k` not
+/// decreasing whenever an LP fee is charged.
+pub fn verify_swap_with_fees(
+    before: Reserves,
+    after: Reserves,
+    result: &SwapResult,
+) -> Result<(), AuditError> {
+    verify_swap(before, after, result)?;
+    let (source_before, destination_before, source_after, destination_after) =
+        orient(before, after);
+    // verified above to be bounded by the amounts they are charged on
+    let source_after = U256::from(source_after - result.protocol_fee);
+    let destination_after = U256::from(destination_after);
+    let source_lp_fee = U256::from(result.trade_fee - result.protocol_fee);
+    // the destination LP fee, `destination_trade_fee - destination_protocol_fee`,
+    // is negative under `Split`, so its protocol part moves to the left
+    let grown = source_after * destination_after
+        + U256::from(result.destination_protocol_fee) * source_after;
+    let required = U256::from(source_before) * U256::from(destination_before)
+        + source_lp_fee * destination_after
+        + U256::from(result.destination_trade_fee) * source_after;
+    if grown < required {
+        return Err(AuditError::InvariantGrowthBelowFees);
+    }
+    Ok(())
+}
+
+/// Verify a deposit or withdrawal that moved the pool from `before` with
+/// `lp_supply_before` LP tokens to `after` with `lp_supply_after`.
+///
+/// Checks that each token backing one LP token did not decrease, ie.
+/// `after / lp_supply_after >= before / lp_supply_before` for both tokens.
+/// A pool with no LP supply before has no LP value to protect, so its first
+/// deposit always passes.
+pub fn verify_liquidity_change(
+    before: Reserves,
+    lp_supply_before: LpAmount,
+    after: Reserves,
+    lp_supply_after: LpAmount,
+) -> Result<(), AuditError> {
+    if lp_supply_before.get() == 0 {
+        return Ok(());
+    }
+    let supply_before = U256::from(lp_supply_before.get());
+    let supply_after = U256::from(lp_supply_after.get());
+    for (token_before, token_after) in [
+        (before.token_0.get(), after.token_0.get()),
+        (before.token_1.get(), after.token_1.get()),
+    ] {
+        if U256::from(token_after) * supply_before < U256::from(token_before) * supply_after {
+            return Err(AuditError::LpValueDecreased);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::curve::{
+            calculator::{CurveCalculator, RoundDirection, TradeDirection},
+            fees::{FeeConfig, FeeRate, MAX_TRADE_FEE_RATE, ProtocolFeeMode, TradeFeeSide},
+            swap_params::SwapParams,
+        },
+        proptest::prelude::*,
+    };
+
+    #[test]
+    fn verify_computed_swaps() {
+        let result = CurveCalculator::swap_base_input(
+            &SwapParams::from_raw_parts(1_000, 50_000, 80_000, 2_500, 120_000).unwrap(),
+        )
+        .unwrap();
+        let before = Reserves::from_raw(50_000, 80_000);
+        let after = Reserves::from_raw(
+            result.new_swap_source_amount,
+            result.new_swap_destination_amount,
+        );
+        assert_eq!(verify_swap(before, after, &result), Ok(()));
+
+        // token 1 in, token 0 out
+        let result = CurveCalculator::swap_base_output(
+            &SwapParams::from_raw_parts(1_000, 80_000, 50_000, 2_500, 120_000).unwrap(),
+        )
+        .unwrap();
+        let after = Reserves::from_raw(
+            result.new_swap_destination_amount,
+            result.new_swap_source_amount,
+        );
+        assert_eq!(verify_swap(before, after, &result), Ok(()));
+    }
+
+    #[test]
+    fn reject_tampered_swaps() {
+        let result = CurveCalculator::swap_base_input(
+            &SwapParams::from_raw_parts(1_000, 50_000, 80_000, 0, 0).unwrap(),
+        )
+        .unwrap();
+        let before = Reserves::from_raw(50_000, 80_000);
+        let after = Reserves::from_raw(
+            result.new_swap_source_amount,
+            result.new_swap_destination_amount,
+        );
+        let overpaid = SwapResult {
+            destination_amount_swapped: result.destination_amount_swapped + 100,
+            new_swap_destination_amount: result.new_swap_destination_amount - 100,
+            ..result
+        };
+        let overpaid_after = Reserves::from_raw(
+            overpaid.new_swap_source_amount,
+            overpaid.new_swap_destination_amount,
+        );
+        assert_eq!(
+            verify_swap(before, overpaid_after, &overpaid),
+            Err(AuditError::InvariantDecreased)
+        );
+        assert_eq!(
+            verify_swap(before, overpaid_after, &result),
+            Err(AuditError::ReservesMismatch)
+        );
+        let excessive_fee = SwapResult {
+            trade_fee: 1,
+            protocol_fee: 2,
+            destination_trade_fee: 0,
+            destination_protocol_fee: 0,
+            ..result
+        };
+        assert_eq!(
+            verify_swap(before, after, &excessive_fee),
+            Err(AuditError::FeeExceedsAmount)
+        );
+    }
+
+    #[test]
+    fn invariant_must_grow_by_the_lp_fee() {
+        let result = CurveCalculator::swap_base_input(
+            &SwapParams::from_raw_parts(10_000, 50_000, 80_000, 2_500, 120_000).unwrap(),
+        )
+        .unwrap();
+        let before = Reserves::from_raw(50_000, 80_000);
+        let after = Reserves::from_raw(
+            result.new_swap_source_amount,
+            result.new_swap_destination_amount,
+        );
+        assert_eq!(verify_swap_with_fees(before, after, &result), Ok(()));
+        // a swap that charges the fee but lets the pool keep none of it
+        // still passes the plain check
+        let fee_paid_out = SwapResult {
+            destination_amount_swapped: result.destination_amount_swapped + 2,
+            new_swap_destination_amount: result.new_swap_destination_amount - 2,
+            ..result
+        };
+        let after = Reserves::from_raw(
+            fee_paid_out.new_swap_source_amount,
+            fee_paid_out.new_swap_destination_amount,
+        );
+        assert_eq!(verify_swap(before, after, &fee_paid_out), Ok(()));
+        assert_eq!(
+            verify_swap_with_fees(before, after, &fee_paid_out),
+            Err(AuditError::InvariantGrowthBelowFees)
+        );
+    }
+
+    #[test]
+    fn verify_deposits_and_withdrawals() {
+        let before = Reserves::from_raw(2, 49);
+        let supply = LpAmount(10);
+        let deposit =
+            CurveCalculator::lp_tokens_to_trading_tokens(5, 10, 2, 49, RoundDirection::Ceiling)
+                .unwrap();
+        let after = Reserves::from_raw(2 + deposit.token_0_amount, 49 + deposit.token_1_amount);
+        assert_eq!(
+            verify_liquidity_change(before, supply, after, LpAmount(15)),
+            Ok(())
+        );
+
+        let withdrawal =
+            CurveCalculator::lp_tokens_to_trading_tokens(5, 10, 2, 49, RoundDirection::Floor)
+                .unwrap();
+        let after = Reserves::from_raw(
+            2 - withdrawal.token_0_amount,
+            49 - withdrawal.token_1_amount,
+        );
+        assert_eq!(
+            verify_liquidity_change(before, supply, after, LpAmount(5)),
+            Ok(())
+        );
+
+        // rounding a withdrawal up takes value from the remaining LPs
+        let after = Reserves::from_raw(1, 24);
+        assert_eq!(
+            verify_liquidity_change(before, supply, after, LpAmount(5)),
+            Err(AuditError::LpValueDecreased)
+        );
+        assert_eq!(
+            verify_liquidity_change(
+                Reserves::from_raw(0, 0),
+                LpAmount(0),
+                Reserves::from_raw(5, 5),
+                LpAmount(5)
+            ),
+            Ok(())
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn computed_swaps_grow_the_invariant_by_the_lp_fees(
+            source_amount in 1..u64::MAX as u128,
+            token_0 in 1..u64::MAX as u128,
+            token_1 in 1..u64::MAX as u128,
+            trade_fee_rate in 0..=MAX_TRADE_FEE_RATE.get(),
+            protocol_fee_rate in 0..=FeeRate::MAX.get(),
+            split: bool,
+            fee_on_output: bool,
+            zero_for_one: bool,
+        ) {
+            let before = Reserves::from_raw(token_0, token_1);
+            let fee_config = FeeConfig::new(
+                FeeRate::checked(trade_fee_rate).unwrap(),
+                FeeRate::checked(protocol_fee_rate).unwrap(),
+            )
+            .with_protocol_fee_mode(if split { ProtocolFeeMode::Split } else { ProtocolFeeMode::Input })
+            .with_trade_fee_side(if fee_on_output { TradeFeeSide::Output } else { TradeFeeSide::Input });
+            let trade_direction = if zero_for_one {
+                TradeDirection::ZeroForOne
+            } else {
+                TradeDirection::OneForZero
+            };
+            let Some(result) =
+                CurveCalculator::swap_exact_in(trade_direction, source_amount, before, fee_config)
+            else {
+                return Ok(());
+            };
+            let after = Reserves::from_source_and_destination(
+                trade_direction,
+                result.new_swap_source_amount,
+                result.new_swap_destination_amount,
+            );
+            // an empty swap leaves the direction ambiguous
+            prop_assume!(after != before);
+            prop_assert_eq!(verify_swap_with_fees(before, after, &result), Ok(()));
+        }
+    }
+}
@@ -0,0 +1,287 @@
+//! `invariant-quote`: print an exact-input or exact-output quote with its full
+//! fee breakdown, from reserves and fees given on the command line or from a
+//! pool account fetched over RPC.
+//!
+//! Pool accounts are quoted on their own curve, at the current time unless
+//! `--now` is given. Exact-output quotes need a constant product pool.
+//!
+//! ```text
+//! invariant-quote --reserve-0 1000000 --reserve-1 4000000 --trade-fee-rate 2500 \
+//!     --exact-in 10000
+//! invariant-quote --rpc-url https://api.mainnet-beta.solana.com --pool <ADDRESS> \
+//!     --direction one-for-zero --exact-out 5000 --slippage-bps 100
+//! ```
+use {
+    base64::{Engine, engine::general_purpose::STANDARD},
+    borsh::BorshDeserialize,
+    clap::{ArgGroup, Parser, ValueEnum},
+    curve::{
+        curve::{
+            calculator::TradeDirection,
+            fees::{FeeConfig, FeeRate},
+            params::CurveParams,
+        },
+        quote::{QuoteError, SwapQuote, quote_curve_exact_in},
+        state::PoolState,
+    },
+    serde_json::{Value, json},
+    std::{
+        process::ExitCode,
+        time::{SystemTime, UNIX_EPOCH},
+    },
+};
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Direction {
+    /// Pay token 0, receive token 1
+    ZeroForOne,
+    /// Pay token 1, receive token 0
+    OneForZero,
+}
+
+impl From<Direction> for TradeDirection {
+    fn from(direction: Direction) -> Self {
+        match direction {
+            Direction::ZeroForOne => TradeDirection::ZeroForOne,
+            Direction::OneForZero => TradeDirection::OneForZero,
+        }
+    }
+}
+
+#[derive(Debug, Parser)]
+#[command(name = "invariant-quote", version, about)]
+#[command(group(ArgGroup::new("pool_source").required(true).args(["reserve_0", "pool"])))]
+#[command(group(ArgGroup::new("amount").required(true).args(["exact_in", "exact_out"])))]
+struct Args {
+    /// Token 0 reserve of the pool
+    #[arg(long, requires = "reserve_1")]
+    reserve_0: Option<u64>,
+    /// Token 1 reserve of the pool
+    #[arg(long, requires = "reserve_0")]
+    reserve_1: Option<u64>,
+    /// Trade fee rate, in millionths
+    #[arg(long, default_value_t = 0, conflicts_with = "pool")]
+    trade_fee_rate: u64,
+    /// Protocol's share of the trade fee, in millionths
+    #[arg(long, default_value_t = 0, conflicts_with = "pool")]
+    protocol_fee_rate: u64,
+    /// Address of a pool account holding a borsh encoded `PoolState`
+    #[arg(long, requires = "rpc_url")]
+    pool: Option<String>,
+    /// JSON-RPC endpoint to fetch `--pool` from
+    #[arg(long)]
+    rpc_url: Option<String>,
+    /// Offset of the `PoolState` in the account data; Anchor accounts start
+    /// with an 8 byte discriminator
+    #[arg(long, default_value_t = 8)]
+    account_offset: usize,
+    /// Direction of the trade
+    #[arg(long, value_enum, default_value_t = Direction::ZeroForOne)]
+    direction: Direction,
+    /// Quote paying exactly this amount of the source token
+    #[arg(long)]
+    exact_in: Option<u64>,
+    /// Quote receiving exactly this amount of the destination token
+    #[arg(long)]
+    exact_out: Option<u64>,
+    /// Slippage tolerance for the minimum out or maximum in, in basis points
+    #[arg(long, default_value_t = 50)]
+    slippage_bps: u16,
+    /// Unix timestamp to evaluate ramping curve parameters at; defaults to
+    /// the current time
+    #[arg(long)]
+    now: Option<u64>,
+}
+
+fn fetch_pool_state(rpc_url: &str, pool: &str, offset: usize) -> Result<PoolState, String> {
+    let request = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "getAccountInfo",
+        "params": [pool, { "encoding": "base64" }],
+    });
+    let response: Value = ureq::post(rpc_url)
+        .send_json(request)
+        .map_err(|err| format!("RPC request failed: {err}"))?
+        .into_json()
+        .map_err(|err| format!("invalid RPC response: {err}"))?;
+    if let Some(error) = response.get("error") {
+        return Err(format!("RPC error: {error}"));
+    }
+    let data = response["result"]["value"]["data"][0]
+        .as_str()
+        .ok_or_else(|| format!("account {pool} not found"))?;
+    let data = STANDARD
+        .decode(data)
+        .map_err(|err| format!("invalid account data: {err}"))?;
+    decode_pool_state(&data, offset)
+}
+
+fn decode_pool_state(data: &[u8], offset: usize) -> Result<PoolState, String> {
+    let mut data = data
+        .get(offset..)
+        .ok_or_else(|| format!("account data is shorter than offset {offset}"))?;
+    PoolState::deserialize(&mut data).map_err(|err| format!("invalid pool state: {err}"))
+}
+
+fn pool_state(args: &Args) -> Result<PoolState, String> {
+    if let (Some(pool), Some(rpc_url)) = (&args.pool, &args.rpc_url) {
+        return fetch_pool_state(rpc_url, pool, args.account_offset);
+    }
+    let rate = |rate| FeeRate::checked(rate).ok_or_else(|| format!("fee rate {rate} exceeds 100%"));
+    Ok(PoolState {
+        fee_config: FeeConfig::new(rate(args.trade_fee_rate)?, rate(args.protocol_fee_rate)?),
+        token_0_reserve: args.reserve_0.unwrap_or_default(),
+        token_1_reserve: args.reserve_1.unwrap_or_default(),
+        ..PoolState::default()
+    })
+}
+
+fn quote(state: &PoolState, args: &Args, now: u64) -> Result<SwapQuote, String> {
+    let curve_params = state
+        .curve_params()
+        .ok_or("the pool's curve parameters are invalid or not built in")?;
+    if curve_params != CurveParams::ConstantProduct {
+        let amount = args
+            .exact_in
+            .ok_or("only constant product pools quote exact-output swaps")?;
+        return quote_curve_exact_in(
+            now,
+            &curve_params,
+            args.direction.into(),
+            amount.into(),
+            state.reserves(),
+            state.fee_config,
+            args.slippage_bps,
+        )
+        .ok_or_else(|| QuoteError::Unfillable.to_string());
+    }
+    let reserves = state.reserves();
+    let builder = SwapQuote::builder()
+        .reserves(reserves.token_0, reserves.token_1)
+        .fees(state.fee_config)
+        .trade_direction(args.direction.into())
+        .slippage_bps(args.slippage_bps);
+    let builder = match (args.exact_in, args.exact_out) {
+        (Some(amount), _) => builder.exact_in(amount.into()),
+        (None, Some(amount)) => builder.exact_out(amount.into()),
+        (None, None) => unreachable!("clap requires an amount"),
+    };
+    builder.build().map_err(|error| error.to_string())
+}
+
+fn run(args: &Args) -> Result<SwapQuote, String> {
+    let state = pool_state(args)?;
+    let now = match args.now {
+        Some(now) => now,
+        None => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|err| format!("the clock is before 1970: {err}"))?
+            .as_secs(),
+    };
+    quote(&state, args, now)
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    match run(&args) {
+        Ok(quote) => {
+            println!("{quote:#}");
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        curve::{curve::stable::AmpRamp, state::CurveType},
+    };
+
+    #[test]
+    fn quote_from_arguments() {
+        let args = Args::parse_from([
+            "invariant-quote",
+            "--reserve-0",
+            "1000000",
+            "--reserve-1",
+            "4000000",
+            "--trade-fee-rate",
+            "2500",
+            "--exact-in",
+            "10000",
+        ]);
+        let quote = run(&args).unwrap();
+        assert_eq!(quote.amount_in, 10_000);
+        assert_eq!(quote.trade_fee, 25);
+        assert!(Args::try_parse_from(["invariant-quote", "--exact-in", "1"]).is_err());
+    }
+
+    #[test]
+    fn decode_account_data() {
+        let state = PoolState {
+            token_0_reserve: 7,
+            ..PoolState::default()
+        };
+        let mut data = vec![0xff; 8];
+        data.extend(borsh::to_vec(&state).unwrap());
+        assert_eq!(decode_pool_state(&data, 8), Ok(state));
+        assert!(decode_pool_state(&data, 100).is_err());
+    }
+
+    #[test]
+    fn stable_pool_accounts_quote_on_their_curve() {
+        let params = CurveParams::Stable(AmpRamp::new(10_000).unwrap());
+        let mut state = PoolState {
+            fee_config: FeeConfig::new(FeeRate::from_bps(30).unwrap(), FeeRate::ZERO),
+            token_0_reserve: 1_000_000,
+            token_1_reserve: 1_000_000,
+            ..PoolState::default()
+        };
+        state.set_curve_params(params);
+        let mut data = vec![0xff; 8];
+        data.extend(borsh::to_vec(&state).unwrap());
+        let state = decode_pool_state(&data, 8).unwrap();
+
+        let args = |amount: &str| {
+            Args::parse_from([
+                "invariant-quote",
+                "--pool",
+                "11111111111111111111111111111111",
+                "--rpc-url",
+                "http://localhost:8899",
+                amount,
+                "100000",
+            ])
+        };
+        let stable = quote(&state, &args("--exact-in"), 0).unwrap();
+        let result = params
+            .swap_exact_in(
+                0,
+                TradeDirection::ZeroForOne,
+                100_000,
+                state.reserves(),
+                state.fee_config,
+            )
+            .unwrap();
+        assert_eq!(stable.amount_out, result.destination_amount_swapped);
+        // the constant product curve pays far less for the same trade
+        let constant_product = PoolState {
+            curve_type: CurveType::ConstantProduct,
+            curve_params: CurveParams::ConstantProduct.pack(),
+            ..state
+        };
+        let constant_product = quote(&constant_product, &args("--exact-in"), 0).unwrap();
+        assert!(constant_product.amount_out < stable.amount_out);
+
+        assert!(quote(&state, &args("--exact-out"), 0).is_err());
+        let mut custom = state;
+        custom.curve_type = CurveType::Custom(200);
+        assert!(quote(&custom, &args("--exact-in"), 0).is_err());
+    }
+}
@@ -0,0 +1,19 @@
+//! `invariant-server`: run the HTTP JSON quoting service.
+//!
+//! Listens on the address given as the first argument, `127.0.0.1:8080` by
+//! default. See `curve::server` for the endpoints.
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let address = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "127.0.0.1:8080".to_string());
+    eprintln!("serving quotes on {address}");
+    match curve::server::serve(&address) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
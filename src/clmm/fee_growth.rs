@@ -0,0 +1,130 @@
+//! Fee growth inside a tick range
+//!
+//! The pool only tracks fee growth globally and, per initialized tick, on
+//! the side of the tick away from the price. Growth inside a range is the
+//! global growth less the growth below its lower tick and above its upper
+//! tick. The counters wrap, and so does this arithmetic: only differences
+//! between two readings for the same range are meaningful, which is all
+//! `Position::update` needs.
+use crate::clmm::ClmmPool;
+
+/// Fee growth per unit of liquidity inside `[tick_lower, tick_upper]`, in
+/// Q64.64, for one token.
+///
+/// `global` is the pool's fee growth and `outside` the fee growth outside
+/// the lower and upper ticks, as recorded on the ticks.
+pub fn fee_growth_inside(
+    tick_lower: i32,
+    tick_upper: i32,
+    current_tick: i32,
+    global: u128,
+    outside: (u128, u128),
+) -> u128 {
+    let (outside_lower, outside_upper) = outside;
+    let below = if current_tick >= tick_lower {
+        outside_lower
+    } else {
+        global.wrapping_sub(outside_lower)
+    };
+    let above = if current_tick < tick_upper {
+        outside_upper
+    } else {
+        global.wrapping_sub(outside_upper)
+    };
+    global.wrapping_sub(below).wrapping_sub(above)
+}
+
+impl ClmmPool {
+    /// Fee growth per unit of liquidity inside `[tick_lower, tick_upper]`
+    /// for token 0 and token 1, in Q64.64.
+    ///
+    /// Returns `None` if either tick is not initialized.
+    pub fn fee_growth_inside(&self, tick_lower: i32, tick_upper: i32) -> Option<(u128, u128)> {
+        let (lower, upper) = (self.ticks.get(&tick_lower)?, self.ticks.get(&tick_upper)?);
+        Some((
+            fee_growth_inside(
+                tick_lower,
+                tick_upper,
+                self.tick,
+                self.fee_growth_global_0,
+                (lower.fee_growth_outside_0, upper.fee_growth_outside_0),
+            ),
+            fee_growth_inside(
+                tick_lower,
+                tick_upper,
+                self.tick,
+                self.fee_growth_global_1,
+                (lower.fee_growth_outside_1, upper.fee_growth_outside_1),
+            ),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            clmm::{Position, sqrt_price_at_tick},
+            curve::{
+                calculator::TradeDirection,
+                fees::{FeeConfig, FeeRate},
+            },
+            utils::{PriceQ64, price::Q64},
+        },
+    };
+
+    #[test]
+    fn growth_by_position_of_the_price() {
+        let outside = (10 * Q64, 3 * Q64);
+        // in range: everything not below or above
+        assert_eq!(fee_growth_inside(-10, 10, 0, 20 * Q64, outside), 7 * Q64);
+        // below the range: the lower tick's outside growth is above it
+        assert_eq!(
+            fee_growth_inside(-10, 10, -20, 20 * Q64, (17 * Q64, 3 * Q64)),
+            14 * Q64
+        );
+        // above the range: the upper tick's outside growth is below it
+        assert_eq!(
+            fee_growth_inside(-10, 10, 10, 20 * Q64, (2 * Q64, 15 * Q64)),
+            13 * Q64
+        );
+        // wrapped counters still give the right difference
+        let before = fee_growth_inside(-10, 10, 0, u128::MAX, (u128::MAX - Q64, 0));
+        let after = fee_growth_inside(-10, 10, 0, Q64, (u128::MAX - Q64, 0));
+        assert_eq!(after.wrapping_sub(before), Q64 + 1);
+    }
+
+    #[test]
+    fn positions_earn_only_in_range() {
+        let fee_config = FeeConfig::new(FeeRate::from_bps(30).unwrap(), FeeRate::ZERO);
+        let mut pool = ClmmPool::new(PriceQ64::ONE, 10, fee_config).unwrap();
+        let ranges = [(-100, 100), (200, 300)];
+        let mut positions = ranges.map(|(lower, upper)| {
+            pool.modify_liquidity(lower, upper, 1_000_000_000).unwrap();
+            let (growth_0, growth_1) = pool.fee_growth_inside(lower, upper).unwrap();
+            let mut position = Position::new(lower, upper).unwrap();
+            position.update(1_000_000_000, growth_0, growth_1).unwrap();
+            position
+        });
+
+        // stays inside the first range
+        pool.swap_exact_in(
+            TradeDirection::ZeroForOne,
+            1_000_000,
+            sqrt_price_at_tick(-50).unwrap(),
+        )
+        .unwrap();
+        for position in &mut positions {
+            let (growth_0, growth_1) = pool
+                .fee_growth_inside(position.tick_lower, position.tick_upper)
+                .unwrap();
+            position.update(0, growth_0, growth_1).unwrap();
+        }
+        // the only position in range earns the whole fee, less rounding
+        assert_eq!(positions[0].tokens_owed_0, 2_999);
+        assert_eq!(positions[0].tokens_owed_1, 0);
+        assert_eq!(positions[1].tokens_owed_0, 0);
+        assert_eq!(pool.fee_growth_inside(-100, 0), None);
+    }
+}
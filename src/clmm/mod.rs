@@ -0,0 +1,46 @@
+//! Concentrated liquidity
+//!
+//! Building blocks for pools whose liquidity is provided over price ranges
+//! rather than the whole curve. Prices are quantized into ticks, each a
+//! 0.01% step: the price of token 0 in token 1 at tick `i` is `1.0001^i`.
+pub mod fee_growth;
+pub mod pool;
+pub mod position;
+pub mod range_order;
+pub mod swap;
+pub mod tick_bitmap;
+pub mod tick_math;
+
+pub use {
+    fee_growth::*, pool::*, position::*, range_order::*, swap::*, tick_bitmap::*, tick_math::*,
+};
+
+use crate::{
+    curve::calculator::RoundDirection,
+    utils::{U256, price::to_u128},
+};
+
+/// Lowest tick whose square root price fits a Q64.64 value
+pub const MIN_TICK: i32 = -443_636;
+
+/// Highest tick whose square root price fits a Q64.64 value
+pub const MAX_TICK: i32 = 443_636;
+
+// `a * b / denominator`, rounded in `round_direction`. Returns `None` if the
+// denominator is zero or the product or result overflows.
+pub(crate) fn mul_div(
+    a: U256,
+    b: U256,
+    denominator: U256,
+    round_direction: RoundDirection,
+) -> Option<u128> {
+    if denominator.is_zero() {
+        return None;
+    }
+    let product = a.checked_mul(b)?;
+    let mut quotient = product / denominator;
+    if round_direction == RoundDirection::Ceiling && !(product % denominator).is_zero() {
+        quotient = quotient + 1;
+    }
+    to_u128(quotient)
+}
@@ -0,0 +1,201 @@
+//! Concentrated liquidity pool state
+//!
+//! Tracks the current price and in-range liquidity, and for each
+//! initialized tick the liquidity that starts or stops being in range when
+//! the price crosses it.
+use {
+    crate::{
+        clmm::{TickBitmap, amounts_for_liquidity, tick_at_sqrt_price},
+        curve::{
+            amount::{Reserves, Token0Amount, Token1Amount, TokenAmounts},
+            calculator::RoundDirection,
+            fees::FeeConfig,
+        },
+        utils::PriceQ64,
+    },
+    alloc::collections::BTreeMap,
+};
+
+/// Liquidity boundary at an initialized tick
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Tick {
+    /// Liquidity of all positions bounded by the tick
+    pub liquidity_gross: u128,
+    /// Liquidity that comes into range when the price crosses the tick
+    /// upward, and goes out of range when it crosses downward
+    pub liquidity_net: i128,
+    /// Fee growth of token 0 per unit of liquidity on the other side of the
+    /// tick from the current price, in Q64.64
+    pub fee_growth_outside_0: u128,
+    /// Fee growth of token 1 per unit of liquidity on the other side of the
+    /// tick from the current price, in Q64.64
+    pub fee_growth_outside_1: u128,
+}
+
+/// A concentrated liquidity pool
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClmmPool {
+    /// Square root of the price of token 0 in token 1
+    pub sqrt_price: PriceQ64,
+    /// Highest tick at or below the current price
+    pub tick: i32,
+    /// Liquidity of the positions in range
+    pub liquidity: u128,
+    pub fee_config: FeeConfig,
+    /// Tokens held by the pool, including fees not yet collected
+    pub balances: Reserves,
+    /// Fee growth of token 0 per unit of liquidity since the pool was
+    /// created, in Q64.64; wraps on overflow
+    pub fee_growth_global_0: u128,
+    /// Fee growth of token 1 per unit of liquidity since the pool was
+    /// created, in Q64.64; wraps on overflow
+    pub fee_growth_global_1: u128,
+    /// Token 0 fees owed to the protocol
+    pub protocol_fees_0: u128,
+    /// Token 1 fees owed to the protocol
+    pub protocol_fees_1: u128,
+    /// Initialized ticks
+    pub ticks: BTreeMap<i32, Tick>,
+    pub tick_bitmap: TickBitmap,
+}
+
+impl ClmmPool {
+    /// An empty pool at square root price `sqrt_price`.
+    ///
+    /// Returns `None` if the price is out of range or `tick_spacing` is zero.
+    pub fn new(sqrt_price: PriceQ64, tick_spacing: u16, fee_config: FeeConfig) -> Option<Self> {
+        Some(Self {
+            sqrt_price,
+            tick: tick_at_sqrt_price(sqrt_price)?,
+            liquidity: 0,
+            fee_config,
+            balances: Reserves::default(),
+            fee_growth_global_0: 0,
+            fee_growth_global_1: 0,
+            protocol_fees_0: 0,
+            protocol_fees_1: 0,
+            ticks: BTreeMap::new(),
+            tick_bitmap: TickBitmap::new(tick_spacing)?,
+        })
+    }
+
+    // Add `liquidity_delta` to the positions bounded by `tick`, as their
+    // upper bound if `upper`
+    fn update_tick(&mut self, tick: i32, liquidity_delta: i128, upper: bool) -> Option<()> {
+        let mut info = self.ticks.get(&tick).copied().unwrap_or_default();
+        let was_initialized = info.liquidity_gross > 0;
+        info.liquidity_gross = info.liquidity_gross.checked_add_signed(liquidity_delta)?;
+        info.liquidity_net = if upper {
+            info.liquidity_net.checked_sub(liquidity_delta)?
+        } else {
+            info.liquidity_net.checked_add(liquidity_delta)?
+        };
+        if !was_initialized && tick <= self.tick {
+            // by convention, all growth so far happened below the tick
+            info.fee_growth_outside_0 = self.fee_growth_global_0;
+            info.fee_growth_outside_1 = self.fee_growth_global_1;
+        }
+        if was_initialized != (info.liquidity_gross > 0) {
+            self.tick_bitmap.flip_tick(tick)?;
+        }
+        if info.liquidity_gross == 0 {
+            self.ticks.remove(&tick);
+        } else {
+            self.ticks.insert(tick, info);
+        }
+        Some(())
+    }
+
+    /// Add `liquidity_delta` over `[tick_lower, tick_upper]`, or remove it
+    /// if negative, moving the tokens backing it into or out of the pool.
+    ///
+    /// Returns the tokens paid in, rounded up, or paid out, rounded down.
+    /// Returns `None`, leaving the pool unchanged, if a tick is not usable,
+    /// more liquidity is removed than the ticks hold, or an amount
+    /// overflows.
+    pub fn modify_liquidity(
+        &mut self,
+        tick_lower: i32,
+        tick_upper: i32,
+        liquidity_delta: i128,
+    ) -> Option<TokenAmounts> {
+        let round_direction = if liquidity_delta >= 0 {
+            RoundDirection::Ceiling
+        } else {
+            RoundDirection::Floor
+        };
+        let amounts = amounts_for_liquidity(
+            self.sqrt_price,
+            tick_lower,
+            tick_upper,
+            liquidity_delta.unsigned_abs(),
+            round_direction,
+        )?;
+        let mut pool = self.clone();
+        pool.update_tick(tick_lower, liquidity_delta, false)?;
+        pool.update_tick(tick_upper, liquidity_delta, true)?;
+        if (tick_lower..tick_upper).contains(&pool.tick) {
+            pool.liquidity = pool.liquidity.checked_add_signed(liquidity_delta)?;
+        }
+        let (token_0, token_1) = (pool.balances.token_0.get(), pool.balances.token_1.get());
+        pool.balances = if liquidity_delta >= 0 {
+            Reserves::new(
+                Token0Amount(token_0.checked_add(amounts.token_0.get())?),
+                Token1Amount(token_1.checked_add(amounts.token_1.get())?),
+            )
+        } else {
+            Reserves::new(
+                Token0Amount(token_0.checked_sub(amounts.token_0.get())?),
+                Token1Amount(token_1.checked_sub(amounts.token_1.get())?),
+            )
+        };
+        *self = pool;
+        Some(amounts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::clmm::sqrt_price_at_tick};
+
+    fn pool() -> ClmmPool {
+        ClmmPool::new(PriceQ64::ONE, 10, FeeConfig::default()).unwrap()
+    }
+
+    #[test]
+    fn add_and_remove_liquidity() {
+        let mut pool = pool();
+        assert_eq!(pool.tick, 0);
+        let paid = pool.modify_liquidity(-100, 100, 1_000_000).unwrap();
+        assert_eq!(pool.liquidity, 1_000_000);
+        assert!(paid.token_0.get() > 0 && paid.token_1.get() > 0);
+        assert_eq!(pool.balances, Reserves::new(paid.token_0, paid.token_1));
+        // out of range liquidity is all token 1 below the price
+        let paid_below = pool.modify_liquidity(-200, -100, 1_000_000).unwrap();
+        assert_eq!(paid_below.token_0.get(), 0);
+        assert_eq!(pool.liquidity, 1_000_000);
+        assert_eq!(pool.ticks[&-100].liquidity_net, 0);
+        assert_eq!(pool.ticks[&-100].liquidity_gross, 2_000_000);
+        assert!(pool.tick_bitmap.is_initialized(-200));
+
+        // a tick that is not a multiple of the spacing leaves the pool as is
+        let before = pool.clone();
+        assert_eq!(pool.modify_liquidity(-100, 105, 1), None);
+        assert_eq!(pool.modify_liquidity(-100, 100, -1_000_001), None);
+        assert_eq!(pool, before);
+
+        let received = pool.modify_liquidity(-100, 100, -1_000_000).unwrap();
+        assert!(paid.token_0.get() - received.token_0.get() <= 1);
+        assert!(paid.token_1.get() - received.token_1.get() <= 1);
+        pool.modify_liquidity(-200, -100, -1_000_000).unwrap();
+        assert_eq!(pool.liquidity, 0);
+        assert!(pool.ticks.is_empty());
+        assert_eq!(pool.tick_bitmap, TickBitmap::new(10).unwrap());
+        assert_eq!(
+            ClmmPool::new(sqrt_price_at_tick(5).unwrap(), 10, FeeConfig::default())
+                .unwrap()
+                .tick,
+            5
+        );
+    }
+}
@@ -0,0 +1,345 @@
+//! Concentrated liquidity positions
+//!
+//! Converts between a position's liquidity `L` over `[tick_lower,
+//! tick_upper]` and the tokens backing it. Between square root prices `a <
+//! b`, `L` is backed by `L * (b - a) / (a * b)` of token 0 and `L * (b - a)`
+//! of token 1. Every conversion rounds in the pool's favour: liquidity from
+//! deposited amounts rounds down, amounts owed for minted liquidity round up,
+//! and amounts paid out for burned liquidity or fees round down.
+use crate::{
+    clmm::{mul_div, sqrt_price_at_tick},
+    curve::{
+        amount::{Token0Amount, Token1Amount, TokenAmounts},
+        calculator::RoundDirection,
+    },
+    utils::{PriceQ64, U256, price::Q64_RESOLUTION},
+};
+
+// Square root prices at the bounds of a range, if it is valid
+fn range_sqrt_prices(tick_lower: i32, tick_upper: i32) -> Option<(PriceQ64, PriceQ64)> {
+    if tick_lower >= tick_upper {
+        return None;
+    }
+    Some((
+        sqrt_price_at_tick(tick_lower)?,
+        sqrt_price_at_tick(tick_upper)?,
+    ))
+}
+
+fn sorted(sqrt_price_a: PriceQ64, sqrt_price_b: PriceQ64) -> (U256, U256) {
+    let (lower, upper) = if sqrt_price_a <= sqrt_price_b {
+        (sqrt_price_a, sqrt_price_b)
+    } else {
+        (sqrt_price_b, sqrt_price_a)
+    };
+    (U256::from(lower.raw()), U256::from(upper.raw()))
+}
+
+/// Token 0 backing `liquidity` between two square root prices, in either
+/// order.
+///
+/// Returns `None` if a price is zero or the amount overflows.
+pub fn amount_0_delta(
+    sqrt_price_a: PriceQ64,
+    sqrt_price_b: PriceQ64,
+    liquidity: u128,
+    round_direction: RoundDirection,
+) -> Option<u128> {
+    let (lower, upper) = sorted(sqrt_price_a, sqrt_price_b);
+    if lower.is_zero() {
+        return None;
+    }
+    let numerator = U256::from(liquidity) << Q64_RESOLUTION;
+    mul_div(numerator, upper - lower, upper * lower, round_direction)
+}
+
+/// Token 1 backing `liquidity` between two square root prices, in either
+/// order.
+///
+/// Returns `None` if the amount overflows.
+pub fn amount_1_delta(
+    sqrt_price_a: PriceQ64,
+    sqrt_price_b: PriceQ64,
+    liquidity: u128,
+    round_direction: RoundDirection,
+) -> Option<u128> {
+    let (lower, upper) = sorted(sqrt_price_a, sqrt_price_b);
+    mul_div(
+        U256::from(liquidity),
+        upper - lower,
+        U256::one() << Q64_RESOLUTION,
+        round_direction,
+    )
+}
+
+// Liquidity `amount_0` of token 0 provides between two prices, rounded down
+fn liquidity_for_amount_0(lower: PriceQ64, upper: PriceQ64, amount_0: u128) -> Option<u128> {
+    let (lower, upper) = sorted(lower, upper);
+    let product = (lower * upper) >> Q64_RESOLUTION;
+    mul_div(
+        U256::from(amount_0),
+        product,
+        upper - lower,
+        RoundDirection::Floor,
+    )
+}
+
+// Liquidity `amount_1` of token 1 provides between two prices, rounded down
+fn liquidity_for_amount_1(lower: PriceQ64, upper: PriceQ64, amount_1: u128) -> Option<u128> {
+    let (lower, upper) = sorted(lower, upper);
+    mul_div(
+        U256::from(amount_1),
+        U256::one() << Q64_RESOLUTION,
+        upper - lower,
+        RoundDirection::Floor,
+    )
+}
+
+/// Most liquidity over `[tick_lower, tick_upper]` that `amounts` can back at
+/// square root price `sqrt_price`, rounded down.
+///
+/// Below the range only token 0 counts, above it only token 1, and within it
+/// the scarcer of the two. Returns `None` if the range is invalid or the
+/// liquidity overflows.
+pub fn liquidity_for_amounts(
+    sqrt_price: PriceQ64,
+    tick_lower: i32,
+    tick_upper: i32,
+    amounts: TokenAmounts,
+) -> Option<u128> {
+    let (lower, upper) = range_sqrt_prices(tick_lower, tick_upper)?;
+    let (amount_0, amount_1) = (amounts.token_0.get(), amounts.token_1.get());
+    if sqrt_price <= lower {
+        liquidity_for_amount_0(lower, upper, amount_0)
+    } else if sqrt_price < upper {
+        Some(
+            liquidity_for_amount_0(sqrt_price, upper, amount_0)?
+                .min(liquidity_for_amount_1(lower, sqrt_price, amount_1)?),
+        )
+    } else {
+        liquidity_for_amount_1(lower, upper, amount_1)
+    }
+}
+
+/// Tokens backing `liquidity` over `[tick_lower, tick_upper]` at square root
+/// price `sqrt_price`.
+///
+/// Round up for the amounts owed when minting and down for the amounts paid
+/// out when burning. Returns `None` if the range is invalid or an amount
+/// overflows.
+pub fn amounts_for_liquidity(
+    sqrt_price: PriceQ64,
+    tick_lower: i32,
+    tick_upper: i32,
+    liquidity: u128,
+    round_direction: RoundDirection,
+) -> Option<TokenAmounts> {
+    let (lower, upper) = range_sqrt_prices(tick_lower, tick_upper)?;
+    let current = sqrt_price.clamp(lower, upper);
+    Some(TokenAmounts {
+        token_0: Token0Amount(amount_0_delta(current, upper, liquidity, round_direction)?),
+        token_1: Token1Amount(amount_1_delta(lower, current, liquidity, round_direction)?),
+    })
+}
+
+/// Liquidity over a tick range and the fees it has earned
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Position {
+    pub tick_lower: i32,
+    pub tick_upper: i32,
+    pub liquidity: u128,
+    /// Fee growth of token 0 inside the range per unit of liquidity at the
+    /// last update, in Q64.64
+    pub fee_growth_inside_0_last: u128,
+    /// Fee growth of token 1 inside the range per unit of liquidity at the
+    /// last update, in Q64.64
+    pub fee_growth_inside_1_last: u128,
+    /// Token 0 fees credited and not yet collected
+    pub tokens_owed_0: u128,
+    /// Token 1 fees credited and not yet collected
+    pub tokens_owed_1: u128,
+}
+
+// Fees earned by `liquidity` while fee growth moved from `last` to `current`.
+// Fee growth counters wrap, so their difference does too.
+fn fees_earned(liquidity: u128, last: u128, current: u128) -> Option<u128> {
+    mul_div(
+        U256::from(current.wrapping_sub(last)),
+        U256::from(liquidity),
+        U256::one() << Q64_RESOLUTION,
+        RoundDirection::Floor,
+    )
+}
+
+impl Position {
+    /// An empty position over `[tick_lower, tick_upper]`, or `None` if the
+    /// range is invalid
+    pub fn new(tick_lower: i32, tick_upper: i32) -> Option<Self> {
+        range_sqrt_prices(tick_lower, tick_upper)?;
+        Some(Self {
+            tick_lower,
+            tick_upper,
+            ..Self::default()
+        })
+    }
+
+    /// Tokens backing the position at `sqrt_price`, rounded as
+    /// `amounts_for_liquidity`
+    pub fn amounts(
+        &self,
+        sqrt_price: PriceQ64,
+        round_direction: RoundDirection,
+    ) -> Option<TokenAmounts> {
+        amounts_for_liquidity(
+            sqrt_price,
+            self.tick_lower,
+            self.tick_upper,
+            self.liquidity,
+            round_direction,
+        )
+    }
+
+    /// Credit the fees earned since the last update, given the current fee
+    /// growth inside the range, then add `liquidity_delta`.
+    ///
+    /// Returns `None`, leaving the position unchanged, if the liquidity
+    /// would go negative or overflow, an amount owed overflows, or the
+    /// position is empty and stays empty.
+    pub fn update(
+        &mut self,
+        liquidity_delta: i128,
+        fee_growth_inside_0: u128,
+        fee_growth_inside_1: u128,
+    ) -> Option<()> {
+        if self.liquidity == 0 && liquidity_delta == 0 {
+            return None;
+        }
+        let liquidity = self.liquidity.checked_add_signed(liquidity_delta)?;
+        let owed_0 = fees_earned(
+            self.liquidity,
+            self.fee_growth_inside_0_last,
+            fee_growth_inside_0,
+        )?;
+        let owed_1 = fees_earned(
+            self.liquidity,
+            self.fee_growth_inside_1_last,
+            fee_growth_inside_1,
+        )?;
+        let tokens_owed_0 = self.tokens_owed_0.checked_add(owed_0)?;
+        let tokens_owed_1 = self.tokens_owed_1.checked_add(owed_1)?;
+        *self = Self {
+            liquidity,
+            fee_growth_inside_0_last: fee_growth_inside_0,
+            fee_growth_inside_1_last: fee_growth_inside_1,
+            tokens_owed_0,
+            tokens_owed_1,
+            ..*self
+        };
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            clmm::{MAX_TICK, MIN_TICK},
+            utils::price::Q64,
+        },
+        proptest::prelude::*,
+    };
+
+    fn amounts(token_0: u128, token_1: u128) -> TokenAmounts {
+        TokenAmounts {
+            token_0: Token0Amount(token_0),
+            token_1: Token1Amount(token_1),
+        }
+    }
+
+    #[test]
+    fn single_sided_outside_the_range() {
+        let below = sqrt_price_at_tick(-200).unwrap();
+        let above = sqrt_price_at_tick(200).unwrap();
+        let liquidity = liquidity_for_amounts(below, -100, 100, amounts(1_000_000, 0)).unwrap();
+        let owed =
+            amounts_for_liquidity(below, -100, 100, liquidity, RoundDirection::Ceiling).unwrap();
+        assert_eq!(owed.token_1.get(), 0);
+        assert!(owed.token_0.get() <= 1_000_000);
+        assert_eq!(
+            liquidity_for_amounts(above, -100, 100, amounts(1_000_000, 0)),
+            Some(0)
+        );
+        let owed =
+            amounts_for_liquidity(above, -100, 100, liquidity, RoundDirection::Floor).unwrap();
+        assert_eq!(owed.token_0.get(), 0);
+        // about 1% of token 0 per tick of range, at a price of 1
+        assert!(owed.token_1.get().abs_diff(1_000_000) < 1_000);
+        assert_eq!(liquidity_for_amounts(below, 100, 100, amounts(1, 1)), None);
+        assert_eq!(
+            liquidity_for_amounts(below, MIN_TICK - 1, 0, amounts(1, 1)),
+            None
+        );
+    }
+
+    #[test]
+    fn full_range_matches_constant_product() {
+        // over the whole range, liquidity is about sqrt(x * y)
+        let liquidity = liquidity_for_amounts(
+            PriceQ64::from_integer(2),
+            MIN_TICK,
+            MAX_TICK,
+            amounts(1_000_000, 4_000_000),
+        )
+        .unwrap();
+        assert!(liquidity.abs_diff(2_000_000) < 10);
+    }
+
+    #[test]
+    fn accrue_fees() {
+        let mut position = Position::new(-60, 60).unwrap();
+        assert_eq!(position.update(0, 0, 0), None);
+        position.update(1_000, u128::MAX - Q64, 0).unwrap();
+        assert_eq!(position.tokens_owed_0, 0);
+        // fee growth wraps past zero: two units of token 0 per unit of
+        // liquidity, and half a unit of token 1
+        position.update(0, Q64, Q64 / 2).unwrap();
+        assert_eq!(position.tokens_owed_0, 2_000);
+        assert_eq!(position.tokens_owed_1, 500);
+        assert_eq!(position.update(-1_001, Q64, Q64), None);
+        position.update(-1_000, Q64, Q64 / 2).unwrap();
+        assert_eq!(position.liquidity, 0);
+        assert_eq!(position.tokens_owed_0, 2_000);
+        assert_eq!(Position::new(60, -60), None);
+    }
+
+    proptest! {
+        #[test]
+        fn round_trip_never_pays_out_more(
+            tick_lower in -10_000i32..10_000,
+            width in 1i32..10_000,
+            tick in -20_000i32..20_000,
+            amount_0 in 0u128..1 << 64,
+            amount_1 in 0u128..1 << 64,
+        ) {
+            let tick_upper = tick_lower + width;
+            let sqrt_price = sqrt_price_at_tick(tick).unwrap();
+            let deposit = amounts(amount_0, amount_1);
+            let liquidity =
+                liquidity_for_amounts(sqrt_price, tick_lower, tick_upper, deposit).unwrap();
+            let minted = amounts_for_liquidity(
+                sqrt_price, tick_lower, tick_upper, liquidity, RoundDirection::Ceiling,
+            )
+            .unwrap();
+            let burned = amounts_for_liquidity(
+                sqrt_price, tick_lower, tick_upper, liquidity, RoundDirection::Floor,
+            )
+            .unwrap();
+            // the deposit covers the liquidity, and burning it returns no more
+            prop_assert!(minted.token_0.get() <= amount_0);
+            prop_assert!(minted.token_1.get() <= amount_1);
+            prop_assert!(burned.token_0.get() <= minted.token_0.get());
+            prop_assert!(burned.token_1.get() <= minted.token_1.get());
+        }
+    }
+}
@@ -0,0 +1,234 @@
+//! Range orders
+//!
+//! A position entirely on one side of the price holds a single token and
+//! converts it into the other as the price crosses the range, like a limit
+//! order filled gradually over one tick spacing. Unlike a limit order it
+//! converts back if the price returns, so the position should be withdrawn
+//! once filled.
+use crate::{
+    clmm::{amount_0_delta, amount_1_delta, liquidity_for_amounts, sqrt_price_at_tick},
+    curve::{
+        amount::{Token0Amount, Token1Amount, TokenAmounts},
+        calculator::{RoundDirection, TradeDirection},
+    },
+    utils::PriceQ64,
+};
+
+/// Denominator of `RangeOrderFill::filled_bps`
+pub const RANGE_ORDER_BPS_DENOMINATOR: u128 = 10_000;
+
+/// A single-sided position of one tick spacing
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RangeOrder {
+    /// Token 0 is sold for token 1 if `ZeroForOne`, as the price rises
+    /// through the range, and bought with it if `OneForZero`, as the price
+    /// falls
+    pub trade_direction: TradeDirection,
+    pub tick_lower: i32,
+    pub tick_upper: i32,
+    pub liquidity: u128,
+}
+
+/// How far a range order has converted
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FillStatus {
+    /// The price has not entered the range
+    Open,
+    /// The price is inside the range
+    PartiallyFilled,
+    /// The price has crossed the whole range
+    Filled,
+}
+
+/// Fill of a range order at some price
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RangeOrderFill {
+    pub status: FillStatus,
+    /// Source tokens not yet converted, rounded down
+    pub remaining: u128,
+    /// Destination tokens converted so far, rounded down
+    pub converted: u128,
+    /// `converted` in basis points of the destination tokens a full fill
+    /// receives, rounded down
+    pub filled_bps: u16,
+}
+
+impl RangeOrder {
+    /// Place an order selling `amount` of the source token of
+    /// `trade_direction` in the first range of `tick_spacing` ticks beyond
+    /// `sqrt_price`, on the side the source token is held.
+    ///
+    /// Returns `None` if the tick spacing is zero, the range would be out of
+    /// bounds, or the liquidity overflows.
+    pub fn new(
+        sqrt_price: PriceQ64,
+        current_tick: i32,
+        tick_spacing: u16,
+        trade_direction: TradeDirection,
+        amount: u128,
+    ) -> Option<Self> {
+        let tick_spacing = i32::from(tick_spacing);
+        if tick_spacing == 0 {
+            return None;
+        }
+        let (tick_lower, amounts) = match trade_direction {
+            // the first usable tick above the price
+            TradeDirection::ZeroForOne => (
+                (current_tick.div_euclid(tick_spacing) + 1) * tick_spacing,
+                TokenAmounts {
+                    token_0: Token0Amount(amount),
+                    token_1: Token1Amount(0),
+                },
+            ),
+            // ending at the last usable tick at or below the price
+            TradeDirection::OneForZero => (
+                (current_tick.div_euclid(tick_spacing) - 1) * tick_spacing,
+                TokenAmounts {
+                    token_0: Token0Amount(0),
+                    token_1: Token1Amount(amount),
+                },
+            ),
+        };
+        let tick_upper = tick_lower + tick_spacing;
+        Some(Self {
+            trade_direction,
+            tick_lower,
+            tick_upper,
+            liquidity: liquidity_for_amounts(sqrt_price, tick_lower, tick_upper, amounts)?,
+        })
+    }
+
+    /// Fill of the order with the pool at `current_tick`.
+    ///
+    /// Within the range the price is taken as the tick's, so a partial fill
+    /// is resolved to the tick. Returns `None` if a tick is out of bounds.
+    pub fn fill(&self, current_tick: i32) -> Option<RangeOrderFill> {
+        let lower = sqrt_price_at_tick(self.tick_lower)?;
+        let upper = sqrt_price_at_tick(self.tick_upper)?;
+        let tick = current_tick.clamp(self.tick_lower, self.tick_upper);
+        let price = sqrt_price_at_tick(tick)?;
+        let liquidity = self.liquidity;
+        let round = RoundDirection::Floor;
+        let (remaining, converted, full, status) = match self.trade_direction {
+            TradeDirection::ZeroForOne => (
+                amount_0_delta(price, upper, liquidity, round)?,
+                amount_1_delta(lower, price, liquidity, round)?,
+                amount_1_delta(lower, upper, liquidity, round)?,
+                if current_tick >= self.tick_upper {
+                    FillStatus::Filled
+                } else if current_tick >= self.tick_lower && price > lower {
+                    FillStatus::PartiallyFilled
+                } else {
+                    FillStatus::Open
+                },
+            ),
+            TradeDirection::OneForZero => (
+                amount_1_delta(lower, price, liquidity, round)?,
+                amount_0_delta(price, upper, liquidity, round)?,
+                amount_0_delta(lower, upper, liquidity, round)?,
+                if current_tick < self.tick_lower {
+                    FillStatus::Filled
+                } else if current_tick < self.tick_upper && price < upper {
+                    FillStatus::PartiallyFilled
+                } else {
+                    FillStatus::Open
+                },
+            ),
+        };
+        let filled_bps = match status {
+            FillStatus::Open => 0,
+            FillStatus::Filled => RANGE_ORDER_BPS_DENOMINATOR,
+            FillStatus::PartiallyFilled => converted
+                .checked_mul(RANGE_ORDER_BPS_DENOMINATOR)?
+                .checked_div(full)?
+                .min(RANGE_ORDER_BPS_DENOMINATOR),
+        };
+        Some(RangeOrderFill {
+            status,
+            remaining,
+            converted,
+            filled_bps: filled_bps as u16,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            clmm::{ClmmPool, tick_at_sqrt_price},
+            curve::fees::FeeConfig,
+        },
+    };
+
+    #[test]
+    fn place_beyond_the_price() {
+        let price = sqrt_price_at_tick(-15).unwrap();
+        let sell = RangeOrder::new(price, -15, 10, TradeDirection::ZeroForOne, 1_000_000).unwrap();
+        assert_eq!((sell.tick_lower, sell.tick_upper), (-10, 0));
+        let buy = RangeOrder::new(price, -15, 10, TradeDirection::OneForZero, 1_000_000).unwrap();
+        assert_eq!((buy.tick_lower, buy.tick_upper), (-30, -20));
+
+        let open = sell.fill(-15).unwrap();
+        assert_eq!(open.status, FillStatus::Open);
+        assert_eq!((open.converted, open.filled_bps), (0, 0));
+        assert!(open.remaining.abs_diff(1_000_000) <= 1);
+
+        let half = sell.fill(-5).unwrap();
+        assert_eq!(half.status, FillStatus::PartiallyFilled);
+        assert!((4_900..=5_100).contains(&half.filled_bps));
+
+        let filled = sell.fill(3).unwrap();
+        assert_eq!(filled.status, FillStatus::Filled);
+        assert_eq!(filled.remaining, 0);
+        assert_eq!(filled.filled_bps, 10_000);
+        // about a million token 0 at a price just under 1
+        assert!(filled.converted.abs_diff(999_500) < 100);
+
+        assert_eq!(buy.fill(-20).unwrap().status, FillStatus::Open);
+        assert_eq!(buy.fill(-25).unwrap().status, FillStatus::PartiallyFilled);
+        assert_eq!(buy.fill(-31).unwrap().status, FillStatus::Filled);
+        assert_eq!(
+            RangeOrder::new(price, -15, 0, TradeDirection::ZeroForOne, 1),
+            None
+        );
+    }
+
+    #[test]
+    fn fill_through_a_swap() {
+        let mut pool = ClmmPool::new(PriceQ64::ONE, 10, FeeConfig::default()).unwrap();
+        pool.modify_liquidity(-1_000, 1_000, 1_000_000_000).unwrap();
+        let order = RangeOrder::new(
+            pool.sqrt_price,
+            pool.tick,
+            10,
+            TradeDirection::ZeroForOne,
+            1_000_000,
+        )
+        .unwrap();
+        let paid = pool
+            .modify_liquidity(order.tick_lower, order.tick_upper, order.liquidity as i128)
+            .unwrap();
+        assert_eq!(paid.token_1.get(), 0);
+
+        pool.swap_exact_in(
+            TradeDirection::OneForZero,
+            100_000_000,
+            sqrt_price_at_tick(50).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(tick_at_sqrt_price(pool.sqrt_price), Some(50));
+        let fill = order.fill(pool.tick).unwrap();
+        assert_eq!(fill.status, FillStatus::Filled);
+        let received = pool
+            .modify_liquidity(
+                order.tick_lower,
+                order.tick_upper,
+                -(order.liquidity as i128),
+            )
+            .unwrap();
+        assert_eq!(received.token_0.get(), 0);
+        assert_eq!(received.token_1.get(), fill.converted);
+    }
+}
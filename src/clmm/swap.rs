@@ -0,0 +1,476 @@
+//! Concentrated liquidity swaps
+//!
+//! An exact-input swap walks the price toward the limit one step at a time.
+//! Each step trades against the in-range liquidity as a constant product
+//! pool would, up to the next initialized tick, the limit, or the end of the
+//! input. When the price reaches an initialized tick, the tick is crossed
+//! and the liquidity it bounds enters or leaves the range.
+use {
+    crate::{
+        clmm::{
+            ClmmPool, MAX_SQRT_PRICE, MAX_TICK, MIN_SQRT_PRICE, MIN_TICK, amount_0_delta,
+            amount_1_delta, mul_div, sqrt_price_at_tick, tick_at_sqrt_price,
+        },
+        curve::{
+            amount::Reserves,
+            calculator::{RoundDirection, SwapResult, TradeDirection},
+            fees::{FEE_RATE_DENOMINATOR_VALUE, FeeRate, Fees},
+        },
+        utils::{PriceQ64, U256, price::Q64_RESOLUTION},
+    },
+    alloc::vec::Vec,
+};
+
+/// One step of a swap, within a single range of constant liquidity
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SwapStep {
+    /// Square root price at the start of the step
+    pub sqrt_price_start: PriceQ64,
+    /// Square root price at the end of the step
+    pub sqrt_price_end: PriceQ64,
+    /// Tick the step traded toward
+    pub tick_next: i32,
+    /// Whether `tick_next` is initialized and was crossed
+    pub crossed: bool,
+    /// Liquidity in range during the step
+    pub liquidity: u128,
+    /// Source tokens paid in, excluding fees
+    pub amount_in: u128,
+    /// Destination tokens paid out
+    pub amount_out: u128,
+    /// Trade fee charged on the step, including the protocol's share
+    pub fee_amount: u128,
+}
+
+/// Outcome of a swap with the steps that produced it
+#[derive(Debug, PartialEq)]
+pub struct SwapTrace {
+    /// Steps in order
+    pub steps: Vec<SwapStep>,
+    /// The whole swap, against the pool's token balances. Concentrated
+    /// liquidity rounds within each step, so `rounding_dust` is zero.
+    pub result: SwapResult,
+}
+
+// Square root price after trading `amount` of the source token against
+// `liquidity`, rounded so the price moves no further than the amount pays for
+fn next_sqrt_price_from_input(
+    sqrt_price: PriceQ64,
+    liquidity: u128,
+    amount: u128,
+    zero_for_one: bool,
+) -> Option<PriceQ64> {
+    if liquidity == 0 {
+        return None;
+    }
+    let price = U256::from(sqrt_price.raw());
+    let liquidity = U256::from(liquidity);
+    let raw = if zero_for_one {
+        // L * P / (L + amount * P), rounded up
+        let numerator = liquidity << Q64_RESOLUTION;
+        let denominator = numerator.checked_add(U256::from(amount).checked_mul(price)?)?;
+        mul_div(numerator, price, denominator, RoundDirection::Ceiling)?
+    } else {
+        // P + amount / L, rounded down
+        let delta = mul_div(
+            U256::from(amount),
+            U256::one() << Q64_RESOLUTION,
+            liquidity,
+            RoundDirection::Floor,
+        )?;
+        sqrt_price.raw().checked_add(delta)?
+    };
+    Some(PriceQ64::from_raw(raw))
+}
+
+// Trade up to `amount_remaining`, fees included, from `sqrt_price` toward
+// `sqrt_price_target`. Returns the price reached, the amounts in and out and
+// the fee.
+fn compute_swap_step(
+    sqrt_price: PriceQ64,
+    sqrt_price_target: PriceQ64,
+    liquidity: u128,
+    amount_remaining: u128,
+    trade_fee_rate: FeeRate,
+) -> Option<(PriceQ64, u128, u128, u128)> {
+    let zero_for_one = sqrt_price_target < sqrt_price;
+    let fee_rate = u128::from(trade_fee_rate.get());
+    let fee_denominator = u128::from(FEE_RATE_DENOMINATOR_VALUE);
+    let amount_less_fee = mul_div(
+        U256::from(amount_remaining),
+        U256::from(fee_denominator - fee_rate),
+        U256::from(fee_denominator),
+        RoundDirection::Floor,
+    )?;
+    let amount_in_to_target = if zero_for_one {
+        amount_0_delta(
+            sqrt_price_target,
+            sqrt_price,
+            liquidity,
+            RoundDirection::Ceiling,
+        )?
+    } else {
+        amount_1_delta(
+            sqrt_price,
+            sqrt_price_target,
+            liquidity,
+            RoundDirection::Ceiling,
+        )?
+    };
+    let reaches_target = amount_less_fee >= amount_in_to_target;
+    let sqrt_price_next = if reaches_target {
+        sqrt_price_target
+    } else {
+        next_sqrt_price_from_input(sqrt_price, liquidity, amount_less_fee, zero_for_one)?
+    };
+    let (amount_in, amount_out) = if zero_for_one {
+        (
+            amount_0_delta(
+                sqrt_price_next,
+                sqrt_price,
+                liquidity,
+                RoundDirection::Ceiling,
+            )?,
+            amount_1_delta(
+                sqrt_price_next,
+                sqrt_price,
+                liquidity,
+                RoundDirection::Floor,
+            )?,
+        )
+    } else {
+        (
+            amount_1_delta(
+                sqrt_price,
+                sqrt_price_next,
+                liquidity,
+                RoundDirection::Ceiling,
+            )?,
+            amount_0_delta(
+                sqrt_price,
+                sqrt_price_next,
+                liquidity,
+                RoundDirection::Floor,
+            )?,
+        )
+    };
+    let fee_amount = if reaches_target {
+        mul_div(
+            U256::from(amount_in),
+            U256::from(fee_rate),
+            U256::from(fee_denominator - fee_rate),
+            RoundDirection::Ceiling,
+        )?
+    } else {
+        // whatever the input could not move the price with is kept as fee
+        amount_remaining.checked_sub(amount_in)?
+    };
+    Some((sqrt_price_next, amount_in, amount_out, fee_amount))
+}
+
+impl ClmmPool {
+    /// Swap exactly `source_amount` in `trade_direction`, stopping early if
+    /// the square root price reaches `sqrt_price_limit`.
+    ///
+    /// Updates the pool and returns the trace of the swap. Returns `None`,
+    /// leaving the pool unchanged, if the limit is on the wrong side of the
+    /// price or beyond the price range, the trade fee is 100%, or an amount
+    /// overflows.
+    pub fn swap_exact_in(
+        &mut self,
+        trade_direction: TradeDirection,
+        source_amount: u128,
+        sqrt_price_limit: PriceQ64,
+    ) -> Option<SwapTrace> {
+        let zero_for_one = trade_direction == TradeDirection::ZeroForOne;
+        let limit_valid = if zero_for_one {
+            sqrt_price_limit < self.sqrt_price && sqrt_price_limit > MIN_SQRT_PRICE
+        } else {
+            sqrt_price_limit > self.sqrt_price && sqrt_price_limit < MAX_SQRT_PRICE
+        };
+        if !limit_valid || self.fee_config.trade_fee_rate == FeeRate::MAX {
+            return None;
+        }
+
+        let mut pool = self.clone();
+        let mut steps = Vec::new();
+        let mut amount_remaining = source_amount;
+        let (mut amount_out, mut trade_fee, mut protocol_fee) = (0u128, 0u128, 0u128);
+        while amount_remaining > 0 && pool.sqrt_price != sqrt_price_limit {
+            let (tick_next, initialized) = pool
+                .tick_bitmap
+                .next_initialized_tick_within_one_word(pool.tick, zero_for_one);
+            let tick_next = tick_next.clamp(MIN_TICK, MAX_TICK);
+            let sqrt_price_next = sqrt_price_at_tick(tick_next)?;
+            let sqrt_price_target = if zero_for_one {
+                sqrt_price_next.max(sqrt_price_limit)
+            } else {
+                sqrt_price_next.min(sqrt_price_limit)
+            };
+            let sqrt_price_start = pool.sqrt_price;
+            let (sqrt_price_end, amount_in, step_out, fee_amount) = compute_swap_step(
+                sqrt_price_start,
+                sqrt_price_target,
+                pool.liquidity,
+                amount_remaining,
+                pool.fee_config.trade_fee_rate,
+            )?;
+            amount_remaining = amount_remaining
+                .checked_sub(amount_in)?
+                .checked_sub(fee_amount)?;
+            amount_out = amount_out.checked_add(step_out)?;
+            trade_fee = trade_fee.checked_add(fee_amount)?;
+
+            let step_protocol_fee =
+                Fees::protocol_fee(fee_amount, pool.fee_config.protocol_fee_rate.get())?;
+            protocol_fee = protocol_fee.checked_add(step_protocol_fee)?;
+            if pool.liquidity > 0 {
+                let growth = mul_div(
+                    U256::from(fee_amount - step_protocol_fee),
+                    U256::one() << Q64_RESOLUTION,
+                    U256::from(pool.liquidity),
+                    RoundDirection::Floor,
+                )?;
+                if zero_for_one {
+                    pool.fee_growth_global_0 = pool.fee_growth_global_0.wrapping_add(growth);
+                } else {
+                    pool.fee_growth_global_1 = pool.fee_growth_global_1.wrapping_add(growth);
+                }
+            }
+
+            pool.sqrt_price = sqrt_price_end;
+            let crossed = initialized && sqrt_price_end == sqrt_price_next;
+            steps.push(SwapStep {
+                sqrt_price_start,
+                sqrt_price_end,
+                tick_next,
+                crossed,
+                liquidity: pool.liquidity,
+                amount_in,
+                amount_out: step_out,
+                fee_amount,
+            });
+            if sqrt_price_end == sqrt_price_next {
+                if crossed {
+                    let liquidity_net = pool.cross_tick(tick_next)?;
+                    let liquidity_net = if zero_for_one {
+                        liquidity_net.checked_neg()?
+                    } else {
+                        liquidity_net
+                    };
+                    pool.liquidity = pool.liquidity.checked_add_signed(liquidity_net)?;
+                }
+                pool.tick = if zero_for_one {
+                    tick_next - 1
+                } else {
+                    tick_next
+                };
+            } else if sqrt_price_end != sqrt_price_start {
+                pool.tick = tick_at_sqrt_price(sqrt_price_end)?;
+            }
+        }
+
+        let amount_in = source_amount - amount_remaining;
+        let (swap_source_amount, swap_destination_amount) =
+            pool.balances.source_and_destination(trade_direction);
+        let result = SwapResult {
+            new_swap_source_amount: swap_source_amount.checked_add(amount_in)?,
+            new_swap_destination_amount: swap_destination_amount.checked_sub(amount_out)?,
+            source_amount_swapped: amount_in,
+            destination_amount_swapped: amount_out,
+            trade_fee,
+            protocol_fee,
+            destination_trade_fee: 0,
+            destination_protocol_fee: 0,
+            rounding_dust: 0,
+        };
+        pool.balances = Reserves::from_source_and_destination(
+            trade_direction,
+            result.new_swap_source_amount,
+            result.new_swap_destination_amount,
+        );
+        if zero_for_one {
+            pool.protocol_fees_0 = pool.protocol_fees_0.checked_add(protocol_fee)?;
+        } else {
+            pool.protocol_fees_1 = pool.protocol_fees_1.checked_add(protocol_fee)?;
+        }
+        *self = pool;
+        Some(SwapTrace { steps, result })
+    }
+
+    // Cross an initialized tick: flip its fee growth to the other side of
+    // the price and return its net liquidity
+    fn cross_tick(&mut self, tick: i32) -> Option<i128> {
+        let info = self.ticks.get_mut(&tick)?;
+        info.fee_growth_outside_0 = self
+            .fee_growth_global_0
+            .wrapping_sub(info.fee_growth_outside_0);
+        info.fee_growth_outside_1 = self
+            .fee_growth_global_1
+            .wrapping_sub(info.fee_growth_outside_1);
+        Some(info.liquidity_net)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::curve::{
+            amount::{Token0Amount, Token1Amount},
+            calculator::CurveCalculator,
+            fees::{FeeConfig, FeeRate},
+        },
+        proptest::prelude::*,
+    };
+
+    fn fee_config(bps: u16) -> FeeConfig {
+        FeeConfig::new(
+            FeeRate::from_bps(bps).unwrap(),
+            FeeRate::from_percent(10).unwrap(),
+        )
+    }
+
+    fn full_range_pool(liquidity: i128, trade_fee_bps: u16) -> ClmmPool {
+        let mut pool = ClmmPool::new(PriceQ64::ONE, 1, fee_config(trade_fee_bps)).unwrap();
+        pool.modify_liquidity(MIN_TICK, MAX_TICK, liquidity)
+            .unwrap();
+        pool
+    }
+
+    #[test]
+    fn full_range_matches_constant_product() {
+        let mut pool = full_range_pool(1_000_000_000, 30);
+        let balances = pool.balances;
+        let trace = pool
+            .swap_exact_in(
+                TradeDirection::ZeroForOne,
+                10_000_000,
+                MIN_SQRT_PRICE
+                    .checked_mul(PriceQ64::from_integer(2))
+                    .unwrap(),
+            )
+            .unwrap();
+        let expected = CurveCalculator::swap_exact_in(
+            TradeDirection::ZeroForOne,
+            10_000_000,
+            balances,
+            fee_config(30),
+        )
+        .unwrap();
+        // no ticks to cross, but the search moves a bitmap word at a time
+        assert!(trace.steps.iter().all(|step| !step.crossed));
+        assert_eq!(trace.result.source_amount_swapped, 10_000_000);
+        assert!(
+            trace
+                .result
+                .destination_amount_swapped
+                .abs_diff(expected.destination_amount_swapped)
+                <= 2
+        );
+        assert_eq!(trace.result.trade_fee, 30_000);
+        assert_eq!(trace.result.protocol_fee, 3_000);
+        assert_eq!(pool.protocol_fees_0, 3_000);
+        assert_eq!(
+            pool.balances,
+            Reserves::new(
+                Token0Amount(trace.result.new_swap_source_amount),
+                Token1Amount(trace.result.new_swap_destination_amount)
+            )
+        );
+        assert_eq!(pool.tick, tick_at_sqrt_price(pool.sqrt_price).unwrap());
+        // the LPs' 90% of the fee, spread over the liquidity
+        assert_eq!(pool.fee_growth_global_0 >> Q64_RESOLUTION, 0);
+        assert_eq!(
+            mul_div(
+                U256::from(pool.fee_growth_global_0),
+                U256::from(1_000_000_000u128),
+                U256::one() << Q64_RESOLUTION,
+                RoundDirection::Floor
+            ),
+            Some(26_999)
+        );
+    }
+
+    #[test]
+    fn cross_ticks_and_stop_at_limit() {
+        let mut pool = ClmmPool::new(PriceQ64::ONE, 10, fee_config(0)).unwrap();
+        pool.modify_liquidity(-100, 100, 1_000_000).unwrap();
+        pool.modify_liquidity(-300, -100, 5_000_000).unwrap();
+        let limit = sqrt_price_at_tick(-200).unwrap();
+        let trace = pool
+            .swap_exact_in(TradeDirection::ZeroForOne, u64::MAX.into(), limit)
+            .unwrap();
+        assert_eq!(pool.sqrt_price, limit);
+        assert_eq!(pool.tick, -200);
+        assert_eq!(pool.liquidity, 5_000_000);
+        let crossed: Vec<_> = trace.steps.iter().filter(|step| step.crossed).collect();
+        assert_eq!(crossed.len(), 1);
+        assert_eq!(crossed[0].tick_next, -100);
+        assert_eq!(crossed[0].liquidity, 1_000_000);
+        assert_eq!(trace.steps.last().unwrap().liquidity, 5_000_000);
+        let amount_in: u128 = trace.steps.iter().map(|step| step.amount_in).sum();
+        assert_eq!(trace.result.source_amount_swapped, amount_in);
+        assert!(trace.result.source_amount_swapped < u64::MAX.into());
+
+        // swap back up across both ticks
+        let limit = sqrt_price_at_tick(150).unwrap();
+        let trace = pool
+            .swap_exact_in(TradeDirection::OneForZero, u64::MAX.into(), limit)
+            .unwrap();
+        assert_eq!(
+            trace
+                .steps
+                .iter()
+                .filter(|step| step.crossed)
+                .map(|step| step.tick_next)
+                .collect::<Vec<_>>(),
+            [-100, 100]
+        );
+        assert_eq!(pool.liquidity, 0);
+        assert_eq!(pool.tick, 150);
+
+        let before = pool.clone();
+        assert_eq!(
+            pool.swap_exact_in(TradeDirection::OneForZero, 1, PriceQ64::ONE),
+            None
+        );
+        assert_eq!(
+            pool.swap_exact_in(TradeDirection::ZeroForOne, 1, MIN_SQRT_PRICE),
+            None
+        );
+        assert_eq!(pool, before);
+    }
+
+    proptest! {
+        #[test]
+        fn swap_conserves_balances(
+            amount in 1u128..1 << 40,
+            zero_for_one in any::<bool>(),
+            trade_fee_bps in 0u16..1_000,
+        ) {
+            let mut pool = ClmmPool::new(PriceQ64::ONE, 10, fee_config(trade_fee_bps)).unwrap();
+            pool.modify_liquidity(-1_000, 1_000, 1 << 40).unwrap();
+            pool.modify_liquidity(-100, 500, 1 << 42).unwrap();
+            pool.modify_liquidity(-5_000, -1_000, 1 << 38).unwrap();
+            let (trade_direction, limit) = if zero_for_one {
+                (TradeDirection::ZeroForOne, sqrt_price_at_tick(-4_000).unwrap())
+            } else {
+                (TradeDirection::OneForZero, sqrt_price_at_tick(4_000).unwrap())
+            };
+            let before = pool.balances;
+            let trace = pool.swap_exact_in(trade_direction, amount, limit).unwrap();
+            let steps_in: u128 = trace.steps.iter().map(|step| step.amount_in + step.fee_amount).sum();
+            prop_assert_eq!(steps_in, trace.result.source_amount_swapped);
+            prop_assert!(trace.result.source_amount_swapped <= amount);
+            // the pool still holds enough to pay out every position
+            let (source, destination) = before.source_and_destination(trade_direction);
+            prop_assert!(trace.result.destination_amount_swapped <= destination);
+            prop_assert_eq!(trace.result.new_swap_source_amount, source + trace.result.source_amount_swapped);
+            for (lower, upper, liquidity) in [(-1_000, 1_000, 1 << 40), (-100, 500, 1 << 42), (-5_000, -1_000, 1 << 38)] {
+                pool.modify_liquidity(lower, upper, -liquidity).unwrap();
+            }
+            prop_assert_eq!(pool.liquidity, 0);
+        }
+    }
+}
@@ -0,0 +1,263 @@
+//! Tick bitmap
+//!
+//! Records which ticks hold liquidity boundaries, one bit per usable tick.
+//! Ticks are compressed by the pool's tick spacing and packed 256 to a
+//! word, so the swap loop finds the next tick to cross by scanning whole
+//! words instead of single ticks. Empty words are not stored.
+use {
+    crate::{
+        clmm::{MAX_TICK, MIN_TICK},
+        utils::U256,
+    },
+    alloc::collections::BTreeMap,
+};
+
+/// Initialized ticks of a pool
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TickBitmap {
+    tick_spacing: u16,
+    words: BTreeMap<i16, U256>,
+}
+
+// Word and bit of a compressed tick
+fn position(compressed: i32) -> (i16, u8) {
+    ((compressed >> 8) as i16, (compressed & 0xff) as u8)
+}
+
+// Bits at or below `bit`
+fn mask_at_or_below(bit: u8) -> U256 {
+    let bit = U256::one() << bit;
+    (bit - 1) | bit
+}
+
+// Bits at or above `bit`
+fn mask_at_or_above(bit: u8) -> U256 {
+    !((U256::one() << bit) - 1)
+}
+
+fn most_significant_bit(word: U256) -> i32 {
+    255 - word.leading_zeros() as i32
+}
+
+fn least_significant_bit(word: U256) -> i32 {
+    word.trailing_zeros() as i32
+}
+
+impl TickBitmap {
+    /// An empty bitmap, or `None` if `tick_spacing` is zero
+    pub fn new(tick_spacing: u16) -> Option<Self> {
+        (tick_spacing > 0).then(|| Self {
+            tick_spacing,
+            words: BTreeMap::new(),
+        })
+    }
+
+    pub const fn tick_spacing(&self) -> u16 {
+        self.tick_spacing
+    }
+
+    // Tick divided by the spacing, rounded toward negative infinity
+    fn compress(&self, tick: i32) -> i32 {
+        tick.div_euclid(self.tick_spacing.into())
+    }
+
+    /// Whether `tick` is a multiple of the tick spacing within
+    /// `MIN_TICK..=MAX_TICK`
+    pub fn is_usable(&self, tick: i32) -> bool {
+        (MIN_TICK..=MAX_TICK).contains(&tick) && tick % i32::from(self.tick_spacing) == 0
+    }
+
+    /// Whether `tick` is initialized
+    pub fn is_initialized(&self, tick: i32) -> bool {
+        if !self.is_usable(tick) {
+            return false;
+        }
+        let (word_pos, bit) = position(self.compress(tick));
+        self.words
+            .get(&word_pos)
+            .is_some_and(|word| word.bit(bit.into()))
+    }
+
+    /// Flip `tick` between initialized and uninitialized.
+    ///
+    /// Returns `None` if the tick is not usable.
+    pub fn flip_tick(&mut self, tick: i32) -> Option<()> {
+        if !self.is_usable(tick) {
+            return None;
+        }
+        let (word_pos, bit) = position(self.compress(tick));
+        let word = self.words.entry(word_pos).or_default();
+        *word ^= U256::one() << bit;
+        if word.is_zero() {
+            self.words.remove(&word_pos);
+        }
+        Some(())
+    }
+
+    /// Next initialized tick in the word of `tick`: the highest at or below
+    /// `tick` if `lte`, else the lowest above it.
+    ///
+    /// If the word holds none, returns the last tick of the word in the
+    /// direction of the search, with `false`, so a caller can stop there and
+    /// continue from the next word.
+    pub fn next_initialized_tick_within_one_word(&self, tick: i32, lte: bool) -> (i32, bool) {
+        let tick_spacing = i32::from(self.tick_spacing);
+        let compressed = self.compress(tick);
+        if lte {
+            let (word_pos, bit) = position(compressed);
+            let masked = self.word(word_pos) & mask_at_or_below(bit);
+            let bit = i32::from(bit);
+            if masked.is_zero() {
+                ((compressed - bit) * tick_spacing, false)
+            } else {
+                (
+                    (compressed - bit + most_significant_bit(masked)) * tick_spacing,
+                    true,
+                )
+            }
+        } else {
+            let (word_pos, bit) = position(compressed + 1);
+            let masked = self.word(word_pos) & mask_at_or_above(bit);
+            let bit = i32::from(bit);
+            if masked.is_zero() {
+                ((compressed + 1 + 255 - bit) * tick_spacing, false)
+            } else {
+                (
+                    (compressed + 1 - bit + least_significant_bit(masked)) * tick_spacing,
+                    true,
+                )
+            }
+        }
+    }
+
+    /// Next initialized tick in any word: the highest at or below `tick` if
+    /// `lte`, else the lowest above it.
+    ///
+    /// Returns `None` if there is none.
+    pub fn next_initialized_tick(&self, tick: i32, lte: bool) -> Option<i32> {
+        let tick_spacing = i32::from(self.tick_spacing);
+        let compressed = self.compress(tick);
+        let compressed = if lte {
+            let (word_pos, bit) = position(compressed);
+            self.words
+                .range(..=word_pos)
+                .rev()
+                .find_map(|(&pos, &word)| {
+                    let word = if pos == word_pos {
+                        word & mask_at_or_below(bit)
+                    } else {
+                        word
+                    };
+                    (!word.is_zero()).then(|| i32::from(pos) * 256 + most_significant_bit(word))
+                })
+        } else {
+            let (word_pos, bit) = position(compressed + 1);
+            self.words.range(word_pos..).find_map(|(&pos, &word)| {
+                let word = if pos == word_pos {
+                    word & mask_at_or_above(bit)
+                } else {
+                    word
+                };
+                (!word.is_zero()).then(|| i32::from(pos) * 256 + least_significant_bit(word))
+            })
+        }?;
+        Some(compressed * tick_spacing)
+    }
+
+    fn word(&self, word_pos: i16) -> U256 {
+        self.words.get(&word_pos).copied().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, alloc::collections::BTreeSet, proptest::prelude::*};
+
+    fn bitmap(tick_spacing: u16, ticks: &BTreeSet<i32>) -> TickBitmap {
+        let mut bitmap = TickBitmap::new(tick_spacing).unwrap();
+        for &tick in ticks {
+            bitmap.flip_tick(tick).unwrap();
+        }
+        bitmap
+    }
+
+    #[test]
+    fn flip_ticks() {
+        let mut bitmap = TickBitmap::new(10).unwrap();
+        assert_eq!(bitmap.flip_tick(15), None);
+        assert_eq!(bitmap.flip_tick(MAX_TICK + 4), None);
+        bitmap.flip_tick(-2_570).unwrap();
+        bitmap.flip_tick(20).unwrap();
+        assert!(bitmap.is_initialized(-2_570));
+        assert!(!bitmap.is_initialized(-2_560));
+        assert_eq!(bitmap.next_initialized_tick(0, true), Some(-2_570));
+        assert_eq!(bitmap.next_initialized_tick(20, true), Some(20));
+        assert_eq!(bitmap.next_initialized_tick(20, false), None);
+        assert_eq!(bitmap.next_initialized_tick(-3_000, false), Some(-2_570));
+        // -2_570 compresses to -257, outside the words of 0 and -10
+        assert_eq!(
+            bitmap.next_initialized_tick_within_one_word(0, true),
+            (0, false)
+        );
+        assert_eq!(
+            bitmap.next_initialized_tick_within_one_word(-10, true),
+            (-2_560, false)
+        );
+        bitmap.flip_tick(20).unwrap();
+        assert!(!bitmap.is_initialized(20));
+        assert_eq!(bitmap.next_initialized_tick(-2_570, false), None);
+        bitmap.flip_tick(-2_570).unwrap();
+        assert_eq!(bitmap, TickBitmap::new(10).unwrap());
+        assert_eq!(TickBitmap::new(0), None);
+    }
+
+    fn ticks() -> impl Strategy<Value = (u16, BTreeSet<i32>, i32)> {
+        (1u16..=60).prop_flat_map(|tick_spacing| {
+            let compressed = -3_000i32..3_000;
+            (
+                Just(tick_spacing),
+                prop::collection::btree_set(
+                    compressed
+                        .clone()
+                        .prop_map(move |c| c * i32::from(tick_spacing)),
+                    0..20,
+                ),
+                compressed.prop_map(move |c| c * i32::from(tick_spacing) + c % 7),
+            )
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn next_initialized_tick_matches_linear_scan((tick_spacing, ticks, tick) in ticks()) {
+            let bitmap = bitmap(tick_spacing, &ticks);
+            let below = ticks.iter().rev().find(|&&t| t <= tick).copied();
+            let above = ticks.iter().find(|&&t| t > tick).copied();
+            prop_assert_eq!(bitmap.next_initialized_tick(tick, true), below);
+            prop_assert_eq!(bitmap.next_initialized_tick(tick, false), above);
+        }
+
+        #[test]
+        fn within_one_word_matches_linear_scan((tick_spacing, ticks, tick) in ticks()) {
+            let bitmap = bitmap(tick_spacing, &ticks);
+            let spacing = i32::from(tick_spacing);
+            let compressed = tick.div_euclid(spacing);
+
+            // search down to the start of the word of the tick
+            let word_start = compressed.div_euclid(256) * 256 * spacing;
+            let expected = match ticks.iter().rev().find(|&&t| t <= tick) {
+                Some(&t) if t >= word_start => (t, true),
+                _ => (word_start, false),
+            };
+            prop_assert_eq!(bitmap.next_initialized_tick_within_one_word(tick, true), expected);
+
+            // search up to the end of the word of the next tick
+            let word_end = ((compressed + 1).div_euclid(256) * 256 + 255) * spacing;
+            let expected = match ticks.iter().find(|&&t| t > tick) {
+                Some(&t) if t <= word_end => (t, true),
+                _ => (word_end, false),
+            };
+            prop_assert_eq!(bitmap.next_initialized_tick_within_one_word(tick, false), expected);
+        }
+    }
+}
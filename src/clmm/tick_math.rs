@@ -0,0 +1,130 @@
+//! Conversions between ticks and square root prices
+//!
+//! The square root price at a tick is `1.0001^(tick / 2)`, computed by
+//! multiplying together precomputed powers of `1.0001^(-1/2)` for each set
+//! bit of the tick, in Q128.128, and rounded up to Q64.64 at the end. The
+//! result is the same on every platform, so it matches the on-chain math.
+use crate::{
+    clmm::{MAX_TICK, MIN_TICK},
+    utils::{PriceQ64, U256, price::to_u128},
+};
+
+// `2^128 / 1.0001^(2^i / 2)`, rounded, for bit `i` of the absolute tick
+const TICK_RATIOS: [u128; 19] = [
+    0xfffcb933bd6fad37aa2d162d1a594001,
+    0xfff97272373d413259a46990580e213a,
+    0xfff2e50f5f656932ef12357cf3c7fdcc,
+    0xffe5caca7e10e4e61c3624eaa0941cd0,
+    0xffcb9843d60f6159c9db58835c926644,
+    0xff973b41fa98c081472e6896dfb254c0,
+    0xff2ea16466c96a3843ec78b326b52861,
+    0xfe5dee046a99a2a811c461f1969c3053,
+    0xfcbe86c7900a88aedcffc83b479aa3a4,
+    0xf987a7253ac413176f2b074cf7815e54,
+    0xf3392b0822b70005940c7a398e4b70f3,
+    0xe7159475a2c29b7443b29c7fa6e889d9,
+    0xd097f3bdfd2022b8845ad8f792aa5825,
+    0xa9f746462d870fdf8a65dc1f90e061e5,
+    0x70d869a156d2a1b890bb3df62baf32f7,
+    0x31be135f97d08fd981231505542fcfa6,
+    0x9aa508b5b7a84e1c677de54f3e99bc9,
+    0x5d6af8dedb81196699c329225ee604,
+    0x2216e584f5fa1ea926041bedfe98,
+];
+
+/// Square root price at `MIN_TICK`
+pub const MIN_SQRT_PRICE: PriceQ64 = PriceQ64::from_raw(4_295_048_017);
+
+/// Square root price at `MAX_TICK`
+pub const MAX_SQRT_PRICE: PriceQ64 = PriceQ64::from_raw(79_226_673_515_401_279_992_447_579_062);
+
+/// Square root of the price of token 0 in token 1 at `tick`, in Q64.64,
+/// rounded up.
+///
+/// Returns `None` if the tick is outside `MIN_TICK..=MAX_TICK`.
+pub fn sqrt_price_at_tick(tick: i32) -> Option<PriceQ64> {
+    if !(MIN_TICK..=MAX_TICK).contains(&tick) {
+        return None;
+    }
+    let abs_tick = tick.unsigned_abs();
+    let mut ratio = U256::one() << 128;
+    for (bit, &tick_ratio) in TICK_RATIOS.iter().enumerate() {
+        if abs_tick & (1 << bit) != 0 {
+            ratio = (ratio * U256::from(tick_ratio)) >> 128;
+        }
+    }
+    if tick > 0 {
+        ratio = U256::MAX / ratio;
+    }
+    let remainder = !(ratio % (U256::one() << 64)).is_zero();
+    let sqrt_price = (ratio >> 64) + U256::from(u8::from(remainder));
+    to_u128(sqrt_price).map(PriceQ64::from_raw)
+}
+
+/// Highest tick whose square root price is at most `sqrt_price`.
+///
+/// Returns `None` if the price is outside `MIN_SQRT_PRICE..=MAX_SQRT_PRICE`.
+pub fn tick_at_sqrt_price(sqrt_price: PriceQ64) -> Option<i32> {
+    if !(MIN_SQRT_PRICE..=MAX_SQRT_PRICE).contains(&sqrt_price) {
+        return None;
+    }
+    // binary search on the monotonic `sqrt_price_at_tick`
+    let (mut low, mut high) = (MIN_TICK, MAX_TICK);
+    while low < high {
+        let middle = low + (high - low + 1) / 2;
+        if sqrt_price_at_tick(middle)? <= sqrt_price {
+            low = middle;
+        } else {
+            high = middle - 1;
+        }
+    }
+    Some(low)
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::utils::price::Q64, proptest::prelude::*};
+
+    #[test]
+    fn sqrt_price_bounds() {
+        assert_eq!(sqrt_price_at_tick(0), Some(PriceQ64::ONE));
+        assert_eq!(sqrt_price_at_tick(MIN_TICK), Some(MIN_SQRT_PRICE));
+        assert_eq!(sqrt_price_at_tick(MAX_TICK), Some(MAX_SQRT_PRICE));
+        assert_eq!(sqrt_price_at_tick(MIN_TICK - 1), None);
+        assert_eq!(sqrt_price_at_tick(MAX_TICK + 1), None);
+        // the square root price spans 2^-32 to 2^32
+        assert_eq!(MIN_SQRT_PRICE.raw() >> 32, 1);
+        assert_eq!(MAX_SQRT_PRICE.to_integer_floor() >> 31, 1);
+        // the price crosses 2 between ticks 6931 and 6932
+        let sqrt_two = sqrt_price_at_tick(6_931).unwrap();
+        assert_eq!(
+            sqrt_two.checked_mul(sqrt_two).unwrap().to_integer_floor(),
+            1
+        );
+        let sqrt_two = sqrt_price_at_tick(6_932).unwrap();
+        assert_eq!(
+            sqrt_two.checked_mul(sqrt_two).unwrap().to_integer_floor(),
+            2
+        );
+        assert_eq!(tick_at_sqrt_price(PriceQ64::ONE), Some(0));
+        assert_eq!(tick_at_sqrt_price(PriceQ64::from_raw(Q64 - 1)), Some(-1));
+        assert_eq!(tick_at_sqrt_price(MIN_SQRT_PRICE), Some(MIN_TICK));
+        assert_eq!(tick_at_sqrt_price(MAX_SQRT_PRICE), Some(MAX_TICK));
+        assert_eq!(
+            tick_at_sqrt_price(PriceQ64::from_raw(MIN_SQRT_PRICE.raw() - 1)),
+            None
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn tick_round_trip(tick in MIN_TICK..MAX_TICK) {
+            let sqrt_price = sqrt_price_at_tick(tick).unwrap();
+            let next = sqrt_price_at_tick(tick + 1).unwrap();
+            prop_assert!(sqrt_price < next);
+            prop_assert_eq!(tick_at_sqrt_price(sqrt_price), Some(tick));
+            let between = PriceQ64::from_raw(next.raw() - 1);
+            prop_assert_eq!(tick_at_sqrt_price(between), Some(tick));
+        }
+    }
+}
@@ -0,0 +1,643 @@
+//! Strongly-typed token amounts
+//!
+//! The raw calculator functions take pool reserves as positional `u128`s, so
+//! swapping the token 0 and token 1 arguments compiles and silently produces
+//! a wrong quote. The newtypes here tag every amount with the token it is
+//! denominated in, and the typed entry points on `CurveCalculator` take
+//! `Reserves` plus a `TradeDirection` instead of source/destination amounts.
+//!
+//! The newtypes are `#[repr(transparent)]` wrappers, so converting to and
+//! from `u128` is free; performance-critical callers can still use the raw
+//! functions re-exported from `curve::raw`.
+use {
+    crate::{
+        curve::{
+            calculator::{CurveCalculator, RoundDirection, SwapResult, TradeDirection},
+            context::ContextError,
+            fees::{FeeConfig, ProtocolFeeMode, TinySwapPolicy},
+            swap_params::SwapParams,
+        },
+        utils::{IntegerSquareRoot, PriceQ64, Q64_RESOLUTION, U256, price::to_u128},
+    },
+    borsh::{BorshDeserialize, BorshSerialize},
+};
+
+macro_rules! amount_newtype {
+    ($(#[$meta:meta])* $name:ident) => {
+        $(#[$meta])*
+        #[repr(transparent)]
+        #[derive(
+            Clone,
+            Copy,
+            Debug,
+            Default,
+            PartialEq,
+            Eq,
+            PartialOrd,
+            Ord,
+            Hash,
+            BorshSerialize,
+            BorshDeserialize,
+        )]
+        pub struct $name(pub u128);
+
+        impl $name {
+            pub const ZERO: Self = Self(0);
+
+            pub const fn new(amount: u128) -> Self {
+                Self(amount)
+            }
+
+            /// The raw amount
+            pub const fn get(self) -> u128 {
+                self.0
+            }
+
+            pub const fn checked_add(self, rhs: Self) -> Option<Self> {
+                match self.0.checked_add(rhs.0) {
+                    Some(amount) => Some(Self(amount)),
+                    None => None,
+                }
+            }
+
+            pub const fn checked_sub(self, rhs: Self) -> Option<Self> {
+                match self.0.checked_sub(rhs.0) {
+                    Some(amount) => Some(Self(amount)),
+                    None => None,
+                }
+            }
+        }
+
+        impl From<u128> for $name {
+            fn from(amount: u128) -> Self {
+                Self(amount)
+            }
+        }
+
+        impl From<u64> for $name {
+            fn from(amount: u64) -> Self {
+                Self(u128::from(amount))
+            }
+        }
+
+        impl From<$name> for u128 {
+            fn from(amount: $name) -> Self {
+                amount.0
+            }
+        }
+    };
+}
+
+amount_newtype!(
+    /// An amount of token 0
+    Token0Amount
+);
+amount_newtype!(
+    /// An amount of token 1
+    Token1Amount
+);
+amount_newtype!(
+    /// An amount of LP tokens
+    LpAmount
+);
+
+/// Token reserves held by a pool
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Reserves {
+    pub token_0: Token0Amount,
+    pub token_1: Token1Amount,
+}
+
+impl Reserves {
+    pub const fn new(token_0: Token0Amount, token_1: Token1Amount) -> Self {
+        Self { token_0, token_1 }
+    }
+
+    /// Reserves of raw `token_0` and `token_1` amounts, in that order
+    pub const fn from_raw(token_0: u128, token_1: u128) -> Self {
+        Self::new(Token0Amount(token_0), Token1Amount(token_1))
+    }
+
+    /// Reserves with spot price `price`, token 0 quoted in token 1, and
+    /// constant product `invariant`.
+    ///
+    /// Solves `token_1 / token_0 = price` and `token_0 * token_1 = invariant`.
+    /// Both amounts are rounded down, so the product never exceeds
+    /// `invariant`. Returns `None` if the price is zero or an amount overflows
+    /// u128.
+    pub fn from_price_and_invariant(price: PriceQ64, invariant: U256) -> Option<Self> {
+        if price == PriceQ64::ZERO {
+            return None;
+        }
+        let price = U256::from(price.raw());
+        let token_0 =
+            (invariant.checked_mul(U256::from(1u128 << Q64_RESOLUTION))? / price).sqrt_floor();
+        let token_1 = (invariant.checked_mul(price)? >> Q64_RESOLUTION).sqrt_floor();
+        Some(Self::new(
+            Token0Amount(to_u128(token_0)?),
+            Token1Amount(to_u128(token_1)?),
+        ))
+    }
+
+    /// Reserves with spot price `price`, token 0 quoted in token 1, worth
+    /// `total_value` of token 1, split evenly in value between the tokens.
+    ///
+    /// Both amounts are rounded down. Returns `None` if the price is zero.
+    pub fn from_price_and_value(price: PriceQ64, total_value: u128) -> Option<Self> {
+        let token_1 = total_value / 2;
+        let token_0 = price.div_amount(token_1, RoundDirection::Floor)?;
+        Some(Self::new(Token0Amount(token_0), Token1Amount(token_1)))
+    }
+
+    /// The `(source, destination)` reserves for a trade in `trade_direction`
+    pub const fn source_and_destination(self, trade_direction: TradeDirection) -> (u128, u128) {
+        match trade_direction {
+            TradeDirection::ZeroForOne => (self.token_0.0, self.token_1.0),
+            TradeDirection::OneForZero => (self.token_1.0, self.token_0.0),
+        }
+    }
+
+    /// The reserve of the token a trade in `trade_direction` pays in
+    pub const fn source(self, trade_direction: TradeDirection) -> u128 {
+        self.source_and_destination(trade_direction).0
+    }
+
+    /// The reserve of the token a trade in `trade_direction` pays out
+    pub const fn destination(self, trade_direction: TradeDirection) -> u128 {
+        self.source_and_destination(trade_direction).1
+    }
+
+    /// The reserve of token `index`, 0 or 1
+    pub const fn get(self, index: usize) -> Option<u128> {
+        match index {
+            0 => Some(self.token_0.0),
+            1 => Some(self.token_1.0),
+            _ => None,
+        }
+    }
+
+    /// Build reserves from `(source, destination)` amounts of a trade in
+    /// `trade_direction`
+    pub const fn from_source_and_destination(
+        trade_direction: TradeDirection,
+        source: u128,
+        destination: u128,
+    ) -> Self {
+        match trade_direction {
+            TradeDirection::ZeroForOne => {
+                Self::new(Token0Amount(source), Token1Amount(destination))
+            }
+            TradeDirection::OneForZero => {
+                Self::new(Token0Amount(destination), Token1Amount(source))
+            }
+        }
+    }
+}
+
+/// Token amounts for an amount of LP tokens
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TokenAmounts {
+    pub token_0: Token0Amount,
+    pub token_1: Token1Amount,
+}
+
+/// Size of a constant product pool
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoolValue {
+    /// The invariant `k = token_0 * token_1`
+    pub invariant: U256,
+    /// `sqrt(k)`, rounded down. Unlike the reserves it is unaffected by
+    /// price moves, so it grows only with fees, rounding and deposits.
+    pub normalized_value: u128,
+}
+
+impl CurveCalculator {
+    /// Quote a swap of exactly `source_amount` in `trade_direction`
+    pub fn swap_exact_in(
+        trade_direction: TradeDirection,
+        source_amount: u128,
+        reserves: Reserves,
+        fee_config: FeeConfig,
+    ) -> Option<SwapResult> {
+        Self::swap_base_input(&SwapParams::new(
+            trade_direction,
+            source_amount,
+            reserves,
+            fee_config,
+        ))
+    }
+
+    /// Quote a swap receiving exactly `destination_amount` in
+    /// `trade_direction`
+    pub fn swap_exact_out(
+        trade_direction: TradeDirection,
+        destination_amount: u128,
+        reserves: Reserves,
+        fee_config: FeeConfig,
+    ) -> Option<SwapResult> {
+        Self::swap_base_output(&SwapParams::new(
+            trade_direction,
+            destination_amount,
+            reserves,
+            fee_config,
+        ))
+    }
+
+    /// Like `swap_exact_in`, reporting why and where the swap cannot be
+    /// quoted
+    pub fn try_swap_exact_in(
+        trade_direction: TradeDirection,
+        source_amount: u128,
+        reserves: Reserves,
+        fee_config: FeeConfig,
+    ) -> Result<SwapResult, ContextError> {
+        Self::swap_base_input_in_context(
+            &SwapParams::new(trade_direction, source_amount, reserves, fee_config),
+            TinySwapPolicy::Clamp,
+        )
+    }
+
+    /// Like `swap_exact_out`, reporting why and where the swap cannot be
+    /// quoted
+    pub fn try_swap_exact_out(
+        trade_direction: TradeDirection,
+        destination_amount: u128,
+        reserves: Reserves,
+        fee_config: FeeConfig,
+    ) -> Result<SwapResult, ContextError> {
+        Self::swap_base_output_in_context(&SwapParams::new(
+            trade_direction,
+            destination_amount,
+            reserves,
+            fee_config,
+        ))
+    }
+
+    // Under `ProtocolFeeMode::Split`, keep half the protocol fee in the pool
+    // and sell it on the curve for destination tokens going to the protocol
+    // instead. The sale rounds down, so the invariant of the reserves
+    // excluding protocol fees does not decrease.
+    pub(crate) fn apply_protocol_fee_mode(
+        mut result: SwapResult,
+        protocol_fee_mode: ProtocolFeeMode,
+    ) -> Option<SwapResult> {
+        let kept = match protocol_fee_mode {
+            ProtocolFeeMode::Input => return Some(result),
+            ProtocolFeeMode::Split => result.protocol_fee / 2,
+        };
+        if kept == 0 {
+            return Some(result);
+        }
+        let swap_source_amount = result
+            .new_swap_source_amount
+            .checked_sub(result.protocol_fee)?
+            .checked_add(kept)?;
+        let destination_protocol_fee = to_u128(
+            U256::from(kept) * U256::from(result.new_swap_destination_amount)
+                / U256::from(swap_source_amount),
+        )?;
+        result.protocol_fee -= kept;
+        result.destination_protocol_fee = destination_protocol_fee;
+        result.new_swap_destination_amount = result
+            .new_swap_destination_amount
+            .checked_sub(destination_protocol_fee)?;
+        Some(result)
+    }
+
+    /// The invariant and normalized value of a pool holding `reserves`
+    pub fn pool_value(reserves: Reserves) -> PoolValue {
+        // both reserves are below 2^128, so neither value can overflow
+        let invariant = U256::from(reserves.token_0.0) * U256::from(reserves.token_1.0);
+        PoolValue {
+            invariant,
+            normalized_value: invariant.sqrt_floor().as_u128(),
+        }
+    }
+
+    /// Normalized value `sqrt(k)` per LP token out of `lp_supply`, in Q64.64,
+    /// rounded down.
+    ///
+    /// Returns `None` if the LP supply is zero or the value does not fit in
+    /// u128.
+    pub fn value_per_lp_token(reserves: Reserves, lp_supply: LpAmount) -> Option<u128> {
+        if lp_supply.0 == 0 {
+            return None;
+        }
+        // sqrt(k * 2^128) keeps the fractional bits of sqrt(k)
+        let scaled = Self::pool_value(reserves)
+            .invariant
+            .checked_mul(U256::one() << (2 * Q64_RESOLUTION))?
+            .sqrt_floor();
+        to_u128(scaled / U256::from(lp_supply.0))
+    }
+
+    /// Token amounts for `lp_amount` LP tokens out of `lp_supply`
+    pub fn lp_to_token_amounts(
+        lp_amount: LpAmount,
+        lp_supply: LpAmount,
+        reserves: Reserves,
+        round_direction: RoundDirection,
+    ) -> Option<TokenAmounts> {
+        let result = Self::lp_tokens_to_trading_tokens(
+            lp_amount.0,
+            lp_supply.0,
+            reserves.token_0.0,
+            reserves.token_1.0,
+            round_direction,
+        )?;
+        Some(TokenAmounts {
+            token_0: Token0Amount(result.token_0_amount),
+            token_1: Token1Amount(result.token_1_amount),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::curve::fees::{FeeRate, TradeFeeSide},
+        proptest::prelude::*,
+    };
+
+    #[test]
+    fn typed_swaps_match_raw() {
+        let reserves = Reserves::new(50_000u128.into(), 80_000u128.into());
+        let fee_config = FeeConfig::new(
+            FeeRate::from_bps(25).unwrap(),
+            FeeRate::from_percent(12).unwrap(),
+        );
+        assert_eq!(
+            CurveCalculator::swap_exact_in(TradeDirection::ZeroForOne, 1_000, reserves, fee_config),
+            CurveCalculator::swap_base_input(
+                &SwapParams::from_raw_parts(1_000, 50_000, 80_000, 2_500, 120_000).unwrap()
+            )
+        );
+        assert_eq!(
+            CurveCalculator::swap_exact_in(TradeDirection::OneForZero, 1_000, reserves, fee_config),
+            CurveCalculator::swap_base_input(
+                &SwapParams::from_raw_parts(1_000, 80_000, 50_000, 2_500, 120_000).unwrap()
+            )
+        );
+        assert_eq!(
+            CurveCalculator::swap_exact_out(
+                TradeDirection::OneForZero,
+                1_000,
+                reserves,
+                fee_config
+            ),
+            CurveCalculator::swap_base_output(
+                &SwapParams::from_raw_parts(1_000, 80_000, 50_000, 2_500, 120_000).unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn reserves_round_trip_through_direction() {
+        let reserves = Reserves::new(Token0Amount(1), Token1Amount(2));
+        for trade_direction in [TradeDirection::ZeroForOne, TradeDirection::OneForZero] {
+            let (source, destination) = reserves.source_and_destination(trade_direction);
+            assert_eq!(
+                Reserves::from_source_and_destination(trade_direction, source, destination),
+                reserves
+            );
+            assert_eq!(reserves.source(trade_direction), source);
+            assert_eq!(reserves.destination(trade_direction), destination);
+            assert_eq!(reserves.get(trade_direction.input_index()), Some(source));
+            assert_eq!(
+                reserves.get(trade_direction.output_index()),
+                Some(destination)
+            );
+            assert_eq!(
+                reserves.source_and_destination(trade_direction.opposite()),
+                (destination, source)
+            );
+            assert_eq!(
+                TradeDirection::try_from((
+                    trade_direction.input_index(),
+                    trade_direction.output_index()
+                )),
+                Ok(trade_direction)
+            );
+        }
+        assert_eq!(reserves.get(2), None);
+        assert_eq!(TradeDirection::try_from((1, 1)), Err((1, 1)));
+    }
+
+    #[test]
+    fn typed_lp_conversion() {
+        let reserves = Reserves::new(Token0Amount(2), Token1Amount(49));
+        let amounts = CurveCalculator::lp_to_token_amounts(
+            LpAmount(5),
+            LpAmount(10),
+            reserves,
+            RoundDirection::Ceiling,
+        )
+        .unwrap();
+        assert_eq!(amounts.token_0, Token0Amount(1));
+        assert_eq!(amounts.token_1, Token1Amount(25));
+        assert_eq!(Token0Amount(3).checked_sub(Token0Amount(4)), None);
+        assert_eq!(u128::from(LpAmount::from(7u64)), 7);
+    }
+
+    #[test]
+    fn pool_value_getters() {
+        let reserves = Reserves::new(Token0Amount(10), Token1Amount(40));
+        let value = CurveCalculator::pool_value(reserves);
+        assert_eq!(value.invariant, U256::from(400u64));
+        assert_eq!(value.normalized_value, 20);
+        let skewed = CurveCalculator::pool_value(Reserves::new(Token0Amount(2), Token1Amount(7)));
+        assert_eq!(skewed.normalized_value, 3);
+        let full = CurveCalculator::pool_value(Reserves::new(
+            Token0Amount(u128::MAX),
+            Token1Amount(u128::MAX),
+        ));
+        assert_eq!(full.normalized_value, u128::MAX);
+
+        assert_eq!(
+            CurveCalculator::value_per_lp_token(reserves, LpAmount(8)),
+            Some(PriceQ64::from_ratio(20, 8).unwrap().raw())
+        );
+        // sqrt(14) = 3.74..., kept to 64 fractional bits
+        let per_lp = CurveCalculator::value_per_lp_token(
+            Reserves::new(Token0Amount(2), Token1Amount(7)),
+            LpAmount(1),
+        )
+        .unwrap();
+        assert_eq!(per_lp >> Q64_RESOLUTION, 3);
+        assert!(per_lp > PriceQ64::from_ratio(374, 100).unwrap().raw());
+        assert_eq!(
+            CurveCalculator::value_per_lp_token(reserves, LpAmount(0)),
+            None
+        );
+    }
+
+    #[test]
+    fn reserves_from_price() {
+        let price = PriceQ64::from_integer(4);
+        let reserves = Reserves::from_price_and_invariant(price, U256::from(400u64)).unwrap();
+        assert_eq!(reserves, Reserves::new(Token0Amount(10), Token1Amount(40)));
+        let reserves = Reserves::from_price_and_value(price, 80).unwrap();
+        assert_eq!(reserves, Reserves::new(Token0Amount(10), Token1Amount(40)));
+        assert_eq!(
+            Reserves::from_price_and_invariant(PriceQ64::ZERO, U256::from(400u64)),
+            None
+        );
+        assert_eq!(Reserves::from_price_and_value(PriceQ64::ZERO, 80), None);
+    }
+
+    fn fee_on_output() -> FeeConfig {
+        FeeConfig::new(
+            FeeRate::from_bps(25).unwrap(),
+            FeeRate::from_percent(12).unwrap(),
+        )
+        .with_trade_fee_side(TradeFeeSide::Output)
+    }
+
+    #[test]
+    fn fee_on_output_is_deducted_from_output() {
+        let reserves = Reserves::new(Token0Amount(1_000_000), Token1Amount(4_000_000));
+        let result = CurveCalculator::swap_exact_in(
+            TradeDirection::ZeroForOne,
+            100_000,
+            reserves,
+            fee_on_output(),
+        )
+        .unwrap();
+        // the whole input is swapped: 100_000 * 4_000_000 / 1_100_000
+        let gross_amount_out = 363_636;
+        assert_eq!(result.source_amount_swapped, 100_000);
+        assert_eq!((result.trade_fee, result.protocol_fee), (0, 0));
+        assert_eq!(result.destination_trade_fee, 910);
+        assert_eq!(result.destination_protocol_fee, 109);
+        assert_eq!(
+            result.destination_amount_swapped,
+            gross_amount_out - result.destination_trade_fee
+        );
+        // the LPs' share of the fee stays in the pool
+        assert_eq!(result.new_swap_source_amount, 1_100_000);
+        assert_eq!(
+            result.new_swap_destination_amount,
+            4_000_000 - gross_amount_out + 910 - 109
+        );
+
+        let result = CurveCalculator::swap_exact_out(
+            TradeDirection::ZeroForOne,
+            result.destination_amount_swapped,
+            reserves,
+            fee_on_output(),
+        )
+        .unwrap();
+        assert_eq!(result.destination_amount_swapped, gross_amount_out - 910);
+        assert_eq!(result.source_amount_swapped, 100_000);
+        assert_eq!(result.destination_trade_fee, 910);
+        // the gross output must stay below the reserve
+        assert_eq!(
+            CurveCalculator::swap_exact_out(
+                TradeDirection::ZeroForOne,
+                3_995_000,
+                reserves,
+                fee_on_output()
+            ),
+            None
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn try_swaps_agree_with_option_swaps(
+            token_0: u64,
+            token_1: u64,
+            amount in 0..u64::MAX as u128 * 2,
+            zero_for_one: bool,
+            on_output: bool,
+            split: bool,
+        ) {
+            let reserves = Reserves::new(Token0Amount(token_0.into()), Token1Amount(token_1.into()));
+            let trade_direction = if zero_for_one {
+                TradeDirection::ZeroForOne
+            } else {
+                TradeDirection::OneForZero
+            };
+            let fee_config = FeeConfig::new(
+                FeeRate::from_bps(25).unwrap(),
+                FeeRate::from_percent(12).unwrap(),
+            )
+            .with_trade_fee_side(if on_output {
+                TradeFeeSide::Output
+            } else {
+                TradeFeeSide::Input
+            })
+            .with_protocol_fee_mode(if split {
+                ProtocolFeeMode::Split
+            } else {
+                ProtocolFeeMode::Input
+            });
+            prop_assert_eq!(
+                CurveCalculator::try_swap_exact_in(trade_direction, amount, reserves, fee_config).ok(),
+                CurveCalculator::swap_exact_in(trade_direction, amount, reserves, fee_config)
+            );
+            prop_assert_eq!(
+                CurveCalculator::try_swap_exact_out(trade_direction, amount, reserves, fee_config).ok(),
+                CurveCalculator::swap_exact_out(trade_direction, amount, reserves, fee_config)
+            );
+        }
+
+        #[test]
+        fn fee_on_output_exact_out_inverts_exact_in(
+            token_0 in 1_000..u64::MAX as u128,
+            token_1 in 1_000..u64::MAX as u128,
+            amount_out in 1..1_000_000u128,
+        ) {
+            let reserves = Reserves::new(Token0Amount(token_0), Token1Amount(token_1));
+            prop_assume!(amount_out < token_1 / 2);
+            let exact_out = CurveCalculator::swap_exact_out(
+                TradeDirection::ZeroForOne,
+                amount_out,
+                reserves,
+                fee_on_output(),
+            )
+            .unwrap();
+            // the trader receives exactly what they asked for after the fee
+            prop_assert_eq!(exact_out.destination_amount_swapped, amount_out);
+            prop_assert!(exact_out.destination_protocol_fee <= exact_out.destination_trade_fee);
+            // and paying that input exactly in receives at least as much
+            let exact_in = CurveCalculator::swap_exact_in(
+                TradeDirection::ZeroForOne,
+                exact_out.source_amount_swapped,
+                reserves,
+                fee_on_output(),
+            )
+            .unwrap();
+            prop_assert!(exact_in.destination_amount_swapped >= amount_out);
+            // the reserves, excluding protocol fees, never lose value
+            for result in [exact_out, exact_in] {
+                let after = Reserves::new(
+                    Token0Amount(result.new_swap_source_amount),
+                    Token1Amount(result.new_swap_destination_amount),
+                );
+                prop_assert!(
+                    CurveCalculator::pool_value(after).invariant
+                        >= CurveCalculator::pool_value(reserves).invariant
+                );
+            }
+        }
+
+        #[test]
+        fn reserves_from_price_match_invariant(
+            token_0 in 1..u64::MAX as u128,
+            token_1 in 1..u64::MAX as u128,
+        ) {
+            let price = PriceQ64::from_reserves(token_0, token_1).unwrap();
+            let invariant = U256::from(token_0) * U256::from(token_1);
+            let reserves = Reserves::from_price_and_invariant(price, invariant).unwrap();
+            let solved = U256::from(reserves.token_0.get()) * U256::from(reserves.token_1.get());
+            prop_assert!(solved <= invariant);
+            // each amount is within a unit or so of the original, up to the
+            // precision of the Q64 price
+            let (solved_0, solved_1) = (reserves.token_0.get(), reserves.token_1.get());
+            prop_assert!(token_0.abs_diff(solved_0) <= 1 + token_0 / (1 << 32));
+            prop_assert!(token_1.abs_diff(solved_1) <= 1 + token_1 / (1 << 32));
+        }
+    }
+}
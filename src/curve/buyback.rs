@@ -0,0 +1,276 @@
+//! Buyback-and-burn of protocol fees
+//!
+//! When protocol fees are collected, `BuybackConfig` routes a share of them
+//! to buying back one of the pool's tokens, typically the protocol's own,
+//! for burning. The share of the fees in the other token is sold to the pool
+//! itself, so the buyback moves the pool's price exactly as a swap would,
+//! and the share already in the bought token is earmarked as is. The rest
+//! of the fees goes to the treasury.
+//!
+//! The buyback pays the pool's trade fee to LPs like any other swap, but no
+//! protocol fee, which would only flow back to the protocol.
+use {
+    crate::curve::{
+        amount::{Reserves, Token0Amount, Token1Amount},
+        calculator::{SwapResult, TradeDirection},
+        fees::{FeeConfig, FeeRate},
+        params::CurveParams,
+    },
+    borsh::{BorshDeserialize, BorshSerialize},
+};
+
+/// Token bought back and burned
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+#[borsh(use_discriminant = true)]
+pub enum BuybackToken {
+    #[default]
+    Token0 = 0,
+    Token1 = 1,
+}
+
+impl BuybackToken {
+    /// Direction of the swap selling the other token for this one
+    pub const fn trade_direction(self) -> TradeDirection {
+        match self {
+            Self::Token0 => TradeDirection::OneForZero,
+            Self::Token1 => TradeDirection::ZeroForOne,
+        }
+    }
+}
+
+/// Share of collected protocol fees spent buying back a token to burn
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct BuybackConfig {
+    /// Share of the protocol fees in each token routed to the buyback; zero
+    /// disables it
+    pub share: FeeRate,
+    /// Token bought back and burned
+    pub token: BuybackToken,
+}
+
+/// Outcome of collecting protocol fees
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FeeCollection {
+    /// Reserves after the buyback
+    pub reserves: Reserves,
+    /// Fees going to the treasury
+    pub collected: Reserves,
+    /// Fees sold to the pool for the bought token
+    pub sold: Reserves,
+    /// Bought token earmarked for burning: its share of the fees plus the
+    /// buyback's output
+    pub burned: Reserves,
+}
+
+impl CurveParams {
+    /// Collect all `protocol_fees` of a pool with `reserves`, routing
+    /// `buyback.share` of them to the buyback at `now`.
+    ///
+    /// Returns `None` if the buyback swap fails.
+    pub fn collect_protocol_fees(
+        &self,
+        now: u64,
+        reserves: Reserves,
+        protocol_fees: Reserves,
+        fee_config: FeeConfig,
+        buyback: BuybackConfig,
+    ) -> Option<FeeCollection> {
+        let trade_direction = buyback.token.trade_direction();
+        let (sell_fees, buy_fees) = protocol_fees.source_and_destination(trade_direction);
+        let sold = buyback.share.mul_floor(sell_fees)?;
+        let earmarked = buyback.share.mul_floor(buy_fees)?;
+        let swap = if sold == 0 {
+            None
+        } else {
+            Some(self.swap_exact_in(
+                now,
+                trade_direction,
+                sold,
+                reserves,
+                FeeConfig {
+                    protocol_fee_rate: FeeRate::ZERO,
+                    ..fee_config
+                },
+            )?)
+        };
+        let (reserves, bought) = match swap {
+            Some(SwapResult {
+                new_swap_source_amount,
+                new_swap_destination_amount,
+                destination_amount_swapped,
+                ..
+            }) => (
+                Reserves::from_source_and_destination(
+                    trade_direction,
+                    new_swap_source_amount,
+                    new_swap_destination_amount,
+                ),
+                destination_amount_swapped,
+            ),
+            None => (reserves, 0),
+        };
+        Some(FeeCollection {
+            reserves,
+            collected: Reserves::from_source_and_destination(
+                trade_direction,
+                sell_fees - sold,
+                buy_fees - earmarked,
+            ),
+            sold: Reserves::from_source_and_destination(trade_direction, sold, 0),
+            burned: Reserves::from_source_and_destination(
+                trade_direction,
+                0,
+                earmarked.checked_add(bought)?,
+            ),
+        })
+    }
+}
+
+impl FeeCollection {
+    /// Total tokens leaving the pool's vaults: the treasury's fees plus
+    /// those earmarked for burning
+    pub fn paid_out(&self) -> Option<Reserves> {
+        Some(Reserves::new(
+            Token0Amount(
+                self.collected
+                    .token_0
+                    .get()
+                    .checked_add(self.burned.token_0.get())?,
+            ),
+            Token1Amount(
+                self.collected
+                    .token_1
+                    .get()
+                    .checked_add(self.burned.token_1.get())?,
+            ),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::curve::{calculator::CurveCalculator, stable::AmpRamp},
+        proptest::prelude::*,
+    };
+
+    fn fee_config() -> FeeConfig {
+        FeeConfig::new(
+            FeeRate::from_bps(30).unwrap(),
+            FeeRate::from_percent(20).unwrap(),
+        )
+    }
+
+    #[test]
+    fn buyback_sells_fees_to_the_pool() {
+        let reserves = Reserves::new(Token0Amount(1_000_000), Token1Amount(2_000_000));
+        let protocol_fees = Reserves::new(Token0Amount(1_000), Token1Amount(5_000));
+        let buyback = BuybackConfig {
+            share: FeeRate::from_percent(40).unwrap(),
+            token: BuybackToken::Token0,
+        };
+        let collection = CurveParams::ConstantProduct
+            .collect_protocol_fees(0, reserves, protocol_fees, fee_config(), buyback)
+            .unwrap();
+        let swap = CurveCalculator::swap_exact_in(
+            TradeDirection::OneForZero,
+            2_000,
+            reserves,
+            FeeConfig::new(fee_config().trade_fee_rate, FeeRate::ZERO),
+        )
+        .unwrap();
+        assert_eq!(
+            collection.sold,
+            Reserves::new(Token0Amount(0), Token1Amount(2_000))
+        );
+        assert_eq!(
+            collection.collected,
+            Reserves::new(Token0Amount(600), Token1Amount(3_000))
+        );
+        assert_eq!(
+            collection.burned,
+            Reserves::new(
+                Token0Amount(400 + swap.destination_amount_swapped),
+                Token1Amount(0)
+            )
+        );
+        assert_eq!(
+            collection.reserves,
+            Reserves::new(
+                Token0Amount(1_000_000 - swap.destination_amount_swapped),
+                Token1Amount(2_002_000)
+            )
+        );
+
+        // a zero share collects everything untouched
+        let collection = CurveParams::ConstantProduct
+            .collect_protocol_fees(
+                0,
+                reserves,
+                protocol_fees,
+                fee_config(),
+                BuybackConfig::default(),
+            )
+            .unwrap();
+        assert_eq!(collection.reserves, reserves);
+        assert_eq!(collection.collected, protocol_fees);
+        assert_eq!(collection.burned, Reserves::default());
+    }
+
+    #[test]
+    fn buyback_uses_the_pool_curve() {
+        let reserves = Reserves::new(Token0Amount(1_000_000), Token1Amount(1_000_000));
+        let protocol_fees = Reserves::new(Token0Amount(10_000), Token1Amount(0));
+        let buyback = BuybackConfig {
+            share: FeeRate::MAX,
+            token: BuybackToken::Token1,
+        };
+        let burned = |curve: CurveParams| {
+            curve
+                .collect_protocol_fees(0, reserves, protocol_fees, fee_config(), buyback)
+                .unwrap()
+                .burned
+                .token_1
+                .get()
+        };
+        // a balanced stable pool gives more for the same fees
+        let stable = CurveParams::Stable(AmpRamp::new(100).unwrap());
+        assert!(burned(stable) > burned(CurveParams::ConstantProduct));
+    }
+
+    proptest! {
+        #[test]
+        fn buyback_conserves_tokens(
+            fees_0 in 0..1_000_000u128,
+            fees_1 in 0..1_000_000u128,
+            share in 0..=1_000_000u64,
+            buy_token_1: bool,
+        ) {
+            let reserves = Reserves::new(Token0Amount(50_000_000), Token1Amount(80_000_000));
+            let protocol_fees = Reserves::new(Token0Amount(fees_0), Token1Amount(fees_1));
+            let buyback = BuybackConfig {
+                share: FeeRate::checked(share).unwrap(),
+                token: if buy_token_1 { BuybackToken::Token1 } else { BuybackToken::Token0 },
+            };
+            let collection = CurveParams::ConstantProduct
+                .collect_protocol_fees(0, reserves, protocol_fees, fee_config(), buyback)
+                .unwrap();
+            // the vaults hold the reserves and the fees before and after
+            let paid_out = collection.paid_out().unwrap();
+            prop_assert_eq!(
+                collection.reserves.token_0.get() + paid_out.token_0.get(),
+                reserves.token_0.get() + fees_0
+            );
+            prop_assert_eq!(
+                collection.reserves.token_1.get() + paid_out.token_1.get(),
+                reserves.token_1.get() + fees_1
+            );
+            prop_assert!(
+                CurveCalculator::pool_value(collection.reserves).invariant
+                    >= CurveCalculator::pool_value(reserves).invariant
+            );
+        }
+    }
+}
@@ -5,12 +5,29 @@
 //! the integrity of the curve calculations.
 
 // Import necessary modules and dependencies
-use crate::curve::{constant_product::ConstantProductCurve, fees::Fees};
-use std::fmt::Debug;
+use crate::{
+    curve::{
+        amount::Reserves,
+        constant_product::ConstantProductCurve,
+        context::{ContextError, ErrorCtx, Operation, Step, WithContext},
+        fee_model::{FeeCtx, FeeModel},
+        fees::{FeeConfig, FeeRate, Fees, TinySwapPolicy, TradeFeeSide},
+        rounding::RoundingPolicy,
+        swap_params::SwapParams,
+    },
+    logging::{self, SwapKind},
+    utils::{
+        IntegerSquareRoot, U256,
+        format::{self, Value},
+        price::to_u128,
+    },
+};
+use borsh::{BorshDeserialize, BorshSerialize};
+use core::fmt::{self, Debug};
 
 // The direction of a trade.
 // This enum is used to determine the direction of the trade.
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
 pub enum TradeDirection {
     // Input token 0, output token 1
     ZeroForOne,
@@ -18,10 +35,48 @@ pub enum TradeDirection {
     OneForZero,
 }
 
+impl TradeDirection {
+    /// The direction trading straight back
+    pub const fn opposite(self) -> Self {
+        match self {
+            Self::ZeroForOne => Self::OneForZero,
+            Self::OneForZero => Self::ZeroForOne,
+        }
+    }
+
+    /// Index of the input token, 0 or 1
+    pub const fn input_index(self) -> usize {
+        match self {
+            Self::ZeroForOne => 0,
+            Self::OneForZero => 1,
+        }
+    }
+
+    /// Index of the output token, 0 or 1
+    pub const fn output_index(self) -> usize {
+        self.opposite().input_index()
+    }
+}
+
+/// The direction from `(input_index, output_index)` token indices, as routing
+/// code looks them up in a pool's token list. Fails with the indices unless
+/// they are `(0, 1)` or `(1, 0)`.
+impl TryFrom<(usize, usize)> for TradeDirection {
+    type Error = (usize, usize);
+
+    fn try_from(indices: (usize, usize)) -> Result<Self, Self::Error> {
+        match indices {
+            (0, 1) => Ok(Self::ZeroForOne),
+            (1, 0) => Ok(Self::OneForZero),
+            _ => Err(indices),
+        }
+    }
+}
+
 /// The direction to round.  Used for pool token to trading token conversions to
 /// avoid losing value on any deposit or withdrawal.
 #[repr(C)]
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum RoundDirection {
     /// Floor the value, ie. 1.9 => 1, 1.1 => 1, 1.5 => 1
     Floor,
@@ -36,6 +91,26 @@ pub struct TradingTokenResult {
     pub token_0_amount: u128,
     /// Amount of token B
     pub token_1_amount: u128,
+    /// Fraction of token A retained by the pool due to rounding, in units of
+    /// `1 / lp_token_supply` of a token
+    pub token_0_rounding_dust: u128,
+    /// Fraction of token B retained by the pool due to rounding, in units of
+    /// `1 / lp_token_supply` of a token
+    pub token_1_rounding_dust: u128,
+}
+
+/// Encodes results of a proportional deposit of up to a maximum of each
+/// token
+#[derive(Debug, PartialEq)]
+pub struct DepositResult {
+    /// LP tokens minted
+    pub lp_token_amount: u128,
+    /// Tokens the pool takes for `lp_token_amount`, rounded up
+    pub trading_tokens: TradingTokenResult,
+    /// Amount of token A offered but not taken, to be refunded
+    pub unused_token_0: u128,
+    /// Amount of token B offered but not taken, to be refunded
+    pub unused_token_1: u128,
 }
 
 // Encodes all results of swapping from a source token to a destination token
@@ -55,100 +130,796 @@ pub struct SwapResult {
     pub trade_fee: u128,
     /// Amount of source tokens going to protocol
     pub protocol_fee: u128,
+    /// Amount of destination tokens withheld from the output, under
+    /// `TradeFeeSide::Output`
+    pub destination_trade_fee: u128,
+    /// Amount of destination tokens going to protocol, under
+    /// `TradeFeeSide::Output` or `ProtocolFeeMode::Split`
+    pub destination_protocol_fee: u128,
+    /// Source tokens paid after fees beyond the minimum needed to receive
+    /// `destination_amount_swapped`, retained by the pool due to rounding
+    pub rounding_dust: u128,
+}
+
+impl SwapResult {
+    /// Protocol fees accrued by a swap in `trade_direction`, per token
+    pub const fn protocol_fees(&self, trade_direction: TradeDirection) -> Reserves {
+        Reserves::from_source_and_destination(
+            trade_direction,
+            self.protocol_fee,
+            self.destination_protocol_fee,
+        )
+    }
+}
+
+/// `{}` is a single `key=value` line for logs; `{:#}` is one labelled row
+/// per field, with thousands separators
+impl fmt::Display for SwapResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        format::write_rows(
+            f,
+            &[
+                (
+                    "source_amount_swapped",
+                    Value::Amount(self.source_amount_swapped),
+                ),
+                (
+                    "destination_amount_swapped",
+                    Value::Amount(self.destination_amount_swapped),
+                ),
+                ("trade_fee", Value::Amount(self.trade_fee)),
+                ("protocol_fee", Value::Amount(self.protocol_fee)),
+                (
+                    "destination_trade_fee",
+                    Value::Amount(self.destination_trade_fee),
+                ),
+                (
+                    "destination_protocol_fee",
+                    Value::Amount(self.destination_protocol_fee),
+                ),
+                ("rounding_dust", Value::Amount(self.rounding_dust)),
+                (
+                    "new_swap_source_amount",
+                    Value::Amount(self.new_swap_source_amount),
+                ),
+                (
+                    "new_swap_destination_amount",
+                    Value::Amount(self.new_swap_destination_amount),
+                ),
+            ],
+        )
+    }
+}
+
+/// `{}` is a single `key=value` line for logs; `{:#}` is one labelled row
+/// per field, with thousands separators
+impl fmt::Display for TradingTokenResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        format::write_rows(
+            f,
+            &[
+                ("token_0_amount", Value::Amount(self.token_0_amount)),
+                ("token_1_amount", Value::Amount(self.token_1_amount)),
+                (
+                    "token_0_rounding_dust",
+                    Value::Amount(self.token_0_rounding_dust),
+                ),
+                (
+                    "token_1_rounding_dust",
+                    Value::Amount(self.token_1_rounding_dust),
+                ),
+            ],
+        )
+    }
 }
 
+/// Why a curve calculation failed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CurveError {
+    /// The amount swapped or converted is zero
+    ZeroTradeAmount,
+    /// A pool reserve is zero, so the pool has no price
+    EmptyPool,
+    /// The LP supply is zero, so LP tokens have no value
+    ZeroSupply,
+    /// The trade fee rate exceeds `MAX_TRADE_FEE_RATE`, or the protocol fee
+    /// rate exceeds 100% of the trade fee
+    FeeConfigInvalid,
+    /// The pool cannot pay out the requested amount, which must stay below
+    /// the destination reserve
+    InsufficientLiquidity,
+    /// A value overflows, or the fee leaves nothing to swap
+    Overflow,
+    /// The input is too small to pay the trade fee and still swap anything
+    MinimumInputNotMet {
+        /// Smallest input that leaves something to swap after the fee
+        minimum_amount: u128,
+    },
+    /// The swap moves the price past its price limit
+    PriceLimitExceeded,
+}
+
+impl fmt::Display for CurveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CurveError::ZeroTradeAmount => f.write_str("the amount is zero"),
+            CurveError::EmptyPool => f.write_str("a pool reserve is zero"),
+            CurveError::ZeroSupply => f.write_str("the LP supply is zero"),
+            CurveError::FeeConfigInvalid => f.write_str("the fee rates are out of bounds"),
+            CurveError::InsufficientLiquidity => {
+                f.write_str("the pool cannot pay out the requested amount")
+            }
+            CurveError::Overflow => f.write_str("a value overflows"),
+            CurveError::MinimumInputNotMet { minimum_amount } => write!(
+                f,
+                "the trade fee consumes the whole input; swap at least {minimum_amount}"
+            ),
+            CurveError::PriceLimitExceeded => {
+                f.write_str("the swap moves the price past its limit")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CurveError {}
+
 // Concrete struct to wrap around the trait object which performs calculation.
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct CurveCalculator {}
 
 impl CurveCalculator {
-    // Subtract fees and calculate how much destination token will be provided
-    // given an amount of source token.
-    // Subtracts fees and calculates how much destination token will be provided
-    // given an amount of source token.
-    //
-    // # Arguments
-    // * `source_amount` - The amount of source tokens to be swapped.
-    // * `swap_source_amount` - The amount of source tokens in the pool.
-    // * `swap_destination_amount` - The amount of destination tokens in the pool.
-    // * `trade_fee_rate` - The fee rate applied to the trade.
-    // * `protocol_fee_rate` - The fee rate applied to the protocol.
-    //
-    // # Returns
-    // An `Option<SwapResult>` containing the details of the swap if successful, or `None
-    pub fn swap_base_input(
+    /// Quote a swap of exactly `params.amount` source tokens.
+    ///
+    /// The trade fee is deducted from the side and the protocol fee accrues
+    /// in the tokens `params.fee_config` says. Returns `None` if the swap
+    /// cannot be quoted; `try_swap_base_input` says why.
+    pub fn swap_base_input(params: &SwapParams) -> Option<SwapResult> {
+        Self::try_swap_base_input(params, TinySwapPolicy::Clamp).ok()
+    }
+
+    /// Like `swap_base_input`, reporting why the swap cannot be quoted.
+    ///
+    /// The trade fee is rounded up, so on a tiny input it can consume the
+    /// whole input, or exceed it at rates above 100%. `tiny_swap_policy`
+    /// either caps the fee at the input, swapping nothing, or rejects the
+    /// swap with `CurveError::MinimumInputNotMet`. Fee configs a pool cannot
+    /// be configured with fail with `CurveError::FeeConfigInvalid`, and
+    /// swaps moving the price past `params.price_limit` with
+    /// `CurveError::PriceLimitExceeded`.
+    pub fn try_swap_base_input(
+        params: &SwapParams,
+        tiny_swap_policy: TinySwapPolicy,
+    ) -> Result<SwapResult, CurveError> {
+        Self::swap_base_input_in_context(params, tiny_swap_policy).map_err(CurveError::from)
+    }
+
+    // `try_swap_base_input`, recording where it fails
+    pub(crate) fn swap_base_input_in_context(
+        params: &SwapParams,
+        tiny_swap_policy: TinySwapPolicy,
+    ) -> Result<SwapResult, ContextError> {
+        let ctx = ErrorCtx::new(Operation::ExactIn);
+        let fee_config = Self::check_fee_config(params.fee_config).context(ctx)?;
+        let (swap_source_amount, swap_destination_amount) = params.source_and_destination();
+        let (trade_fee_rate, protocol_fee_rate) = (
+            fee_config.trade_fee_rate.get(),
+            fee_config.protocol_fee_rate.get(),
+        );
+        let result = match fee_config.trade_fee_side {
+            TradeFeeSide::Input => {
+                let result = Self::swap_base_input_with_fee_model_in_context(
+                    params.amount,
+                    swap_source_amount,
+                    swap_destination_amount,
+                    &fee_config,
+                    tiny_swap_policy,
+                    &RoundingPolicy::SAFE,
+                )?;
+                logging::fee_event(
+                    SwapKind::BaseInput,
+                    trade_fee_rate,
+                    protocol_fee_rate,
+                    &result,
+                );
+                result
+            }
+            TradeFeeSide::Output => {
+                Self::check_swap_amounts(
+                    params.amount,
+                    swap_source_amount,
+                    swap_destination_amount,
+                )
+                .context(ctx)?;
+                Self::swap_base_input_fee_on_output(
+                    params.amount,
+                    swap_source_amount,
+                    swap_destination_amount,
+                    trade_fee_rate,
+                    protocol_fee_rate,
+                )
+                .ok_or(ctx.error(CurveError::Overflow))?
+            }
+        };
+        Self::settle_params(ctx, params, result)
+    }
+
+    /// Like `try_swap_base_input`, charging the fees `fee_model` calculates.
+    ///
+    /// Fails with `CurveError::FeeConfigInvalid` if the protocol's share
+    /// exceeds the trade fee.
+    pub fn try_swap_base_input_with_fee_model<M: FeeModel + ?Sized>(
         source_amount: u128,
         swap_source_amount: u128,
         swap_destination_amount: u128,
-        trade_fee_rate: u64,
-        protocol_fee_rate: u64,
-    ) -> Option<SwapResult> {
-        println!("Calculator::swap_base_input called with source_amount: {}", source_amount);
+        fee_model: &M,
+        tiny_swap_policy: TinySwapPolicy,
+    ) -> Result<SwapResult, CurveError> {
+        Self::swap_base_input_with_fee_model_in_context(
+            source_amount,
+            swap_source_amount,
+            swap_destination_amount,
+            fee_model,
+            tiny_swap_policy,
+            &RoundingPolicy::SAFE,
+        )
+        .map_err(CurveError::from)
+    }
 
+    // `try_swap_base_input_with_fee_model`, recording where it fails, with
+    // the destination amount rounded per `rounding_policy`
+    pub(crate) fn swap_base_input_with_fee_model_in_context<M: FeeModel + ?Sized>(
+        source_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        fee_model: &M,
+        tiny_swap_policy: TinySwapPolicy,
+        rounding_policy: &RoundingPolicy,
+    ) -> Result<SwapResult, ContextError> {
+        let ctx = ErrorCtx::new(Operation::ExactIn);
+        Self::check_swap_amounts(source_amount, swap_source_amount, swap_destination_amount)
+            .context(ctx)?;
+        let fee_ctx = FeeCtx {
+            swap_source_amount,
+            swap_destination_amount,
+        };
         // debit the fee to calculate the amount swapped
-        let trade_fee = Fees::trading_fee(source_amount, trade_fee_rate)?;
-        let protocol_fee = Fees::protocol_fee(trade_fee, protocol_fee_rate)?;
+        let mut trade_fee = fee_model
+            .trade_fee(source_amount, &fee_ctx)
+            .ok_or(ctx.error(CurveError::Overflow))?;
+        if trade_fee >= source_amount && trade_fee != 0 {
+            match tiny_swap_policy {
+                TinySwapPolicy::Clamp => trade_fee = source_amount,
+                TinySwapPolicy::Reject => {
+                    return Err(ctx.error(CurveError::MinimumInputNotMet {
+                        minimum_amount: fee_model
+                            .minimum_input(&fee_ctx)
+                            .ok_or(ctx.error(CurveError::Overflow))?,
+                    }));
+                }
+            }
+        }
+        let protocol_fee = Self::checked_protocol_share(ctx, fee_model, trade_fee)?;
 
-        let source_amount_less_fees = source_amount.checked_sub(trade_fee)?;        
+        let source_amount_less_fees = source_amount - trade_fee;
 
         // Calculate the destination amount to be received after the swap.
-        let destination_amount_swapped = ConstantProductCurve::swap_base_input_without_fees(
+        let destination_amount_swapped = Self::checked_destination_amount(
             source_amount_less_fees,
             swap_source_amount,
             swap_destination_amount,
-        );
+            rounding_policy.swap_output,
+        )?;
 
-        Some(SwapResult {
-            new_swap_source_amount: swap_source_amount.checked_add(source_amount)?,
+        let rounding_dust = Self::rounding_dust(
+            source_amount_less_fees,
+            destination_amount_swapped,
+            swap_source_amount,
+            swap_destination_amount,
+        )
+        .ok_or(ctx.error(CurveError::Overflow))?;
+        let new_swap_source_amount =
+            Self::checked_new_source_amount(ctx, swap_source_amount, source_amount)?;
+
+        let result = SwapResult {
+            new_swap_source_amount,
             new_swap_destination_amount: swap_destination_amount
-                .checked_sub(destination_amount_swapped)?,
+                .checked_sub(destination_amount_swapped)
+                .ok_or(ctx.error(CurveError::Overflow))?,
             source_amount_swapped: source_amount,
             destination_amount_swapped,
             trade_fee,
             protocol_fee,
-        })
+            destination_trade_fee: 0,
+            destination_protocol_fee: 0,
+            rounding_dust,
+        };
+        logging::swap_event(
+            SwapKind::BaseInput,
+            swap_source_amount,
+            swap_destination_amount,
+            &result,
+        );
+        Ok(result)
     }
 
-    // Calculates the required amount of source tokens to swap for a given amount of destination tokens.
-    //
-    // # Arguments
-    // * `destination_amount` - The amount of destination tokens to be received.
-    // * `swap_source_amount` - The amount of source tokens in the pool.
-    // * `swap_destination_amount` - The amount of destination tokens in the pool.
-    // * `trade_fee_rate` - The fee rate applied to the trade.
-    // * `protocol_fee_rate` - The fee rate applied to the protocol.
-    //
-    // # Returns
-    // An `Option<SwapResult>` containing the details of the swap if successful, or `None` if any calculation fails.
-    pub fn swap_base_output(
+    /// Quote a swap receiving exactly `params.amount` destination tokens.
+    ///
+    /// The trade fee is deducted from the side and the protocol fee accrues
+    /// in the tokens `params.fee_config` says. Returns `None` if the swap
+    /// cannot be quoted; `try_swap_base_output` says why.
+    pub fn swap_base_output(params: &SwapParams) -> Option<SwapResult> {
+        Self::try_swap_base_output(params).ok()
+    }
+
+    /// Like `swap_base_output`, reporting why the swap cannot be quoted.
+    ///
+    /// Never panics: outputs at or beyond the destination reserve fail with
+    /// `CurveError::InsufficientLiquidity`, and anything that does not fit in
+    /// u128 with `CurveError::Overflow`. Fee configs a pool cannot be
+    /// configured with fail with `CurveError::FeeConfigInvalid`, and swaps
+    /// moving the price past `params.price_limit` with
+    /// `CurveError::PriceLimitExceeded`.
+    pub fn try_swap_base_output(params: &SwapParams) -> Result<SwapResult, CurveError> {
+        Self::swap_base_output_in_context(params).map_err(CurveError::from)
+    }
+
+    // `try_swap_base_output`, recording where it fails
+    pub(crate) fn swap_base_output_in_context(
+        params: &SwapParams,
+    ) -> Result<SwapResult, ContextError> {
+        let ctx = ErrorCtx::new(Operation::ExactOut);
+        let fee_config = Self::check_fee_config(params.fee_config).context(ctx)?;
+        let (swap_source_amount, swap_destination_amount) = params.source_and_destination();
+        let (trade_fee_rate, protocol_fee_rate) = (
+            fee_config.trade_fee_rate.get(),
+            fee_config.protocol_fee_rate.get(),
+        );
+        let result = match fee_config.trade_fee_side {
+            TradeFeeSide::Input => {
+                let result = Self::swap_base_output_with_fee_model_in_context(
+                    params.amount,
+                    swap_source_amount,
+                    swap_destination_amount,
+                    &fee_config,
+                    &RoundingPolicy::SAFE,
+                )?;
+                logging::fee_event(
+                    SwapKind::BaseOutput,
+                    trade_fee_rate,
+                    protocol_fee_rate,
+                    &result,
+                );
+                result
+            }
+            TradeFeeSide::Output => {
+                Self::check_swap_amounts(
+                    params.amount,
+                    swap_source_amount,
+                    swap_destination_amount,
+                )
+                .context(ctx)?;
+                if params.amount >= swap_destination_amount {
+                    return Err(ctx
+                        .failed_at(
+                            Step::Sub,
+                            ("reserve", swap_destination_amount),
+                            ("requested", params.amount),
+                        )
+                        .error(CurveError::InsufficientLiquidity));
+                }
+                Self::swap_base_output_fee_on_output(
+                    params.amount,
+                    swap_source_amount,
+                    swap_destination_amount,
+                    trade_fee_rate,
+                    protocol_fee_rate,
+                )
+                .ok_or(ctx.error(CurveError::Overflow))?
+            }
+        };
+        Self::settle_params(ctx, params, result)
+    }
+
+    // Apply the protocol fee mode of `params` to `result` and check it
+    // against the price limit
+    fn settle_params(
+        ctx: ErrorCtx,
+        params: &SwapParams,
+        result: SwapResult,
+    ) -> Result<SwapResult, ContextError> {
+        let result = Self::apply_protocol_fee_mode(result, params.fee_config.protocol_fee_mode)
+            .ok_or(ctx.error(CurveError::Overflow))?;
+        params.check_price_limit(&result).context(ctx)?;
+        Ok(result)
+    }
+
+    /// Like `try_swap_base_output`, charging the fees `fee_model` calculates.
+    ///
+    /// Fails with `CurveError::FeeConfigInvalid` if the protocol's share
+    /// exceeds the trade fee.
+    pub fn try_swap_base_output_with_fee_model<M: FeeModel + ?Sized>(
+        destination_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        fee_model: &M,
+    ) -> Result<SwapResult, CurveError> {
+        Self::swap_base_output_with_fee_model_in_context(
+            destination_amount,
+            swap_source_amount,
+            swap_destination_amount,
+            fee_model,
+            &RoundingPolicy::SAFE,
+        )
+        .map_err(CurveError::from)
+    }
+
+    // `try_swap_base_output_with_fee_model`, recording where it fails, with
+    // the source amount before fees rounded per `rounding_policy`
+    pub(crate) fn swap_base_output_with_fee_model_in_context<M: FeeModel + ?Sized>(
         destination_amount: u128,
         swap_source_amount: u128,
         swap_destination_amount: u128,
+        fee_model: &M,
+        rounding_policy: &RoundingPolicy,
+    ) -> Result<SwapResult, ContextError> {
+        let ctx = ErrorCtx::new(Operation::ExactOut);
+        Self::check_swap_amounts(
+            destination_amount,
+            swap_source_amount,
+            swap_destination_amount,
+        )
+        .context(ctx)?;
+        let fee_ctx = FeeCtx {
+            swap_source_amount,
+            swap_destination_amount,
+        };
+        // Calculate the source amount required to receive the desired destination amount.
+        let source_amount_swapped = Self::checked_source_amount(
+            destination_amount,
+            swap_source_amount,
+            swap_destination_amount,
+            rounding_policy.exact_out_input,
+        )?;
+
+        let overflow = ctx.error(CurveError::Overflow);
+        let source_amount = fee_model
+            .pre_fee_amount(source_amount_swapped, &fee_ctx)
+            .ok_or(overflow)?;
+        let trade_fee = fee_model
+            .trade_fee(source_amount, &fee_ctx)
+            .ok_or(overflow)?;
+        let protocol_fee = Self::checked_protocol_share(ctx, fee_model, trade_fee)?;
+        let rounding_dust = Self::rounding_dust(
+            source_amount.checked_sub(trade_fee).ok_or(overflow)?,
+            destination_amount,
+            swap_source_amount,
+            swap_destination_amount,
+        )
+        .ok_or(overflow)?;
+        let new_swap_source_amount =
+            Self::checked_new_source_amount(ctx, swap_source_amount, source_amount)?;
+
+        let result = SwapResult {
+            new_swap_source_amount,
+            new_swap_destination_amount: swap_destination_amount - destination_amount,
+            source_amount_swapped: source_amount,
+            destination_amount_swapped: destination_amount,
+            trade_fee,
+            protocol_fee,
+            destination_trade_fee: 0,
+            destination_protocol_fee: 0,
+            rounding_dust,
+        };
+        logging::swap_event(
+            SwapKind::BaseOutput,
+            swap_source_amount,
+            swap_destination_amount,
+            &result,
+        );
+        Ok(result)
+    }
+
+    // The protocol's share of `trade_fee` per `fee_model`, rejecting models
+    // that claim more than the fee
+    fn checked_protocol_share<M: FeeModel + ?Sized>(
+        ctx: ErrorCtx,
+        fee_model: &M,
+        trade_fee: u128,
+    ) -> Result<u128, ContextError> {
+        match fee_model.protocol_share(trade_fee) {
+            Some(protocol_fee) if protocol_fee <= trade_fee => Ok(protocol_fee),
+            Some(_) => Err(ctx.error(CurveError::FeeConfigInvalid)),
+            None => Err(ctx.error(CurveError::Overflow)),
+        }
+    }
+
+    // A config a pool cannot be configured with fails with
+    // `FeeConfigInvalid`
+    fn check_fee_config(fee_config: FeeConfig) -> Result<FeeConfig, CurveError> {
+        if fee_config.is_valid() {
+            Ok(fee_config)
+        } else {
+            Err(CurveError::FeeConfigInvalid)
+        }
+    }
+
+    // Rates a pool cannot be configured with, as a corrupted config would
+    // have, fail with `FeeConfigInvalid`
+    fn check_fee_rates(
+        trade_fee_rate: u64,
+        protocol_fee_rate: u64,
+    ) -> Result<FeeConfig, CurveError> {
+        match (
+            FeeRate::checked(trade_fee_rate),
+            FeeRate::checked(protocol_fee_rate),
+        ) {
+            (Some(trade_fee_rate), Some(protocol_fee_rate)) => {
+                FeeConfig::checked(trade_fee_rate, protocol_fee_rate)
+                    .ok_or(CurveError::FeeConfigInvalid)
+            }
+            _ => Err(CurveError::FeeConfigInvalid),
+        }
+    }
+
+    // Zero amounts fail with `ZeroTradeAmount` and pools missing either
+    // reserve with `EmptyPool`, before any math can divide by them
+    pub(crate) fn check_swap_amounts(
+        amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+    ) -> Result<(), CurveError> {
+        if amount == 0 {
+            Err(CurveError::ZeroTradeAmount)
+        } else if swap_source_amount == 0 || swap_destination_amount == 0 {
+            Err(CurveError::EmptyPool)
+        } else {
+            Ok(())
+        }
+    }
+
+    // `x * delta_y / (y - delta_y)`, the source amount before fees
+    // buying `destination_amount`, as
+    // `ConstantProductCurve::try_swap_base_output_without_fees` with the
+    // failed step in the error
+    fn checked_source_amount(
+        destination_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        round_direction: RoundDirection,
+    ) -> Result<u128, ContextError> {
+        let ctx = ErrorCtx::new(Operation::ExactOut);
+        if destination_amount >= swap_destination_amount {
+            return Err(ctx
+                .failed_at(
+                    Step::Sub,
+                    ("reserve", swap_destination_amount),
+                    ("requested", destination_amount),
+                )
+                .error(CurveError::InsufficientLiquidity));
+        }
+        let denominator = swap_destination_amount - destination_amount;
+        // fast path for the usual case of `x * delta_y` fitting in u128
+        if let Some(numerator) = swap_source_amount.checked_mul(destination_amount) {
+            return Ok(Self::div_rounded(numerator, denominator, round_direction));
+        }
+        let numerator = U256::from(swap_source_amount) * U256::from(destination_amount);
+        let quotient = Self::div_rounded_wide(numerator, denominator, round_direction);
+        to_u128(quotient).ok_or_else(|| {
+            ctx.failed_at(
+                Step::Mul,
+                ("source reserve", swap_source_amount),
+                ("requested", destination_amount),
+            )
+            .error(CurveError::Overflow)
+        })
+    }
+
+    // `delta_x * y / (x + delta_x)`, the destination amount bought
+    // with `source_amount` after fees, as
+    // `ConstantProductCurve::try_swap_base_input_without_fees` with the
+    // failed step in the error. Assumes a non-empty pool.
+    fn checked_destination_amount(
+        source_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        round_direction: RoundDirection,
+    ) -> Result<u128, ContextError> {
+        let ctx = ErrorCtx::new(Operation::ExactIn);
+        let denominator = Self::checked_new_source_amount(ctx, swap_source_amount, source_amount)?;
+        // fast path for the usual case of `delta_x * y` fitting in u128
+        if let Some(numerator) = source_amount.checked_mul(swap_destination_amount) {
+            return Ok(Self::div_rounded(numerator, denominator, round_direction));
+        }
+        // at most `y`, so it always fits
+        let numerator = U256::from(source_amount) * U256::from(swap_destination_amount);
+        let quotient = Self::div_rounded_wide(numerator, denominator, round_direction);
+        to_u128(quotient).ok_or(ctx.error(CurveError::Overflow))
+    }
+
+    // `numerator / denominator` rounded in `round_direction`, for a nonzero
+    // denominator
+    const fn div_rounded(
+        numerator: u128,
+        denominator: u128,
+        round_direction: RoundDirection,
+    ) -> u128 {
+        match round_direction {
+            RoundDirection::Floor => numerator / denominator,
+            RoundDirection::Ceiling => numerator.div_ceil(denominator),
+        }
+    }
+
+    // `div_rounded` of a U256 numerator
+    fn div_rounded_wide(
+        numerator: U256,
+        denominator: u128,
+        round_direction: RoundDirection,
+    ) -> U256 {
+        let (quotient, remainder) = numerator.div_mod(U256::from(denominator));
+        match round_direction {
+            RoundDirection::Ceiling if !remainder.is_zero() => quotient + 1,
+            _ => quotient,
+        }
+    }
+
+    // The source reserve after `source_amount` is added to it
+    fn checked_new_source_amount(
+        ctx: ErrorCtx,
+        swap_source_amount: u128,
+        source_amount: u128,
+    ) -> Result<u128, ContextError> {
+        swap_source_amount
+            .checked_add(source_amount)
+            .ok_or_else(|| {
+                ctx.failed_at(
+                    Step::Add,
+                    ("source reserve", swap_source_amount),
+                    ("amount in", source_amount),
+                )
+                .error(CurveError::Overflow)
+            })
+    }
+
+    /// Like `swap_base_input`, with the trade fee deducted from the output
+    /// instead of the input
+    pub fn swap_base_input_fee_on_output(
+        source_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
         trade_fee_rate: u64,
         protocol_fee_rate: u64,
     ) -> Option<SwapResult> {
+        Self::check_fee_rates(trade_fee_rate, protocol_fee_rate).ok()?;
+        Self::check_swap_amounts(source_amount, swap_source_amount, swap_destination_amount)
+            .ok()?;
+        let gross_amount_out = Self::checked_destination_amount(
+            source_amount,
+            swap_source_amount,
+            swap_destination_amount,
+            RoundDirection::Floor,
+        )
+        .ok()?;
+        let trade_fee = Fees::trading_fee(gross_amount_out, trade_fee_rate)?;
+        let protocol_fee = Fees::protocol_fee(trade_fee, protocol_fee_rate)?;
+        let destination_amount_swapped = gross_amount_out.checked_sub(trade_fee)?;
+        let rounding_dust = Self::rounding_dust(
+            source_amount,
+            gross_amount_out,
+            swap_source_amount,
+            swap_destination_amount,
+        )?;
 
-        // Calculate the source amount required to receive the desired destination amount.
-        let source_amount_swapped = ConstantProductCurve::swap_base_output_without_fees(
-            destination_amount,
+        let result = SwapResult {
+            new_swap_source_amount: swap_source_amount.checked_add(source_amount)?,
+            new_swap_destination_amount: swap_destination_amount
+                .checked_sub(destination_amount_swapped)?
+                .checked_sub(protocol_fee)?,
+            source_amount_swapped: source_amount,
+            destination_amount_swapped,
+            trade_fee: 0,
+            protocol_fee: 0,
+            destination_trade_fee: trade_fee,
+            destination_protocol_fee: protocol_fee,
+            rounding_dust,
+        };
+        logging::swap_event(
+            SwapKind::BaseInput,
             swap_source_amount,
             swap_destination_amount,
+            &result,
         );
+        logging::fee_event(SwapKind::BaseInput, trade_fee_rate, protocol_fee_rate, &result);
+        Some(result)
+    }
 
-        let source_amount =
-        Fees::calculate_pre_fee_amount(source_amount_swapped, trade_fee_rate).unwrap();
-        let trade_fee = Fees::trading_fee(source_amount, trade_fee_rate)?;
+    /// Like `swap_base_output`, with the trade fee deducted from the output
+    /// instead of the input: the pool pays out `destination_amount` grossed
+    /// up by the fee, so the trader still receives exactly
+    /// `destination_amount`
+    pub fn swap_base_output_fee_on_output(
+        destination_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        trade_fee_rate: u64,
+        protocol_fee_rate: u64,
+    ) -> Option<SwapResult> {
+        Self::check_fee_rates(trade_fee_rate, protocol_fee_rate).ok()?;
+        Self::check_swap_amounts(
+            destination_amount,
+            swap_source_amount,
+            swap_destination_amount,
+        )
+        .ok()?;
+        // gross - ceil(gross * rate) == destination_amount
+        let gross_amount_out = Fees::calculate_pre_fee_amount(destination_amount, trade_fee_rate)?;
+        let trade_fee = Fees::trading_fee(gross_amount_out, trade_fee_rate)?;
         let protocol_fee = Fees::protocol_fee(trade_fee, protocol_fee_rate)?;
+        let destination_amount_swapped = gross_amount_out.checked_sub(trade_fee)?;
+        let source_amount = Self::checked_source_amount(
+            gross_amount_out,
+            swap_source_amount,
+            swap_destination_amount,
+            RoundDirection::Ceiling,
+        )
+        .ok()?;
+        let rounding_dust = Self::rounding_dust(
+            source_amount,
+            gross_amount_out,
+            swap_source_amount,
+            swap_destination_amount,
+        )?;
 
-        Some(SwapResult {
+        let result = SwapResult {
             new_swap_source_amount: swap_source_amount.checked_add(source_amount)?,
             new_swap_destination_amount: swap_destination_amount
-                .checked_sub(destination_amount)?,
+                .checked_sub(destination_amount_swapped)?
+                .checked_sub(protocol_fee)?,
             source_amount_swapped: source_amount,
-            destination_amount_swapped: destination_amount,
-            trade_fee,
-            protocol_fee,
-        })
+            destination_amount_swapped,
+            trade_fee: 0,
+            protocol_fee: 0,
+            destination_trade_fee: trade_fee,
+            destination_protocol_fee: protocol_fee,
+            rounding_dust,
+        };
+        logging::swap_event(
+            SwapKind::BaseOutput,
+            swap_source_amount,
+            swap_destination_amount,
+            &result,
+        );
+        logging::fee_event(SwapKind::BaseOutput, trade_fee_rate, protocol_fee_rate, &result);
+        Some(result)
+    }
+
+    // Source tokens paid beyond the minimum needed to receive the destination
+    // amount, ie. `source_amount_less_fees - ceil(x * delta_y / (y - delta_y))`.
+    //
+    // Computed in U256 since `x * delta_y` can exceed u128 even when the swap
+    // itself does not overflow.
+    pub(crate) fn rounding_dust(
+        source_amount_less_fees: u128,
+        destination_amount_swapped: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+    ) -> Option<u128> {
+        let numerator = U256::from(swap_source_amount) * U256::from(destination_amount_swapped);
+        let denominator = U256::from(swap_destination_amount.checked_sub(destination_amount_swapped)?);
+        if denominator.is_zero() {
+            return None;
+        }
+        let (quotient, remainder) = numerator.div_mod(denominator);
+        let required = if remainder.is_zero() {
+            quotient
+        } else {
+            quotient + 1
+        };
+        if required >= U256::from(source_amount_less_fees) {
+            Some(0)
+        } else {
+            Some(source_amount_less_fees - required.as_u128())
+        }
     }
 
     /// Get the amount of trading tokens for the given amount of pool tokens,
@@ -160,189 +931,596 @@ impl CurveCalculator {
         swap_token_1_amount: u128,
         round_direction: RoundDirection,
     ) -> Option<TradingTokenResult> {
-        ConstantProductCurve::lp_tokens_to_trading_tokens(
+        Self::try_lp_tokens_to_trading_tokens(
             lp_token_amount,
             lp_token_supply,
             swap_token_0_amount,
             swap_token_1_amount,
             round_direction,
         )
+        .ok()
     }
-}
-
-// Test helpers for curves
-#[cfg(test)]
-pub mod test {
-    use {
-        super::*, proptest::prelude::*, spl_math::precise_number::PreciseNumber,
-        spl_math::uint::U256,
-    };
 
-    /// The epsilon for most curves when performing the conversion test,
-    /// comparing a one-sided deposit to a swap + deposit.
-    pub const CONVERSION_BASIS_POINTS_GUARANTEE: u128 = 50;
-
-    /// Calculates the total normalized value of the curve given the liquidity
-    /// parameters.
+    /// Like `lp_tokens_to_trading_tokens`, reporting why the conversion
+    /// fails.
     ///
-    /// The constant product implementation for this function gives the square root
-    /// of the Uniswap invariant.
-    pub fn normalized_value(
-        swap_token_a_amount: u128,
-        swap_token_b_amount: u128,
-    ) -> Option<PreciseNumber> {
-        let swap_token_a_amount = PreciseNumber::new(swap_token_a_amount)?;
-        let swap_token_b_amount = PreciseNumber::new(swap_token_b_amount)?;
-        swap_token_a_amount
-            .checked_mul(&swap_token_b_amount)?
-            .sqrt()
-    }
-    // Test function checking that a swap never reduces the overall value of
-    // the pool.
-    //
-    // Since curve calculations use unsigned integers, there is potential for
-    // truncation at some point, meaning a potential for value to be lost in
-    // either direction if too much is given to the swapper.
-    //
-    // This test guarantees that the relative change in value will be at most
-    // 1 normalized token, and that the value will never decrease from a trade.
-    pub fn check_curve_value_from_swap(
-        source_token_amount: u128,
-        swap_source_amount: u128,
-        swap_destination_amount: u128,
-        trade_direction: TradeDirection,
-    ) {
-        // Calculate the destination amount swapped using the constant product curve.
-        let destination_amount_swapped = ConstantProductCurve::swap_base_input_without_fees(
-            source_token_amount,
-            swap_source_amount,
-            swap_destination_amount,
-        );
-
-        // Determine the token amounts based on the trade direction.
-        let (swap_token_0_amount, swap_token_1_amount) = match trade_direction {
-            TradeDirection::ZeroForOne => (swap_source_amount, swap_destination_amount),
-            TradeDirection::OneForZero => (swap_destination_amount, swap_source_amount),
-        };
-
-        // Calculate the invariant (product of token amounts) before the swap.
-        let previous_value = swap_token_0_amount.checked_mul(swap_token_1_amount).unwrap();
-
-        // Calculate the new token amounts after the swap.
-        let new_swap_source_amount = swap_source_amount.checked_add(source_token_amount).unwrap();
-        let new_swap_destination_amount =
-            swap_destination_amount.checked_sub(destination_amount_swapped).unwrap();
-
-        // Determine the new token amounts based on the trade direction.
-        let (swap_token_0_amount, swap_token_1_amount) = match trade_direction {
-            TradeDirection::ZeroForOne => (new_swap_source_amount, new_swap_destination_amount),
-            TradeDirection::OneForZero => (new_swap_destination_amount, new_swap_source_amount),
-        };
-
-        // Calculate the new invariant after the swap.
-        let new_value = swap_token_0_amount.checked_mul(swap_token_1_amount).unwrap();
-
-        // Assert that the new invariant is greater than or equal to the previous invariant.
-        assert!(new_value >= previous_value);
+    /// A zero supply fails with `CurveError::ZeroSupply` and a zero
+    /// `lp_token_amount` with `CurveError::ZeroTradeAmount`. Empty reserves
+    /// are allowed and convert to zero tokens.
+    pub fn try_lp_tokens_to_trading_tokens(
+        lp_token_amount: u128,
+        lp_token_supply: u128,
+        swap_token_0_amount: u128,
+        swap_token_1_amount: u128,
+        round_direction: RoundDirection,
+    ) -> Result<TradingTokenResult, CurveError> {
+        if lp_token_supply == 0 {
+            return Err(CurveError::ZeroSupply);
+        }
+        if lp_token_amount == 0 {
+            return Err(CurveError::ZeroTradeAmount);
+        }
+        ConstantProductCurve::lp_tokens_to_trading_tokens(
+            lp_token_amount,
+            lp_token_supply,
+            swap_token_0_amount,
+            swap_token_1_amount,
+            round_direction,
+        )
+        .ok_or(CurveError::Overflow)
     }
 
+    /// LP tokens minted for the first deposit into a pool without LP
+    /// supply: `sqrt(token_0_amount * token_1_amount)`, rounded down.
+    ///
+    /// Returns `None` if either amount is zero.
+    pub fn initial_lp_tokens(token_0_amount: u128, token_1_amount: u128) -> Option<u128> {
+        // the root of a product of two u128 values fits in u128
+        let lp_token_amount = (U256::from(token_0_amount) * U256::from(token_1_amount))
+            .sqrt_floor()
+            .as_u128();
+        (lp_token_amount != 0).then_some(lp_token_amount)
+    }
 
-    /// Test function checking that a deposit never reduces the value of pool
-    /// tokens.
+    /// Deposit up to `maximum_token_0_amount` and `maximum_token_1_amount`
+    /// in proportion to the reserves, minting as many LP tokens as the
+    /// scarcer side allows.
     ///
-    /// Since curve calculations use unsigned integers, there is potential for
-    /// truncation at some point, meaning a potential for value to be lost if
-    /// too much is given to the depositor.
-    pub fn check_pool_value_from_deposit(
-        lp_token_amount: u128,
+    /// The pool takes the rounded-up price of the minted LP tokens, never
+    /// more than offered; whatever the ratio or rounding leaves over is
+    /// returned as `unused_token_0` and `unused_token_1` for the caller to
+    /// refund.
+    ///
+    /// A pool without LP supply, new or fully withdrawn, is bootstrapped
+    /// instead: it takes both amounts in full and mints `initial_lp_tokens`,
+    /// and the depositor owns whatever the reserves still hold. Returns
+    /// `None` if a reserve is empty while there is LP supply, or if the
+    /// deposit is too small to mint a single LP token.
+    pub fn deposit_trading_tokens(
+        maximum_token_0_amount: u128,
+        maximum_token_1_amount: u128,
         lp_token_supply: u128,
         swap_token_0_amount: u128,
         swap_token_1_amount: u128,
-    ) {
-        let deposit_result = CurveCalculator::lp_tokens_to_trading_tokens(
-            lp_token_amount,
+    ) -> Option<DepositResult> {
+        Self::deposit_trading_tokens_rounded(
+            maximum_token_0_amount,
+            maximum_token_1_amount,
             lp_token_supply,
             swap_token_0_amount,
             swap_token_1_amount,
             RoundDirection::Ceiling,
         )
-        .unwrap();
-        let new_swap_token_0_amount = swap_token_0_amount + deposit_result.token_0_amount;
-        let new_swap_token_1_amount = swap_token_1_amount + deposit_result.token_1_amount;
-        let new_lp_token_supply = lp_token_supply + lp_token_amount;
-
-        // the following inequality must hold:
-        // new_token_a / new_pool_token_supply >= token_a / pool_token_supply
-        // which reduces to:
-        // new_token_a * pool_token_supply >= token_a * new_pool_token_supply
-
-        // These numbers can be just slightly above u64 after the deposit, which
-        // means that their multiplication can be just above the range of u128.
-        // For ease of testing, we bump these up to U256.
-        let lp_token_supply = U256::from(lp_token_supply);
-        let new_lp_token_supply = U256::from(new_lp_token_supply);
-        let swap_token_0_amount = U256::from(swap_token_0_amount);
-        let new_swap_token_0_amount = U256::from(new_swap_token_0_amount);
-        let swap_token_b_amount = U256::from(swap_token_1_amount);
-        let new_swap_token_b_amount = U256::from(new_swap_token_1_amount);
-
-        assert!(
-            new_swap_token_0_amount * lp_token_supply >= swap_token_0_amount * new_lp_token_supply
-        );
-        assert!(
-            new_swap_token_b_amount * lp_token_supply >= swap_token_b_amount * new_lp_token_supply
-        );
     }
 
-    /// Test function checking that a withdraw never reduces the value of pool
-    /// tokens.
-    ///
-    /// Since curve calculations use unsigned integers, there is potential for
-    /// truncation at some point, meaning a potential for value to be lost if
-    /// too much is given to the depositor.
-    pub fn check_pool_value_from_withdraw(
-        lp_token_amount: u128,
+    // `deposit_trading_tokens`, with the price of the minted LP tokens
+    // rounded in `round_direction`
+    pub(crate) fn deposit_trading_tokens_rounded(
+        maximum_token_0_amount: u128,
+        maximum_token_1_amount: u128,
         lp_token_supply: u128,
         swap_token_0_amount: u128,
         swap_token_1_amount: u128,
-    ) {
-        let withdraw_result = CurveCalculator::lp_tokens_to_trading_tokens(
+        round_direction: RoundDirection,
+    ) -> Option<DepositResult> {
+        if lp_token_supply == 0 {
+            return Some(DepositResult {
+                lp_token_amount: Self::initial_lp_tokens(
+                    maximum_token_0_amount,
+                    maximum_token_1_amount,
+                )?,
+                trading_tokens: TradingTokenResult {
+                    token_0_amount: maximum_token_0_amount,
+                    token_1_amount: maximum_token_1_amount,
+                    token_0_rounding_dust: 0,
+                    token_1_rounding_dust: 0,
+                },
+                unused_token_0: 0,
+                unused_token_1: 0,
+            });
+        }
+        if swap_token_0_amount == 0 || swap_token_1_amount == 0 {
+            return None;
+        }
+        let supply = U256::from(lp_token_supply);
+        let lp_token_amount = to_u128(
+            (U256::from(maximum_token_0_amount) * supply / U256::from(swap_token_0_amount))
+                .min(U256::from(maximum_token_1_amount) * supply / U256::from(swap_token_1_amount)),
+        )?;
+        let trading_tokens = Self::lp_tokens_to_trading_tokens(
             lp_token_amount,
             lp_token_supply,
             swap_token_0_amount,
             swap_token_1_amount,
+            round_direction,
+        )?;
+        Some(DepositResult {
+            lp_token_amount,
+            unused_token_0: maximum_token_0_amount.checked_sub(trading_tokens.token_0_amount)?,
+            unused_token_1: maximum_token_1_amount.checked_sub(trading_tokens.token_1_amount)?,
+            trading_tokens,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::curve::fees::{FEE_RATE_DENOMINATOR_VALUE, MAX_TRADE_FEE_RATE},
+        proptest::prelude::*,
+    };
+
+    #[test]
+    fn results_display_compact_and_pretty() {
+        let result = CurveCalculator::swap_base_input(
+            &SwapParams::from_raw_parts(1_000_000, 1_000_000, 4_000_000, 2_500, 120_000).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            result.to_string(),
+            "source_amount_swapped=1000000 destination_amount_swapped=1997496 trade_fee=2500 \
+             protocol_fee=300 destination_trade_fee=0 destination_protocol_fee=0 \
+             rounding_dust=0 new_swap_source_amount=2000000 new_swap_destination_amount=2002504"
+        );
+        assert_eq!(
+            format!("{result:#}"),
+            "source amount swapped:       1,000,000\n\
+             destination amount swapped:  1,997,496\n\
+             trade fee:                   2,500\n\
+             protocol fee:                300\n\
+             destination trade fee:       0\n\
+             destination protocol fee:    0\n\
+             rounding dust:               0\n\
+             new swap source amount:      2,000,000\n\
+             new swap destination amount: 2,002,504"
+        );
+
+        let result = CurveCalculator::lp_tokens_to_trading_tokens(
+            1,
+            3,
+            1_000_000,
+            2_000_000,
             RoundDirection::Floor,
         )
         .unwrap();
-        let new_swap_token_0_amount = swap_token_0_amount - withdraw_result.token_0_amount;
-        let new_swap_token_1_amount = swap_token_1_amount - withdraw_result.token_1_amount;
-        let new_pool_token_supply = lp_token_supply - lp_token_amount;
+        assert_eq!(
+            format!("{result:#}"),
+            "token 0 amount:        333,333\n\
+             token 1 amount:        666,666\n\
+             token 0 rounding dust: 1\n\
+             token 1 rounding dust: 2"
+        );
+    }
 
-        let value = normalized_value(swap_token_0_amount, swap_token_1_amount).unwrap();
-        // since we can get rounding issues on the pool value which make it seem that
-        // the value per token has gone down, we bump it up by an epsilon of 1
-        // to cover all cases
-        let new_value = normalized_value(new_swap_token_0_amount, new_swap_token_1_amount).unwrap();
+    #[test]
+    fn exact_out_at_the_reserve_boundary() {
+        let swap = |destination_amount| {
+            CurveCalculator::try_swap_base_output(
+                &SwapParams::from_raw_parts(destination_amount, 50_000, 80_000, 2_500, 0).unwrap(),
+            )
+        };
+        assert!(swap(79_999).is_ok());
+        assert_eq!(swap(80_000), Err(CurveError::InsufficientLiquidity));
+        assert_eq!(swap(u128::MAX), Err(CurveError::InsufficientLiquidity));
+        // the input needed or its fee gross-up does not fit
+        assert_eq!(
+            CurveCalculator::try_swap_base_output(
+                &SwapParams::from_raw_parts(u128::MAX - 1, u128::MAX, u128::MAX, 0, 0).unwrap()
+            ),
+            Err(CurveError::Overflow)
+        );
+        assert_eq!(
+            CurveCalculator::try_swap_base_output(
+                &SwapParams::from_raw_parts(1, u128::MAX, 2, 2_500, 0).unwrap()
+            ),
+            Err(CurveError::Overflow)
+        );
+        assert_eq!(
+            CurveCalculator::swap_base_output(
+                &SwapParams::from_raw_parts(80_000, 50_000, 80_000, 2_500, 0).unwrap()
+            ),
+            None
+        );
+    }
 
-        // the following inequality must hold:
-        // new_pool_value / new_pool_token_supply >= pool_value / pool_token_supply
-        // which can also be written:
-        // new_pool_value * pool_token_supply >= pool_value * new_pool_token_supply
+    #[test]
+    fn zero_amounts_and_reserves_are_rejected() {
+        let input = |source_amount, swap_source_amount, swap_destination_amount| {
+            CurveCalculator::try_swap_base_input(
+                &SwapParams::from_raw_parts(
+                    source_amount,
+                    swap_source_amount,
+                    swap_destination_amount,
+                    2_500,
+                    0,
+                )
+                .unwrap(),
+                TinySwapPolicy::Clamp,
+            )
+        };
+        let output = |destination_amount, swap_source_amount, swap_destination_amount| {
+            CurveCalculator::try_swap_base_output(
+                &SwapParams::from_raw_parts(
+                    destination_amount,
+                    swap_source_amount,
+                    swap_destination_amount,
+                    2_500,
+                    0,
+                )
+                .unwrap(),
+            )
+        };
+        assert_eq!(input(0, 50_000, 80_000), Err(CurveError::ZeroTradeAmount));
+        assert_eq!(output(0, 50_000, 80_000), Err(CurveError::ZeroTradeAmount));
+        // a zero amount is reported before an empty pool
+        assert_eq!(input(0, 0, 0), Err(CurveError::ZeroTradeAmount));
+        for (swap_source_amount, swap_destination_amount) in [(0, 80_000), (50_000, 0), (0, 0)] {
+            assert_eq!(
+                input(1_000, swap_source_amount, swap_destination_amount),
+                Err(CurveError::EmptyPool)
+            );
+            assert_eq!(
+                output(1_000, swap_source_amount, swap_destination_amount),
+                Err(CurveError::EmptyPool)
+            );
+            assert_eq!(
+                CurveCalculator::swap_base_input_fee_on_output(
+                    1_000,
+                    swap_source_amount,
+                    swap_destination_amount,
+                    2_500,
+                    0
+                ),
+                None
+            );
+            assert_eq!(
+                CurveCalculator::swap_base_output_fee_on_output(
+                    1_000,
+                    swap_source_amount,
+                    swap_destination_amount,
+                    2_500,
+                    0
+                ),
+                None
+            );
+        }
+        assert_eq!(
+            CurveCalculator::swap_base_input(
+                &SwapParams::from_raw_parts(0, 50_000, 80_000, 2_500, 0).unwrap()
+            ),
+            None
+        );
+        assert_eq!(
+            CurveCalculator::swap_base_input_fee_on_output(0, 50_000, 80_000, 2_500, 0),
+            None
+        );
+        assert_eq!(
+            CurveCalculator::swap_base_output_fee_on_output(0, 50_000, 80_000, 2_500, 0),
+            None
+        );
 
-        let lp_token_supply = PreciseNumber::new(lp_token_supply).unwrap();
-        let new_lp_token_supply = PreciseNumber::new(new_pool_token_supply).unwrap();
-        assert!(new_value
-            .checked_mul(&lp_token_supply)
-            .unwrap()
-            .greater_than_or_equal(&value.checked_mul(&new_lp_token_supply).unwrap()));
+        let lp = |lp_token_amount, lp_token_supply, swap_token_0_amount| {
+            CurveCalculator::try_lp_tokens_to_trading_tokens(
+                lp_token_amount,
+                lp_token_supply,
+                swap_token_0_amount,
+                80_000,
+                RoundDirection::Floor,
+            )
+        };
+        assert_eq!(lp(10, 0, 50_000), Err(CurveError::ZeroSupply));
+        assert_eq!(lp(0, 0, 50_000), Err(CurveError::ZeroSupply));
+        assert_eq!(lp(0, 100, 50_000), Err(CurveError::ZeroTradeAmount));
+        // an emptied reserve converts to nothing
+        let result = lp(10, 100, 0).unwrap();
+        assert_eq!((result.token_0_amount, result.token_1_amount), (0, 8_000));
+        assert_eq!(
+            CurveCalculator::lp_tokens_to_trading_tokens(
+                10,
+                0,
+                50_000,
+                80_000,
+                RoundDirection::Floor
+            ),
+            None
+        );
     }
 
-    prop_compose! {
-        pub fn total_and_intermediate(max_value: u64)(total in 1..max_value)
-                        (intermediate in 1..total, total in Just(total))
-                        -> (u64, u64) {
-           (total, intermediate)
-       }
+    #[test]
+    fn out_of_bounds_fee_rates_are_rejected() {
+        let max = MAX_TRADE_FEE_RATE.get();
+        // rates above 100% are not `FeeRate`s, so no swap can be quoted at them
+        let input = |trade_fee_rate, protocol_fee_rate| {
+            SwapParams::from_raw_parts(1_000, 50_000, 80_000, trade_fee_rate, protocol_fee_rate)
+                .map(|params| CurveCalculator::try_swap_base_input(&params, TinySwapPolicy::Clamp))
+        };
+        let output = |trade_fee_rate, protocol_fee_rate| {
+            SwapParams::from_raw_parts(1_000, 50_000, 80_000, trade_fee_rate, protocol_fee_rate)
+                .map(|params| CurveCalculator::try_swap_base_output(&params))
+        };
+        assert!(matches!(
+            input(max, FEE_RATE_DENOMINATOR_VALUE),
+            Some(Ok(_))
+        ));
+        assert!(matches!(
+            output(max, FEE_RATE_DENOMINATOR_VALUE),
+            Some(Ok(_))
+        ));
+        for (trade_fee_rate, protocol_fee_rate) in [
+            (max + 1, 0),
+            (FEE_RATE_DENOMINATOR_VALUE, 0),
+            (u64::MAX, 0),
+            (2_500, FEE_RATE_DENOMINATOR_VALUE + 1),
+            (2_500, u64::MAX),
+        ] {
+            assert!(matches!(
+                input(trade_fee_rate, protocol_fee_rate),
+                None | Some(Err(CurveError::FeeConfigInvalid))
+            ));
+            assert!(matches!(
+                output(trade_fee_rate, protocol_fee_rate),
+                None | Some(Err(CurveError::FeeConfigInvalid))
+            ));
+            assert_eq!(
+                CurveCalculator::swap_base_input_fee_on_output(
+                    1_000,
+                    50_000,
+                    80_000,
+                    trade_fee_rate,
+                    protocol_fee_rate
+                ),
+                None
+            );
+            assert_eq!(
+                CurveCalculator::swap_base_output_fee_on_output(
+                    1_000,
+                    50_000,
+                    80_000,
+                    trade_fee_rate,
+                    protocol_fee_rate
+                ),
+                None
+            );
+        }
+        // a corrupted config is reported before anything else
+        let corrupted = SwapParams::new(
+            TradeDirection::ZeroForOne,
+            0,
+            Reserves::default(),
+            FeeConfig::new(FeeRate::MAX, FeeRate::ZERO),
+        );
+        assert_eq!(
+            CurveCalculator::try_swap_base_output(&corrupted),
+            Err(CurveError::FeeConfigInvalid)
+        );
+    }
+
+    #[test]
+    fn tiny_swaps_follow_the_policy() {
+        let swap = |source_amount, trade_fee_rate, policy| {
+            CurveCalculator::try_swap_base_input(
+                &SwapParams::from_raw_parts(source_amount, 50_000, 80_000, trade_fee_rate, 500_000)
+                    .unwrap(),
+                policy,
+            )
+        };
+        // a 1 unit swap pays a 1 unit fee and swaps nothing
+        let clamped = swap(1, 2_500, TinySwapPolicy::Clamp).unwrap();
+        assert_eq!(clamped.trade_fee, 1);
+        assert_eq!(clamped.destination_amount_swapped, 0);
+        assert_eq!(clamped.new_swap_source_amount, 50_001);
+        assert_eq!(
+            swap(1, 2_500, TinySwapPolicy::Reject),
+            Err(CurveError::MinimumInputNotMet { minimum_amount: 2 })
+        );
+        let minimum = swap(2, 2_500, TinySwapPolicy::Reject).unwrap();
+        assert_eq!(minimum.trade_fee, 1);
+        assert_eq!(minimum.destination_amount_swapped, 1);
+        // the clamp is the default behavior
+        assert_eq!(
+            CurveCalculator::swap_base_input(
+                &SwapParams::from_raw_parts(1, 50_000, 80_000, 2_500, 500_000).unwrap()
+            ),
+            swap(1, 2_500, TinySwapPolicy::Clamp).ok()
+        );
+    }
+
+    #[test]
+    fn deposits_into_pools_without_supply_bootstrap() {
+        assert_eq!(CurveCalculator::initial_lp_tokens(1_000, 4_000), Some(2_000));
+        assert_eq!(CurveCalculator::initial_lp_tokens(2, 3), Some(2));
+        assert_eq!(
+            CurveCalculator::initial_lp_tokens(u128::MAX, u128::MAX),
+            Some(u128::MAX)
+        );
+        assert_eq!(CurveCalculator::initial_lp_tokens(0, 4_000), None);
+        // no division by the zero supply, whatever the reserves hold
+        for reserves in [(0, 0), (5, 7)] {
+            let result =
+                CurveCalculator::deposit_trading_tokens(1_000, 4_000, 0, reserves.0, reserves.1)
+                    .unwrap();
+            assert_eq!(result.lp_token_amount, 2_000);
+            assert_eq!(result.trading_tokens.token_0_amount, 1_000);
+            assert_eq!(result.trading_tokens.token_1_amount, 4_000);
+            assert_eq!((result.unused_token_0, result.unused_token_1), (0, 0));
+        }
     }
 
+    #[test]
+    fn deposits_return_unused_tokens() {
+        // 1_000 token A buys 10 of 100 LP tokens, which need 2_000 token B
+        let result =
+            CurveCalculator::deposit_trading_tokens(1_000, 5_000, 100, 10_000, 20_000).unwrap();
+        assert_eq!(result.lp_token_amount, 10);
+        assert_eq!(result.trading_tokens.token_0_amount, 1_000);
+        assert_eq!(result.trading_tokens.token_1_amount, 2_000);
+        assert_eq!((result.unused_token_0, result.unused_token_1), (0, 3_000));
+        // an exactly proportional deposit can still leave rounding dust
+        let result = CurveCalculator::deposit_trading_tokens(10, 10, 3, 10, 10).unwrap();
+        assert_eq!(result.lp_token_amount, 3);
+        assert_eq!((result.unused_token_0, result.unused_token_1), (0, 0));
+        let result = CurveCalculator::deposit_trading_tokens(5, 5, 3, 10, 10).unwrap();
+        assert_eq!(result.lp_token_amount, 1);
+        assert_eq!(result.trading_tokens.token_0_amount, 4);
+        assert_eq!((result.unused_token_0, result.unused_token_1), (1, 1));
+        assert_eq!(
+            CurveCalculator::deposit_trading_tokens(1, 1, 3, 10, 10),
+            None
+        );
+        assert_eq!(
+            CurveCalculator::deposit_trading_tokens(0, 10, 0, 10, 10),
+            None
+        );
+        assert_eq!(
+            CurveCalculator::deposit_trading_tokens(10, 10, 3, 0, 10),
+            None
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn deposits_never_take_more_than_offered(
+            maximum_token_0_amount in 0..u64::MAX as u128,
+            maximum_token_1_amount in 0..u64::MAX as u128,
+            lp_token_supply in 1..u64::MAX as u128,
+            swap_token_0_amount in 1..u64::MAX as u128,
+            swap_token_1_amount in 1..u64::MAX as u128,
+        ) {
+            let Some(result) = CurveCalculator::deposit_trading_tokens(
+                maximum_token_0_amount,
+                maximum_token_1_amount,
+                lp_token_supply,
+                swap_token_0_amount,
+                swap_token_1_amount,
+            ) else {
+                return Ok(());
+            };
+            let tokens = &result.trading_tokens;
+            prop_assert_eq!(tokens.token_0_amount + result.unused_token_0, maximum_token_0_amount);
+            prop_assert_eq!(tokens.token_1_amount + result.unused_token_1, maximum_token_1_amount);
+            // one more LP token would be worth more than was offered of a side
+            let more = U256::from(result.lp_token_amount) + 1;
+            let supply = U256::from(lp_token_supply);
+            prop_assert!(
+                more * U256::from(swap_token_0_amount) > U256::from(maximum_token_0_amount) * supply
+                    || more * U256::from(swap_token_1_amount)
+                        > U256::from(maximum_token_1_amount) * supply
+            );
+        }
+
+        #[test]
+        fn fee_never_exceeds_the_input(
+            source_amount in 1..u64::MAX as u128,
+            trade_fee_rate in 0..=FEE_RATE_DENOMINATOR_VALUE,
+        ) {
+            let swap = |source_amount| {
+                CurveCalculator::try_swap_base_input(
+                    &SwapParams::from_raw_parts(
+                        source_amount,
+                        1_000_000,
+                        1_000_000,
+                        trade_fee_rate,
+                        0,
+                    )
+                    .unwrap(),
+                    TinySwapPolicy::Reject,
+                )
+            };
+            let result = swap(source_amount);
+            match result {
+                Ok(result) => prop_assert!(result.trade_fee < source_amount),
+                Err(CurveError::MinimumInputNotMet { minimum_amount }) => {
+                    prop_assert!(source_amount < minimum_amount);
+                    let minimum = swap(minimum_amount).unwrap();
+                    prop_assert!(minimum.trade_fee < minimum_amount);
+                }
+                Err(error) => {
+                    prop_assert_eq!(error, CurveError::FeeConfigInvalid);
+                    prop_assert!(trade_fee_rate > MAX_TRADE_FEE_RATE.get());
+                }
+            }
+        }
+
+        #[test]
+        fn exact_out_never_panics_up_to_and_past_the_reserve(
+            swap_source_amount in 0..=u128::MAX,
+            swap_destination_amount in 0..u64::MAX as u128,
+            // offsets around the reserve, from well below to past it
+            offset in -1_000i128..1_000,
+            trade_fee_rate in 0..=MAX_TRADE_FEE_RATE.get() + 1,
+            protocol_fee_rate in 0..=FEE_RATE_DENOMINATOR_VALUE,
+        ) {
+            let destination_amount = swap_destination_amount.saturating_add_signed(offset);
+            let result = CurveCalculator::try_swap_base_output(
+                &SwapParams::from_raw_parts(
+                    destination_amount,
+                    swap_source_amount,
+                    swap_destination_amount,
+                    trade_fee_rate,
+                    protocol_fee_rate,
+                )
+                .unwrap(),
+            );
+            if trade_fee_rate > MAX_TRADE_FEE_RATE.get() {
+                prop_assert_eq!(result, Err(CurveError::FeeConfigInvalid));
+            } else if destination_amount == 0 {
+                prop_assert_eq!(result, Err(CurveError::ZeroTradeAmount));
+            } else if swap_source_amount == 0 || swap_destination_amount == 0 {
+                prop_assert_eq!(result, Err(CurveError::EmptyPool));
+            } else if destination_amount >= swap_destination_amount {
+                prop_assert_eq!(result, Err(CurveError::InsufficientLiquidity));
+            } else if let Ok(result) = result {
+                prop_assert_eq!(result.destination_amount_swapped, destination_amount);
+                let before = U256::from(swap_source_amount) * U256::from(swap_destination_amount);
+                let after = U256::from(result.new_swap_source_amount)
+                    * U256::from(result.new_swap_destination_amount);
+                prop_assert!(after >= before);
+            }
+        }
+
+        #[test]
+        fn exact_out_succeeds_below_the_reserve(
+            swap_source_amount in 1..u32::MAX as u128,
+            swap_destination_amount in 2..u64::MAX as u128,
+            fraction in 0.0..1.0f64,
+            trade_fee_rate in 0..=MAX_TRADE_FEE_RATE.get(),
+        ) {
+            // with these reserves even the last unit of the destination
+            // reserve, grossed up by the largest trade fee, fits in u128
+            let destination_amount =
+                1 + ((swap_destination_amount - 2) as f64 * fraction) as u128;
+            let result = CurveCalculator::try_swap_base_output(
+                &SwapParams::from_raw_parts(
+                    destination_amount,
+                    swap_source_amount,
+                    swap_destination_amount,
+                    trade_fee_rate,
+                    0,
+                )
+                .unwrap(),
+            );
+            prop_assert!(result.is_ok(), "{:?}", result);
+        }
+    }
 }
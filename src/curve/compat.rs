@@ -0,0 +1,762 @@
+//! Compatibility with other AMM programs
+//!
+//! Pools porting over from another program, and routers quoting several,
+//! need quotes that match that program to the token.
+//!
+//! # SPL token-swap
+//!
+//! The destination amount of a constant product swap and the LP token
+//! conversions already agree bit-for-bit whenever SPL succeeds; two things
+//! differ:
+//!
+//! - SPL only charges the source tokens needed for the destination amount,
+//!   `ceil(x * y / ceil(x * y / (x + delta_x))) - x`, leaving the rest with
+//!   the trader, where this crate keeps the whole input and reports the
+//!   excess as `rounding_dust`. SPL also rejects swaps that would return
+//!   nothing, and fails where `x * y` overflows u128.
+//! - SPL fees are separate trade and owner rates on the source amount, each
+//!   rounded down but at least one token when the rate is non-zero.
+//!
+//! `Compatibility::SplTokenSwap` selects SPL's rounding for the curve, and
+//! `CurveCalculator::spl_swap` reproduces a whole SPL swap, fees included.
+//!
+//! # Raydium CP-swap
+//!
+//! Raydium orders fees as this crate does: the trade fee is charged on the
+//! input, rounded up, and the protocol fee is a share of it, rounded down.
+//! It differs in two ways:
+//!
+//! - Raydium's exact-out source amount rounds quotients below one to the
+//!   nearest integer instead of up, so an output worth less than half a
+//!   source token is quoted for nothing.
+//! - Raydium also carves a fund fee out of the trade fee, rounded down like
+//!   the protocol fee.
+//!
+//! `Compatibility::RaydiumCpSwap` selects Raydium's rounding for the curve,
+//! and `CurveCalculator::raydium_swap_base_input` and
+//! `CurveCalculator::raydium_swap_base_output` reproduce whole Raydium swaps.
+use crate::curve::{
+    calculator::{CurveCalculator, SwapResult},
+    constant_product::ConstantProductCurve,
+    fees::{FeeRate, Fees},
+};
+
+/// Whose rounding conventions a swap follows
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compatibility {
+    /// This crate's: the whole input is swapped, and tiny swaps may return
+    /// nothing
+    #[default]
+    Native,
+    /// The SPL token-swap program's: only the source tokens needed are
+    /// swapped, and swaps returning nothing fail
+    SplTokenSwap,
+    /// Raydium CP-swap's: exact-out source amounts below one token are
+    /// rounded to the nearest integer
+    RaydiumCpSwap,
+}
+
+/// Amounts exchanged by a swap before fees
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SwapWithoutFeesResult {
+    /// Source tokens taken from the trader
+    pub source_amount_swapped: u128,
+    /// Destination tokens given to the trader
+    pub destination_amount_swapped: u128,
+}
+
+/// Fee rates of an SPL token-swap pool, as numerator / denominator pairs
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SplFees {
+    /// Trade fee numerator, accruing to LPs
+    pub trade_fee_numerator: u64,
+    /// Trade fee denominator
+    pub trade_fee_denominator: u64,
+    /// Owner trade fee numerator, minted to the owner as pool tokens
+    pub owner_trade_fee_numerator: u64,
+    /// Owner trade fee denominator
+    pub owner_trade_fee_denominator: u64,
+}
+
+impl SplFees {
+    // `floor(amount * numerator / denominator)`, at least 1 unless the rate
+    // or amount is zero, as in SPL's `calculate_fee`
+    fn calculate_fee(amount: u128, numerator: u64, denominator: u64) -> Option<u128> {
+        if numerator == 0 || amount == 0 {
+            return Some(0);
+        }
+        let fee = amount
+            .checked_mul(numerator as u128)?
+            .checked_div(denominator as u128)?;
+        Some(fee.max(1))
+    }
+
+    /// Trade fee on `amount` source tokens
+    pub fn trading_fee(&self, amount: u128) -> Option<u128> {
+        Self::calculate_fee(amount, self.trade_fee_numerator, self.trade_fee_denominator)
+    }
+
+    /// Owner trade fee on `amount` source tokens
+    pub fn owner_trading_fee(&self, amount: u128) -> Option<u128> {
+        Self::calculate_fee(
+            amount,
+            self.owner_trade_fee_numerator,
+            self.owner_trade_fee_denominator,
+        )
+    }
+}
+
+/// Fee rates of a Raydium CP-swap pool, in units of
+/// `FEE_RATE_DENOMINATOR_VALUE`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RaydiumFees {
+    /// Trade fee rate, on the input
+    pub trade_fee_rate: FeeRate,
+    /// Protocol's share of the trade fee
+    pub protocol_fee_rate: FeeRate,
+    /// Fund's share of the trade fee
+    pub fund_fee_rate: FeeRate,
+}
+
+/// A swap priced as Raydium CP-swap prices it
+#[derive(Debug, PartialEq)]
+pub struct RaydiumSwapResult {
+    /// The swap, with the protocol's share of the trade fee as
+    /// `protocol_fee`
+    pub result: SwapResult,
+    /// Fund's share of the trade fee, in the source token. Like the
+    /// protocol fee, it is included in `new_swap_source_amount`.
+    pub fund_fee: u128,
+}
+
+impl ConstantProductCurve {
+    /// Swap `source_amount` before fees, rounding as `compatibility` says.
+    ///
+    /// All modes return the same destination amount; `SplTokenSwap` may
+    /// take fewer source tokens, and returns `None` where SPL fails.
+    pub fn swap_without_fees(
+        source_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        compatibility: Compatibility,
+    ) -> Option<SwapWithoutFeesResult> {
+        match compatibility {
+            Compatibility::Native | Compatibility::RaydiumCpSwap => {
+                let numerator = source_amount.checked_mul(swap_destination_amount)?;
+                let denominator = swap_source_amount.checked_add(source_amount)?;
+                Some(SwapWithoutFeesResult {
+                    source_amount_swapped: source_amount,
+                    destination_amount_swapped: numerator.checked_div(denominator)?,
+                })
+            }
+            Compatibility::SplTokenSwap => {
+                let invariant = swap_source_amount.checked_mul(swap_destination_amount)?;
+                let new_swap_source_amount = swap_source_amount.checked_add(source_amount)?;
+                let (new_swap_destination_amount, new_swap_source_amount) =
+                    spl_ceil_div(invariant, new_swap_source_amount)?;
+                let destination_amount_swapped =
+                    swap_destination_amount.checked_sub(new_swap_destination_amount)?;
+                if destination_amount_swapped == 0 {
+                    return None;
+                }
+                Some(SwapWithoutFeesResult {
+                    source_amount_swapped: new_swap_source_amount
+                        .checked_sub(swap_source_amount)?,
+                    destination_amount_swapped,
+                })
+            }
+        }
+    }
+
+    /// Source tokens before fees buying `destination_amount`, rounding as
+    /// `compatibility` says.
+    ///
+    /// Returns `None` for `SplTokenSwap`, which has no exact-out swaps, and
+    /// where the pool cannot pay out `destination_amount`.
+    pub fn source_amount_without_fees(
+        destination_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        compatibility: Compatibility,
+    ) -> Option<u128> {
+        let numerator = swap_source_amount.checked_mul(destination_amount)?;
+        let denominator = swap_destination_amount.checked_sub(destination_amount)?;
+        if denominator == 0 {
+            return None;
+        }
+        match compatibility {
+            Compatibility::Native => Some(numerator.div_ceil(denominator)),
+            Compatibility::SplTokenSwap => None,
+            // Raydium's `checked_ceil_div` rounds a zero quotient to the
+            // nearest integer
+            Compatibility::RaydiumCpSwap if numerator < denominator => {
+                Some((numerator.checked_mul(2)? >= denominator) as u128)
+            }
+            Compatibility::RaydiumCpSwap => Some(numerator.div_ceil(denominator)),
+        }
+    }
+}
+
+// SPL's `checked_ceil_div`: `ceil(dividend / divisor)`, together with the
+// smallest divisor giving that same quotient. Fails on a zero quotient.
+fn spl_ceil_div(dividend: u128, divisor: u128) -> Option<(u128, u128)> {
+    let mut quotient = dividend.checked_div(divisor)?;
+    if quotient == 0 {
+        return None;
+    }
+    let mut divisor = divisor;
+    if dividend.checked_rem(divisor)? > 0 {
+        quotient = quotient.checked_add(1)?;
+        divisor = dividend.checked_div(quotient)?;
+        if dividend.checked_rem(quotient)? > 0 {
+            divisor = divisor.checked_add(1)?;
+        }
+    }
+    Some((quotient, divisor))
+}
+
+impl CurveCalculator {
+    /// Exact-in swap of `source_amount` priced as the SPL token-swap program
+    /// prices it on a constant product pool.
+    ///
+    /// `trade_fee` is the sum of SPL's trade and owner fees and
+    /// `protocol_fee` the owner's share, so the amounts line up with this
+    /// crate's `SwapResult`. `source_amount_swapped` can be less than
+    /// `source_amount`; the difference stays with the trader.
+    pub fn spl_swap(
+        source_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        fees: &SplFees,
+    ) -> Option<SwapResult> {
+        let trade_fee = fees.trading_fee(source_amount)?;
+        let owner_fee = fees.owner_trading_fee(source_amount)?;
+        let total_fees = trade_fee.checked_add(owner_fee)?;
+        let source_amount_less_fees = source_amount.checked_sub(total_fees)?;
+
+        let swapped = ConstantProductCurve::swap_without_fees(
+            source_amount_less_fees,
+            swap_source_amount,
+            swap_destination_amount,
+            Compatibility::SplTokenSwap,
+        )?;
+        let source_amount_swapped = swapped.source_amount_swapped.checked_add(total_fees)?;
+        Some(SwapResult {
+            new_swap_source_amount: swap_source_amount.checked_add(source_amount_swapped)?,
+            new_swap_destination_amount: swap_destination_amount
+                .checked_sub(swapped.destination_amount_swapped)?,
+            source_amount_swapped,
+            destination_amount_swapped: swapped.destination_amount_swapped,
+            trade_fee: total_fees,
+            protocol_fee: owner_fee,
+            destination_trade_fee: 0,
+            destination_protocol_fee: 0,
+            rounding_dust: Self::rounding_dust(
+                swapped.source_amount_swapped,
+                swapped.destination_amount_swapped,
+                swap_source_amount,
+                swap_destination_amount,
+            )?,
+        })
+    }
+}
+
+impl CurveCalculator {
+    /// Exact-in swap of `source_amount` priced as Raydium CP-swap prices it
+    pub fn raydium_swap_base_input(
+        source_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        fees: &RaydiumFees,
+    ) -> Option<RaydiumSwapResult> {
+        let trade_fee = Fees::trading_fee(source_amount, fees.trade_fee_rate.get())?;
+        let source_amount_less_fees = source_amount.checked_sub(trade_fee)?;
+        let swapped = ConstantProductCurve::swap_without_fees(
+            source_amount_less_fees,
+            swap_source_amount,
+            swap_destination_amount,
+            Compatibility::RaydiumCpSwap,
+        )?;
+        Self::raydium_swap_result(
+            source_amount,
+            swapped.destination_amount_swapped,
+            swap_source_amount,
+            swap_destination_amount,
+            trade_fee,
+            fees,
+        )
+    }
+
+    /// Exact-out swap for `destination_amount` priced as Raydium CP-swap
+    /// prices it
+    pub fn raydium_swap_base_output(
+        destination_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        fees: &RaydiumFees,
+    ) -> Option<RaydiumSwapResult> {
+        let source_amount_swapped = ConstantProductCurve::source_amount_without_fees(
+            destination_amount,
+            swap_source_amount,
+            swap_destination_amount,
+            Compatibility::RaydiumCpSwap,
+        )?;
+        let source_amount = fees.trade_fee_rate.gross_up(source_amount_swapped)?;
+        let trade_fee = Fees::trading_fee(source_amount, fees.trade_fee_rate.get())?;
+        Self::raydium_swap_result(
+            source_amount,
+            destination_amount,
+            swap_source_amount,
+            swap_destination_amount,
+            trade_fee,
+            fees,
+        )
+    }
+
+    // Split the trade fee and settle a Raydium swap
+    fn raydium_swap_result(
+        source_amount: u128,
+        destination_amount_swapped: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        trade_fee: u128,
+        fees: &RaydiumFees,
+    ) -> Option<RaydiumSwapResult> {
+        let result = SwapResult {
+            new_swap_source_amount: swap_source_amount.checked_add(source_amount)?,
+            new_swap_destination_amount: swap_destination_amount
+                .checked_sub(destination_amount_swapped)?,
+            source_amount_swapped: source_amount,
+            destination_amount_swapped,
+            trade_fee,
+            protocol_fee: fees.protocol_fee_rate.mul_floor(trade_fee)?,
+            destination_trade_fee: 0,
+            destination_protocol_fee: 0,
+            rounding_dust: Self::rounding_dust(
+                source_amount.checked_sub(trade_fee)?,
+                destination_amount_swapped,
+                swap_source_amount,
+                swap_destination_amount,
+            )?,
+        };
+        Some(RaydiumSwapResult {
+            result,
+            fund_fee: fees.fund_fee_rate.mul_floor(trade_fee)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::curve::{
+            calculator::RoundDirection,
+            fees::{FEE_RATE_DENOMINATOR_VALUE, MAX_TRADE_FEE_RATE, TinySwapPolicy},
+            swap_params::SwapParams,
+        },
+        proptest::prelude::*,
+    };
+
+    // Raydium CP-swap's calculator, transcribed with plain u128 arithmetic
+    // as a reference: (source amount, destination amount, trade fee,
+    // protocol fee, fund fee)
+    mod raydium {
+        const DENOMINATOR: u128 = 1_000_000;
+
+        fn ceil_div(amount: u128, numerator: u128, denominator: u128) -> u128 {
+            (amount * numerator).div_ceil(denominator)
+        }
+
+        fn checked_ceil_div(numerator: u128, denominator: u128) -> u128 {
+            let quotient = numerator / denominator;
+            if quotient == 0 {
+                (numerator * 2 >= denominator) as u128
+            } else if !numerator.is_multiple_of(denominator) {
+                quotient + 1
+            } else {
+                quotient
+            }
+        }
+
+        fn fees(trade_fee: u128, protocol_fee_rate: u64, fund_fee_rate: u64) -> (u128, u128) {
+            (
+                trade_fee * protocol_fee_rate as u128 / DENOMINATOR,
+                trade_fee * fund_fee_rate as u128 / DENOMINATOR,
+            )
+        }
+
+        pub fn swap_base_input(
+            source_amount: u128,
+            swap_source_amount: u128,
+            swap_destination_amount: u128,
+            rates: (u64, u64, u64),
+        ) -> (u128, u128, u128, u128, u128) {
+            let trade_fee = ceil_div(source_amount, rates.0 as u128, DENOMINATOR);
+            let source_amount_less_fees = source_amount - trade_fee;
+            let destination_amount = source_amount_less_fees * swap_destination_amount
+                / (swap_source_amount + source_amount_less_fees);
+            let (protocol_fee, fund_fee) = fees(trade_fee, rates.1, rates.2);
+            (
+                source_amount,
+                destination_amount,
+                trade_fee,
+                protocol_fee,
+                fund_fee,
+            )
+        }
+
+        pub fn swap_base_output(
+            destination_amount: u128,
+            swap_source_amount: u128,
+            swap_destination_amount: u128,
+            rates: (u64, u64, u64),
+        ) -> (u128, u128, u128, u128, u128) {
+            let source_amount_swapped = checked_ceil_div(
+                swap_source_amount * destination_amount,
+                swap_destination_amount - destination_amount,
+            );
+            let source_amount = if rates.0 == 0 {
+                source_amount_swapped
+            } else {
+                ceil_div(
+                    source_amount_swapped,
+                    DENOMINATOR,
+                    DENOMINATOR - rates.0 as u128,
+                )
+            };
+            let trade_fee = ceil_div(source_amount, rates.0 as u128, DENOMINATOR);
+            let (protocol_fee, fund_fee) = fees(trade_fee, rates.1, rates.2);
+            (
+                source_amount,
+                destination_amount,
+                trade_fee,
+                protocol_fee,
+                fund_fee,
+            )
+        }
+    }
+
+    fn raydium_fees(rates: (u64, u64, u64)) -> RaydiumFees {
+        RaydiumFees {
+            trade_fee_rate: FeeRate::checked(rates.0).unwrap(),
+            protocol_fee_rate: FeeRate::checked(rates.1).unwrap(),
+            fund_fee_rate: FeeRate::checked(rates.2).unwrap(),
+        }
+    }
+
+    fn raydium_fields(swap: &RaydiumSwapResult) -> (u128, u128, u128, u128, u128) {
+        (
+            swap.result.source_amount_swapped,
+            swap.result.destination_amount_swapped,
+            swap.result.trade_fee,
+            swap.result.protocol_fee,
+            swap.fund_fee,
+        )
+    }
+
+    // SPL's `constant_product_swap_rounding` vectors: (source amount,
+    // swap source amount, swap destination amount, source amount swapped,
+    // destination amount swapped)
+    const SPL_ROUNDING_VECTORS: &[(u128, u128, u128, u128, u128)] = &[
+        (10, 4_000_000, 70_000_000_000, 10, 174_999),
+        (20, 30_000 - 20, 10_000, 18, 6),
+        (19, 30_000 - 20, 10_000, 18, 6),
+        (18, 30_000 - 20, 10_000, 18, 6),
+        (10, 20_000, 30_000, 10, 14),
+        (10, 20_000 - 9, 30_000, 10, 14),
+        (10, 20_000 - 10, 30_000, 10, 15),
+        (100, 60_000, 30_000, 99, 49),
+        (99, 60_000, 30_000, 99, 49),
+        (98, 60_000, 30_000, 97, 48),
+    ];
+
+    #[test]
+    fn spl_rounding_vectors() {
+        for &(source_amount, swap_source_amount, swap_destination_amount, source, destination) in
+            SPL_ROUNDING_VECTORS
+        {
+            let spl = ConstantProductCurve::swap_without_fees(
+                source_amount,
+                swap_source_amount,
+                swap_destination_amount,
+                Compatibility::SplTokenSwap,
+            )
+            .unwrap();
+            assert_eq!(spl.source_amount_swapped, source);
+            assert_eq!(spl.destination_amount_swapped, destination);
+            // the native mode keeps the whole input for the same output
+            let native = ConstantProductCurve::swap_without_fees(
+                source_amount,
+                swap_source_amount,
+                swap_destination_amount,
+                Compatibility::Native,
+            )
+            .unwrap();
+            assert_eq!(native.source_amount_swapped, source_amount);
+            assert_eq!(native.destination_amount_swapped, destination);
+        }
+    }
+
+    #[test]
+    fn spl_rejects_empty_output() {
+        let swap =
+            |compatibility| ConstantProductCurve::swap_without_fees(1, 1_000, 10, compatibility);
+        assert_eq!(swap(Compatibility::SplTokenSwap), None);
+        assert_eq!(
+            swap(Compatibility::Native),
+            Some(SwapWithoutFeesResult {
+                source_amount_swapped: 1,
+                destination_amount_swapped: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn spl_fees_round_down_to_at_least_one() {
+        let fees = SplFees {
+            trade_fee_numerator: 25,
+            trade_fee_denominator: 10_000,
+            owner_trade_fee_numerator: 5,
+            owner_trade_fee_denominator: 10_000,
+        };
+        assert_eq!(fees.trading_fee(1_000), Some(2));
+        assert_eq!(fees.owner_trading_fee(1_000), Some(1));
+        assert_eq!(fees.owner_trading_fee(0), Some(0));
+        assert_eq!(SplFees::default().trading_fee(1_000), Some(0));
+
+        // 997 tokens swap after fees, all of them needed
+        let result = CurveCalculator::spl_swap(1_000, 50_000, 1_000_000, &fees).unwrap();
+        assert_eq!(
+            result,
+            SwapResult {
+                new_swap_source_amount: 51_000,
+                new_swap_destination_amount: 980_450,
+                source_amount_swapped: 1_000,
+                destination_amount_swapped: 19_550,
+                trade_fee: 3,
+                protocol_fee: 1,
+                destination_trade_fee: 0,
+                destination_protocol_fee: 0,
+                rounding_dust: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn raydium_splits_the_trade_fee() {
+        // Raydium's default 0.25% trade fee, 12% to the protocol and 4% to
+        // the fund
+        let fees = raydium_fees((2_500, 120_000, 40_000));
+        let swap = CurveCalculator::raydium_swap_base_input(
+            1_000_000,
+            1_000_000_000,
+            1_000_000_000,
+            &fees,
+        )
+        .unwrap();
+        assert_eq!(
+            swap,
+            RaydiumSwapResult {
+                result: SwapResult {
+                    new_swap_source_amount: 1_001_000_000,
+                    new_swap_destination_amount: 999_003_495,
+                    source_amount_swapped: 1_000_000,
+                    destination_amount_swapped: 996_505,
+                    trade_fee: 2_500,
+                    protocol_fee: 300,
+                    destination_trade_fee: 0,
+                    destination_protocol_fee: 0,
+                    rounding_dust: 0,
+                },
+                fund_fee: 100,
+            }
+        );
+    }
+
+    #[test]
+    fn raydium_rounds_tiny_sources_to_nearest() {
+        let source = |swap_source_amount, compatibility| {
+            ConstantProductCurve::source_amount_without_fees(
+                1,
+                swap_source_amount,
+                10,
+                compatibility,
+            )
+        };
+        // 4/9 of a token is quoted for nothing, 5/9 for one token
+        assert_eq!(source(4, Compatibility::RaydiumCpSwap), Some(0));
+        assert_eq!(source(4, Compatibility::Native), Some(1));
+        assert_eq!(source(5, Compatibility::RaydiumCpSwap), Some(1));
+        // SPL token-swap has no exact-out swaps
+        assert_eq!(source(5, Compatibility::SplTokenSwap), None);
+        assert_eq!(
+            ConstantProductCurve::source_amount_without_fees(10, 5, 10, Compatibility::Native),
+            None
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn raydium_matches_reference(
+            amount in 1..u64::MAX as u128,
+            swap_source_amount in 1..u64::MAX as u128,
+            swap_destination_amount in 2..u64::MAX as u128,
+            rates in (
+                0..FEE_RATE_DENOMINATOR_VALUE,
+                0..=FEE_RATE_DENOMINATOR_VALUE,
+                0..=FEE_RATE_DENOMINATOR_VALUE,
+            ),
+        ) {
+            let fees = raydium_fees(rates);
+            let swap = CurveCalculator::raydium_swap_base_input(
+                amount,
+                swap_source_amount,
+                swap_destination_amount,
+                &fees,
+            )
+            .unwrap();
+            prop_assert_eq!(
+                raydium_fields(&swap),
+                raydium::swap_base_input(amount, swap_source_amount, swap_destination_amount, rates)
+            );
+            let destination_amount = amount % swap_destination_amount;
+            prop_assume!(destination_amount != 0);
+            let swap = CurveCalculator::raydium_swap_base_output(
+                destination_amount,
+                swap_source_amount,
+                swap_destination_amount,
+                &fees,
+            )
+            .unwrap();
+            prop_assert_eq!(
+                raydium_fields(&swap),
+                raydium::swap_base_output(
+                    destination_amount,
+                    swap_source_amount,
+                    swap_destination_amount,
+                    rates,
+                )
+            );
+        }
+
+        #[test]
+        fn raydium_agrees_with_native_swaps(
+            amount in 1..u64::MAX as u128,
+            swap_source_amount in 1..u64::MAX as u128,
+            swap_destination_amount in 2..u64::MAX as u128,
+            trade_fee_rate in 0..=MAX_TRADE_FEE_RATE.get(),
+            protocol_fee_rate in 0..=FEE_RATE_DENOMINATOR_VALUE,
+        ) {
+            let fees = raydium_fees((trade_fee_rate, protocol_fee_rate, 0));
+            let raydium = CurveCalculator::raydium_swap_base_input(
+                amount,
+                swap_source_amount,
+                swap_destination_amount,
+                &fees,
+            )
+            .unwrap();
+            let native = CurveCalculator::try_swap_base_input(
+                &SwapParams::from_raw_parts(
+                    amount,
+                    swap_source_amount,
+                    swap_destination_amount,
+                    trade_fee_rate,
+                    protocol_fee_rate,
+                )
+                .unwrap(),
+                TinySwapPolicy::Clamp,
+            )
+            .unwrap();
+            prop_assert_eq!(raydium.result, native);
+
+            // exact-out only differs where the source rounds below one token
+            let destination_amount = amount % swap_destination_amount;
+            prop_assume!(destination_amount != 0);
+            prop_assume!(
+                swap_source_amount * destination_amount
+                    >= swap_destination_amount - destination_amount
+            );
+            let raydium = CurveCalculator::raydium_swap_base_output(
+                destination_amount,
+                swap_source_amount,
+                swap_destination_amount,
+                &fees,
+            )
+            .unwrap();
+            let native = CurveCalculator::try_swap_base_output(
+                &SwapParams::from_raw_parts(
+                    destination_amount,
+                    swap_source_amount,
+                    swap_destination_amount,
+                    trade_fee_rate,
+                    protocol_fee_rate,
+                )
+                .unwrap(),
+            )
+            .unwrap();
+            prop_assert_eq!(raydium.result, native);
+        }
+
+        #[test]
+        fn spl_matches_native_output(
+            source_amount in 1..u64::MAX as u128,
+            swap_source_amount in 1..u64::MAX as u128,
+            swap_destination_amount in 1..u64::MAX as u128,
+        ) {
+            let Some(spl) = ConstantProductCurve::swap_without_fees(
+                source_amount,
+                swap_source_amount,
+                swap_destination_amount,
+                Compatibility::SplTokenSwap,
+            ) else {
+                return Ok(());
+            };
+            let native = ConstantProductCurve::swap_without_fees(
+                source_amount,
+                swap_source_amount,
+                swap_destination_amount,
+                Compatibility::Native,
+            )
+            .unwrap();
+            prop_assert_eq!(spl.destination_amount_swapped, native.destination_amount_swapped);
+            prop_assert!(spl.source_amount_swapped <= source_amount);
+            // SPL's adjusted input still preserves the invariant
+            prop_assert!(
+                (swap_source_amount + spl.source_amount_swapped)
+                    * (swap_destination_amount - spl.destination_amount_swapped)
+                    >= swap_source_amount * swap_destination_amount
+            );
+        }
+
+        #[test]
+        fn lp_conversions_match_spl(
+            lp_token_amount in 0..u64::MAX as u128,
+            lp_token_supply in 1..u64::MAX as u128,
+            swap_token_0_amount in 0..u64::MAX as u128,
+            swap_token_1_amount in 0..u64::MAX as u128,
+        ) {
+            // SPL's `pool_tokens_to_trading_tokens`, for one token
+            let spl = |reserve: u128, round_direction| {
+                let amount = lp_token_amount * reserve / lp_token_supply;
+                let remainder = lp_token_amount * reserve % lp_token_supply;
+                match round_direction {
+                    RoundDirection::Ceiling if remainder > 0 && amount > 0 => amount + 1,
+                    _ => amount,
+                }
+            };
+            for round_direction in [RoundDirection::Floor, RoundDirection::Ceiling] {
+                let result = ConstantProductCurve::lp_tokens_to_trading_tokens(
+                    lp_token_amount,
+                    lp_token_supply,
+                    swap_token_0_amount,
+                    swap_token_1_amount,
+                    round_direction,
+                )
+                .unwrap();
+                prop_assert_eq!(result.token_0_amount, spl(swap_token_0_amount, round_direction));
+                prop_assert_eq!(result.token_1_amount, spl(swap_token_1_amount, round_direction));
+            }
+        }
+    }
+}
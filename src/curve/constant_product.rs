@@ -1,7 +1,6 @@
-// Import the `CheckedCeilDiv` utility for safe ceiling division operations.
 use crate::{
-    curve::calculator::{RoundDirection, TradingTokenResult},
-    utils::CheckedCeilDiv,
+    curve::calculator::{CurveError, RoundDirection, TradingTokenResult},
+    utils::MathUint,
 };
 
 // ConstantProductCurve struct implementing CurveCalculator
@@ -34,21 +33,107 @@ impl ConstantProductCurve {
     // * `swap_destination_amount` - The amount of destination tokens in the pool.
     //
     // # Returns
-    // The amount of destination tokens to be received.
-    pub fn swap_base_input_without_fees(
+    // The amount of destination tokens to be received, rounded down so the
+    // pool never gives out more than the invariant allows.
+    //
+    // Fails with `CurveError::Overflow` if `delta_x * y` or `x + delta_x`
+    // overflows, and `CurveError::EmptyPool` if `x + delta_x` is zero.
+    pub const fn try_swap_base_input_without_fees(
         source_amount: u128,
         swap_source_amount: u128,
         swap_destination_amount: u128,
-    ) -> u128 {
+    ) -> Result<u128, CurveError> {
+        Self::try_swap_base_input_without_fees_rounded(
+            source_amount,
+            swap_source_amount,
+            swap_destination_amount,
+            RoundDirection::Floor,
+        )
+    }
+
+    // Same as `try_swap_base_input_without_fees`, with an explicit rounding
+    // direction for the destination amount.
+    //
+    // `RoundDirection::Ceiling` favors the trader and can decrease the
+    // invariant; it is meant for simulators and alternative curves, not for
+    // settling trades against a pool.
+    //
+    // Spelled out rather than calling `checked_swap_base_input_without_fees`
+    // so it stays `const`.
+    pub const fn try_swap_base_input_without_fees_rounded(
+        source_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        round_direction: RoundDirection,
+    ) -> Result<u128, CurveError> {
         // (delta_x * y)
-        let numerator = source_amount.checked_mul(swap_destination_amount).unwrap();
+        let Some(numerator) = source_amount.checked_mul(swap_destination_amount) else {
+            return Err(CurveError::Overflow);
+        };
 
         // (x + delta_x)
-        let denominator = swap_source_amount.checked_add(source_amount).unwrap();
+        let Some(denominator) = swap_source_amount.checked_add(source_amount) else {
+            return Err(CurveError::Overflow);
+        };
+        if denominator == 0 {
+            return Err(CurveError::EmptyPool);
+        }
 
         // (delta_x * y) / (x + delta_x)
-        let destination_amount_swapped = numerator.checked_div(denominator).unwrap();
-        destination_amount_swapped
+        Ok(match round_direction {
+            RoundDirection::Floor => numerator / denominator,
+            RoundDirection::Ceiling => numerator.div_ceil(denominator),
+        })
+    }
+
+    /// Panicking form of `try_swap_base_input_without_fees`.
+    ///
+    /// # Panics
+    /// Where `try_swap_base_input_without_fees` fails.
+    #[cfg(feature = "legacy-panicking")]
+    #[deprecated(
+        note = "use `try_swap_base_input_without_fees`, which returns an error instead of \
+                panicking; `.unwrap()` its result to keep the old behavior"
+    )]
+    pub const fn swap_base_input_without_fees(
+        source_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+    ) -> u128 {
+        match Self::try_swap_base_input_without_fees(
+            source_amount,
+            swap_source_amount,
+            swap_destination_amount,
+        ) {
+            Ok(destination_amount) => destination_amount,
+            Err(_) => panic!("swap_base_input_without_fees failed"),
+        }
+    }
+
+    /// Panicking form of `try_swap_base_input_without_fees_rounded`.
+    ///
+    /// # Panics
+    /// Where `try_swap_base_input_without_fees_rounded` fails.
+    #[cfg(feature = "legacy-panicking")]
+    #[deprecated(
+        note = "use `try_swap_base_input_without_fees_rounded`, which returns an error \
+                instead of panicking; `.unwrap()` its result to keep the old behavior"
+    )]
+    pub const fn swap_base_input_without_fees_rounded(
+        source_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        round_direction: RoundDirection,
+    ) -> u128 {
+        match Self::try_swap_base_input_without_fees_rounded(
+            source_amount,
+            swap_source_amount,
+            swap_destination_amount,
+            round_direction,
+        ) {
+            Ok(destination_amount) => destination_amount,
+            Err(_) => panic!("swap_base_input_without_fees_rounded failed"),
+        }
     }
 
     // Calculates the amount of source tokens required to receive a given amount of destination tokens,
@@ -68,21 +153,166 @@ impl ConstantProductCurve {
     // * `swap_destination_amount` - The amount of destination tokens in the pool.
     //
     // # Returns
-    // The amount of source tokens required for the swap.
+    // The amount of source tokens required for the swap, rounded up.
+    //
+    // Fails with `CurveError::InsufficientLiquidity` if `delta_y` is not below
+    // `y`, and `CurveError::Overflow` if `x * delta_y` overflows.
+    pub fn try_swap_base_output_without_fees(
+        destination_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+    ) -> Result<u128, CurveError> {
+        Self::try_swap_base_output_without_fees_rounded(
+            destination_amount,
+            swap_source_amount,
+            swap_destination_amount,
+            RoundDirection::Ceiling,
+        )
+    }
+
+    // Same as `try_swap_base_output_without_fees`, with an explicit rounding
+    // direction for the source amount.
+    //
+    // `RoundDirection::Floor` favors the trader and can decrease the
+    // invariant; it is meant for simulators and alternative curves, not for
+    // settling trades against a pool.
+    pub fn try_swap_base_output_without_fees_rounded(
+        destination_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        round_direction: RoundDirection,
+    ) -> Result<u128, CurveError> {
+        if destination_amount >= swap_destination_amount {
+            return Err(CurveError::InsufficientLiquidity);
+        }
+        Self::checked_swap_base_output_without_fees(
+            destination_amount,
+            swap_source_amount,
+            swap_destination_amount,
+            round_direction,
+        )
+        .ok_or(CurveError::Overflow)
+    }
+
+    /// Panicking form of `try_swap_base_output_without_fees`.
+    ///
+    /// # Panics
+    /// Where `try_swap_base_output_without_fees` fails.
+    #[cfg(feature = "legacy-panicking")]
+    #[deprecated(
+        note = "use `try_swap_base_output_without_fees`, which returns an error instead \
+                of panicking; `.unwrap()` its result to keep the old behavior"
+    )]
     pub fn swap_base_output_without_fees(
         destination_amount: u128,
         swap_source_amount: u128,
         swap_destination_amount: u128,
     ) -> u128 {
+        Self::try_swap_base_output_without_fees(
+            destination_amount,
+            swap_source_amount,
+            swap_destination_amount,
+        )
+        .unwrap()
+    }
+
+    /// Panicking form of `try_swap_base_output_without_fees_rounded`.
+    ///
+    /// # Panics
+    /// Where `try_swap_base_output_without_fees_rounded` fails.
+    #[cfg(feature = "legacy-panicking")]
+    #[deprecated(
+        note = "use `try_swap_base_output_without_fees_rounded`, which returns an error \
+                instead of panicking; `.unwrap()` its result to keep the old behavior"
+    )]
+    pub fn swap_base_output_without_fees_rounded(
+        destination_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        round_direction: RoundDirection,
+    ) -> u128 {
+        Self::try_swap_base_output_without_fees_rounded(
+            destination_amount,
+            swap_source_amount,
+            swap_destination_amount,
+            round_direction,
+        )
+        .unwrap()
+    }
+
+    /// `try_swap_base_input_without_fees_rounded` over any `MathUint`.
+    ///
+    /// Returns `None` if `source_amount * swap_destination_amount` or
+    /// `swap_source_amount + source_amount` overflows `T`, or both are zero.
+    pub fn checked_swap_base_input_without_fees<T: MathUint>(
+        source_amount: T,
+        swap_source_amount: T,
+        swap_destination_amount: T,
+        round_direction: RoundDirection,
+    ) -> Option<T> {
+        // (delta_x * y)
+        let numerator = source_amount.checked_mul(swap_destination_amount)?;
+
+        // (x + delta_x)
+        let denominator = swap_source_amount.checked_add(source_amount)?;
+
+        // (delta_x * y) / (x + delta_x)
+        match round_direction {
+            RoundDirection::Floor => numerator.checked_div(denominator),
+            RoundDirection::Ceiling => numerator.checked_div_ceil(denominator),
+        }
+    }
+
+    /// `try_swap_base_output_without_fees_rounded` over any `MathUint`.
+    ///
+    /// Returns `None` if `swap_source_amount * destination_amount` overflows
+    /// `T`, or `destination_amount` is not below `swap_destination_amount`.
+    pub fn checked_swap_base_output_without_fees<T: MathUint>(
+        destination_amount: T,
+        swap_source_amount: T,
+        swap_destination_amount: T,
+        round_direction: RoundDirection,
+    ) -> Option<T> {
         // (x * delta_y)
-        let numerator = swap_source_amount.checked_mul(destination_amount).unwrap();
+        let numerator = swap_source_amount.checked_mul(destination_amount)?;
 
         // (y - delta_y)
-        let denominator = swap_destination_amount.checked_sub(destination_amount).unwrap();
+        let denominator = swap_destination_amount.checked_sub(destination_amount)?;
 
         // (x * delta_y) / (y - delta_y)
-        let (source_amount_swapped, _) = numerator.checked_ceil_div(denominator).unwrap();
-        source_amount_swapped
+        match round_direction {
+            RoundDirection::Floor => numerator.checked_div(denominator),
+            // `checked_ceil_div` rounds quotients below 1/2 down to zero,
+            // which would let tiny outputs be bought for nothing
+            RoundDirection::Ceiling => numerator.checked_div_ceil(denominator),
+        }
+    }
+
+    /// The amount of one trading token for `lp_token_amount` pool tokens
+    /// over any `MathUint`, and its rounding dust in units of
+    /// `1 / lp_token_supply` of a token.
+    ///
+    /// Rounding up only applies to nonzero amounts, as in
+    /// `lp_tokens_to_trading_tokens`. Returns `None` if
+    /// `lp_token_amount * swap_token_amount` overflows `T` or the supply is
+    /// zero.
+    pub fn checked_lp_tokens_to_trading_token<T: MathUint>(
+        lp_token_amount: T,
+        lp_token_supply: T,
+        swap_token_amount: T,
+        round_direction: RoundDirection,
+    ) -> Option<(T, T)> {
+        let product = lp_token_amount.checked_mul(swap_token_amount)?;
+        let amount = product.checked_div(lp_token_supply)?;
+        let remainder = product.checked_rem(lp_token_supply)?;
+        match round_direction {
+            RoundDirection::Floor => Some((amount, remainder)),
+            RoundDirection::Ceiling if remainder > T::ZERO && amount > T::ZERO => Some((
+                amount.checked_add(T::ONE)?,
+                lp_token_supply.checked_sub(remainder)?,
+            )),
+            RoundDirection::Ceiling => Some((amount, T::ZERO)),
+        }
     }
 
     /// Get the amount of trading tokens for the given amount of pool tokens,
@@ -97,38 +327,38 @@ impl ConstantProductCurve {
         swap_token_1_amount: u128,
         round_direction: RoundDirection,
     ) -> Option<TradingTokenResult> {
-        let mut token_0_amount = lp_token_amount
-            .checked_mul(swap_token_0_amount)?
-            .checked_div(lp_token_supply)?;
-        let mut token_1_amount = lp_token_amount
-            .checked_mul(swap_token_1_amount)?
-            .checked_div(lp_token_supply)?;
-        let (token_0_amount, token_1_amount) = match round_direction {
-            RoundDirection::Floor => (token_0_amount, token_1_amount),
-            RoundDirection::Ceiling => {
-                let token_0_remainder = lp_token_amount
-                    .checked_mul(swap_token_0_amount)?
-                    .checked_rem(lp_token_supply)?;
-                // Also check for 0 token A and B amount to avoid taking too much
-                // for tiny amounts of pool tokens.  For example, if someone asks
-                // for 1 pool token, which is worth 0.01 token A, we avoid the
-                // ceiling of taking 1 token A and instead return 0, for it to be
-                // rejected later in processing.
-                if token_0_remainder > 0 && token_0_amount > 0 {
-                    token_0_amount += 1;
-                }
-                let token_1_remainder = lp_token_amount
-                    .checked_mul(swap_token_1_amount)?
-                    .checked_rem(lp_token_supply)?;
-                if token_1_remainder > 0 && token_1_amount > 0 {
-                    token_1_amount += 1;
-                }
-                (token_0_amount, token_1_amount)
-            }
-        };
+        // burning the whole supply takes the reserves exactly, even where
+        // `lp_token_amount * reserve` would overflow
+        if lp_token_amount == lp_token_supply && lp_token_supply != 0 {
+            return Some(TradingTokenResult {
+                token_0_amount: swap_token_0_amount,
+                token_1_amount: swap_token_1_amount,
+                token_0_rounding_dust: 0,
+                token_1_rounding_dust: 0,
+            });
+        }
+        // Also check for 0 token A and B amount to avoid taking too much
+        // for tiny amounts of pool tokens.  For example, if someone asks
+        // for 1 pool token, which is worth 0.01 token A, we avoid the
+        // ceiling of taking 1 token A and instead return 0, for it to be
+        // rejected later in processing.
+        let (token_0_amount, token_0_rounding_dust) = Self::checked_lp_tokens_to_trading_token(
+            lp_token_amount,
+            lp_token_supply,
+            swap_token_0_amount,
+            round_direction,
+        )?;
+        let (token_1_amount, token_1_rounding_dust) = Self::checked_lp_tokens_to_trading_token(
+            lp_token_amount,
+            lp_token_supply,
+            swap_token_1_amount,
+            round_direction,
+        )?;
         Some(TradingTokenResult {
             token_0_amount,
             token_1_amount,
+            token_0_rounding_dust,
+            token_1_rounding_dust,
         })
     }
 
@@ -139,13 +369,19 @@ impl ConstantProductCurve {
 mod tests {
     use {
         super::*,
-        crate::curve::calculator::{
-            test::{
-                check_curve_value_from_swap, check_pool_value_from_deposit,
-                check_pool_value_from_withdraw, total_and_intermediate,
+        crate::curve::{
+            amount::{Reserves, Token0Amount, Token1Amount},
+            calculator::{CurveCalculator, RoundDirection, TradeDirection},
+            fees::{FeeConfig, FeeRate, ProtocolFeeMode, TradeFeeSide},
+            swap_params::SwapParams,
+            test_utils::{
+                check_curve_value_from_swap, check_curve_value_from_swap_exact_out,
+                check_exact_out_then_exact_in, check_pool_value_from_deposit,
+                check_pool_value_from_withdraw, check_round_trip_no_profit,
+                total_and_intermediate,
             },
-            RoundDirection, TradeDirection,
         },
+        crate::utils::{U256, price::to_u128},
         proptest::prelude::*,
     };
     fn check_pool_token_rate(
@@ -175,6 +411,240 @@ mod tests {
         check_pool_token_rate(5, 501, 2, 10, 1, 101);
     }
 
+    #[test]
+    fn trading_token_rounding_dust() {
+        // 5 * 49 / 10 = 24.5, so half a token B is at stake either way
+        let floor =
+            ConstantProductCurve::lp_tokens_to_trading_tokens(5, 10, 2, 49, RoundDirection::Floor)
+                .unwrap();
+        assert_eq!(floor.token_0_rounding_dust, 0);
+        assert_eq!(floor.token_1_rounding_dust, 5);
+        let ceiling =
+            ConstantProductCurve::lp_tokens_to_trading_tokens(2, 10, 5, 501, RoundDirection::Ceiling)
+                .unwrap();
+        // 2 * 5 / 10 = 1 exactly, 2 * 501 / 10 = 100.2 rounded up to 101
+        assert_eq!(ceiling.token_0_rounding_dust, 0);
+        assert_eq!(ceiling.token_1_rounding_dust, 8);
+    }
+
+    #[test]
+    fn burning_the_whole_supply_takes_the_reserves() {
+        for round_direction in [RoundDirection::Floor, RoundDirection::Ceiling] {
+            let results = ConstantProductCurve::lp_tokens_to_trading_tokens(
+                u128::MAX,
+                u128::MAX,
+                u128::MAX,
+                7,
+                round_direction,
+            )
+            .unwrap();
+            assert_eq!(results.token_0_amount, u128::MAX);
+            assert_eq!(results.token_1_amount, 7);
+            assert_eq!(results.token_0_rounding_dust, 0);
+            assert_eq!(results.token_1_rounding_dust, 0);
+        }
+    }
+
+    #[test]
+    fn swap_rounding_dust() {
+        // (source_amount, swap_source_amount, swap_destination_amount, dust)
+        let tests: &[(u128, u128, u128, u128)] = &[
+            // 6 destination tokens only need 18 source tokens
+            (20, 30_000 - 20, 10_000, 2),
+            (19, 30_000 - 20, 10_000, 1),
+            (18, 30_000 - 20, 10_000, 0),
+            // 49 destination tokens only need 99 source tokens
+            (100, 60_000, 30_000, 1),
+            (99, 60_000, 30_000, 0),
+        ];
+        for (source_amount, swap_source_amount, swap_destination_amount, dust) in tests.iter() {
+            let result = CurveCalculator::swap_base_input(
+                &SwapParams::from_raw_parts(
+                    *source_amount,
+                    *swap_source_amount,
+                    *swap_destination_amount,
+                    0,
+                    0,
+                )
+                .unwrap(),
+            )
+            .unwrap();
+            assert_eq!(result.rounding_dust, *dust);
+        }
+        // exact-out charges the ceiling, which is already the minimum
+        let result = CurveCalculator::swap_base_output(
+            &SwapParams::from_raw_parts(6, 30_000 - 20, 10_000, 0, 0).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(result.source_amount_swapped, 18);
+        assert_eq!(result.rounding_dust, 0);
+    }
+
+    // Evaluated by the compiler
+    const SWAP_VECTOR: Result<u128, CurveError> =
+        ConstantProductCurve::try_swap_base_input_without_fees(10, 20_000, 30_000);
+
+    #[test]
+    fn swap_rounding_direction() {
+        assert_eq!(SWAP_VECTOR, Ok(14));
+        // 10 * 30_000 / 20_010 = 14.99
+        assert_eq!(
+            ConstantProductCurve::try_swap_base_input_without_fees_rounded(
+                10,
+                20_000,
+                30_000,
+                RoundDirection::Floor
+            ),
+            Ok(14)
+        );
+        assert_eq!(
+            ConstantProductCurve::try_swap_base_input_without_fees_rounded(
+                10,
+                20_000,
+                30_000,
+                RoundDirection::Ceiling
+            ),
+            Ok(15)
+        );
+        // 10 * 30_000 / 20_000 = 15 exactly
+        assert_eq!(
+            ConstantProductCurve::try_swap_base_input_without_fees_rounded(
+                10,
+                20_000 - 10,
+                30_000,
+                RoundDirection::Ceiling
+            ),
+            Ok(15)
+        );
+        // 20_000 * 14 / 29_986 = 9.34
+        assert_eq!(
+            ConstantProductCurve::try_swap_base_output_without_fees_rounded(
+                14,
+                20_000,
+                30_000,
+                RoundDirection::Floor
+            ),
+            Ok(9)
+        );
+        assert_eq!(
+            ConstantProductCurve::try_swap_base_output_without_fees_rounded(
+                14,
+                20_000,
+                30_000,
+                RoundDirection::Ceiling
+            ),
+            ConstantProductCurve::try_swap_base_output_without_fees(14, 20_000, 30_000)
+        );
+        // 10 * 2^36 / (y - 10) is far below 1/2, and must still cost 1
+        let swap_destination_amount = 327_121_104_131_016_143_941_120;
+        assert_eq!(
+            ConstantProductCurve::try_swap_base_output_without_fees(
+                10,
+                1 << 36,
+                swap_destination_amount
+            ),
+            Ok(1)
+        );
+    }
+
+    #[test]
+    fn swaps_without_fees_report_failures() {
+        assert_eq!(
+            ConstantProductCurve::try_swap_base_input_without_fees(u128::MAX, 1, 2),
+            Err(CurveError::Overflow)
+        );
+        assert_eq!(
+            ConstantProductCurve::try_swap_base_input_without_fees(1, u128::MAX, 1),
+            Err(CurveError::Overflow)
+        );
+        assert_eq!(
+            ConstantProductCurve::try_swap_base_input_without_fees(0, 0, 1_000),
+            Err(CurveError::EmptyPool)
+        );
+        assert_eq!(
+            ConstantProductCurve::try_swap_base_output_without_fees(1_000, 1_000, 1_000),
+            Err(CurveError::InsufficientLiquidity)
+        );
+        assert_eq!(
+            ConstantProductCurve::try_swap_base_output_without_fees(2, u128::MAX, 1_000),
+            Err(CurveError::Overflow)
+        );
+    }
+
+    #[cfg(feature = "legacy-panicking")]
+    #[test]
+    #[allow(deprecated)]
+    fn legacy_swaps_unwrap_the_fallible_ones() {
+        assert_eq!(
+            ConstantProductCurve::swap_base_input_without_fees(10, 20_000, 30_000),
+            14
+        );
+        assert_eq!(
+            ConstantProductCurve::swap_base_input_without_fees_rounded(
+                10,
+                20_000,
+                30_000,
+                RoundDirection::Ceiling
+            ),
+            15
+        );
+        assert_eq!(
+            ConstantProductCurve::swap_base_output_without_fees(14, 20_000, 30_000),
+            10
+        );
+        assert_eq!(
+            ConstantProductCurve::swap_base_output_without_fees_rounded(
+                14,
+                20_000,
+                30_000,
+                RoundDirection::Floor
+            ),
+            9
+        );
+    }
+
+    #[cfg(feature = "legacy-panicking")]
+    #[test]
+    #[allow(deprecated)]
+    #[should_panic]
+    fn legacy_swaps_panic_on_failure() {
+        ConstantProductCurve::swap_base_output_without_fees(1_000, 1_000, 1_000);
+    }
+
+    #[test]
+    fn wide_backend_avoids_intermediate_overflow() {
+        // 2^100 * 2^100 overflows u128 before the division brings it back
+        let amount = 1u128 << 100;
+        assert_eq!(
+            ConstantProductCurve::checked_swap_base_input_without_fees(
+                amount,
+                amount,
+                amount,
+                RoundDirection::Floor
+            ),
+            None
+        );
+        assert_eq!(
+            ConstantProductCurve::checked_swap_base_input_without_fees(
+                U256::from(amount),
+                U256::from(amount),
+                U256::from(amount),
+                RoundDirection::Floor
+            ),
+            Some(U256::from(amount / 2))
+        );
+        // and the narrow backend overflows first
+        assert_eq!(
+            ConstantProductCurve::checked_swap_base_input_without_fees(
+                1u64 << 40,
+                1u64 << 40,
+                1u64 << 40,
+                RoundDirection::Floor
+            ),
+            None
+        );
+    }
+
     #[test]
     fn fail_trading_token_conversion() {
         let results = ConstantProductCurve::lp_tokens_to_trading_tokens(
@@ -223,11 +693,12 @@ mod tests {
         let invariant = swap_source_amount * swap_destination_amount;
 
         // Perform the swap calculation to determine the destination amount received.
-        let destination_amount_swapped = ConstantProductCurve::swap_base_input_without_fees(
+        let destination_amount_swapped = ConstantProductCurve::try_swap_base_input_without_fees(
             source_amount,
             swap_source_amount,
             swap_destination_amount,
-        );
+        )
+        .unwrap();
 
         // Assert that the source amount swapped matches the expected value.
         assert_eq!(source_amount, expected_source_amount_swapped);
@@ -390,4 +861,219 @@ mod tests {
         }
     }
 
+    // small amounts exercise the rounding, large ones the overflow bounds
+    fn amounts() -> impl Strategy<Value = u128> {
+        prop_oneof![1..1_000u128, 1..u64::MAX as u128]
+    }
+
+    fn fee_configs() -> impl Strategy<Value = FeeConfig> {
+        (
+            0..=100_000u64,
+            0..=1_000_000u64,
+            any::<bool>(),
+            any::<bool>(),
+        )
+            .prop_map(|(trade_fee_rate, protocol_fee_rate, split, on_output)| {
+                FeeConfig::new(
+                    FeeRate::checked(trade_fee_rate).unwrap(),
+                    FeeRate::checked(protocol_fee_rate).unwrap(),
+                )
+                .with_protocol_fee_mode(if split {
+                    ProtocolFeeMode::Split
+                } else {
+                    ProtocolFeeMode::Input
+                })
+                .with_trade_fee_side(if on_output {
+                    TradeFeeSide::Output
+                } else {
+                    TradeFeeSide::Input
+                })
+            })
+    }
+
+    #[test]
+    fn exact_out_quotes_the_cheapest_input() {
+        // one unit of token 0 buys 500 of token 1, so the cheapest input
+        // for a single unit overshoots it
+        let reserves = Reserves::new(Token0Amount(1), Token1Amount(1_000));
+        let quote = CurveCalculator::swap_exact_out(
+            TradeDirection::ZeroForOne,
+            1,
+            reserves,
+            FeeConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(quote.source_amount_swapped, 1);
+        let fee_config = FeeConfig::default();
+        check_exact_out_then_exact_in(1, reserves, TradeDirection::ZeroForOne, fee_config);
+        check_round_trip_no_profit(1, reserves, TradeDirection::ZeroForOne, fee_config);
+    }
+
+    proptest! {
+        #[test]
+        fn exact_out_then_exact_in_is_consistent(
+            amount in amounts(),
+            token_0 in amounts(),
+            token_1 in amounts(),
+            zero_for_one: bool,
+            fee_config in fee_configs(),
+        ) {
+            let trade_direction = if zero_for_one {
+                TradeDirection::ZeroForOne
+            } else {
+                TradeDirection::OneForZero
+            };
+            let reserves = Reserves::new(Token0Amount(token_0), Token1Amount(token_1));
+            check_exact_out_then_exact_in(amount, reserves, trade_direction, fee_config);
+        }
+
+        #[test]
+        fn curve_value_does_not_decrease_from_swap_exact_out(
+            amount in amounts(),
+            token_0 in amounts(),
+            token_1 in amounts(),
+            zero_for_one: bool,
+            fee_config in prop_oneof![Just(FeeConfig::default()), fee_configs()],
+        ) {
+            let trade_direction = if zero_for_one {
+                TradeDirection::ZeroForOne
+            } else {
+                TradeDirection::OneForZero
+            };
+            let reserves = Reserves::new(Token0Amount(token_0), Token1Amount(token_1));
+            // most amounts are past the destination reserve, so also try
+            // ones below it
+            let (_, swap_destination_amount) = reserves.source_and_destination(trade_direction);
+            check_curve_value_from_swap_exact_out(
+                amount % swap_destination_amount,
+                reserves,
+                trade_direction,
+                fee_config,
+            );
+            check_curve_value_from_swap_exact_out(amount, reserves, trade_direction, fee_config);
+        }
+
+        #[test]
+        fn round_trips_never_profit(
+            amount in amounts(),
+            token_0 in amounts(),
+            token_1 in amounts(),
+            zero_for_one: bool,
+            fee_config in fee_configs(),
+        ) {
+            let trade_direction = if zero_for_one {
+                TradeDirection::ZeroForOne
+            } else {
+                TradeDirection::OneForZero
+            };
+            let reserves = Reserves::new(Token0Amount(token_0), Token1Amount(token_1));
+            check_round_trip_no_profit(amount, reserves, trade_direction, fee_config);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn generic_formulas_agree_across_widths(
+            amount: u64,
+            swap_source_amount: u64,
+            swap_destination_amount: u64,
+            ceiling: bool,
+        ) {
+            let round_direction = if ceiling {
+                RoundDirection::Ceiling
+            } else {
+                RoundDirection::Floor
+            };
+            let wide = |amount: u64| U256::from(amount);
+            let narrow = |amount: U256| to_u128(amount).unwrap();
+
+            let exact_in_64 = ConstantProductCurve::checked_swap_base_input_without_fees(
+                amount,
+                swap_source_amount,
+                swap_destination_amount,
+                round_direction,
+            );
+            let exact_in_128 = ConstantProductCurve::checked_swap_base_input_without_fees(
+                u128::from(amount),
+                u128::from(swap_source_amount),
+                u128::from(swap_destination_amount),
+                round_direction,
+            );
+            let exact_in_256 = ConstantProductCurve::checked_swap_base_input_without_fees(
+                wide(amount),
+                wide(swap_source_amount),
+                wide(swap_destination_amount),
+                round_direction,
+            );
+            // u64 only fails where the product overflows it
+            if let Some(exact_in_64) = exact_in_64 {
+                prop_assert_eq!(Some(u128::from(exact_in_64)), exact_in_128);
+            }
+            prop_assert_eq!(exact_in_128, exact_in_256.map(narrow));
+            if exact_in_128.is_some() {
+                prop_assert_eq!(
+                    exact_in_128,
+                    ConstantProductCurve::try_swap_base_input_without_fees_rounded(
+                        u128::from(amount),
+                        u128::from(swap_source_amount),
+                        u128::from(swap_destination_amount),
+                        round_direction,
+                    )
+                    .ok()
+                );
+            }
+
+            let exact_out_64 = ConstantProductCurve::checked_swap_base_output_without_fees(
+                amount,
+                swap_source_amount,
+                swap_destination_amount,
+                round_direction,
+            );
+            let exact_out_256 = ConstantProductCurve::checked_swap_base_output_without_fees(
+                wide(amount),
+                wide(swap_source_amount),
+                wide(swap_destination_amount),
+                round_direction,
+            );
+            if let Some(exact_out_64) = exact_out_64 {
+                prop_assert_eq!(Some(u128::from(exact_out_64)), exact_out_256.map(narrow));
+            }
+            prop_assert_eq!(
+                ConstantProductCurve::checked_swap_base_output_without_fees(
+                    u128::from(amount),
+                    u128::from(swap_source_amount),
+                    u128::from(swap_destination_amount),
+                    round_direction,
+                ),
+                exact_out_256.map(narrow)
+            );
+
+            // LP conversions of a supply of `swap_source_amount`
+            let lp_64 = ConstantProductCurve::checked_lp_tokens_to_trading_token(
+                amount,
+                swap_source_amount,
+                swap_destination_amount,
+                round_direction,
+            );
+            let lp_256 = ConstantProductCurve::checked_lp_tokens_to_trading_token(
+                wide(amount),
+                wide(swap_source_amount),
+                wide(swap_destination_amount),
+                round_direction,
+            )
+            .map(|(amount, dust)| (narrow(amount), narrow(dust)));
+            if let Some((amount, dust)) = lp_64 {
+                prop_assert_eq!(Some((u128::from(amount), u128::from(dust))), lp_256);
+            }
+            prop_assert_eq!(
+                ConstantProductCurve::checked_lp_tokens_to_trading_token(
+                    u128::from(amount),
+                    u128::from(swap_source_amount),
+                    u128::from(swap_destination_amount),
+                    round_direction,
+                ),
+                lp_256
+            );
+        }
+    }
 }
@@ -0,0 +1,258 @@
+//! Error context
+//!
+//! A bare `CurveError::Overflow` does not say which calculation failed, nor
+//! on what values. `ErrorCtx` records the operation, the hop of a route it
+//! was quoted for and the arithmetic step that failed with its operands.
+//! `ContextError` pairs it with the error, so a failed route quote reads
+//! `hop 2 exact-out sub: requested 10000 > reserve 9500: the pool cannot pay
+//! out the requested amount`.
+//!
+//! The context is a few words of `Copy` data, built only on the error path.
+use {crate::curve::calculator::CurveError, core::fmt};
+
+/// The calculation that failed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operation {
+    /// A swap of an exact input amount
+    ExactIn,
+    /// A swap for an exact output amount
+    ExactOut,
+    /// A conversion between LP tokens and trading tokens
+    LpConversion,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Operation::ExactIn => "exact-in",
+            Operation::ExactOut => "exact-out",
+            Operation::LpConversion => "LP conversion",
+        })
+    }
+}
+
+/// An arithmetic operation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Step::Add => "add",
+            Step::Sub => "sub",
+            Step::Mul => "mul",
+            Step::Div => "div",
+        })
+    }
+}
+
+/// A named operand of a failed step
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Operand {
+    /// What the value is, as in `"reserve"`
+    pub name: &'static str,
+    pub value: u128,
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.name, self.value)
+    }
+}
+
+/// The arithmetic step a calculation failed at, as `lhs step rhs`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FailedStep {
+    pub step: Step,
+    pub lhs: Operand,
+    pub rhs: Operand,
+}
+
+impl fmt::Display for FailedStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (lhs, rhs) = (self.lhs, self.rhs);
+        match self.step {
+            // a subtraction fails because the right side is the larger
+            Step::Sub => write!(f, "{rhs} > {lhs}"),
+            Step::Add => write!(f, "{lhs} + {rhs}"),
+            Step::Mul => write!(f, "{lhs} * {rhs}"),
+            Step::Div => write!(f, "{lhs} / {rhs}"),
+        }
+    }
+}
+
+/// Where a calculation failed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ErrorCtx {
+    operation: Operation,
+    hop: Option<u32>,
+    // `FailedStep` split up, so that `ContextError` stays small
+    step: Option<Step>,
+    operands: [Operand; 2],
+}
+
+impl ErrorCtx {
+    pub const fn new(operation: Operation) -> Self {
+        const NONE: Operand = Operand { name: "", value: 0 };
+        Self {
+            operation,
+            hop: None,
+            step: None,
+            operands: [NONE; 2],
+        }
+    }
+
+    /// The same context, for hop `hop` of a route
+    pub const fn at_hop(self, hop: u32) -> Self {
+        Self {
+            hop: Some(hop),
+            ..self
+        }
+    }
+
+    /// The same context, failed at `lhs step rhs`
+    pub const fn failed_at(
+        self,
+        step: Step,
+        lhs: (&'static str, u128),
+        rhs: (&'static str, u128),
+    ) -> Self {
+        Self {
+            step: Some(step),
+            operands: [
+                Operand {
+                    name: lhs.0,
+                    value: lhs.1,
+                },
+                Operand {
+                    name: rhs.0,
+                    value: rhs.1,
+                },
+            ],
+            ..self
+        }
+    }
+
+    /// The calculation that failed
+    pub const fn operation(&self) -> Operation {
+        self.operation
+    }
+
+    /// Position of the pool in a route, counting from 1
+    pub const fn hop(&self) -> Option<u32> {
+        self.hop
+    }
+
+    /// The arithmetic step that failed, if the error comes from one
+    pub const fn failed_step(&self) -> Option<FailedStep> {
+        match self.step {
+            Some(step) => Some(FailedStep {
+                step,
+                lhs: self.operands[0],
+                rhs: self.operands[1],
+            }),
+            None => None,
+        }
+    }
+
+    /// `error`, with this context
+    pub const fn error(self, error: CurveError) -> ContextError {
+        ContextError { error, ctx: self }
+    }
+}
+
+/// A `CurveError` with where it occurred
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ContextError {
+    pub error: CurveError,
+    pub ctx: ErrorCtx,
+}
+
+impl ContextError {
+    /// The same error, for hop `hop` of a route
+    pub const fn at_hop(self, hop: u32) -> Self {
+        Self {
+            ctx: self.ctx.at_hop(hop),
+            ..self
+        }
+    }
+}
+
+impl From<ContextError> for CurveError {
+    fn from(error: ContextError) -> Self {
+        error.error
+    }
+}
+
+impl fmt::Display for ContextError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(hop) = self.ctx.hop {
+            write!(f, "hop {hop} ")?;
+        }
+        write!(f, "{}", self.ctx.operation)?;
+        if let Some(failed_step) = self.ctx.failed_step() {
+            write!(f, " {}: {failed_step}", failed_step.step)?;
+        }
+        write!(f, ": {}", self.error)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ContextError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Attaches an `ErrorCtx` to a failed calculation
+pub trait WithContext<T> {
+    fn context(self, ctx: ErrorCtx) -> Result<T, ContextError>;
+}
+
+impl<T> WithContext<T> for Result<T, CurveError> {
+    fn context(self, ctx: ErrorCtx) -> Result<T, ContextError> {
+        self.map_err(|error| ctx.error(error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn context_reads_as_a_sentence() {
+        let error = ErrorCtx::new(Operation::ExactOut)
+            .failed_at(Step::Sub, ("reserve", 9_500), ("requested", 10_000))
+            .error(CurveError::InsufficientLiquidity)
+            .at_hop(2);
+        assert_eq!(
+            error.to_string(),
+            "hop 2 exact-out sub: requested 10000 > reserve 9500: \
+             the pool cannot pay out the requested amount"
+        );
+        assert_eq!(CurveError::from(error), CurveError::InsufficientLiquidity);
+
+        let error = ErrorCtx::new(Operation::ExactIn)
+            .failed_at(Step::Add, ("source reserve", u128::MAX), ("amount in", 1))
+            .error(CurveError::Overflow);
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "exact-in add: source reserve {} + amount in 1: a value overflows",
+                u128::MAX
+            )
+        );
+        assert_eq!(
+            Err::<(), _>(CurveError::ZeroSupply)
+                .context(ErrorCtx::new(Operation::LpConversion))
+                .unwrap_err()
+                .to_string(),
+            "LP conversion: the LP supply is zero"
+        );
+    }
+}
@@ -0,0 +1,464 @@
+//! Repegging curve for volatile pairs
+//!
+//! Like Curve v2, liquidity is concentrated around an internal price scale
+//! rather than around 1:1. Token 1 is valued at `price_scale` token 0, and
+//! the StableSwap invariant is applied to the scaled balances, so trades
+//! near the price scale see little slippage. An exponential moving average
+//! of trade prices tracks the market, and once the pool has earned enough
+//! in fees the price scale is moved toward the average, spending part of
+//! the profit on the loss the move realizes.
+//!
+//! Profit is measured by the virtual price: the geometric mean of the
+//! balances the invariant would hold at the price scale, relative to its
+//! value when the pool was created.
+use crate::{
+    curve::{
+        amount::{Reserves, Token0Amount, Token1Amount},
+        calculator::{RoundDirection, SwapResult, TradeDirection},
+        fees::{FEE_RATE_DENOMINATOR_VALUE, FeeRate, Fees},
+        stable::{StableSwapCurve, is_valid_amp},
+    },
+    utils::{
+        IntegerSquareRoot, PriceQ64, U256,
+        fixed_math::pow,
+        price::{Q64, Q64_RESOLUTION, to_u128},
+    },
+};
+
+/// Parameters of a repegging pool. Fractions are Q64.64.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CryptoSwapParams {
+    /// Amplification around the price scale, scaled by `AMP_PRECISION`
+    pub amp: u64,
+    /// Fee charged when the scaled balances are equal
+    pub mid_fee: FeeRate,
+    /// Fee approached as the scaled balances diverge
+    pub out_fee: FeeRate,
+    /// How quickly the fee moves from `mid_fee` to `out_fee`; smaller
+    /// values move it sooner
+    pub fee_gamma: PriceQ64,
+    /// Share of the trade fee going to the protocol
+    pub protocol_fee_rate: FeeRate,
+    /// Virtual price growth beyond half the profit that must be kept before
+    /// the price scale moves
+    pub allowed_extra_profit: PriceQ64,
+    /// Smallest relative move of the price scale
+    pub adjustment_step: PriceQ64,
+    /// Seconds for a price's weight in the moving average to halve
+    pub ma_half_time: u64,
+}
+
+/// A two-token repegging pool
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CryptoSwapPool {
+    pub params: CryptoSwapParams,
+    /// Tokens held for liquidity providers
+    pub balances: Reserves,
+    /// Fees owed to the protocol
+    pub protocol_fees: Reserves,
+    /// Price of token 1 in token 0 the liquidity is concentrated around
+    pub price_scale: PriceQ64,
+    /// Moving average of `last_price`
+    pub price_oracle: PriceQ64,
+    /// Price of token 1 in token 0 of the last trade
+    pub last_price: PriceQ64,
+    /// Timestamp of the last trade
+    pub last_timestamp: u64,
+    /// Invariant of the scaled balances
+    pub d: u128,
+    /// Geometric mean of the balances when the pool was created, the unit
+    /// of `virtual_price`
+    pub xcp_base: u128,
+    /// Geometric mean of the balances at the price scale over `xcp_base`
+    pub virtual_price: PriceQ64,
+    /// Product of the virtual price growth of every trade, ignoring the
+    /// losses from moving the price scale
+    pub xcp_profit: PriceQ64,
+}
+
+impl CryptoSwapPool {
+    /// A pool holding `balances`, concentrated around `price_scale`.
+    ///
+    /// Returns `None` if the amplification is out of the bounds of
+    /// `AmpRamp::new`, a balance or the price is zero, or the invariant
+    /// cannot be computed.
+    pub fn new(
+        params: CryptoSwapParams,
+        balances: Reserves,
+        price_scale: PriceQ64,
+        now: u64,
+    ) -> Option<Self> {
+        if !is_valid_amp(params.amp) || price_scale == PriceQ64::ZERO {
+            return None;
+        }
+        let d = StableSwapCurve::compute_d(params.amp, scaled_balances(balances, price_scale)?)?;
+        Some(Self {
+            params,
+            balances,
+            protocol_fees: Reserves::default(),
+            price_scale,
+            price_oracle: price_scale,
+            last_price: price_scale,
+            last_timestamp: now,
+            d,
+            xcp_base: xcp(d, price_scale)?,
+            virtual_price: PriceQ64::ONE,
+            xcp_profit: PriceQ64::ONE,
+        })
+    }
+
+    /// Fee rate for a pool with scaled balances `xp`, between `mid_fee`
+    /// when they are equal and `out_fee` as they diverge
+    pub fn fee_rate(&self, xp: Reserves) -> Option<FeeRate> {
+        let (x, y) = (U256::from(xp.token_0.get()), U256::from(xp.token_1.get()));
+        let sum = x + y;
+        if sum.is_zero() {
+            return None;
+        }
+        // 4xy / (x + y)^2, 1 when balanced
+        let balance = to_u128(((x * y) << (Q64_RESOLUTION + 2)) / (sum * sum))?;
+        let fee_gamma = self.params.fee_gamma.raw();
+        let weight = PriceQ64::from_ratio(fee_gamma, fee_gamma.checked_add(Q64 - balance)?)?;
+        let mid_fee = u128::from(self.params.mid_fee.get());
+        let out_fee = u128::from(self.params.out_fee.get());
+        let fee = (mid_fee * weight.raw() + out_fee * (Q64 - weight.raw())) >> Q64_RESOLUTION;
+        FeeRate::checked(fee.min(u128::from(FEE_RATE_DENOMINATOR_VALUE)) as u64)
+    }
+
+    /// Swap exactly `source_amount` in `trade_direction` at `now`, then
+    /// update the price oracle and move the price scale if the pool has
+    /// earned enough.
+    ///
+    /// The fee rate is set by the balances after the trade, and charged on
+    /// the source amount. Returns `None`, leaving the pool unchanged, if the
+    /// swap would empty a balance or a value overflows.
+    pub fn swap_exact_in(
+        &mut self,
+        trade_direction: TradeDirection,
+        source_amount: u128,
+        now: u64,
+    ) -> Option<SwapResult> {
+        let mut pool = self.clone();
+        let (swap_source_amount, swap_destination_amount) =
+            pool.balances.source_and_destination(trade_direction);
+        // the trade without fees sets the fee rate
+        let (_, balances_without_fees) = pool.quote(trade_direction, source_amount)?;
+        let fee_rate = pool.fee_rate(scaled_balances(balances_without_fees, pool.price_scale)?)?;
+        let trade_fee = fee_rate.mul_ceil(source_amount)?;
+        let protocol_fee = Fees::protocol_fee(trade_fee, pool.params.protocol_fee_rate.get())?;
+        let (destination_amount_swapped, _) =
+            pool.quote(trade_direction, source_amount.checked_sub(trade_fee)?)?;
+
+        let new_swap_source_amount = swap_source_amount
+            .checked_add(source_amount)?
+            .checked_sub(protocol_fee)?;
+        let new_swap_destination_amount =
+            swap_destination_amount.checked_sub(destination_amount_swapped)?;
+        pool.balances = Reserves::from_source_and_destination(
+            trade_direction,
+            new_swap_source_amount,
+            new_swap_destination_amount,
+        );
+        let (protocol_fee_0, protocol_fee_1) = match trade_direction {
+            TradeDirection::ZeroForOne => (protocol_fee, 0),
+            TradeDirection::OneForZero => (0, protocol_fee),
+        };
+        pool.protocol_fees = Reserves::new(
+            Token0Amount(
+                pool.protocol_fees
+                    .token_0
+                    .get()
+                    .checked_add(protocol_fee_0)?,
+            ),
+            Token1Amount(
+                pool.protocol_fees
+                    .token_1
+                    .get()
+                    .checked_add(protocol_fee_1)?,
+            ),
+        );
+
+        let price = match trade_direction {
+            TradeDirection::ZeroForOne => {
+                PriceQ64::from_ratio(source_amount, destination_amount_swapped)
+            }
+            TradeDirection::OneForZero => {
+                PriceQ64::from_ratio(destination_amount_swapped, source_amount)
+            }
+        };
+        pool.tweak_price(now, price)?;
+        *self = pool;
+        Some(SwapResult {
+            new_swap_source_amount,
+            new_swap_destination_amount,
+            source_amount_swapped: source_amount,
+            destination_amount_swapped,
+            trade_fee,
+            protocol_fee,
+            destination_trade_fee: 0,
+            destination_protocol_fee: 0,
+            rounding_dust: 0,
+        })
+    }
+
+    // Destination tokens for `source_amount` without fees, and the balances
+    // after it
+    fn quote(
+        &self,
+        trade_direction: TradeDirection,
+        source_amount: u128,
+    ) -> Option<(u128, Reserves)> {
+        let xp = scaled_balances(self.balances, self.price_scale)?;
+        let (xp_source, xp_destination) = xp.source_and_destination(trade_direction);
+        let scaled_in = match trade_direction {
+            TradeDirection::ZeroForOne => source_amount,
+            TradeDirection::OneForZero => self
+                .price_scale
+                .mul_amount(source_amount, RoundDirection::Floor)?,
+        };
+        let scaled_out = StableSwapCurve::swap_base_input_without_fees(
+            self.params.amp,
+            scaled_in,
+            xp_source,
+            xp_destination,
+        )?;
+        let destination_amount = match trade_direction {
+            TradeDirection::ZeroForOne => self
+                .price_scale
+                .div_amount(scaled_out, RoundDirection::Floor)?,
+            TradeDirection::OneForZero => scaled_out,
+        };
+        let (swap_source_amount, swap_destination_amount) =
+            self.balances.source_and_destination(trade_direction);
+        let balances = Reserves::from_source_and_destination(
+            trade_direction,
+            swap_source_amount.checked_add(source_amount)?,
+            swap_destination_amount.checked_sub(destination_amount)?,
+        );
+        Some((destination_amount, balances))
+    }
+
+    // Fold the last trade price into the oracle, record `price` as the new
+    // last price, and move the price scale toward the oracle if the profit
+    // allows it
+    fn tweak_price(&mut self, now: u64, price: Option<PriceQ64>) -> Option<()> {
+        let params = self.params;
+        if now > self.last_timestamp {
+            let elapsed = i128::from(now - self.last_timestamp) << Q64_RESOLUTION;
+            let half_lives = elapsed / i128::from(params.ma_half_time.max(1));
+            // weight of the old average, halving every `ma_half_time`
+            let alpha = pow(Q64 >> 1, half_lives)?;
+            let oracle = (U256::from(self.last_price.raw()) * U256::from(Q64 - alpha)
+                + U256::from(self.price_oracle.raw()) * U256::from(alpha))
+                >> Q64_RESOLUTION;
+            self.price_oracle = PriceQ64::from_raw(to_u128(oracle)?);
+            self.last_timestamp = now;
+        }
+        // a trade too small to price leaves the last price as it was
+        if let Some(price) = price.filter(|price| *price != PriceQ64::ZERO) {
+            self.last_price = price;
+        }
+
+        let d = StableSwapCurve::compute_d(
+            params.amp,
+            scaled_balances(self.balances, self.price_scale)?,
+        )?;
+        let virtual_price = self.virtual_price_at(d, self.price_scale)?;
+        // fees only ever grow the invariant, so the virtual price too
+        self.xcp_profit = self
+            .xcp_profit
+            .checked_mul(virtual_price.checked_div(self.virtual_price)?)?;
+        self.d = d;
+        self.virtual_price = virtual_price;
+
+        let half_profit = (self.xcp_profit.raw().saturating_sub(Q64)) / 2;
+        let threshold = half_profit.checked_add(params.allowed_extra_profit.raw())?;
+        if virtual_price.raw().saturating_sub(Q64) <= threshold {
+            return Some(());
+        }
+        let (scale, oracle) = (self.price_scale.raw(), self.price_oracle.raw());
+        let norm = PriceQ64::from_ratio(scale.abs_diff(oracle), scale)?.raw();
+        let step = params.adjustment_step.raw();
+        if norm <= step {
+            return Some(());
+        }
+        // move `step` of the way, relative to the scale, toward the oracle
+        let new_scale = (U256::from(scale) * U256::from(norm - step)
+            + U256::from(step) * U256::from(oracle))
+            / U256::from(norm);
+        let new_scale = PriceQ64::from_raw(to_u128(new_scale)?);
+        let new_d =
+            StableSwapCurve::compute_d(params.amp, scaled_balances(self.balances, new_scale)?)?;
+        let new_virtual_price = self.virtual_price_at(new_d, new_scale)?;
+        if new_virtual_price.raw().saturating_sub(Q64) > half_profit {
+            self.price_scale = new_scale;
+            self.d = new_d;
+            self.virtual_price = new_virtual_price;
+        }
+        Some(())
+    }
+
+    fn virtual_price_at(&self, d: u128, price_scale: PriceQ64) -> Option<PriceQ64> {
+        PriceQ64::from_ratio(xcp(d, price_scale)?, self.xcp_base)
+    }
+}
+
+/// `balances` with token 1 valued in token 0 at `price_scale`, rounded down
+pub fn scaled_balances(balances: Reserves, price_scale: PriceQ64) -> Option<Reserves> {
+    Some(Reserves::new(
+        balances.token_0,
+        Token1Amount(price_scale.mul_amount(balances.token_1.get(), RoundDirection::Floor)?),
+    ))
+}
+
+// Geometric mean of the balances a pool with invariant `d` holds when
+// balanced at `price_scale`: `sqrt(d / 2 * d / (2 * price_scale))`
+fn xcp(d: u128, price_scale: PriceQ64) -> Option<u128> {
+    let sqrt_price = (U256::from(price_scale.raw()) << Q64_RESOLUTION).sqrt_floor();
+    if sqrt_price.is_zero() {
+        return None;
+    }
+    to_u128((U256::from(d) << Q64_RESOLUTION) / (sqrt_price * 2))
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::curve::stable::{AMP_PRECISION, MAX_AMP},
+        proptest::prelude::*,
+    };
+
+    fn params() -> CryptoSwapParams {
+        CryptoSwapParams {
+            amp: 50 * AMP_PRECISION,
+            mid_fee: FeeRate::from_bps(5).unwrap(),
+            out_fee: FeeRate::from_bps(45).unwrap(),
+            fee_gamma: PriceQ64::from_ratio(1, 4).unwrap(),
+            protocol_fee_rate: FeeRate::from_percent(50).unwrap(),
+            allowed_extra_profit: PriceQ64::from_ratio(1, 1_000_000).unwrap(),
+            adjustment_step: PriceQ64::from_ratio(1, 1_000).unwrap(),
+            ma_half_time: 600,
+        }
+    }
+
+    // token 1 worth 2_000 token 0, balanced at that price
+    fn pool() -> CryptoSwapPool {
+        CryptoSwapPool::new(
+            params(),
+            Reserves::new(
+                Token0Amount(2_000_000_000_000_000),
+                Token1Amount(1_000_000_000_000),
+            ),
+            PriceQ64::from_integer(2_000),
+            0,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn trades_near_the_price_scale() {
+        let mut pool = pool();
+        assert_eq!(pool.xcp_base, 44_721_359_549_995);
+        let result = pool
+            .swap_exact_in(TradeDirection::OneForZero, 1_000_000, 1)
+            .unwrap();
+        // the mid fee, and almost no slippage
+        assert_eq!(result.trade_fee, 500);
+        assert_eq!(result.protocol_fee, 250);
+        let expected = 2_000 * (1_000_000 - 500);
+        assert!(result.destination_amount_swapped <= expected);
+        assert!(expected - result.destination_amount_swapped < expected / 100_000);
+        assert_eq!(pool.protocol_fees.token_1.get(), 250);
+        assert!(pool.virtual_price > PriceQ64::ONE);
+        assert_eq!(pool.xcp_profit, pool.virtual_price);
+    }
+
+    #[test]
+    fn amplification_is_bounded() {
+        let balances = pool().balances;
+        let price_scale = PriceQ64::from_integer(2_000);
+        for amp in [0, 1, AMP_PRECISION - 1, MAX_AMP * AMP_PRECISION] {
+            let params = CryptoSwapParams { amp, ..params() };
+            assert_eq!(CryptoSwapPool::new(params, balances, price_scale, 0), None);
+        }
+        let params = CryptoSwapParams {
+            amp: AMP_PRECISION,
+            ..params()
+        };
+        assert!(CryptoSwapPool::new(params, balances, price_scale, 0).is_some());
+    }
+
+    #[test]
+    fn fee_rises_out_of_balance() {
+        let pool = pool();
+        let balanced = scaled_balances(pool.balances, pool.price_scale).unwrap();
+        assert_eq!(pool.fee_rate(balanced), Some(pool.params.mid_fee));
+        let skewed = Reserves::new(balanced.token_0, Token1Amount(balanced.token_1.get() / 4));
+        let fee = pool.fee_rate(skewed).unwrap().get();
+        assert!(fee > pool.params.mid_fee.get() && fee < pool.params.out_fee.get());
+    }
+
+    #[test]
+    fn repegs_toward_the_market() {
+        let mut pool = pool();
+        // the market moves to 2_040: arbitrageurs buy token 1 until the pool
+        // trades there, then trade around that price, and the fees let the
+        // price scale follow the oracle
+        let market = PriceQ64::from_integer(2_040);
+        let mut now = 0;
+        while pool.last_price < market {
+            now += 60;
+            let amount = pool.balances.token_0.get() / 200;
+            pool.swap_exact_in(TradeDirection::ZeroForOne, amount, now)
+                .unwrap();
+        }
+        for _ in 0..2_000 {
+            now += 60;
+            let trade_direction = if pool.last_price < market {
+                TradeDirection::ZeroForOne
+            } else {
+                TradeDirection::OneForZero
+            };
+            let amount = pool.balances.source_and_destination(trade_direction).0 / 500;
+            pool.swap_exact_in(trade_direction, amount, now).unwrap();
+        }
+        assert!(pool.price_oracle > PriceQ64::from_integer(2_030));
+        assert!(pool.price_scale > PriceQ64::from_integer(2_005));
+        assert!(pool.price_scale < pool.price_oracle);
+        assert!(pool.virtual_price > PriceQ64::ONE);
+        assert!(pool.virtual_price < pool.xcp_profit);
+    }
+
+    proptest! {
+        #[test]
+        fn swaps_keep_value_in_the_pool(
+            trades in prop::collection::vec((any::<bool>(), 1u128..1 << 44, 0u64..3_600), 1..10),
+        ) {
+            let mut pool = pool();
+            let mut now = 0;
+            for (zero_for_one, amount, elapsed) in trades {
+                now += elapsed;
+                let (trade_direction, reverse, amount) = if zero_for_one {
+                    (TradeDirection::ZeroForOne, TradeDirection::OneForZero, amount * 2_000)
+                } else {
+                    (TradeDirection::OneForZero, TradeDirection::ZeroForOne, amount)
+                };
+                let before = pool.clone();
+                let Some(result) = pool.swap_exact_in(trade_direction, amount, now) else {
+                    prop_assert_eq!(&pool, &before);
+                    continue;
+                };
+                prop_assert!(pool.xcp_profit >= before.xcp_profit);
+                prop_assert!(pool.virtual_price >= PriceQ64::ONE);
+                // trading straight back never returns more than was paid
+                let mut round_trip = pool.clone();
+                if let Some(back) =
+                    round_trip.swap_exact_in(reverse, result.destination_amount_swapped, now)
+                {
+                    prop_assert!(back.destination_amount_swapped <= amount);
+                }
+            }
+        }
+    }
+}
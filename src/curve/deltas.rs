@@ -0,0 +1,232 @@
+//! Signed reserve deltas
+//!
+//! Swaps, deposits and withdrawals each report what they moved in their own
+//! result type. `ReserveDeltas` expresses any of them as the signed change
+//! to a pool's vault balances and LP supply, so a ledger can fold every
+//! operation with one addition.
+use crate::curve::calculator::{DepositResult, SwapResult, TradeDirection, TradingTokenResult};
+
+/// Signed change to a pool's token vaults and LP supply, positive when the
+/// pool gains.
+///
+/// The vaults hold accrued protocol fees alongside the reserves, so a swap's
+/// deltas include its protocol fee.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ReserveDeltas {
+    /// Change to the token 0 vault
+    pub token_0: i128,
+    /// Change to the token 1 vault
+    pub token_1: i128,
+    /// Change to the LP supply
+    pub lp: i128,
+}
+
+impl ReserveDeltas {
+    pub const ZERO: Self = Self {
+        token_0: 0,
+        token_1: 0,
+        lp: 0,
+    };
+
+    /// Deltas of a swap in `trade_direction`: the source amount in and the
+    /// destination amount out.
+    ///
+    /// Returns `None` if an amount exceeds `i128::MAX`.
+    pub fn from_swap(trade_direction: TradeDirection, result: &SwapResult) -> Option<Self> {
+        let source = i128::try_from(result.source_amount_swapped).ok()?;
+        let destination = i128::try_from(result.destination_amount_swapped).ok()?;
+        let (token_0, token_1) = match trade_direction {
+            TradeDirection::ZeroForOne => (source, -destination),
+            TradeDirection::OneForZero => (-destination, source),
+        };
+        Some(Self {
+            token_0,
+            token_1,
+            lp: 0,
+        })
+    }
+
+    /// Deltas of minting `lp_token_amount` LP tokens for `trading_tokens`.
+    ///
+    /// Returns `None` if an amount exceeds `i128::MAX`.
+    pub fn from_deposit(
+        lp_token_amount: u128,
+        trading_tokens: &TradingTokenResult,
+    ) -> Option<Self> {
+        Some(Self {
+            token_0: i128::try_from(trading_tokens.token_0_amount).ok()?,
+            token_1: i128::try_from(trading_tokens.token_1_amount).ok()?,
+            lp: i128::try_from(lp_token_amount).ok()?,
+        })
+    }
+
+    /// Deltas of burning `lp_token_amount` LP tokens for `trading_tokens`.
+    ///
+    /// Returns `None` if an amount exceeds `i128::MAX`.
+    pub fn from_withdraw(
+        lp_token_amount: u128,
+        trading_tokens: &TradingTokenResult,
+    ) -> Option<Self> {
+        Self::from_deposit(lp_token_amount, trading_tokens)?.checked_neg()
+    }
+
+    /// The deltas of two operations applied one after the other
+    pub const fn checked_add(self, rhs: Self) -> Option<Self> {
+        let (Some(token_0), Some(token_1), Some(lp)) = (
+            self.token_0.checked_add(rhs.token_0),
+            self.token_1.checked_add(rhs.token_1),
+            self.lp.checked_add(rhs.lp),
+        ) else {
+            return None;
+        };
+        Some(Self {
+            token_0,
+            token_1,
+            lp,
+        })
+    }
+
+    /// The deltas reverting these
+    pub const fn checked_neg(self) -> Option<Self> {
+        let (Some(token_0), Some(token_1), Some(lp)) = (
+            self.token_0.checked_neg(),
+            self.token_1.checked_neg(),
+            self.lp.checked_neg(),
+        ) else {
+            return None;
+        };
+        Some(Self {
+            token_0,
+            token_1,
+            lp,
+        })
+    }
+}
+
+impl TryFrom<&DepositResult> for ReserveDeltas {
+    type Error = core::num::TryFromIntError;
+
+    /// Deltas of a proportional deposit; unused tokens never enter the pool
+    fn try_from(result: &DepositResult) -> Result<Self, Self::Error> {
+        Ok(Self {
+            token_0: i128::try_from(result.trading_tokens.token_0_amount)?,
+            token_1: i128::try_from(result.trading_tokens.token_1_amount)?,
+            lp: i128::try_from(result.lp_token_amount)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            curve::{
+                amount::{LpAmount, Reserves, Token0Amount, Token1Amount},
+                fees::{FeeConfig, FeeRate},
+            },
+            sim::Pool,
+        },
+        proptest::prelude::*,
+    };
+
+    // The pool's vault balances and LP supply
+    fn balances(pool: &Pool) -> (i128, i128, i128) {
+        (
+            (pool.reserves.token_0.get() + pool.protocol_fees.token_0.get()) as i128,
+            (pool.reserves.token_1.get() + pool.protocol_fees.token_1.get()) as i128,
+            pool.lp_supply.get() as i128,
+        )
+    }
+
+    fn fold(start: (i128, i128, i128), deltas: ReserveDeltas) -> (i128, i128, i128) {
+        (
+            start.0 + deltas.token_0,
+            start.1 + deltas.token_1,
+            start.2 + deltas.lp,
+        )
+    }
+
+    #[test]
+    fn swap_deltas_follow_the_direction() {
+        let result = SwapResult {
+            new_swap_source_amount: 1_100,
+            new_swap_destination_amount: 950,
+            source_amount_swapped: 100,
+            destination_amount_swapped: 50,
+            trade_fee: 1,
+            protocol_fee: 0,
+            destination_trade_fee: 0,
+            destination_protocol_fee: 0,
+            rounding_dust: 0,
+        };
+        let deltas = ReserveDeltas::from_swap(TradeDirection::ZeroForOne, &result).unwrap();
+        assert_eq!(
+            deltas,
+            ReserveDeltas {
+                token_0: 100,
+                token_1: -50,
+                lp: 0
+            }
+        );
+        assert_eq!(
+            ReserveDeltas::from_swap(TradeDirection::OneForZero, &result),
+            Some(ReserveDeltas {
+                token_0: -50,
+                token_1: 100,
+                lp: 0
+            })
+        );
+        assert_eq!(
+            deltas.checked_add(deltas.checked_neg().unwrap()),
+            Some(ReserveDeltas::ZERO)
+        );
+        let huge = SwapResult {
+            source_amount_swapped: u128::MAX,
+            ..result
+        };
+        assert_eq!(
+            ReserveDeltas::from_swap(TradeDirection::ZeroForOne, &huge),
+            None
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn folded_deltas_track_the_vaults(
+            operations in prop::collection::vec((0..3u8, 1..100_000u128), 1..20),
+        ) {
+            let mut pool = Pool::new(
+                Reserves::new(Token0Amount(1_000_000), Token1Amount(2_000_000)),
+                LpAmount(1_000_000),
+                FeeConfig::new(
+                    FeeRate::from_bps(30).unwrap(),
+                    FeeRate::from_percent(20).unwrap(),
+                ),
+            );
+            let mut ledger = balances(&pool);
+            for (operation, amount) in operations {
+                let deltas = match operation {
+                    0 => pool
+                        .apply_swap(TradeDirection::ZeroForOne, amount)
+                        .and_then(|(result, _)| {
+                            ReserveDeltas::from_swap(TradeDirection::ZeroForOne, &result)
+                        }),
+                    1 => pool
+                        .apply_deposit(LpAmount(amount))
+                        .and_then(|result| ReserveDeltas::from_deposit(amount, &result)),
+                    _ => pool
+                        .apply_withdraw(LpAmount(amount))
+                        .and_then(|result| ReserveDeltas::from_withdraw(amount, &result)),
+                };
+                ledger = fold(ledger, deltas.unwrap_or_default());
+                prop_assert_eq!(ledger, balances(&pool));
+            }
+
+            let maximum = Reserves::new(Token0Amount(5_000), Token1Amount(5_000));
+            let result = pool.apply_deposit_tokens(maximum).unwrap();
+            ledger = fold(ledger, ReserveDeltas::try_from(&result).unwrap());
+            prop_assert_eq!(ledger, balances(&pool));
+        }
+    }
+}
@@ -0,0 +1,119 @@
+//! Exhaustive small-domain verification
+//!
+//! Rounding edge cases concentrate on small reserves and amounts, where one
+//! unit is a large share of everything. Rather than sampling them, these
+//! tests check every swap and LP conversion with reserves and amounts up to
+//! `limit()` against the exact rational oracle.
+//!
+//! They are long-running and only built with the `exhaustive-tests` feature.
+//! The domain defaults to `DEFAULT_LIMIT` units and can be widened to a few
+//! thousand through the `INVARIANT_EXHAUSTIVE_LIMIT` environment variable,
+//! at a cost cubic in the limit.
+use crate::curve::{
+    calculator::{CurveCalculator, RoundDirection},
+    fees::MAX_TRADE_FEE_RATE,
+    rational,
+    swap_params::SwapParams,
+};
+
+const DEFAULT_LIMIT: u128 = 48;
+
+// (trade fee rate, protocol fee rate): no fee, a common fee and the highest
+// a pool can be configured with
+const FEE_RATES: [(u64, u64); 3] = [
+    (0, 0),
+    (2_500, 120_000),
+    (MAX_TRADE_FEE_RATE.get(), 500_000),
+];
+
+fn limit() -> u128 {
+    std::env::var("INVARIANT_EXHAUSTIVE_LIMIT")
+        .map(|limit| limit.parse().expect("invalid INVARIANT_EXHAUSTIVE_LIMIT"))
+        .unwrap_or(DEFAULT_LIMIT)
+}
+
+#[test]
+fn every_small_exact_in_swap_rounds_for_the_pool() {
+    let limit = limit();
+    for swap_source_amount in 1..=limit {
+        for swap_destination_amount in 1..=limit {
+            for source_amount in 1..=limit {
+                for (trade_fee_rate, protocol_fee_rate) in FEE_RATES {
+                    assert!(
+                        rational::differential_swap_base_input(
+                            source_amount,
+                            swap_source_amount,
+                            swap_destination_amount,
+                            trade_fee_rate,
+                            protocol_fee_rate,
+                        )
+                        .is_some()
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn every_small_exact_out_swap_rounds_for_the_pool() {
+    let limit = limit();
+    for swap_source_amount in 1..=limit {
+        for swap_destination_amount in 2..=limit {
+            // the pool cannot pay out its whole reserve
+            for destination_amount in 1..swap_destination_amount {
+                for (trade_fee_rate, protocol_fee_rate) in FEE_RATES {
+                    let result = CurveCalculator::swap_base_output(
+                        &SwapParams::from_raw_parts(
+                            destination_amount,
+                            swap_source_amount,
+                            swap_destination_amount,
+                            trade_fee_rate,
+                            protocol_fee_rate,
+                        )
+                        .unwrap(),
+                    )
+                    .unwrap();
+                    rational::check_swap_base_output(
+                        &result,
+                        destination_amount,
+                        swap_source_amount,
+                        swap_destination_amount,
+                        trade_fee_rate,
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn every_small_lp_conversion_rounds_for_the_pool() {
+    let limit = limit();
+    for lp_token_supply in 1..=limit {
+        for lp_token_amount in 1..=lp_token_supply {
+            for swap_token_0_amount in 0..=limit {
+                // the second reserve covers the other end of the domain
+                let swap_token_1_amount = limit - swap_token_0_amount;
+                for round_direction in [RoundDirection::Floor, RoundDirection::Ceiling] {
+                    let result = CurveCalculator::lp_tokens_to_trading_tokens(
+                        lp_token_amount,
+                        lp_token_supply,
+                        swap_token_0_amount,
+                        swap_token_1_amount,
+                        round_direction,
+                    )
+                    .unwrap();
+                    rational::check_lp_tokens_to_trading_tokens(
+                        &result,
+                        lp_token_amount,
+                        lp_token_supply,
+                        swap_token_0_amount,
+                        swap_token_1_amount,
+                        round_direction,
+                    );
+                }
+            }
+        }
+    }
+}
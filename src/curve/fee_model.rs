@@ -0,0 +1,248 @@
+//! Pluggable fee models
+//!
+//! The calculator charges trade fees through `FeeModel`, so pools can charge
+//! dynamic, tiered or rebated fees without changes to the swap math. A
+//! `FeeConfig` is the flat-rate model pools are configured with.
+use crate::curve::fees::{FeeConfig, Fees};
+
+/// The pool a trade fee is charged in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FeeCtx {
+    /// Amount of source tokens in the pool
+    pub swap_source_amount: u128,
+    /// Amount of destination tokens in the pool
+    pub swap_destination_amount: u128,
+}
+
+/// How a pool charges trade fees and shares them with the protocol
+pub trait FeeModel {
+    /// Trade fee charged on swapping `amount` source tokens.
+    ///
+    /// Fees of the whole amount or more are capped or rejected per the
+    /// swap's `TinySwapPolicy`. Returns `None` if the fee cannot be
+    /// calculated.
+    fn trade_fee(&self, amount: u128, ctx: &FeeCtx) -> Option<u128>;
+
+    /// The protocol's share of trade fee `fee`, at most `fee`
+    fn protocol_share(&self, fee: u128) -> Option<u128>;
+
+    /// Smallest amount that leaves `post_fee_amount` after its trade fee.
+    ///
+    /// The default searches `trade_fee`, which must leave more as the amount
+    /// grows. Returns `None` if no amount does.
+    fn pre_fee_amount(&self, post_fee_amount: u128, ctx: &FeeCtx) -> Option<u128> {
+        search_pre_fee_amount(self, post_fee_amount, ctx)
+    }
+
+    /// Smallest amount that leaves something to swap after its trade fee
+    fn minimum_input(&self, ctx: &FeeCtx) -> Option<u128> {
+        self.pre_fee_amount(1, ctx)
+    }
+}
+
+// The default `FeeModel::pre_fee_amount`, for implementations overriding
+// it for some configurations only
+pub(crate) fn search_pre_fee_amount<M: FeeModel + ?Sized>(
+    fee_model: &M,
+    post_fee_amount: u128,
+    ctx: &FeeCtx,
+) -> Option<u128> {
+    let leaves = |amount: u128| {
+        fee_model
+            .trade_fee(amount, ctx)
+            .is_some_and(|fee| amount.saturating_sub(fee) >= post_fee_amount)
+    };
+    if !leaves(u128::MAX) {
+        return None;
+    }
+    // fees are never negative, so less than `post_fee_amount` never does
+    let (mut low, mut high) = (post_fee_amount, u128::MAX);
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if leaves(middle) {
+            high = middle;
+        } else {
+            low = middle + 1;
+        }
+    }
+    Some(low)
+}
+
+/// The flat rates of the config, whatever the pool.
+///
+/// `trade_fee_side` and `protocol_fee_mode` are not part of the model; they
+/// are applied by the calculations taking a `FeeConfig`.
+impl FeeModel for FeeConfig {
+    fn trade_fee(&self, amount: u128, _ctx: &FeeCtx) -> Option<u128> {
+        Fees::trading_fee(amount, self.trade_fee_rate.get())
+    }
+
+    fn protocol_share(&self, fee: u128) -> Option<u128> {
+        Fees::protocol_fee(fee, self.protocol_fee_rate.get())
+    }
+
+    fn pre_fee_amount(&self, post_fee_amount: u128, _ctx: &FeeCtx) -> Option<u128> {
+        Fees::calculate_pre_fee_amount(post_fee_amount, self.trade_fee_rate.get())
+    }
+
+    fn minimum_input(&self, _ctx: &FeeCtx) -> Option<u128> {
+        Fees::minimum_input(self.trade_fee_rate.get())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::curve::{
+            amount::{Reserves, Token0Amount, Token1Amount},
+            calculator::{CurveCalculator, CurveError, TradeDirection},
+            fees::{FeeRate, TinySwapPolicy},
+            swap_params::SwapParams,
+        },
+        proptest::prelude::*,
+    };
+
+    const CTX: FeeCtx = FeeCtx {
+        swap_source_amount: 1_000_000,
+        swap_destination_amount: 1_000_000,
+    };
+
+    // Flat rate charged with the default search for the pre-fee amount
+    struct Searched(FeeConfig);
+
+    impl FeeModel for Searched {
+        fn trade_fee(&self, amount: u128, ctx: &FeeCtx) -> Option<u128> {
+            self.0.trade_fee(amount, ctx)
+        }
+
+        fn protocol_share(&self, fee: u128) -> Option<u128> {
+            self.0.protocol_share(fee)
+        }
+    }
+
+    // 1% on the first 10_000 tokens and 0.1% beyond
+    struct Tiered;
+
+    impl FeeModel for Tiered {
+        fn trade_fee(&self, amount: u128, _ctx: &FeeCtx) -> Option<u128> {
+            let low = amount.min(10_000);
+            Some(low.div_ceil(100) + (amount - low).div_ceil(1_000))
+        }
+
+        fn protocol_share(&self, fee: u128) -> Option<u128> {
+            Some(fee / 2)
+        }
+    }
+
+    // Claims more than the whole fee for the protocol
+    struct Greedy;
+
+    impl FeeModel for Greedy {
+        fn trade_fee(&self, amount: u128, _ctx: &FeeCtx) -> Option<u128> {
+            Some(amount / 10)
+        }
+
+        fn protocol_share(&self, fee: u128) -> Option<u128> {
+            fee.checked_add(1)
+        }
+    }
+
+    #[test]
+    fn flat_config_matches_rate_based_swaps() {
+        let fee_config = FeeConfig::STANDARD_25BPS;
+        let reserves = Reserves::new(Token0Amount(1_000_000), Token1Amount(2_000_000));
+        let params = SwapParams::new(TradeDirection::ZeroForOne, 100_000, reserves, fee_config);
+        assert_eq!(
+            CurveCalculator::try_swap_base_input_with_fee_model(
+                100_000,
+                1_000_000,
+                2_000_000,
+                &fee_config,
+                TinySwapPolicy::Clamp,
+            ),
+            CurveCalculator::try_swap_base_input(&params, TinySwapPolicy::Clamp)
+        );
+        assert_eq!(
+            CurveCalculator::try_swap_base_output_with_fee_model(
+                100_000,
+                1_000_000,
+                2_000_000,
+                &fee_config,
+            ),
+            CurveCalculator::try_swap_base_output(&params)
+        );
+    }
+
+    #[test]
+    fn tiered_model_is_charged_per_tier() {
+        let result = CurveCalculator::try_swap_base_input_with_fee_model(
+            110_000,
+            1_000_000,
+            1_000_000,
+            &Tiered,
+            TinySwapPolicy::Clamp,
+        )
+        .unwrap();
+        assert_eq!(result.trade_fee, 100 + 100);
+        assert_eq!(result.protocol_fee, 100);
+
+        let result = CurveCalculator::try_swap_base_output_with_fee_model(
+            50_000, 1_000_000, 1_000_000, &Tiered,
+        )
+        .unwrap();
+        let swapped = result.source_amount_swapped - result.trade_fee;
+        assert_eq!(
+            Tiered.trade_fee(result.source_amount_swapped, &CTX),
+            Some(result.trade_fee)
+        );
+        assert_eq!(
+            Tiered.pre_fee_amount(swapped, &CTX),
+            Some(result.source_amount_swapped)
+        );
+
+        assert_eq!(Tiered.minimum_input(&CTX), Some(2));
+        assert_eq!(
+            CurveCalculator::try_swap_base_input_with_fee_model(
+                1,
+                1_000_000,
+                1_000_000,
+                &Tiered,
+                TinySwapPolicy::Reject,
+            ),
+            Err(CurveError::MinimumInputNotMet { minimum_amount: 2 })
+        );
+    }
+
+    #[test]
+    fn protocol_share_is_bounded_by_the_fee() {
+        assert_eq!(
+            CurveCalculator::try_swap_base_input_with_fee_model(
+                1_000,
+                1_000_000,
+                1_000_000,
+                &Greedy,
+                TinySwapPolicy::Clamp,
+            ),
+            Err(CurveError::FeeConfigInvalid)
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn default_search_matches_gross_up(
+            post_fee_amount in 0..u128::MAX / 2,
+            trade_fee_rate in 0..=100_000u64,
+        ) {
+            let fee_config = FeeConfig::new(FeeRate::checked(trade_fee_rate).unwrap(), FeeRate::ZERO);
+            prop_assert_eq!(
+                Searched(fee_config).pre_fee_amount(post_fee_amount, &CTX),
+                fee_config.pre_fee_amount(post_fee_amount, &CTX)
+            );
+            prop_assert_eq!(
+                Searched(fee_config).minimum_input(&CTX),
+                fee_config.minimum_input(&CTX)
+            );
+        }
+    }
+}
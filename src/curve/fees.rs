@@ -1,31 +1,295 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+
 // Denominator value used for fee rate calculations
 pub const FEE_RATE_DENOMINATOR_VALUE: u64 = 1_000_000;
 
+/// Highest trade fee rate a pool can be configured with, 10%.
+///
+/// Kept well below 100%, where grossing up an exact-out amount would divide
+/// by zero and any swap would be all fee.
+pub const MAX_TRADE_FEE_RATE: FeeRate = FeeRate(FEE_RATE_DENOMINATOR_VALUE / 10);
+const _: () = assert!(MAX_TRADE_FEE_RATE.0 < FEE_RATE_DENOMINATOR_VALUE / 2);
+
 // Struct representing fees (currently empty, but used for implementing fee calculations)
 pub struct Fees {}
 
+/// A fee rate in units of `FEE_RATE_DENOMINATOR_VALUE`, at most 100%
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, BorshSerialize)]
+pub struct FeeRate(u64);
+
+// Decoded through `FeeRate::checked`, so account data cannot hold a rate
+// above 100%
+impl BorshDeserialize for FeeRate {
+    fn deserialize_reader<R: borsh::io::Read>(reader: &mut R) -> borsh::io::Result<Self> {
+        Self::checked(u64::deserialize_reader(reader)?).ok_or_else(|| {
+            borsh::io::Error::new(borsh::io::ErrorKind::InvalidData, "fee rate exceeds 100%")
+        })
+    }
+}
+
+impl FeeRate {
+    /// No fee
+    pub const ZERO: Self = Self(0);
+    /// A fee of 100%
+    pub const MAX: Self = Self(FEE_RATE_DENOMINATOR_VALUE);
+
+    /// Fee rate units per basis point
+    const UNITS_PER_BPS: u64 = FEE_RATE_DENOMINATOR_VALUE / 10_000;
+    /// Fee rate units per percent
+    const UNITS_PER_PERCENT: u64 = FEE_RATE_DENOMINATOR_VALUE / 100;
+
+    /// Validate a raw rate in units of `FEE_RATE_DENOMINATOR_VALUE`
+    pub const fn checked(rate: u64) -> Option<Self> {
+        if rate > FEE_RATE_DENOMINATOR_VALUE {
+            None
+        } else {
+            Some(Self(rate))
+        }
+    }
+
+    /// A rate in basis points, eg. `from_bps(25)` is 0.25%
+    pub const fn from_bps(bps: u16) -> Option<Self> {
+        Self::checked(bps as u64 * Self::UNITS_PER_BPS)
+    }
+
+    /// A rate in whole percent
+    pub const fn from_percent(percent: u8) -> Option<Self> {
+        Self::checked(percent as u64 * Self::UNITS_PER_PERCENT)
+    }
+
+    /// The raw rate in units of `FEE_RATE_DENOMINATOR_VALUE`
+    pub const fn get(self) -> u64 {
+        self.0
+    }
+
+    /// `amount * rate`, rounded up
+    pub const fn mul_ceil(self, amount: u128) -> Option<u128> {
+        ceil_div(amount, self.0 as u128, FEE_RATE_DENOMINATOR_VALUE as u128)
+    }
+
+    /// `amount * rate`, rounded down
+    pub const fn mul_floor(self, amount: u128) -> Option<u128> {
+        floor_div(amount, self.0 as u128, FEE_RATE_DENOMINATOR_VALUE as u128)
+    }
+
+    /// `amount / (1 - rate)`, rounded up, ie. the gross amount from which
+    /// deducting a fee at this rate leaves `amount`
+    ///
+    /// Returns `None` at 100% or more, where no gross amount leaves anything
+    /// after the fee.
+    pub const fn gross_up(self, amount: u128) -> Option<u128> {
+        if self.0 == 0 {
+            return Some(amount);
+        }
+        if self.0 >= FEE_RATE_DENOMINATOR_VALUE {
+            return None;
+        }
+        let denominator = (FEE_RATE_DENOMINATOR_VALUE - self.0) as u128;
+        ceil_div(amount, FEE_RATE_DENOMINATOR_VALUE as u128, denominator)
+    }
+}
+
+/// Side of a swap the trade fee is deducted from
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, BorshSerialize, BorshDeserialize)]
+#[borsh(use_discriminant = true)]
+pub enum TradeFeeSide {
+    /// The fee is deducted from the input before it is swapped
+    #[default]
+    Input = 0,
+    /// The whole input is swapped and the fee is deducted from the output,
+    /// so the fee and the protocol's share of it are in the output token.
+    /// Only the constant product curve supports this; other curves reject
+    /// it.
+    Output = 1,
+}
+
+/// What to do with a swap so small its trade fee, rounded up, consumes
+/// the whole input
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum TinySwapPolicy {
+    /// Charge the whole input as the fee and swap nothing
+    #[default]
+    Clamp,
+    /// Reject the swap, reporting the minimum input
+    Reject,
+}
+
+/// Tokens the protocol fee accrues in
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, BorshSerialize, BorshDeserialize)]
+#[borsh(use_discriminant = true)]
+pub enum ProtocolFeeMode {
+    /// The whole protocol fee is taken from the input
+    #[default]
+    Input = 0,
+    /// Half the protocol fee is taken from the input. The pool keeps the
+    /// other half and sells it on the curve, fee free, for the output token,
+    /// so the protocol accrues both tokens. Only the constant product curve
+    /// supports this; other curves reject it. Fees deducted from the output
+    /// are unaffected.
+    Split = 1,
+}
+
+/// Share of the trade fee the presets give the protocol, 12%
+pub const DEFAULT_PROTOCOL_FEE_RATE: FeeRate = FeeRate(120_000);
+
+/// A canonical fee configuration, see `FeeConfig::preset`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Preset {
+    /// 0.01%, for pegged pairs
+    Stable,
+    /// 0.25%, for most pairs
+    Standard,
+    /// 1%, for volatile and exotic pairs
+    Volatile,
+}
+
+impl Preset {
+    /// Every preset, lowest fee first
+    pub const ALL: [Self; 3] = [Self::Stable, Self::Standard, Self::Volatile];
+}
+
+// every preset is a valid pool configuration
+const _: () = {
+    let mut index = 0;
+    while index < Preset::ALL.len() {
+        assert!(FeeConfig::preset(Preset::ALL[index]).is_valid());
+        index += 1;
+    }
+};
+
+/// Fee rates configured for a pool
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct FeeConfig {
+    /// Share of the source amount charged on every trade
+    pub trade_fee_rate: FeeRate,
+    /// Share of the trade fee that goes to the protocol
+    pub protocol_fee_rate: FeeRate,
+    /// Tokens the protocol fee accrues in
+    pub protocol_fee_mode: ProtocolFeeMode,
+    /// Side of a swap the trade fee is deducted from
+    pub trade_fee_side: TradeFeeSide,
+}
+
+impl FeeConfig {
+    /// `Preset::Stable`: a 0.01% trade fee
+    pub const STABLE_1BPS: Self = Self::new(FeeRate(100), DEFAULT_PROTOCOL_FEE_RATE);
+    /// `Preset::Standard`: a 0.25% trade fee
+    pub const STANDARD_25BPS: Self = Self::new(FeeRate(2_500), DEFAULT_PROTOCOL_FEE_RATE);
+    /// `Preset::Volatile`: a 1% trade fee
+    pub const VOLATILE_100BPS: Self = Self::new(FeeRate(10_000), DEFAULT_PROTOCOL_FEE_RATE);
+
+    pub const fn new(trade_fee_rate: FeeRate, protocol_fee_rate: FeeRate) -> Self {
+        Self {
+            trade_fee_rate,
+            protocol_fee_rate,
+            protocol_fee_mode: ProtocolFeeMode::Input,
+            trade_fee_side: TradeFeeSide::Input,
+        }
+    }
+
+    /// Like `new`, for configuring a pool.
+    ///
+    /// Returns `None` if the trade fee rate exceeds `MAX_TRADE_FEE_RATE`.
+    pub const fn checked(trade_fee_rate: FeeRate, protocol_fee_rate: FeeRate) -> Option<Self> {
+        let fee_config = Self::new(trade_fee_rate, protocol_fee_rate);
+        if fee_config.is_valid() {
+            Some(fee_config)
+        } else {
+            None
+        }
+    }
+
+    /// The configuration of `preset`, with `DEFAULT_PROTOCOL_FEE_RATE` of
+    /// the trade fee going to the protocol, taken from the input
+    pub const fn preset(preset: Preset) -> Self {
+        match preset {
+            Preset::Stable => Self::STABLE_1BPS,
+            Preset::Standard => Self::STANDARD_25BPS,
+            Preset::Volatile => Self::VOLATILE_100BPS,
+        }
+    }
+
+    /// Whether a pool can be configured with these rates, ie. the trade fee
+    /// rate is at most `MAX_TRADE_FEE_RATE`
+    pub const fn is_valid(&self) -> bool {
+        self.trade_fee_rate.0 <= MAX_TRADE_FEE_RATE.0
+    }
+
+    /// Whether the trade fee and the protocol's share of it are both taken
+    /// from the input, the only configuration curves other than the constant
+    /// product curve implement
+    pub const fn is_input_only(&self) -> bool {
+        matches!(
+            (self.trade_fee_side, self.protocol_fee_mode),
+            (TradeFeeSide::Input, ProtocolFeeMode::Input)
+        )
+    }
+
+    /// The same rates with the protocol fee accruing per `protocol_fee_mode`
+    pub const fn with_protocol_fee_mode(self, protocol_fee_mode: ProtocolFeeMode) -> Self {
+        Self {
+            protocol_fee_mode,
+            ..self
+        }
+    }
+
+    /// The same rates deducted from `trade_fee_side`
+    pub const fn with_trade_fee_side(self, trade_fee_side: TradeFeeSide) -> Self {
+        Self {
+            trade_fee_side,
+            ..self
+        }
+    }
+}
+
 // Helper function to perform ceiling division
 // Ensures that the division result rounds up when there is a remainder
-// Returns `None` if an overflow occurs during multiplication or addition
-fn ceil_div(token_amount: u128, fee_numerator: u128, fee_denominator: u128) -> Option<u128> {
-    token_amount
-        .checked_mul(u128::from(fee_numerator)) // Multiply amount by the numerator
-        .unwrap()
-        .checked_add(fee_denominator)? // Add denominator to ensure proper rounding up
-        .checked_sub(1)? // Subtract 1 to maintain proper division behavior
-        .checked_div(fee_denominator) // Perform division
+// Returns `None` if the denominator is zero or the result does not fit
+//
+// Written without `?` so it can be evaluated in const contexts
+const fn ceil_div(token_amount: u128, fee_numerator: u128, fee_denominator: u128) -> Option<u128> {
+    let Some((whole, remainder)) = split_mul(token_amount, fee_numerator, fee_denominator) else {
+        return None;
+    };
+    // Round the fractional part up
+    let fraction = remainder / fee_denominator + (remainder % fee_denominator != 0) as u128;
+    whole.checked_add(fraction)
 }
 
 // Helper function for calculating swap fee using floor division
 // Ensures that the division result rounds down
-// Returns `None` if an overflow occurs during multiplication
-pub fn floor_div(token_amount: u128, fee_numerator: u128, fee_denominator: u128) -> Option<u128> {
-    Some(
-        token_amount
-            .checked_mul(fee_numerator)? // Multiply amount by the numerator
-            .checked_div(fee_denominator)?, // Perform division
-    )
+// Returns `None` if the denominator is zero or the result does not fit
+pub const fn floor_div(
+    token_amount: u128,
+    fee_numerator: u128,
+    fee_denominator: u128,
+) -> Option<u128> {
+    let Some((whole, remainder)) = split_mul(token_amount, fee_numerator, fee_denominator) else {
+        return None;
+    };
+    whole.checked_add(remainder / fee_denominator)
 }
+
+// Split `amount * numerator` around the denominator, as
+// `(amount / denominator) * numerator` and `(amount % denominator) * numerator`,
+// so that multiplying only overflows when the quotient does, instead of
+// whenever `amount * numerator` does
+const fn split_mul(amount: u128, numerator: u128, denominator: u128) -> Option<(u128, u128)> {
+    let Some(quotient) = amount.checked_div(denominator) else {
+        return None;
+    };
+    let Some(whole) = quotient.checked_mul(numerator) else {
+        return None;
+    };
+    let Some(remainder) = (amount % denominator).checked_mul(numerator) else {
+        return None;
+    };
+    Some((whole, remainder))
+}
+
 impl Fees {
     // Calculate the trading fee based on the provided trade fee rate
     // Uses `ceil_div` to ensure rounding up when necessary
@@ -36,35 +300,214 @@ impl Fees {
     //
     // # Returns
     // * `Some(u128)` containing the fee amount if successful, otherwise `None`
-    pub fn trading_fee(amount: u128, trade_fee_rate: u64) -> Option<u128> {
-        println!(
-            "Trading fee calculation -> amount: {}, trade_fee_rate: {}",
-            amount, trade_fee_rate
-        );
-        ceil_div(amount, u128::from(trade_fee_rate), u128::from(FEE_RATE_DENOMINATOR_VALUE))
+    pub const fn trading_fee(amount: u128, trade_fee_rate: u64) -> Option<u128> {
+        FeeRate(trade_fee_rate).mul_ceil(amount)
     }
-    
+
     /// Calculate the owner trading fee in trading tokens
-    pub fn protocol_fee(amount: u128, protocol_fee_rate: u64) -> Option<u128> {
-        floor_div(
-            amount,
-            u128::from(protocol_fee_rate),
-            u128::from(FEE_RATE_DENOMINATOR_VALUE),
-        )
+    pub const fn protocol_fee(amount: u128, protocol_fee_rate: u64) -> Option<u128> {
+        FeeRate(protocol_fee_rate).mul_floor(amount)
     }
-    pub fn calculate_pre_fee_amount(post_fee_amount: u128, trade_fee_rate: u64) -> Option<u128> {
-        if trade_fee_rate == 0 {
-            Some(post_fee_amount)
-        } else {
-            let numerator = post_fee_amount.checked_mul(u128::from(FEE_RATE_DENOMINATOR_VALUE))?;
-            let denominator =
-                u128::from(FEE_RATE_DENOMINATOR_VALUE).checked_sub(u128::from(trade_fee_rate))?;
-
-            numerator
-                .checked_add(denominator)?
-                .checked_sub(1)?
-                .checked_div(denominator)
+    /// Smallest input that leaves something to swap after the trade fee.
+    ///
+    /// Returns `None` if the rate is 100% or more.
+    pub const fn minimum_input(trade_fee_rate: u64) -> Option<u128> {
+        if trade_fee_rate >= FEE_RATE_DENOMINATOR_VALUE {
+            return None;
         }
+        FeeRate(trade_fee_rate).gross_up(1)
     }
 
+    /// Amount before a trade fee at `trade_fee_rate` that leaves
+    /// `post_fee_amount`, rounded up.
+    ///
+    /// Returns `None` if the rate is 100% or more.
+    pub const fn calculate_pre_fee_amount(
+        post_fee_amount: u128,
+        trade_fee_rate: u64,
+    ) -> Option<u128> {
+        FeeRate(trade_fee_rate).gross_up(post_fee_amount)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::utils::{U256, price::to_u128},
+        proptest::prelude::*,
+    };
+
+    // Evaluated by the compiler, so any regression in const-ness fails the build
+    const TRADE_FEE: Option<u128> = Fees::trading_fee(1_000_000, 2_500);
+    const PROTOCOL_FEE: Option<u128> = Fees::protocol_fee(2_500, 120_000);
+    const PRE_FEE_AMOUNT: Option<u128> = Fees::calculate_pre_fee_amount(997_500, 2_500);
+
+    #[test]
+    fn fees_evaluate_at_compile_time() {
+        assert_eq!(TRADE_FEE, Some(2_500));
+        assert_eq!(PROTOCOL_FEE, Some(300));
+        assert_eq!(PRE_FEE_AMOUNT, Some(1_000_000));
+        assert_eq!(TRADE_FEE, Fees::trading_fee(1_000_000, 2_500));
+        // rounds up for any remainder
+        assert_eq!(Fees::trading_fee(1, 1), Some(1));
+        assert_eq!(floor_div(7, 1, 2), Some(3));
+        assert_eq!(floor_div(u128::MAX, 2, 1), None);
+    }
+
+    #[test]
+    fn minimum_input_leaves_something_to_swap() {
+        for trade_fee_rate in [0, 1, 2_500, 500_000, 999_999] {
+            let minimum = Fees::minimum_input(trade_fee_rate).unwrap();
+            let fee = |amount| Fees::trading_fee(amount, trade_fee_rate).unwrap();
+            assert!(fee(minimum) < minimum);
+            assert_eq!(fee(minimum - 1), minimum - 1);
+        }
+        assert_eq!(Fees::minimum_input(2_500), Some(2));
+        assert_eq!(Fees::minimum_input(999_999), Some(1_000_000));
+        assert_eq!(Fees::minimum_input(FEE_RATE_DENOMINATOR_VALUE), None);
+    }
+
+    #[test]
+    fn fee_rate_constructors() {
+        assert_eq!(FeeRate::from_bps(25), Some(FeeRate(2_500)));
+        assert_eq!(FeeRate::from_percent(12), Some(FeeRate(120_000)));
+        assert_eq!(FeeRate::from_bps(10_000), Some(FeeRate::MAX));
+        assert_eq!(FeeRate::from_bps(10_001), None);
+        assert_eq!(FeeRate::from_percent(101), None);
+        assert_eq!(FeeRate::checked(FEE_RATE_DENOMINATOR_VALUE + 1), None);
+        assert_eq!(FeeRate::checked(7).map(FeeRate::get), Some(7));
+    }
+
+    #[test]
+    fn decoded_fee_rates_are_checked() {
+        let bytes = borsh::to_vec(&FeeRate::MAX).unwrap();
+        assert_eq!(FeeRate::try_from_slice(&bytes).unwrap(), FeeRate::MAX);
+        let bytes = (FEE_RATE_DENOMINATOR_VALUE + 1).to_le_bytes();
+        assert!(FeeRate::try_from_slice(&bytes).is_err());
+        let mut bytes = borsh::to_vec(&FeeConfig::STANDARD_25BPS).unwrap();
+        bytes[..8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(FeeConfig::try_from_slice(&bytes).is_err());
+    }
+
+    #[test]
+    fn fee_rate_arithmetic() {
+        let rate = FeeRate::from_bps(25).unwrap();
+        assert_eq!(rate.mul_ceil(1_001), Some(3));
+        assert_eq!(rate.mul_floor(1_001), Some(2));
+        let gross = rate.gross_up(997_500).unwrap();
+        assert_eq!(gross, 1_000_000);
+        assert_eq!(gross - rate.mul_ceil(gross).unwrap(), 997_500);
+        assert_eq!(FeeRate::ZERO.gross_up(u128::MAX), Some(u128::MAX));
+        assert_eq!(FeeRate::MAX.mul_floor(5), Some(5));
+    }
+
+    #[test]
+    fn full_fee_has_no_pre_fee_amount() {
+        for trade_fee_rate in [
+            FEE_RATE_DENOMINATOR_VALUE,
+            FEE_RATE_DENOMINATOR_VALUE + 1,
+            u64::MAX,
+        ] {
+            for post_fee_amount in [0, 1, 1_000_000, u128::MAX] {
+                assert_eq!(
+                    Fees::calculate_pre_fee_amount(post_fee_amount, trade_fee_rate),
+                    None
+                );
+            }
+        }
+        assert_eq!(FeeRate::MAX.gross_up(0), None);
+        // just below 100% the gross-up is large but defined
+        assert_eq!(
+            Fees::calculate_pre_fee_amount(1, FEE_RATE_DENOMINATOR_VALUE - 1),
+            Some(1_000_000)
+        );
+    }
+
+    #[test]
+    fn pool_fee_configs_cap_the_trade_fee() {
+        assert_eq!(MAX_TRADE_FEE_RATE, FeeRate::from_percent(10).unwrap());
+        assert!(FeeConfig::checked(MAX_TRADE_FEE_RATE, FeeRate::MAX).is_some());
+        let too_high = FeeRate::checked(MAX_TRADE_FEE_RATE.get() + 1).unwrap();
+        assert_eq!(FeeConfig::checked(too_high, FeeRate::ZERO), None);
+        assert_eq!(FeeConfig::checked(FeeRate::MAX, FeeRate::ZERO), None);
+        assert!(!FeeConfig::new(FeeRate::MAX, FeeRate::ZERO).is_valid());
+        assert!(FeeConfig::default().is_valid());
+    }
+
+    #[test]
+    fn presets_are_canonical() {
+        assert_eq!(
+            FeeConfig::STABLE_1BPS,
+            FeeConfig::new(
+                FeeRate::from_bps(1).unwrap(),
+                FeeRate::from_percent(12).unwrap()
+            )
+        );
+        assert_eq!(
+            FeeConfig::STANDARD_25BPS.trade_fee_rate,
+            FeeRate::from_bps(25).unwrap()
+        );
+        assert_eq!(
+            FeeConfig::VOLATILE_100BPS.trade_fee_rate,
+            FeeRate::from_bps(100).unwrap()
+        );
+        for preset in Preset::ALL {
+            let fee_config = FeeConfig::preset(preset);
+            assert!(fee_config.is_valid());
+            assert_eq!(fee_config.protocol_fee_rate, DEFAULT_PROTOCOL_FEE_RATE);
+            assert_eq!(fee_config.protocol_fee_mode, ProtocolFeeMode::Input);
+            assert_eq!(fee_config.trade_fee_side, TradeFeeSide::Input);
+        }
+        assert_eq!(
+            FeeConfig::preset(Preset::Standard),
+            FeeConfig::STANDARD_25BPS
+        );
+    }
+
+    #[test]
+    fn fee_math_at_the_u128_boundary() {
+        // results that fit are exact even when `amount * rate` does not
+        assert_eq!(FeeRate::MAX.mul_ceil(u128::MAX), Some(u128::MAX));
+        assert_eq!(FeeRate::MAX.mul_floor(u128::MAX), Some(u128::MAX));
+        let half = FeeRate::from_percent(50).unwrap();
+        assert_eq!(half.mul_ceil(u128::MAX), Some(u128::MAX / 2 + 1));
+        assert_eq!(half.mul_floor(u128::MAX), Some(u128::MAX / 2));
+        assert_eq!(FeeRate::ZERO.mul_ceil(u128::MAX), Some(0));
+        assert_eq!(half.gross_up(u128::MAX / 2), Some(u128::MAX - 1));
+        // results past u128::MAX fail instead of panicking
+        assert_eq!(half.gross_up(u128::MAX / 2 + 1), None);
+        assert_eq!(FeeRate::MAX.gross_up(1), None);
+        let double = 2 * FEE_RATE_DENOMINATOR_VALUE;
+        assert_eq!(
+            Fees::trading_fee(u128::MAX / 2, double),
+            Some(u128::MAX - 1)
+        );
+        assert_eq!(Fees::trading_fee(u128::MAX / 2 + 1, double), None);
+        assert_eq!(ceil_div(u128::MAX, u128::MAX, u128::MAX), Some(u128::MAX));
+        assert_eq!(ceil_div(u128::MAX, 1, 0), None);
+        assert_eq!(floor_div(u128::MAX, 1, 0), None);
+    }
+
+    proptest! {
+        #[test]
+        fn fee_math_matches_wide_arithmetic(
+            amount in any::<u128>(),
+            rate in 0..=2 * FEE_RATE_DENOMINATOR_VALUE,
+        ) {
+            let product = U256::from(amount) * U256::from(rate);
+            let denominator = U256::from(FEE_RATE_DENOMINATOR_VALUE);
+            let (quotient, remainder) = product.div_mod(denominator);
+            let ceiling = if remainder.is_zero() { quotient } else { quotient + 1 };
+            prop_assert_eq!(Fees::trading_fee(amount, rate), to_u128(ceiling));
+            prop_assert_eq!(Fees::protocol_fee(amount, rate), to_u128(quotient));
+            if let Some(rate) = FeeRate::checked(rate).filter(|rate| *rate != FeeRate::MAX) {
+                let net = U256::from(FEE_RATE_DENOMINATOR_VALUE - rate.get());
+                let (quotient, remainder) = (U256::from(amount) * denominator).div_mod(net);
+                let gross = if remainder.is_zero() { quotient } else { quotient + 1 };
+                prop_assert_eq!(rate.gross_up(amount), to_u128(gross));
+            }
+        }
+    }
 }
@@ -0,0 +1,262 @@
+//! JSON regression fixtures
+//!
+//! Audited vectors and bug regressions kept as data in `fixtures/*.json`
+//! rather than as hand-written asserts. Each file holds a list of cases:
+//!
+//! ```json
+//! {
+//!   "cases": [
+//!     {
+//!       "name": "exact-in with fees",
+//!       "kind": "swap_base_input",
+//!       "input": { "source_amount": "1000", "swap_source_amount": "50000", ... },
+//!       "expected": { "destination_amount_swapped": "1564", ... }
+//!     },
+//!     { "name": "...", "kind": "withdraw", "input": { ... }, "error": "ZeroSupply" }
+//!   ]
+//! }
+//! ```
+//!
+//! `kind` is `swap_base_input`, `swap_base_output`, `deposit` or `withdraw`,
+//! run through the calculator's `try_*` functions. Amounts are decimal
+//! strings, or plain numbers below 2^53. `expected` lists any subset of the
+//! result's fields; `error` the `Debug` form of the expected `CurveError`.
+use {
+    crate::curve::{
+        calculator::{CurveCalculator, RoundDirection, SwapResult, TradingTokenResult},
+        fees::TinySwapPolicy,
+        swap_params::SwapParams,
+    },
+    serde_json::{Map, Value},
+    std::{fs, path::Path},
+};
+
+/// A fixture case, loaded from JSON
+#[derive(Debug)]
+pub struct Case {
+    pub name: String,
+    pub kind: String,
+    pub input: Map<String, Value>,
+    pub expected: Outcome,
+}
+
+/// What a case expects the calculator to return
+#[derive(Debug)]
+pub enum Outcome {
+    /// These result fields, by name
+    Fields(Map<String, Value>),
+    /// This error, in its `Debug` form
+    Error(String),
+}
+
+/// Load the cases of every `.json` file in `directory`, in file name order
+pub fn load(directory: &Path) -> Vec<Case> {
+    let mut paths: Vec<_> = fs::read_dir(directory)
+        .unwrap_or_else(|err| panic!("cannot read {}: {err}", directory.display()))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .collect();
+    paths.sort();
+    paths.iter().flat_map(|path| load_file(path)).collect()
+}
+
+fn load_file(path: &Path) -> Vec<Case> {
+    let text = fs::read_to_string(path).unwrap();
+    let fixture: Value = serde_json::from_str(&text)
+        .unwrap_or_else(|err| panic!("invalid JSON in {}: {err}", path.display()));
+    let file = path.file_name().unwrap().to_string_lossy();
+    fixture["cases"]
+        .as_array()
+        .unwrap_or_else(|| panic!("{file} has no `cases` array"))
+        .iter()
+        .map(|case| {
+            let name = format!("{file}: {}", string(&case["name"]));
+            let expected = match (&case["expected"], &case["error"]) {
+                (Value::Object(fields), Value::Null) => Outcome::Fields(fields.clone()),
+                (Value::Null, Value::String(error)) => Outcome::Error(error.clone()),
+                _ => panic!("{name} needs exactly one of `expected` and `error`"),
+            };
+            Case {
+                kind: string(&case["kind"]),
+                input: case["input"]
+                    .as_object()
+                    .unwrap_or_else(|| panic!("{name} has no `input` object"))
+                    .clone(),
+                expected,
+                name,
+            }
+        })
+        .collect()
+}
+
+fn string(value: &Value) -> String {
+    value.as_str().unwrap_or_default().into()
+}
+
+fn amount(value: &Value) -> Option<u128> {
+    match value {
+        Value::String(amount) => amount.parse().ok(),
+        Value::Number(amount) => amount.as_u64().map(u128::from),
+        _ => None,
+    }
+}
+
+/// Run `case` against the calculator, describing the first mismatch
+pub fn run(case: &Case) -> Result<(), String> {
+    let input = |field: &str| {
+        case.input
+            .get(field)
+            .and_then(amount)
+            .ok_or_else(|| format!("missing or invalid input `{field}`"))
+    };
+    let fee_rate = |field: &str| {
+        u64::try_from(input(field)?).map_err(|_| format!("input `{field}` exceeds u64"))
+    };
+    let swap_params = |amount_field: &str| {
+        SwapParams::from_raw_parts(
+            input(amount_field)?,
+            input("swap_source_amount")?,
+            input("swap_destination_amount")?,
+            fee_rate("trade_fee_rate")?,
+            fee_rate("protocol_fee_rate")?,
+        )
+        .ok_or_else(|| "fee rates exceed 100%".to_string())
+    };
+    let result = match case.kind.as_str() {
+        "swap_base_input" => {
+            let tiny_swap_policy = match case.input.get("tiny_swap_policy").map(string) {
+                None => TinySwapPolicy::Clamp,
+                Some(policy) if policy == "Clamp" => TinySwapPolicy::Clamp,
+                Some(policy) if policy == "Reject" => TinySwapPolicy::Reject,
+                Some(policy) => return Err(format!("unknown tiny swap policy `{policy}`")),
+            };
+            CurveCalculator::try_swap_base_input(&swap_params("source_amount")?, tiny_swap_policy)
+                .map(|result| swap_fields(&result))
+        }
+        "swap_base_output" => {
+            CurveCalculator::try_swap_base_output(&swap_params("destination_amount")?)
+                .map(|result| swap_fields(&result))
+        }
+        kind @ ("deposit" | "withdraw") => CurveCalculator::try_lp_tokens_to_trading_tokens(
+            input("lp_token_amount")?,
+            input("lp_token_supply")?,
+            input("swap_token_0_amount")?,
+            input("swap_token_1_amount")?,
+            if kind == "deposit" {
+                RoundDirection::Ceiling
+            } else {
+                RoundDirection::Floor
+            },
+        )
+        .map(|result| trading_token_fields(&result)),
+        kind => return Err(format!("unknown kind `{kind}`")),
+    };
+    match (&case.expected, result) {
+        (Outcome::Fields(expected), Ok(fields)) => {
+            for (field, value) in expected {
+                let actual = fields
+                    .iter()
+                    .find(|(name, _)| name == field)
+                    .ok_or_else(|| format!("unknown result field `{field}`"))?
+                    .1;
+                let value = amount(value).ok_or_else(|| format!("invalid `{field}`"))?;
+                if actual != value {
+                    return Err(format!("`{field}` is {actual}, expected {value}"));
+                }
+            }
+            Ok(())
+        }
+        (Outcome::Fields(_), Err(error)) => Err(format!("failed with {error:?}")),
+        (Outcome::Error(expected), Ok(_)) => Err(format!("succeeded, expected {expected}")),
+        (Outcome::Error(expected), Err(error)) => {
+            let error = format!("{error:?}");
+            if error == *expected {
+                Ok(())
+            } else {
+                Err(format!("failed with {error}, expected {expected}"))
+            }
+        }
+    }
+}
+
+fn swap_fields(result: &SwapResult) -> Vec<(&'static str, u128)> {
+    vec![
+        ("new_swap_source_amount", result.new_swap_source_amount),
+        (
+            "new_swap_destination_amount",
+            result.new_swap_destination_amount,
+        ),
+        ("source_amount_swapped", result.source_amount_swapped),
+        (
+            "destination_amount_swapped",
+            result.destination_amount_swapped,
+        ),
+        ("trade_fee", result.trade_fee),
+        ("protocol_fee", result.protocol_fee),
+        ("destination_trade_fee", result.destination_trade_fee),
+        ("destination_protocol_fee", result.destination_protocol_fee),
+        ("rounding_dust", result.rounding_dust),
+    ]
+}
+
+fn trading_token_fields(result: &TradingTokenResult) -> Vec<(&'static str, u128)> {
+    vec![
+        ("token_0_amount", result.token_0_amount),
+        ("token_1_amount", result.token_1_amount),
+        ("token_0_rounding_dust", result.token_0_rounding_dust),
+        ("token_1_rounding_dust", result.token_1_rounding_dust),
+    ]
+}
+
+#[test]
+fn regression_fixtures() {
+    let cases = load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures"));
+    assert!(!cases.is_empty());
+    let failures: Vec<_> = cases
+        .iter()
+        .filter_map(|case| {
+            run(case)
+                .err()
+                .map(|error| format!("{}: {error}", case.name))
+        })
+        .collect();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn mismatches_are_reported() {
+    let case = |expected| Case {
+        name: "case".into(),
+        kind: "withdraw".into(),
+        input: serde_json::json!({
+            "lp_token_amount": 5,
+            "lp_token_supply": "10",
+            "swap_token_0_amount": 2,
+            "swap_token_1_amount": 49,
+        })
+        .as_object()
+        .unwrap()
+        .clone(),
+        expected,
+    };
+    let fields = |fields: Value| Outcome::Fields(fields.as_object().unwrap().clone());
+    assert_eq!(
+        run(&case(fields(serde_json::json!({ "token_1_amount": 24 })))),
+        Ok(())
+    );
+    assert_eq!(
+        run(&case(fields(serde_json::json!({ "token_1_amount": "25" })))),
+        Err("`token_1_amount` is 24, expected 25".into())
+    );
+    assert_eq!(
+        run(&case(fields(serde_json::json!({ "token_2_amount": 0 })))),
+        Err("unknown result field `token_2_amount`".into())
+    );
+    assert_eq!(
+        run(&case(Outcome::Error("ZeroSupply".into()))),
+        Err("succeeded, expected ZeroSupply".into())
+    );
+}
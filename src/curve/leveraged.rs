@@ -0,0 +1,509 @@
+//! Leveraged constant product curve
+//!
+//! Prices trades on the constant product of virtual reserves, each a
+//! multiple of the real reserve when the pool is created. Trades move real
+//! and virtual reserves by the same amounts, so the virtual reserves exceed
+//! the real ones by fixed offsets and liquidity is concentrated around the
+//! initial price without ticks. The price range ends where a real reserve
+//! runs out; swaps past that point fail.
+//!
+//! Deposits and withdrawals scale real and virtual reserves by the same
+//! fraction, preserving the price. Withdrawals pay out of the real reserves
+//! only, so they can never take more than the pool holds.
+use crate::curve::{
+    amount::{LpAmount, Reserves, Token0Amount, Token1Amount},
+    calculator::{CurveCalculator, RoundDirection, SwapResult, TradeDirection, TradingTokenResult},
+    fees::FeeConfig,
+};
+
+/// Denominator of `LeveragedReserves::new`'s leverage
+pub const LEVERAGE_BPS_DENOMINATOR: u32 = 10_000;
+
+/// Real and virtual reserves of a leveraged constant product pool
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LeveragedReserves {
+    /// Tokens backing the LP supply, excluding protocol fees
+    pub real: Reserves,
+    /// Reserves the constant product is taken over, never below `real`
+    pub virtual_reserves: Reserves,
+}
+
+impl LeveragedReserves {
+    /// Reserves whose virtual amounts are the real ones boosted by
+    /// `leverage_bps / LEVERAGE_BPS_DENOMINATOR`, rounded down.
+    ///
+    /// Returns `None` if the leverage is below 1 or a virtual reserve
+    /// overflows.
+    pub fn new(real: Reserves, leverage_bps: u32) -> Option<Self> {
+        if leverage_bps < LEVERAGE_BPS_DENOMINATOR {
+            return None;
+        }
+        let boost = |amount: u128| {
+            amount
+                .checked_mul(u128::from(leverage_bps))
+                .map(|amount| amount / u128::from(LEVERAGE_BPS_DENOMINATOR))
+        };
+        Some(Self {
+            real,
+            virtual_reserves: Reserves::new(
+                Token0Amount(boost(real.token_0.get())?),
+                Token1Amount(boost(real.token_1.get())?),
+            ),
+        })
+    }
+
+    /// Reserves whose virtual amounts exceed `real` by `offsets`, as a pool
+    /// created by `new` does after any trades.
+    ///
+    /// Returns `None` if a virtual reserve overflows.
+    pub fn from_offsets(real: Reserves, offsets: Reserves) -> Option<Self> {
+        Some(Self {
+            real,
+            virtual_reserves: Reserves::new(
+                real.token_0.checked_add(offsets.token_0)?,
+                real.token_1.checked_add(offsets.token_1)?,
+            ),
+        })
+    }
+
+    /// Amounts by which the virtual reserves exceed the real ones. Swaps
+    /// keep them fixed; deposits and withdrawals scale them with the pool.
+    ///
+    /// Returns `None` if a virtual reserve is below the real one.
+    pub fn offsets(&self) -> Option<Reserves> {
+        Some(Reserves::new(
+            self.virtual_reserves
+                .token_0
+                .checked_sub(self.real.token_0)?,
+            self.virtual_reserves
+                .token_1
+                .checked_sub(self.real.token_1)?,
+        ))
+    }
+
+    /// Quote a swap of exactly `source_amount` in `trade_direction`.
+    ///
+    /// The result's new reserves are real reserves. Returns `None` if the
+    /// trade would pay out more than the real destination reserve.
+    pub fn swap_exact_in(
+        &self,
+        trade_direction: TradeDirection,
+        source_amount: u128,
+        fee_config: FeeConfig,
+    ) -> Option<SwapResult> {
+        let result = CurveCalculator::swap_exact_in(
+            trade_direction,
+            source_amount,
+            self.virtual_reserves,
+            fee_config,
+        )?;
+        self.real_quote(trade_direction, result)
+    }
+
+    /// Quote a swap receiving exactly `destination_amount` in
+    /// `trade_direction`.
+    ///
+    /// The result's new reserves are real reserves. Returns `None` if
+    /// `destination_amount` exceeds the real destination reserve.
+    pub fn swap_exact_out(
+        &self,
+        trade_direction: TradeDirection,
+        destination_amount: u128,
+        fee_config: FeeConfig,
+    ) -> Option<SwapResult> {
+        let (_, real_destination) = self.real.source_and_destination(trade_direction);
+        let (_, virtual_destination) = self
+            .virtual_reserves
+            .source_and_destination(trade_direction);
+        // the curve cannot pay out its whole virtual reserve
+        if destination_amount > real_destination || destination_amount >= virtual_destination {
+            return None;
+        }
+        let result = CurveCalculator::swap_exact_out(
+            trade_direction,
+            destination_amount,
+            self.virtual_reserves,
+            fee_config,
+        )?;
+        self.real_quote(trade_direction, result)
+    }
+
+    // Replace the virtual reserves in a quote with real ones, which, like the
+    // constant product curve's, exclude the destination protocol fee
+    fn real_quote(
+        &self,
+        trade_direction: TradeDirection,
+        result: SwapResult,
+    ) -> Option<SwapResult> {
+        let (real_source, real_destination) = self.real.source_and_destination(trade_direction);
+        Some(SwapResult {
+            new_swap_source_amount: real_source.checked_add(result.source_amount_swapped)?,
+            new_swap_destination_amount: real_destination
+                .checked_sub(result.destination_amount_swapped)?
+                .checked_sub(result.destination_protocol_fee)?,
+            ..result
+        })
+    }
+
+    /// Settle a swap quoted by `swap_exact_in` or `swap_exact_out`, moving
+    /// real and virtual reserves by the same amounts. The protocol fee is
+    /// left out of the reserves, in whichever token it accrues.
+    pub fn apply_swap(
+        &mut self,
+        trade_direction: TradeDirection,
+        result: &SwapResult,
+    ) -> Option<()> {
+        let added = result
+            .source_amount_swapped
+            .checked_sub(result.protocol_fee)?;
+        let removed = result
+            .destination_amount_swapped
+            .checked_add(result.destination_protocol_fee)?;
+        let (real_source, real_destination) = self.real.source_and_destination(trade_direction);
+        let (virtual_source, virtual_destination) = self
+            .virtual_reserves
+            .source_and_destination(trade_direction);
+        let real = Reserves::from_source_and_destination(
+            trade_direction,
+            real_source.checked_add(added)?,
+            real_destination.checked_sub(removed)?,
+        );
+        self.virtual_reserves = Reserves::from_source_and_destination(
+            trade_direction,
+            virtual_source.checked_add(added)?,
+            virtual_destination.checked_sub(removed)?,
+        );
+        self.real = real;
+        Some(())
+    }
+
+    /// Take the deposit for `lp_amount` new LP tokens out of `lp_supply`,
+    /// rounded up in the pool's favor.
+    ///
+    /// Returns `None`, leaving the pool unchanged, if a real or virtual
+    /// reserve overflows.
+    pub fn deposit(
+        &mut self,
+        lp_amount: LpAmount,
+        lp_supply: LpAmount,
+    ) -> Option<TradingTokenResult> {
+        let real = Self::share(lp_amount, lp_supply, self.real, RoundDirection::Ceiling)?;
+        let boost = Self::share(
+            lp_amount,
+            lp_supply,
+            self.virtual_reserves,
+            RoundDirection::Ceiling,
+        )?;
+        let real_reserves = Reserves::new(
+            self.real
+                .token_0
+                .checked_add(Token0Amount(real.token_0_amount))?,
+            self.real
+                .token_1
+                .checked_add(Token1Amount(real.token_1_amount))?,
+        );
+        let virtual_reserves = Reserves::new(
+            self.virtual_reserves
+                .token_0
+                .checked_add(Token0Amount(boost.token_0_amount))?,
+            self.virtual_reserves
+                .token_1
+                .checked_add(Token1Amount(boost.token_1_amount))?,
+        );
+        self.real = real_reserves;
+        self.virtual_reserves = virtual_reserves;
+        Some(real)
+    }
+
+    /// Pay out the share of the real reserves for `lp_amount` LP tokens out
+    /// of `lp_supply`, rounded down in the pool's favor.
+    ///
+    /// The virtual reserves shrink by the same fraction, also rounded down;
+    /// since they are never below the real ones, they stay so. Returns
+    /// `None`, leaving the pool unchanged, if `lp_amount` exceeds
+    /// `lp_supply`.
+    pub fn withdraw(
+        &mut self,
+        lp_amount: LpAmount,
+        lp_supply: LpAmount,
+    ) -> Option<TradingTokenResult> {
+        if lp_amount > lp_supply {
+            return None;
+        }
+        let real = Self::share(lp_amount, lp_supply, self.real, RoundDirection::Floor)?;
+        let boost = Self::share(
+            lp_amount,
+            lp_supply,
+            self.virtual_reserves,
+            RoundDirection::Floor,
+        )?;
+        let real_reserves = Reserves::new(
+            self.real
+                .token_0
+                .checked_sub(Token0Amount(real.token_0_amount))?,
+            self.real
+                .token_1
+                .checked_sub(Token1Amount(real.token_1_amount))?,
+        );
+        let virtual_reserves = Reserves::new(
+            self.virtual_reserves
+                .token_0
+                .checked_sub(Token0Amount(boost.token_0_amount))?,
+            self.virtual_reserves
+                .token_1
+                .checked_sub(Token1Amount(boost.token_1_amount))?,
+        );
+        self.real = real_reserves;
+        self.virtual_reserves = virtual_reserves;
+        Some(real)
+    }
+
+    fn share(
+        lp_amount: LpAmount,
+        lp_supply: LpAmount,
+        reserves: Reserves,
+        round_direction: RoundDirection,
+    ) -> Option<TradingTokenResult> {
+        CurveCalculator::lp_tokens_to_trading_tokens(
+            lp_amount.get(),
+            lp_supply.get(),
+            reserves.token_0.get(),
+            reserves.token_1.get(),
+            round_direction,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::curve::{
+            calculator::CurveCalculator,
+            fees::{FeeRate, ProtocolFeeMode, TradeFeeSide},
+        },
+        proptest::prelude::*,
+    };
+
+    fn fee_config() -> FeeConfig {
+        FeeConfig::new(
+            FeeRate::from_bps(30).unwrap(),
+            FeeRate::from_percent(20).unwrap(),
+        )
+    }
+
+    #[test]
+    fn leverage_must_be_at_least_one() {
+        assert_eq!(
+            LeveragedReserves::new(Reserves::from_raw(1, 1), 9_999),
+            None
+        );
+        let pool = LeveragedReserves::new(Reserves::from_raw(1_000, 3_000), 25_000).unwrap();
+        assert_eq!(pool.virtual_reserves, Reserves::from_raw(2_500, 7_500));
+    }
+
+    #[test]
+    fn pools_rebuild_from_their_offsets() {
+        let mut pool = LeveragedReserves::new(Reserves::from_raw(1_000, 3_000), 25_000).unwrap();
+        let offsets = pool.offsets().unwrap();
+        assert_eq!(offsets, Reserves::from_raw(1_500, 4_500));
+        let result = pool
+            .swap_exact_in(TradeDirection::ZeroForOne, 100, fee_config())
+            .unwrap();
+        pool.apply_swap(TradeDirection::ZeroForOne, &result)
+            .unwrap();
+        // swaps keep the offsets, so they and the real reserves are the pool
+        assert_eq!(pool.offsets(), Some(offsets));
+        assert_eq!(
+            LeveragedReserves::from_offsets(pool.real, offsets),
+            Some(pool)
+        );
+    }
+
+    #[test]
+    fn leverage_deepens_liquidity() {
+        let real = Reserves::from_raw(1_000_000, 1_000_000);
+        let plain =
+            CurveCalculator::swap_exact_in(TradeDirection::ZeroForOne, 100_000, real, fee_config())
+                .unwrap();
+        let leveraged = LeveragedReserves::new(real, 100_000)
+            .unwrap()
+            .swap_exact_in(TradeDirection::ZeroForOne, 100_000, fee_config())
+            .unwrap();
+        assert!(leveraged.destination_amount_swapped > plain.destination_amount_swapped);
+        assert_eq!(
+            leveraged.new_swap_destination_amount,
+            1_000_000 - leveraged.destination_amount_swapped
+        );
+    }
+
+    #[test]
+    fn unleveraged_pools_match_the_constant_product_curve() {
+        let real = Reserves::from_raw(1_000_000, 1_000_000);
+        let fee_config = FeeConfig::new(
+            FeeRate::from_bps(30).unwrap(),
+            FeeRate::from_percent(50).unwrap(),
+        );
+        for fee_config in [
+            fee_config,
+            fee_config.with_trade_fee_side(TradeFeeSide::Output),
+            fee_config.with_protocol_fee_mode(ProtocolFeeMode::Split),
+        ] {
+            let direction = TradeDirection::ZeroForOne;
+            let pool = LeveragedReserves::new(real, LEVERAGE_BPS_DENOMINATOR).unwrap();
+            for (leveraged, plain) in [
+                (
+                    pool.swap_exact_in(direction, 100_000, fee_config),
+                    CurveCalculator::swap_exact_in(direction, 100_000, real, fee_config),
+                ),
+                (
+                    pool.swap_exact_out(direction, 100_000, fee_config),
+                    CurveCalculator::swap_exact_out(direction, 100_000, real, fee_config),
+                ),
+            ] {
+                let (leveraged, plain) = (leveraged.unwrap(), plain.unwrap());
+                assert_eq!(leveraged, plain);
+                // settling leaves the protocol fees out of the reserves
+                let mut settled = pool;
+                settled.apply_swap(direction, &leveraged).unwrap();
+                let expected = Reserves::from_raw(
+                    plain.new_swap_source_amount - plain.protocol_fee,
+                    plain.new_swap_destination_amount,
+                );
+                assert_eq!(settled.real, expected);
+                assert_eq!(settled.virtual_reserves, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn swaps_stop_at_the_real_reserve() {
+        let pool = LeveragedReserves::new(Reserves::from_raw(1_000, 1_000), 100_000).unwrap();
+        // the virtual curve has 10_000 token 1 to give, the pool only 1_000
+        assert_eq!(
+            pool.swap_exact_in(TradeDirection::ZeroForOne, 5_000, fee_config()),
+            None
+        );
+        assert_eq!(
+            pool.swap_exact_out(TradeDirection::ZeroForOne, 1_001, fee_config()),
+            None
+        );
+        let mut drained = pool;
+        let result = pool
+            .swap_exact_out(TradeDirection::ZeroForOne, 1_000, fee_config())
+            .unwrap();
+        drained
+            .apply_swap(TradeDirection::ZeroForOne, &result)
+            .unwrap();
+        assert_eq!(drained.real.token_1, Token1Amount(0));
+        assert_eq!(drained.virtual_reserves.token_1, Token1Amount(9_000));
+    }
+
+    #[test]
+    fn withdrawing_everything_empties_the_pool() {
+        let mut pool = LeveragedReserves::new(Reserves::from_raw(1_000, 2_000), 30_000).unwrap();
+        let supply = LpAmount(500);
+        let result = pool.withdraw(LpAmount(500), supply).unwrap();
+        assert_eq!(
+            (result.token_0_amount, result.token_1_amount),
+            (1_000, 2_000)
+        );
+        assert_eq!(pool, LeveragedReserves::default());
+        assert_eq!(pool.withdraw(LpAmount(2), LpAmount(1)), None);
+    }
+
+    #[test]
+    fn failed_deposits_leave_the_pool_unchanged() {
+        // the real reserves can grow, but not the virtual ones
+        let mut pool = LeveragedReserves::from_offsets(
+            Reserves::from_raw(1_000, 2_000),
+            Reserves::from_raw(u128::MAX / 2, 0),
+        )
+        .unwrap();
+        let before = pool;
+        assert_eq!(pool.deposit(LpAmount(1), LpAmount(1)), None);
+        assert_eq!(pool, before);
+    }
+
+    #[derive(Clone, Debug)]
+    enum Step {
+        Swap(bool, u128),
+        Deposit(u128),
+        Withdraw(u128),
+    }
+
+    fn step() -> impl Strategy<Value = Step> {
+        prop_oneof![
+            (any::<bool>(), 1..10_000_000u128)
+                .prop_map(|(zero_for_one, amount)| Step::Swap(zero_for_one, amount)),
+            (1..1_000u128).prop_map(Step::Deposit),
+            (1..1_000u128).prop_map(Step::Withdraw),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn real_reserves_are_never_overdrawn(
+            token_0 in 1_000..1_000_000_000u128,
+            token_1 in 1_000..1_000_000_000u128,
+            leverage_bps in 10_000..1_000_000u32,
+            split in any::<bool>(),
+            steps in proptest::collection::vec(step(), 1..40),
+        ) {
+            let fee_config = if split {
+                fee_config().with_protocol_fee_mode(ProtocolFeeMode::Split)
+            } else {
+                fee_config()
+            };
+            let mut pool = LeveragedReserves::new(Reserves::from_raw(token_0, token_1), leverage_bps).unwrap();
+            let mut lp_supply = 1_000u128;
+            // tokens in and out of the pool, excluding protocol fees
+            let (mut held_0, mut held_1) = (token_0, token_1);
+            for step in steps {
+                match step {
+                    Step::Swap(zero_for_one, amount) => {
+                        let direction = if zero_for_one {
+                            TradeDirection::ZeroForOne
+                        } else {
+                            TradeDirection::OneForZero
+                        };
+                        let before = CurveCalculator::pool_value(pool.virtual_reserves).invariant;
+                        let Some(result) = pool.swap_exact_in(direction, amount, fee_config) else {
+                            continue;
+                        };
+                        pool.apply_swap(direction, &result).unwrap();
+                        prop_assert!(CurveCalculator::pool_value(pool.virtual_reserves).invariant >= before);
+                        let added = result.source_amount_swapped - result.protocol_fee;
+                        let removed = result.destination_amount_swapped + result.destination_protocol_fee;
+                        if zero_for_one {
+                            held_0 += added;
+                            held_1 = held_1.checked_sub(removed).unwrap();
+                        } else {
+                            held_1 += added;
+                            held_0 = held_0.checked_sub(removed).unwrap();
+                        }
+                    }
+                    Step::Deposit(lp_amount) => {
+                        let result = pool.deposit(LpAmount(lp_amount), LpAmount(lp_supply)).unwrap();
+                        lp_supply += lp_amount;
+                        held_0 += result.token_0_amount;
+                        held_1 += result.token_1_amount;
+                    }
+                    Step::Withdraw(lp_amount) => {
+                        let lp_amount = lp_amount.min(lp_supply);
+                        let result = pool.withdraw(LpAmount(lp_amount), LpAmount(lp_supply)).unwrap();
+                        lp_supply -= lp_amount;
+                        held_0 = held_0.checked_sub(result.token_0_amount).unwrap();
+                        held_1 = held_1.checked_sub(result.token_1_amount).unwrap();
+                        if lp_supply == 0 {
+                            break;
+                        }
+                    }
+                }
+                prop_assert_eq!(pool.real, Reserves::from_raw(held_0, held_1));
+                prop_assert!(pool.virtual_reserves.token_0 >= pool.real.token_0);
+                prop_assert!(pool.virtual_reserves.token_1 >= pool.real.token_1);
+            }
+        }
+    }
+}
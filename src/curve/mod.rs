@@ -1,7 +1,59 @@
+pub mod amount;
+pub mod buyback;
 pub mod calculator;
+pub mod compat;
 pub mod constant_product;
+pub mod context;
+pub mod crypto_swap;
+pub mod deltas;
+#[cfg(all(test, feature = "exhaustive-tests"))]
+mod exhaustive_tests;
+pub mod fee_model;
 pub mod fees;
+#[cfg(test)]
+mod fixtures;
+pub mod leveraged;
+pub mod params;
+pub mod pegged;
+pub mod pmm;
+pub mod pool_math;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod rational;
+pub mod raw;
+#[cfg(any(test, feature = "reference-math"))]
+pub mod reference;
+pub mod registry;
+pub mod rounding;
+pub mod saturating;
+pub mod slippage;
+pub mod stable;
+#[cfg(test)]
+mod stress_tests;
+pub mod swap_params;
+pub mod sync;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+pub mod weighted;
 
+pub use amount::*;
+pub use buyback::*;
 pub use calculator::*;
+pub use compat::*;
 pub use constant_product::*;
+pub use context::*;
+pub use crypto_swap::*;
+pub use deltas::*;
+pub use fee_model::*;
 pub use fees::*;
+pub use leveraged::*;
+pub use params::*;
+pub use pegged::*;
+pub use pmm::*;
+pub use pool_math::*;
+pub use registry::*;
+pub use rounding::*;
+pub use slippage::*;
+pub use stable::*;
+pub use swap_params::*;
+pub use sync::*;
+pub use weighted::*;
@@ -0,0 +1,641 @@
+//! Curve parameter encoding
+//!
+//! `CurveParams` packs a curve's configuration into `CurveParams::LEN`
+//! bytes so `PoolState` can persist it next to the reserves:
+//!
+//! ```text
+//! version: u8 | curve type: u8 | fee tier: u8 | parameters, little endian,
+//! zero padded
+//! ```
+//!
+//! The fee tier byte is zero for pools outside the canonical tiers and the
+//! tier's seed plus one otherwise.
+//!
+//! Unpacking rejects unknown versions, curve types and fee tiers, nonzero
+//! padding and parameters the curve's constructor would reject, so a packed
+//! configuration always reconstructs the same curve. Version 0 packed only
+//! the first four curves, without a fee tier, into fewer bytes; no version
+//! decodes all-zero bytes, so a zeroed account must be initialized before
+//! its curve is read.
+//!
+//! PMM and repegging pools keep state beyond their reserves, such as an
+//! oracle price or a moving price scale, so their parameters are persisted
+//! but do not quote swaps on their own; they trade through `PmmPool` and
+//! `CryptoSwapPool`.
+//!
+//! Curves registered with `CurveRegistry` share the layout: the header
+//! holds their `CurveType::Custom` id and the parameter bytes are theirs to
+//! define.
+use crate::{
+    curve::{
+        amount::Reserves,
+        calculator::{CurveCalculator, SwapResult, TradeDirection},
+        crypto_swap::CryptoSwapParams,
+        fees::{FeeConfig, FeeRate},
+        leveraged::LeveragedReserves,
+        pegged::{OutOfBand, PeggedCurve},
+        pmm::PMM_K_DENOMINATOR,
+        registry::SwapCurve,
+        stable::{AmpRamp, is_valid_amp},
+        weighted::{WeightSchedule, Weights},
+    },
+    fee_tier::FeeTier,
+    state::CurveType,
+    utils::PriceQ64,
+};
+
+/// Version of the encoding written by `CurveParams::pack`
+pub const CURVE_PARAMS_VERSION: u8 = 1;
+
+// Bytes before the parameters: the version, the curve type and the fee tier
+const HEADER_LEN: usize = 3;
+
+/// Configuration of a pool's curve
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CurveParams {
+    #[default]
+    ConstantProduct,
+    /// StableSwap curve with its amplification ramp
+    Stable(AmpRamp),
+    /// Weighted curve with its weight schedule; fixed weights start and end
+    /// at the same weights
+    Weighted(WeightSchedule),
+    Pegged(PeggedCurve),
+    /// PMM curve with its slippage parameter, scaled by
+    /// `PMM_K_DENOMINATOR`
+    Pmm {
+        k: u64,
+    },
+    /// Leveraged constant product curve with the amounts by which its
+    /// virtual reserves exceed the real ones, as
+    /// `LeveragedReserves::offsets` returns them
+    Leveraged {
+        offsets: Reserves,
+    },
+    /// Repegging curve with its parameters
+    CryptoSwap(CryptoSwapParams),
+}
+
+impl CurveParams {
+    /// Size of the packed parameters in bytes
+    pub const LEN: usize = HEADER_LEN + Self::PARAMS_LEN;
+    /// Size of the parameters after the header in bytes, those of the
+    /// repegging curve being the largest
+    pub const PARAMS_LEN: usize = 4 + 3 * 8 + 3 * 16 + 8;
+
+    /// The curve type these parameters configure
+    pub const fn curve_type(&self) -> CurveType {
+        match self {
+            Self::ConstantProduct => CurveType::ConstantProduct,
+            Self::Stable(_) => CurveType::Stable,
+            Self::Weighted(_) => CurveType::Weighted,
+            Self::Pegged(_) => CurveType::Pegged,
+            Self::Pmm { .. } => CurveType::Pmm,
+            Self::Leveraged { .. } => CurveType::Leveraged,
+            Self::CryptoSwap(_) => CurveType::CryptoSwap,
+        }
+    }
+
+    /// Encode as `LEN` bytes in the current version, outside any fee tier
+    pub fn pack(&self) -> [u8; Self::LEN] {
+        self.pack_with_fee_tier(None)
+    }
+
+    /// Encode as `LEN` bytes in the current version, recording the pool's
+    /// fee tier
+    pub fn pack_with_fee_tier(&self, fee_tier: Option<FeeTier>) -> [u8; Self::LEN] {
+        let mut bytes = header(self.curve_type());
+        Self::pack_fee_tier(&mut bytes, fee_tier);
+        let mut params = Writer(&mut bytes[HEADER_LEN..]);
+        match self {
+            Self::ConstantProduct => {}
+            Self::Stable(ramp) => {
+                // scaled amplifications are below 2^32
+                params.u32(ramp.initial_amp as u32);
+                params.u32(ramp.target_amp as u32);
+                params.u64(ramp.initial_time);
+                params.u64(ramp.target_time);
+            }
+            Self::Weighted(schedule) => {
+                params.u32(schedule.start_weights.token_0 as u32);
+                params.u32(schedule.end_weights.token_0 as u32);
+                params.u64(schedule.start_time);
+                params.u64(schedule.end_time);
+            }
+            Self::Pegged(curve) => {
+                params.u128(curve.peg.raw());
+                params.bytes(&curve.band_bps.to_le_bytes());
+                params.bytes(&[match curve.out_of_band {
+                    OutOfBand::Revert => 0,
+                    OutOfBand::ConstantProduct => 1,
+                }]);
+            }
+            Self::Pmm { k } => params.u64(*k),
+            Self::Leveraged { offsets } => {
+                params.u128(offsets.token_0.get());
+                params.u128(offsets.token_1.get());
+            }
+            Self::CryptoSwap(crypto_swap) => {
+                // bounded like the stable curve's amplification
+                params.u32(crypto_swap.amp as u32);
+                params.u64(crypto_swap.mid_fee.get());
+                params.u64(crypto_swap.out_fee.get());
+                params.u64(crypto_swap.protocol_fee_rate.get());
+                params.u128(crypto_swap.fee_gamma.raw());
+                params.u128(crypto_swap.allowed_extra_profit.raw());
+                params.u128(crypto_swap.adjustment_step.raw());
+                params.u64(crypto_swap.ma_half_time);
+            }
+        }
+        bytes
+    }
+
+    /// Record `fee_tier` in packed bytes, leaving the curve and its
+    /// parameters as they are
+    pub fn pack_fee_tier(bytes: &mut [u8; Self::LEN], fee_tier: Option<FeeTier>) {
+        bytes[HEADER_LEN - 1] = fee_tier.map_or(0, |tier| tier as u8 + 1);
+    }
+
+    /// Encode `params` for any curve type, zero padded, in the current
+    /// version and outside any fee tier.
+    ///
+    /// Returns `None` if `params` is longer than `PARAMS_LEN`.
+    pub fn pack_raw(curve_type: CurveType, params: &[u8]) -> Option<[u8; Self::LEN]> {
+        let mut bytes = header(curve_type);
+        bytes[HEADER_LEN..]
+            .get_mut(..params.len())?
+            .copy_from_slice(params);
+        Some(bytes)
+    }
+
+    /// Split packed bytes into the curve type, the fee tier and the
+    /// `PARAMS_LEN` parameter bytes.
+    ///
+    /// Returns `None` if `bytes` is not `LEN` long, is in an unknown version
+    /// or records an unknown fee tier.
+    pub fn unpack_header(bytes: &[u8]) -> Option<(CurveType, Option<FeeTier>, &[u8])> {
+        let bytes: &[u8; Self::LEN] = bytes.try_into().ok()?;
+        let ([version, curve_type, fee_tier], params) = bytes.split_first_chunk::<HEADER_LEN>()?;
+        if *version != CURVE_PARAMS_VERSION {
+            return None;
+        }
+        let fee_tier = match fee_tier.checked_sub(1) {
+            Some(seed) => Some(FeeTier::from_seed(seed)?),
+            None => None,
+        };
+        Some((CurveType::from_id(*curve_type), fee_tier, params))
+    }
+
+    /// Returns `None` if `bytes` is not `LEN` long or does not hold valid
+    /// parameters for a built-in curve in a known version.
+    pub fn unpack(bytes: &[u8]) -> Option<Self> {
+        let (curve_type, _, params) = Self::unpack_header(bytes)?;
+        Self::unpack_params(curve_type, params)
+    }
+
+    /// Decode the `PARAMS_LEN` parameter bytes of built-in curve
+    /// `curve_type`.
+    ///
+    /// Returns `None` for custom curves and invalid parameters.
+    pub fn unpack_params(curve_type: CurveType, params: &[u8]) -> Option<Self> {
+        if params.len() != Self::PARAMS_LEN {
+            return None;
+        }
+        let mut params = Reader(params);
+        let result = match curve_type {
+            CurveType::ConstantProduct => Self::ConstantProduct,
+            CurveType::Stable => {
+                let ramp = AmpRamp {
+                    initial_amp: params.u32()?.into(),
+                    target_amp: params.u32()?.into(),
+                    initial_time: params.u64()?,
+                    target_time: params.u64()?,
+                };
+                if !is_valid_amp(ramp.initial_amp)
+                    || !is_valid_amp(ramp.target_amp)
+                    || ramp.target_time < ramp.initial_time
+                {
+                    return None;
+                }
+                Self::Stable(ramp)
+            }
+            CurveType::Weighted => Self::Weighted(WeightSchedule::new(
+                Weights::new(params.u32()?.into())?,
+                Weights::new(params.u32()?.into())?,
+                params.u64()?,
+                params.u64()?,
+            )?),
+            CurveType::Pegged => {
+                let peg = PriceQ64::from_raw(params.u128()?);
+                let band_bps = u16::from_le_bytes(params.take()?);
+                let out_of_band = match params.take::<1>()? {
+                    [0] => OutOfBand::Revert,
+                    [1] => OutOfBand::ConstantProduct,
+                    _ => return None,
+                };
+                Self::Pegged(PeggedCurve::new(peg, band_bps, out_of_band)?)
+            }
+            CurveType::Pmm => {
+                let k = params.u64()?;
+                if k > PMM_K_DENOMINATOR {
+                    return None;
+                }
+                Self::Pmm { k }
+            }
+            CurveType::Leveraged => Self::Leveraged {
+                offsets: Reserves::from_raw(params.u128()?, params.u128()?),
+            },
+            CurveType::CryptoSwap => {
+                let crypto_swap = CryptoSwapParams {
+                    amp: params.u32()?.into(),
+                    mid_fee: FeeRate::checked(params.u64()?)?,
+                    out_fee: FeeRate::checked(params.u64()?)?,
+                    protocol_fee_rate: FeeRate::checked(params.u64()?)?,
+                    fee_gamma: PriceQ64::from_raw(params.u128()?),
+                    allowed_extra_profit: PriceQ64::from_raw(params.u128()?),
+                    adjustment_step: PriceQ64::from_raw(params.u128()?),
+                    ma_half_time: params.u64()?,
+                };
+                if !is_valid_amp(crypto_swap.amp) {
+                    return None;
+                }
+                Self::CryptoSwap(crypto_swap)
+            }
+            CurveType::Custom(_) => return None,
+        };
+        // padding is reserved for later versions
+        params.0.iter().all(|&byte| byte == 0).then_some(result)
+    }
+
+    /// Quote a swap of exactly `source_amount` in `trade_direction` at
+    /// `now` on the configured curve.
+    ///
+    /// Returns `None` for the PMM and repegging curves, which need more
+    /// than the reserves to quote.
+    pub fn swap_exact_in(
+        &self,
+        now: u64,
+        trade_direction: TradeDirection,
+        source_amount: u128,
+        reserves: Reserves,
+        fee_config: FeeConfig,
+    ) -> Option<SwapResult> {
+        match self {
+            Self::ConstantProduct => {
+                CurveCalculator::swap_exact_in(trade_direction, source_amount, reserves, fee_config)
+            }
+            Self::Stable(ramp) => SwapCurve::swap_exact_in(
+                ramp,
+                now,
+                trade_direction,
+                source_amount,
+                reserves,
+                fee_config,
+            ),
+            Self::Weighted(schedule) => SwapCurve::swap_exact_in(
+                schedule,
+                now,
+                trade_direction,
+                source_amount,
+                reserves,
+                fee_config,
+            ),
+            Self::Pegged(curve) => SwapCurve::swap_exact_in(
+                curve,
+                now,
+                trade_direction,
+                source_amount,
+                reserves,
+                fee_config,
+            ),
+            Self::Leveraged { offsets } => LeveragedReserves::from_offsets(reserves, *offsets)?
+                .swap_exact_in(trade_direction, source_amount, fee_config),
+            Self::Pmm { .. } | Self::CryptoSwap(_) => None,
+        }
+    }
+}
+
+fn header(curve_type: CurveType) -> [u8; CurveParams::LEN] {
+    let mut bytes = [0; CurveParams::LEN];
+    bytes[0] = CURVE_PARAMS_VERSION;
+    bytes[1] = curve_type.id();
+    bytes
+}
+
+struct Writer<'a>(&'a mut [u8]);
+
+impl Writer<'_> {
+    fn bytes(&mut self, value: &[u8]) {
+        let (head, tail) = core::mem::take(&mut self.0).split_at_mut(value.len());
+        head.copy_from_slice(value);
+        self.0 = tail;
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u128(&mut self, value: u128) {
+        self.bytes(&value.to_le_bytes());
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let (head, tail) = self.0.split_first_chunk::<N>()?;
+        self.0 = tail;
+        Some(*head)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.take().map(u64::from_le_bytes)
+    }
+
+    fn u128(&mut self) -> Option<u128> {
+        self.take().map(u128::from_le_bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::curve::{
+            amount::{Token0Amount, Token1Amount},
+            fees::{ProtocolFeeMode, TradeFeeSide},
+            pegged::PEG_BAND_BPS_DENOMINATOR,
+            stable::{AMP_PRECISION, MAX_AMP},
+        },
+    };
+
+    fn crypto_swap_params() -> CryptoSwapParams {
+        CryptoSwapParams {
+            amp: 50 * AMP_PRECISION,
+            mid_fee: FeeRate::from_bps(5).unwrap(),
+            out_fee: FeeRate::from_bps(45).unwrap(),
+            fee_gamma: PriceQ64::from_ratio(1, 4).unwrap(),
+            protocol_fee_rate: FeeRate::from_percent(50).unwrap(),
+            allowed_extra_profit: PriceQ64::from_ratio(1, 1_000_000).unwrap(),
+            adjustment_step: PriceQ64::from_ratio(1, 1_000).unwrap(),
+            ma_half_time: 600,
+        }
+    }
+
+    fn all_params() -> [CurveParams; 7] {
+        let mut ramp = AmpRamp::new(100).unwrap();
+        ramp.ramp(500, 2 * 86_400, 86_400).unwrap();
+        [
+            CurveParams::ConstantProduct,
+            CurveParams::Stable(ramp),
+            CurveParams::Weighted(
+                WeightSchedule::new(
+                    Weights::new(960_000).unwrap(),
+                    Weights::new(500_000).unwrap(),
+                    10,
+                    20,
+                )
+                .unwrap(),
+            ),
+            CurveParams::Pegged(
+                PeggedCurve::new(
+                    PriceQ64::from_ratio(101, 100).unwrap(),
+                    250,
+                    OutOfBand::ConstantProduct,
+                )
+                .unwrap(),
+            ),
+            CurveParams::Pmm { k: 100_000 },
+            CurveParams::Leveraged {
+                offsets: Reserves::from_raw(3_000_000, 1_000_000),
+            },
+            CurveParams::CryptoSwap(crypto_swap_params()),
+        ]
+    }
+
+    #[test]
+    fn params_round_trip() {
+        for params in all_params() {
+            let bytes = params.pack();
+            assert_eq!(bytes[0], CURVE_PARAMS_VERSION);
+            assert_eq!(bytes[1], params.curve_type().id());
+            assert_eq!(CurveParams::unpack(&bytes), Some(params));
+            assert_eq!(CurveParams::unpack_header(&bytes).unwrap().1, None);
+            for fee_tier in FeeTier::ALL {
+                let bytes = params.pack_with_fee_tier(Some(fee_tier));
+                let (curve_type, unpacked_tier, _) = CurveParams::unpack_header(&bytes).unwrap();
+                assert_eq!(curve_type, params.curve_type());
+                assert_eq!(unpacked_tier, Some(fee_tier));
+                assert_eq!(CurveParams::unpack(&bytes), Some(params));
+            }
+        }
+        // zeroed bytes predate every version
+        assert_eq!(CurveParams::unpack(&[0; CurveParams::LEN]), None);
+    }
+
+    #[test]
+    fn pinned_encoding() {
+        let schedule = WeightSchedule::new(
+            Weights::new(800_000).unwrap(),
+            Weights::new(200_000).unwrap(),
+            1,
+            2,
+        )
+        .unwrap();
+        let mut expected = vec![1, 2, 0];
+        expected.extend_from_slice(&800_000u32.to_le_bytes());
+        expected.extend_from_slice(&200_000u32.to_le_bytes());
+        expected.extend_from_slice(&1u64.to_le_bytes());
+        expected.extend_from_slice(&2u64.to_le_bytes());
+        expected.resize(CurveParams::LEN, 0);
+        assert_eq!(CurveParams::Weighted(schedule).pack().to_vec(), expected);
+        // the tier's seed plus one
+        expected[2] = 5;
+        assert_eq!(
+            CurveParams::Weighted(schedule)
+                .pack_with_fee_tier(Some(FeeTier::Bps25))
+                .to_vec(),
+            expected
+        );
+
+        let mut expected = vec![1, 6, 0];
+        expected.extend_from_slice(&(50 * AMP_PRECISION as u32).to_le_bytes());
+        for fee_rate in [500u64, 4_500, 500_000] {
+            expected.extend_from_slice(&fee_rate.to_le_bytes());
+        }
+        for raw in [1u128 << 62, (1 << 64) / 1_000_000, (1 << 64) / 1_000] {
+            expected.extend_from_slice(&raw.to_le_bytes());
+        }
+        expected.extend_from_slice(&600u64.to_le_bytes());
+        assert_eq!(expected.len(), CurveParams::LEN);
+        assert_eq!(
+            CurveParams::CryptoSwap(crypto_swap_params())
+                .pack()
+                .to_vec(),
+            expected
+        );
+    }
+
+    #[test]
+    fn invalid_params_are_rejected() {
+        for params in all_params() {
+            let bytes = params.pack();
+            assert_eq!(CurveParams::unpack(&bytes[..CurveParams::LEN - 1]), None);
+            let mut unknown_version = bytes;
+            unknown_version[0] = CURVE_PARAMS_VERSION + 1;
+            assert_eq!(CurveParams::unpack(&unknown_version), None);
+        }
+        // the repegging parameters are the only ones filling every byte
+        for params in &all_params()[..6] {
+            let mut padded = params.pack();
+            padded[CurveParams::LEN - 1] = 1;
+            assert_eq!(CurveParams::unpack(&padded), None);
+        }
+        let mut unknown_curve = CurveParams::ConstantProduct.pack();
+        unknown_curve[1] = 7;
+        assert_eq!(CurveParams::unpack(&unknown_curve), None);
+        let mut unknown_tier = CurveParams::ConstantProduct.pack();
+        unknown_tier[2] = FeeTier::ALL.len() as u8 + 1;
+        assert_eq!(CurveParams::unpack_header(&unknown_tier), None);
+        assert_eq!(CurveParams::unpack(&unknown_tier), None);
+        // parameters go through the curve's own checks
+        let mut zero_amp = CurveParams::Stable(AmpRamp::new(1).unwrap()).pack();
+        zero_amp[HEADER_LEN..HEADER_LEN + 4].fill(0);
+        assert_eq!(CurveParams::unpack(&zero_amp), None);
+        let mut wide_band = all_params()[3].pack();
+        wide_band[HEADER_LEN + 16..HEADER_LEN + 18]
+            .copy_from_slice(&PEG_BAND_BPS_DENOMINATOR.to_le_bytes());
+        assert_eq!(CurveParams::unpack(&wide_band), None);
+        let mut light = all_params()[2].pack();
+        light[HEADER_LEN..HEADER_LEN + 4].copy_from_slice(&1u32.to_le_bytes());
+        assert_eq!(CurveParams::unpack(&light), None);
+        let steep = CurveParams::Pmm {
+            k: PMM_K_DENOMINATOR + 1,
+        };
+        assert_eq!(CurveParams::unpack(&steep.pack()), None);
+        let mut flat = CurveParams::CryptoSwap(crypto_swap_params()).pack();
+        flat[HEADER_LEN..HEADER_LEN + 4].copy_from_slice(&1u32.to_le_bytes());
+        assert_eq!(CurveParams::unpack(&flat), None);
+        let expensive = CurveParams::CryptoSwap(CryptoSwapParams {
+            out_fee: FeeRate::MAX,
+            ..crypto_swap_params()
+        });
+        let mut expensive = expensive.pack();
+        expensive[HEADER_LEN + 12] += 1;
+        assert_eq!(CurveParams::unpack(&expensive), None);
+    }
+
+    #[test]
+    fn amps_outside_the_ramp_bounds_are_rejected() {
+        let stable = |initial_amp, target_amp| {
+            CurveParams::Stable(AmpRamp {
+                initial_amp,
+                target_amp,
+                initial_time: 0,
+                target_time: 0,
+            })
+        };
+        let round_trip = |params: CurveParams| CurveParams::unpack(&params.pack());
+        for amp in [AMP_PRECISION, MAX_AMP * AMP_PRECISION - 1] {
+            assert_eq!(round_trip(stable(amp, amp)), Some(stable(amp, amp)));
+        }
+        // small enough to fit the packed bytes, but not a valid ramp
+        for amp in [1, AMP_PRECISION - 1, MAX_AMP * AMP_PRECISION] {
+            assert_eq!(round_trip(stable(amp, AMP_PRECISION)), None);
+            assert_eq!(round_trip(stable(AMP_PRECISION, amp)), None);
+        }
+    }
+
+    #[test]
+    fn only_constant_product_curves_charge_fees_on_the_output() {
+        let reserves = Reserves::new(Token0Amount(1_000_000), Token1Amount(1_000_000));
+        let fee_config = FeeConfig::STANDARD_25BPS;
+        for fee_config in [
+            fee_config.with_trade_fee_side(TradeFeeSide::Output),
+            fee_config.with_protocol_fee_mode(ProtocolFeeMode::Split),
+        ] {
+            for params in all_params() {
+                let result = params.swap_exact_in(
+                    0,
+                    TradeDirection::ZeroForOne,
+                    1_000,
+                    reserves,
+                    fee_config,
+                );
+                assert_eq!(
+                    result.is_some(),
+                    matches!(
+                        params,
+                        CurveParams::ConstantProduct | CurveParams::Leveraged { .. }
+                    )
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn unpacked_params_quote_like_their_curve() {
+        let reserves = Reserves::new(Token0Amount(1_000_000), Token1Amount(1_000_000));
+        let fee_config = FeeConfig::default();
+        for params in all_params() {
+            let unpacked = CurveParams::unpack(&params.pack()).unwrap();
+            for now in [0, 15, 86_400 * 3 / 2] {
+                assert_eq!(
+                    unpacked.swap_exact_in(
+                        now,
+                        TradeDirection::ZeroForOne,
+                        1_000,
+                        reserves,
+                        fee_config
+                    ),
+                    params.swap_exact_in(
+                        now,
+                        TradeDirection::ZeroForOne,
+                        1_000,
+                        reserves,
+                        fee_config
+                    )
+                );
+            }
+        }
+        // the weighted curve follows its schedule
+        let weighted = all_params()[2];
+        let quote = |now| {
+            weighted
+                .swap_exact_in(now, TradeDirection::OneForZero, 1_000, reserves, fee_config)
+                .unwrap()
+                .destination_amount_swapped
+        };
+        assert!(quote(20) > quote(10));
+    }
+
+    #[test]
+    fn leveraged_params_quote_like_their_pool() {
+        let reserves = Reserves::new(Token0Amount(1_000_000), Token1Amount(2_000_000));
+        let pool = LeveragedReserves::new(reserves, 40_000).unwrap();
+        let params = CurveParams::Leveraged {
+            offsets: pool.offsets().unwrap(),
+        };
+        let fee_config = FeeConfig::STANDARD_25BPS;
+        assert_eq!(
+            params.swap_exact_in(0, TradeDirection::OneForZero, 10_000, reserves, fee_config),
+            pool.swap_exact_in(TradeDirection::OneForZero, 10_000, fee_config)
+        );
+        // quotes that need an oracle or a price scale are left to the pools
+        for params in [all_params()[4], all_params()[6]] {
+            assert_eq!(
+                params.swap_exact_in(0, TradeDirection::OneForZero, 10_000, reserves, fee_config),
+                None
+            );
+        }
+    }
+}
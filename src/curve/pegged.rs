@@ -0,0 +1,406 @@
+//! Hard-bounded curve for pegged tokens
+//!
+//! Trades at exactly the peg while the pool stays within a band around
+//! balance, measured on the balances valued in token 0 at the peg: for
+//! values `a` and `b` the pool is in band while `|a - b| <= band * (a + b)`.
+//! A swap that would leave the band either fails with
+//! `PegError::ExitsBand`, or continues along a constant product curve
+//! joined to the band's edge at the peg price, depending on the pool's
+//! `OutOfBand` setting.
+//!
+//! With `D` the value of the pool at the peg when in band, the curve is
+//!
+//! ```text
+//! a + b = D                                  in band
+//! (a - band * D) * b = ((1 - band) * D / 2)^2   beyond the edge where b is scarce
+//! ```
+//!
+//! and its mirror image where `a` is scarce, so quotes do not depend on how
+//! a trade is split.
+use {
+    crate::{
+        curve::{
+            amount::Reserves,
+            calculator::{RoundDirection, SwapResult, TradeDirection},
+            fees::{FeeConfig, Fees},
+        },
+        utils::{IntegerSquareRoot, PriceQ64, U256, U512},
+    },
+    core::fmt,
+};
+
+/// Denominator of `PeggedCurve::band_bps`
+pub const PEG_BAND_BPS_DENOMINATOR: u16 = 10_000;
+
+/// What a pegged pool does with a swap that would leave its band
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutOfBand {
+    /// Fail the swap
+    Revert,
+    /// Price the part beyond the band on a constant product curve
+    ConstantProduct,
+}
+
+/// Why a pegged pool rejected a swap
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PegError {
+    /// The swap would move the pool out of its band
+    ExitsBand {
+        /// Source tokens, after fees, the pool can still take without
+        /// leaving the band
+        remaining_capacity: u128,
+    },
+    /// A reserve is empty or a value overflows
+    Overflow,
+    /// The fee configuration does not take every fee from the input
+    FeeConfigInvalid,
+}
+
+impl fmt::Display for PegError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PegError::ExitsBand { remaining_capacity } => write!(
+                f,
+                "swap would leave the peg band; at most {remaining_capacity} more source tokens after fees fit"
+            ),
+            PegError::Overflow => f.write_str("a reserve is empty or a value overflows"),
+            PegError::FeeConfigInvalid => f.write_str("the curve only charges fees on the input"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PegError {}
+
+/// A pool of two tokens pegged at a fixed price
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PeggedCurve {
+    /// Price of token 1 in token 0
+    pub peg: PriceQ64,
+    /// Largest imbalance traded at the peg, in basis points of the pool's
+    /// value
+    pub band_bps: u16,
+    pub out_of_band: OutOfBand,
+}
+
+impl PeggedCurve {
+    /// Returns `None` if the peg is zero or the band is not below 100%.
+    pub fn new(peg: PriceQ64, band_bps: u16, out_of_band: OutOfBand) -> Option<Self> {
+        (peg != PriceQ64::ZERO && band_bps < PEG_BAND_BPS_DENOMINATOR).then_some(Self {
+            peg,
+            band_bps,
+            out_of_band,
+        })
+    }
+
+    /// `reserves` valued in token 0 at the peg, rounded down
+    pub fn values(&self, reserves: Reserves) -> Option<(u128, u128)> {
+        Some((
+            reserves.token_0.get(),
+            self.peg
+                .mul_amount(reserves.token_1.get(), RoundDirection::Floor)?,
+        ))
+    }
+
+    /// Whether values `a` and `b` are within the band
+    pub fn in_band(&self, a: u128, b: u128) -> bool {
+        let imbalance = U256::from(a.abs_diff(b)) * U256::from(PEG_BAND_BPS_DENOMINATOR);
+        imbalance <= (U256::from(a) + U256::from(b)) * U256::from(self.band_bps)
+    }
+
+    // Band as its basis points, the rest of the denominator, and the
+    // denominator
+    fn band_terms(&self) -> (U512, U512, U512) {
+        let band = U512::from(self.band_bps);
+        let denominator = U512::from(PEG_BAND_BPS_DENOMINATOR);
+        (band, denominator - band, denominator)
+    }
+
+    /// Value `D` of the curve through values `a` and `b`, rounded down.
+    ///
+    /// Returns `None` if the values are beyond the band and one is zero.
+    pub fn compute_d(&self, a: u128, b: u128) -> Option<u128> {
+        if self.in_band(a, b) {
+            return a.checked_add(b);
+        }
+        if a == 0 || b == 0 {
+            return None;
+        }
+        // (hi - band * D) * lo = ((1 - band) * D / 2)^2, solved for D:
+        // D = 2 * (sqrt(band^2 lo^2 + (1 - band)^2 hi lo) - band * lo) / (1 - band)^2
+        let (band, rest, denominator) = self.band_terms();
+        let (hi, lo) = (U512::from(a.max(b)), U512::from(a.min(b)));
+        let root = (band * band * lo * lo + rest * rest * hi * lo).sqrt_floor();
+        let mut d = (root - band * lo) * 2 * denominator / (rest * rest);
+        // the root is rounded down, so D may be a little low
+        let covers = |d: U512| {
+            let hi_less_band = denominator * hi;
+            hi_less_band >= band * d
+                && denominator * (hi_less_band - band * d) * lo * 4 >= rest * rest * d * d
+        };
+        while covers(d + 1) {
+            d += U512::one();
+        }
+        narrow(d)
+    }
+
+    // Largest source value the band can hold on the curve of value `d`
+    fn high_edge(&self, d: u128) -> Option<u128> {
+        let (_, rest, denominator) = self.band_terms();
+        let d = U512::from(d);
+        let low_edge = (rest * d + denominator * 2 - 1) / (denominator * 2);
+        narrow(d - low_edge)
+    }
+
+    // Destination value on the curve of value `d` once the source value is
+    // `a`, rounded up
+    fn destination_value(&self, d: u128, a: u128) -> Option<U512> {
+        let (band, rest, denominator) = self.band_terms();
+        let (a, d) = (U512::from(a), U512::from(d));
+        let ceil_div = |numerator: U512, divisor: U512| {
+            (!divisor.is_zero()).then(|| (numerator + divisor - 1) / divisor)
+        };
+        if denominator * a * 2 < rest * d {
+            // the source is scarce: (b - band * D) * a = ((1 - band) * D / 2)^2
+            ceil_div(
+                rest * rest * d * d + denominator * band * d * a * 4,
+                denominator * denominator * a * 4,
+            )
+        } else if denominator * a * 2 <= denominator * d * 2 - rest * d {
+            Some(d - a)
+        } else {
+            // the destination is scarce: (a - band * D) * b = ((1 - band) * D / 2)^2
+            ceil_div(
+                rest * rest * d * d,
+                denominator * (denominator * a - band * d) * 4,
+            )
+        }
+    }
+
+    /// Destination tokens received for `source_amount`, without fees.
+    pub fn swap_base_input_without_fees(
+        &self,
+        trade_direction: TradeDirection,
+        source_amount: u128,
+        reserves: Reserves,
+    ) -> Result<u128, PegError> {
+        let (value_0, value_1) = self.values(reserves).ok_or(PegError::Overflow)?;
+        let d = self.compute_d(value_0, value_1).ok_or(PegError::Overflow)?;
+        let (source_value, source_amount_value) = match trade_direction {
+            TradeDirection::ZeroForOne => (value_0, source_amount),
+            TradeDirection::OneForZero => (
+                value_1,
+                self.peg
+                    .mul_amount(source_amount, RoundDirection::Floor)
+                    .ok_or(PegError::Overflow)?,
+            ),
+        };
+        let new_source_value = source_value
+            .checked_add(source_amount_value)
+            .ok_or(PegError::Overflow)?;
+        if self.out_of_band == OutOfBand::Revert {
+            let high_edge = self.high_edge(d).ok_or(PegError::Overflow)?;
+            if new_source_value > high_edge {
+                let capacity = high_edge.saturating_sub(source_value);
+                let remaining_capacity = match trade_direction {
+                    TradeDirection::ZeroForOne => Some(capacity),
+                    TradeDirection::OneForZero => {
+                        self.peg.div_amount(capacity, RoundDirection::Floor)
+                    }
+                };
+                return Err(PegError::ExitsBand {
+                    remaining_capacity: remaining_capacity.ok_or(PegError::Overflow)?,
+                });
+            }
+        }
+        let new_destination_value = self
+            .destination_value(d, new_source_value)
+            .ok_or(PegError::Overflow)?;
+        let new_destination_value = narrow(new_destination_value).ok_or(PegError::Overflow)?;
+        // converting the reserve left, rather than the value paid out, keeps
+        // the rounding of both valuations with the pool
+        let new_destination_amount = match trade_direction {
+            TradeDirection::ZeroForOne => self
+                .peg
+                .div_amount(new_destination_value, RoundDirection::Ceiling)
+                .ok_or(PegError::Overflow)?,
+            TradeDirection::OneForZero => new_destination_value,
+        };
+        let (_, swap_destination_amount) = reserves.source_and_destination(trade_direction);
+        Ok(swap_destination_amount.saturating_sub(new_destination_amount.max(1)))
+    }
+
+    /// Quote a swap of exactly `source_amount` in `trade_direction`,
+    /// charging fees on the source amount like the constant product curve.
+    ///
+    /// Fails with `PegError::FeeConfigInvalid` unless `fee_config` takes
+    /// every fee from the input.
+    pub fn swap_exact_in(
+        &self,
+        trade_direction: TradeDirection,
+        source_amount: u128,
+        reserves: Reserves,
+        fee_config: FeeConfig,
+    ) -> Result<SwapResult, PegError> {
+        if !fee_config.is_input_only() {
+            return Err(PegError::FeeConfigInvalid);
+        }
+        let (swap_source_amount, swap_destination_amount) =
+            reserves.source_and_destination(trade_direction);
+        let trade_fee = Fees::trading_fee(source_amount, fee_config.trade_fee_rate.get())
+            .ok_or(PegError::Overflow)?;
+        let protocol_fee = Fees::protocol_fee(trade_fee, fee_config.protocol_fee_rate.get())
+            .ok_or(PegError::Overflow)?;
+        let destination_amount_swapped = self.swap_base_input_without_fees(
+            trade_direction,
+            source_amount - trade_fee,
+            reserves,
+        )?;
+        Ok(SwapResult {
+            new_swap_source_amount: swap_source_amount
+                .checked_add(source_amount)
+                .ok_or(PegError::Overflow)?,
+            new_swap_destination_amount: swap_destination_amount - destination_amount_swapped,
+            source_amount_swapped: source_amount,
+            destination_amount_swapped,
+            trade_fee,
+            protocol_fee,
+            destination_trade_fee: 0,
+            destination_protocol_fee: 0,
+            rounding_dust: 0,
+        })
+    }
+}
+
+fn narrow(value: U512) -> Option<u128> {
+    (value <= U512::from(u128::MAX)).then(|| value.as_u128())
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::curve::fees::FeeRate, proptest::prelude::*};
+
+    #[test]
+    fn trades_at_the_peg_in_band() {
+        // token 1 pegged at 2 token 0, 10% band
+        let curve = PeggedCurve::new(PriceQ64::from_integer(2), 1_000, OutOfBand::Revert).unwrap();
+        let pool = Reserves::from_raw(1_000_000, 500_000);
+        assert_eq!(
+            curve.swap_base_input_without_fees(TradeDirection::OneForZero, 1_000, pool),
+            Ok(2_000)
+        );
+        assert_eq!(
+            curve.swap_base_input_without_fees(TradeDirection::ZeroForOne, 1_000, pool),
+            Ok(500)
+        );
+        // the band ends at values 1_100_000 and 900_000
+        assert_eq!(
+            curve.swap_base_input_without_fees(TradeDirection::ZeroForOne, 100_000, pool),
+            Ok(50_000)
+        );
+        let error = curve
+            .swap_base_input_without_fees(TradeDirection::OneForZero, 50_001, pool)
+            .unwrap_err();
+        assert_eq!(
+            error,
+            PegError::ExitsBand {
+                remaining_capacity: 50_000
+            }
+        );
+
+        let fee_config = FeeConfig::new(FeeRate::from_bps(1).unwrap(), FeeRate::ZERO);
+        let result = curve
+            .swap_exact_in(
+                TradeDirection::ZeroForOne,
+                1_000_000,
+                Reserves::from_raw(1_000_000_000, 500_000_000),
+                fee_config,
+            )
+            .unwrap();
+        assert_eq!(result.trade_fee, 100);
+        assert_eq!(result.destination_amount_swapped, 499_950);
+        assert_eq!(
+            PeggedCurve::new(PriceQ64::ONE, 10_000, OutOfBand::Revert),
+            None
+        );
+        assert_eq!(PeggedCurve::new(PriceQ64::ZERO, 1, OutOfBand::Revert), None);
+    }
+
+    #[test]
+    fn constant_product_beyond_the_band() {
+        let curve = PeggedCurve::new(PriceQ64::ONE, 1_000, OutOfBand::ConstantProduct).unwrap();
+        let pool = Reserves::from_raw(1_000_000, 1_000_000);
+        // at the edge the price is still the peg
+        assert_eq!(
+            curve.swap_base_input_without_fees(TradeDirection::ZeroForOne, 100_000, pool),
+            Ok(100_000)
+        );
+        // beyond it every further token buys less
+        let out = curve
+            .swap_base_input_without_fees(TradeDirection::ZeroForOne, 200_000, pool)
+            .unwrap();
+        assert!(out > 180_000 && out < 200_000);
+        let out = curve
+            .swap_base_input_without_fees(TradeDirection::ZeroForOne, 100_000_000, pool)
+            .unwrap();
+        assert!(out > 900_000 && out < 1_000_000);
+        // coming back from beyond the band pays more than the peg
+        let skewed = Reserves::from_raw(1_200_000, 1_000_000 - 190_000);
+        let back = curve
+            .swap_base_input_without_fees(TradeDirection::OneForZero, 10_000, skewed)
+            .unwrap();
+        assert!(back > 10_000);
+    }
+
+    proptest! {
+        #[test]
+        fn quotes_never_lose_value(
+            band_bps in 0u16..5_000,
+            peg in 1u128 << 64..1 << 70,
+            token_0 in 1_000u128..1 << 80,
+            token_1 in 1_000u128..1 << 80,
+            source_amount in 1u128..1 << 80,
+            zero_for_one in any::<bool>(),
+        ) {
+            let curve = PeggedCurve::new(PriceQ64::from_raw(peg), band_bps, OutOfBand::ConstantProduct).unwrap();
+            let pool = Reserves::from_raw(token_0, token_1);
+            let trade_direction = if zero_for_one {
+                TradeDirection::ZeroForOne
+            } else {
+                TradeDirection::OneForZero
+            };
+            let Ok(out) = curve.swap_base_input_without_fees(trade_direction, source_amount, pool) else {
+                return Ok(());
+            };
+            let (source, destination) = pool.source_and_destination(trade_direction);
+            prop_assert!(out < destination);
+            let after_reserves = Reserves::from_source_and_destination(
+                trade_direction,
+                source + source_amount,
+                destination - out,
+            );
+            let (a, b) = curve.values(pool).unwrap();
+            let (a_after, b_after) = curve.values(after_reserves).unwrap();
+            let before = curve.compute_d(a, b).unwrap();
+            let after = curve.compute_d(a_after, b_after).unwrap();
+            prop_assert!(after >= before);
+            // trading straight back never returns more than was paid; beyond
+            // the band the reserves can sit up to D's rounding above the
+            // curve, which a round trip may collect
+            let reverse = trade_direction.opposite();
+            if let (true, Ok(back)) = (
+                curve.in_band(a, b),
+                curve.swap_base_input_without_fees(reverse, out, after_reserves),
+            ) {
+                prop_assert!(back <= source_amount);
+            }
+            // a swap that fits in the band trades at the peg
+            let revert = PeggedCurve { out_of_band: OutOfBand::Revert, ..curve };
+            if let Ok(out_in_band) =
+                revert.swap_base_input_without_fees(trade_direction, source_amount, pool)
+            {
+                prop_assert_eq!(out_in_band, out);
+            }
+        }
+    }
+}
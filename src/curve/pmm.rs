@@ -0,0 +1,779 @@
+//! Proactive market maker curve
+//!
+//! A DODO-style PMM trades around a mid-price supplied by an external
+//! oracle, quoted as token 1 per token 0. Each token has a target: the
+//! reserve the pool would hold if every trade had been reversed. While both
+//! reserves are at their targets the marginal price is the oracle price;
+//! once a token is short of its target it gets more expensive, as
+//!
+//! ```text
+//! P = i * (1 - k + k * (B0 / B)^2)        token 0 short
+//! P = i / (1 - k + k * (Q0 / Q)^2)        token 1 short
+//! ```
+//!
+//! for oracle price `i`, token 0 reserve `B` with target `B0`, and token 1
+//! reserve `Q` with target `Q0`. `k` sets the slippage: at zero the pool
+//! trades at the oracle price until a reserve runs out, at one it behaves
+//! like a constant product pool.
+//!
+//! Only the target of the long token is stored; the short token's target is
+//! the reserve that makes both sides of the curve meet, so it follows the
+//! oracle. Trades moving away from the targets round the derived target up
+//! and trades moving back round it down, so the pool never pays for the
+//! rounding. Fees stay in the long reserve and raise both targets.
+use crate::{
+    curve::{
+        amount::{LpAmount, Reserves, Token0Amount, Token1Amount},
+        calculator::{
+            CurveCalculator, RoundDirection, SwapResult, TradeDirection, TradingTokenResult,
+        },
+        fees::{FeeConfig, Fees},
+    },
+    utils::{IntegerSquareRoot, PriceQ64, U512, price::Q64},
+};
+
+/// Denominator of `PmmPool::k`
+pub const PMM_K_DENOMINATOR: u64 = 1_000_000;
+
+/// Which token of a PMM pool is short of its target
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PmmState {
+    /// Both reserves are at their targets
+    #[default]
+    AtTarget,
+    /// Token 0 is below its target and token 1 above
+    Token0Short,
+    /// Token 1 is below its target and token 0 above
+    Token1Short,
+}
+
+/// A two-token PMM pool
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PmmPool {
+    /// Slippage parameter, scaled by `PMM_K_DENOMINATOR`
+    pub k: u64,
+    /// Fee rates charged on swaps
+    pub fee_config: FeeConfig,
+    /// Tokens held for liquidity providers
+    pub reserves: Reserves,
+    /// Reserves the pool returns to at the oracle price. Only the target of
+    /// a token that is not short is kept up to date.
+    pub targets: Reserves,
+    /// Which token is short of its target
+    pub state: PmmState,
+    /// Fees owed to the protocol
+    pub protocol_fees: Reserves,
+}
+
+// Price of one side in the other token as a fraction
+type Ratio = (U512, U512);
+
+// Which side of a trade is short of its target
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Short {
+    Neither,
+    Source,
+    Destination,
+}
+
+impl PmmPool {
+    /// A pool at its targets holding `reserves`.
+    ///
+    /// Returns `None` if `k` exceeds `PMM_K_DENOMINATOR` or `fee_config`
+    /// does not take every fee from the input.
+    pub fn new(k: u64, fee_config: FeeConfig, reserves: Reserves) -> Option<Self> {
+        (k <= PMM_K_DENOMINATOR && fee_config.is_input_only()).then_some(Self {
+            k,
+            fee_config,
+            reserves,
+            targets: reserves,
+            state: PmmState::AtTarget,
+            protocol_fees: Reserves::default(),
+        })
+    }
+
+    /// Targets at `oracle_price`, with the short token's target rounded
+    /// down
+    pub fn adjusted_targets(&self, oracle_price: PriceQ64) -> Option<Reserves> {
+        let (base_price, quote_price) = prices(oracle_price)?;
+        let (base, quote) = (self.reserves.token_0.get(), self.reserves.token_1.get());
+        let (base_target, quote_target) = (self.targets.token_0.get(), self.targets.token_1.get());
+        let (base_target, quote_target) = match self.state {
+            PmmState::AtTarget => (base_target, quote_target),
+            PmmState::Token0Short => (
+                solve_target(
+                    self.k,
+                    base,
+                    quote.checked_sub(quote_target)?,
+                    base_price,
+                    RoundDirection::Floor,
+                )?,
+                quote_target,
+            ),
+            PmmState::Token1Short => (
+                base_target,
+                solve_target(
+                    self.k,
+                    quote,
+                    base.checked_sub(base_target)?,
+                    quote_price,
+                    RoundDirection::Floor,
+                )?,
+            ),
+        };
+        Some(Reserves::new(
+            Token0Amount(base_target),
+            Token1Amount(quote_target),
+        ))
+    }
+
+    /// Swap exactly `source_amount` in `trade_direction` around
+    /// `oracle_price`, token 1 per token 0.
+    ///
+    /// The trade fee is charged on the source amount. Returns `None`,
+    /// leaving the pool unchanged, if the fee configuration does not take
+    /// every fee from the input, the oracle price is zero, the pool cannot
+    /// pay out the trade, or a value overflows.
+    pub fn swap_exact_in(
+        &mut self,
+        oracle_price: PriceQ64,
+        trade_direction: TradeDirection,
+        source_amount: u128,
+    ) -> Option<SwapResult> {
+        if !self.fee_config.is_input_only() {
+            return None;
+        }
+        let trade_fee = Fees::trading_fee(source_amount, self.fee_config.trade_fee_rate.get())?;
+        let protocol_fee = Fees::protocol_fee(trade_fee, self.fee_config.protocol_fee_rate.get())?;
+        let (base_price, quote_price) = prices(oracle_price)?;
+        let (source_price, destination_price) = match trade_direction {
+            TradeDirection::ZeroForOne => (base_price, quote_price),
+            TradeDirection::OneForZero => (quote_price, base_price),
+        };
+        let short = match (self.state, trade_direction) {
+            (PmmState::AtTarget, _) => Short::Neither,
+            (PmmState::Token0Short, TradeDirection::ZeroForOne)
+            | (PmmState::Token1Short, TradeDirection::OneForZero) => Short::Source,
+            _ => Short::Destination,
+        };
+        let (source, destination) = self.reserves.source_and_destination(trade_direction);
+        let (source_target, destination_target) =
+            self.targets.source_and_destination(trade_direction);
+        let trade = Trade {
+            k: self.k,
+            source,
+            destination,
+            source_target,
+            destination_target,
+            source_price,
+            destination_price,
+        };
+        let (destination_amount_swapped, short, source_target) =
+            trade.sell(source_amount.checked_sub(trade_fee)?, short)?;
+
+        let new_swap_source_amount = source
+            .checked_add(source_amount)?
+            .checked_sub(protocol_fee)?;
+        let new_swap_destination_amount = destination.checked_sub(destination_amount_swapped)?;
+        let reserves = Reserves::from_source_and_destination(
+            trade_direction,
+            new_swap_source_amount,
+            new_swap_destination_amount,
+        );
+        let (source_fees, destination_fees) =
+            self.protocol_fees.source_and_destination(trade_direction);
+        self.protocol_fees = Reserves::from_source_and_destination(
+            trade_direction,
+            source_fees.checked_add(protocol_fee)?,
+            destination_fees,
+        );
+        self.state = match (short, trade_direction) {
+            (Short::Neither, _) => PmmState::AtTarget,
+            (Short::Source, TradeDirection::ZeroForOne)
+            | (Short::Destination, TradeDirection::OneForZero) => PmmState::Token0Short,
+            _ => PmmState::Token1Short,
+        };
+        self.targets = if self.state == PmmState::AtTarget {
+            // landing exactly on the targets moves them by the fee
+            reserves
+        } else {
+            Reserves::from_source_and_destination(
+                trade_direction,
+                source_target,
+                destination_target,
+            )
+        };
+        self.reserves = reserves;
+        Some(SwapResult {
+            new_swap_source_amount,
+            new_swap_destination_amount,
+            source_amount_swapped: source_amount,
+            destination_amount_swapped,
+            trade_fee,
+            protocol_fee,
+            destination_trade_fee: 0,
+            destination_protocol_fee: 0,
+            rounding_dust: 0,
+        })
+    }
+
+    /// Take the deposit for `lp_amount` new LP tokens out of `lp_supply`,
+    /// rounded up in the pool's favor. The targets grow by the same
+    /// fraction.
+    ///
+    /// Returns `None`, leaving the pool unchanged, if a reserve or target
+    /// overflows.
+    pub fn deposit(
+        &mut self,
+        lp_amount: LpAmount,
+        lp_supply: LpAmount,
+    ) -> Option<TradingTokenResult> {
+        let result = share(lp_amount, lp_supply, self.reserves, RoundDirection::Ceiling)?;
+        let target_share = share(lp_amount, lp_supply, self.targets, RoundDirection::Ceiling)?;
+        let reserves = Reserves::new(
+            self.reserves
+                .token_0
+                .checked_add(Token0Amount(result.token_0_amount))?,
+            self.reserves
+                .token_1
+                .checked_add(Token1Amount(result.token_1_amount))?,
+        );
+        let targets = Reserves::new(
+            self.targets
+                .token_0
+                .checked_add(Token0Amount(target_share.token_0_amount))?,
+            self.targets
+                .token_1
+                .checked_add(Token1Amount(target_share.token_1_amount))?,
+        );
+        self.reserves = reserves;
+        self.targets = targets;
+        Some(result)
+    }
+
+    /// Pay out the share of the reserves for `lp_amount` LP tokens out of
+    /// `lp_supply`, rounded down in the pool's favor. The targets shrink by
+    /// the same fraction.
+    ///
+    /// Returns `None`, leaving the pool unchanged, if `lp_amount` exceeds
+    /// `lp_supply`.
+    pub fn withdraw(
+        &mut self,
+        lp_amount: LpAmount,
+        lp_supply: LpAmount,
+    ) -> Option<TradingTokenResult> {
+        if lp_amount > lp_supply {
+            return None;
+        }
+        let result = share(lp_amount, lp_supply, self.reserves, RoundDirection::Floor)?;
+        let target_share = share(lp_amount, lp_supply, self.targets, RoundDirection::Floor)?;
+        let reserves = Reserves::new(
+            self.reserves
+                .token_0
+                .checked_sub(Token0Amount(result.token_0_amount))?,
+            self.reserves
+                .token_1
+                .checked_sub(Token1Amount(result.token_1_amount))?,
+        );
+        let targets = Reserves::new(
+            self.targets
+                .token_0
+                .checked_sub(Token0Amount(target_share.token_0_amount))?,
+            self.targets
+                .token_1
+                .checked_sub(Token1Amount(target_share.token_1_amount))?,
+        );
+        self.reserves = reserves;
+        self.targets = targets;
+        Some(result)
+    }
+}
+
+// The pool seen from a trade
+struct Trade {
+    k: u64,
+    source: u128,
+    destination: u128,
+    source_target: u128,
+    destination_target: u128,
+    // destination tokens per source token
+    source_price: Ratio,
+    // source tokens per destination token
+    destination_price: Ratio,
+}
+
+impl Trade {
+    // Destination tokens for `amount` source tokens after fees, which side
+    // is short afterwards, and the source target to keep
+    fn sell(&self, amount: u128, short: Short) -> Option<(u128, Short, u128)> {
+        if amount == 0 {
+            return Some((0, short, self.source_target));
+        }
+        match short {
+            Short::Neither => Some((
+                self.away(self.destination_target, self.destination, amount)?,
+                Short::Destination,
+                self.source_target,
+            )),
+            Short::Destination => {
+                let destination_target = solve_target(
+                    self.k,
+                    self.destination,
+                    self.source.checked_sub(self.source_target)?,
+                    self.destination_price,
+                    RoundDirection::Ceiling,
+                )?;
+                Some((
+                    self.away(destination_target, self.destination, amount)?,
+                    Short::Destination,
+                    self.source_target,
+                ))
+            }
+            Short::Source => {
+                let surplus = self.destination.checked_sub(self.destination_target)?;
+                let target = |round_direction| {
+                    solve_target(
+                        self.k,
+                        self.source,
+                        surplus,
+                        self.source_price,
+                        round_direction,
+                    )
+                };
+                // reaching the targets costs the most and pays the least
+                let source_target = target(RoundDirection::Ceiling)?;
+                let back_to_target = source_target.checked_sub(self.source)?;
+                if amount < back_to_target {
+                    let output = integrate(
+                        self.k,
+                        target(RoundDirection::Floor)?,
+                        self.source.checked_add(amount)?,
+                        self.source,
+                        self.source_price,
+                    )?;
+                    Some((output.min(surplus), Short::Source, source_target))
+                } else if amount == back_to_target {
+                    Some((surplus, Short::Neither, source_target))
+                } else {
+                    let beyond = self.away(
+                        self.destination_target,
+                        self.destination_target,
+                        amount - back_to_target,
+                    )?;
+                    Some((
+                        surplus.checked_add(beyond)?,
+                        Short::Destination,
+                        source_target,
+                    ))
+                }
+            }
+        }
+    }
+
+    // Destination tokens for `amount` source tokens moving the destination
+    // reserve from `destination` further below `destination_target`
+    fn away(&self, destination_target: u128, destination: u128, amount: u128) -> Option<u128> {
+        solve_trade(
+            self.k,
+            destination_target,
+            destination,
+            amount,
+            self.destination_price,
+        )
+    }
+}
+
+// Price fractions of token 0 in token 1 and of token 1 in token 0
+fn prices(oracle_price: PriceQ64) -> Option<(Ratio, Ratio)> {
+    if oracle_price == PriceQ64::ZERO {
+        return None;
+    }
+    let (raw, one) = (U512::from(oracle_price.raw()), U512::from(Q64));
+    Some(((raw, one), (one, raw)))
+}
+
+fn narrow(value: U512) -> Option<u128> {
+    (value.bits() <= 128).then(|| value.low_u128())
+}
+
+fn ceil_div(numerator: U512, denominator: U512) -> U512 {
+    let quotient = numerator / denominator;
+    if quotient * denominator < numerator {
+        quotient + 1
+    } else {
+        quotient
+    }
+}
+
+// Tokens of the other side paid for moving a reserve with target `v0` from
+// `v1` down to `v2`, or received for moving it from `v2` up to `v1`,
+// rounded down:
+//
+// price * (v1 - v2) * (1 - k + k * v0^2 / (v1 * v2))
+fn integrate(k: u64, v0: u128, v1: u128, v2: u128, (num, den): Ratio) -> Option<u128> {
+    let (k, denominator) = (U512::from(k), U512::from(PMM_K_DENOMINATOR));
+    let (v0, v1, v2) = (U512::from(v0), U512::from(v1), U512::from(v2));
+    let product = v1.checked_mul(v2)?;
+    let fair = num.checked_mul(v1.checked_sub(v2)?)?;
+    if product.is_zero() {
+        // only a curve without slippage can reach an empty reserve
+        return if k.is_zero() {
+            narrow(fair / den)
+        } else {
+            None
+        };
+    }
+    let penalty = (denominator - k)
+        .checked_mul(product)?
+        .checked_add(k.checked_mul(v0.checked_mul(v0)?)?)?;
+    narrow(fair.checked_mul(penalty)? / den.checked_mul(denominator)?.checked_mul(product)?)
+}
+
+// Reserve `v1` below its target `v0` gives up `v1 - v2` for `delta` tokens
+// of the other side at `price` other tokens each, with `v2` solving
+//
+// delta / price = (v1 - v2) * (1 - k + k * v0^2 / (v1 * v2))
+//
+// Rounds `v2` up, so the output is rounded down.
+fn solve_trade(k: u64, v0: u128, v1: u128, delta: u128, (num, den): Ratio) -> Option<u128> {
+    if v1 == 0 {
+        return Some(0);
+    }
+    let (k, denominator) = (U512::from(k), U512::from(PMM_K_DENOMINATOR));
+    let (v0, v1_wide, delta) = (U512::from(v0), U512::from(v1), U512::from(delta));
+    // (1 - k) * v2^2 + (k * v0^2 / v1 + delta / price - (1 - k) * v1) * v2
+    // - k * v0^2 = 0, scaled by `denominator * num * v1`
+    let a = (denominator - k).checked_mul(num)?.checked_mul(v1_wide)?;
+    let penalty = k.checked_mul(num)?.checked_mul(v0.checked_mul(v0)?)?;
+    let c = penalty.checked_mul(v1_wide)?;
+    let positive = penalty.checked_add(
+        denominator
+            .checked_mul(delta)?
+            .checked_mul(den)?
+            .checked_mul(v1_wide)?,
+    )?;
+    let negative = a.checked_mul(v1_wide)?;
+    let v2 = if a.is_zero() {
+        if positive.is_zero() {
+            return None;
+        }
+        ceil_div(c, positive)
+    } else {
+        let (b, b_negative) = if positive >= negative {
+            (positive - negative, false)
+        } else {
+            (negative - positive, true)
+        };
+        let root = b
+            .checked_mul(b)?
+            .checked_add(a.checked_mul(c)?.checked_mul(U512::from(4))?)?
+            .sqrt_ceil();
+        let numerator = if b_negative { root + b } else { root - b };
+        ceil_div(numerator, a << 1)
+    };
+    Some(v1 - narrow(v2)?.min(v1))
+}
+
+// Target `v0` of a reserve at `v1` whose other side holds `delta` more than
+// its target, at `price` other tokens each, solving
+//
+// delta / price = (v0 - v1) * (1 - k + k * v0 / v1)
+fn solve_target(
+    k: u64,
+    v1: u128,
+    delta: u128,
+    (num, den): Ratio,
+    round_direction: RoundDirection,
+) -> Option<u128> {
+    let div = |numerator: U512, denominator: U512| match round_direction {
+        RoundDirection::Floor => numerator / denominator,
+        RoundDirection::Ceiling => ceil_div(numerator, denominator),
+    };
+    let delta = U512::from(delta).checked_mul(den)?;
+    let x = if k == 0 {
+        div(delta, num)
+    } else {
+        if v1 == 0 {
+            return None;
+        }
+        // with v0 = v1 + x: k * x^2 + v1 * x - delta / price * v1 = 0,
+        // scaled by `denominator * num`
+        let (k, denominator) = (U512::from(k), U512::from(PMM_K_DENOMINATOR));
+        let b = denominator.checked_mul(num)?.checked_mul(U512::from(v1))?;
+        let discriminant = b.checked_mul(b)?.checked_add(
+            k.checked_mul(denominator)?
+                .checked_mul(delta)?
+                .checked_mul(num)?
+                .checked_mul(U512::from(v1))?
+                .checked_mul(U512::from(4))?,
+        )?;
+        let root = match round_direction {
+            RoundDirection::Floor => discriminant.sqrt_floor(),
+            RoundDirection::Ceiling => discriminant.sqrt_ceil(),
+        };
+        div(root - b, (k * num) << 1)
+    };
+    v1.checked_add(narrow(x)?)
+}
+
+fn share(
+    lp_amount: LpAmount,
+    lp_supply: LpAmount,
+    reserves: Reserves,
+    round_direction: RoundDirection,
+) -> Option<TradingTokenResult> {
+    CurveCalculator::lp_tokens_to_trading_tokens(
+        lp_amount.get(),
+        lp_supply.get(),
+        reserves.token_0.get(),
+        reserves.token_1.get(),
+        round_direction,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::curve::fees::{FeeRate, ProtocolFeeMode, TradeFeeSide},
+        proptest::prelude::*,
+    };
+
+    fn pool(k: u64, token_0: u128, token_1: u128) -> PmmPool {
+        PmmPool::new(
+            k,
+            FeeConfig::default(),
+            Reserves::from_raw(token_0, token_1),
+        )
+        .unwrap()
+    }
+
+    // Value of the targets in token 1 at `oracle_price`
+    fn target_value(pool: &PmmPool, oracle_price: PriceQ64) -> u128 {
+        let targets = pool.adjusted_targets(oracle_price).unwrap();
+        oracle_price
+            .mul_amount(targets.token_0.get(), RoundDirection::Floor)
+            .unwrap()
+            + targets.token_1.get()
+    }
+
+    #[test]
+    fn k_bounds() {
+        assert!(
+            PmmPool::new(
+                PMM_K_DENOMINATOR,
+                FeeConfig::default(),
+                Reserves::from_raw(1, 1)
+            )
+            .is_some()
+        );
+        assert_eq!(
+            PmmPool::new(
+                PMM_K_DENOMINATOR + 1,
+                FeeConfig::default(),
+                Reserves::from_raw(1, 1)
+            ),
+            None
+        );
+        assert_eq!(
+            pool(0, 1, 1).swap_exact_in(PriceQ64::ZERO, TradeDirection::ZeroForOne, 1),
+            None
+        );
+    }
+
+    #[test]
+    fn fees_are_only_charged_on_the_input() {
+        let fee_config = FeeConfig::STANDARD_25BPS;
+        for unsupported in [
+            fee_config.with_trade_fee_side(TradeFeeSide::Output),
+            fee_config.with_protocol_fee_mode(ProtocolFeeMode::Split),
+        ] {
+            let reserves = Reserves::from_raw(1_000_000, 1_000_000);
+            assert_eq!(PmmPool::new(0, unsupported, reserves), None);
+            let mut pool = PmmPool::new(0, fee_config, reserves).unwrap();
+            pool.fee_config = unsupported;
+            let before = pool;
+            assert_eq!(
+                pool.swap_exact_in(PriceQ64::ONE, TradeDirection::ZeroForOne, 1_000),
+                None
+            );
+            assert_eq!(pool, before);
+        }
+    }
+
+    #[test]
+    fn without_slippage_trades_at_the_oracle_price() {
+        let price = PriceQ64::from_integer(2);
+        let mut pmm = pool(0, 1_000, 1_000);
+        let result = pmm
+            .swap_exact_in(price, TradeDirection::ZeroForOne, 100)
+            .unwrap();
+        assert_eq!(result.destination_amount_swapped, 200);
+        assert_eq!(pmm.state, PmmState::Token1Short);
+        // back to the targets exactly
+        let result = pmm
+            .swap_exact_in(price, TradeDirection::OneForZero, 200)
+            .unwrap();
+        assert_eq!(result.destination_amount_swapped, 100);
+        assert_eq!(pmm.state, PmmState::AtTarget);
+        assert_eq!(pmm.reserves, Reserves::from_raw(1_000, 1_000));
+        // the reserve runs out at the oracle price
+        assert_eq!(
+            pmm.swap_exact_in(price, TradeDirection::ZeroForOne, 600)
+                .unwrap()
+                .destination_amount_swapped,
+            1_000
+        );
+    }
+
+    #[test]
+    fn slippage_grows_with_k() {
+        let price = PriceQ64::from_integer(3);
+        let outputs: Vec<u128> = [0, 100_000, 500_000, PMM_K_DENOMINATOR]
+            .into_iter()
+            .map(|k| {
+                pool(k, 1_000_000, 3_000_000)
+                    .swap_exact_in(price, TradeDirection::ZeroForOne, 100_000)
+                    .unwrap()
+                    .destination_amount_swapped
+            })
+            .collect();
+        assert_eq!(outputs[0], 300_000);
+        assert!(outputs.windows(2).all(|pair| pair[0] > pair[1]));
+        // at k = 1 the pool prices like a constant product of its targets
+        assert_eq!(
+            outputs[3],
+            3_000_000 - (3_000_000u128 * 1_000_000).div_ceil(1_100_000)
+        );
+    }
+
+    #[test]
+    fn targets_follow_the_oracle() {
+        let mut pmm = pool(200_000, 1_000_000, 1_000_000);
+        let price = PriceQ64::ONE;
+        pmm.swap_exact_in(price, TradeDirection::ZeroForOne, 200_000)
+            .unwrap();
+        let targets = pmm.adjusted_targets(price).unwrap();
+        assert_eq!(targets.token_0.get(), 1_000_000);
+        assert!(targets.token_1.get() <= 1_000_000);
+        // a higher price for token 0 makes its surplus worth more token 1
+        let higher = pmm.adjusted_targets(PriceQ64::from_integer(2)).unwrap();
+        assert!(higher.token_1 > targets.token_1);
+    }
+
+    #[test]
+    fn fees_raise_the_targets() {
+        let fee_config = FeeConfig::new(
+            FeeRate::from_bps(30).unwrap(),
+            FeeRate::from_percent(10).unwrap(),
+        );
+        let mut pmm = PmmPool::new(
+            100_000,
+            fee_config,
+            Reserves::from_raw(1_000_000, 1_000_000),
+        )
+        .unwrap();
+        let price = PriceQ64::ONE;
+        let out = pmm
+            .swap_exact_in(price, TradeDirection::ZeroForOne, 100_000)
+            .unwrap()
+            .destination_amount_swapped;
+        pmm.swap_exact_in(price, TradeDirection::OneForZero, out)
+            .unwrap();
+        assert_eq!(pmm.protocol_fees.token_0.get(), 30);
+        assert!(target_value(&pmm, price) > 2_000_000);
+    }
+
+    #[test]
+    fn withdrawing_everything_empties_the_pool() {
+        let mut pmm = pool(100_000, 1_000, 2_000);
+        pmm.deposit(LpAmount(50), LpAmount(100)).unwrap();
+        assert_eq!(pmm.reserves, Reserves::from_raw(1_500, 3_000));
+        assert_eq!(pmm.targets, pmm.reserves);
+        let result = pmm.withdraw(LpAmount(150), LpAmount(150)).unwrap();
+        assert_eq!(
+            (result.token_0_amount, result.token_1_amount),
+            (1_500, 3_000)
+        );
+        assert_eq!(pmm.reserves, Reserves::default());
+        assert_eq!(pmm.withdraw(LpAmount(2), LpAmount(1)), None);
+    }
+
+    #[test]
+    fn failed_deposits_leave_the_pool_unchanged() {
+        let mut pmm = pool(100_000, 1_000, 2_000);
+        // the reserves can grow, but not the targets
+        pmm.targets = Reserves::from_raw(u128::MAX / 2 + 1, 2_000);
+        let before = pmm;
+        assert_eq!(pmm.deposit(LpAmount(1), LpAmount(1)), None);
+        assert_eq!(pmm, before);
+    }
+
+    fn direction(zero_for_one: bool) -> TradeDirection {
+        if zero_for_one {
+            TradeDirection::ZeroForOne
+        } else {
+            TradeDirection::OneForZero
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn round_trips_never_profit(
+            k in 0..=PMM_K_DENOMINATOR,
+            price in Q64 / 100..Q64 * 100,
+            token_0 in 1_000_000..1_000_000_000_000u128,
+            token_1 in 1_000_000..1_000_000_000_000u128,
+            setup in proptest::collection::vec((any::<bool>(), 0..1_000_000_000u128), 0..4),
+            zero_for_one in any::<bool>(),
+            amount in 1..1_000_000_000u128,
+        ) {
+            let price = PriceQ64::from_raw(price);
+            let mut pmm = pool(k, token_0, token_1);
+            for (zero_for_one, amount) in setup {
+                let _ = pmm.swap_exact_in(price, direction(zero_for_one), amount);
+            }
+            let before = pmm;
+            let Some(out) = pmm.swap_exact_in(price, direction(zero_for_one), amount) else {
+                return Ok(());
+            };
+            if let Some(back) =
+                pmm.swap_exact_in(price, direction(!zero_for_one), out.destination_amount_swapped)
+            {
+                prop_assert!(back.destination_amount_swapped <= amount);
+            }
+            // selling in two parts pays no more than at once
+            let mut split = before;
+            let half = amount / 2;
+            let first = split.swap_exact_in(price, direction(zero_for_one), half);
+            let second = split.swap_exact_in(price, direction(zero_for_one), amount - half);
+            if let (Some(first), Some(second)) = (first, second) {
+                prop_assert!(
+                    first.destination_amount_swapped + second.destination_amount_swapped
+                        <= out.destination_amount_swapped + 2
+                );
+            }
+        }
+
+        #[test]
+        fn trades_never_lower_the_target_value(
+            k in 0..=PMM_K_DENOMINATOR,
+            price in Q64 / 100..Q64 * 100,
+            token_0 in 1_000_000..1_000_000_000_000u128,
+            token_1 in 1_000_000..1_000_000_000_000u128,
+            trades in proptest::collection::vec((any::<bool>(), 1..1_000_000_000u128), 1..8),
+        ) {
+            let price = PriceQ64::from_raw(price);
+            let mut pmm = pool(k, token_0, token_1);
+            let mut value = target_value(&pmm, price);
+            for (zero_for_one, amount) in trades {
+                if pmm.swap_exact_in(price, direction(zero_for_one), amount).is_none() {
+                    continue;
+                }
+                let after = target_value(&pmm, price);
+                prop_assert!(after >= value, "{} < {}", after, value);
+                value = after;
+            }
+        }
+    }
+}
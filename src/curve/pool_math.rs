@@ -0,0 +1,447 @@
+//! Pool math facade
+//!
+//! The calculator functions take a pool's reserves, LP supply, fee rates
+//! and curve as loose arguments on every call. `PoolMath` bundles them once
+//! so callers quote swaps, deposits and withdrawals against a pool by the
+//! amounts that actually vary.
+//!
+//! It also carries the pool's `RoundingPolicy`, so a quote rounds every
+//! amount consistently. Swaps round other than `RoundingPolicy::SAFE` only
+//! on constant product pools whose fees are taken from the input.
+use crate::{
+    curve::{
+        amount::Reserves,
+        calculator::{
+            CurveCalculator, DepositResult, SwapResult, TradeDirection, TradingTokenResult,
+        },
+        fees::{FeeConfig, ProtocolFeeMode, TinySwapPolicy, TradeFeeSide},
+        leveraged::LeveragedReserves,
+        params::CurveParams,
+        rounding::{RoundedFee, RoundingPolicy},
+        weighted::WeightedCurve,
+    },
+    state::PoolState,
+    utils::PriceQ64,
+};
+
+/// A pool's reserves, LP supply, fee rates and curve, for quoting against
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoolMath {
+    reserves: Reserves,
+    lp_token_supply: u128,
+    fee_config: FeeConfig,
+    curve_params: CurveParams,
+    now: u64,
+    rounding_policy: RoundingPolicy,
+}
+
+impl PoolMath {
+    /// Math for a pool with `reserves`, charging `fee_config` on the curve
+    /// configured by `curve_params`, with no LP supply, at time zero and
+    /// rounding per `RoundingPolicy::SAFE`
+    pub const fn new(reserves: Reserves, fee_config: FeeConfig, curve_params: CurveParams) -> Self {
+        Self {
+            reserves,
+            lp_token_supply: 0,
+            fee_config,
+            curve_params,
+            now: 0,
+            rounding_policy: RoundingPolicy::SAFE,
+        }
+    }
+
+    /// The same pool with `lp_token_supply` LP tokens outstanding
+    pub const fn with_lp_token_supply(self, lp_token_supply: u128) -> Self {
+        Self {
+            lp_token_supply,
+            ..self
+        }
+    }
+
+    /// The same pool at `now`, for curves whose parameters ramp over time
+    pub const fn at(self, now: u64) -> Self {
+        Self { now, ..self }
+    }
+
+    /// The same pool, rounding per `rounding_policy`
+    pub const fn with_rounding_policy(self, rounding_policy: RoundingPolicy) -> Self {
+        Self {
+            rounding_policy,
+            ..self
+        }
+    }
+
+    /// Math for `state` at `now`.
+    ///
+    /// Returns `None` if the state's curve parameters are invalid.
+    pub fn from_state(state: &PoolState, now: u64) -> Option<Self> {
+        Some(
+            Self::new(state.reserves(), state.fee_config, state.curve_params()?)
+                .with_lp_token_supply(state.lp_supply.into())
+                .at(now),
+        )
+    }
+
+    /// The pool's reserves
+    pub const fn reserves(&self) -> Reserves {
+        self.reserves
+    }
+
+    /// Quote a swap of exactly `source_amount` in `trade_direction`.
+    ///
+    /// Returns `None` where the rounding policy cannot be applied.
+    pub fn swap_in(
+        &self,
+        trade_direction: TradeDirection,
+        source_amount: u128,
+    ) -> Option<SwapResult> {
+        if !self.rounding_policy.rounds_swaps_safely() {
+            let (swap_source_amount, swap_destination_amount) =
+                self.reserves.source_and_destination(trade_direction);
+            return CurveCalculator::swap_base_input_with_fee_model_in_context(
+                source_amount,
+                swap_source_amount,
+                swap_destination_amount,
+                &self.rounded_fee()?,
+                TinySwapPolicy::Clamp,
+                &self.rounding_policy,
+            )
+            .ok();
+        }
+        self.curve_params.swap_exact_in(
+            self.now,
+            trade_direction,
+            source_amount,
+            self.reserves,
+            self.fee_config,
+        )
+    }
+
+    /// Quote a swap receiving exactly `destination_amount` in
+    /// `trade_direction`.
+    ///
+    /// Only the constant product curve quotes exact-out swaps; returns
+    /// `None` for other curves, for outputs the pool cannot pay, and where
+    /// the rounding policy cannot be applied.
+    pub fn swap_out(
+        &self,
+        trade_direction: TradeDirection,
+        destination_amount: u128,
+    ) -> Option<SwapResult> {
+        let CurveParams::ConstantProduct = self.curve_params else {
+            return None;
+        };
+        let (_, swap_destination_amount) = self.reserves.source_and_destination(trade_direction);
+        // the curve cannot pay out its whole reserve
+        if destination_amount >= swap_destination_amount {
+            return None;
+        }
+        if !self.rounding_policy.rounds_swaps_safely() {
+            let (swap_source_amount, _) = self.reserves.source_and_destination(trade_direction);
+            return CurveCalculator::swap_base_output_with_fee_model_in_context(
+                destination_amount,
+                swap_source_amount,
+                swap_destination_amount,
+                &self.rounded_fee()?,
+                &self.rounding_policy,
+            )
+            .ok();
+        }
+        CurveCalculator::swap_exact_out(
+            trade_direction,
+            destination_amount,
+            self.reserves,
+            self.fee_config,
+        )
+    }
+
+    /// Quote a deposit of at most the given amounts, as
+    /// `CurveCalculator::deposit_trading_tokens` does, with the price of the
+    /// minted LP tokens rounded per the policy's `lp_mint`
+    pub fn deposit(
+        &self,
+        maximum_token_0_amount: u128,
+        maximum_token_1_amount: u128,
+    ) -> Option<DepositResult> {
+        CurveCalculator::deposit_trading_tokens_rounded(
+            maximum_token_0_amount,
+            maximum_token_1_amount,
+            self.lp_token_supply,
+            self.reserves.token_0.get(),
+            self.reserves.token_1.get(),
+            self.rounding_policy.lp_mint,
+        )
+    }
+
+    /// Quote burning `lp_token_amount` LP tokens, rounded per the policy's
+    /// `lp_burn`.
+    ///
+    /// Returns `None` if `lp_token_amount` is zero or exceeds the LP supply.
+    pub fn withdraw(&self, lp_token_amount: u128) -> Option<TradingTokenResult> {
+        if lp_token_amount == 0 || lp_token_amount > self.lp_token_supply {
+            return None;
+        }
+        CurveCalculator::lp_tokens_to_trading_tokens(
+            lp_token_amount,
+            self.lp_token_supply,
+            self.reserves.token_0.get(),
+            self.reserves.token_1.get(),
+            self.rounding_policy.lp_burn,
+        )
+    }
+
+    // The pool's fees rounded per the policy, for the constant product
+    // curve charging them on the input
+    fn rounded_fee(&self) -> Option<RoundedFee> {
+        let fee_config = self.fee_config;
+        let supported = matches!(self.curve_params, CurveParams::ConstantProduct)
+            && fee_config.trade_fee_side == TradeFeeSide::Input
+            && fee_config.protocol_fee_mode == ProtocolFeeMode::Input
+            && fee_config.is_valid();
+        supported.then_some(RoundedFee {
+            fee_config,
+            round_direction: self.rounding_policy.fee,
+        })
+    }
+
+    /// Spot price of token 0 in token 1, rounded down.
+    ///
+    /// Returns `None` for an empty reserve, for the stable and pegged
+    /// curves, which have no closed-form spot price, and for the PMM and
+    /// repegging curves, which need more than the reserves to price.
+    pub fn spot_price(&self) -> Option<PriceQ64> {
+        match self.curve_params {
+            CurveParams::ConstantProduct => {
+                PriceQ64::from_reserves(self.reserves.token_0.get(), self.reserves.token_1.get())
+            }
+            CurveParams::Weighted(schedule) => {
+                WeightedCurve::spot_price(self.reserves, schedule.weights_at(self.now))
+            }
+            CurveParams::Leveraged { offsets } => {
+                let virtual_reserves =
+                    LeveragedReserves::from_offsets(self.reserves, offsets)?.virtual_reserves;
+                PriceQ64::from_reserves(
+                    virtual_reserves.token_0.get(),
+                    virtual_reserves.token_1.get(),
+                )
+            }
+            CurveParams::Stable(_)
+            | CurveParams::Pegged(_)
+            | CurveParams::Pmm { .. }
+            | CurveParams::CryptoSwap(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::curve::{
+            amount::{Token0Amount, Token1Amount},
+            calculator::RoundDirection,
+            fees::FeeRate,
+            stable::AmpRamp,
+            weighted::{WeightSchedule, Weights},
+        },
+    };
+
+    fn reserves() -> Reserves {
+        Reserves::new(Token0Amount(1_000_000), Token1Amount(4_000_000))
+    }
+
+    fn fee_config() -> FeeConfig {
+        FeeConfig::new(
+            FeeRate::from_bps(25).unwrap(),
+            FeeRate::from_percent(12).unwrap(),
+        )
+    }
+
+    #[test]
+    fn constant_product_matches_calculator() {
+        let math = PoolMath::new(reserves(), fee_config(), CurveParams::ConstantProduct)
+            .with_lp_token_supply(2_000_000);
+        assert_eq!(
+            math.swap_in(TradeDirection::ZeroForOne, 10_000),
+            CurveCalculator::swap_exact_in(
+                TradeDirection::ZeroForOne,
+                10_000,
+                reserves(),
+                fee_config()
+            )
+        );
+        assert_eq!(
+            math.swap_out(TradeDirection::OneForZero, 10_000),
+            CurveCalculator::swap_exact_out(
+                TradeDirection::OneForZero,
+                10_000,
+                reserves(),
+                fee_config()
+            )
+        );
+        assert_eq!(math.swap_out(TradeDirection::OneForZero, 1_000_000), None);
+        assert_eq!(math.spot_price(), Some(PriceQ64::from_integer(4)));
+
+        let deposit = math.deposit(1_000, 10_000).unwrap();
+        assert_eq!(deposit.lp_token_amount, 2_000);
+        assert_eq!(deposit.unused_token_1, 6_000);
+        let withdrawal = math.withdraw(2_000_000).unwrap();
+        assert_eq!(withdrawal.token_0_amount, 1_000_000);
+        assert_eq!(withdrawal.token_1_amount, 4_000_000);
+        assert_eq!(math.withdraw(2_000_001), None);
+        assert_eq!(math.withdraw(0), None);
+    }
+
+    #[test]
+    fn other_curves_dispatch_on_their_params() {
+        let schedule = WeightSchedule::new(
+            Weights::new(200_000).unwrap(),
+            Weights::new(800_000).unwrap(),
+            0,
+            100,
+        )
+        .unwrap();
+        let weighted = PoolMath::new(reserves(), fee_config(), CurveParams::Weighted(schedule));
+        // weights move over the schedule, and the spot price with them
+        assert_eq!(
+            weighted.at(50).spot_price(),
+            WeightedCurve::spot_price(reserves(), schedule.weights_at(50))
+        );
+        assert!(weighted.at(100).spot_price() > weighted.spot_price());
+        assert_eq!(
+            weighted.at(50).swap_in(TradeDirection::ZeroForOne, 10_000),
+            CurveParams::Weighted(schedule).swap_exact_in(
+                50,
+                TradeDirection::ZeroForOne,
+                10_000,
+                reserves(),
+                fee_config()
+            )
+        );
+        assert_eq!(weighted.swap_out(TradeDirection::ZeroForOne, 10_000), None);
+
+        let stable = PoolMath::new(
+            reserves(),
+            fee_config(),
+            CurveParams::Stable(AmpRamp::new(100).unwrap()),
+        );
+        assert!(stable.swap_in(TradeDirection::ZeroForOne, 10_000).is_some());
+        assert_eq!(stable.spot_price(), None);
+    }
+
+    #[test]
+    fn rounding_policy_applies_to_every_quote() {
+        let safe = PoolMath::new(reserves(), fee_config(), CurveParams::ConstantProduct)
+            .with_lp_token_supply(3_000_000);
+        let explicit = safe.with_rounding_policy(RoundingPolicy::SAFE);
+        assert_eq!(
+            explicit.swap_in(TradeDirection::ZeroForOne, 10_001),
+            safe.swap_in(TradeDirection::ZeroForOne, 10_001)
+        );
+        assert_eq!(explicit.withdraw(7), safe.withdraw(7));
+
+        let generous = safe.with_rounding_policy(RoundingPolicy {
+            swap_output: RoundDirection::Ceiling,
+            exact_out_input: RoundDirection::Floor,
+            fee: RoundDirection::Floor,
+            lp_mint: RoundDirection::Floor,
+            lp_burn: RoundDirection::Ceiling,
+        });
+        // 10_001 in: a fee of 25.0025, leaving 9_975 * 4_000_000 / 1_009_975 =
+        // 39_505.93 out, or 9_976 * 4_000_000 / 1_009_976 = 39_509.85 with
+        // the fee rounded down
+        let (safe_in, generous_in) = (
+            safe.swap_in(TradeDirection::ZeroForOne, 10_001).unwrap(),
+            generous
+                .swap_in(TradeDirection::ZeroForOne, 10_001)
+                .unwrap(),
+        );
+        assert_eq!(
+            (safe_in.trade_fee, safe_in.destination_amount_swapped),
+            (26, 39_505)
+        );
+        assert_eq!(
+            (
+                generous_in.trade_fee,
+                generous_in.destination_amount_swapped
+            ),
+            (25, 39_510)
+        );
+        let (safe_out, generous_out) = (
+            safe.swap_out(TradeDirection::OneForZero, 10_000).unwrap(),
+            generous
+                .swap_out(TradeDirection::OneForZero, 10_000)
+                .unwrap(),
+        );
+        assert!(generous_out.source_amount_swapped < safe_out.source_amount_swapped);
+        assert!(generous_out.trade_fee <= safe_out.trade_fee);
+
+        // 7 / 3 of a token 0 and 28 / 3 of a token 1
+        let withdrawal = generous.withdraw(7).unwrap();
+        assert_eq!(
+            (withdrawal.token_0_amount, withdrawal.token_1_amount),
+            (3, 10)
+        );
+        let withdrawal = safe.withdraw(7).unwrap();
+        assert_eq!(
+            (withdrawal.token_0_amount, withdrawal.token_1_amount),
+            (2, 9)
+        );
+        // 2_000 LP tokens minted for 2_000 / 3 of a token 0 and 8_000 / 3 of
+        // a token 1
+        let deposit = |math: PoolMath| {
+            let deposit = math.deposit(1_000, 2_667).unwrap();
+            assert_eq!(deposit.lp_token_amount, 2_000);
+            (
+                deposit.trading_tokens.token_0_amount,
+                deposit.trading_tokens.token_1_amount,
+            )
+        };
+        assert_eq!(deposit(safe), (667, 2_667));
+        assert_eq!(deposit(generous), (666, 2_666));
+
+        // other curves, and fees off the input, only round safely
+        let stable = PoolMath::new(
+            reserves(),
+            fee_config(),
+            CurveParams::Stable(AmpRamp::new(100).unwrap()),
+        );
+        assert!(stable.swap_in(TradeDirection::ZeroForOne, 10_000).is_some());
+        assert_eq!(
+            stable
+                .with_rounding_policy(generous.rounding_policy)
+                .swap_in(TradeDirection::ZeroForOne, 10_000),
+            None
+        );
+        let fee_on_output = PoolMath::new(
+            reserves(),
+            fee_config().with_trade_fee_side(TradeFeeSide::Output),
+            CurveParams::ConstantProduct,
+        )
+        .with_rounding_policy(generous.rounding_policy);
+        assert_eq!(
+            fee_on_output.swap_in(TradeDirection::ZeroForOne, 10_000),
+            None
+        );
+    }
+
+    #[test]
+    fn from_state_reads_the_pool() {
+        let mut state = PoolState {
+            fee_config: fee_config(),
+            token_0_reserve: 1_000_000,
+            token_1_reserve: 4_000_000,
+            lp_supply: 2_000_000,
+            ..PoolState::default()
+        };
+        let math = PoolMath::from_state(&state, 7).unwrap();
+        assert_eq!(
+            math,
+            PoolMath::new(reserves(), fee_config(), CurveParams::ConstantProduct)
+                .with_lp_token_supply(2_000_000)
+                .at(7)
+        );
+        state.curve_params[0] = u8::MAX;
+        assert_eq!(PoolMath::from_state(&state, 7), None);
+    }
+}
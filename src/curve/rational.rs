@@ -0,0 +1,279 @@
+//! Exact rational oracle for differential testing
+//!
+//! Recomputes swaps and LP conversions with arbitrary-precision rationals, so
+//! the integer results can be checked against the exact values they
+//! approximate: every rounded amount must be within one unit of the exact
+//! value, and must round in the pool's favor.
+use {
+    crate::curve::{
+        calculator::{CurveCalculator, RoundDirection, SwapResult, TradingTokenResult},
+        fees::FEE_RATE_DENOMINATOR_VALUE,
+        swap_params::SwapParams,
+    },
+    num_bigint::BigInt,
+    num_rational::BigRational,
+};
+
+fn rational(value: u128) -> BigRational {
+    BigRational::from_integer(BigInt::from(value))
+}
+
+fn fee_rate(rate: u64) -> BigRational {
+    BigRational::new(BigInt::from(rate), BigInt::from(FEE_RATE_DENOMINATOR_VALUE))
+}
+
+// Asserts `exact <= value < exact + 1`
+fn assert_rounded_up(value: u128, exact: &BigRational, what: &str) {
+    let value = rational(value);
+    assert!(
+        value >= *exact,
+        "{what} {value} rounded below exact {exact}"
+    );
+    assert!(
+        value < exact + rational(1),
+        "{what} {value} more than one unit above exact {exact}"
+    );
+}
+
+// Asserts `exact - 1 < value <= exact`
+fn assert_rounded_down(value: u128, exact: &BigRational, what: &str) {
+    let value = rational(value);
+    assert!(
+        value <= *exact,
+        "{what} {value} rounded above exact {exact}"
+    );
+    assert!(
+        value > exact - rational(1),
+        "{what} {value} more than one unit below exact {exact}"
+    );
+}
+
+/// Exact output of the constant product curve, `y * dx / (x + dx)`
+pub fn swap_base_input_without_fees(
+    source_amount: u128,
+    swap_source_amount: u128,
+    swap_destination_amount: u128,
+) -> BigRational {
+    rational(swap_destination_amount) * rational(source_amount)
+        / (rational(swap_source_amount) + rational(source_amount))
+}
+
+/// Exact input required by the constant product curve, `x * dy / (y - dy)`
+pub fn swap_base_output_without_fees(
+    destination_amount: u128,
+    swap_source_amount: u128,
+    swap_destination_amount: u128,
+) -> BigRational {
+    rational(swap_source_amount) * rational(destination_amount)
+        / (rational(swap_destination_amount) - rational(destination_amount))
+}
+
+/// Check an exact-input swap against the rational oracle.
+///
+/// The trade fee must be the exact fee rounded up, the protocol fee its exact
+/// share rounded down, and the output the exact curve output for the
+/// post-fee input rounded down.
+pub fn check_swap_base_input(
+    result: &SwapResult,
+    source_amount: u128,
+    swap_source_amount: u128,
+    swap_destination_amount: u128,
+    trade_fee_rate: u64,
+    protocol_fee_rate: u64,
+) {
+    let exact_trade_fee = rational(source_amount) * fee_rate(trade_fee_rate);
+    assert_rounded_up(result.trade_fee, &exact_trade_fee, "trade fee");
+    let exact_protocol_fee = rational(result.trade_fee) * fee_rate(protocol_fee_rate);
+    assert_rounded_down(result.protocol_fee, &exact_protocol_fee, "protocol fee");
+    let exact_output = swap_base_input_without_fees(
+        source_amount - result.trade_fee,
+        swap_source_amount,
+        swap_destination_amount,
+    );
+    assert_rounded_down(result.destination_amount_swapped, &exact_output, "output");
+}
+
+/// Check an exact-output swap against the rational oracle.
+///
+/// The input before fees must be the exact curve input rounded up, and the
+/// total input that amount grossed up by the fee rate, rounded up.
+pub fn check_swap_base_output(
+    result: &SwapResult,
+    destination_amount: u128,
+    swap_source_amount: u128,
+    swap_destination_amount: u128,
+    trade_fee_rate: u64,
+) {
+    let exact_input = swap_base_output_without_fees(
+        destination_amount,
+        swap_source_amount,
+        swap_destination_amount,
+    );
+    // the integer input before fees, which the fee gross-up is applied to
+    let input_without_fees = exact_input.ceil().to_integer();
+    let input_without_fees = BigRational::from_integer(input_without_fees);
+    let exact_gross = input_without_fees / (rational(1) - fee_rate(trade_fee_rate));
+    assert_rounded_up(result.source_amount_swapped, &exact_gross, "input");
+    assert!(
+        rational(result.source_amount_swapped - result.trade_fee) >= exact_input,
+        "input after fees is below the exact curve input"
+    );
+}
+
+/// Check an LP token conversion against the rational oracle.
+///
+/// Withdrawals must round each amount down; deposits must round up, except
+/// for amounts worth less than one token, which are returned as zero for the
+/// caller to reject.
+pub fn check_lp_tokens_to_trading_tokens(
+    result: &TradingTokenResult,
+    lp_token_amount: u128,
+    lp_token_supply: u128,
+    swap_token_0_amount: u128,
+    swap_token_1_amount: u128,
+    round_direction: RoundDirection,
+) {
+    let share = rational(lp_token_amount) / rational(lp_token_supply);
+    for (amount, reserve) in [
+        (result.token_0_amount, swap_token_0_amount),
+        (result.token_1_amount, swap_token_1_amount),
+    ] {
+        let exact = &share * rational(reserve);
+        match round_direction {
+            RoundDirection::Floor => assert_rounded_down(amount, &exact, "withdrawal"),
+            RoundDirection::Ceiling if amount == 0 => {
+                assert!(exact < rational(1), "deposit of {exact} rounded to zero")
+            }
+            RoundDirection::Ceiling => assert_rounded_up(amount, &exact, "deposit"),
+        }
+    }
+}
+
+/// Run an exact-input swap and check it against the oracle
+pub fn differential_swap_base_input(
+    source_amount: u128,
+    swap_source_amount: u128,
+    swap_destination_amount: u128,
+    trade_fee_rate: u64,
+    protocol_fee_rate: u64,
+) -> Option<SwapResult> {
+    let result = CurveCalculator::swap_base_input(&SwapParams::from_raw_parts(
+        source_amount,
+        swap_source_amount,
+        swap_destination_amount,
+        trade_fee_rate,
+        protocol_fee_rate,
+    )?)?;
+    check_swap_base_input(
+        &result,
+        source_amount,
+        swap_source_amount,
+        swap_destination_amount,
+        trade_fee_rate,
+        protocol_fee_rate,
+    );
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, proptest::prelude::*};
+
+    #[test]
+    fn oracle_catches_favorable_rounding() {
+        let result = CurveCalculator::swap_base_input(
+            &SwapParams::from_raw_parts(1_000, 50_000, 80_000, 2_500, 120_000).unwrap(),
+        )
+        .unwrap();
+        let favorable = SwapResult {
+            destination_amount_swapped: result.destination_amount_swapped + 1,
+            ..result
+        };
+        let check = std::panic::catch_unwind(|| {
+            check_swap_base_input(&favorable, 1_000, 50_000, 80_000, 2_500, 120_000)
+        });
+        assert!(check.is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn swap_base_input_matches_oracle(
+            source_amount in 1..u64::MAX,
+            swap_source_amount in 1..u64::MAX,
+            swap_destination_amount in 1..u64::MAX,
+            trade_fee_rate in 0..FEE_RATE_DENOMINATOR_VALUE,
+            protocol_fee_rate in 0..=FEE_RATE_DENOMINATOR_VALUE,
+        ) {
+            differential_swap_base_input(
+                source_amount as u128,
+                swap_source_amount as u128,
+                swap_destination_amount as u128,
+                trade_fee_rate,
+                protocol_fee_rate,
+            );
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn swap_base_output_matches_oracle(
+            swap_source_amount in 1..u64::MAX,
+            swap_destination_amount in 2..u64::MAX,
+            destination_fraction in 1..10_000u128,
+            trade_fee_rate in 0..FEE_RATE_DENOMINATOR_VALUE,
+        ) {
+            let swap_destination_amount = swap_destination_amount as u128;
+            let destination_amount = (swap_destination_amount * destination_fraction / 10_000).max(1);
+            if let Some(result) = CurveCalculator::swap_base_output(
+                &SwapParams::from_raw_parts(
+                    destination_amount,
+                    swap_source_amount as u128,
+                    swap_destination_amount,
+                    trade_fee_rate,
+                    0,
+                )
+                .unwrap(),
+            ) {
+                check_swap_base_output(
+                    &result,
+                    destination_amount,
+                    swap_source_amount as u128,
+                    swap_destination_amount,
+                    trade_fee_rate,
+                );
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn lp_conversion_matches_oracle(
+            lp_token_supply in 1..u64::MAX,
+            lp_fraction in 0..=10_000u128,
+            swap_token_0_amount in 0..u64::MAX,
+            swap_token_1_amount in 0..u64::MAX,
+        ) {
+            let lp_token_supply = lp_token_supply as u128;
+            // zero amounts are rejected before any rounding
+            let lp_token_amount = (lp_token_supply * lp_fraction / 10_000).max(1);
+            for round_direction in [RoundDirection::Floor, RoundDirection::Ceiling] {
+                let result = CurveCalculator::lp_tokens_to_trading_tokens(
+                    lp_token_amount,
+                    lp_token_supply,
+                    swap_token_0_amount as u128,
+                    swap_token_1_amount as u128,
+                    round_direction,
+                )
+                .unwrap();
+                check_lp_tokens_to_trading_tokens(
+                    &result,
+                    lp_token_amount,
+                    lp_token_supply,
+                    swap_token_0_amount as u128,
+                    swap_token_1_amount as u128,
+                    round_direction,
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,9 @@
+//! Raw `u128` calculator functions
+//!
+//! The positional `u128` entry points, for performance-critical callers that
+//! keep reserves in their own layout. Prefer the typed entry points taking
+//! `Reserves` and a `TradeDirection` elsewhere: nothing here stops token 0 and
+//! token 1 amounts from being transposed.
+pub use crate::curve::{
+    calculator::CurveCalculator, constant_product::ConstantProductCurve, fees::Fees,
+};
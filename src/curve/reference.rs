@@ -0,0 +1,259 @@
+//! Reference swap math using `PreciseNumber`
+//!
+//! These functions compute the same quantities as the integer curve and fee
+//! code, but keep 12 decimal digits of fractional precision instead of
+//! truncating to whole tokens at each step. They are far more expensive and are
+//! not meant for on-chain use: integrators and auditors can run them off-chain
+//! to cross-check the integer results, which should never be more than one
+//! token away and should never favor the trader.
+use {crate::curve::fees::FEE_RATE_DENOMINATOR_VALUE, spl_math::precise_number::PreciseNumber};
+
+/// Unrounded results of a swap
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReferenceSwapResult {
+    /// Amount of source token swapped (includes fees)
+    pub source_amount_swapped: PreciseNumber,
+    /// Amount of destination token swapped
+    pub destination_amount_swapped: PreciseNumber,
+    /// Amount of source tokens going to pool holders
+    pub trade_fee: PreciseNumber,
+    /// Amount of source tokens going to protocol
+    pub protocol_fee: PreciseNumber,
+}
+
+/// Calculates the total normalized value of the curve given the liquidity
+/// parameters.
+///
+/// The constant product implementation for this function gives the square root
+/// of the Uniswap invariant. `CurveCalculator::pool_value` is the integer
+/// version for production use.
+pub fn normalized_value(
+    swap_token_a_amount: u128,
+    swap_token_b_amount: u128,
+) -> Option<PreciseNumber> {
+    let swap_token_a_amount = PreciseNumber::new(swap_token_a_amount)?;
+    let swap_token_b_amount = PreciseNumber::new(swap_token_b_amount)?;
+    swap_token_a_amount
+        .checked_mul(&swap_token_b_amount)?
+        .sqrt()
+}
+
+/// Exact destination amount for a given source amount, without fees:
+/// `delta_y = (delta_x * y) / (x + delta_x)`
+pub fn swap_base_input_without_fees(
+    source_amount: u128,
+    swap_source_amount: u128,
+    swap_destination_amount: u128,
+) -> Option<PreciseNumber> {
+    let source_amount = PreciseNumber::new(source_amount)?;
+    let numerator = source_amount.checked_mul(&PreciseNumber::new(swap_destination_amount)?)?;
+    let denominator = PreciseNumber::new(swap_source_amount)?.checked_add(&source_amount)?;
+    numerator.checked_div(&denominator)
+}
+
+/// Exact source amount required for a given destination amount, without fees:
+/// `delta_x = (x * delta_y) / (y - delta_y)`
+pub fn swap_base_output_without_fees(
+    destination_amount: u128,
+    swap_source_amount: u128,
+    swap_destination_amount: u128,
+) -> Option<PreciseNumber> {
+    let destination_amount = PreciseNumber::new(destination_amount)?;
+    let numerator = PreciseNumber::new(swap_source_amount)?.checked_mul(&destination_amount)?;
+    let denominator =
+        PreciseNumber::new(swap_destination_amount)?.checked_sub(&destination_amount)?;
+    numerator.checked_div(&denominator)
+}
+
+/// Exact trading fee on `amount`
+pub fn trading_fee(amount: &PreciseNumber, trade_fee_rate: u64) -> Option<PreciseNumber> {
+    amount
+        .checked_mul(&PreciseNumber::new(u128::from(trade_fee_rate))?)?
+        .checked_div(&PreciseNumber::new(u128::from(FEE_RATE_DENOMINATOR_VALUE))?)
+}
+
+/// Exact protocol share of a trading fee
+pub fn protocol_fee(trade_fee: &PreciseNumber, protocol_fee_rate: u64) -> Option<PreciseNumber> {
+    trading_fee(trade_fee, protocol_fee_rate)
+}
+
+/// Reference counterpart of `CurveCalculator::swap_base_input`
+pub fn swap_base_input(
+    source_amount: u128,
+    swap_source_amount: u128,
+    swap_destination_amount: u128,
+    trade_fee_rate: u64,
+    protocol_fee_rate: u64,
+) -> Option<ReferenceSwapResult> {
+    let source_amount_swapped = PreciseNumber::new(source_amount)?;
+    let trade_fee = trading_fee(&source_amount_swapped, trade_fee_rate)?;
+    let protocol_fee = protocol_fee(&trade_fee, protocol_fee_rate)?;
+    let source_amount_less_fees = source_amount_swapped.checked_sub(&trade_fee)?;
+
+    let numerator =
+        source_amount_less_fees.checked_mul(&PreciseNumber::new(swap_destination_amount)?)?;
+    let denominator =
+        PreciseNumber::new(swap_source_amount)?.checked_add(&source_amount_less_fees)?;
+    let destination_amount_swapped = numerator.checked_div(&denominator)?;
+
+    Some(ReferenceSwapResult {
+        source_amount_swapped,
+        destination_amount_swapped,
+        trade_fee,
+        protocol_fee,
+    })
+}
+
+/// Reference counterpart of `CurveCalculator::swap_base_output`
+pub fn swap_base_output(
+    destination_amount: u128,
+    swap_source_amount: u128,
+    swap_destination_amount: u128,
+    trade_fee_rate: u64,
+    protocol_fee_rate: u64,
+) -> Option<ReferenceSwapResult> {
+    let source_amount_less_fees = swap_base_output_without_fees(
+        destination_amount,
+        swap_source_amount,
+        swap_destination_amount,
+    )?;
+    // gross up so that the fee on the gross amount leaves exactly the net amount
+    let denominator = PreciseNumber::new(u128::from(FEE_RATE_DENOMINATOR_VALUE))?;
+    let source_amount_swapped = source_amount_less_fees
+        .checked_mul(&denominator)?
+        .checked_div(&denominator.checked_sub(&PreciseNumber::new(u128::from(trade_fee_rate))?)?)?;
+    let trade_fee = trading_fee(&source_amount_swapped, trade_fee_rate)?;
+    let protocol_fee = protocol_fee(&trade_fee, protocol_fee_rate)?;
+
+    Some(ReferenceSwapResult {
+        source_amount_swapped,
+        destination_amount_swapped: PreciseNumber::new(destination_amount)?,
+        trade_fee,
+        protocol_fee,
+    })
+}
+
+/// Reference counterpart of `CurveCalculator::lp_tokens_to_trading_tokens`,
+/// returning the unrounded token 0 and token 1 amounts
+pub fn lp_tokens_to_trading_tokens(
+    lp_token_amount: u128,
+    lp_token_supply: u128,
+    swap_token_0_amount: u128,
+    swap_token_1_amount: u128,
+) -> Option<(PreciseNumber, PreciseNumber)> {
+    let share = PreciseNumber::new(lp_token_amount)?;
+    let supply = PreciseNumber::new(lp_token_supply)?;
+    let token_0_amount = share
+        .checked_mul(&PreciseNumber::new(swap_token_0_amount)?)?
+        .checked_div(&supply)?;
+    let token_1_amount = share
+        .checked_mul(&PreciseNumber::new(swap_token_1_amount)?)?
+        .checked_div(&supply)?;
+    Some((token_0_amount, token_1_amount))
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::curve::{
+            calculator::{CurveCalculator, RoundDirection},
+            constant_product::ConstantProductCurve,
+            swap_params::SwapParams,
+        },
+        proptest::prelude::*,
+    };
+
+    fn precise(value: u128) -> PreciseNumber {
+        PreciseNumber::new(value).unwrap()
+    }
+
+    /// `integer` must not exceed `reference` and be less than one token below
+    fn assert_floor_of(integer: u128, reference: &PreciseNumber) {
+        let integer = precise(integer);
+        assert!(integer.less_than_or_equal(&reference.ceiling().unwrap()));
+        assert!(
+            integer
+                .checked_add(&precise(1))
+                .unwrap()
+                .greater_than_or_equal(reference)
+        );
+    }
+
+    #[test]
+    fn reference_matches_known_swap() {
+        let exact = swap_base_input_without_fees(10, 20_000, 30_000).unwrap();
+        // 300_000 / 20_010 = 14.99250...
+        assert_eq!(exact.floor().unwrap(), precise(14));
+        assert_floor_of(
+            ConstantProductCurve::try_swap_base_input_without_fees(10, 20_000, 30_000).unwrap(),
+            &exact,
+        );
+        let exact = swap_base_output_without_fees(14, 20_000, 30_000).unwrap();
+        assert_eq!(exact.ceiling().unwrap(), precise(10));
+    }
+
+    proptest! {
+        #[test]
+        fn integer_swap_input_matches_reference(
+            source_amount in 1..u32::MAX,
+            swap_source_amount in 1..u64::MAX,
+            swap_destination_amount in 1..u64::MAX,
+            trade_fee_rate in 0..100_000u64,
+            protocol_fee_rate in 0..1_000_000u64,
+        ) {
+            let integer = CurveCalculator::swap_base_input(
+                &SwapParams::from_raw_parts(
+                    source_amount as u128,
+                    swap_source_amount as u128,
+                    swap_destination_amount as u128,
+                    trade_fee_rate,
+                    protocol_fee_rate,
+                )
+                .unwrap(),
+            )
+            .unwrap();
+            let reference = swap_base_input(
+                source_amount as u128,
+                swap_source_amount as u128,
+                swap_destination_amount as u128,
+                trade_fee_rate,
+                protocol_fee_rate,
+            ).unwrap();
+            // the fee is rounded up, so the trader receives at most the
+            // reference amount
+            prop_assert!(precise(integer.destination_amount_swapped)
+                .less_than_or_equal(&reference.destination_amount_swapped.ceiling().unwrap()));
+            prop_assert!(precise(integer.trade_fee).greater_than_or_equal(&reference.trade_fee.floor().unwrap()));
+            prop_assert!(precise(integer.trade_fee)
+                .less_than_or_equal(&reference.trade_fee.checked_add(&precise(1)).unwrap()));
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn integer_lp_conversion_matches_reference(
+            lp_token_amount in 1..u64::MAX,
+            lp_token_supply in 1..u64::MAX,
+            swap_token_0_amount in 1..u64::MAX,
+            swap_token_1_amount in 1..u64::MAX,
+        ) {
+            prop_assume!(lp_token_amount <= lp_token_supply);
+            let integer = CurveCalculator::lp_tokens_to_trading_tokens(
+                lp_token_amount as u128,
+                lp_token_supply as u128,
+                swap_token_0_amount as u128,
+                swap_token_1_amount as u128,
+                RoundDirection::Floor,
+            ).unwrap();
+            let (token_0_amount, token_1_amount) = lp_tokens_to_trading_tokens(
+                lp_token_amount as u128,
+                lp_token_supply as u128,
+                swap_token_0_amount as u128,
+                swap_token_1_amount as u128,
+            ).unwrap();
+            assert_floor_of(integer.token_0_amount, &token_0_amount);
+            assert_floor_of(integer.token_1_amount, &token_1_amount);
+        }
+    }
+}
@@ -0,0 +1,802 @@
+//! Runtime registry of curves
+//!
+//! The curves built into this crate are registered under their `CurveType`
+//! ids. Integrators with their own curves register a decoder under a
+//! `CurveType::Custom` id, and pool parameters, packed in the `CurveParams`
+//! layout, are decoded through the registry into a `SwapCurve` trait
+//! object.
+//!
+//! Built-in curves support what their `CurveParams` do, which is less than
+//! their own types: only the constant product and leveraged curves quote
+//! exact-output swaps, the weighted curve has no invariant, and the PMM and
+//! repegging curves decode but quote nothing, since they price against an
+//! oracle or a moving price scale that the parameters do not hold.
+use {
+    crate::{
+        curve::{
+            amount::Reserves,
+            calculator::{
+                CurveCalculator, RoundDirection, SwapResult, TradeDirection, TradingTokenResult,
+            },
+            constant_product::ConstantProductCurve,
+            fees::FeeConfig,
+            leveraged::LeveragedReserves,
+            params::CurveParams,
+            pegged::PeggedCurve,
+            stable::{AmpRamp, StableSwapCurve},
+            weighted::{WeightSchedule, WeightedCurve},
+        },
+        state::CurveType,
+        utils::U256,
+    },
+    alloc::{boxed::Box, collections::BTreeMap},
+};
+
+/// A curve that can quote swaps against a pool's reserves
+pub trait SwapCurve {
+    /// Quote a swap of exactly `source_amount` in `trade_direction` at
+    /// `now`
+    fn swap_exact_in(
+        &self,
+        now: u64,
+        trade_direction: TradeDirection,
+        source_amount: u128,
+        reserves: Reserves,
+        fee_config: FeeConfig,
+    ) -> Option<SwapResult>;
+
+    /// Quote a swap receiving exactly `destination_amount` in
+    /// `trade_direction` at `now`. Curves without a closed form may leave
+    /// this unsupported.
+    fn swap_exact_out(
+        &self,
+        _now: u64,
+        _trade_direction: TradeDirection,
+        _destination_amount: u128,
+        _reserves: Reserves,
+        _fee_config: FeeConfig,
+    ) -> Option<SwapResult> {
+        None
+    }
+
+    /// A value of the pool holding `reserves` at `now`, such as `x * y` for
+    /// the constant product curve, that no swap decreases once its protocol
+    /// fees are set aside. Curves without an exact one may leave this
+    /// unsupported.
+    fn invariant(&self, _now: u64, _reserves: Reserves) -> Option<U256> {
+        None
+    }
+
+    /// Trading tokens worth `lp_token_amount` out of `lp_token_supply`.
+    /// Defaults to a proportional share of both reserves.
+    fn lp_tokens_to_trading_tokens(
+        &self,
+        lp_token_amount: u128,
+        lp_token_supply: u128,
+        reserves: Reserves,
+        round_direction: RoundDirection,
+    ) -> Option<TradingTokenResult> {
+        CurveCalculator::lp_tokens_to_trading_tokens(
+            lp_token_amount,
+            lp_token_supply,
+            reserves.token_0.get(),
+            reserves.token_1.get(),
+            round_direction,
+        )
+    }
+}
+
+impl SwapCurve for ConstantProductCurve {
+    fn swap_exact_in(
+        &self,
+        _now: u64,
+        trade_direction: TradeDirection,
+        source_amount: u128,
+        reserves: Reserves,
+        fee_config: FeeConfig,
+    ) -> Option<SwapResult> {
+        CurveCalculator::swap_exact_in(trade_direction, source_amount, reserves, fee_config)
+    }
+
+    fn swap_exact_out(
+        &self,
+        _now: u64,
+        trade_direction: TradeDirection,
+        destination_amount: u128,
+        reserves: Reserves,
+        fee_config: FeeConfig,
+    ) -> Option<SwapResult> {
+        let (_, swap_destination_amount) = reserves.source_and_destination(trade_direction);
+        // the curve cannot pay out its whole reserve
+        if destination_amount >= swap_destination_amount {
+            return None;
+        }
+        CurveCalculator::swap_exact_out(trade_direction, destination_amount, reserves, fee_config)
+    }
+
+    fn invariant(&self, _now: u64, reserves: Reserves) -> Option<U256> {
+        Some(CurveCalculator::pool_value(reserves).invariant)
+    }
+}
+
+/// The StableSwap curve, amplified as its ramp is at `now`
+impl SwapCurve for AmpRamp {
+    fn swap_exact_in(
+        &self,
+        now: u64,
+        trade_direction: TradeDirection,
+        source_amount: u128,
+        reserves: Reserves,
+        fee_config: FeeConfig,
+    ) -> Option<SwapResult> {
+        StableSwapCurve::swap_exact_in(
+            self,
+            now,
+            trade_direction,
+            source_amount,
+            reserves,
+            fee_config,
+        )
+    }
+
+    fn invariant(&self, now: u64, reserves: Reserves) -> Option<U256> {
+        StableSwapCurve::compute_d(self.current_amp(now), reserves).map(U256::from)
+    }
+}
+
+/// The weighted curve, weighted as its schedule is at `now`. Its invariant
+/// `B0^w0 * B1^w1` has no exact integer form, and the fixed-point
+/// approximation is coarser than the rounding swaps leave in the pool, so
+/// it is left unsupported.
+impl SwapCurve for WeightSchedule {
+    fn swap_exact_in(
+        &self,
+        now: u64,
+        trade_direction: TradeDirection,
+        source_amount: u128,
+        reserves: Reserves,
+        fee_config: FeeConfig,
+    ) -> Option<SwapResult> {
+        WeightedCurve::swap_exact_in(
+            trade_direction,
+            source_amount,
+            reserves,
+            self.weights_at(now),
+            fee_config,
+        )
+    }
+}
+
+impl SwapCurve for PeggedCurve {
+    fn swap_exact_in(
+        &self,
+        _now: u64,
+        trade_direction: TradeDirection,
+        source_amount: u128,
+        reserves: Reserves,
+        fee_config: FeeConfig,
+    ) -> Option<SwapResult> {
+        PeggedCurve::swap_exact_in(self, trade_direction, source_amount, reserves, fee_config).ok()
+    }
+
+    fn invariant(&self, _now: u64, reserves: Reserves) -> Option<U256> {
+        let (a, b) = self.values(reserves)?;
+        self.compute_d(a, b).map(U256::from)
+    }
+}
+
+impl SwapCurve for CurveParams {
+    fn swap_exact_in(
+        &self,
+        now: u64,
+        trade_direction: TradeDirection,
+        source_amount: u128,
+        reserves: Reserves,
+        fee_config: FeeConfig,
+    ) -> Option<SwapResult> {
+        CurveParams::swap_exact_in(
+            self,
+            now,
+            trade_direction,
+            source_amount,
+            reserves,
+            fee_config,
+        )
+    }
+
+    fn swap_exact_out(
+        &self,
+        now: u64,
+        trade_direction: TradeDirection,
+        destination_amount: u128,
+        reserves: Reserves,
+        fee_config: FeeConfig,
+    ) -> Option<SwapResult> {
+        match self {
+            Self::ConstantProduct => ConstantProductCurve.swap_exact_out(
+                now,
+                trade_direction,
+                destination_amount,
+                reserves,
+                fee_config,
+            ),
+            Self::Leveraged { offsets } => LeveragedReserves::from_offsets(reserves, *offsets)?
+                .swap_exact_out(trade_direction, destination_amount, fee_config),
+            Self::Stable(_)
+            | Self::Weighted(_)
+            | Self::Pegged(_)
+            | Self::Pmm { .. }
+            | Self::CryptoSwap(_) => None,
+        }
+    }
+
+    fn invariant(&self, now: u64, reserves: Reserves) -> Option<U256> {
+        match self {
+            Self::ConstantProduct => ConstantProductCurve.invariant(now, reserves),
+            Self::Stable(amp_ramp) => amp_ramp.invariant(now, reserves),
+            Self::Weighted(schedule) => schedule.invariant(now, reserves),
+            Self::Pegged(pegged) => pegged.invariant(now, reserves),
+            Self::Leveraged { offsets } => {
+                let pool = LeveragedReserves::from_offsets(reserves, *offsets)?;
+                ConstantProductCurve.invariant(now, pool.virtual_reserves)
+            }
+            Self::Pmm { .. } | Self::CryptoSwap(_) => None,
+        }
+    }
+}
+
+/// Builds a curve from its `CurveParams::PARAMS_LEN` parameter bytes, or
+/// `None` if they are invalid
+pub type CurveDecoder = fn(&[u8]) -> Option<Box<dyn SwapCurve>>;
+
+// Decodes the built-in curve registered under `ID`
+fn decode_built_in<const ID: u8>(params: &[u8]) -> Option<Box<dyn SwapCurve>> {
+    CurveParams::unpack_params(CurveType::from_id(ID), params)
+        .map(|params| Box::new(params) as Box<dyn SwapCurve>)
+}
+
+/// Curve decoders keyed by curve type id.
+///
+/// `CurveRegistry::default` decodes every built-in curve, with the limits
+/// described in the module documentation; curves decoded from PMM and
+/// repegging parameters return `None` from every quote.
+#[derive(Clone, Debug)]
+pub struct CurveRegistry {
+    decoders: BTreeMap<u8, CurveDecoder>,
+}
+
+impl CurveRegistry {
+    /// A registry without any curves
+    pub const fn empty() -> Self {
+        Self {
+            decoders: BTreeMap::new(),
+        }
+    }
+
+    /// Register `decoder` for curve type `id`.
+    ///
+    /// Returns `None`, keeping the existing decoder, if `id` is taken.
+    pub fn register(&mut self, id: u8, decoder: CurveDecoder) -> Option<()> {
+        if self.decoders.contains_key(&id) {
+            return None;
+        }
+        self.decoders.insert(id, decoder);
+        Some(())
+    }
+
+    /// Whether a decoder is registered for curve type `id`
+    pub fn contains(&self, id: u8) -> bool {
+        self.decoders.contains_key(&id)
+    }
+
+    /// Registered curve type ids, in increasing order
+    pub fn ids(&self) -> impl Iterator<Item = u8> + '_ {
+        self.decoders.keys().copied()
+    }
+
+    /// Decode pool parameters packed in the `CurveParams` layout, as
+    /// `CurveParams::pack` and `CurveParams::pack_raw` write them.
+    ///
+    /// Returns `None` if the data is not in that layout, the curve type id
+    /// is not registered, or its decoder rejects the parameters.
+    pub fn decode(&self, data: &[u8]) -> Option<Box<dyn SwapCurve>> {
+        let (curve_type, _, params) = CurveParams::unpack_header(data)?;
+        (self.decoders.get(&curve_type.id())?)(params)
+    }
+}
+
+impl Default for CurveRegistry {
+    /// A registry with the curves built into this crate
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.decoders.extend([
+            (
+                CurveType::ConstantProduct.id(),
+                decode_built_in::<0> as CurveDecoder,
+            ),
+            (CurveType::Stable.id(), decode_built_in::<1>),
+            (CurveType::Weighted.id(), decode_built_in::<2>),
+            (CurveType::Pegged.id(), decode_built_in::<3>),
+            (CurveType::Pmm.id(), decode_built_in::<4>),
+            (CurveType::Leveraged.id(), decode_built_in::<5>),
+            (CurveType::CryptoSwap.id(), decode_built_in::<6>),
+        ]);
+        registry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            curve::{
+                amount::{Token0Amount, Token1Amount},
+                crypto_swap::{CryptoSwapParams, CryptoSwapPool},
+                fees::{
+                    FEE_RATE_DENOMINATOR_VALUE, FeeRate, Fees, MAX_TRADE_FEE_RATE, ProtocolFeeMode,
+                    TradeFeeSide,
+                },
+                pegged::{OutOfBand, PEG_BAND_BPS_DENOMINATOR},
+                pmm::{PMM_K_DENOMINATOR, PmmPool},
+                stable::{AMP_PRECISION, MAX_AMP},
+                test_utils::{CurveConformance, total_and_intermediate},
+                weighted::{MIN_WEIGHT, WEIGHT_DENOMINATOR, Weights},
+            },
+            state::PoolState,
+            utils::{PriceQ64, Q64_RESOLUTION},
+        },
+        borsh::BorshDeserialize,
+        proptest::prelude::*,
+    };
+
+    // Trades at a fixed number of token 1 per token 0
+    struct FixedPrice(u128);
+
+    impl SwapCurve for FixedPrice {
+        fn swap_exact_in(
+            &self,
+            _now: u64,
+            trade_direction: TradeDirection,
+            source_amount: u128,
+            reserves: Reserves,
+            fee_config: FeeConfig,
+        ) -> Option<SwapResult> {
+            let (source, destination) = reserves.source_and_destination(trade_direction);
+            let trade_fee = Fees::trading_fee(source_amount, fee_config.trade_fee_rate.get())?;
+            let amount = source_amount - trade_fee;
+            let destination_amount_swapped = match trade_direction {
+                TradeDirection::ZeroForOne => amount.checked_mul(self.0)?,
+                TradeDirection::OneForZero => amount / self.0,
+            };
+            Some(SwapResult {
+                new_swap_source_amount: source.checked_add(source_amount)?,
+                new_swap_destination_amount: destination.checked_sub(destination_amount_swapped)?,
+                source_amount_swapped: source_amount,
+                destination_amount_swapped,
+                trade_fee,
+                protocol_fee: 0,
+                destination_trade_fee: 0,
+                destination_protocol_fee: 0,
+                rounding_dust: 0,
+            })
+        }
+
+        // the reserves valued in token 1
+        fn invariant(&self, _now: u64, reserves: Reserves) -> Option<U256> {
+            Some(U256::from(reserves.token_0.get()) * U256::from(self.0) + reserves.token_1.get())
+        }
+    }
+
+    fn decode_fixed_price(params: &[u8]) -> Option<Box<dyn SwapCurve>> {
+        let (price, padding) = params.split_first_chunk()?;
+        let price = u64::from_le_bytes(*price);
+        (price != 0 && padding.iter().all(|&byte| byte == 0))
+            .then(|| Box::new(FixedPrice(u128::from(price))) as Box<dyn SwapCurve>)
+    }
+
+    // The built-in curves, plus a custom one to show registered curves are
+    // held to the same properties
+    fn registry() -> CurveRegistry {
+        let mut registry = CurveRegistry::default();
+        registry.register(200, decode_fixed_price).unwrap();
+        registry
+    }
+
+    fn reserves() -> Reserves {
+        Reserves::new(Token0Amount(1_000_000), Token1Amount(1_000_000))
+    }
+
+    #[test]
+    fn built_in_curves_decode() {
+        let registry = CurveRegistry::default();
+        let fee_config = FeeConfig::new(FeeRate::from_bps(25).unwrap(), FeeRate::ZERO);
+        for params in [
+            CurveParams::ConstantProduct,
+            CurveParams::Stable(AmpRamp::new(10_000).unwrap()),
+            CurveParams::Weighted(
+                WeightSchedule::new(
+                    Weights::new(800_000).unwrap(),
+                    Weights::new(200_000).unwrap(),
+                    0,
+                    100,
+                )
+                .unwrap(),
+            ),
+            CurveParams::Pegged(
+                PeggedCurve::new(PriceQ64::ONE, 100, OutOfBand::ConstantProduct).unwrap(),
+            ),
+            CurveParams::Pmm { k: 100_000 },
+            CurveParams::Leveraged {
+                offsets: Reserves::from_raw(1_000_000, 3_000_000),
+            },
+            CurveParams::CryptoSwap(crypto_swap_params(50 * AMP_PRECISION)),
+        ] {
+            assert!(registry.contains(params.curve_type().id()));
+            let curve = registry.decode(&params.pack()).unwrap();
+            for now in [0, 50, 200] {
+                assert_eq!(
+                    curve.swap_exact_in(
+                        now,
+                        TradeDirection::ZeroForOne,
+                        1_000,
+                        reserves(),
+                        fee_config
+                    ),
+                    params.swap_exact_in(
+                        now,
+                        TradeDirection::ZeroForOne,
+                        1_000,
+                        reserves(),
+                        fee_config
+                    )
+                );
+            }
+        }
+
+        let curve = registry
+            .decode(&CurveParams::ConstantProduct.pack())
+            .unwrap();
+        assert_eq!(
+            curve.swap_exact_out(0, TradeDirection::OneForZero, 1_000, reserves(), fee_config),
+            CurveCalculator::swap_exact_out(
+                TradeDirection::OneForZero,
+                1_000,
+                reserves(),
+                fee_config
+            )
+        );
+        let offsets = Reserves::from_raw(1_000_000, 3_000_000);
+        let leveraged = registry
+            .decode(&CurveParams::Leveraged { offsets }.pack())
+            .unwrap();
+        assert_eq!(
+            leveraged.swap_exact_out(0, TradeDirection::OneForZero, 1_000, reserves(), fee_config),
+            LeveragedReserves::from_offsets(reserves(), offsets)
+                .unwrap()
+                .swap_exact_out(TradeDirection::OneForZero, 1_000, fee_config)
+        );
+        assert!(
+            curve
+                .swap_exact_out(
+                    0,
+                    TradeDirection::OneForZero,
+                    1_000_000,
+                    reserves(),
+                    fee_config
+                )
+                .is_none()
+        );
+        // the registry reads the same encoding as `CurveParams::unpack`
+        let mut padded = CurveParams::ConstantProduct.pack();
+        padded[CurveParams::LEN - 1] = 1;
+        assert!(registry.decode(&padded).is_none());
+        assert!(registry.decode(&[0, 0]).is_none());
+        assert!(registry.decode(&[]).is_none());
+        assert!(
+            CurveRegistry::empty()
+                .decode(&CurveParams::ConstantProduct.pack())
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn custom_curves_dispatch_by_id() {
+        let mut registry = CurveRegistry::default();
+        assert_eq!(registry.register(200, decode_fixed_price), Some(()));
+        assert!(registry.contains(200));
+        // ids are not reassigned
+        assert_eq!(registry.register(200, decode_built_in::<0>), None);
+        assert_eq!(
+            registry.register(CurveType::ConstantProduct.id(), decode_fixed_price),
+            None
+        );
+
+        let data = CurveParams::pack_raw(CurveType::Custom(200), &3u64.to_le_bytes()).unwrap();
+        let curve = registry.decode(&data).unwrap();
+        let result = curve
+            .swap_exact_in(
+                0,
+                TradeDirection::ZeroForOne,
+                100,
+                reserves(),
+                FeeConfig::default(),
+            )
+            .unwrap();
+        assert_eq!(result.destination_amount_swapped, 300);
+        assert!(
+            curve
+                .swap_exact_out(
+                    0,
+                    TradeDirection::ZeroForOne,
+                    1,
+                    reserves(),
+                    FeeConfig::default()
+                )
+                .is_none()
+        );
+        // the decoder validates its own parameters
+        let mut padded = data;
+        padded[CurveParams::LEN - 1] = 1;
+        assert!(registry.decode(&padded).is_none());
+        assert!(CurveParams::pack_raw(CurveType::Custom(200), &[0; CurveParams::LEN]).is_none());
+        let unregistered = CurveParams::pack_raw(CurveType::Custom(201), &3u64.to_le_bytes());
+        assert!(registry.decode(&unregistered.unwrap()).is_none());
+        // custom parameters are not a built-in configuration
+        assert_eq!(CurveParams::unpack(&data), None);
+    }
+
+    #[test]
+    fn pools_store_custom_curves() {
+        let mut registry = CurveRegistry::default();
+        registry.register(200, decode_fixed_price).unwrap();
+        let mut state = PoolState {
+            token_0_reserve: 1_000_000,
+            token_1_reserve: 1_000_000,
+            ..PoolState::default()
+        };
+        state
+            .set_curve(CurveType::Custom(200), &3u64.to_le_bytes())
+            .unwrap();
+        assert_eq!(state.curve_type, CurveType::Custom(200));
+        let state = PoolState::try_from_slice(&borsh::to_vec(&state).unwrap()).unwrap();
+        assert_eq!(state.curve_type, CurveType::Custom(200));
+        assert_eq!(state.curve_params(), None);
+        let result = state
+            .curve(&registry)
+            .unwrap()
+            .swap_exact_in(
+                0,
+                TradeDirection::ZeroForOne,
+                100,
+                state.reserves(),
+                state.fee_config,
+            )
+            .unwrap();
+        assert_eq!(result.destination_amount_swapped, 300);
+        assert!(state.curve(&CurveRegistry::default()).is_none());
+
+        // built-in curves decode the same way
+        let mut stable = state;
+        stable.set_curve_params(CurveParams::Stable(AmpRamp::new(100).unwrap()));
+        assert!(stable.curve(&registry).is_some());
+        // parameters packed for another curve are not used
+        stable.curve_type = CurveType::Custom(200);
+        assert!(stable.curve(&registry).is_none());
+    }
+
+    // Adapters quoting the pools that keep state beyond their reserves as
+    // fresh pools holding the reserves they are given, which their packed
+    // parameters cannot quote on their own
+
+    // A PMM pool at its targets
+    #[derive(Debug)]
+    struct Pmm {
+        k: u64,
+        oracle_price: PriceQ64,
+    }
+
+    impl SwapCurve for Pmm {
+        fn swap_exact_in(
+            &self,
+            _now: u64,
+            trade_direction: TradeDirection,
+            source_amount: u128,
+            reserves: Reserves,
+            fee_config: FeeConfig,
+        ) -> Option<SwapResult> {
+            PmmPool::new(self.k, fee_config, reserves)?
+                .swap_exact_in(self.oracle_price, trade_direction, source_amount)
+                .and_then(with_protocol_fee)
+        }
+
+        // the reserves valued in token 1 at the oracle price, which PMM
+        // trades never beat
+        fn invariant(&self, _now: u64, reserves: Reserves) -> Option<U256> {
+            Some(
+                U256::from(reserves.token_0.get()) * U256::from(self.oracle_price.raw())
+                    + (U256::from(reserves.token_1.get()) << Q64_RESOLUTION),
+            )
+        }
+    }
+
+    // A repegging pool created at the price scale; it charges its own fees
+    // rather than `fee_config`
+    #[derive(Debug)]
+    struct CryptoSwap {
+        params: CryptoSwapParams,
+        price_scale: PriceQ64,
+    }
+
+    impl SwapCurve for CryptoSwap {
+        fn swap_exact_in(
+            &self,
+            now: u64,
+            trade_direction: TradeDirection,
+            source_amount: u128,
+            reserves: Reserves,
+            _fee_config: FeeConfig,
+        ) -> Option<SwapResult> {
+            CryptoSwapPool::new(self.params, reserves, self.price_scale, now)?
+                .swap_exact_in(trade_direction, source_amount, now)
+                .and_then(with_protocol_fee)
+        }
+
+        fn invariant(&self, now: u64, reserves: Reserves) -> Option<U256> {
+            let pool = CryptoSwapPool::new(self.params, reserves, self.price_scale, now)?;
+            Some(U256::from(pool.d))
+        }
+    }
+
+    // PMM and repegging pools hold protocol fees apart from the reserves,
+    // while `SwapCurve` quotes count them in the new source amount
+    fn with_protocol_fee(result: SwapResult) -> Option<SwapResult> {
+        Some(SwapResult {
+            new_swap_source_amount: result
+                .new_swap_source_amount
+                .checked_add(result.protocol_fee)?,
+            ..result
+        })
+    }
+
+    fn crypto_swap_params(amp: u64) -> CryptoSwapParams {
+        CryptoSwapParams {
+            amp,
+            mid_fee: FeeRate::from_bps(5).unwrap(),
+            out_fee: FeeRate::from_bps(45).unwrap(),
+            fee_gamma: PriceQ64::from_ratio(1, 4).unwrap(),
+            protocol_fee_rate: FeeRate::from_percent(50).unwrap(),
+            allowed_extra_profit: PriceQ64::from_ratio(1, 1_000_000).unwrap(),
+            adjustment_step: PriceQ64::from_ratio(1, 1_000).unwrap(),
+            ma_half_time: 600,
+        }
+    }
+
+    prop_compose! {
+        // Valid parameters for every curve in `registry()`, in id order
+        fn packed_params()(
+            amps in (
+                AMP_PRECISION..MAX_AMP * AMP_PRECISION,
+                AMP_PRECISION..MAX_AMP * AMP_PRECISION,
+            ),
+            weights in (
+                MIN_WEIGHT..=WEIGHT_DENOMINATOR - MIN_WEIGHT,
+                MIN_WEIGHT..=WEIGHT_DENOMINATOR - MIN_WEIGHT,
+            ),
+            peg in (1..=1_000u128, 1..=1_000u128),
+            band_bps in 0..PEG_BAND_BPS_DENOMINATOR,
+            out_of_band in prop_oneof![
+                Just(OutOfBand::Revert),
+                Just(OutOfBand::ConstantProduct),
+            ],
+            k in 0..=PMM_K_DENOMINATOR,
+            offsets in (0..u64::MAX as u128, 0..u64::MAX as u128),
+            crypto_swap_amp in AMP_PRECISION..MAX_AMP * AMP_PRECISION,
+            price in 1..=u8::MAX,
+        ) -> Vec<[u8; CurveParams::LEN]> {
+            let ramp = AmpRamp {
+                initial_amp: amps.0,
+                target_amp: amps.1,
+                initial_time: 0,
+                target_time: 200,
+            };
+            let schedule = WeightSchedule::new(
+                Weights::new(weights.0).unwrap(),
+                Weights::new(weights.1).unwrap(),
+                0,
+                200,
+            )
+            .unwrap();
+            let peg = PriceQ64::from_ratio(peg.0, peg.1).unwrap();
+            vec![
+                CurveParams::ConstantProduct.pack(),
+                CurveParams::Stable(ramp).pack(),
+                CurveParams::Weighted(schedule).pack(),
+                CurveParams::Pegged(PeggedCurve::new(peg, band_bps, out_of_band).unwrap()).pack(),
+                CurveParams::Pmm { k }.pack(),
+                CurveParams::Leveraged {
+                    offsets: Reserves::from_raw(offsets.0, offsets.1),
+                }
+                .pack(),
+                CurveParams::CryptoSwap(crypto_swap_params(crypto_swap_amp)).pack(),
+                CurveParams::pack_raw(CurveType::Custom(200), &u64::from(price).to_le_bytes())
+                    .unwrap(),
+            ]
+        }
+    }
+
+    prop_compose! {
+        fn pools()(
+            k in 0..=PMM_K_DENOMINATOR,
+            oracle_price in (1..=1_000u128, 1..=1_000u128),
+            amp in AMP_PRECISION..MAX_AMP * AMP_PRECISION,
+            price_scale in (1..=1_000u128, 1..=1_000u128),
+        ) -> (Pmm, CryptoSwap) {
+            (
+                Pmm {
+                    k,
+                    oracle_price: PriceQ64::from_ratio(oracle_price.0, oracle_price.1).unwrap(),
+                },
+                CryptoSwap {
+                    params: crypto_swap_params(amp),
+                    price_scale: PriceQ64::from_ratio(price_scale.0, price_scale.1).unwrap(),
+                },
+            )
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn built_in_curves_conform(
+            packed_params in packed_params(),
+            (pmm, crypto_swap) in pools(),
+            now in 0..=300u64,
+            amount in 1..u32::MAX as u128,
+            (lp_token_supply, lp_token_amount) in total_and_intermediate(u64::MAX),
+            more_lp_token_amount in 0..u64::MAX as u128,
+            (token_0, token_1) in (1..u64::MAX as u128, 1..u64::MAX as u128),
+            trade_fee_rate in 0..=MAX_TRADE_FEE_RATE.get(),
+            protocol_fee_rate in 0..=FEE_RATE_DENOMINATOR_VALUE,
+            trade_fee_side in prop_oneof![Just(TradeFeeSide::Input), Just(TradeFeeSide::Output)],
+            protocol_fee_mode in prop_oneof![
+                Just(ProtocolFeeMode::Input),
+                Just(ProtocolFeeMode::Split),
+            ],
+            trade_direction in prop_oneof![
+                Just(TradeDirection::ZeroForOne),
+                Just(TradeDirection::OneForZero),
+            ],
+        ) {
+            let registry = registry();
+            // every registered curve is checked
+            prop_assert!(registry.ids().eq(packed_params.iter().map(|params| params[1])));
+            let decoded: Vec<_> = packed_params
+                .iter()
+                .map(|params| registry.decode(params).unwrap())
+                .collect();
+            let pools: [&dyn SwapCurve; 2] = [&pmm, &crypto_swap];
+
+            let reserves = Reserves::from_raw(token_0, token_1);
+            let fee_config = FeeConfig::new(
+                FeeRate::checked(trade_fee_rate).unwrap(),
+                FeeRate::checked(protocol_fee_rate).unwrap(),
+            )
+            .with_trade_fee_side(trade_fee_side)
+            .with_protocol_fee_mode(protocol_fee_mode);
+            let (lp_token_supply, lp_token_amount) =
+                (u128::from(lp_token_supply), u128::from(lp_token_amount));
+            let more_lp_token_amount = lp_token_amount.max(more_lp_token_amount);
+            for curve in decoded.iter().map(Box::as_ref).chain(pools) {
+                curve.check_swap_value(now, trade_direction, amount, reserves, fee_config);
+                curve.check_exact_out_exact_in(now, trade_direction, amount, reserves, fee_config);
+                curve.check_lp_conversion_bounds(lp_token_amount, lp_token_supply, reserves);
+                curve.check_deposit_withdraw_monotonic(
+                    lp_token_amount,
+                    more_lp_token_amount,
+                    lp_token_supply,
+                    reserves,
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! Rounding policy
+//!
+//! Every amount a pool calculates is rounded, and the safe direction is
+//! always the pool's favor. `RoundingPolicy` gathers those choices in one
+//! place, so an integration matching another program's rounding sets them
+//! together on `PoolMath` instead of passing a direction to each call.
+use crate::curve::{
+    calculator::RoundDirection,
+    fee_model::{self, FeeCtx, FeeModel},
+    fees::{FeeConfig, Fees},
+};
+
+/// The rounding of each amount a pool calculates
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RoundingPolicy {
+    /// Destination amount of an exact-in swap
+    pub swap_output: RoundDirection,
+    /// Source amount of an exact-out swap, before fees
+    pub exact_out_input: RoundDirection,
+    /// Trade fee
+    pub fee: RoundDirection,
+    /// Trading tokens a deposit pays for the LP tokens it mints
+    pub lp_mint: RoundDirection,
+    /// Trading tokens a withdrawal receives for the LP tokens it burns
+    pub lp_burn: RoundDirection,
+}
+
+impl RoundingPolicy {
+    /// Every amount rounded in the pool's favor, so no operation can
+    /// decrease the value of an LP token
+    pub const SAFE: Self = Self {
+        swap_output: RoundDirection::Floor,
+        exact_out_input: RoundDirection::Ceiling,
+        fee: RoundDirection::Ceiling,
+        lp_mint: RoundDirection::Ceiling,
+        lp_burn: RoundDirection::Floor,
+    };
+
+    /// Whether swaps round as `SAFE` does
+    pub const fn rounds_swaps_safely(&self) -> bool {
+        matches!(
+            (self.swap_output, self.exact_out_input, self.fee),
+            (
+                RoundDirection::Floor,
+                RoundDirection::Ceiling,
+                RoundDirection::Ceiling
+            )
+        )
+    }
+}
+
+impl Default for RoundingPolicy {
+    fn default() -> Self {
+        Self::SAFE
+    }
+}
+
+// The flat rates of a `FeeConfig`, with the trade fee rounded in
+// `round_direction`
+pub(crate) struct RoundedFee {
+    pub(crate) fee_config: FeeConfig,
+    pub(crate) round_direction: RoundDirection,
+}
+
+impl FeeModel for RoundedFee {
+    fn trade_fee(&self, amount: u128, _ctx: &FeeCtx) -> Option<u128> {
+        match self.round_direction {
+            RoundDirection::Floor => self.fee_config.trade_fee_rate.mul_floor(amount),
+            RoundDirection::Ceiling => self.fee_config.trade_fee_rate.mul_ceil(amount),
+        }
+    }
+
+    fn protocol_share(&self, fee: u128) -> Option<u128> {
+        Fees::protocol_fee(fee, self.fee_config.protocol_fee_rate.get())
+    }
+
+    fn pre_fee_amount(&self, post_fee_amount: u128, ctx: &FeeCtx) -> Option<u128> {
+        match self.round_direction {
+            RoundDirection::Floor => fee_model::search_pre_fee_amount(self, post_fee_amount, ctx),
+            RoundDirection::Ceiling => self.fee_config.pre_fee_amount(post_fee_amount, ctx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::curve::fees::FeeRate};
+
+    #[test]
+    fn default_is_safe() {
+        assert_eq!(RoundingPolicy::default(), RoundingPolicy::SAFE);
+        assert!(RoundingPolicy::SAFE.rounds_swaps_safely());
+        let lp_only = RoundingPolicy {
+            lp_burn: RoundDirection::Ceiling,
+            ..RoundingPolicy::SAFE
+        };
+        assert!(lp_only.rounds_swaps_safely());
+        let fee_down = RoundingPolicy {
+            fee: RoundDirection::Floor,
+            ..RoundingPolicy::SAFE
+        };
+        assert!(!fee_down.rounds_swaps_safely());
+    }
+
+    #[test]
+    fn rounded_fee_grosses_up_to_the_smallest_amount() {
+        let fee_config = FeeConfig::new(FeeRate::from_bps(30).unwrap(), FeeRate::ZERO);
+        let ctx = FeeCtx {
+            swap_source_amount: 1,
+            swap_destination_amount: 1,
+        };
+        for round_direction in [RoundDirection::Floor, RoundDirection::Ceiling] {
+            let fee = RoundedFee {
+                fee_config,
+                round_direction,
+            };
+            for post_fee_amount in [1, 332, 333, 10_000, 1_000_000_007] {
+                let amount = fee.pre_fee_amount(post_fee_amount, &ctx).unwrap();
+                let left = |amount| amount - fee.trade_fee(amount, &ctx).unwrap();
+                assert!(left(amount) >= post_fee_amount);
+                assert!(left(amount - 1) < post_fee_amount);
+            }
+        }
+        // 0.3% of 333 is 0.999, nothing when rounded down
+        let floor = RoundedFee {
+            fee_config,
+            round_direction: RoundDirection::Floor,
+        };
+        assert_eq!(floor.trade_fee(333, &ctx), Some(0));
+        assert_eq!(floor.minimum_input(&ctx), Some(1));
+    }
+}
@@ -0,0 +1,411 @@
+//! Saturating swap quotes
+//!
+//! The strict `swap_base_input` / `swap_base_output` functions are what a pool
+//! settles trades with, and fail on inputs that cannot be executed. UIs that
+//! sweep an amount slider up to (and past) the full reserve would rather get
+//! the best executable quote, so the functions here clamp the requested amount
+//! to the largest one the strict math accepts and never fail or panic.
+//!
+//! `quote_preview` wraps both in one entry point that also reports whether
+//! the amount was clamped, so a slider can flag the quote as partial.
+//!
+//! These quotes are for display only: the clamped amount may differ from what
+//! the user asked for, so they must not be used to settle trades on-chain.
+use crate::curve::{
+    calculator::{CurveCalculator, SwapResult},
+    fees::MAX_TRADE_FEE_RATE,
+    swap_params::SwapParams,
+};
+
+/// Which side of a previewed swap the requested amount fixes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PreviewSide {
+    /// The amount is the source tokens paid in
+    ExactInput,
+    /// The amount is the destination tokens received
+    ExactOutput,
+}
+
+/// Saturating quote for a UI preview
+#[derive(Debug, PartialEq)]
+pub struct QuotePreview {
+    /// Best executable swap for the requested amount
+    pub result: SwapResult,
+    /// Whether less than the requested amount could be quoted, including
+    /// nothing at all on an empty pool or with out-of-bounds fee rates
+    pub clamped: bool,
+}
+
+impl CurveCalculator {
+    /// Preview a swap of `amount` on `side`, clamping it to the largest
+    /// tradeable amount instead of failing.
+    ///
+    /// Never fails or panics; `clamped` is set whenever the quoted amount is
+    /// less than `amount`.
+    pub fn quote_preview(
+        side: PreviewSide,
+        amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        trade_fee_rate: u64,
+        protocol_fee_rate: u64,
+    ) -> QuotePreview {
+        let (result, quoted) = match side {
+            PreviewSide::ExactInput => {
+                let result = Self::quote_base_input_saturating(
+                    amount,
+                    swap_source_amount,
+                    swap_destination_amount,
+                    trade_fee_rate,
+                    protocol_fee_rate,
+                );
+                let quoted = result.source_amount_swapped;
+                (result, quoted)
+            }
+            PreviewSide::ExactOutput => {
+                let result = Self::quote_base_output_saturating(
+                    amount,
+                    swap_source_amount,
+                    swap_destination_amount,
+                    trade_fee_rate,
+                    protocol_fee_rate,
+                );
+                let quoted = result.destination_amount_swapped;
+                (result, quoted)
+            }
+        };
+        QuotePreview {
+            result,
+            clamped: quoted < amount,
+        }
+    }
+
+    /// Quote an exact-input swap, clamping `source_amount` to the largest
+    /// amount that can be computed without overflow.
+    ///
+    /// Returns a result with nothing swapped if the pool is empty or the fee
+    /// rates are out of bounds.
+    pub fn quote_base_input_saturating(
+        source_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        trade_fee_rate: u64,
+        protocol_fee_rate: u64,
+    ) -> SwapResult {
+        if swap_source_amount == 0 || swap_destination_amount == 0 {
+            return empty_swap_result(swap_source_amount, swap_destination_amount);
+        }
+        let source_amount = source_amount.min(max_source_amount(
+            swap_source_amount,
+            swap_destination_amount,
+        ));
+        SwapParams::from_raw_parts(
+            source_amount,
+            swap_source_amount,
+            swap_destination_amount,
+            trade_fee_rate,
+            protocol_fee_rate,
+        )
+        .and_then(|params| Self::swap_base_input(&params))
+        .unwrap_or_else(|| empty_swap_result(swap_source_amount, swap_destination_amount))
+    }
+
+    /// Quote an exact-output swap, clamping `destination_amount` to the largest
+    /// amount that can be bought, which is always less than the full reserve.
+    ///
+    /// Returns a result with nothing swapped if the pool is empty or the fee
+    /// rates are out of bounds.
+    pub fn quote_base_output_saturating(
+        destination_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        trade_fee_rate: u64,
+        protocol_fee_rate: u64,
+    ) -> SwapResult {
+        if swap_source_amount == 0
+            || swap_destination_amount == 0
+            || trade_fee_rate > MAX_TRADE_FEE_RATE.get()
+        {
+            return empty_swap_result(swap_source_amount, swap_destination_amount);
+        }
+        let computable = |amount: u128| {
+            output_is_computable(
+                amount,
+                swap_source_amount,
+                swap_destination_amount,
+                trade_fee_rate,
+                protocol_fee_rate,
+            )
+        };
+        // the last unit of the reserve can never be bought
+        let mut destination_amount = destination_amount.min(swap_destination_amount - 1);
+        if !computable(destination_amount) {
+            // the required input or its fee gross-up can overflow; both grow
+            // with the output, so search for the largest computable amount
+            let (mut low, mut high) = (0, destination_amount);
+            while low < high {
+                let middle = low + (high - low).div_ceil(2);
+                if computable(middle) {
+                    low = middle;
+                } else {
+                    high = middle - 1;
+                }
+            }
+            destination_amount = low;
+        }
+        SwapParams::from_raw_parts(
+            destination_amount,
+            swap_source_amount,
+            swap_destination_amount,
+            trade_fee_rate,
+            protocol_fee_rate,
+        )
+        .and_then(|params| Self::swap_base_output(&params))
+        .unwrap_or_else(|| empty_swap_result(swap_source_amount, swap_destination_amount))
+    }
+}
+
+// Largest exact-input amount for which the curve does not overflow; the
+// fee is at most the amount at valid rates, so it cannot
+fn max_source_amount(swap_source_amount: u128, swap_destination_amount: u128) -> u128 {
+    // the curve computes `delta_x * y` and `x + delta_x`
+    let product_bound = u128::MAX / swap_destination_amount;
+    let sum_bound = u128::MAX - swap_source_amount;
+    product_bound.min(sum_bound)
+}
+
+// Whether `swap_base_output` returns a result for `destination_amount`
+fn output_is_computable(
+    destination_amount: u128,
+    swap_source_amount: u128,
+    swap_destination_amount: u128,
+    trade_fee_rate: u64,
+    protocol_fee_rate: u64,
+) -> bool {
+    SwapParams::from_raw_parts(
+        destination_amount,
+        swap_source_amount,
+        swap_destination_amount,
+        trade_fee_rate,
+        protocol_fee_rate,
+    )
+    .and_then(|params| CurveCalculator::swap_base_output(&params))
+    .is_some()
+}
+
+fn empty_swap_result(swap_source_amount: u128, swap_destination_amount: u128) -> SwapResult {
+    SwapResult {
+        new_swap_source_amount: swap_source_amount,
+        new_swap_destination_amount: swap_destination_amount,
+        source_amount_swapped: 0,
+        destination_amount_swapped: 0,
+        trade_fee: 0,
+        protocol_fee: 0,
+        destination_trade_fee: 0,
+        destination_protocol_fee: 0,
+        rounding_dust: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::curve::fees::FEE_RATE_DENOMINATOR_VALUE, proptest::prelude::*};
+
+    #[test]
+    fn saturating_input_matches_strict_in_range() {
+        let strict = CurveCalculator::swap_base_input(
+            &SwapParams::from_raw_parts(1_000, 50_000, 80_000, 2_500, 120_000).unwrap(),
+        );
+        let saturating =
+            CurveCalculator::quote_base_input_saturating(1_000, 50_000, 80_000, 2_500, 120_000);
+        assert_eq!(strict, Some(saturating));
+    }
+
+    #[test]
+    fn saturating_input_clamps_overflow() {
+        let result = CurveCalculator::quote_base_input_saturating(
+            u128::MAX,
+            u64::MAX as u128,
+            u64::MAX as u128,
+            2_500,
+            120_000,
+        );
+        assert!(result.source_amount_swapped < u128::MAX);
+        assert!(result.destination_amount_swapped < u64::MAX as u128);
+        assert_eq!(
+            result.new_swap_source_amount,
+            u64::MAX as u128 + result.source_amount_swapped
+        );
+    }
+
+    #[test]
+    fn saturating_output_clamps_to_reserve() {
+        let result =
+            CurveCalculator::quote_base_output_saturating(1_000_000, 50_000, 80_000, 2_500, 0);
+        assert_eq!(result.destination_amount_swapped, 79_999);
+        assert_eq!(result.new_swap_destination_amount, 1);
+        let exact = CurveCalculator::swap_base_output(
+            &SwapParams::from_raw_parts(79_999, 50_000, 80_000, 2_500, 0).unwrap(),
+        );
+        assert_eq!(exact, Some(result));
+    }
+
+    #[test]
+    fn saturating_output_clamps_fee_overflow() {
+        // buying about two thirds of the reserve needs an input that fits in
+        // u128 but whose fee gross-up does not
+        let swap_source_amount = 1 << 127;
+        let swap_destination_amount = 1 << 20;
+        let trade_fee_rate = MAX_TRADE_FEE_RATE.get();
+        let result = CurveCalculator::quote_base_output_saturating(
+            u128::MAX,
+            swap_source_amount,
+            swap_destination_amount,
+            trade_fee_rate,
+            0,
+        );
+        assert!(result.destination_amount_swapped > 0);
+        assert!(result.destination_amount_swapped < swap_destination_amount - 1);
+        assert!(!output_is_computable(
+            result.destination_amount_swapped + 1,
+            swap_source_amount,
+            swap_destination_amount,
+            trade_fee_rate,
+            0,
+        ));
+    }
+
+    #[test]
+    fn saturating_quotes_on_impossible_pools() {
+        let empty = empty_swap_result(0, 100);
+        assert_eq!(
+            CurveCalculator::quote_base_input_saturating(10, 0, 100, 0, 0),
+            empty
+        );
+        assert_eq!(
+            CurveCalculator::quote_base_output_saturating(10, 0, 100, 0, 0),
+            empty
+        );
+        let empty = empty_swap_result(100, 100);
+        assert_eq!(
+            CurveCalculator::quote_base_output_saturating(
+                10,
+                100,
+                100,
+                FEE_RATE_DENOMINATOR_VALUE,
+                0
+            ),
+            empty
+        );
+    }
+
+    #[test]
+    fn preview_flags_clamped_amounts() {
+        let preview = CurveCalculator::quote_preview(
+            PreviewSide::ExactInput,
+            1_000,
+            50_000,
+            80_000,
+            2_500,
+            0,
+        );
+        assert!(!preview.clamped);
+        assert_eq!(
+            Some(preview.result),
+            CurveCalculator::swap_base_input(
+                &SwapParams::from_raw_parts(1_000, 50_000, 80_000, 2_500, 0).unwrap()
+            )
+        );
+        let preview = CurveCalculator::quote_preview(
+            PreviewSide::ExactOutput,
+            1_000_000,
+            50_000,
+            80_000,
+            2_500,
+            0,
+        );
+        assert!(preview.clamped);
+        assert_eq!(preview.result.destination_amount_swapped, 79_999);
+        let preview = CurveCalculator::quote_preview(
+            PreviewSide::ExactInput,
+            u128::MAX,
+            u64::MAX as u128,
+            u64::MAX as u128,
+            2_500,
+            0,
+        );
+        assert!(preview.clamped);
+        assert!(preview.result.source_amount_swapped > 0);
+        // nothing can be quoted on an empty pool
+        let preview = CurveCalculator::quote_preview(PreviewSide::ExactOutput, 10, 0, 100, 0, 0);
+        assert!(preview.clamped);
+        assert_eq!(preview.result, empty_swap_result(0, 100));
+        // a zero amount is never clamped
+        let preview =
+            CurveCalculator::quote_preview(PreviewSide::ExactInput, 0, 50_000, 80_000, 2_500, 0);
+        assert!(!preview.clamped);
+    }
+
+    proptest! {
+        #[test]
+        fn preview_is_exact_unless_clamped(
+            amount in any::<u128>(),
+            swap_source_amount in any::<u128>(),
+            swap_destination_amount in any::<u128>(),
+            trade_fee_rate in 0..FEE_RATE_DENOMINATOR_VALUE,
+            protocol_fee_rate in 0..FEE_RATE_DENOMINATOR_VALUE,
+            exact_output: bool,
+        ) {
+            let side = if exact_output {
+                PreviewSide::ExactOutput
+            } else {
+                PreviewSide::ExactInput
+            };
+            let QuotePreview { result, clamped } = CurveCalculator::quote_preview(
+                side,
+                amount,
+                swap_source_amount,
+                swap_destination_amount,
+                trade_fee_rate,
+                protocol_fee_rate,
+            );
+            let quoted = if exact_output {
+                result.destination_amount_swapped
+            } else {
+                result.source_amount_swapped
+            };
+            prop_assert!(quoted <= amount);
+            prop_assert_eq!(clamped, quoted != amount);
+        }
+
+        #[test]
+        fn saturating_quotes_never_panic(
+            amount in any::<u128>(),
+            swap_source_amount in any::<u128>(),
+            swap_destination_amount in any::<u128>(),
+            trade_fee_rate in 0..FEE_RATE_DENOMINATOR_VALUE,
+            protocol_fee_rate in 0..FEE_RATE_DENOMINATOR_VALUE,
+        ) {
+            let input = CurveCalculator::quote_base_input_saturating(
+                amount,
+                swap_source_amount,
+                swap_destination_amount,
+                trade_fee_rate,
+                protocol_fee_rate,
+            );
+            prop_assert!(input.destination_amount_swapped <= swap_destination_amount);
+            let output = CurveCalculator::quote_base_output_saturating(
+                amount,
+                swap_source_amount,
+                swap_destination_amount,
+                trade_fee_rate,
+                protocol_fee_rate,
+            );
+            prop_assert!(output.destination_amount_swapped <= amount);
+            prop_assert!(
+                output.destination_amount_swapped < swap_destination_amount
+                    || output.destination_amount_swapped == 0
+            );
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//! Slippage tolerance helpers
+//!
+//! Tolerances are expressed in basis points of the quoted amount and are
+//! always rounded against the trader, so a transaction built from them never
+//! accepts a worse price than the user agreed to.
+
+/// Denominator for slippage tolerances expressed in basis points
+pub const SLIPPAGE_BPS_DENOMINATOR: u128 = 10_000;
+
+/// Smallest output to accept for an exact-input swap quoted at `amount_out`,
+/// rounded up.
+///
+/// Returns `None` if `slippage_bps` exceeds 100%.
+pub const fn minimum_amount_out(amount_out: u128, slippage_bps: u16) -> Option<u128> {
+    if slippage_bps as u128 > SLIPPAGE_BPS_DENOMINATOR {
+        return None;
+    }
+    let Some(numerator) = amount_out.checked_mul(SLIPPAGE_BPS_DENOMINATOR - slippage_bps as u128)
+    else {
+        return None;
+    };
+    Some(numerator.div_ceil(SLIPPAGE_BPS_DENOMINATOR))
+}
+
+/// Largest input to accept for an exact-output swap quoted at `amount_in`,
+/// rounded down.
+pub const fn maximum_amount_in(amount_in: u128, slippage_bps: u16) -> Option<u128> {
+    let Some(numerator) = amount_in.checked_mul(SLIPPAGE_BPS_DENOMINATOR + slippage_bps as u128)
+    else {
+        return None;
+    };
+    Some(numerator / SLIPPAGE_BPS_DENOMINATOR)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slippage_bounds_round_against_trader() {
+        // 0.5% of 1_001 is 5.005
+        assert_eq!(minimum_amount_out(1_001, 50), Some(996));
+        assert_eq!(maximum_amount_in(1_001, 50), Some(1_006));
+        assert_eq!(minimum_amount_out(1_000, 0), Some(1_000));
+        assert_eq!(maximum_amount_in(1_000, 0), Some(1_000));
+        assert_eq!(minimum_amount_out(1_000, 10_000), Some(0));
+        assert_eq!(minimum_amount_out(1_000, 10_001), None);
+        assert_eq!(maximum_amount_in(u128::MAX, 1), None);
+    }
+}
@@ -0,0 +1,444 @@
+//! StableSwap curve
+//!
+//! Curve's invariant for two tokens meant to trade near 1:1,
+//!
+//! ```text
+//! A * n^n * (x + y) + D = A * n^n * D + D^(n + 1) / (n^n * x * y)
+//! ```
+//!
+//! which behaves like a constant sum for large amplification `A` and like a
+//! constant product as it approaches zero. `A` is stored scaled by
+//! `AMP_PRECISION` and can be ramped gradually, as in Curve's factory pools,
+//! so a pool migrated from Curve quotes the same amounts at the same times.
+use crate::{
+    curve::{
+        amount::Reserves,
+        calculator::{SwapResult, TradeDirection},
+        fees::{FeeConfig, Fees},
+    },
+    utils::{U256, price::to_u128},
+};
+
+/// Number of tokens in a pool
+const N_COINS: u128 = 2;
+
+/// Scale of amplification values
+pub const AMP_PRECISION: u64 = 100;
+
+/// Largest amplification, unscaled
+pub const MAX_AMP: u64 = 1_000_000;
+
+/// Largest factor a single ramp can change the amplification by
+pub const MAX_AMP_CHANGE: u64 = 10;
+
+/// Shortest ramp, and shortest time between the start of two ramps, in
+/// seconds
+pub const MIN_RAMP_TIME: u64 = 86_400;
+
+// Newton's method converges in a handful of iterations for any pool Curve
+// accepts; give up rather than loop on degenerate input
+const MAX_ITERATIONS: usize = 255;
+
+/// Amplification moving linearly from `initial_amp` at `initial_time` to
+/// `target_amp` at `target_time`, both scaled by `AMP_PRECISION`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AmpRamp {
+    pub initial_amp: u64,
+    pub target_amp: u64,
+    pub initial_time: u64,
+    pub target_time: u64,
+}
+
+/// Whether `amp`, scaled by `AMP_PRECISION`, is within the bounds
+/// `AmpRamp::new` accepts: at least 1 and below `MAX_AMP`, unscaled
+pub const fn is_valid_amp(amp: u64) -> bool {
+    AMP_PRECISION <= amp && amp < MAX_AMP * AMP_PRECISION
+}
+
+impl AmpRamp {
+    /// A constant amplification of `amp`, unscaled.
+    ///
+    /// Returns `None` if `amp` is zero or at least `MAX_AMP`.
+    pub const fn new(amp: u64) -> Option<Self> {
+        if amp == 0 || amp >= MAX_AMP {
+            return None;
+        }
+        let amp = amp * AMP_PRECISION;
+        Some(Self {
+            initial_amp: amp,
+            target_amp: amp,
+            initial_time: 0,
+            target_time: 0,
+        })
+    }
+
+    /// Amplification at `now`, scaled by `AMP_PRECISION`, rounded toward
+    /// the initial value. A ramp ending before it starts is already done.
+    pub fn current_amp(&self, now: u64) -> u64 {
+        let duration = u128::from(self.target_time.saturating_sub(self.initial_time));
+        if now >= self.target_time || duration == 0 {
+            return self.target_amp;
+        }
+        let elapsed = u128::from(now.saturating_sub(self.initial_time));
+        let (initial, target) = (u128::from(self.initial_amp), u128::from(self.target_amp));
+        // elapsed < duration, so the step is below the difference
+        if target > initial {
+            (initial + (target - initial) * elapsed / duration) as u64
+        } else {
+            (initial - (initial - target) * elapsed / duration) as u64
+        }
+    }
+
+    /// Start ramping from the current amplification at `now` to
+    /// `target_amp`, unscaled, at `target_time`.
+    ///
+    /// Like Curve's `ramp_A`, returns `None`, leaving the ramp unchanged, if
+    /// the last ramp started less than `MIN_RAMP_TIME` ago, the ramp is
+    /// shorter than `MIN_RAMP_TIME`, the target is zero or at least `MAX_AMP`,
+    /// or it changes the amplification by more than `MAX_AMP_CHANGE` times.
+    pub fn ramp(&mut self, target_amp: u64, target_time: u64, now: u64) -> Option<()> {
+        if now < self.initial_time.checked_add(MIN_RAMP_TIME)?
+            || target_time < now.checked_add(MIN_RAMP_TIME)?
+            || target_amp == 0
+            || target_amp >= MAX_AMP
+        {
+            return None;
+        }
+        let initial_amp = self.current_amp(now);
+        let target_amp = target_amp * AMP_PRECISION;
+        let within_bounds = if target_amp < initial_amp {
+            target_amp * MAX_AMP_CHANGE >= initial_amp
+        } else {
+            target_amp <= initial_amp.checked_mul(MAX_AMP_CHANGE)?
+        };
+        if !within_bounds {
+            return None;
+        }
+        *self = Self {
+            initial_amp,
+            target_amp,
+            initial_time: now,
+            target_time,
+        };
+        Some(())
+    }
+
+    /// Stop ramping, keeping the amplification at `now`
+    pub fn stop_ramp(&mut self, now: u64) {
+        let amp = self.current_amp(now);
+        *self = Self {
+            initial_amp: amp,
+            target_amp: amp,
+            initial_time: now,
+            target_time: now,
+        };
+    }
+}
+
+// StableSwapCurve struct implementing the StableSwap invariant for two tokens.
+// All amounts are raw token amounts; tokens with different decimals must be
+// scaled to a common precision first.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StableSwapCurve;
+
+impl StableSwapCurve {
+    /// The invariant `D` of a pool holding `reserves` with amplification
+    /// `amp`, scaled by `AMP_PRECISION`, found by Newton's method exactly as
+    /// Curve's `get_D`.
+    ///
+    /// Returns `None` if `amp` is below `AMP_PRECISION`, a value overflows
+    /// or the iteration does not converge.
+    pub fn compute_d(amp: u64, reserves: Reserves) -> Option<u128> {
+        let (x, y) = (
+            U256::from(reserves.token_0.get()),
+            U256::from(reserves.token_1.get()),
+        );
+        let sum = x + y;
+        if sum.is_zero() {
+            return Some(0);
+        }
+        if amp < AMP_PRECISION || x.is_zero() || y.is_zero() {
+            return None;
+        }
+        let n = U256::from(N_COINS);
+        let precision = U256::from(AMP_PRECISION);
+        let amp_n = U256::from(amp) * n;
+        let (mut d, mut last_step) = (sum, U256::MAX);
+        for _ in 0..MAX_ITERATIONS {
+            let d_p = d.checked_mul(d)? / (x * n);
+            let d_p = d_p.checked_mul(d)? / (y * n);
+            let d_prev = d;
+            let numerator = (amp_n * sum / precision + d_p * n).checked_mul(d)?;
+            let denominator = amp_n.checked_sub(precision)? * d / precision + (n + 1) * d_p;
+            d = numerator / denominator;
+            let step = d.abs_diff(d_prev);
+            if step <= U256::one() {
+                return to_u128(d);
+            }
+            // once rounding stops the steps shrinking it only cycles through
+            // nearby values; take the lower so the pool is never valued
+            // above its balances
+            if step >= last_step {
+                return to_u128(d.min(d_prev));
+            }
+            last_step = step;
+        }
+        None
+    }
+
+    /// The destination reserve that keeps invariant `d` with the source
+    /// reserve at `new_source_amount`, as Curve's `get_y`.
+    ///
+    /// Returns `None` if `amp` is below `AMP_PRECISION`, the source reserve
+    /// is zero, a value overflows or the iteration does not converge.
+    pub fn compute_y(amp: u64, new_source_amount: u128, d: u128) -> Option<u128> {
+        if amp < AMP_PRECISION || new_source_amount == 0 {
+            return None;
+        }
+        let (x, d) = (U256::from(new_source_amount), U256::from(d));
+        let n = U256::from(N_COINS);
+        let precision = U256::from(AMP_PRECISION);
+        let amp_n = U256::from(amp) * n;
+        let c = d.checked_mul(d)? / (x * n);
+        let c = c.checked_mul(d)?.checked_mul(precision)? / (amp_n * n);
+        let b = x + d * precision / amp_n;
+        let (mut y, mut last_step) = (d, U256::MAX);
+        for _ in 0..MAX_ITERATIONS {
+            let y_prev = y;
+            y = (y.checked_mul(y)? + c) / ((y << 1) + b).checked_sub(d)?;
+            let step = y.abs_diff(y_prev);
+            if step <= U256::one() {
+                return to_u128(y);
+            }
+            // as for `compute_d`, but take the higher value, leaving the
+            // pool more of the destination token
+            if step >= last_step {
+                return to_u128(y.max(y_prev));
+            }
+            last_step = step;
+        }
+        None
+    }
+
+    /// Destination tokens received for `source_amount`, without fees.
+    ///
+    /// As in Curve, one token is kept back from the computed amount so the
+    /// invariant cannot decrease through the iteration's rounding.
+    pub fn swap_base_input_without_fees(
+        amp: u64,
+        source_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+    ) -> Option<u128> {
+        let d = Self::compute_d(
+            amp,
+            Reserves::from_source_and_destination(
+                TradeDirection::ZeroForOne,
+                swap_source_amount,
+                swap_destination_amount,
+            ),
+        )?;
+        let y = Self::compute_y(amp, swap_source_amount.checked_add(source_amount)?, d)?;
+        Some(swap_destination_amount.saturating_sub(y).saturating_sub(1))
+    }
+
+    /// Quote a swap of exactly `source_amount` in `trade_direction` at `now`,
+    /// charging fees on the source amount like the constant product curve.
+    ///
+    /// Returns `None` unless `fee_config` takes every fee from the input.
+    pub fn swap_exact_in(
+        amp_ramp: &AmpRamp,
+        now: u64,
+        trade_direction: TradeDirection,
+        source_amount: u128,
+        reserves: Reserves,
+        fee_config: FeeConfig,
+    ) -> Option<SwapResult> {
+        if !fee_config.is_input_only() {
+            return None;
+        }
+        let (swap_source_amount, swap_destination_amount) =
+            reserves.source_and_destination(trade_direction);
+        let trade_fee = Fees::trading_fee(source_amount, fee_config.trade_fee_rate.get())?;
+        let protocol_fee = Fees::protocol_fee(trade_fee, fee_config.protocol_fee_rate.get())?;
+        let destination_amount_swapped = Self::swap_base_input_without_fees(
+            amp_ramp.current_amp(now),
+            source_amount.checked_sub(trade_fee)?,
+            swap_source_amount,
+            swap_destination_amount,
+        )?;
+        Some(SwapResult {
+            new_swap_source_amount: swap_source_amount.checked_add(source_amount)?,
+            new_swap_destination_amount: swap_destination_amount
+                .checked_sub(destination_amount_swapped)?,
+            source_amount_swapped: source_amount,
+            destination_amount_swapped,
+            trade_fee,
+            protocol_fee,
+            destination_trade_fee: 0,
+            destination_protocol_fee: 0,
+            rounding_dust: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::curve::fees::FeeRate, proptest::prelude::*};
+
+    #[test]
+    fn ramp_interpolates_like_curve() {
+        let mut ramp = AmpRamp::new(100).unwrap();
+        assert_eq!(ramp.current_amp(0), 10_000);
+        assert_eq!(ramp.ramp(200, 2 * MIN_RAMP_TIME, 0), None);
+        ramp.ramp(200, 3 * MIN_RAMP_TIME, MIN_RAMP_TIME).unwrap();
+        assert_eq!(ramp.current_amp(MIN_RAMP_TIME), 10_000);
+        assert_eq!(ramp.current_amp(2 * MIN_RAMP_TIME), 15_000);
+        assert_eq!(ramp.current_amp(2 * MIN_RAMP_TIME + 1), 15_000);
+        assert_eq!(ramp.current_amp(3 * MIN_RAMP_TIME), 20_000);
+        assert_eq!(ramp.current_amp(u64::MAX), 20_000);
+
+        // a new ramp starts from wherever the last one had got to
+        let now = 2 * MIN_RAMP_TIME + MIN_RAMP_TIME / 2;
+        ramp.ramp(50, now + MIN_RAMP_TIME, now).unwrap();
+        assert_eq!(ramp.initial_amp, 17_500);
+        assert_eq!(ramp.current_amp(now + MIN_RAMP_TIME / 2), 11_250);
+        ramp.stop_ramp(now + MIN_RAMP_TIME / 2);
+        assert_eq!(ramp.current_amp(u64::MAX), 11_250);
+
+        // the fields are public, so a ramp can end before it starts
+        let backwards = AmpRamp {
+            initial_amp: 10_000,
+            target_amp: 20_000,
+            initial_time: 100,
+            target_time: 50,
+        };
+        assert_eq!(backwards.current_amp(0), 20_000);
+        assert_eq!(backwards.current_amp(75), 20_000);
+    }
+
+    #[test]
+    fn ramp_bounds() {
+        let mut ramp = AmpRamp::new(100).unwrap();
+        let now = MIN_RAMP_TIME;
+        let before = ramp;
+        // too short, too far, zero and above the maximum
+        assert_eq!(ramp.ramp(200, now + MIN_RAMP_TIME - 1, now), None);
+        assert_eq!(ramp.ramp(1_001, now + MIN_RAMP_TIME, now), None);
+        assert_eq!(ramp.ramp(9, now + MIN_RAMP_TIME, now), None);
+        assert_eq!(ramp.ramp(0, now + MIN_RAMP_TIME, now), None);
+        assert_eq!(ramp, before);
+        assert_eq!(AmpRamp::new(0), None);
+        assert_eq!(AmpRamp::new(MAX_AMP), None);
+        ramp.ramp(1_000, now + MIN_RAMP_TIME, now).unwrap();
+        // a tenth of the amplification the last ramp reached, but no lower
+        let now = now + 2 * MIN_RAMP_TIME;
+        assert_eq!(ramp.ramp(99, now + MIN_RAMP_TIME, now), None);
+        ramp.ramp(100, now + MIN_RAMP_TIME, now).unwrap();
+    }
+
+    #[test]
+    fn balanced_pool_trades_near_par() {
+        let amp = 100 * AMP_PRECISION;
+        let pool = Reserves::from_raw(1_000_000_000_000, 1_000_000_000_000);
+        assert_eq!(
+            StableSwapCurve::compute_d(amp, pool),
+            Some(2_000_000_000_000)
+        );
+        let out = StableSwapCurve::swap_base_input_without_fees(
+            amp,
+            1_000_000,
+            1_000_000_000_000,
+            1_000_000_000_000,
+        )
+        .unwrap();
+        assert!((999_990..1_000_000).contains(&out));
+        // far more than the constant product curve pays
+        let out = StableSwapCurve::swap_base_input_without_fees(
+            amp,
+            100_000_000_000,
+            1_000_000_000_000,
+            1_000_000_000_000,
+        )
+        .unwrap();
+        assert!(out > 99_000_000_000);
+
+        let ramp = AmpRamp::new(100).unwrap();
+        let fee_config = FeeConfig::new(FeeRate::from_bps(4).unwrap(), FeeRate::ZERO);
+        let result = StableSwapCurve::swap_exact_in(
+            &ramp,
+            0,
+            TradeDirection::OneForZero,
+            1_000_000,
+            pool,
+            fee_config,
+        )
+        .unwrap();
+        assert_eq!(result.trade_fee, 400);
+        assert!((999_590..999_600).contains(&result.destination_amount_swapped));
+        assert_eq!(StableSwapCurve::compute_d(0, pool), None);
+        assert_eq!(
+            StableSwapCurve::compute_d(amp, Reserves::from_raw(0, 0)),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn tiny_amps_are_rejected() {
+        // below half of `AMP_PRECISION` the denominator used to underflow
+        let pool = Reserves::from_raw(1_000_000, 2_000_000);
+        for amp in [1, AMP_PRECISION / 2, AMP_PRECISION - 1] {
+            assert!(!is_valid_amp(amp));
+            assert_eq!(StableSwapCurve::compute_d(amp, pool), None);
+            assert_eq!(StableSwapCurve::compute_y(amp, 1_000_000, 3_000_000), None);
+        }
+        assert!(is_valid_amp(AMP_PRECISION));
+        assert!(StableSwapCurve::compute_d(AMP_PRECISION, pool).is_some());
+        assert!(is_valid_amp(MAX_AMP * AMP_PRECISION - 1));
+        assert!(!is_valid_amp(MAX_AMP * AMP_PRECISION));
+    }
+
+    proptest! {
+        #[test]
+        fn ramp_is_monotone(
+            amp in 1u64..1_000,
+            factor in 1u64..=10,
+            up in any::<bool>(),
+            duration in MIN_RAMP_TIME..10 * MIN_RAMP_TIME,
+            t in 0u64..20 * MIN_RAMP_TIME,
+        ) {
+            let mut ramp = AmpRamp::new(amp).unwrap();
+            let target = if up { amp * factor } else { amp.div_ceil(factor) };
+            let start = MIN_RAMP_TIME;
+            prop_assume!(target < MAX_AMP);
+            ramp.ramp(target, start + duration, start).unwrap();
+            let (now, later) = (ramp.current_amp(start + t), ramp.current_amp(start + t + 1));
+            let (low, high) = (amp.min(target) * AMP_PRECISION, amp.max(target) * AMP_PRECISION);
+            prop_assert!((low..=high).contains(&now));
+            prop_assert_eq!(later.cmp(&now).is_ge(), up || later == now);
+        }
+
+        #[test]
+        fn swaps_never_decrease_the_invariant(
+            amp in 1u64..10_000,
+            token_0 in 1_000_000u128..1 << 80,
+            token_1 in 1_000_000u128..1 << 80,
+            source_amount in 1u128..1 << 80,
+        ) {
+            let amp = amp * AMP_PRECISION;
+            let before = StableSwapCurve::compute_d(amp, Reserves::from_raw(token_0, token_1)).unwrap();
+            let out = StableSwapCurve::swap_base_input_without_fees(
+                amp,
+                source_amount,
+                token_0,
+                token_1,
+            )
+            .unwrap();
+            prop_assert!(out < token_1);
+            let after =
+                StableSwapCurve::compute_d(amp, Reserves::from_raw(token_0 + source_amount, token_1 - out))
+                    .unwrap();
+            prop_assert!(after >= before);
+        }
+    }
+}
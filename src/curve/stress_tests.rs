@@ -0,0 +1,264 @@
+//! Overflow stress tests
+//!
+//! Swaps and LP conversions against reserves near `u64::MAX`, the largest a
+//! token account can hold, with amounts and LP supplies up to the u128
+//! limits. Every input must either succeed or fail with a typed error, and
+//! results must match wide arithmetic whether the calculator took its u128
+//! fast path or fell back to U256.
+use {
+    crate::{
+        curve::{
+            amount::{Reserves, Token0Amount, Token1Amount},
+            calculator::{CurveCalculator, CurveError, RoundDirection, TradeDirection},
+            fees::{
+                FEE_RATE_DENOMINATOR_VALUE, FeeConfig, FeeRate, MAX_TRADE_FEE_RATE,
+                ProtocolFeeMode, TinySwapPolicy, TradeFeeSide,
+            },
+            swap_params::SwapParams,
+        },
+        utils::U256,
+    },
+    proptest::prelude::*,
+};
+
+const NEAR_U64_MAX: core::ops::RangeInclusive<u128> =
+    (u64::MAX - (1 << 32)) as u128..=u64::MAX as u128;
+
+fn trade_fee_rate() -> impl Strategy<Value = u64> {
+    0..=MAX_TRADE_FEE_RATE.get()
+}
+
+fn protocol_fee_rate() -> impl Strategy<Value = u64> {
+    0..=FEE_RATE_DENOMINATOR_VALUE
+}
+
+fn invariant(swap_source_amount: u128, swap_destination_amount: u128) -> U256 {
+    U256::from(swap_source_amount) * U256::from(swap_destination_amount)
+}
+
+#[test]
+fn full_reserves_at_the_limits() {
+    let max = u64::MAX as u128;
+    // `delta_x * y` needs U256, the result does not
+    let result = CurveCalculator::try_swap_base_input(
+        &SwapParams::from_raw_parts(u128::MAX - max, max, max, 0, 0).unwrap(),
+        TinySwapPolicy::Clamp,
+    )
+    .unwrap();
+    assert_eq!(result.new_swap_source_amount, u128::MAX);
+    assert_eq!(result.destination_amount_swapped, max - 1);
+    // one more unit and the source reserve overflows
+    assert_eq!(
+        CurveCalculator::try_swap_base_input(
+            &SwapParams::from_raw_parts(u128::MAX - max + 1, max, max, 0, 0).unwrap(),
+            TinySwapPolicy::Clamp
+        ),
+        Err(CurveError::Overflow)
+    );
+    // buying all but one unit of a full reserve needs about `max^2` in
+    assert_eq!(
+        CurveCalculator::try_swap_base_output(
+            &SwapParams::from_raw_parts(max - 1, max, max, 0, 0).unwrap()
+        )
+        .unwrap()
+        .source_amount_swapped,
+        max * (max - 1)
+    );
+    assert_eq!(
+        CurveCalculator::try_swap_base_output(
+            &SwapParams::from_raw_parts(max - 1, u128::MAX, max, 0, 0).unwrap()
+        ),
+        Err(CurveError::Overflow)
+    );
+    assert_eq!(
+        CurveCalculator::try_swap_base_output(
+            &SwapParams::from_raw_parts(max, max, max, 0, 0).unwrap()
+        ),
+        Err(CurveError::InsufficientLiquidity)
+    );
+    // `lp_amount * reserve` overflows u128 before the share is taken
+    assert_eq!(
+        CurveCalculator::try_lp_tokens_to_trading_tokens(
+            u128::MAX - 1,
+            u128::MAX,
+            max,
+            max,
+            RoundDirection::Floor
+        ),
+        Err(CurveError::Overflow)
+    );
+    // except when burning the whole supply, which takes the reserves as is
+    let result = CurveCalculator::try_lp_tokens_to_trading_tokens(
+        u128::MAX,
+        u128::MAX,
+        max,
+        max,
+        RoundDirection::Floor,
+    )
+    .unwrap();
+    assert_eq!((result.token_0_amount, result.token_1_amount), (max, max));
+}
+
+proptest! {
+    #[test]
+    fn exact_in_near_max_reserves(
+        source_amount in 1..=u128::MAX,
+        swap_source_amount in NEAR_U64_MAX,
+        swap_destination_amount in NEAR_U64_MAX,
+        trade_fee_rate in trade_fee_rate(),
+        protocol_fee_rate in protocol_fee_rate(),
+    ) {
+        let result = CurveCalculator::try_swap_base_input(
+            &SwapParams::from_raw_parts(
+                source_amount,
+                swap_source_amount,
+                swap_destination_amount,
+                trade_fee_rate,
+                protocol_fee_rate,
+            )
+            .unwrap(),
+            TinySwapPolicy::Clamp,
+        );
+        // the only failure left is the source reserve itself overflowing
+        let Ok(result) = result else {
+            prop_assert_eq!(result, Err(CurveError::Overflow));
+            prop_assert!(swap_source_amount.checked_add(source_amount).is_none());
+            return Ok(());
+        };
+        let source_amount_less_fees = U256::from(source_amount - result.trade_fee);
+        let expected = source_amount_less_fees * U256::from(swap_destination_amount)
+            / (U256::from(swap_source_amount) + source_amount_less_fees);
+        prop_assert_eq!(U256::from(result.destination_amount_swapped), expected);
+        prop_assert!(
+            invariant(result.new_swap_source_amount, result.new_swap_destination_amount)
+                >= invariant(swap_source_amount, swap_destination_amount)
+        );
+    }
+
+    #[test]
+    fn exact_out_near_max_reserves(
+        destination_amount in 1..=u128::MAX,
+        swap_source_amount in 1..=u128::MAX,
+        swap_destination_amount in NEAR_U64_MAX,
+        trade_fee_rate in trade_fee_rate(),
+        protocol_fee_rate in protocol_fee_rate(),
+    ) {
+        // most amounts are past the reserve, so also try ones below it
+        let destination_amount = destination_amount % (swap_destination_amount + 1);
+        let result = CurveCalculator::try_swap_base_output(
+            &SwapParams::from_raw_parts(
+                destination_amount,
+                swap_source_amount,
+                swap_destination_amount,
+                trade_fee_rate,
+                protocol_fee_rate,
+            )
+            .unwrap(),
+        );
+        match result {
+            Ok(result) => {
+                let numerator = U256::from(swap_source_amount) * U256::from(destination_amount);
+                let denominator = U256::from(swap_destination_amount - destination_amount);
+                let (quotient, remainder) = numerator.div_mod(denominator);
+                let expected = if remainder.is_zero() { quotient } else { quotient + 1 };
+                // the fee gross-up rounds up, so more than needed can be left
+                let source_amount_less_fees =
+                    U256::from(result.source_amount_swapped - result.trade_fee);
+                prop_assert!(source_amount_less_fees >= expected);
+                if trade_fee_rate == 0 {
+                    prop_assert_eq!(source_amount_less_fees, expected);
+                }
+                prop_assert!(
+                    invariant(result.new_swap_source_amount, result.new_swap_destination_amount)
+                        >= invariant(swap_source_amount, swap_destination_amount)
+                );
+            }
+            Err(CurveError::ZeroTradeAmount) => prop_assert_eq!(destination_amount, 0),
+            Err(CurveError::InsufficientLiquidity) => {
+                prop_assert_eq!(destination_amount, swap_destination_amount)
+            }
+            Err(error) => prop_assert_eq!(error, CurveError::Overflow),
+        }
+    }
+
+    #[test]
+    fn lp_conversions_near_max_supply(
+        // small amounts too, or nearly every product would overflow
+        lp_token_amount in prop_oneof![1..=u64::MAX as u128, 1..=u128::MAX],
+        lp_token_supply in 1..=u128::MAX,
+        swap_token_0_amount in NEAR_U64_MAX,
+        swap_token_1_amount in NEAR_U64_MAX,
+        round_up: bool,
+    ) {
+        let round_direction = if round_up {
+            RoundDirection::Ceiling
+        } else {
+            RoundDirection::Floor
+        };
+        let result = CurveCalculator::try_lp_tokens_to_trading_tokens(
+            lp_token_amount,
+            lp_token_supply,
+            swap_token_0_amount,
+            swap_token_1_amount,
+            round_direction,
+        );
+        let Ok(result) = result else {
+            prop_assert_eq!(result, Err(CurveError::Overflow));
+            prop_assert!(
+                lp_token_amount.checked_mul(swap_token_0_amount).is_none()
+                    || lp_token_amount.checked_mul(swap_token_1_amount).is_none()
+            );
+            return Ok(());
+        };
+        for (amount, reserve) in [
+            (result.token_0_amount, swap_token_0_amount),
+            (result.token_1_amount, swap_token_1_amount),
+        ] {
+            let share = U256::from(lp_token_amount) * U256::from(reserve);
+            let supply = U256::from(lp_token_supply);
+            prop_assert!(U256::from(amount) * supply <= share + supply);
+            if lp_token_amount <= lp_token_supply && !round_up {
+                prop_assert!(amount <= reserve);
+            }
+        }
+    }
+
+    #[test]
+    fn typed_swaps_near_max_reserves(
+        amount in 1..=u64::MAX as u128,
+        token_0 in NEAR_U64_MAX,
+        token_1 in NEAR_U64_MAX,
+        trade_fee_rate in trade_fee_rate(),
+        protocol_fee_rate in protocol_fee_rate(),
+        split: bool,
+        fee_on_output: bool,
+        zero_for_one: bool,
+    ) {
+        let reserves = Reserves::new(Token0Amount(token_0), Token1Amount(token_1));
+        let fee_config = FeeConfig::new(
+            FeeRate::checked(trade_fee_rate).unwrap(),
+            FeeRate::checked(protocol_fee_rate).unwrap(),
+        )
+        .with_protocol_fee_mode(if split { ProtocolFeeMode::Split } else { ProtocolFeeMode::Input })
+        .with_trade_fee_side(if fee_on_output { TradeFeeSide::Output } else { TradeFeeSide::Input });
+        let trade_direction = if zero_for_one {
+            TradeDirection::ZeroForOne
+        } else {
+            TradeDirection::OneForZero
+        };
+        // amounts up to a whole reserve always fit once both reserves are
+        // below 2^64
+        prop_assert!(
+            CurveCalculator::swap_exact_in(trade_direction, amount, reserves, fee_config).is_some()
+        );
+        let (_, swap_destination_amount) = reserves.source_and_destination(trade_direction);
+        let destination_amount = amount % swap_destination_amount;
+        let result =
+            CurveCalculator::swap_exact_out(trade_direction, destination_amount, reserves, fee_config);
+        // the fee gross-up of amounts close to the reserve can still overflow
+        match result {
+            Some(result) => prop_assert_eq!(result.destination_amount_swapped, destination_amount),
+            None => prop_assert!(destination_amount == 0 || trade_fee_rate > 0),
+        }
+    }
+}
@@ -0,0 +1,260 @@
+//! Swap parameters
+//!
+//! `swap_base_input` and `swap_base_output` used to take the amount, both
+//! reserves and both fee rates as positional integers, so transposing two of
+//! them compiled and quoted a different swap. `SwapParams` names each input
+//! instead, and new inputs become fields with a default rather than new
+//! arguments.
+use crate::{
+    curve::{
+        amount::Reserves,
+        calculator::{CurveError, SwapResult, TradeDirection},
+        fees::{FeeConfig, FeeRate},
+    },
+    utils::{PriceQ64, Q64_RESOLUTION, U256},
+};
+
+/// A swap to quote
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SwapParams {
+    /// Source amount of an exact-in swap, destination amount of an exact-out
+    /// swap
+    pub amount: u128,
+    /// Reserves of the pool before the swap
+    pub reserves: Reserves,
+    pub trade_direction: TradeDirection,
+    pub fee_config: FeeConfig,
+    /// Price of token 0 quoted in token 1 the swap may move the pool to, a
+    /// floor for `ZeroForOne` and a ceiling for `OneForZero` swaps
+    pub price_limit: Option<PriceQ64>,
+}
+
+impl SwapParams {
+    /// A swap of `amount` in `trade_direction`, without a price limit
+    pub const fn new(
+        trade_direction: TradeDirection,
+        amount: u128,
+        reserves: Reserves,
+        fee_config: FeeConfig,
+    ) -> Self {
+        Self {
+            amount,
+            reserves,
+            trade_direction,
+            fee_config,
+            price_limit: None,
+        }
+    }
+
+    /// The same swap, failing if it moves the price past `price_limit`
+    pub const fn with_price_limit(self, price_limit: PriceQ64) -> Self {
+        Self {
+            price_limit: Some(price_limit),
+            ..self
+        }
+    }
+
+    // A swap of `amount` in a pool holding the raw `swap_source_amount` and
+    // `swap_destination_amount`, as bindings receive them, or `None` if a
+    // fee rate exceeds 100%. Rates a pool cannot be configured with are left
+    // for the swap to reject.
+    pub(crate) fn from_raw_parts(
+        amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        trade_fee_rate: u64,
+        protocol_fee_rate: u64,
+    ) -> Option<Self> {
+        let trade_direction = TradeDirection::ZeroForOne;
+        Some(Self::new(
+            trade_direction,
+            amount,
+            Reserves::from_source_and_destination(
+                trade_direction,
+                swap_source_amount,
+                swap_destination_amount,
+            ),
+            FeeConfig::new(
+                FeeRate::checked(trade_fee_rate)?,
+                FeeRate::checked(protocol_fee_rate)?,
+            ),
+        ))
+    }
+
+    /// The `(source, destination)` reserves of the swap
+    pub const fn source_and_destination(&self) -> (u128, u128) {
+        self.reserves.source_and_destination(self.trade_direction)
+    }
+
+    // Fails with `PriceLimitExceeded` if the reserves `result` leaves, less
+    // the protocol fees, are priced past the limit. Compared exactly, as
+    // `token_1 * 2^64` against `limit * token_0`, so no rounding of the
+    // price lets a swap through.
+    pub(crate) fn check_price_limit(&self, result: &SwapResult) -> Result<(), CurveError> {
+        let Some(price_limit) = self.price_limit else {
+            return Ok(());
+        };
+        let reserves = Reserves::from_source_and_destination(
+            self.trade_direction,
+            result
+                .new_swap_source_amount
+                .checked_sub(result.protocol_fee)
+                .ok_or(CurveError::Overflow)?,
+            result.new_swap_destination_amount,
+        );
+        let price = U256::from(reserves.token_1.0) << Q64_RESOLUTION;
+        let limit = U256::from(price_limit.raw()) * U256::from(reserves.token_0.0);
+        let within_limit = match self.trade_direction {
+            TradeDirection::ZeroForOne => price >= limit,
+            TradeDirection::OneForZero => price <= limit,
+        };
+        if within_limit {
+            Ok(())
+        } else {
+            Err(CurveError::PriceLimitExceeded)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::curve::{
+            amount::{Token0Amount, Token1Amount},
+            calculator::CurveCalculator,
+            fees::{FeeRate, TinySwapPolicy, TradeFeeSide},
+        },
+        proptest::prelude::*,
+    };
+
+    const RESERVES: Reserves = Reserves::new(Token0Amount(1_000_000), Token1Amount(1_000_000));
+
+    // Price of token 0 in token 1 after `result`, rounded down
+    fn price_after(params: &SwapParams, result: &SwapResult) -> Option<PriceQ64> {
+        let reserves = Reserves::from_source_and_destination(
+            params.trade_direction,
+            result.new_swap_source_amount - result.protocol_fee,
+            result.new_swap_destination_amount,
+        );
+        PriceQ64::from_reserves(reserves.token_0.0, reserves.token_1.0)
+    }
+
+    #[test]
+    fn params_follow_the_direction_and_fee_config() {
+        let fee_config = FeeConfig::STANDARD_25BPS;
+        let reserves = Reserves::new(Token0Amount(1_000_000), Token1Amount(4_000_000));
+        let zero_for_one = SwapParams::new(TradeDirection::ZeroForOne, 1_000, reserves, fee_config);
+        // the same pool with the tokens swapped
+        let mirrored = SwapParams::new(
+            TradeDirection::OneForZero,
+            1_000,
+            Reserves::from_source_and_destination(TradeDirection::OneForZero, 1_000_000, 4_000_000),
+            fee_config,
+        );
+        assert_eq!(
+            CurveCalculator::swap_base_input(&zero_for_one),
+            CurveCalculator::swap_base_input(&mirrored)
+        );
+
+        let fee_on_output = SwapParams {
+            fee_config: fee_config.with_trade_fee_side(TradeFeeSide::Output),
+            ..zero_for_one
+        };
+        let result = CurveCalculator::swap_base_input(&fee_on_output).unwrap();
+        assert_eq!(result.trade_fee, 0);
+        assert_ne!(result.destination_trade_fee, 0);
+
+        let invalid = SwapParams {
+            fee_config: FeeConfig::new(FeeRate::MAX, FeeRate::ZERO),
+            ..zero_for_one
+        };
+        assert_eq!(
+            CurveCalculator::try_swap_base_input(&invalid, TinySwapPolicy::Clamp),
+            Err(CurveError::FeeConfigInvalid)
+        );
+        assert_eq!(
+            CurveCalculator::try_swap_base_output(&invalid),
+            Err(CurveError::FeeConfigInvalid)
+        );
+    }
+
+    #[test]
+    fn price_limit_bounds_the_move() {
+        // selling 1% of token 0 moves its price from 1 to about 0.98
+        let params = SwapParams::new(
+            TradeDirection::ZeroForOne,
+            10_000,
+            RESERVES,
+            FeeConfig::STANDARD_25BPS,
+        );
+        let unlimited = CurveCalculator::swap_base_input(&params).unwrap();
+        let limited = params.with_price_limit(PriceQ64::from_ratio(98, 100).unwrap());
+        assert_eq!(CurveCalculator::swap_base_input(&limited), Some(unlimited));
+        let limited = params.with_price_limit(PriceQ64::from_ratio(99, 100).unwrap());
+        assert_eq!(
+            CurveCalculator::try_swap_base_input(&limited, TinySwapPolicy::Clamp),
+            Err(CurveError::PriceLimitExceeded)
+        );
+
+        // buying 1% of token 0 raises its price to about 1.02, so the limit
+        // is a ceiling
+        let params = SwapParams::new(
+            TradeDirection::OneForZero,
+            10_000,
+            RESERVES,
+            FeeConfig::STANDARD_25BPS,
+        );
+        let limited = params.with_price_limit(PriceQ64::from_ratio(103, 100).unwrap());
+        assert!(CurveCalculator::try_swap_base_output(&limited).is_ok());
+        let limited = params.with_price_limit(PriceQ64::from_ratio(102, 100).unwrap());
+        assert_eq!(
+            CurveCalculator::try_swap_base_output(&limited),
+            Err(CurveError::PriceLimitExceeded)
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn price_limit_is_exact(
+            token_0 in 1..u64::MAX as u128,
+            token_1 in 1..u64::MAX as u128,
+            amount in 1..u64::MAX as u128,
+            zero_for_one: bool,
+        ) {
+            let trade_direction = if zero_for_one {
+                TradeDirection::ZeroForOne
+            } else {
+                TradeDirection::OneForZero
+            };
+            let params = SwapParams::new(
+                trade_direction,
+                amount,
+                Reserves::new(Token0Amount(token_0), Token1Amount(token_1)),
+                FeeConfig::STANDARD_25BPS,
+            );
+            let result = CurveCalculator::swap_base_input(&params);
+            prop_assume!(result.is_some());
+            let result = result.unwrap();
+            let price = price_after(&params, &result);
+            prop_assume!(price.is_some_and(|price| price.raw() > 0));
+            let raw = price.unwrap().raw();
+            let swap = |limit| {
+                let params = params.with_price_limit(PriceQ64::from_raw(limit));
+                CurveCalculator::swap_base_input(&params)
+            };
+            // the price rounded down is at or below the exact price, and one
+            // unit more is above it
+            match trade_direction {
+                TradeDirection::ZeroForOne => {
+                    prop_assert_eq!(swap(raw), Some(result));
+                    prop_assert_eq!(swap(raw + 1), None);
+                }
+                TradeDirection::OneForZero => {
+                    prop_assert_eq!(swap(raw + 1), Some(result));
+                    prop_assert_eq!(swap(raw - 1), None);
+                }
+            }
+        }
+    }
+}
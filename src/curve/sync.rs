@@ -0,0 +1,266 @@
+//! Vault balance reconciliation
+//!
+//! A pool's recorded reserves only change through swaps, deposits and
+//! withdrawals, but the vaults backing them can drift: rebasing and
+//! elastic-supply tokens change balances in place, and anyone can donate
+//! tokens directly to a vault. `CurveCalculator::sync` reconciles the
+//! recorded reserves with the actual vault balances.
+//!
+//! A surplus is either folded into the reserves, accruing to LPs, skimmed so
+//! the program can move it to a fee vault, or rejected outright, as chosen by
+//! the pool's `SurplusPolicy`. A shortfall (a negative rebase) is always
+//! absorbed by the reserves; accrued protocol fees are never reduced.
+use {
+    crate::curve::{
+        amount::{Reserves, Token0Amount, Token1Amount},
+        calculator::CurveCalculator,
+    },
+    borsh::{BorshDeserialize, BorshSerialize},
+};
+
+/// What to do with vault balances in excess of reserves and protocol fees
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+#[borsh(use_discriminant = true)]
+pub enum SurplusPolicy {
+    /// Add the surplus to the reserves, benefiting LPs
+    #[default]
+    FoldIntoReserves = 0,
+    /// Leave the reserves unchanged and report the surplus for transfer to a
+    /// fee vault
+    SkimToFeeVault = 1,
+    /// Fail the sync while the vaults hold a surplus, for pools whose books
+    /// must not be moved by out-of-band transfers
+    Reject = 2,
+}
+
+/// Outcome of reconciling reserves with vault balances
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SyncResult {
+    /// Reserves after reconciliation
+    pub reserves: Reserves,
+    /// Surplus to transfer out of the vaults, zero unless skimming
+    pub skimmed: Reserves,
+    /// Amount by which the reserves were reduced to match the vaults
+    pub shortfall: Reserves,
+}
+
+// (new reserve, skimmed, shortfall) for one token
+fn reconcile(
+    reserve: u128,
+    protocol_fees: u128,
+    balance: u128,
+    policy: SurplusPolicy,
+) -> Option<(u128, u128, u128)> {
+    let owed = reserve.checked_add(protocol_fees)?;
+    match balance.checked_sub(owed) {
+        Some(surplus) => match policy {
+            SurplusPolicy::FoldIntoReserves => Some((reserve + surplus, 0, 0)),
+            SurplusPolicy::SkimToFeeVault => Some((reserve, surplus, 0)),
+            SurplusPolicy::Reject => (surplus == 0).then_some((reserve, 0, 0)),
+        },
+        None => {
+            let new_reserve = balance.checked_sub(protocol_fees)?;
+            Some((new_reserve, 0, reserve - new_reserve))
+        }
+    }
+}
+
+impl CurveCalculator {
+    /// Reconcile `reserves` with the vault `balances`, which also hold the
+    /// uncollected `protocol_fees`.
+    ///
+    /// Returns `None` if a vault holds less than its protocol fees, the
+    /// recorded amounts overflow, or `policy` rejects a surplus.
+    pub fn sync(
+        reserves: Reserves,
+        protocol_fees: Reserves,
+        balances: Reserves,
+        policy: SurplusPolicy,
+    ) -> Option<SyncResult> {
+        let (reserve_0, skimmed_0, shortfall_0) = reconcile(
+            reserves.token_0.get(),
+            protocol_fees.token_0.get(),
+            balances.token_0.get(),
+            policy,
+        )?;
+        let (reserve_1, skimmed_1, shortfall_1) = reconcile(
+            reserves.token_1.get(),
+            protocol_fees.token_1.get(),
+            balances.token_1.get(),
+            policy,
+        )?;
+        Some(SyncResult {
+            reserves: Reserves::new(Token0Amount(reserve_0), Token1Amount(reserve_1)),
+            skimmed: Reserves::new(Token0Amount(skimmed_0), Token1Amount(skimmed_1)),
+            shortfall: Reserves::new(Token0Amount(shortfall_0), Token1Amount(shortfall_1)),
+        })
+    }
+
+    /// Vault balances above `reserves` and `protocol_fees`, to transfer out
+    /// of the vaults whatever the pool's `SurplusPolicy`.
+    ///
+    /// The recorded amounts are left as they are. Returns `None` if a vault
+    /// holds less than it owes, which a sync must reconcile first.
+    pub fn skim(
+        reserves: Reserves,
+        protocol_fees: Reserves,
+        balances: Reserves,
+    ) -> Option<Reserves> {
+        let result = Self::sync(
+            reserves,
+            protocol_fees,
+            balances,
+            SurplusPolicy::SkimToFeeVault,
+        )?;
+        (result.shortfall == Reserves::default()).then_some(result.skimmed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, proptest::prelude::*};
+
+    #[test]
+    fn surplus_follows_policy() {
+        let fees = Reserves::from_raw(5, 7);
+        let balances = Reserves::from_raw(1_105, 2_007);
+        let folded = CurveCalculator::sync(
+            Reserves::from_raw(1_000, 2_000),
+            fees,
+            balances,
+            SurplusPolicy::FoldIntoReserves,
+        )
+        .unwrap();
+        assert_eq!(folded.reserves, Reserves::from_raw(1_100, 2_000));
+        assert_eq!(folded.skimmed, Reserves::default());
+        assert_eq!(folded.shortfall, Reserves::default());
+
+        let skimmed = CurveCalculator::sync(
+            Reserves::from_raw(1_000, 2_000),
+            fees,
+            balances,
+            SurplusPolicy::SkimToFeeVault,
+        )
+        .unwrap();
+        assert_eq!(skimmed.reserves, Reserves::from_raw(1_000, 2_000));
+        assert_eq!(skimmed.skimmed, Reserves::from_raw(100, 0));
+    }
+
+    #[test]
+    fn rejecting_policy_refuses_surplus() {
+        let sync = |balances| {
+            CurveCalculator::sync(
+                Reserves::from_raw(1_000, 2_000),
+                Reserves::from_raw(5, 7),
+                balances,
+                SurplusPolicy::Reject,
+            )
+        };
+        assert_eq!(sync(Reserves::from_raw(1_006, 2_007)), None);
+        assert_eq!(sync(Reserves::from_raw(1_005, 2_008)), None);
+        let result = sync(Reserves::from_raw(1_005, 2_007)).unwrap();
+        assert_eq!(result.reserves, Reserves::from_raw(1_000, 2_000));
+        // a shortfall is still absorbed
+        let result = sync(Reserves::from_raw(905, 2_007)).unwrap();
+        assert_eq!(result.reserves, Reserves::from_raw(900, 2_000));
+        assert_eq!(result.shortfall, Reserves::from_raw(100, 0));
+    }
+
+    #[test]
+    fn skim_takes_only_the_excess() {
+        let skim = |balances| {
+            CurveCalculator::skim(
+                Reserves::from_raw(1_000, 2_000),
+                Reserves::from_raw(5, 7),
+                balances,
+            )
+        };
+        assert_eq!(
+            skim(Reserves::from_raw(1_105, 2_007)),
+            Some(Reserves::from_raw(100, 0))
+        );
+        assert_eq!(
+            skim(Reserves::from_raw(1_005, 2_007)),
+            Some(Reserves::default())
+        );
+        // a vault short of what it owes must be synced first
+        assert_eq!(skim(Reserves::from_raw(1_105, 2_006)), None);
+    }
+
+    #[test]
+    fn shortfall_is_absorbed_by_reserves() {
+        let result = CurveCalculator::sync(
+            Reserves::from_raw(1_000, 2_000),
+            Reserves::from_raw(5, 7),
+            Reserves::from_raw(905, 2_007),
+            SurplusPolicy::SkimToFeeVault,
+        )
+        .unwrap();
+        assert_eq!(result.reserves, Reserves::from_raw(900, 2_000));
+        assert_eq!(result.shortfall, Reserves::from_raw(100, 0));
+        // protocol fees are never reduced
+        assert_eq!(
+            CurveCalculator::sync(
+                Reserves::from_raw(1_000, 2_000),
+                Reserves::from_raw(5, 7),
+                Reserves::from_raw(4, 2_007),
+                SurplusPolicy::FoldIntoReserves,
+            ),
+            None
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn sync_accounts_for_every_token(
+            reserve in 0..u64::MAX as u128,
+            fees in 0..u64::MAX as u128,
+            balance in 0..u64::MAX as u128,
+            policy in prop_oneof![
+                Just(SurplusPolicy::FoldIntoReserves),
+                Just(SurplusPolicy::SkimToFeeVault),
+                Just(SurplusPolicy::Reject),
+            ],
+        ) {
+            let result = CurveCalculator::sync(
+                Reserves::from_raw(reserve, 0),
+                Reserves::from_raw(fees, 0),
+                Reserves::from_raw(balance, 0),
+                policy,
+            );
+            let surplus = balance > reserve + fees;
+            prop_assert_eq!(
+                result.is_some(),
+                balance >= fees && !(surplus && policy == SurplusPolicy::Reject)
+            );
+            let Some(result) = result else {
+                return Ok(());
+            };
+            let new_reserve = result.reserves.token_0.get();
+            // only LPs are credited with a surplus, and only when folding
+            match policy {
+                SurplusPolicy::FoldIntoReserves => prop_assert!(!surplus || new_reserve > reserve),
+                SurplusPolicy::SkimToFeeVault | SurplusPolicy::Reject => {
+                    prop_assert!(new_reserve <= reserve)
+                }
+            }
+            // the vault exactly covers reserves, fees and the skimmed surplus
+            prop_assert_eq!(
+                new_reserve + fees + result.skimmed.token_0.get(),
+                balance
+            );
+            prop_assert_eq!(new_reserve + result.shortfall.token_0.get(), reserve.max(new_reserve));
+            // synced state is a fixed point
+            let again = CurveCalculator::sync(
+                result.reserves,
+                Reserves::from_raw(fees, 0),
+                Reserves::from_raw(balance - result.skimmed.token_0.get(), 0),
+                policy,
+            )
+            .unwrap();
+            prop_assert_eq!(again.reserves, result.reserves);
+            prop_assert_eq!(again.skimmed, Reserves::default());
+        }
+    }
+}
@@ -0,0 +1,487 @@
+//! Property checks for curve implementations
+//!
+//! Assertions that swaps, deposits and withdrawals never reduce the value of
+//! the pool or its LP tokens, plus the proptest strategies used to drive them.
+//! `CurveConformance` holds any `SwapCurve` to the same properties.
+//! Available to downstream crates through the `test-utils` feature, so
+//! programs embedding this math can run the same checks on their own pools.
+use {
+    crate::curve::{
+        amount::{Reserves, Token0Amount, Token1Amount},
+        calculator::{CurveCalculator, RoundDirection, SwapResult, TradeDirection},
+        constant_product::ConstantProductCurve,
+        fees::FeeConfig,
+        reference::normalized_value,
+        registry::SwapCurve,
+    },
+    proptest::prelude::*,
+    spl_math::{precise_number::PreciseNumber, uint::U256},
+};
+
+/// The epsilon for most curves when performing the conversion test,
+/// comparing a one-sided deposit to a swap + deposit.
+pub const CONVERSION_BASIS_POINTS_GUARANTEE: u128 = 50;
+
+// Test function checking that a swap never reduces the overall value of
+// the pool.
+//
+// Since curve calculations use unsigned integers, there is potential for
+// truncation at some point, meaning a potential for value to be lost in
+// either direction if too much is given to the swapper.
+//
+// This test guarantees that the relative change in value will be at most
+// 1 normalized token, and that the value will never decrease from a trade.
+pub fn check_curve_value_from_swap(
+    source_token_amount: u128,
+    swap_source_amount: u128,
+    swap_destination_amount: u128,
+    trade_direction: TradeDirection,
+) {
+    // Calculate the destination amount swapped using the constant product curve.
+    let destination_amount_swapped = ConstantProductCurve::try_swap_base_input_without_fees(
+        source_token_amount,
+        swap_source_amount,
+        swap_destination_amount,
+    )
+    .unwrap();
+
+    // Determine the token amounts based on the trade direction.
+    let (swap_token_0_amount, swap_token_1_amount) = match trade_direction {
+        TradeDirection::ZeroForOne => (swap_source_amount, swap_destination_amount),
+        TradeDirection::OneForZero => (swap_destination_amount, swap_source_amount),
+    };
+
+    // Calculate the invariant (product of token amounts) before the swap.
+    let previous_value = swap_token_0_amount.checked_mul(swap_token_1_amount).unwrap();
+
+    // Calculate the new token amounts after the swap.
+    let new_swap_source_amount = swap_source_amount.checked_add(source_token_amount).unwrap();
+    let new_swap_destination_amount =
+        swap_destination_amount.checked_sub(destination_amount_swapped).unwrap();
+
+    // Determine the new token amounts based on the trade direction.
+    let (swap_token_0_amount, swap_token_1_amount) = match trade_direction {
+        TradeDirection::ZeroForOne => (new_swap_source_amount, new_swap_destination_amount),
+        TradeDirection::OneForZero => (new_swap_destination_amount, new_swap_source_amount),
+    };
+
+    // Calculate the new invariant after the swap.
+    let new_value = swap_token_0_amount.checked_mul(swap_token_1_amount).unwrap();
+
+    // Assert that the new invariant is greater than or equal to the previous invariant.
+    assert!(new_value >= previous_value);
+}
+
+/// Test function checking that an exact-out swap never reduces the overall
+/// value of the pool.
+///
+/// The input is rounded up and the fees are added on top, so the invariant
+/// of the reserves left to LPs, excluding protocol fees, must not decrease.
+/// Swaps the calculator rejects are skipped.
+pub fn check_curve_value_from_swap_exact_out(
+    destination_amount: u128,
+    reserves: Reserves,
+    trade_direction: TradeDirection,
+    fee_config: FeeConfig,
+) {
+    let Some(result) =
+        CurveCalculator::swap_exact_out(trade_direction, destination_amount, reserves, fee_config)
+    else {
+        return;
+    };
+    let invariant = |reserves: Reserves| {
+        U256::from(reserves.token_0.get()) * U256::from(reserves.token_1.get())
+    };
+    let after = reserves_after(trade_direction, &result);
+    assert!(
+        invariant(after) >= invariant(reserves),
+        "exact-out swap of {destination_amount} lost value: {reserves:?} to {after:?}"
+    );
+}
+
+/// Test function checking that a deposit never reduces the value of pool
+/// tokens.
+///
+/// Since curve calculations use unsigned integers, there is potential for
+/// truncation at some point, meaning a potential for value to be lost if
+/// too much is given to the depositor.
+pub fn check_pool_value_from_deposit(
+    lp_token_amount: u128,
+    lp_token_supply: u128,
+    swap_token_0_amount: u128,
+    swap_token_1_amount: u128,
+) {
+    let deposit_result = CurveCalculator::lp_tokens_to_trading_tokens(
+        lp_token_amount,
+        lp_token_supply,
+        swap_token_0_amount,
+        swap_token_1_amount,
+        RoundDirection::Ceiling,
+    )
+    .unwrap();
+    let new_swap_token_0_amount = swap_token_0_amount + deposit_result.token_0_amount;
+    let new_swap_token_1_amount = swap_token_1_amount + deposit_result.token_1_amount;
+    let new_lp_token_supply = lp_token_supply + lp_token_amount;
+
+    // the following inequality must hold:
+    // new_token_a / new_pool_token_supply >= token_a / pool_token_supply
+    // which reduces to:
+    // new_token_a * pool_token_supply >= token_a * new_pool_token_supply
+
+    // These numbers can be just slightly above u64 after the deposit, which
+    // means that their multiplication can be just above the range of u128.
+    // For ease of testing, we bump these up to U256.
+    let lp_token_supply = U256::from(lp_token_supply);
+    let new_lp_token_supply = U256::from(new_lp_token_supply);
+    let swap_token_0_amount = U256::from(swap_token_0_amount);
+    let new_swap_token_0_amount = U256::from(new_swap_token_0_amount);
+    let swap_token_b_amount = U256::from(swap_token_1_amount);
+    let new_swap_token_b_amount = U256::from(new_swap_token_1_amount);
+
+    assert!(
+        new_swap_token_0_amount * lp_token_supply >= swap_token_0_amount * new_lp_token_supply
+    );
+    assert!(
+        new_swap_token_b_amount * lp_token_supply >= swap_token_b_amount * new_lp_token_supply
+    );
+}
+
+/// Test function checking that a withdraw never reduces the value of pool
+/// tokens.
+///
+/// Since curve calculations use unsigned integers, there is potential for
+/// truncation at some point, meaning a potential for value to be lost if
+/// too much is given to the depositor.
+pub fn check_pool_value_from_withdraw(
+    lp_token_amount: u128,
+    lp_token_supply: u128,
+    swap_token_0_amount: u128,
+    swap_token_1_amount: u128,
+) {
+    let withdraw_result = CurveCalculator::lp_tokens_to_trading_tokens(
+        lp_token_amount,
+        lp_token_supply,
+        swap_token_0_amount,
+        swap_token_1_amount,
+        RoundDirection::Floor,
+    )
+    .unwrap();
+    let new_swap_token_0_amount = swap_token_0_amount - withdraw_result.token_0_amount;
+    let new_swap_token_1_amount = swap_token_1_amount - withdraw_result.token_1_amount;
+    let new_pool_token_supply = lp_token_supply - lp_token_amount;
+
+    let value = normalized_value(swap_token_0_amount, swap_token_1_amount).unwrap();
+    // since we can get rounding issues on the pool value which make it seem that
+    // the value per token has gone down, we bump it up by an epsilon of 1
+    // to cover all cases
+    let new_value = normalized_value(new_swap_token_0_amount, new_swap_token_1_amount).unwrap();
+
+    // the following inequality must hold:
+    // new_pool_value / new_pool_token_supply >= pool_value / pool_token_supply
+    // which can also be written:
+    // new_pool_value * pool_token_supply >= pool_value * new_pool_token_supply
+
+    let lp_token_supply = PreciseNumber::new(lp_token_supply).unwrap();
+    let new_lp_token_supply = PreciseNumber::new(new_pool_token_supply).unwrap();
+    assert!(new_value
+        .checked_mul(&lp_token_supply)
+        .unwrap()
+        .greater_than_or_equal(&value.checked_mul(&new_lp_token_supply).unwrap()));
+}
+
+// Reserves after settling `result`, excluding the protocol fees
+fn reserves_after(trade_direction: TradeDirection, result: &SwapResult) -> Reserves {
+    Reserves::from_source_and_destination(
+        trade_direction,
+        result.new_swap_source_amount - result.protocol_fee,
+        result.new_swap_destination_amount,
+    )
+}
+
+// Exact-in quotes panic when `source_amount * destination reserve`
+// overflows, so the checks skip swaps outside that domain
+fn exact_in(
+    trade_direction: TradeDirection,
+    source_amount: u128,
+    reserves: Reserves,
+    fee_config: FeeConfig,
+) -> Option<SwapResult> {
+    let (_, swap_destination_amount) = reserves.source_and_destination(trade_direction);
+    source_amount.checked_mul(swap_destination_amount)?;
+    CurveCalculator::swap_exact_in(trade_direction, source_amount, reserves, fee_config)
+}
+
+/// Test function checking that an exact-out quote charges the cheapest
+/// input that buys the requested output.
+///
+/// Executing exact-in with the quoted input yields at least the requested
+/// output, and one unit less yields less. The exact-in output can still
+/// exceed the request when one input unit buys several output units, but
+/// the overshoot is never more than one input unit is worth.
+pub fn check_exact_out_then_exact_in(
+    destination_amount: u128,
+    reserves: Reserves,
+    trade_direction: TradeDirection,
+    fee_config: FeeConfig,
+) {
+    let Some(quote) =
+        CurveCalculator::swap_exact_out(trade_direction, destination_amount, reserves, fee_config)
+    else {
+        return;
+    };
+    let Some(exact_in) = exact_in(
+        trade_direction,
+        quote.source_amount_swapped,
+        reserves,
+        fee_config,
+    ) else {
+        return;
+    };
+    assert!(exact_in.destination_amount_swapped >= destination_amount);
+    if let Some(cheaper) = self::exact_in(
+        trade_direction,
+        quote.source_amount_swapped - 1,
+        reserves,
+        fee_config,
+    ) {
+        assert!(cheaper.destination_amount_swapped < destination_amount);
+    }
+}
+
+/// Test function checking that selling the output of a swap straight back
+/// to the pool never returns more than the original input, whether the
+/// first leg is exact-in for `amount` of source or exact-out for `amount`
+/// of destination.
+pub fn check_round_trip_no_profit(
+    amount: u128,
+    reserves: Reserves,
+    trade_direction: TradeDirection,
+    fee_config: FeeConfig,
+) {
+    let first_legs = [
+        exact_in(trade_direction, amount, reserves, fee_config),
+        CurveCalculator::swap_exact_out(trade_direction, amount, reserves, fee_config),
+    ];
+    for first in first_legs.into_iter().flatten() {
+        let Some(back) = exact_in(
+            trade_direction.opposite(),
+            first.destination_amount_swapped,
+            reserves_after(trade_direction, &first),
+            fee_config,
+        ) else {
+            continue;
+        };
+        assert!(
+            back.destination_amount_swapped <= first.source_amount_swapped,
+            "round trip of {amount} profits: {first:?} then {back:?}"
+        );
+    }
+}
+
+/// Properties every `SwapCurve` must have, checked through the trait alone
+/// so they apply to any registered curve.
+///
+/// Each check panics on a violation and skips quotes the curve declines,
+/// such as exact-out swaps on curves without a closed form.
+pub trait CurveConformance: SwapCurve {
+    /// An exact-in swap at `now` moves exactly the quoted amounts through
+    /// the pool, never decreases the curve's invariant once protocol fees are
+    /// set aside, and selling its output straight back never returns more
+    /// than the input.
+    fn check_swap_value(
+        &self,
+        now: u64,
+        trade_direction: TradeDirection,
+        source_amount: u128,
+        reserves: Reserves,
+        fee_config: FeeConfig,
+    ) {
+        let Some(first) =
+            self.swap_exact_in(now, trade_direction, source_amount, reserves, fee_config)
+        else {
+            return;
+        };
+        let (swap_source_amount, swap_destination_amount) =
+            reserves.source_and_destination(trade_direction);
+        assert_eq!(
+            Some(first.new_swap_source_amount),
+            swap_source_amount.checked_add(first.source_amount_swapped)
+        );
+        // protocol fees taken in the destination token leave the pool
+        assert_eq!(
+            Some(first.new_swap_destination_amount),
+            swap_destination_amount
+                .checked_sub(first.destination_amount_swapped)
+                .and_then(|amount| amount.checked_sub(first.destination_protocol_fee))
+        );
+        let settled = reserves_after(trade_direction, &first);
+        if let (Some(before), Some(after)) =
+            (self.invariant(now, reserves), self.invariant(now, settled))
+        {
+            assert!(
+                after >= before,
+                "swap of {source_amount} decreases the invariant: {first:?}"
+            );
+        }
+        let Some(back) = self.swap_exact_in(
+            now,
+            trade_direction.opposite(),
+            first.destination_amount_swapped,
+            settled,
+            fee_config,
+        ) else {
+            return;
+        };
+        assert!(
+            back.destination_amount_swapped <= first.source_amount_swapped,
+            "round trip of {source_amount} profits: {first:?} then {back:?}"
+        );
+    }
+
+    /// An exact-out quote at `now` pays out exactly the requested amount,
+    /// and paying its input exact-in buys at least as much.
+    fn check_exact_out_exact_in(
+        &self,
+        now: u64,
+        trade_direction: TradeDirection,
+        destination_amount: u128,
+        reserves: Reserves,
+        fee_config: FeeConfig,
+    ) {
+        let Some(quote) = self.swap_exact_out(
+            now,
+            trade_direction,
+            destination_amount,
+            reserves,
+            fee_config,
+        ) else {
+            return;
+        };
+        assert_eq!(quote.destination_amount_swapped, destination_amount);
+        let Some(exact_in) = self.swap_exact_in(
+            now,
+            trade_direction,
+            quote.source_amount_swapped,
+            reserves,
+            fee_config,
+        ) else {
+            return;
+        };
+        assert!(
+            exact_in.destination_amount_swapped >= destination_amount,
+            "exact-out {quote:?} buys less exact-in: {exact_in:?}"
+        );
+    }
+
+    /// Deposits charge at least, and withdrawals pay at most, the exact
+    /// share of each reserve that `lp_token_amount` is worth, and the two
+    /// differ by at most one token.
+    fn check_lp_conversion_bounds(
+        &self,
+        lp_token_amount: u128,
+        lp_token_supply: u128,
+        reserves: Reserves,
+    ) {
+        let convert = |round_direction| {
+            self.lp_tokens_to_trading_tokens(
+                lp_token_amount,
+                lp_token_supply,
+                reserves,
+                round_direction,
+            )
+        };
+        let (Some(withdraw), Some(deposit)) = (
+            convert(RoundDirection::Floor),
+            convert(RoundDirection::Ceiling),
+        ) else {
+            return;
+        };
+        for (reserve, withdrawn, deposited) in [
+            (
+                reserves.token_0.get(),
+                withdraw.token_0_amount,
+                deposit.token_0_amount,
+            ),
+            (
+                reserves.token_1.get(),
+                withdraw.token_1_amount,
+                deposit.token_1_amount,
+            ),
+        ] {
+            let share = U256::from(lp_token_amount) * U256::from(reserve);
+            let supply = U256::from(lp_token_supply);
+            assert!(U256::from(withdrawn) * supply <= share);
+            // depositing a fraction of a token is quoted as nothing, for
+            // the deposit to be rejected
+            assert!(deposited == 0 || U256::from(deposited) * supply >= share);
+            assert!(withdrawn <= deposited);
+            assert!(deposited <= withdrawn + 1);
+        }
+    }
+
+    /// More LP tokens never cost less to deposit or pay less on withdrawal,
+    /// and depositing then withdrawing the same LP tokens returns at most
+    /// what was deposited.
+    fn check_deposit_withdraw_monotonic(
+        &self,
+        lp_token_amount: u128,
+        more_lp_token_amount: u128,
+        lp_token_supply: u128,
+        reserves: Reserves,
+    ) {
+        assert!(lp_token_amount <= more_lp_token_amount);
+        for round_direction in [RoundDirection::Floor, RoundDirection::Ceiling] {
+            let convert = |lp_token_amount| {
+                self.lp_tokens_to_trading_tokens(
+                    lp_token_amount,
+                    lp_token_supply,
+                    reserves,
+                    round_direction,
+                )
+            };
+            let (Some(fewer), Some(more)) =
+                (convert(lp_token_amount), convert(more_lp_token_amount))
+            else {
+                continue;
+            };
+            assert!(fewer.token_0_amount <= more.token_0_amount);
+            assert!(fewer.token_1_amount <= more.token_1_amount);
+        }
+
+        let Some(deposit) = self.lp_tokens_to_trading_tokens(
+            lp_token_amount,
+            lp_token_supply,
+            reserves,
+            RoundDirection::Ceiling,
+        ) else {
+            return;
+        };
+        let (Some(token_0), Some(token_1), Some(lp_token_supply)) = (
+            reserves.token_0.get().checked_add(deposit.token_0_amount),
+            reserves.token_1.get().checked_add(deposit.token_1_amount),
+            lp_token_supply.checked_add(lp_token_amount),
+        ) else {
+            return;
+        };
+        let Some(withdraw) = self.lp_tokens_to_trading_tokens(
+            lp_token_amount,
+            lp_token_supply,
+            Reserves::new(Token0Amount(token_0), Token1Amount(token_1)),
+            RoundDirection::Floor,
+        ) else {
+            return;
+        };
+        assert!(withdraw.token_0_amount <= deposit.token_0_amount);
+        assert!(withdraw.token_1_amount <= deposit.token_1_amount);
+    }
+}
+
+impl<T: SwapCurve + ?Sized> CurveConformance for T {}
+
+prop_compose! {
+    pub fn total_and_intermediate(max_value: u64)(total in 1..max_value)
+                    (intermediate in 1..total, total in Just(total))
+                    -> (u64, u64) {
+       (total, intermediate)
+   }
+}
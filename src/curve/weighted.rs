@@ -0,0 +1,346 @@
+//! Weighted curve and liquidity bootstrapping schedules
+//!
+//! Balancer-style weighted pools keep `B0^w0 * B1^w1` constant, so the spot
+//! price of token 0 in token 1 is `(B1 / w1) / (B0 / w0)`. Equal weights
+//! give the constant product curve.
+//!
+//! A liquidity bootstrapping pool moves the weights linearly over time
+//! along a `WeightSchedule`. Starting with a heavy weight on the token being
+//! sold and shifting it to the other token lowers the price while no one
+//! trades, so a launch can run a declining-price sale. The interpolation is
+//! integer only, so every caller sees the same weights at the same time.
+use crate::{
+    curve::{
+        amount::Reserves,
+        calculator::{SwapResult, TradeDirection},
+        fees::{FeeConfig, Fees},
+    },
+    utils::{
+        PriceQ64, U256,
+        fixed_math::pow,
+        price::{Q64, Q64_RESOLUTION, to_u128},
+    },
+};
+
+/// Sum of the two weights of a pool
+pub const WEIGHT_DENOMINATOR: u64 = 1_000_000;
+
+/// Smallest weight of either token, 1%
+pub const MIN_WEIGHT: u64 = WEIGHT_DENOMINATOR / 100;
+
+/// Weights of a two-token pool, summing to `WEIGHT_DENOMINATOR`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Weights {
+    pub token_0: u64,
+    pub token_1: u64,
+}
+
+impl Weights {
+    /// Weights giving token 0 `token_0` and token 1 the rest.
+    ///
+    /// Returns `None` if either weight is below `MIN_WEIGHT`.
+    pub const fn new(token_0: u64) -> Option<Self> {
+        if token_0 < MIN_WEIGHT || token_0 > WEIGHT_DENOMINATOR - MIN_WEIGHT {
+            return None;
+        }
+        Some(Self {
+            token_0,
+            token_1: WEIGHT_DENOMINATOR - token_0,
+        })
+    }
+
+    /// Equal weights
+    pub const EVEN: Self = Self {
+        token_0: WEIGHT_DENOMINATOR / 2,
+        token_1: WEIGHT_DENOMINATOR / 2,
+    };
+
+    /// `(source, destination)` weights for `trade_direction`
+    pub const fn source_and_destination(self, trade_direction: TradeDirection) -> (u64, u64) {
+        match trade_direction {
+            TradeDirection::ZeroForOne => (self.token_0, self.token_1),
+            TradeDirection::OneForZero => (self.token_1, self.token_0),
+        }
+    }
+}
+
+/// Linear move between two sets of weights
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WeightSchedule {
+    pub start_weights: Weights,
+    pub end_weights: Weights,
+    pub start_time: u64,
+    pub end_time: u64,
+}
+
+impl WeightSchedule {
+    /// Returns `None` if the schedule ends before it starts.
+    pub const fn new(
+        start_weights: Weights,
+        end_weights: Weights,
+        start_time: u64,
+        end_time: u64,
+    ) -> Option<Self> {
+        if end_time < start_time {
+            return None;
+        }
+        Some(Self {
+            start_weights,
+            end_weights,
+            start_time,
+            end_time,
+        })
+    }
+
+    /// Weights at `now`: the start weights before the schedule, the end
+    /// weights after it, and in between token 0's weight interpolated
+    /// linearly, rounded towards the start weight
+    pub fn weights_at(&self, now: u64) -> Weights {
+        if now <= self.start_time {
+            return self.start_weights;
+        }
+        if now >= self.end_time {
+            return self.end_weights;
+        }
+        let (start, end) = (self.start_weights.token_0, self.end_weights.token_0);
+        // both weights are at most WEIGHT_DENOMINATOR, so none of this
+        // overflows
+        let moved = u128::from(start.abs_diff(end)) * u128::from(now - self.start_time)
+            / u128::from(self.end_time - self.start_time);
+        let token_0 = if end >= start {
+            start + moved as u64
+        } else {
+            start - moved as u64
+        };
+        Weights {
+            token_0,
+            token_1: WEIGHT_DENOMINATOR - token_0,
+        }
+    }
+}
+
+/// Weighted constant product curve
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WeightedCurve;
+
+impl WeightedCurve {
+    /// Spot price of token 0 in token 1, rounded down.
+    ///
+    /// Returns `None` if token 0's reserve or a weight is zero.
+    pub fn spot_price(reserves: Reserves, weights: Weights) -> Option<PriceQ64> {
+        let numerator = U256::from(reserves.token_1.get()) * U256::from(weights.token_0);
+        let denominator = U256::from(reserves.token_0.get()) * U256::from(weights.token_1);
+        if denominator.is_zero() {
+            return None;
+        }
+        to_u128((numerator << Q64_RESOLUTION) / denominator).map(PriceQ64::from_raw)
+    }
+
+    /// Destination tokens for `source_amount` without fees:
+    ///
+    /// ```text
+    /// destination * (1 - (source / (source + source_amount))^(w_source / w_destination))
+    /// ```
+    ///
+    /// The power is bounded above by its maximum error, so the result is
+    /// rounded down. Returns `None` if a reserve or weight is zero.
+    pub fn swap_base_input_without_fees(
+        source_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        source_weight: u64,
+        destination_weight: u64,
+    ) -> Option<u128> {
+        if swap_source_amount == 0 || source_weight == 0 || destination_weight == 0 {
+            return None;
+        }
+        // the base rounded up and the exponent down keep the power high
+        let total = U256::from(swap_source_amount.checked_add(source_amount)?);
+        let base = ((U256::from(swap_source_amount) << Q64_RESOLUTION) + total - 1) / total;
+        let exponent =
+            (u128::from(source_weight) << Q64_RESOLUTION) / u128::from(destination_weight);
+        let power = pow(base.as_u128(), i128::try_from(exponent).ok()?)?;
+        // `pow`'s relative error is below (exponent + 2) * 2^-63, plus an ulp
+        let error = ((U256::from(power)
+            * U256::from(u128::from(source_weight.div_ceil(destination_weight)) + 2))
+            >> (Q64_RESOLUTION - 1))
+            .as_u128()
+            + 1;
+        let power = power.saturating_add(error).min(Q64);
+        to_u128((U256::from(swap_destination_amount) * U256::from(Q64 - power)) >> Q64_RESOLUTION)
+    }
+
+    /// Quote a swap of exactly `source_amount` in `trade_direction` at
+    /// `weights`, charging fees on the source amount.
+    ///
+    /// Returns `None` unless `fee_config` takes every fee from the input.
+    pub fn swap_exact_in(
+        trade_direction: TradeDirection,
+        source_amount: u128,
+        reserves: Reserves,
+        weights: Weights,
+        fee_config: FeeConfig,
+    ) -> Option<SwapResult> {
+        if !fee_config.is_input_only() {
+            return None;
+        }
+        let (swap_source_amount, swap_destination_amount) =
+            reserves.source_and_destination(trade_direction);
+        let (source_weight, destination_weight) = weights.source_and_destination(trade_direction);
+        let trade_fee = Fees::trading_fee(source_amount, fee_config.trade_fee_rate.get())?;
+        let protocol_fee = Fees::protocol_fee(trade_fee, fee_config.protocol_fee_rate.get())?;
+        let destination_amount_swapped = Self::swap_base_input_without_fees(
+            source_amount.checked_sub(trade_fee)?,
+            swap_source_amount,
+            swap_destination_amount,
+            source_weight,
+            destination_weight,
+        )?;
+        Some(SwapResult {
+            new_swap_source_amount: swap_source_amount.checked_add(source_amount)?,
+            new_swap_destination_amount: swap_destination_amount
+                .checked_sub(destination_amount_swapped)?,
+            source_amount_swapped: source_amount,
+            destination_amount_swapped,
+            trade_fee,
+            protocol_fee,
+            destination_trade_fee: 0,
+            destination_protocol_fee: 0,
+            rounding_dust: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::curve::constant_product::ConstantProductCurve, proptest::prelude::*};
+
+    #[test]
+    fn weight_bounds() {
+        assert_eq!(Weights::new(MIN_WEIGHT - 1), None);
+        assert_eq!(Weights::new(WEIGHT_DENOMINATOR - MIN_WEIGHT + 1), None);
+        assert_eq!(
+            Weights::new(960_000),
+            Some(Weights {
+                token_0: 960_000,
+                token_1: 40_000
+            })
+        );
+    }
+
+    #[test]
+    fn schedule_interpolates_linearly() {
+        let start = Weights::new(960_000).unwrap();
+        let end = Weights::new(500_000).unwrap();
+        let schedule = WeightSchedule::new(start, end, 1_000, 4_000).unwrap();
+        assert_eq!(schedule.weights_at(0), start);
+        assert_eq!(schedule.weights_at(1_000), start);
+        assert_eq!(schedule.weights_at(4_000), end);
+        assert_eq!(schedule.weights_at(u64::MAX), end);
+        assert_eq!(schedule.weights_at(2_500).token_0, 730_000);
+        // rounded towards the start weight
+        assert_eq!(schedule.weights_at(1_001).token_0, 959_847);
+        assert_eq!(WeightSchedule::new(start, end, 2, 1), None);
+        // an instant schedule jumps to the end weights
+        let instant = WeightSchedule::new(start, end, 7, 7).unwrap();
+        assert_eq!(instant.weights_at(6), start);
+        assert_eq!(instant.weights_at(7), start);
+        assert_eq!(instant.weights_at(8), end);
+    }
+
+    #[test]
+    fn bootstrapping_price_declines_without_trades() {
+        // selling token 0 for token 1, starting at 96/4
+        let schedule = WeightSchedule::new(
+            Weights::new(960_000).unwrap(),
+            Weights::new(500_000).unwrap(),
+            0,
+            3 * 86_400,
+        )
+        .unwrap();
+        let pool = Reserves::from_raw(10_000_000, 1_000_000);
+        let prices: Vec<PriceQ64> = (0..=3)
+            .map(|day| WeightedCurve::spot_price(pool, schedule.weights_at(day * 86_400)).unwrap())
+            .collect();
+        assert!(prices.windows(2).all(|pair| pair[0] > pair[1]));
+        // 1_000_000 * 96 / (10_000_000 * 4) = 2.4
+        assert_eq!(prices[0].raw(), Q64 * 12 / 5);
+        assert_eq!(prices[3], PriceQ64::from_ratio(1, 10).unwrap());
+    }
+
+    #[test]
+    fn even_weights_match_constant_product() {
+        for amount in [1, 1_000, 1_000_000, 1_000_000_000] {
+            let weighted = WeightedCurve::swap_base_input_without_fees(
+                amount,
+                1_000_000_000,
+                3_000_000_000,
+                Weights::EVEN.token_0,
+                Weights::EVEN.token_1,
+            )
+            .unwrap();
+            let constant_product = ConstantProductCurve::try_swap_base_input_without_fees(
+                amount,
+                1_000_000_000,
+                3_000_000_000,
+            )
+            .unwrap();
+            assert!(weighted <= constant_product);
+            // the error margin costs a few units per 2^63 of the reserve
+            assert!(constant_product - weighted <= 2, "{amount}");
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn weighted_swaps_never_lower_the_invariant(
+            token_0 in 1_000..u64::MAX as u128,
+            token_1 in 1_000..u64::MAX as u128,
+            weight in MIN_WEIGHT..=WEIGHT_DENOMINATOR - MIN_WEIGHT,
+            source_amount in 1..u64::MAX as u128,
+            zero_for_one in any::<bool>(),
+        ) {
+            let trade_direction = if zero_for_one {
+                TradeDirection::ZeroForOne
+            } else {
+                TradeDirection::OneForZero
+            };
+            let weights = Weights::new(weight).unwrap();
+            let pool = Reserves::from_raw(token_0, token_1);
+            let result = WeightedCurve::swap_exact_in(
+                trade_direction,
+                source_amount,
+                pool,
+                weights,
+                FeeConfig::default(),
+            )
+            .unwrap();
+            let (source, destination) = pool.source_and_destination(trade_direction);
+            let (source_weight, destination_weight) = weights.source_and_destination(trade_direction);
+            prop_assert!(result.destination_amount_swapped < destination);
+            // compare w_s * ln(source) + w_d * ln(destination) before and after
+            let value = |source: u128, destination: u128| {
+                source_weight as f64 * (source as f64).ln()
+                    + destination_weight as f64 * (destination as f64).ln()
+            };
+            let before = value(source, destination);
+            let after = value(result.new_swap_source_amount, result.new_swap_destination_amount);
+            prop_assert!(after >= before - before.abs() * 1e-14);
+            // a round trip never profits
+            let back = WeightedCurve::swap_exact_in(
+                trade_direction.opposite(),
+                result.destination_amount_swapped,
+                Reserves::from_source_and_destination(
+                    trade_direction,
+                    result.new_swap_source_amount,
+                    result.new_swap_destination_amount,
+                ),
+                weights,
+                FeeConfig::default(),
+            );
+            if let Some(back) = back {
+                prop_assert!(back.destination_amount_swapped <= source_amount);
+            }
+        }
+    }
+}
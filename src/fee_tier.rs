@@ -0,0 +1,297 @@
+//! Canonical fee tiers
+//!
+//! A trading pair can have one pool per fee tier, so liquidity providers
+//! choose how much to charge and routers compare the pools. Each tier fixes
+//! the trade fee and, for concentrated liquidity pools, the tick spacing:
+//! wider spacing for higher fees, since pairs that pay more are more
+//! volatile and need fewer, coarser ticks.
+//!
+//! Every `Preset` fee configuration charges one of the tiers' trade fees.
+//!
+//! Pool addresses are program derived from the pair's mints, in canonical
+//! order, and the tier, so each pool of a pair has a distinct address. The
+//! helpers deriving them need the `anchor` feature.
+use {
+    crate::{
+        clmm::ClmmPool,
+        curve::fees::{FeeConfig, FeeRate, Preset},
+        utils::PriceQ64,
+    },
+    borsh::{BorshDeserialize, BorshSerialize},
+    core::cmp::Ordering,
+};
+#[cfg(feature = "anchor")]
+use anchor_lang::prelude::Pubkey;
+
+/// Seed prefix of pool addresses
+pub const POOL_SEED: &[u8] = b"pool";
+
+/// Trade fee and tick spacing of a pool, ordered by fee. The discriminant
+/// is the tier's seed, so tiers added later keep existing pool addresses.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, BorshSerialize, BorshDeserialize)]
+#[borsh(use_discriminant = true)]
+pub enum FeeTier {
+    /// 0.01%, for pegged pairs
+    Bps1 = 0,
+    /// 0.05%, for correlated pairs
+    Bps5 = 1,
+    /// 0.25%, `Preset::Standard`
+    Bps25 = 4,
+    /// 0.3%, for most pairs
+    Bps30 = 2,
+    /// 1%, for exotic pairs
+    Bps100 = 3,
+}
+
+impl Ord for FeeTier {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.trade_fee_bps().cmp(&other.trade_fee_bps())
+    }
+}
+
+impl PartialOrd for FeeTier {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// every tier is a valid pool configuration
+const _: () = {
+    let mut index = 0;
+    while index < FeeTier::ALL.len() {
+        assert!(FeeTier::ALL[index].fee_config(FeeRate::MAX).is_valid());
+        index += 1;
+    }
+};
+
+impl FeeTier {
+    /// Every tier, lowest fee first
+    pub const ALL: [Self; 5] = [
+        Self::Bps1,
+        Self::Bps5,
+        Self::Bps25,
+        Self::Bps30,
+        Self::Bps100,
+    ];
+
+    /// Trade fee in basis points
+    pub const fn trade_fee_bps(self) -> u16 {
+        match self {
+            Self::Bps1 => 1,
+            Self::Bps5 => 5,
+            Self::Bps25 => 25,
+            Self::Bps30 => 30,
+            Self::Bps100 => 100,
+        }
+    }
+
+    /// Trade fee as a rate
+    pub const fn trade_fee_rate(self) -> FeeRate {
+        FeeRate::from_bps(self.trade_fee_bps()).unwrap()
+    }
+
+    /// Spacing of the ticks concentrated liquidity positions can be bounded
+    /// by
+    pub const fn tick_spacing(self) -> u16 {
+        match self {
+            Self::Bps1 => 1,
+            Self::Bps5 => 10,
+            Self::Bps25 => 50,
+            Self::Bps30 => 60,
+            Self::Bps100 => 200,
+        }
+    }
+
+    /// The tier charging `preset`'s trade fee
+    pub const fn from_preset(preset: Preset) -> Self {
+        match preset {
+            Preset::Stable => Self::Bps1,
+            Preset::Standard => Self::Bps25,
+            Preset::Volatile => Self::Bps100,
+        }
+    }
+
+    /// The tier charging `trade_fee_rate`, if any
+    pub fn from_trade_fee_rate(trade_fee_rate: FeeRate) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|tier| tier.trade_fee_rate() == trade_fee_rate)
+    }
+
+    /// The tier's trade fee, with `protocol_fee_rate` of it going to the
+    /// protocol
+    pub const fn fee_config(self, protocol_fee_rate: FeeRate) -> FeeConfig {
+        FeeConfig::new(self.trade_fee_rate(), protocol_fee_rate)
+    }
+
+    /// An empty concentrated liquidity pool in this tier at square root
+    /// price `sqrt_price`.
+    ///
+    /// Returns `None` if the price is out of range.
+    pub fn clmm_pool(self, sqrt_price: PriceQ64, protocol_fee_rate: FeeRate) -> Option<ClmmPool> {
+        ClmmPool::new(
+            sqrt_price,
+            self.tick_spacing(),
+            self.fee_config(protocol_fee_rate),
+        )
+    }
+
+    /// The tier whose seed is `[seed]`, if any
+    pub fn from_seed(seed: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|tier| tier.seed() == [seed])
+    }
+
+    /// The tier's seed in pool addresses
+    pub const fn seed(self) -> &'static [u8] {
+        match self {
+            Self::Bps1 => &[0],
+            Self::Bps5 => &[1],
+            Self::Bps25 => &[4],
+            Self::Bps30 => &[2],
+            Self::Bps100 => &[3],
+        }
+    }
+
+    /// Seeds of the address of the pool trading `token_0_mint` against
+    /// `token_1_mint` in this tier.
+    ///
+    /// Returns `None` unless the mints are in canonical order, `token_0_mint`
+    /// first, so each pool has exactly one address.
+    #[cfg(feature = "anchor")]
+    pub fn pool_seeds<'a>(
+        self,
+        token_0_mint: &'a Pubkey,
+        token_1_mint: &'a Pubkey,
+    ) -> Option<[&'a [u8]; 4]> {
+        (token_0_mint < token_1_mint).then_some([
+            POOL_SEED,
+            token_0_mint.as_ref(),
+            token_1_mint.as_ref(),
+            self.seed(),
+        ])
+    }
+
+    /// Address and bump of the pool trading `token_0_mint` against
+    /// `token_1_mint` in this tier, derived from `program_id`.
+    ///
+    /// Returns `None` unless the mints are in canonical order.
+    #[cfg(feature = "anchor")]
+    pub fn find_pool_address(
+        self,
+        program_id: &Pubkey,
+        token_0_mint: &Pubkey,
+        token_1_mint: &Pubkey,
+    ) -> Option<(Pubkey, u8)> {
+        let seeds = self.pool_seeds(token_0_mint, token_1_mint)?;
+        Some(Pubkey::find_program_address(&seeds, program_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            curve::{calculator::TradeDirection, fees::DEFAULT_PROTOCOL_FEE_RATE},
+            router::best_quote,
+            state::PoolState,
+        },
+    };
+
+    #[test]
+    fn tiers_are_ordered_by_fee() {
+        assert!(FeeTier::ALL.windows(2).all(|pair| {
+            pair[0].trade_fee_bps() < pair[1].trade_fee_bps()
+                && pair[0].tick_spacing() < pair[1].tick_spacing()
+                && pair[0] < pair[1]
+        }));
+        for tier in FeeTier::ALL {
+            assert_eq!(
+                FeeTier::from_trade_fee_rate(tier.trade_fee_rate()),
+                Some(tier)
+            );
+            assert_eq!(tier.seed(), &[tier as u8]);
+            assert_eq!(FeeTier::from_seed(tier as u8), Some(tier));
+            let pool = tier.clmm_pool(PriceQ64::ONE, FeeRate::ZERO).unwrap();
+            assert_eq!(pool.tick_bitmap.tick_spacing(), tier.tick_spacing());
+        }
+        assert_eq!(
+            FeeTier::from_trade_fee_rate(FeeRate::from_bps(20).unwrap()),
+            None
+        );
+        assert_eq!(FeeTier::from_seed(FeeTier::ALL.len() as u8), None);
+        assert_eq!(FeeTier::Bps30.trade_fee_rate().get(), 3_000);
+    }
+
+    #[test]
+    fn every_preset_is_a_tier() {
+        for preset in Preset::ALL {
+            let fee_config = FeeConfig::preset(preset);
+            let tier = FeeTier::from_preset(preset);
+            assert_eq!(
+                FeeTier::from_trade_fee_rate(fee_config.trade_fee_rate),
+                Some(tier)
+            );
+            assert_eq!(tier.fee_config(DEFAULT_PROTOCOL_FEE_RATE), fee_config);
+        }
+        assert_eq!(
+            FeeTier::from_preset(Preset::Standard).trade_fee_rate(),
+            FeeConfig::STANDARD_25BPS.trade_fee_rate
+        );
+    }
+
+    #[cfg(feature = "anchor")]
+    #[test]
+    fn each_tier_has_its_own_pool_address() {
+        let program_id = Pubkey::new_from_array([7; 32]);
+        let (mint_0, mint_1) = (
+            Pubkey::new_from_array([1; 32]),
+            Pubkey::new_from_array([2; 32]),
+        );
+        let addresses: Vec<_> = FeeTier::ALL
+            .into_iter()
+            .map(|tier| {
+                tier.find_pool_address(&program_id, &mint_0, &mint_1)
+                    .unwrap()
+            })
+            .collect();
+        for (index, (address, bump)) in addresses.iter().enumerate() {
+            let tier = FeeTier::ALL[index];
+            let seeds = tier.pool_seeds(&mint_0, &mint_1).unwrap();
+            let bump = [*bump];
+            let signer_seeds = [seeds[0], seeds[1], seeds[2], seeds[3], &bump];
+            assert_eq!(
+                Pubkey::create_program_address(&signer_seeds, &program_id).unwrap(),
+                *address
+            );
+            assert!(addresses[..index].iter().all(|(other, _)| other != address));
+        }
+        // the mints must be in canonical order
+        assert_eq!(
+            FeeTier::Bps5.find_pool_address(&program_id, &mint_1, &mint_0),
+            None
+        );
+        assert_eq!(FeeTier::Bps5.pool_seeds(&mint_0, &mint_0), None);
+    }
+
+    #[test]
+    fn routers_compare_tiers_of_a_pair() {
+        // the cheap tier is shallow, so large orders do better in a deeper
+        // pool charging more
+        let pool = |tier: FeeTier, reserve: u64| PoolState {
+            fee_config: tier.fee_config(FeeRate::ZERO),
+            token_0_reserve: reserve,
+            token_1_reserve: reserve,
+            ..PoolState::default()
+        };
+        let pools = [
+            pool(FeeTier::Bps1, 100_000),
+            pool(FeeTier::Bps30, 10_000_000),
+        ];
+        let small = best_quote(0, TradeDirection::ZeroForOne, &pools, 10, 50);
+        assert_eq!(small[0].pool_index, 0);
+        let large = best_quote(0, TradeDirection::ZeroForOne, &pools, 50_000, 50);
+        assert_eq!(large[0].pool_index, 1);
+    }
+}
@@ -0,0 +1,118 @@
+//! Instruction data
+//!
+//! Borsh encoded payloads for the instructions of a pool program. The enum
+//! tag is the first byte, followed by the variant's fields in order.
+use {
+    crate::{curve::fees::FeeConfig, state::CurveType},
+    borsh::{BorshDeserialize, BorshSerialize},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub enum CurveInstruction {
+    /// Create a pool with its first deposit
+    Initialize {
+        curve_type: CurveType,
+        fee_config: FeeConfig,
+        initial_token_0_amount: u64,
+        initial_token_1_amount: u64,
+    },
+    /// Swap an exact amount of source token
+    SwapBaseInput {
+        amount_in: u64,
+        minimum_amount_out: u64,
+    },
+    /// Swap for an exact amount of destination token
+    SwapBaseOutput { max_amount_in: u64, amount_out: u64 },
+    /// Deposit both tokens in exchange for an exact amount of LP tokens
+    Deposit {
+        lp_token_amount: u64,
+        maximum_token_0_amount: u64,
+        maximum_token_1_amount: u64,
+    },
+    /// Burn an exact amount of LP tokens in exchange for both tokens
+    Withdraw {
+        lp_token_amount: u64,
+        minimum_token_0_amount: u64,
+        minimum_token_1_amount: u64,
+    },
+    /// Transfer vault balances above the reserves and protocol fees to the
+    /// recipient account, which is the protocol fee vault unless another is
+    /// designated. Anyone may call it.
+    Skim,
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::curve::fees::FeeRate};
+
+    fn encode(fields: &[u64]) -> Vec<u8> {
+        fields
+            .iter()
+            .flat_map(|field| field.to_le_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn instruction_layout() {
+        let cases = [
+            (
+                CurveInstruction::Initialize {
+                    curve_type: CurveType::ConstantProduct,
+                    fee_config: FeeConfig::new(
+                        FeeRate::from_bps(25).unwrap(),
+                        FeeRate::from_percent(12).unwrap(),
+                    ),
+                    initial_token_0_amount: 10,
+                    initial_token_1_amount: 20,
+                },
+                [
+                    vec![0u8, 0u8],
+                    encode(&[2_500, 120_000]),
+                    vec![0u8, 0u8],
+                    encode(&[10, 20]),
+                ]
+                .concat(),
+            ),
+            (
+                CurveInstruction::SwapBaseInput {
+                    amount_in: 5,
+                    minimum_amount_out: 4,
+                },
+                [vec![1u8], encode(&[5, 4])].concat(),
+            ),
+            (
+                CurveInstruction::SwapBaseOutput {
+                    max_amount_in: 6,
+                    amount_out: 3,
+                },
+                [vec![2u8], encode(&[6, 3])].concat(),
+            ),
+            (
+                CurveInstruction::Deposit {
+                    lp_token_amount: 7,
+                    maximum_token_0_amount: 8,
+                    maximum_token_1_amount: 9,
+                },
+                [vec![3u8], encode(&[7, 8, 9])].concat(),
+            ),
+            (
+                CurveInstruction::Withdraw {
+                    lp_token_amount: 7,
+                    minimum_token_0_amount: 1,
+                    minimum_token_1_amount: u64::MAX,
+                },
+                [vec![4u8], encode(&[7, 1, u64::MAX])].concat(),
+            ),
+            (CurveInstruction::Skim, vec![5u8]),
+        ];
+        for (instruction, expected) in cases {
+            let bytes = borsh::to_vec(&instruction).unwrap();
+            assert_eq!(bytes, expected);
+            assert_eq!(
+                CurveInstruction::try_from_slice(&bytes).unwrap(),
+                instruction
+            );
+        }
+        assert!(CurveInstruction::try_from_slice(&[6u8]).is_err());
+    }
+}
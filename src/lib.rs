@@ -1,2 +1,34 @@
+//! Constant product curve and fee math
+//!
+//! The crate is `no_std` unless the default `std` feature is enabled, so the
+//! curve and fee calculations can be embedded in on-chain programs without
+//! pulling in std's formatting machinery.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+pub mod analytics;
+pub mod audit;
+pub mod clmm;
 pub mod curve;
-pub mod utils;
\ No newline at end of file
+pub mod fee_tier;
+pub mod instruction;
+mod logging;
+pub mod oracle;
+pub mod postconditions;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod quote;
+pub mod router;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod sim;
+pub mod state;
+pub mod stats;
+pub mod twamm;
+pub mod ui;
+pub mod utils;
+#[cfg(feature = "verification")]
+pub mod verification;
+#[cfg(feature = "wasm")]
+pub mod wasm;
\ No newline at end of file
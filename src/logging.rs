@@ -0,0 +1,92 @@
+//! Optional structured logging
+//!
+//! The calculator emits a `swap` event for every computed swap and a `fee`
+//! event with its fee breakdown. With the `tracing` feature these are
+//! `tracing` events with one field per value; with only the `log` feature
+//! they are `log` records with `key=value` messages. Without either feature
+//! the functions compile to nothing, so on-chain builds pay no cost.
+use crate::curve::calculator::SwapResult;
+
+/// Which swap entry point produced an event
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum SwapKind {
+    BaseInput,
+    BaseOutput,
+}
+
+impl SwapKind {
+    #[cfg(any(feature = "log", feature = "tracing"))]
+    const fn as_str(self) -> &'static str {
+        match self {
+            SwapKind::BaseInput => "base_input",
+            SwapKind::BaseOutput => "base_output",
+        }
+    }
+}
+
+#[cfg_attr(
+    not(any(feature = "log", feature = "tracing")),
+    allow(unused_variables)
+)]
+pub(crate) fn swap_event(
+    kind: SwapKind,
+    swap_source_amount: u128,
+    swap_destination_amount: u128,
+    result: &SwapResult,
+) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(
+        target: "curve::swap",
+        kind = kind.as_str(),
+        swap_source_amount,
+        swap_destination_amount,
+        source_amount_swapped = result.source_amount_swapped,
+        destination_amount_swapped = result.destination_amount_swapped,
+        rounding_dust = result.rounding_dust,
+        "swap"
+    );
+    #[cfg(all(feature = "log", not(feature = "tracing")))]
+    log::debug!(
+        target: "curve::swap",
+        "swap kind={} swap_source_amount={} swap_destination_amount={} \
+         source_amount_swapped={} destination_amount_swapped={} rounding_dust={}",
+        kind.as_str(),
+        swap_source_amount,
+        swap_destination_amount,
+        result.source_amount_swapped,
+        result.destination_amount_swapped,
+        result.rounding_dust,
+    );
+}
+
+#[cfg_attr(
+    not(any(feature = "log", feature = "tracing")),
+    allow(unused_variables)
+)]
+pub(crate) fn fee_event(
+    kind: SwapKind,
+    trade_fee_rate: u64,
+    protocol_fee_rate: u64,
+    result: &SwapResult,
+) {
+    #[cfg(feature = "tracing")]
+    tracing::trace!(
+        target: "curve::fee",
+        kind = kind.as_str(),
+        trade_fee_rate,
+        protocol_fee_rate,
+        trade_fee = result.trade_fee,
+        protocol_fee = result.protocol_fee,
+        "fee"
+    );
+    #[cfg(all(feature = "log", not(feature = "tracing")))]
+    log::trace!(
+        target: "curve::fee",
+        "fee kind={} trade_fee_rate={} protocol_fee_rate={} trade_fee={} protocol_fee={}",
+        kind.as_str(),
+        trade_fee_rate,
+        protocol_fee_rate,
+        result.trade_fee,
+        result.protocol_fee,
+    );
+}
@@ -0,0 +1,411 @@
+//! Time-weighted average price oracle
+//!
+//! The pool accumulates `price * seconds` for both token prices, Uniswap v2
+//! style, and keeps a ring buffer of recent accumulator readings. A TWAP over
+//! any window is the difference of two cumulative readings divided by the
+//! elapsed time.
+//!
+//! The accumulators wrap on overflow: only the difference between two
+//! readings is meaningful, and it stays correct across a wrap as long as the
+//! window is short enough for the true difference to fit in u128.
+use {
+    crate::{curve::amount::Reserves, utils::PriceQ64},
+    alloc::vec::Vec,
+    borsh::{BorshDeserialize, BorshSerialize},
+};
+
+/// Cumulative prices at a point in time
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct Observation {
+    /// Unix timestamp of the reading, in seconds
+    pub timestamp: u64,
+    /// Sum of the Q64.64 price of token 0 in token 1 over each second
+    pub price_0_cumulative: u128,
+    /// Sum of the Q64.64 price of token 1 in token 0 over each second
+    pub price_1_cumulative: u128,
+}
+
+impl Observation {
+    /// The reading at `timestamp`, assuming `reserves` held since this one.
+    ///
+    /// A price that cannot be represented, eg. with an empty reserve, does
+    /// not accumulate. Returns `None` if `timestamp` is earlier than this
+    /// reading.
+    pub fn advance(&self, timestamp: u64, reserves: Reserves) -> Option<Self> {
+        let elapsed = timestamp.checked_sub(self.timestamp)?;
+        let (reserve_0, reserve_1) = (reserves.token_0.get(), reserves.token_1.get());
+        let accumulate = |cumulative: u128, price: Option<PriceQ64>| {
+            let price = price.map_or(0, PriceQ64::raw);
+            cumulative.wrapping_add(price.wrapping_mul(elapsed.into()))
+        };
+        Some(Self {
+            timestamp,
+            price_0_cumulative: accumulate(
+                self.price_0_cumulative,
+                PriceQ64::from_reserves(reserve_0, reserve_1),
+            ),
+            price_1_cumulative: accumulate(
+                self.price_1_cumulative,
+                PriceQ64::from_reserves(reserve_1, reserve_0),
+            ),
+        })
+    }
+
+    // Reading at `timestamp`, linearly between `self` and the later `next`,
+    // using the average price over the interval like Uniswap v3
+    fn interpolate(&self, next: &Self, timestamp: u64) -> Self {
+        let interval = u128::from(next.timestamp - self.timestamp);
+        let elapsed = u128::from(timestamp - self.timestamp);
+        let between = |start: u128, end: u128| {
+            let average = end.wrapping_sub(start) / interval;
+            start.wrapping_add(average * elapsed)
+        };
+        Self {
+            timestamp,
+            price_0_cumulative: between(self.price_0_cumulative, next.price_0_cumulative),
+            price_1_cumulative: between(self.price_1_cumulative, next.price_1_cumulative),
+        }
+    }
+}
+
+/// Time-weighted average prices of token 0 in token 1 and of token 1 in
+/// token 0 between two readings, rounded down.
+///
+/// Returns `None` if `end` is not later than `start`.
+pub fn time_weighted_prices(
+    start: &Observation,
+    end: &Observation,
+) -> Option<(PriceQ64, PriceQ64)> {
+    let elapsed = end.timestamp.checked_sub(start.timestamp)?;
+    if elapsed == 0 {
+        return None;
+    }
+    let average =
+        |start: u128, end: u128| PriceQ64::from_raw(end.wrapping_sub(start) / u128::from(elapsed));
+    Some((
+        average(start.price_0_cumulative, end.price_0_cumulative),
+        average(start.price_1_cumulative, end.price_1_cumulative),
+    ))
+}
+
+/// Ring buffer of the most recent observations of a pool
+#[derive(Clone, Debug, PartialEq, Eq, BorshSerialize)]
+pub struct Oracle {
+    /// Maximum number of observations kept
+    pub capacity: u16,
+    /// Position of the latest observation in `observations`
+    pub index: u16,
+    /// Stored observations, at most `capacity`; once full, the oldest is
+    /// overwritten
+    pub observations: Vec<Observation>,
+}
+
+// Decoded only in a shape `Oracle::new` and `Oracle::write` can leave it
+// in, so account data cannot make them index out of bounds
+impl BorshDeserialize for Oracle {
+    fn deserialize_reader<R: borsh::io::Read>(reader: &mut R) -> borsh::io::Result<Self> {
+        let oracle = Self {
+            capacity: u16::deserialize_reader(reader)?,
+            index: u16::deserialize_reader(reader)?,
+            observations: Vec::deserialize_reader(reader)?,
+        };
+        if !oracle.is_valid() {
+            return Err(borsh::io::Error::new(
+                borsh::io::ErrorKind::InvalidData,
+                "invalid oracle ring buffer",
+            ));
+        }
+        Ok(oracle)
+    }
+}
+
+impl Oracle {
+    /// Start an oracle keeping `capacity` observations, with zero
+    /// accumulators at `timestamp`.
+    ///
+    /// Returns `None` if `capacity` is zero.
+    pub fn new(capacity: u16, timestamp: u64) -> Option<Self> {
+        if capacity == 0 {
+            return None;
+        }
+        let mut observations = Vec::with_capacity(capacity.into());
+        observations.push(Observation {
+            timestamp,
+            ..Observation::default()
+        });
+        Some(Self {
+            capacity,
+            index: 0,
+            observations,
+        })
+    }
+
+    // Whether the buffer holds between one and `capacity` observations
+    // with `index` at one of them, the last until the buffer is full
+    fn is_valid(&self) -> bool {
+        let len = self.observations.len();
+        let index = usize::from(self.index);
+        let capacity = usize::from(self.capacity);
+        index < len && len <= capacity && (len == capacity || index == len - 1)
+    }
+
+    /// The most recent observation
+    pub fn latest(&self) -> &Observation {
+        &self.observations[usize::from(self.index)]
+    }
+
+    /// The oldest observation still stored
+    pub fn oldest(&self) -> &Observation {
+        self.chronological(0)
+    }
+
+    // The `position`-th stored observation, oldest first. While the buffer
+    // fills, `index` is the last element, so this also holds before it wraps
+    fn chronological(&self, position: usize) -> &Observation {
+        let len = self.observations.len();
+        &self.observations[(usize::from(self.index) + 1 + position) % len]
+    }
+
+    /// Record an observation at `timestamp`, given the `reserves` that held
+    /// since the latest one, ie. before the update being made now.
+    ///
+    /// Like Uniswap, at most one observation is kept per timestamp; later
+    /// writes in the same second are ignored. Returns `None` if `timestamp`
+    /// is earlier than the latest observation.
+    pub fn write(&mut self, timestamp: u64, reserves: Reserves) -> Option<()> {
+        let latest = self.latest();
+        if timestamp == latest.timestamp {
+            return Some(());
+        }
+        let observation = latest.advance(timestamp, reserves)?;
+        if self.observations.len() < usize::from(self.capacity) {
+            self.observations.push(observation);
+            self.index = self.index.checked_add(1)?;
+        } else {
+            self.index = (self.index + 1) % self.capacity;
+            self.observations[usize::from(self.index)] = observation;
+        }
+        Some(())
+    }
+
+    /// Cumulative prices `seconds_ago` before `now`, for each entry, with
+    /// Uniswap v3 semantics: readings between two observations are
+    /// interpolated, and readings after the latest extrapolate it with the
+    /// current `reserves`.
+    ///
+    /// Returns `None` if a target is older than the oldest observation or
+    /// `now` is earlier than the latest one.
+    pub fn observe(
+        &self,
+        now: u64,
+        seconds_ago: &[u64],
+        reserves: Reserves,
+    ) -> Option<Vec<Observation>> {
+        seconds_ago
+            .iter()
+            .map(|&seconds_ago| self.observe_single(now, now.checked_sub(seconds_ago)?, reserves))
+            .collect()
+    }
+
+    fn observe_single(&self, now: u64, target: u64, reserves: Reserves) -> Option<Observation> {
+        let latest = self.latest();
+        if now < latest.timestamp {
+            return None;
+        }
+        if target >= latest.timestamp {
+            return latest.advance(target, reserves);
+        }
+        if target < self.oldest().timestamp {
+            return None;
+        }
+        // first observation after the target; the oldest is at or before it
+        // and the latest after it, so 0 < after < len
+        let len = self.observations.len();
+        let after = partition_point(len, |position| {
+            self.chronological(position).timestamp <= target
+        });
+        let before = self.chronological(after - 1);
+        if before.timestamp == target {
+            return Some(*before);
+        }
+        Some(before.interpolate(self.chronological(after), target))
+    }
+}
+
+// Binary search for the first position in `0..len` where `pred` is false,
+// given that it is true before and false after that point
+fn partition_point(len: usize, pred: impl Fn(usize) -> bool) -> usize {
+    let (mut low, mut high) = (0, len);
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if pred(middle) {
+            low = middle + 1;
+        } else {
+            high = middle;
+        }
+    }
+    low
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, proptest::prelude::*};
+
+    #[test]
+    fn twap_over_price_changes() {
+        let mut oracle = Oracle::new(8, 1_000).unwrap();
+        // price 2 for 10 seconds, then 4 for 30 seconds
+        oracle.write(1_010, Reserves::from_raw(100, 200)).unwrap();
+        oracle.write(1_040, Reserves::from_raw(100, 400)).unwrap();
+        let start = oracle.oldest();
+        let (price_0, price_1) = time_weighted_prices(start, oracle.latest()).unwrap();
+        assert_eq!(price_0, PriceQ64::from_ratio(2 * 10 + 4 * 30, 40).unwrap());
+        assert_eq!(
+            price_1,
+            PriceQ64::from_raw(
+                (PriceQ64::from_ratio(1, 2).unwrap().raw() * 10
+                    + PriceQ64::from_ratio(1, 4).unwrap().raw() * 30)
+                    / 40
+            )
+        );
+        assert_eq!(time_weighted_prices(start, start), None);
+        // same-second writes are ignored, earlier ones rejected
+        oracle.write(1_040, Reserves::from_raw(1, 1)).unwrap();
+        assert_eq!(oracle.observations.len(), 3);
+        assert_eq!(oracle.write(1_039, Reserves::from_raw(1, 1)), None);
+    }
+
+    #[test]
+    fn observe_interpolates_and_extrapolates() {
+        let mut oracle = Oracle::new(8, 0).unwrap();
+        oracle.write(10, Reserves::from_raw(100, 200)).unwrap();
+        oracle.write(40, Reserves::from_raw(100, 400)).unwrap();
+        let current = Reserves::from_raw(100, 800);
+        let observed = oracle.observe(50, &[50, 45, 20, 10, 0], current).unwrap();
+        let two = PriceQ64::from_integer(2).raw();
+        let four = PriceQ64::from_integer(4).raw();
+        let eight = PriceQ64::from_integer(8).raw();
+        let cumulative: Vec<_> = observed.iter().map(|o| o.price_0_cumulative).collect();
+        assert_eq!(
+            cumulative,
+            [
+                0,
+                5 * two,
+                10 * two + 20 * four,
+                10 * two + 30 * four,
+                10 * two + 30 * four + 10 * eight
+            ]
+        );
+        assert_eq!(observed[1].timestamp, 5);
+        // before the oldest observation, or a clock running backwards
+        assert_eq!(oracle.observe(50, &[51], current), None);
+        assert_eq!(oracle.observe(39, &[0], current), None);
+    }
+
+    #[test]
+    fn ring_buffer_overwrites_oldest() {
+        let mut oracle = Oracle::new(3, 0).unwrap();
+        for timestamp in 1..=5 {
+            oracle
+                .write(timestamp * 10, Reserves::from_raw(1, 1))
+                .unwrap();
+        }
+        assert_eq!(oracle.observations.len(), 3);
+        assert_eq!(oracle.oldest().timestamp, 30);
+        assert_eq!(oracle.latest().timestamp, 50);
+        let current = Reserves::from_raw(1, 1);
+        assert_eq!(oracle.observe(50, &[21], current), None);
+        let observed = oracle.observe(50, &[15], current).unwrap();
+        assert_eq!(observed[0].price_0_cumulative, 35 * PriceQ64::ONE.raw());
+        assert_eq!(Oracle::new(0, 0), None);
+    }
+
+    #[test]
+    fn malformed_buffers_are_rejected() {
+        let mut oracle = Oracle::new(3, 0).unwrap();
+        oracle.write(10, Reserves::from_raw(1, 1)).unwrap();
+        let bytes = borsh::to_vec(&oracle).unwrap();
+        assert_eq!(Oracle::try_from_slice(&bytes).unwrap(), oracle);
+        let decode = |oracle: Oracle| Oracle::try_from_slice(&borsh::to_vec(&oracle).unwrap());
+        for malformed in [
+            // no capacity
+            Oracle {
+                capacity: 0,
+                ..oracle.clone()
+            },
+            // more observations than the capacity
+            Oracle {
+                capacity: 1,
+                ..oracle.clone()
+            },
+            // no observations
+            Oracle {
+                index: 0,
+                observations: Vec::new(),
+                ..oracle.clone()
+            },
+            // the latest observation is missing
+            Oracle {
+                index: 2,
+                ..oracle.clone()
+            },
+            // a filling buffer not ending at the latest observation
+            Oracle {
+                index: 0,
+                ..oracle.clone()
+            },
+        ] {
+            assert!(decode(malformed).is_err());
+        }
+        // once full, the latest observation can be anywhere
+        let mut full = Oracle::new(2, 0).unwrap();
+        full.write(10, Reserves::from_raw(1, 1)).unwrap();
+        for index in 0..2 {
+            assert!(
+                decode(Oracle {
+                    index,
+                    ..full.clone()
+                })
+                .is_ok()
+            );
+        }
+    }
+
+    #[test]
+    fn accumulators_wrap() {
+        let start = Observation {
+            timestamp: 0,
+            price_0_cumulative: u128::MAX,
+            price_1_cumulative: u128::MAX - 5,
+        };
+        let end = start.advance(10, Reserves::from_raw(1, 3)).unwrap();
+        assert!(end.price_0_cumulative < start.price_0_cumulative);
+        let (price_0, _) = time_weighted_prices(&start, &end).unwrap();
+        assert_eq!(price_0, PriceQ64::from_integer(3));
+    }
+
+    proptest! {
+        #[test]
+        fn observe_matches_stored_observations(
+            prices in prop::collection::vec((1u128..1_000_000, 1u128..1_000_000, 1u64..1_000), 1..20),
+        ) {
+            let mut oracle = Oracle::new(32, 0).unwrap();
+            let mut now = 0;
+            for &(reserve_0, reserve_1, elapsed) in &prices {
+                now += elapsed;
+                oracle.write(now, Reserves::from_raw(reserve_0, reserve_1)).unwrap();
+            }
+            let current = Reserves::from_raw(1, 1);
+            for observation in &oracle.observations {
+                let observed = oracle.observe(now, &[now - observation.timestamp], current).unwrap();
+                prop_assert_eq!(observed[0], *observation);
+            }
+            // interpolated readings are monotone in time, as prices are positive
+            let seconds_ago: Vec<_> = (0..=now).rev().collect();
+            let observed = oracle.observe(now, &seconds_ago, current).unwrap();
+            for pair in observed.windows(2) {
+                prop_assert!(pair[0].price_0_cumulative <= pair[1].price_0_cumulative);
+            }
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! Post-condition checks for processors
+//!
+//! Assertions an operation runs after updating pool state, as defense in
+//! depth behind the calculator's own checks. They are `debug_assert!`s, so
+//! debug builds and tests panic on a violation while release builds compile
+//! them away, and processors call them unconditionally.
+//!
+//! Unlike `audit`, which reports violations to indexers as errors, these
+//! are for code that has just produced the state and considers a violation
+//! a bug.
+use crate::{curve::amount::Reserves, utils::U256};
+
+fn invariant(reserves: Reserves) -> U256 {
+    U256::from(reserves.token_0.get()) * U256::from(reserves.token_1.get())
+}
+
+// `a + b` per token, which cannot overflow in U256
+fn sum(a: Reserves, b: Reserves) -> [U256; 2] {
+    [
+        U256::from(a.token_0.get()) + U256::from(b.token_0.get()),
+        U256::from(a.token_1.get()) + U256::from(b.token_1.get()),
+    ]
+}
+
+/// Assert that the constant product of the reserves did not decrease from
+/// `before` to `after`.
+///
+/// Holds for swaps and fee collection, not for withdrawals.
+#[track_caller]
+pub fn invariant_non_decreasing(before: Reserves, after: Reserves) {
+    debug_assert!(
+        invariant(after) >= invariant(before),
+        "pool invariant decreased from {before:?} to {after:?}"
+    );
+}
+
+/// Assert that the protocol fees moved from `before` to `after` by exactly
+/// `accrued` in and `collected` out
+#[track_caller]
+pub fn fees_conserved(before: Reserves, after: Reserves, accrued: Reserves, collected: Reserves) {
+    debug_assert!(
+        sum(after, collected) == sum(before, accrued),
+        "protocol fees {before:?} + {accrued:?} - {collected:?} != {after:?}"
+    );
+}
+
+/// Assert that the pool's books, its reserves plus protocol fees, moved by
+/// exactly the vault deltas: `deposited` transferred into the vaults and
+/// `withdrawn` out of them
+#[track_caller]
+pub fn reserves_match_vault_deltas(
+    reserves_before: Reserves,
+    fees_before: Reserves,
+    reserves_after: Reserves,
+    fees_after: Reserves,
+    deposited: Reserves,
+    withdrawn: Reserves,
+) {
+    debug_assert!(
+        {
+            let [before_0, before_1] = sum(reserves_before, fees_before);
+            let [after_0, after_1] = sum(reserves_after, fees_after);
+            let [withdrawn_0, withdrawn_1] = [withdrawn.token_0.get(), withdrawn.token_1.get()];
+            let [deposited_0, deposited_1] = [deposited.token_0.get(), deposited.token_1.get()];
+            after_0 + U256::from(withdrawn_0) == before_0 + U256::from(deposited_0)
+                && after_1 + U256::from(withdrawn_1) == before_1 + U256::from(deposited_1)
+        },
+        "books moved from {reserves_before:?} + {fees_before:?} to {reserves_after:?} + \
+         {fees_after:?} for vault deposits {deposited:?} and withdrawals {withdrawn:?}"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_operations_pass() {
+        invariant_non_decreasing(Reserves::from_raw(100, 100), Reserves::from_raw(110, 91));
+        invariant_non_decreasing(
+            Reserves::from_raw(u128::MAX, u128::MAX),
+            Reserves::from_raw(u128::MAX, u128::MAX),
+        );
+        fees_conserved(
+            Reserves::from_raw(5, 7),
+            Reserves::from_raw(0, 9),
+            Reserves::from_raw(0, 2),
+            Reserves::from_raw(5, 0),
+        );
+        // a swap of 10 token 0 for 9 token 1, with 1 token 0 of protocol fee
+        reserves_match_vault_deltas(
+            Reserves::from_raw(100, 100),
+            Reserves::from_raw(0, 0),
+            Reserves::from_raw(109, 91),
+            Reserves::from_raw(1, 0),
+            Reserves::from_raw(10, 0),
+            Reserves::from_raw(0, 9),
+        );
+        // books at the edge of u128
+        reserves_match_vault_deltas(
+            Reserves::from_raw(u128::MAX - 1, 100),
+            Reserves::from_raw(1, 0),
+            Reserves::from_raw(u128::MAX, 0),
+            Reserves::from_raw(1, 0),
+            Reserves::from_raw(1, 0),
+            Reserves::from_raw(0, 100),
+        );
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "pool invariant decreased")]
+    fn decreasing_invariant_panics() {
+        invariant_non_decreasing(Reserves::from_raw(100, 100), Reserves::from_raw(110, 90));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "protocol fees")]
+    fn unaccounted_fees_panic() {
+        fees_conserved(
+            Reserves::from_raw(5, 7),
+            Reserves::from_raw(5, 8),
+            Reserves::from_raw(0, 0),
+            Reserves::from_raw(0, 0),
+        );
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "for vault deposits")]
+    fn books_drifting_from_vaults_panic() {
+        // the protocol fee is booked on top of the whole input
+        reserves_match_vault_deltas(
+            Reserves::from_raw(100, 100),
+            Reserves::from_raw(0, 0),
+            Reserves::from_raw(110, 91),
+            Reserves::from_raw(1, 0),
+            Reserves::from_raw(10, 0),
+            Reserves::from_raw(0, 9),
+        );
+    }
+}
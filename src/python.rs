@@ -0,0 +1,533 @@
+//! Python bindings
+//!
+//! Exposes the calculator, fee math and router as the `curve` Python module,
+//! so strategies can be backtested against the exact integer arithmetic used
+//! on-chain. Python integers are converted to u128 without loss; calculations
+//! that cannot be computed raise `ValueError` instead of returning `None`.
+//!
+//! The router functions take constant product pools as
+//! `(reserve_0, reserve_1, trade_fee_rate, protocol_fee_rate)` tuples, and
+//! route hops as the same tuple followed by `zero_for_one`.
+use {
+    crate::{
+        curve::{
+            amount::Reserves,
+            calculator::{
+                CurveCalculator, RoundDirection, SwapResult, TradeDirection, TradingTokenResult,
+            },
+            context::ContextError,
+            fees::{FEE_RATE_DENOMINATOR_VALUE, FeeConfig, FeeRate, Fees},
+            swap_params::SwapParams,
+        },
+        quote::SwapQuote,
+        router::{self, Hop, PoolQuote, SplitLeg, SplitOrder},
+        state::PoolState,
+    },
+    pyo3::{exceptions::PyValueError, prelude::*},
+};
+
+/// Results of a swap, see `curve::calculator::SwapResult`
+#[pyclass(name = "SwapResult", module = "curve", frozen, get_all)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PySwapResult {
+    pub new_swap_source_amount: u128,
+    pub new_swap_destination_amount: u128,
+    pub source_amount_swapped: u128,
+    pub destination_amount_swapped: u128,
+    pub trade_fee: u128,
+    pub protocol_fee: u128,
+    pub destination_trade_fee: u128,
+    pub destination_protocol_fee: u128,
+    pub rounding_dust: u128,
+}
+
+impl From<SwapResult> for PySwapResult {
+    fn from(result: SwapResult) -> Self {
+        Self {
+            new_swap_source_amount: result.new_swap_source_amount,
+            new_swap_destination_amount: result.new_swap_destination_amount,
+            source_amount_swapped: result.source_amount_swapped,
+            destination_amount_swapped: result.destination_amount_swapped,
+            trade_fee: result.trade_fee,
+            protocol_fee: result.protocol_fee,
+            destination_trade_fee: result.destination_trade_fee,
+            destination_protocol_fee: result.destination_protocol_fee,
+            rounding_dust: result.rounding_dust,
+        }
+    }
+}
+
+#[pymethods]
+impl PySwapResult {
+    fn __repr__(&self) -> String {
+        format!(
+            "SwapResult(source_amount_swapped={}, destination_amount_swapped={}, trade_fee={}, protocol_fee={})",
+            self.source_amount_swapped,
+            self.destination_amount_swapped,
+            self.trade_fee,
+            self.protocol_fee
+        )
+    }
+}
+
+/// Token amounts for an amount of LP tokens, see
+/// `curve::calculator::TradingTokenResult`
+#[pyclass(name = "TradingTokenResult", module = "curve", frozen, get_all)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PyTradingTokenResult {
+    pub token_0_amount: u128,
+    pub token_1_amount: u128,
+    pub token_0_rounding_dust: u128,
+    pub token_1_rounding_dust: u128,
+}
+
+impl From<TradingTokenResult> for PyTradingTokenResult {
+    fn from(result: TradingTokenResult) -> Self {
+        Self {
+            token_0_amount: result.token_0_amount,
+            token_1_amount: result.token_1_amount,
+            token_0_rounding_dust: result.token_0_rounding_dust,
+            token_1_rounding_dust: result.token_1_rounding_dust,
+        }
+    }
+}
+
+#[pymethods]
+impl PyTradingTokenResult {
+    fn __repr__(&self) -> String {
+        format!(
+            "TradingTokenResult(token_0_amount={}, token_1_amount={})",
+            self.token_0_amount, self.token_1_amount
+        )
+    }
+}
+
+/// One pool's share of a split order, see `curve::router::SplitLeg`
+#[pyclass(name = "SplitLeg", module = "curve", frozen, get_all)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PySplitLeg {
+    pub amount_in: u128,
+    pub amount_out: u128,
+}
+
+impl From<SplitLeg> for PySplitLeg {
+    fn from(leg: SplitLeg) -> Self {
+        Self {
+            amount_in: leg.amount_in,
+            amount_out: leg.amount_out,
+        }
+    }
+}
+
+#[pymethods]
+impl PySplitLeg {
+    fn __repr__(&self) -> String {
+        format!(
+            "SplitLeg(amount_in={}, amount_out={})",
+            self.amount_in, self.amount_out
+        )
+    }
+}
+
+/// An order split across pools, see `curve::router::SplitOrder`
+#[pyclass(name = "SplitOrder", module = "curve", frozen, get_all)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PySplitOrder {
+    pub legs: Vec<PySplitLeg>,
+    pub total_amount_in: u128,
+    pub total_amount_out: u128,
+}
+
+impl From<SplitOrder> for PySplitOrder {
+    fn from(order: SplitOrder) -> Self {
+        Self {
+            legs: order.legs.into_iter().map(PySplitLeg::from).collect(),
+            total_amount_in: order.total_amount_in,
+            total_amount_out: order.total_amount_out,
+        }
+    }
+}
+
+#[pymethods]
+impl PySplitOrder {
+    fn __repr__(&self) -> String {
+        format!(
+            "SplitOrder(legs={}, total_amount_in={}, total_amount_out={})",
+            self.legs.len(),
+            self.total_amount_in,
+            self.total_amount_out
+        )
+    }
+}
+
+/// A pool's quote for a whole order, see `curve::router::PoolQuote` and
+/// `curve::quote::SwapQuote`. Prices are raw Q64.64 values.
+#[pyclass(name = "PoolQuote", module = "curve", frozen, get_all)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PyPoolQuote {
+    pub pool_index: usize,
+    pub amount_in: u128,
+    pub amount_out: u128,
+    pub trade_fee: u128,
+    pub protocol_fee: u128,
+    pub destination_trade_fee: u128,
+    pub destination_protocol_fee: u128,
+    pub price_before: u128,
+    pub price_after: u128,
+    pub price_impact_bps: u128,
+    pub minimum_amount_out: u128,
+}
+
+impl From<PoolQuote> for PyPoolQuote {
+    fn from(PoolQuote { pool_index, quote }: PoolQuote) -> Self {
+        let SwapQuote {
+            amount_in,
+            amount_out,
+            trade_fee,
+            protocol_fee,
+            destination_trade_fee,
+            destination_protocol_fee,
+            price_before,
+            price_after,
+            price_impact_bps,
+            minimum_amount_out,
+            ..
+        } = quote;
+        Self {
+            pool_index,
+            amount_in,
+            amount_out,
+            trade_fee,
+            protocol_fee,
+            destination_trade_fee,
+            destination_protocol_fee,
+            price_before: price_before.raw(),
+            price_after: price_after.raw(),
+            price_impact_bps,
+            minimum_amount_out,
+        }
+    }
+}
+
+#[pymethods]
+impl PyPoolQuote {
+    fn __repr__(&self) -> String {
+        format!(
+            "PoolQuote(pool_index={}, amount_out={}, price_impact_bps={})",
+            self.pool_index, self.amount_out, self.price_impact_bps
+        )
+    }
+}
+
+fn computed<T>(value: Option<T>, what: &str) -> PyResult<T> {
+    value.ok_or_else(|| PyValueError::new_err(format!("{what} cannot be computed")))
+}
+
+/// Quote an exact-input swap
+#[pyfunction]
+fn swap_base_input(
+    source_amount: u128,
+    swap_source_amount: u128,
+    swap_destination_amount: u128,
+    trade_fee_rate: u64,
+    protocol_fee_rate: u64,
+) -> PyResult<PySwapResult> {
+    if swap_source_amount == 0 && source_amount == 0 {
+        return Err(PyValueError::new_err("pool has no source liquidity"));
+    }
+    let result = SwapParams::from_raw_parts(
+        source_amount,
+        swap_source_amount,
+        swap_destination_amount,
+        trade_fee_rate,
+        protocol_fee_rate,
+    )
+    .and_then(|params| CurveCalculator::swap_base_input(&params));
+    computed(result, "swap").map(PySwapResult::from)
+}
+
+/// Quote an exact-output swap
+#[pyfunction]
+fn swap_base_output(
+    destination_amount: u128,
+    swap_source_amount: u128,
+    swap_destination_amount: u128,
+    trade_fee_rate: u64,
+    protocol_fee_rate: u64,
+) -> PyResult<PySwapResult> {
+    // the strict calculator panics on these
+    if destination_amount >= swap_destination_amount {
+        return Err(PyValueError::new_err(
+            "destination_amount must be less than the pool reserve",
+        ));
+    }
+    if trade_fee_rate >= FEE_RATE_DENOMINATOR_VALUE {
+        return Err(PyValueError::new_err("trade_fee_rate must be below 100%"));
+    }
+    let result = SwapParams::from_raw_parts(
+        destination_amount,
+        swap_source_amount,
+        swap_destination_amount,
+        trade_fee_rate,
+        protocol_fee_rate,
+    )
+    .and_then(|params| CurveCalculator::swap_base_output(&params));
+    computed(result, "swap").map(PySwapResult::from)
+}
+
+/// Token amounts for depositing (`round_up=True`) or withdrawing
+/// (`round_up=False`) the given amount of LP tokens
+#[pyfunction]
+#[pyo3(signature = (lp_token_amount, lp_token_supply, swap_token_0_amount, swap_token_1_amount, round_up=false))]
+fn lp_tokens_to_trading_tokens(
+    lp_token_amount: u128,
+    lp_token_supply: u128,
+    swap_token_0_amount: u128,
+    swap_token_1_amount: u128,
+    round_up: bool,
+) -> PyResult<PyTradingTokenResult> {
+    let round_direction = if round_up {
+        RoundDirection::Ceiling
+    } else {
+        RoundDirection::Floor
+    };
+    let result = CurveCalculator::lp_tokens_to_trading_tokens(
+        lp_token_amount,
+        lp_token_supply,
+        swap_token_0_amount,
+        swap_token_1_amount,
+        round_direction,
+    );
+    computed(result, "LP token conversion").map(PyTradingTokenResult::from)
+}
+
+/// Trading fee charged on `amount`, rounded up
+#[pyfunction]
+fn trading_fee(amount: u128, trade_fee_rate: u64) -> PyResult<u128> {
+    computed(Fees::trading_fee(amount, trade_fee_rate), "trading fee")
+}
+
+/// Protocol share of a trading fee, rounded down
+#[pyfunction]
+fn protocol_fee(amount: u128, protocol_fee_rate: u64) -> PyResult<u128> {
+    computed(
+        Fees::protocol_fee(amount, protocol_fee_rate),
+        "protocol fee",
+    )
+}
+
+/// Amount before fees that leaves `post_fee_amount` once the trading fee is
+/// deducted
+#[pyfunction]
+fn calculate_pre_fee_amount(post_fee_amount: u128, trade_fee_rate: u64) -> PyResult<u128> {
+    computed(
+        Fees::calculate_pre_fee_amount(post_fee_amount, trade_fee_rate),
+        "pre-fee amount",
+    )
+}
+
+fn direction(zero_for_one: bool) -> TradeDirection {
+    if zero_for_one {
+        TradeDirection::ZeroForOne
+    } else {
+        TradeDirection::OneForZero
+    }
+}
+
+fn fee_config(trade_fee_rate: u64, protocol_fee_rate: u64) -> Option<FeeConfig> {
+    Some(FeeConfig::new(
+        FeeRate::checked(trade_fee_rate)?,
+        FeeRate::checked(protocol_fee_rate)?,
+    ))
+}
+
+/// Constant product pools from `(reserve_0, reserve_1, trade_fee_rate,
+/// protocol_fee_rate)` tuples, or `None` if a fee rate exceeds 100%
+fn pool_states(pools: Vec<(u64, u64, u64, u64)>) -> Option<Vec<PoolState>> {
+    pools
+        .into_iter()
+        .map(
+            |(token_0_reserve, token_1_reserve, trade_fee_rate, protocol_fee_rate)| {
+                Some(PoolState {
+                    fee_config: fee_config(trade_fee_rate, protocol_fee_rate)?,
+                    token_0_reserve,
+                    token_1_reserve,
+                    ..PoolState::default()
+                })
+            },
+        )
+        .collect()
+}
+
+/// Route hops from pool tuples followed by `zero_for_one`, or `None` if a fee
+/// rate exceeds 100%
+fn hops(hops: Vec<(u128, u128, u64, u64, bool)>) -> Option<Vec<Hop>> {
+    hops.into_iter()
+        .map(
+            |(reserve_0, reserve_1, trade_fee_rate, protocol_fee_rate, zero_for_one)| {
+                Some(Hop {
+                    reserves: Reserves::from_raw(reserve_0, reserve_1),
+                    fee_config: fee_config(trade_fee_rate, protocol_fee_rate)?,
+                    trade_direction: direction(zero_for_one),
+                })
+            },
+        )
+        .collect()
+}
+
+fn routed(results: Result<Vec<SwapResult>, ContextError>) -> PyResult<Vec<PySwapResult>> {
+    results
+        .map(|results| results.into_iter().map(PySwapResult::from).collect())
+        .map_err(|error| PyValueError::new_err(error.to_string()))
+}
+
+/// Split `total_amount_in` across `pools` of one pair to maximize the
+/// amount out
+#[pyfunction]
+fn split_order(
+    zero_for_one: bool,
+    pools: Vec<(u64, u64, u64, u64)>,
+    total_amount_in: u64,
+) -> PyResult<PySplitOrder> {
+    let pools = computed(pool_states(pools), "pool fee config")?;
+    // constant product pools do not depend on the time
+    let order = router::split_order(0, direction(zero_for_one), &pools, total_amount_in);
+    computed(order, "split").map(PySplitOrder::from)
+}
+
+/// Quote `amount_in` on each of `pools` of one pair, best first; pools that
+/// cannot fill the order are left out
+#[pyfunction]
+#[pyo3(signature = (zero_for_one, pools, amount_in, slippage_bps=50))]
+fn best_quote(
+    zero_for_one: bool,
+    pools: Vec<(u64, u64, u64, u64)>,
+    amount_in: u128,
+    slippage_bps: u16,
+) -> PyResult<Vec<PyPoolQuote>> {
+    let pools = computed(pool_states(pools), "pool fee config")?;
+    let quotes = router::best_quote(0, direction(zero_for_one), &pools, amount_in, slippage_bps);
+    Ok(quotes.into_iter().map(PyPoolQuote::from).collect())
+}
+
+/// Quote `amount_in` through `hops`, each paying into the next; errors name
+/// the hop that failed
+#[pyfunction]
+fn quote_route_exact_in(
+    hops: Vec<(u128, u128, u64, u64, bool)>,
+    amount_in: u128,
+) -> PyResult<Vec<PySwapResult>> {
+    let hops = computed(self::hops(hops), "hop fee config")?;
+    routed(router::quote_route_exact_in(&hops, amount_in))
+}
+
+/// Quote the input `hops` need to pay out exactly `amount_out`; errors name
+/// the hop that failed
+#[pyfunction]
+fn quote_route_exact_out(
+    hops: Vec<(u128, u128, u64, u64, bool)>,
+    amount_out: u128,
+) -> PyResult<Vec<PySwapResult>> {
+    let hops = computed(self::hops(hops), "hop fee config")?;
+    routed(router::quote_route_exact_out(&hops, amount_out))
+}
+
+#[pymodule]
+fn curve(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add("FEE_RATE_DENOMINATOR_VALUE", FEE_RATE_DENOMINATOR_VALUE)?;
+    module.add_class::<PySwapResult>()?;
+    module.add_class::<PyTradingTokenResult>()?;
+    module.add_class::<PySplitLeg>()?;
+    module.add_class::<PySplitOrder>()?;
+    module.add_class::<PyPoolQuote>()?;
+    module.add_function(wrap_pyfunction!(swap_base_input, module)?)?;
+    module.add_function(wrap_pyfunction!(swap_base_output, module)?)?;
+    module.add_function(wrap_pyfunction!(lp_tokens_to_trading_tokens, module)?)?;
+    module.add_function(wrap_pyfunction!(trading_fee, module)?)?;
+    module.add_function(wrap_pyfunction!(protocol_fee, module)?)?;
+    module.add_function(wrap_pyfunction!(calculate_pre_fee_amount, module)?)?;
+    module.add_function(wrap_pyfunction!(split_order, module)?)?;
+    module.add_function(wrap_pyfunction!(best_quote, module)?)?;
+    module.add_function(wrap_pyfunction!(quote_route_exact_in, module)?)?;
+    module.add_function(wrap_pyfunction!(quote_route_exact_out, module)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    // the bindings themselves raise `PyErr`, which needs libpython to link,
+    // so these tests drive the conversions the bindings are built from
+    use super::*;
+
+    fn pools(pools: Vec<(u64, u64, u64, u64)>) -> Vec<PoolState> {
+        pool_states(pools).unwrap()
+    }
+
+    #[test]
+    fn invalid_fee_rates_are_rejected() {
+        let too_high = FEE_RATE_DENOMINATOR_VALUE + 1;
+        assert!(pool_states(vec![(1, 1, too_high, 0)]).is_none());
+        assert!(pool_states(vec![(1, 1, 0, too_high)]).is_none());
+        assert!(hops(vec![(1, 1, too_high, 0, true)]).is_none());
+        assert_eq!(pool_states(vec![]), Some(vec![]));
+    }
+
+    #[test]
+    fn split_orders_across_pools() {
+        let pools = pools(vec![
+            (1_000_000, 2_000_000, 3_000, 0),
+            (3_000_000, 6_000_000, 3_000, 0),
+        ]);
+        let order = router::split_order(0, direction(false), &pools, 400_000).unwrap();
+        let order = PySplitOrder::from(order);
+        assert_eq!(order.total_amount_in, 400_000);
+        assert_eq!(order.legs.len(), 2);
+        assert!(order.legs[0].amount_in.abs_diff(100_000) <= 1);
+        assert!(order.legs[1].amount_in.abs_diff(300_000) <= 1);
+        assert_eq!(
+            order.legs.iter().map(|leg| leg.amount_out).sum::<u128>(),
+            order.total_amount_out
+        );
+    }
+
+    #[test]
+    fn best_quotes_rank_pools() {
+        let pools = pools(vec![
+            (1_000_000, 1_000_000, 10_000, 0),
+            (0, 0, 100, 0),
+            (4_000_000, 4_000_000, 3_000, 0),
+        ]);
+        let quotes: Vec<_> = router::best_quote(0, direction(true), &pools, 10_000, 50)
+            .into_iter()
+            .map(PyPoolQuote::from)
+            .collect();
+        let order: Vec<_> = quotes.iter().map(|quote| quote.pool_index).collect();
+        assert_eq!(order, [2, 0]);
+        assert_eq!(quotes[0].amount_in, 10_000);
+        assert!(quotes[0].amount_out > quotes[1].amount_out);
+        assert!(quotes[0].minimum_amount_out <= quotes[0].amount_out);
+    }
+
+    #[test]
+    fn routes_chain_hops() {
+        let hops = hops(vec![
+            (1_000_000, 2_000_000, 3_000, 0, true),
+            (3_000_000, 2_000_000, 3_000, 0, true),
+        ])
+        .unwrap();
+        let exact_in = router::quote_route_exact_in(&hops, 10_000).unwrap();
+        assert_eq!(
+            exact_in[1].source_amount_swapped,
+            exact_in[0].destination_amount_swapped
+        );
+        let amount_out = exact_in[1].destination_amount_swapped;
+        let exact_out = router::quote_route_exact_out(&hops, amount_out).unwrap();
+        assert_eq!(exact_out[1].destination_amount_swapped, amount_out);
+        assert!(exact_out[0].source_amount_swapped <= 10_000);
+        let exact_in: Vec<_> = exact_in.into_iter().map(PySwapResult::from).collect();
+        assert_eq!(
+            exact_in[1].source_amount_swapped,
+            exact_in[0].destination_amount_swapped
+        );
+    }
+}
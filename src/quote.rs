@@ -0,0 +1,704 @@
+//! Swap quotes for user interfaces
+//!
+//! A `SwapQuote` gathers everything a UI shows for a trade: the amounts, each
+//! fee component, the spot price before and after, the price impact and the
+//! slippage bound to submit with the transaction. `SwapQuote::builder` names
+//! each input of a quote, and `ladder` quotes a whole range of sizes at once,
+//! for slippage tables.
+use {
+    crate::{
+        curve::{
+            amount::{Reserves, Token0Amount, Token1Amount},
+            calculator::{CurveCalculator, CurveError, SwapResult, TradeDirection},
+            fees::FeeConfig,
+            params::CurveParams,
+            slippage::{SLIPPAGE_BPS_DENOMINATOR, maximum_amount_in, minimum_amount_out},
+        },
+        utils::{
+            PriceQ64, U256,
+            format::{self, Value},
+            price::to_u128,
+        },
+    },
+    alloc::vec::Vec,
+    core::fmt,
+};
+
+/// Full breakdown of a swap
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SwapQuote {
+    /// Direction of the trade
+    pub trade_direction: TradeDirection,
+    /// Amount of source token paid in, including fees
+    pub amount_in: u128,
+    /// Amount of destination token received
+    pub amount_out: u128,
+    /// Fee charged on the input, including the protocol's share
+    pub trade_fee: u128,
+    /// Protocol's share of the trade fee taken from the input
+    pub protocol_fee: u128,
+    /// Fee deducted from the output under `TradeFeeSide::Output`,
+    /// including the protocol's share
+    pub destination_trade_fee: u128,
+    /// Destination tokens going to the protocol under
+    /// `TradeFeeSide::Output` or `ProtocolFeeMode::Split`
+    pub destination_protocol_fee: u128,
+    /// Trade fee less `protocol_fee`. Under `ProtocolFeeMode::Split` this
+    /// includes the source tokens the pool sells for
+    /// `destination_protocol_fee`.
+    pub lp_fee: u128,
+    /// Source tokens retained by the pool due to rounding
+    pub rounding_dust: u128,
+    /// Spot price of token 0 in token 1 before the trade
+    pub price_before: PriceQ64,
+    /// Spot price of token 0 in token 1 after the trade, excluding the
+    /// protocol fee from the reserves
+    pub price_after: PriceQ64,
+    /// How much worse the trade executes than the spot price, in basis
+    /// points, rounded up. Fees are excluded, as they are broken out above.
+    pub price_impact_bps: u128,
+    /// Smallest output to accept at the requested slippage; equal to
+    /// `amount_out` for exact-output quotes
+    pub minimum_amount_out: u128,
+    /// Largest input to accept at the requested slippage; equal to
+    /// `amount_in` for exact-input quotes
+    pub maximum_amount_in: u128,
+}
+
+/// `{}` is a single `key=value` line for logs; `{:#}` is one labelled row
+/// per field, with thousands separators and prices followed by their exact
+/// Q64.64 value
+impl fmt::Display for SwapQuote {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        format::write_rows(
+            f,
+            &[
+                ("trade_direction", Value::Direction(self.trade_direction)),
+                ("amount_in", Value::Amount(self.amount_in)),
+                ("amount_out", Value::Amount(self.amount_out)),
+                ("trade_fee", Value::Amount(self.trade_fee)),
+                ("protocol_fee", Value::Amount(self.protocol_fee)),
+                ("lp_fee", Value::Amount(self.lp_fee)),
+                (
+                    "destination_trade_fee",
+                    Value::Amount(self.destination_trade_fee),
+                ),
+                (
+                    "destination_protocol_fee",
+                    Value::Amount(self.destination_protocol_fee),
+                ),
+                ("rounding_dust", Value::Amount(self.rounding_dust)),
+                ("price_before", Value::Price(self.price_before)),
+                ("price_after", Value::Price(self.price_after)),
+                ("price_impact_bps", Value::Amount(self.price_impact_bps)),
+                ("minimum_amount_out", Value::Amount(self.minimum_amount_out)),
+                ("maximum_amount_in", Value::Amount(self.maximum_amount_in)),
+            ],
+        )
+    }
+}
+
+fn has_liquidity(reserves: Reserves) -> bool {
+    reserves.token_0.get() != 0 && reserves.token_1.get() != 0
+}
+
+fn spot_price(reserves: Reserves) -> Option<PriceQ64> {
+    PriceQ64::from_reserves(reserves.token_0.get(), reserves.token_1.get())
+}
+
+/// Quote a swap of exactly `source_amount` in `trade_direction`, accepting
+/// `slippage_bps` less output.
+///
+/// Returns `None` if the swap fails, a reserve is empty, or the slippage
+/// exceeds 100%.
+pub fn quote_exact_in(
+    trade_direction: TradeDirection,
+    source_amount: u128,
+    reserves: Reserves,
+    fee_config: FeeConfig,
+    slippage_bps: u16,
+) -> Option<SwapQuote> {
+    if !has_liquidity(reserves) {
+        return None;
+    }
+    let price_before = spot_price(reserves)?;
+    quote_exact_in_at(
+        trade_direction,
+        source_amount,
+        reserves,
+        fee_config,
+        slippage_bps,
+        price_before,
+    )
+}
+
+fn quote_exact_in_at(
+    trade_direction: TradeDirection,
+    source_amount: u128,
+    reserves: Reserves,
+    fee_config: FeeConfig,
+    slippage_bps: u16,
+    price_before: PriceQ64,
+) -> Option<SwapQuote> {
+    let result =
+        CurveCalculator::swap_exact_in(trade_direction, source_amount, reserves, fee_config)?;
+    let mut quote = SwapQuote::new(trade_direction, reserves, price_before, &result)?;
+    quote.minimum_amount_out = minimum_amount_out(quote.amount_out, slippage_bps)?;
+    Some(quote)
+}
+
+/// Quote a swap of exactly `source_amount` in `trade_direction` at `now`
+/// on the curve `curve_params` configures, as `quote_exact_in` does for the
+/// constant product curve.
+///
+/// Prices and price impact are measured against the ratio of the reserves,
+/// which is the spot price of the constant product curve only.
+pub fn quote_curve_exact_in(
+    now: u64,
+    curve_params: &CurveParams,
+    trade_direction: TradeDirection,
+    source_amount: u128,
+    reserves: Reserves,
+    fee_config: FeeConfig,
+    slippage_bps: u16,
+) -> Option<SwapQuote> {
+    if !has_liquidity(reserves) {
+        return None;
+    }
+    let result =
+        curve_params.swap_exact_in(now, trade_direction, source_amount, reserves, fee_config)?;
+    let mut quote = SwapQuote::new(trade_direction, reserves, spot_price(reserves)?, &result)?;
+    quote.minimum_amount_out = minimum_amount_out(quote.amount_out, slippage_bps)?;
+    Some(quote)
+}
+
+/// Quote a swap for exactly `destination_amount` in `trade_direction`,
+/// accepting `slippage_bps` more input.
+///
+/// Returns `None` if the swap fails or a reserve is empty.
+pub fn quote_exact_out(
+    trade_direction: TradeDirection,
+    destination_amount: u128,
+    reserves: Reserves,
+    fee_config: FeeConfig,
+    slippage_bps: u16,
+) -> Option<SwapQuote> {
+    let (_, swap_destination_amount) = reserves.source_and_destination(trade_direction);
+    // the curve cannot pay out its whole reserve
+    if !has_liquidity(reserves) || destination_amount >= swap_destination_amount {
+        return None;
+    }
+    let result =
+        CurveCalculator::swap_exact_out(trade_direction, destination_amount, reserves, fee_config)?;
+    let mut quote = SwapQuote::new(trade_direction, reserves, spot_price(reserves)?, &result)?;
+    quote.maximum_amount_in = maximum_amount_in(quote.amount_in, slippage_bps)?;
+    Some(quote)
+}
+
+/// Quote exact-input swaps of each of `source_amounts` in
+/// `trade_direction`, as `quote_exact_in` would, in one call.
+///
+/// The pool's liquidity and spot price are checked once for the whole
+/// ladder. Each entry is `None` if that swap fails; all are if a reserve is
+/// empty or the slippage exceeds 100%.
+pub fn ladder(
+    trade_direction: TradeDirection,
+    reserves: Reserves,
+    fee_config: FeeConfig,
+    source_amounts: &[u128],
+    slippage_bps: u16,
+) -> Vec<Option<SwapQuote>> {
+    let price_before = if has_liquidity(reserves) {
+        spot_price(reserves)
+    } else {
+        None
+    };
+    source_amounts
+        .iter()
+        .map(|&source_amount| {
+            quote_exact_in_at(
+                trade_direction,
+                source_amount,
+                reserves,
+                fee_config,
+                slippage_bps,
+                price_before?,
+            )
+        })
+        .collect()
+}
+
+/// Why a `SwapQuoteBuilder` could not build a quote
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuoteError {
+    /// `reserves` was not set
+    MissingReserves,
+    /// `fees` was not set
+    MissingFees,
+    /// `trade_direction` was not set
+    MissingTradeDirection,
+    /// Neither `exact_in` nor `exact_out` was set
+    MissingAmount,
+    /// The slippage tolerance exceeds 100%
+    SlippageTooHigh,
+    /// The inputs are out of the curve's domain
+    Curve(CurveError),
+    /// The pool cannot fill the trade
+    Unfillable,
+}
+
+impl fmt::Display for QuoteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuoteError::MissingReserves => f.write_str("the reserves are not set"),
+            QuoteError::MissingFees => f.write_str("the fees are not set"),
+            QuoteError::MissingTradeDirection => f.write_str("the trade direction is not set"),
+            QuoteError::MissingAmount => f.write_str("neither amount in nor amount out is set"),
+            QuoteError::SlippageTooHigh => f.write_str("the slippage exceeds 100%"),
+            QuoteError::Curve(error) => error.fmt(f),
+            QuoteError::Unfillable => f.write_str("the pool cannot fill this trade"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for QuoteError {}
+
+// The side of a quote the amount fixes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum QuoteAmount {
+    ExactIn(u128),
+    ExactOut(u128),
+}
+
+/// Builds a `SwapQuote` from named inputs, as an alternative to the
+/// positional `quote_exact_in` and `quote_exact_out`.
+///
+/// ```
+/// # use curve::{curve::{*, calculator::TradeDirection}, quote::SwapQuote};
+/// let quote = SwapQuote::builder()
+///     .reserves(Token0Amount(1_000_000), Token1Amount(4_000_000))
+///     .fees(FeeConfig::new(FeeRate::from_bps(25).unwrap(), FeeRate::ZERO))
+///     .trade_direction(TradeDirection::ZeroForOne)
+///     .exact_in(10_000)
+///     .slippage_bps(50)
+///     .build()
+///     .unwrap();
+/// assert!(quote.minimum_amount_out < quote.amount_out);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SwapQuoteBuilder {
+    reserves: Option<Reserves>,
+    fee_config: Option<FeeConfig>,
+    trade_direction: Option<TradeDirection>,
+    amount: Option<QuoteAmount>,
+    slippage_bps: u16,
+}
+
+impl SwapQuoteBuilder {
+    /// The pool's reserves
+    pub const fn reserves(mut self, token_0: Token0Amount, token_1: Token1Amount) -> Self {
+        self.reserves = Some(Reserves::new(token_0, token_1));
+        self
+    }
+
+    /// The pool's fee configuration
+    pub const fn fees(mut self, fee_config: FeeConfig) -> Self {
+        self.fee_config = Some(fee_config);
+        self
+    }
+
+    /// Direction of the trade
+    pub const fn trade_direction(mut self, trade_direction: TradeDirection) -> Self {
+        self.trade_direction = Some(trade_direction);
+        self
+    }
+
+    /// Quote a swap of exactly `source_amount`, replacing any amount set
+    /// before
+    pub const fn exact_in(mut self, source_amount: u128) -> Self {
+        self.amount = Some(QuoteAmount::ExactIn(source_amount));
+        self
+    }
+
+    /// Quote a swap for exactly `destination_amount`, replacing any amount
+    /// set before
+    pub const fn exact_out(mut self, destination_amount: u128) -> Self {
+        self.amount = Some(QuoteAmount::ExactOut(destination_amount));
+        self
+    }
+
+    /// Slippage tolerance in basis points, zero if not set
+    pub const fn slippage_bps(mut self, slippage_bps: u16) -> Self {
+        self.slippage_bps = slippage_bps;
+        self
+    }
+
+    /// Validate the inputs and quote the swap
+    pub fn build(self) -> Result<SwapQuote, QuoteError> {
+        let reserves = self.reserves.ok_or(QuoteError::MissingReserves)?;
+        let fee_config = self.fee_config.ok_or(QuoteError::MissingFees)?;
+        let trade_direction = self
+            .trade_direction
+            .ok_or(QuoteError::MissingTradeDirection)?;
+        let amount = self.amount.ok_or(QuoteError::MissingAmount)?;
+        if u128::from(self.slippage_bps) > SLIPPAGE_BPS_DENOMINATOR {
+            return Err(QuoteError::SlippageTooHigh);
+        }
+        if !fee_config.is_valid() {
+            return Err(QuoteError::Curve(CurveError::FeeConfigInvalid));
+        }
+        if !has_liquidity(reserves) {
+            return Err(QuoteError::Curve(CurveError::EmptyPool));
+        }
+        let quote = match amount {
+            QuoteAmount::ExactIn(0) | QuoteAmount::ExactOut(0) => {
+                return Err(QuoteError::Curve(CurveError::ZeroTradeAmount));
+            }
+            QuoteAmount::ExactIn(source_amount) => quote_exact_in(
+                trade_direction,
+                source_amount,
+                reserves,
+                fee_config,
+                self.slippage_bps,
+            ),
+            QuoteAmount::ExactOut(destination_amount) => {
+                let (_, swap_destination_amount) = reserves.source_and_destination(trade_direction);
+                if destination_amount >= swap_destination_amount {
+                    return Err(QuoteError::Curve(CurveError::InsufficientLiquidity));
+                }
+                quote_exact_out(
+                    trade_direction,
+                    destination_amount,
+                    reserves,
+                    fee_config,
+                    self.slippage_bps,
+                )
+            }
+        };
+        quote.ok_or(QuoteError::Unfillable)
+    }
+}
+
+impl SwapQuote {
+    /// Start building a quote from named inputs
+    pub fn builder() -> SwapQuoteBuilder {
+        SwapQuoteBuilder::default()
+    }
+
+    fn new(
+        trade_direction: TradeDirection,
+        reserves: Reserves,
+        price_before: PriceQ64,
+        result: &SwapResult,
+    ) -> Option<Self> {
+        let (swap_source_amount, swap_destination_amount) =
+            reserves.source_and_destination(trade_direction);
+        let reserves_after = Reserves::from_source_and_destination(
+            trade_direction,
+            result
+                .new_swap_source_amount
+                .checked_sub(result.protocol_fee)?,
+            result.new_swap_destination_amount,
+        );
+        // output the input after fees would receive at the spot price,
+        // against the output before any fee deducted from it
+        let source_after_fees = result.source_amount_swapped.checked_sub(result.trade_fee)?;
+        let spot_amount_out = U256::from(source_after_fees) * U256::from(swap_destination_amount);
+        let amount_out = U256::from(
+            result
+                .destination_amount_swapped
+                .checked_add(result.destination_trade_fee)?,
+        ) * U256::from(swap_source_amount);
+        let price_impact_bps = if spot_amount_out.is_zero() {
+            0
+        } else {
+            let shortfall =
+                spot_amount_out.saturating_sub(amount_out) * U256::from(SLIPPAGE_BPS_DENOMINATOR);
+            to_u128((shortfall + spot_amount_out - 1) / spot_amount_out)?
+        };
+
+        Some(Self {
+            trade_direction,
+            amount_in: result.source_amount_swapped,
+            amount_out: result.destination_amount_swapped,
+            trade_fee: result.trade_fee,
+            protocol_fee: result.protocol_fee,
+            destination_trade_fee: result.destination_trade_fee,
+            destination_protocol_fee: result.destination_protocol_fee,
+            lp_fee: result.trade_fee.checked_sub(result.protocol_fee)?,
+            rounding_dust: result.rounding_dust,
+            price_before,
+            price_after: spot_price(reserves_after)?,
+            price_impact_bps,
+            minimum_amount_out: result.destination_amount_swapped,
+            maximum_amount_in: result.source_amount_swapped,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::curve::{
+            fees::{FeeRate, TradeFeeSide},
+            swap_params::SwapParams,
+        },
+    };
+
+    fn setup() -> (Reserves, FeeConfig) {
+        (
+            Reserves::new(Token0Amount(1_000_000), Token1Amount(4_000_000)),
+            FeeConfig::new(
+                FeeRate::from_bps(25).unwrap(),
+                FeeRate::from_percent(12).unwrap(),
+            ),
+        )
+    }
+
+    #[test]
+    fn exact_in_breakdown() {
+        let (reserves, fee_config) = setup();
+        let quote =
+            quote_exact_in(TradeDirection::ZeroForOne, 10_000, reserves, fee_config, 50).unwrap();
+        let result = CurveCalculator::swap_base_input(
+            &SwapParams::from_raw_parts(10_000, 1_000_000, 4_000_000, 2_500, 120_000).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(quote.amount_in, 10_000);
+        assert_eq!(quote.amount_out, result.destination_amount_swapped);
+        assert_eq!(quote.trade_fee, 25);
+        assert_eq!(quote.protocol_fee, 3);
+        assert_eq!(quote.lp_fee, 22);
+        assert_eq!(quote.rounding_dust, result.rounding_dust);
+        assert_eq!(quote.price_before, PriceQ64::from_integer(4));
+        assert!(quote.price_after < quote.price_before);
+        // 9_975 in against 1_000_000 moves the price by about 1%
+        assert_eq!(quote.price_impact_bps, 99);
+        assert_eq!(
+            quote.minimum_amount_out,
+            minimum_amount_out(quote.amount_out, 50).unwrap()
+        );
+        assert_eq!(quote.maximum_amount_in, 10_000);
+        assert_eq!(
+            quote_exact_in(
+                TradeDirection::ZeroForOne,
+                10_000,
+                reserves,
+                fee_config,
+                10_001
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn output_fees_are_not_price_impact() {
+        // the whole input is swapped, and the fee only deducted after
+        let (reserves, fee_config) = setup();
+        let without_fees = FeeConfig::new(FeeRate::ZERO, FeeRate::ZERO);
+        let on_output = fee_config.with_trade_fee_side(TradeFeeSide::Output);
+        let expected = quote_exact_in(
+            TradeDirection::ZeroForOne,
+            10_000,
+            reserves,
+            without_fees,
+            50,
+        )
+        .unwrap();
+        let quote =
+            quote_exact_in(TradeDirection::ZeroForOne, 10_000, reserves, on_output, 50).unwrap();
+        assert_ne!(quote.destination_trade_fee, 0);
+        assert_eq!(
+            quote.amount_out + quote.destination_trade_fee,
+            expected.amount_out
+        );
+        assert_eq!(quote.price_impact_bps, expected.price_impact_bps);
+    }
+
+    #[test]
+    fn exact_out_breakdown() {
+        let (reserves, fee_config) = setup();
+        let quote = quote_exact_out(
+            TradeDirection::OneForZero,
+            10_000,
+            reserves,
+            fee_config,
+            100,
+        )
+        .unwrap();
+        assert_eq!(quote.amount_out, 10_000);
+        assert_eq!(quote.minimum_amount_out, 10_000);
+        assert_eq!(
+            quote.maximum_amount_in,
+            maximum_amount_in(quote.amount_in, 100).unwrap()
+        );
+        assert!(quote.price_after > quote.price_before);
+        assert_eq!(quote.price_impact_bps, 101);
+        assert_eq!(
+            quote_exact_out(
+                TradeDirection::OneForZero,
+                1_000_000,
+                reserves,
+                fee_config,
+                100
+            ),
+            None
+        );
+        let empty = Reserves::new(Token0Amount(0), Token1Amount(4_000_000));
+        assert_eq!(
+            quote_exact_in(TradeDirection::OneForZero, 10_000, empty, fee_config, 100),
+            None
+        );
+    }
+
+    #[test]
+    fn builder_matches_positional_quotes() {
+        let (reserves, fee_config) = setup();
+        let builder = SwapQuote::builder()
+            .reserves(reserves.token_0, reserves.token_1)
+            .fees(fee_config)
+            .trade_direction(TradeDirection::OneForZero)
+            .slippage_bps(50);
+        assert_eq!(
+            builder.exact_in(10_000).build().ok(),
+            quote_exact_in(TradeDirection::OneForZero, 10_000, reserves, fee_config, 50)
+        );
+        assert_eq!(
+            builder.exact_out(10_000).build().ok(),
+            quote_exact_out(TradeDirection::OneForZero, 10_000, reserves, fee_config, 50)
+        );
+        // the last amount set wins
+        assert_eq!(
+            builder
+                .exact_out(10_000)
+                .exact_in(5_000)
+                .build()
+                .unwrap()
+                .amount_in,
+            5_000
+        );
+    }
+
+    #[test]
+    fn builder_validates_inputs() {
+        let (reserves, fee_config) = setup();
+        let complete = SwapQuote::builder()
+            .reserves(reserves.token_0, reserves.token_1)
+            .fees(fee_config)
+            .trade_direction(TradeDirection::ZeroForOne)
+            .exact_in(10_000);
+        assert!(complete.build().is_ok());
+        let missing = |builder: SwapQuoteBuilder| builder.build().unwrap_err();
+        assert_eq!(
+            missing(
+                SwapQuote::builder()
+                    .fees(fee_config)
+                    .trade_direction(TradeDirection::ZeroForOne)
+                    .exact_in(1)
+            ),
+            QuoteError::MissingReserves
+        );
+        assert_eq!(
+            missing(
+                SwapQuote::builder()
+                    .reserves(reserves.token_0, reserves.token_1)
+                    .trade_direction(TradeDirection::ZeroForOne)
+                    .exact_in(1)
+            ),
+            QuoteError::MissingFees
+        );
+        assert_eq!(
+            missing(
+                SwapQuote::builder()
+                    .reserves(reserves.token_0, reserves.token_1)
+                    .fees(fee_config)
+                    .exact_in(1)
+            ),
+            QuoteError::MissingTradeDirection
+        );
+        assert_eq!(
+            missing(
+                SwapQuote::builder()
+                    .reserves(reserves.token_0, reserves.token_1)
+                    .fees(fee_config)
+                    .trade_direction(TradeDirection::ZeroForOne)
+            ),
+            QuoteError::MissingAmount
+        );
+        assert_eq!(
+            missing(complete.slippage_bps(10_001)),
+            QuoteError::SlippageTooHigh
+        );
+        assert_eq!(
+            missing(complete.exact_in(0)),
+            QuoteError::Curve(CurveError::ZeroTradeAmount)
+        );
+        assert_eq!(
+            missing(complete.exact_out(4_000_000)),
+            QuoteError::Curve(CurveError::InsufficientLiquidity)
+        );
+        assert_eq!(
+            missing(complete.reserves(Token0Amount(0), reserves.token_1)),
+            QuoteError::Curve(CurveError::EmptyPool)
+        );
+        assert_eq!(
+            missing(complete.fees(FeeConfig::new(FeeRate::MAX, FeeRate::ZERO))),
+            QuoteError::Curve(CurveError::FeeConfigInvalid)
+        );
+    }
+
+    #[test]
+    fn ladder_matches_single_quotes() {
+        let (reserves, fee_config) = setup();
+        let amounts = [0, 1, 1_000, 10_000, 100_000, 1_000_000];
+        let quotes = ladder(
+            TradeDirection::OneForZero,
+            reserves,
+            fee_config,
+            &amounts,
+            50,
+        );
+        assert_eq!(quotes.len(), amounts.len());
+        for (&amount, quote) in amounts.iter().zip(&quotes) {
+            assert_eq!(
+                *quote,
+                quote_exact_in(TradeDirection::OneForZero, amount, reserves, fee_config, 50)
+            );
+        }
+        for pair in quotes[1..].windows(2) {
+            let (smaller, larger) = (pair[0].unwrap(), pair[1].unwrap());
+            assert!(smaller.price_impact_bps <= larger.price_impact_bps);
+        }
+        let empty = Reserves::new(Token0Amount(0), Token1Amount(4_000_000));
+        let quotes = ladder(TradeDirection::OneForZero, empty, fee_config, &amounts, 50);
+        assert!(quotes.iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn display_formats() {
+        let (reserves, fee_config) = setup();
+        let quote = quote_exact_in(
+            TradeDirection::ZeroForOne,
+            1_000_000,
+            reserves,
+            fee_config,
+            50,
+        )
+        .unwrap();
+        assert_eq!(
+            quote.to_string(),
+            "trade_direction=ZeroForOne amount_in=1000000 amount_out=1997496 trade_fee=2500 \
+             protocol_fee=300 lp_fee=2200 destination_trade_fee=0 destination_protocol_fee=0 \
+             rounding_dust=0 price_before=4.000000000 price_after=1.001402210 \
+             price_impact_bps=4994 minimum_amount_out=1987509 maximum_amount_in=1000000"
+        );
+        let pretty = format!("{quote:#}");
+        assert_eq!(pretty.lines().count(), 14);
+        assert!(pretty.contains("\namount out:               1,997,496\n"));
+        assert!(
+            pretty.contains("\nprice before:             4.000000000 (Q64 73786976294838206464)\n")
+        );
+    }
+}
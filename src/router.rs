@@ -0,0 +1,585 @@
+//! Order routing across pools of the same pair
+//!
+//! Splitting a trade across pools maximizes the total output when every pool
+//! that receives flow ends at the same marginal rate: otherwise moving a unit
+//! from the pool with the lowest marginal rate to the one with the highest
+//! would receive more. For a constant product pool with source and
+//! destination reserves `x` and `y` and `γ = 1 - fee`, the input at which the
+//! marginal rate falls to `λ` is `(sqrt(γ * x * y / λ) - x) / γ`, so the
+//! optimal split is found by bisecting on the common rate `λ`. Curves without
+//! that closed form are searched instead: their input at `λ` is where the
+//! next slice of the order, `1 / 2^MARGINAL_STEP_SHIFT` of it, no longer buys
+//! at `λ` or better.
+//!
+//! When the order goes to a single pool, `best_quote` ranks the candidates.
+//! `quote_route_exact_in` and `quote_route_exact_out` quote an order through
+//! a sequence of pools instead, reporting which hop fails.
+use {
+    crate::{
+        curve::{
+            amount::Reserves,
+            calculator::{CurveCalculator, SwapResult, TradeDirection},
+            context::ContextError,
+            fees::{FEE_RATE_DENOMINATOR_VALUE, FeeConfig},
+            params::CurveParams,
+        },
+        quote::{SwapQuote, quote_curve_exact_in},
+        state::PoolState,
+        utils::{IntegerSquareRoot, Q64_RESOLUTION, U256},
+    },
+    alloc::vec::Vec,
+};
+
+/// Slices of an order that marginal rates are measured over on curves
+/// without a closed form, as a power of two
+pub const MARGINAL_STEP_SHIFT: u32 = 4;
+
+/// The part of a split order routed through one pool
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SplitLeg {
+    /// Amount of source token paid into the pool, including fees
+    pub amount_in: u128,
+    /// Amount of destination token received from the pool
+    pub amount_out: u128,
+}
+
+/// An order split across several pools
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SplitOrder {
+    /// One leg per pool, in the order the pools were given; pools receiving
+    /// no flow have an empty leg
+    pub legs: Vec<SplitLeg>,
+    /// Sum of the legs' inputs
+    pub total_amount_in: u128,
+    /// Sum of the legs' outputs
+    pub total_amount_out: u128,
+}
+
+// Input, up to `total_amount_in`, at which the pool's marginal rate,
+// destination per source in Q64, falls to `rate`; zero if it is already
+// below
+fn input_at_marginal_rate(
+    now: u64,
+    pool: &PoolState,
+    trade_direction: TradeDirection,
+    rate: U256,
+    total_amount_in: u64,
+) -> U256 {
+    match pool.curve_params() {
+        Some(CurveParams::ConstantProduct) => {
+            constant_product_input_at_marginal_rate(pool, trade_direction, rate)
+        }
+        Some(_) => searched_input_at_marginal_rate(
+            now,
+            pool,
+            trade_direction,
+            rate,
+            total_amount_in.into(),
+        ),
+        None => U256::zero(),
+    }
+}
+
+fn constant_product_input_at_marginal_rate(
+    pool: &PoolState,
+    trade_direction: TradeDirection,
+    rate: U256,
+) -> U256 {
+    let (source, destination) = pool.reserves().source_and_destination(trade_direction);
+    let denominator = U256::from(FEE_RATE_DENOMINATOR_VALUE);
+    let net_of_fee = denominator.saturating_sub(U256::from(pool.fee_config.trade_fee_rate.get()));
+    if source == 0 || destination == 0 || net_of_fee.is_zero() {
+        return U256::zero();
+    }
+    let source = U256::from(source);
+    // reserves are u64, so the product cannot overflow
+    let invariant = source * U256::from(destination) * net_of_fee;
+    let source_after = ((invariant << Q64_RESOLUTION) / (denominator * rate)).sqrt_floor();
+    source_after.saturating_sub(source) * denominator / net_of_fee
+}
+
+// Bisect the pool's quotes for the largest input, up to `cap`, whose next
+// `step` tokens still buy at `rate` or better. Their average rate is the
+// marginal rate halfway through the step, which is where the input is put.
+// The marginal rate falls as the input grows on every built-in curve, which
+// the bisection relies on.
+fn searched_input_at_marginal_rate(
+    now: u64,
+    pool: &PoolState,
+    trade_direction: TradeDirection,
+    rate: U256,
+    cap: u128,
+) -> U256 {
+    let step = (cap >> MARGINAL_STEP_SHIFT).max(1);
+    let buys_at_rate = |amount_in: u128| {
+        let (Some(amount_out), Some(more_amount_out)) = (
+            quote(now, pool, trade_direction, amount_in),
+            quote(now, pool, trade_direction, amount_in + step),
+        ) else {
+            return false;
+        };
+        U256::from(more_amount_out.saturating_sub(amount_out)) << Q64_RESOLUTION
+            >= rate * U256::from(step)
+    };
+    if !buys_at_rate(0) {
+        return U256::zero();
+    }
+    let (mut low, mut high) = (0, cap);
+    while low < high {
+        let middle = low + (high - low).div_ceil(2);
+        if buys_at_rate(middle) {
+            low = middle;
+        } else {
+            high = middle - 1;
+        }
+    }
+    U256::from((low + step / 2).min(cap))
+}
+
+fn total_input_at_marginal_rate(
+    now: u64,
+    pools: &[PoolState],
+    trade_direction: TradeDirection,
+    rate: U256,
+    total_amount_in: u64,
+) -> U256 {
+    pools
+        .iter()
+        .map(|pool| input_at_marginal_rate(now, pool, trade_direction, rate, total_amount_in))
+        .fold(U256::zero(), |total, amount| total + amount)
+}
+
+fn quote(
+    now: u64,
+    pool: &PoolState,
+    trade_direction: TradeDirection,
+    amount_in: u128,
+) -> Option<u128> {
+    if amount_in == 0 {
+        return Some(0);
+    }
+    let (source, destination) = pool.reserves().source_and_destination(trade_direction);
+    if source == 0 || destination == 0 {
+        return None;
+    }
+    let result = pool.curve_params()?.swap_exact_in(
+        now,
+        trade_direction,
+        amount_in,
+        pool.reserves(),
+        pool.fee_config,
+    )?;
+    Some(result.destination_amount_swapped)
+}
+
+/// Split `total_amount_in` in `trade_direction` across `pools` to maximize
+/// the total amount out.
+///
+/// Every pool must trade the same pair, and is quoted at `now`. Allocations
+/// are rounded down to a common marginal rate and the units left over go to
+/// the pool that pays the most for them. Returns `None` if no pool can take
+/// the trade or a quote fails.
+pub fn split_order(
+    now: u64,
+    trade_direction: TradeDirection,
+    pools: &[PoolState],
+    total_amount_in: u64,
+) -> Option<SplitOrder> {
+    let total = U256::from(total_amount_in);
+    // the marginal rate can fall no lower than 1 in Q64, and no pool takes
+    // flow above the best spot rate
+    let mut low = U256::one();
+    let mut high = U256::one() << (2 * Q64_RESOLUTION);
+    // smallest rate at which the pools absorb no more than the order
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if total_input_at_marginal_rate(now, pools, trade_direction, middle, total_amount_in)
+            <= total
+        {
+            high = middle;
+        } else {
+            low = middle + 1;
+        }
+    }
+    let mut amounts_in: Vec<u128> = pools
+        .iter()
+        .map(|pool| {
+            input_at_marginal_rate(now, pool, trade_direction, low, total_amount_in).as_u128()
+        })
+        .collect();
+    let allocated: u128 = amounts_in.iter().sum();
+    let remainder = u128::from(total_amount_in) - allocated;
+    if remainder > 0 {
+        let mut best = None;
+        for (index, pool) in pools.iter().enumerate() {
+            let Some(with_remainder) =
+                quote(now, pool, trade_direction, amounts_in[index] + remainder)
+            else {
+                continue;
+            };
+            let gain = with_remainder - quote(now, pool, trade_direction, amounts_in[index])?;
+            if best.is_none_or(|(_, best_gain)| gain > best_gain) {
+                best = Some((index, gain));
+            }
+        }
+        let (index, _) = best?;
+        amounts_in[index] += remainder;
+    }
+
+    let mut order = SplitOrder::default();
+    for (pool, amount_in) in pools.iter().zip(amounts_in) {
+        let amount_out = quote(now, pool, trade_direction, amount_in)?;
+        order.legs.push(SplitLeg {
+            amount_in,
+            amount_out,
+        });
+        order.total_amount_in += amount_in;
+        order.total_amount_out += amount_out;
+    }
+    Some(order)
+}
+
+/// Quote of one of the pools compared by `best_quote`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoolQuote {
+    /// Position of the pool in the slice given to `best_quote`
+    pub pool_index: usize,
+    /// The pool's quote for the whole order
+    pub quote: SwapQuote,
+}
+
+/// Quote `amount_in` in `trade_direction` on each of `pools` at `now`, best
+/// first.
+///
+/// Every pool must trade the same pair, but curve types and fee tiers may
+/// differ. Quotes are ranked by amount out, then by lower price impact, then
+/// by position in `pools`. Pools that cannot fill the order or whose curve
+/// parameters are invalid are left out.
+pub fn best_quote(
+    now: u64,
+    trade_direction: TradeDirection,
+    pools: &[PoolState],
+    amount_in: u128,
+    slippage_bps: u16,
+) -> Vec<PoolQuote> {
+    let mut quotes: Vec<_> = pools
+        .iter()
+        .enumerate()
+        .filter_map(|(pool_index, pool)| {
+            let quote = quote_curve_exact_in(
+                now,
+                &pool.curve_params()?,
+                trade_direction,
+                amount_in,
+                pool.reserves(),
+                pool.fee_config,
+                slippage_bps,
+            )?;
+            Some(PoolQuote { pool_index, quote })
+        })
+        .collect();
+    // stable, so ties keep the order of `pools`
+    quotes.sort_by(|a, b| {
+        b.quote
+            .amount_out
+            .cmp(&a.quote.amount_out)
+            .then(a.quote.price_impact_bps.cmp(&b.quote.price_impact_bps))
+    });
+    quotes
+}
+
+/// A pool a route trades through, and the direction it is traded in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Hop {
+    pub reserves: Reserves,
+    pub fee_config: FeeConfig,
+    pub trade_direction: TradeDirection,
+}
+
+/// Quote `amount_in` through constant product pools `hops`, each hop's
+/// output paying into the next, returning each hop's result in order.
+///
+/// Errors name the hop that failed, counting from 1.
+pub fn quote_route_exact_in(
+    hops: &[Hop],
+    amount_in: u128,
+) -> Result<Vec<SwapResult>, ContextError> {
+    let mut results = Vec::with_capacity(hops.len());
+    let mut amount = amount_in;
+    for (index, hop) in hops.iter().enumerate() {
+        let result = CurveCalculator::try_swap_exact_in(
+            hop.trade_direction,
+            amount,
+            hop.reserves,
+            hop.fee_config,
+        )
+        .map_err(|error| error.at_hop(index as u32 + 1))?;
+        amount = result.destination_amount_swapped;
+        results.push(result);
+    }
+    Ok(results)
+}
+
+/// Quote receiving exactly `amount_out` from the last of constant product
+/// pools `hops`, working back to the input each hop needs, returning each
+/// hop's result in route order.
+///
+/// Errors name the hop that failed, counting from 1.
+pub fn quote_route_exact_out(
+    hops: &[Hop],
+    amount_out: u128,
+) -> Result<Vec<SwapResult>, ContextError> {
+    let mut results = Vec::with_capacity(hops.len());
+    let mut amount = amount_out;
+    for (index, hop) in hops.iter().enumerate().rev() {
+        let result = CurveCalculator::try_swap_exact_out(
+            hop.trade_direction,
+            amount,
+            hop.reserves,
+            hop.fee_config,
+        )
+        .map_err(|error| error.at_hop(index as u32 + 1))?;
+        amount = result.source_amount_swapped;
+        results.push(result);
+    }
+    results.reverse();
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::curve::{
+            calculator::CurveError,
+            context::{ErrorCtx, Operation},
+            fees::FeeRate,
+            params::CurveParams,
+            stable::AmpRamp,
+        },
+        proptest::prelude::*,
+    };
+
+    fn pool(token_0_reserve: u64, token_1_reserve: u64, trade_fee_bps: u16) -> PoolState {
+        PoolState {
+            fee_config: FeeConfig::new(FeeRate::from_bps(trade_fee_bps).unwrap(), FeeRate::ZERO),
+            token_0_reserve,
+            token_1_reserve,
+            ..PoolState::default()
+        }
+    }
+
+    #[test]
+    fn split_evenly_across_identical_pools() {
+        let pools = [
+            pool(1_000_000, 1_000_000, 30),
+            pool(1_000_000, 1_000_000, 30),
+        ];
+        let order = split_order(0, TradeDirection::ZeroForOne, &pools, 100_000).unwrap();
+        assert_eq!(order.total_amount_in, 100_000);
+        assert!(order.legs[0].amount_in.abs_diff(order.legs[1].amount_in) <= 1);
+        let single = quote(0, &pools[0], TradeDirection::ZeroForOne, 100_000).unwrap();
+        assert!(order.total_amount_out > single);
+    }
+
+    #[test]
+    fn split_in_proportion_to_depth() {
+        let pools = [
+            pool(1_000_000, 2_000_000, 30),
+            pool(3_000_000, 6_000_000, 30),
+        ];
+        let order = split_order(0, TradeDirection::OneForZero, &pools, 400_000).unwrap();
+        assert!(order.legs[0].amount_in.abs_diff(100_000) <= 1);
+        assert!(order.legs[1].amount_in.abs_diff(300_000) <= 1);
+    }
+
+    #[test]
+    fn skip_pools_that_cannot_take_flow() {
+        let pools = [pool(0, 0, 30), pool(1_000_000, 1_000_000, 30)];
+        let order = split_order(0, TradeDirection::ZeroForOne, &pools, 1_000).unwrap();
+        assert_eq!(order.legs[0], SplitLeg::default());
+        assert_eq!(order.legs[1].amount_in, 1_000);
+        assert_eq!(
+            split_order(0, TradeDirection::ZeroForOne, &pools[..1], 1_000),
+            None
+        );
+        assert_eq!(split_order(0, TradeDirection::ZeroForOne, &[], 1_000), None);
+    }
+
+    #[test]
+    fn split_across_stable_and_constant_product_pools() {
+        let mut stable = pool(1_000_000, 1_000_000, 30);
+        stable.set_curve_params(CurveParams::Stable(AmpRamp::new(10_000).unwrap()));
+        let pools = [pool(4_000_000, 4_000_000, 5), stable];
+        let split = |amount_in: u64, total: u64| {
+            quote(0, &pools[0], TradeDirection::ZeroForOne, amount_in.into()).unwrap()
+                + quote(
+                    0,
+                    &pools[1],
+                    TradeDirection::ZeroForOne,
+                    (total - amount_in).into(),
+                )
+                .unwrap()
+        };
+        for total in [1_000, 50_000, 400_000] {
+            let order = split_order(0, TradeDirection::ZeroForOne, &pools, total).unwrap();
+            assert_eq!(order.total_amount_in, u128::from(total));
+            // within rounding of the best split on a fine grid
+            let best = (0..=total)
+                .step_by(100)
+                .map(|amount_in| split(amount_in, total))
+                .max()
+                .unwrap();
+            assert!(order.total_amount_out + 2 >= best, "{order:?} < {best}");
+        }
+        // the cheap pool fills small orders, and the flat stable curve takes
+        // over as they grow
+        let order = split_order(0, TradeDirection::ZeroForOne, &pools, 50_000).unwrap();
+        assert!(order.legs.iter().all(|leg| leg.amount_in > 0));
+        let order = split_order(0, TradeDirection::ZeroForOne, &pools, 1_000).unwrap();
+        assert_eq!(order.legs[1], SplitLeg::default());
+    }
+
+    #[test]
+    fn rank_quotes_across_fee_tiers() {
+        let pools = [
+            pool(1_000_000, 1_000_000, 100),
+            pool(0, 0, 1),
+            pool(1_000_000, 1_000_000, 5),
+            pool(4_000_000, 4_000_000, 30),
+            pool(1_000_000, 1_000_000, 5),
+        ];
+        let quotes = best_quote(0, TradeDirection::ZeroForOne, &pools, 10_000, 50);
+        let order: Vec<_> = quotes.iter().map(|quote| quote.pool_index).collect();
+        // the deep pool beats the cheap ones; identical pools keep their order
+        assert_eq!(order, [3, 2, 4, 0]);
+        for pair in quotes.windows(2) {
+            assert!(pair[0].quote.amount_out >= pair[1].quote.amount_out);
+        }
+        assert_eq!(quotes[0].quote.amount_in, 10_000);
+        assert!(best_quote(0, TradeDirection::ZeroForOne, &pools[1..2], 10_000, 50).is_empty());
+    }
+
+    #[test]
+    fn break_ties_by_price_impact() {
+        // both pay out 98 for 100 in, the shallow pool through price impact
+        // and the deep one through its fee
+        let pools = [pool(5_000, 5_000, 0), pool(1_000_000, 1_000_000, 100)];
+        let quotes = best_quote(0, TradeDirection::ZeroForOne, &pools, 100, 0);
+        assert_eq!(quotes[0].quote.amount_out, quotes[1].quote.amount_out);
+        assert_eq!(quotes[0].pool_index, 1);
+        assert!(quotes[0].quote.price_impact_bps < quotes[1].quote.price_impact_bps);
+    }
+
+    #[test]
+    fn rank_stable_pools_with_constant_product_ones() {
+        let params = CurveParams::Stable(AmpRamp::new(10_000).unwrap());
+        let mut stable = pool(1_000_000, 1_000_000, 30);
+        stable.set_curve_params(params);
+        let pools = [pool(2_000_000, 2_000_000, 5), stable];
+        // the stable pool's flat curve beats a deeper, cheaper constant
+        // product pool on large orders, but not on small ones
+        let large = best_quote(0, TradeDirection::ZeroForOne, &pools, 100_000, 50);
+        assert_eq!(large[0].pool_index, 1);
+        assert_eq!(
+            large[0].quote.amount_out,
+            params
+                .swap_exact_in(
+                    0,
+                    TradeDirection::ZeroForOne,
+                    100_000,
+                    stable.reserves(),
+                    stable.fee_config
+                )
+                .unwrap()
+                .destination_amount_swapped
+        );
+        let small = best_quote(0, TradeDirection::ZeroForOne, &pools, 1_000, 50);
+        assert_eq!(small[0].pool_index, 0);
+        assert_eq!(small.len(), 2);
+
+        // a pool whose parameters are invalid is left out
+        let mut invalid = stable;
+        invalid.curve_params[0] = u8::MAX;
+        assert!(best_quote(0, TradeDirection::ZeroForOne, &[invalid], 1_000, 50).is_empty());
+    }
+
+    fn hop(token_0_reserve: u64, token_1_reserve: u64, trade_direction: TradeDirection) -> Hop {
+        let pool = pool(token_0_reserve, token_1_reserve, 30);
+        Hop {
+            reserves: pool.reserves(),
+            fee_config: pool.fee_config,
+            trade_direction,
+        }
+    }
+
+    #[test]
+    fn routes_chain_hops() {
+        let hops = [
+            hop(1_000_000, 2_000_000, TradeDirection::ZeroForOne),
+            hop(3_000_000, 2_000_000, TradeDirection::ZeroForOne),
+        ];
+        let exact_in = quote_route_exact_in(&hops, 10_000).unwrap();
+        assert_eq!(
+            exact_in[1].source_amount_swapped,
+            exact_in[0].destination_amount_swapped
+        );
+        let amount_out = exact_in[1].destination_amount_swapped;
+        let exact_out = quote_route_exact_out(&hops, amount_out).unwrap();
+        assert_eq!(
+            exact_out[0].destination_amount_swapped,
+            exact_out[1].source_amount_swapped
+        );
+        assert_eq!(exact_out[1].destination_amount_swapped, amount_out);
+        // the cheapest input for the output never exceeds what bought it
+        assert!(exact_out[0].source_amount_swapped <= 10_000);
+        assert_eq!(quote_route_exact_in(&[], 10_000), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn route_errors_name_the_hop() {
+        let hops = [
+            hop(1_000_000, 1_000_000, TradeDirection::ZeroForOne),
+            hop(9_500, 1_000_000, TradeDirection::OneForZero),
+        ];
+        let error = quote_route_exact_out(&hops, 10_000).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "hop 2 exact-out sub: requested 10000 > reserve 9500: \
+             the pool cannot pay out the requested amount"
+        );
+        assert_eq!(error.ctx.hop(), Some(2));
+
+        let hops = [
+            hop(1_000_000, 1, TradeDirection::ZeroForOne),
+            hop(1_000_000, 1_000_000, TradeDirection::ZeroForOne),
+        ];
+        // the first hop pays out nothing for the second to swap
+        assert_eq!(
+            quote_route_exact_in(&hops, 1_000).unwrap_err(),
+            ErrorCtx::new(Operation::ExactIn)
+                .at_hop(2)
+                .error(CurveError::ZeroTradeAmount)
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn split_beats_any_single_pool(
+            reserves in proptest::collection::vec((1_000..u64::MAX / 4, 1_000..u64::MAX / 4), 1..5),
+            trade_fee_bps in 0..100u16,
+            total_amount_in in 1..u64::MAX / 4,
+        ) {
+            let pools: Vec<_> = reserves
+                .iter()
+                .map(|&(token_0, token_1)| pool(token_0, token_1, trade_fee_bps))
+                .collect();
+            let order = split_order(0, TradeDirection::ZeroForOne, &pools, total_amount_in).unwrap();
+            prop_assert_eq!(order.total_amount_in, u128::from(total_amount_in));
+            for pool in &pools {
+                let single = quote(0, pool, TradeDirection::ZeroForOne, total_amount_in.into()).unwrap();
+                prop_assert!(order.total_amount_out >= single);
+            }
+        }
+    }
+}
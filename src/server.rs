@@ -0,0 +1,288 @@
+//! HTTP JSON quoting service
+//!
+//! A small synchronous server exposing the quoting, depth and routing APIs,
+//! so market makers can run a local sidecar that prices trades with the same
+//! math as the chain. Every endpoint takes a JSON object in a `POST` body:
+//!
+//! - `/quote`: a pool, a `direction`, `exactIn` or `exactOut`, and an
+//!   optional `slippageBps`
+//! - `/depth`: a pool, a `direction`, and optional `points` and
+//!   `maxInputBps`
+//! - `/route`: `pools`, a list of pools of one pair, a `direction` and
+//!   `amountIn`
+//!
+//! A pool is given inline as `reserve0`, `reserve1`, `tradeFeeRate` and
+//! `protocolFeeRate`. As in the WebAssembly bindings, token amounts are
+//! decimal strings, since JSON numbers lose precision above 2^53 in most
+//! clients; plain numbers are accepted on input too. Direction is
+//! `zeroForOne` or `oneForZero`.
+use {
+    crate::{
+        analytics::{DepthCurveConfig, depth_curve},
+        curve::{
+            calculator::TradeDirection,
+            fees::{FeeConfig, FeeRate},
+        },
+        quote::SwapQuote,
+        router::split_order,
+        state::PoolState,
+    },
+    serde_json::{Value, json},
+    std::io,
+    tiny_http::{Header, Method, Response, Server},
+};
+
+type HandlerResult = Result<Value, String>;
+
+fn field<'a>(request: &'a Value, key: &str) -> Result<&'a Value, String> {
+    request
+        .get(key)
+        .filter(|value| !value.is_null())
+        .ok_or_else(|| format!("missing field `{key}`"))
+}
+
+fn parse_u64(request: &Value, key: &str) -> Result<u64, String> {
+    let value = field(request, key)?;
+    let parsed = match value {
+        Value::String(amount) => amount.parse().ok(),
+        Value::Number(amount) => amount.as_u64(),
+        _ => None,
+    };
+    parsed.ok_or_else(|| format!("`{key}` must be an unsigned 64-bit integer"))
+}
+
+fn optional_u64(request: &Value, key: &str, default: u64) -> Result<u64, String> {
+    match request.get(key) {
+        None | Some(Value::Null) => Ok(default),
+        Some(_) => parse_u64(request, key),
+    }
+}
+
+fn parse_u16(request: &Value, key: &str, default: u16) -> Result<u16, String> {
+    let value = optional_u64(request, key, default.into())?;
+    u16::try_from(value).map_err(|_| format!("`{key}` must be at most {}", u16::MAX))
+}
+
+fn parse_direction(request: &Value) -> Result<TradeDirection, String> {
+    match field(request, "direction")?.as_str() {
+        Some("zeroForOne") => Ok(TradeDirection::ZeroForOne),
+        Some("oneForZero") => Ok(TradeDirection::OneForZero),
+        _ => Err("`direction` must be `zeroForOne` or `oneForZero`".to_string()),
+    }
+}
+
+fn parse_pool(request: &Value) -> Result<PoolState, String> {
+    let fee_rate = |key| {
+        let rate = optional_u64(request, key, 0)?;
+        FeeRate::checked(rate).ok_or_else(|| format!("`{key}` exceeds 100%"))
+    };
+    Ok(PoolState {
+        fee_config: FeeConfig::new(fee_rate("tradeFeeRate")?, fee_rate("protocolFeeRate")?),
+        token_0_reserve: parse_u64(request, "reserve0")?,
+        token_1_reserve: parse_u64(request, "reserve1")?,
+        ..PoolState::default()
+    })
+}
+
+fn quote_json(quote: &SwapQuote) -> Value {
+    json!({
+        "amountIn": quote.amount_in.to_string(),
+        "amountOut": quote.amount_out.to_string(),
+        "tradeFee": quote.trade_fee.to_string(),
+        "protocolFee": quote.protocol_fee.to_string(),
+        "destinationTradeFee": quote.destination_trade_fee.to_string(),
+        "destinationProtocolFee": quote.destination_protocol_fee.to_string(),
+        "lpFee": quote.lp_fee.to_string(),
+        "roundingDust": quote.rounding_dust.to_string(),
+        "priceBeforeQ64": quote.price_before.raw().to_string(),
+        "priceAfterQ64": quote.price_after.raw().to_string(),
+        "priceImpactBps": quote.price_impact_bps.to_string(),
+        "minimumAmountOut": quote.minimum_amount_out.to_string(),
+        "maximumAmountIn": quote.maximum_amount_in.to_string(),
+    })
+}
+
+fn quote(request: &Value) -> HandlerResult {
+    let pool = parse_pool(request)?;
+    let trade_direction = parse_direction(request)?;
+    let slippage_bps = parse_u16(request, "slippageBps", 50)?;
+    let reserves = pool.reserves();
+    let builder = SwapQuote::builder()
+        .reserves(reserves.token_0, reserves.token_1)
+        .fees(pool.fee_config)
+        .trade_direction(trade_direction)
+        .slippage_bps(slippage_bps);
+    let builder = match (request.get("exactIn"), request.get("exactOut")) {
+        (Some(_), None) => builder.exact_in(parse_u64(request, "exactIn")?.into()),
+        (None, Some(_)) => builder.exact_out(parse_u64(request, "exactOut")?.into()),
+        _ => return Err("exactly one of `exactIn` and `exactOut` is required".to_string()),
+    };
+    let quote = builder.build().map_err(|error| error.to_string())?;
+    Ok(quote_json(&quote))
+}
+
+fn depth(request: &Value) -> HandlerResult {
+    let pool = parse_pool(request)?;
+    let defaults = DepthCurveConfig::default();
+    let config = DepthCurveConfig {
+        points: parse_u16(request, "points", defaults.points)?,
+        max_input_bps: parse_u16(request, "maxInputBps", defaults.max_input_bps)?,
+    };
+    let curve = depth_curve(
+        parse_direction(request)?,
+        pool.reserves(),
+        pool.fee_config,
+        config,
+    )
+    .ok_or("the pool cannot quote this ladder")?;
+    let points: Vec<_> = curve
+        .points
+        .iter()
+        .map(|point| {
+            json!({
+                "amountIn": point.amount_in.to_string(),
+                "amountOut": point.amount_out.to_string(),
+            })
+        })
+        .collect();
+    Ok(json!({ "points": points }))
+}
+
+fn route(request: &Value) -> HandlerResult {
+    let pools = field(request, "pools")?
+        .as_array()
+        .ok_or("`pools` must be an array")?
+        .iter()
+        .map(parse_pool)
+        .collect::<Result<Vec<_>, _>>()?;
+    // inline pools trade on the constant product curve, which does not
+    // depend on the time
+    let order = split_order(
+        0,
+        parse_direction(request)?,
+        &pools,
+        parse_u64(request, "amountIn")?,
+    )
+    .ok_or("no pool can take this trade")?;
+    let legs: Vec<_> = order
+        .legs
+        .iter()
+        .map(|leg| {
+            json!({
+                "amountIn": leg.amount_in.to_string(),
+                "amountOut": leg.amount_out.to_string(),
+            })
+        })
+        .collect();
+    Ok(json!({
+        "legs": legs,
+        "totalAmountIn": order.total_amount_in.to_string(),
+        "totalAmountOut": order.total_amount_out.to_string(),
+    }))
+}
+
+/// Handle a `POST` to `path` with a JSON `body`, returning the HTTP status
+/// and the JSON response
+pub fn handle(path: &str, body: &str) -> (u16, Value) {
+    let handler: fn(&Value) -> HandlerResult = match path {
+        "/quote" => quote,
+        "/depth" => depth,
+        "/route" => route,
+        _ => {
+            return (
+                404,
+                json!({ "error": format!("unknown endpoint `{path}`") }),
+            );
+        }
+    };
+    let result = serde_json::from_str(body)
+        .map_err(|err| format!("invalid JSON: {err}"))
+        .and_then(|request| handler(&request));
+    match result {
+        Ok(response) => (200, response),
+        Err(error) => (400, json!({ "error": error })),
+    }
+}
+
+/// Serve the quoting endpoints on `address`, eg. `127.0.0.1:8080`, until the
+/// process exits
+pub fn serve(address: &str) -> io::Result<()> {
+    let server = Server::http(address).map_err(io::Error::other)?;
+    let content_type =
+        Header::from_bytes("Content-Type", "application/json").expect("static header is valid");
+    for mut request in server.incoming_requests() {
+        let (status, response) = if *request.method() != Method::Post {
+            (405, json!({ "error": "only POST is supported" }))
+        } else {
+            let mut body = String::new();
+            match request.as_reader().read_to_string(&mut body) {
+                Ok(_) => handle(request.url(), &body),
+                Err(err) => (400, json!({ "error": format!("unreadable body: {err}") })),
+            }
+        };
+        let response = Response::from_string(response.to_string())
+            .with_status_code(status)
+            .with_header(content_type.clone());
+        // the client may have gone away; keep serving the others
+        let _ = request.respond(response);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POOL: &str = r#""reserve0": "1000000", "reserve1": "4000000", "tradeFeeRate": 2500"#;
+
+    #[test]
+    fn quote_endpoint() {
+        let body = format!(r#"{{ {POOL}, "direction": "zeroForOne", "exactIn": 10000 }}"#);
+        let (status, response) = handle("/quote", &body);
+        assert_eq!(status, 200, "{response}");
+        assert_eq!(response["amountIn"], "10000");
+        assert_eq!(response["tradeFee"], "25");
+
+        let body = format!(r#"{{ {POOL}, "direction": "oneForZero", "exactOut": "5000" }}"#);
+        let (status, response) = handle("/quote", &body);
+        assert_eq!(status, 200, "{response}");
+        assert_eq!(response["amountOut"], "5000");
+    }
+
+    #[test]
+    fn depth_and_route_endpoints() {
+        let body = format!(r#"{{ {POOL}, "direction": "zeroForOne", "points": 5 }}"#);
+        let (status, response) = handle("/depth", &body);
+        assert_eq!(status, 200, "{response}");
+        assert_eq!(response["points"].as_array().unwrap().len(), 5);
+        assert_eq!(response["points"][4]["amountIn"], "100000");
+
+        let body = format!(
+            r#"{{ "pools": [{{ {POOL} }}, {{ {POOL} }}], "direction": "zeroForOne", "amountIn": "20000" }}"#
+        );
+        let (status, response) = handle("/route", &body);
+        assert_eq!(status, 200, "{response}");
+        assert_eq!(response["legs"][0]["amountIn"], "10000");
+        assert_eq!(response["totalAmountIn"], "20000");
+    }
+
+    #[test]
+    fn bad_requests() {
+        assert_eq!(handle("/swap", "{}").0, 404);
+        let (status, response) = handle("/quote", "not json");
+        assert_eq!(status, 400);
+        assert!(
+            response["error"]
+                .as_str()
+                .unwrap()
+                .starts_with("invalid JSON")
+        );
+        let body = format!(r#"{{ {POOL}, "direction": "sideways", "exactIn": 1 }}"#);
+        assert_eq!(handle("/quote", &body).0, 400);
+        let body = format!(r#"{{ {POOL}, "direction": "zeroForOne" }}"#);
+        assert_eq!(handle("/quote", &body).0, 400);
+        let body =
+            r#"{ "reserve0": "-1", "reserve1": "1", "direction": "zeroForOne", "exactIn": 1 }"#;
+        assert_eq!(handle("/quote", body).0, 400);
+    }
+}
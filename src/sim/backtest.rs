@@ -0,0 +1,173 @@
+//! Backtesting on historical trade flow
+//!
+//! Replays a stream of recorded trades through a simulated `Pool` and
+//! records, after each one, the cumulative volume and fees, the impermanent
+//! loss since the start, and the TWAP oracle readings, so curve and fee
+//! choices can be evaluated on real flow. The pool's reserves alone decide
+//! the price; liquidity events are not part of the stream.
+use {
+    crate::{
+        analytics::impermanent_loss,
+        curve::{amount::Reserves, calculator::TradeDirection},
+        oracle::{Observation, time_weighted_prices},
+        sim::Pool,
+        stats::PoolStats,
+        utils::PriceQ64,
+    },
+    alloc::vec::Vec,
+};
+
+/// A recorded exact-input trade
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HistoricalTrade {
+    /// Unix timestamp of the trade, in seconds
+    pub timestamp: u64,
+    /// Direction of the trade
+    pub trade_direction: TradeDirection,
+    /// Amount of source token paid in, including fees
+    pub source_amount: u128,
+}
+
+/// State of the backtested pool after one trade
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BacktestPoint {
+    /// Timestamp of the trade
+    pub timestamp: u64,
+    /// Whether the pool could fill the trade; unfilled trades leave it
+    /// unchanged
+    pub filled: bool,
+    /// Reserves after the trade
+    pub reserves: Reserves,
+    /// Volume and fees since the start of the backtest
+    pub stats: PoolStats,
+    /// Impermanent loss of the pool's LPs since the start, in basis points
+    pub impermanent_loss_bps: u128,
+    /// Cumulative prices at the trade, for TWAPs between any two points
+    pub observation: Observation,
+    /// Time-weighted average price of token 0 in token 1 since the start;
+    /// `None` until time has passed
+    pub twap: Option<PriceQ64>,
+}
+
+fn spot_price(reserves: Reserves) -> Option<PriceQ64> {
+    PriceQ64::from_reserves(reserves.token_0.get(), reserves.token_1.get())
+}
+
+/// Replay `trades`, in timestamp order, through `pool` starting at
+/// `start_timestamp`, returning one point per trade.
+///
+/// Returns `None` if the pool starts empty, a trade is earlier than the
+/// previous one or the start, or a price cannot be represented.
+pub fn backtest(
+    mut pool: Pool,
+    start_timestamp: u64,
+    trades: impl IntoIterator<Item = HistoricalTrade>,
+) -> Option<Vec<BacktestPoint>> {
+    let initial_price = spot_price(pool.reserves)?;
+    let start = Observation {
+        timestamp: start_timestamp,
+        ..Observation::default()
+    };
+    let mut observation = start;
+    let mut stats = PoolStats::default();
+    trades
+        .into_iter()
+        .map(|trade| {
+            // the price before the trade held since the last one
+            observation = observation.advance(trade.timestamp, pool.reserves)?;
+            let result = pool.apply_swap(trade.trade_direction, trade.source_amount);
+            if let Some((result, _)) = &result {
+                stats.record_swap(trade.trade_direction, result);
+            }
+            Some(BacktestPoint {
+                timestamp: trade.timestamp,
+                filled: result.is_some(),
+                reserves: pool.reserves,
+                stats,
+                impermanent_loss_bps: impermanent_loss(initial_price, spot_price(pool.reserves)?)?,
+                observation,
+                twap: time_weighted_prices(&start, &observation).map(|(price_0, _)| price_0),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::curve::{
+            amount::{LpAmount, Token0Amount, Token1Amount},
+            fees::{FeeConfig, FeeRate},
+        },
+    };
+
+    fn pool() -> Pool {
+        Pool::new(
+            Reserves::new(Token0Amount(1_000_000), Token1Amount(1_000_000)),
+            LpAmount(1_000_000),
+            FeeConfig::new(FeeRate::from_bps(30).unwrap(), FeeRate::ZERO),
+        )
+    }
+
+    fn trade(
+        timestamp: u64,
+        trade_direction: TradeDirection,
+        source_amount: u128,
+    ) -> HistoricalTrade {
+        HistoricalTrade {
+            timestamp,
+            trade_direction,
+            source_amount,
+        }
+    }
+
+    #[test]
+    fn record_series_per_trade() {
+        let trades = [
+            trade(100, TradeDirection::ZeroForOne, 1_000_000),
+            trade(100, TradeDirection::ZeroForOne, 0),
+            trade(400, TradeDirection::OneForZero, 500_000),
+        ];
+        let points = backtest(pool(), 0, trades).unwrap();
+        assert_eq!(points.len(), 3);
+
+        // the first trade doubles token 0 and about quarters its price, an
+        // impermanent loss of about 20%
+        assert!(points[0].filled);
+        assert_eq!(points[0].stats.volume_in_token_0, 1_000_000);
+        assert_eq!(points[0].stats.trade_fees_token_0, 3_000);
+        assert_eq!(points[0].impermanent_loss_bps, 1_996);
+        // the price was 1 for the first 100 seconds
+        assert_eq!(points[0].twap, Some(PriceQ64::ONE));
+
+        // an empty trade in the same second is rejected and moves nothing
+        assert!(!points[1].filled);
+        assert_eq!(points[1].reserves, points[0].reserves);
+        assert_eq!(points[1].stats.volume_in_token_0, 1_000_000);
+        assert_eq!(points[1].observation, points[0].observation);
+
+        assert!(points[2].filled);
+        assert_eq!(points[2].stats.swap_count, 2);
+        assert_eq!(points[2].stats.volume_in_token_1, 500_000);
+        // a quarter of the time at 1, the rest at about 1/4
+        let twap = points[2].twap.unwrap();
+        assert!(twap < PriceQ64::from_ratio(1, 2).unwrap());
+        assert!(twap > PriceQ64::from_ratio(1, 4).unwrap());
+        let (window, _) =
+            time_weighted_prices(&points[0].observation, &points[2].observation).unwrap();
+        assert_eq!(window, spot_price(points[0].reserves).unwrap());
+    }
+
+    #[test]
+    fn reject_out_of_order_trades() {
+        let trades = [
+            trade(100, TradeDirection::ZeroForOne, 1_000),
+            trade(99, TradeDirection::ZeroForOne, 1_000),
+        ];
+        assert_eq!(backtest(pool(), 0, trades), None);
+        assert_eq!(backtest(pool(), 101, trades.into_iter().take(1)), None);
+        assert_eq!(backtest(Pool::default(), 0, []), None);
+        assert_eq!(backtest(pool(), 0, []), Some(Vec::new()));
+    }
+}
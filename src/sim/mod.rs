@@ -0,0 +1,748 @@
+//! Off-chain pool simulation
+//!
+//! A `Pool` applies swaps, deposits and withdrawals to its own reserves with
+//! the same calculator an on-chain program uses, so multi-step scenarios can
+//! be replayed deterministically without a validator. Every operation either
+//! succeeds and updates the pool, or returns `None` and leaves it untouched.
+//! Swaps also return an `Undo` that reverts them, for exploring branches of
+//! a search without copying the pool at every step.
+pub mod backtest;
+#[cfg(feature = "std")]
+pub mod montecarlo;
+
+use {
+    crate::{
+        curve::{
+            amount::{LpAmount, Reserves, Token0Amount, Token1Amount},
+            calculator::{
+                CurveCalculator, DepositResult, RoundDirection, SwapResult, TradeDirection,
+                TradingTokenResult,
+            },
+            fees::FeeConfig,
+        },
+        postconditions,
+        state::PoolState,
+    },
+    borsh::{BorshDeserialize, BorshSerialize},
+    core::fmt,
+};
+
+/// A simulated pool
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Pool {
+    /// Tokens backing the LP supply, excluding protocol fees
+    pub reserves: Reserves,
+    /// Outstanding LP tokens
+    pub lp_supply: LpAmount,
+    /// Fee rates charged on swaps
+    pub fee_config: FeeConfig,
+    /// Protocol fees accrued in each token and not yet collected
+    pub protocol_fees: Reserves,
+}
+
+impl Pool {
+    pub const fn new(reserves: Reserves, lp_supply: LpAmount, fee_config: FeeConfig) -> Self {
+        Self {
+            reserves,
+            lp_supply,
+            fee_config,
+            protocol_fees: Reserves::new(Token0Amount::ZERO, Token1Amount::ZERO),
+        }
+    }
+
+    /// Swap exactly `source_amount` in `trade_direction`, returning the
+    /// result and an `Undo` reverting the swap
+    pub fn apply_swap(
+        &mut self,
+        trade_direction: TradeDirection,
+        source_amount: u128,
+    ) -> Option<(SwapResult, Undo)> {
+        let result = CurveCalculator::swap_exact_in(
+            trade_direction,
+            source_amount,
+            self.reserves,
+            self.fee_config,
+        )?;
+        let before = Books::from(&*self);
+        self.settle_swap(trade_direction, &result)?;
+        let undo = Undo {
+            before,
+            after: Books::from(&*self),
+        };
+        Some((result, undo))
+    }
+
+    /// Revert the operation `undo` was returned by.
+    ///
+    /// Operations must be undone in reverse order: returns `None`, leaving
+    /// the pool untouched, if it has changed since that operation.
+    pub fn undo(&mut self, undo: Undo) -> Option<()> {
+        if Books::from(&*self) != undo.after {
+            return None;
+        }
+        self.reserves = undo.before.reserves;
+        self.lp_supply = undo.before.lp_supply;
+        self.protocol_fees = undo.before.protocol_fees;
+        Some(())
+    }
+
+    /// Swap for exactly `destination_amount` in `trade_direction`
+    pub fn apply_swap_exact_out(
+        &mut self,
+        trade_direction: TradeDirection,
+        destination_amount: u128,
+    ) -> Option<SwapResult> {
+        let (_, swap_destination_amount) = self.reserves.source_and_destination(trade_direction);
+        // the curve cannot pay out its whole reserve
+        if destination_amount >= swap_destination_amount {
+            return None;
+        }
+        let result = CurveCalculator::swap_exact_out(
+            trade_direction,
+            destination_amount,
+            self.reserves,
+            self.fee_config,
+        )?;
+        self.settle_swap(trade_direction, &result)?;
+        Some(result)
+    }
+
+    // The protocol fee is set aside from the reserves; the destination
+    // share is already excluded from the new destination amount
+    fn settle_swap(&mut self, trade_direction: TradeDirection, result: &SwapResult) -> Option<()> {
+        let reserves = Reserves::from_source_and_destination(
+            trade_direction,
+            result
+                .new_swap_source_amount
+                .checked_sub(result.protocol_fee)?,
+            result.new_swap_destination_amount,
+        );
+        let fees = result.protocol_fees(trade_direction);
+        let protocol_fees = Reserves::new(
+            self.protocol_fees.token_0.checked_add(fees.token_0)?,
+            self.protocol_fees.token_1.checked_add(fees.token_1)?,
+        );
+        let before = *self;
+        self.reserves = reserves;
+        self.protocol_fees = protocol_fees;
+        postconditions::invariant_non_decreasing(before.reserves, self.reserves);
+        postconditions::fees_conserved(
+            before.protocol_fees,
+            self.protocol_fees,
+            fees,
+            Reserves::default(),
+        );
+        self.check_vault_deltas(
+            &before,
+            Reserves::from_source_and_destination(
+                trade_direction,
+                result.source_amount_swapped,
+                0,
+            ),
+            Reserves::from_source_and_destination(
+                trade_direction,
+                0,
+                result.destination_amount_swapped,
+            ),
+        );
+        Some(())
+    }
+
+    // Debug-build check that the books moved from `before` by exactly the
+    // tokens transferred into and out of the vaults
+    fn check_vault_deltas(&self, before: &Self, deposited: Reserves, withdrawn: Reserves) {
+        postconditions::reserves_match_vault_deltas(
+            before.reserves,
+            before.protocol_fees,
+            self.reserves,
+            self.protocol_fees,
+            deposited,
+            withdrawn,
+        );
+    }
+
+    /// Mint `lp_amount` LP tokens against deposited tokens, rounded up in
+    /// the pool's favor.
+    ///
+    /// Returns `None` if the pool has no LP supply to price the deposit
+    /// against.
+    pub fn apply_deposit(&mut self, lp_amount: LpAmount) -> Option<TradingTokenResult> {
+        let result = self.convert_lp_tokens(lp_amount, RoundDirection::Ceiling)?;
+        self.settle_deposit(lp_amount, &result)?;
+        Some(result)
+    }
+
+    /// Deposit up to `maximum` of each token in proportion to the reserves,
+    /// minting as many LP tokens as the scarcer side allows.
+    ///
+    /// Only the tokens taken enter the pool; `unused_token_0` and
+    /// `unused_token_1` are left with the depositor. Returns `None` if the
+    /// deposit cannot mint a single LP token.
+    pub fn apply_deposit_tokens(&mut self, maximum: Reserves) -> Option<DepositResult> {
+        let result = CurveCalculator::deposit_trading_tokens(
+            maximum.token_0.get(),
+            maximum.token_1.get(),
+            self.lp_supply.get(),
+            self.reserves.token_0.get(),
+            self.reserves.token_1.get(),
+        )?;
+        self.settle_deposit(LpAmount(result.lp_token_amount), &result.trading_tokens)?;
+        Some(result)
+    }
+
+    fn settle_deposit(&mut self, lp_amount: LpAmount, result: &TradingTokenResult) -> Option<()> {
+        let reserves = Reserves::new(
+            self.reserves
+                .token_0
+                .checked_add(Token0Amount(result.token_0_amount))?,
+            self.reserves
+                .token_1
+                .checked_add(Token1Amount(result.token_1_amount))?,
+        );
+        let before = *self;
+        self.lp_supply = self.lp_supply.checked_add(lp_amount)?;
+        self.reserves = reserves;
+        self.check_vault_deltas(&before, transferred(result), Reserves::default());
+        Some(())
+    }
+
+    /// Burn `lp_amount` LP tokens for withdrawn tokens, rounded down in the
+    /// pool's favor.
+    ///
+    /// Returns `None` if `lp_amount` exceeds the LP supply.
+    pub fn apply_withdraw(&mut self, lp_amount: LpAmount) -> Option<TradingTokenResult> {
+        let lp_supply = self.lp_supply.checked_sub(lp_amount)?;
+        let result = self.convert_lp_tokens(lp_amount, RoundDirection::Floor)?;
+        let reserves = Reserves::new(
+            self.reserves
+                .token_0
+                .checked_sub(Token0Amount(result.token_0_amount))?,
+            self.reserves
+                .token_1
+                .checked_sub(Token1Amount(result.token_1_amount))?,
+        );
+        let before = *self;
+        self.lp_supply = lp_supply;
+        self.reserves = reserves;
+        self.check_vault_deltas(&before, Reserves::default(), transferred(&result));
+        Some(result)
+    }
+
+    fn convert_lp_tokens(
+        &self,
+        lp_amount: LpAmount,
+        round_direction: RoundDirection,
+    ) -> Option<TradingTokenResult> {
+        CurveCalculator::lp_tokens_to_trading_tokens(
+            lp_amount.get(),
+            self.lp_supply.get(),
+            self.reserves.token_0.get(),
+            self.reserves.token_1.get(),
+            round_direction,
+        )
+    }
+}
+
+// Tokens moved by a deposit or withdrawal
+fn transferred(result: &TradingTokenResult) -> Reserves {
+    Reserves::new(
+        Token0Amount(result.token_0_amount),
+        Token1Amount(result.token_1_amount),
+    )
+}
+
+// The parts of a pool operations change
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Books {
+    reserves: Reserves,
+    lp_supply: LpAmount,
+    protocol_fees: Reserves,
+}
+
+impl From<&Pool> for Books {
+    fn from(pool: &Pool) -> Self {
+        Self {
+            reserves: pool.reserves,
+            lp_supply: pool.lp_supply,
+            protocol_fees: pool.protocol_fees,
+        }
+    }
+}
+
+/// Reverts an operation on a `Pool`, see `Pool::undo`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Undo {
+    before: Books,
+    after: Books,
+}
+
+impl From<PoolState> for Pool {
+    fn from(state: PoolState) -> Self {
+        Self {
+            reserves: state.reserves(),
+            lp_supply: LpAmount::from(state.lp_supply),
+            fee_config: state.fee_config,
+            protocol_fees: Reserves::new(
+                Token0Amount::from(state.protocol_fees_token_0),
+                Token1Amount::from(state.protocol_fees_token_1),
+            ),
+        }
+    }
+}
+
+/// Serializable state of a simulated pool, for audit reconciliation and
+/// regression fixtures
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct PoolSnapshot {
+    pub token_0_reserve: Token0Amount,
+    pub token_1_reserve: Token1Amount,
+    pub lp_supply: LpAmount,
+    pub fee_config: FeeConfig,
+    pub protocol_fees_token_0: Token0Amount,
+    pub protocol_fees_token_1: Token1Amount,
+}
+
+impl From<&Pool> for PoolSnapshot {
+    fn from(pool: &Pool) -> Self {
+        Self {
+            token_0_reserve: pool.reserves.token_0,
+            token_1_reserve: pool.reserves.token_1,
+            lp_supply: pool.lp_supply,
+            fee_config: pool.fee_config,
+            protocol_fees_token_0: pool.protocol_fees.token_0,
+            protocol_fees_token_1: pool.protocol_fees.token_1,
+        }
+    }
+}
+
+impl From<&PoolSnapshot> for Pool {
+    fn from(snapshot: &PoolSnapshot) -> Self {
+        Self {
+            reserves: Reserves::new(snapshot.token_0_reserve, snapshot.token_1_reserve),
+            lp_supply: snapshot.lp_supply,
+            fee_config: snapshot.fee_config,
+            protocol_fees: Reserves::new(
+                snapshot.protocol_fees_token_0,
+                snapshot.protocol_fees_token_1,
+            ),
+        }
+    }
+}
+
+/// A recorded pool operation
+#[derive(Clone, Copy, Debug, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub enum Action {
+    /// Swap exactly `source_amount` in `trade_direction`
+    SwapExactIn {
+        trade_direction: TradeDirection,
+        source_amount: u128,
+    },
+    /// Swap for exactly `destination_amount` in `trade_direction`
+    SwapExactOut {
+        trade_direction: TradeDirection,
+        destination_amount: u128,
+    },
+    /// Deposit for `lp_amount` LP tokens
+    Deposit { lp_amount: LpAmount },
+    /// Withdraw by burning `lp_amount` LP tokens
+    Withdraw { lp_amount: LpAmount },
+}
+
+impl Pool {
+    /// Apply a recorded action, returning whether it succeeded
+    pub fn apply(&mut self, action: Action) -> bool {
+        match action {
+            Action::SwapExactIn {
+                trade_direction,
+                source_amount,
+            } => self.apply_swap(trade_direction, source_amount).is_some(),
+            Action::SwapExactOut {
+                trade_direction,
+                destination_amount,
+            } => self
+                .apply_swap_exact_out(trade_direction, destination_amount)
+                .is_some(),
+            Action::Deposit { lp_amount } => self.apply_deposit(lp_amount).is_some(),
+            Action::Withdraw { lp_amount } => self.apply_withdraw(lp_amount).is_some(),
+        }
+    }
+}
+
+/// Why a recorded sequence failed to replay
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplayError {
+    /// The action at this index failed to apply
+    ActionFailed(usize),
+    /// Every action applied, but the final state differs from the recording
+    StateMismatch,
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::ActionFailed(index) => write!(f, "action {index} failed to apply"),
+            ReplayError::StateMismatch => f.write_str("final state does not match the recording"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ReplayError {}
+
+/// Re-execute `actions` on the pool in `snapshot`, returning the final
+/// state.
+pub fn replay(snapshot: &PoolSnapshot, actions: &[Action]) -> Result<PoolSnapshot, ReplayError> {
+    let mut pool = Pool::from(snapshot);
+    for (index, action) in actions.iter().enumerate() {
+        if !pool.apply(*action) {
+            return Err(ReplayError::ActionFailed(index));
+        }
+    }
+    Ok(PoolSnapshot::from(&pool))
+}
+
+/// Replay `actions` from `snapshot` and check that they end in `expected`,
+/// eg. the pool state recorded after the actions ran on chain.
+pub fn verify_replay(
+    snapshot: &PoolSnapshot,
+    actions: &[Action],
+    expected: &PoolSnapshot,
+) -> Result<(), ReplayError> {
+    if replay(snapshot, actions)? == *expected {
+        Ok(())
+    } else {
+        Err(ReplayError::StateMismatch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::curve::{
+            fees::{FeeRate, ProtocolFeeMode},
+            swap_params::SwapParams,
+        },
+        proptest::prelude::*,
+    };
+
+    fn pool() -> Pool {
+        Pool::new(
+            Reserves::new(Token0Amount(1_000_000), Token1Amount(4_000_000)),
+            LpAmount(2_000_000),
+            FeeConfig::new(
+                FeeRate::from_bps(25).unwrap(),
+                FeeRate::from_percent(12).unwrap(),
+            ),
+        )
+    }
+
+    #[test]
+    fn swaps_update_reserves_and_protocol_fees() {
+        let mut pool = pool();
+        let (result, _) = pool
+            .apply_swap(TradeDirection::OneForZero, 100_000)
+            .unwrap();
+        assert_eq!(
+            result,
+            CurveCalculator::swap_base_input(
+                &SwapParams::from_raw_parts(100_000, 4_000_000, 1_000_000, 2_500, 120_000).unwrap()
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            pool.reserves,
+            Reserves::new(
+                Token0Amount(1_000_000 - result.destination_amount_swapped),
+                Token1Amount(4_100_000 - result.protocol_fee),
+            )
+        );
+        assert_eq!(
+            pool.protocol_fees,
+            Reserves::new(Token0Amount(0), Token1Amount(result.protocol_fee))
+        );
+
+        let result = pool
+            .apply_swap_exact_out(TradeDirection::ZeroForOne, 50_000)
+            .unwrap();
+        assert_eq!(result.destination_amount_swapped, 50_000);
+        assert_eq!(
+            pool.protocol_fees.token_0,
+            Token0Amount(result.protocol_fee)
+        );
+    }
+
+    #[test]
+    fn token_deposits_leave_unused_tokens_with_the_depositor() {
+        let mut pool = pool();
+        // 10_000 token 0 is worth 20_000 LP tokens and 40_000 token 1
+        let result = pool
+            .apply_deposit_tokens(Reserves::new(Token0Amount(10_000), Token1Amount(50_000)))
+            .unwrap();
+        assert_eq!(result.lp_token_amount, 20_000);
+        assert_eq!((result.unused_token_0, result.unused_token_1), (0, 10_000));
+        assert_eq!(
+            pool.reserves,
+            Reserves::new(Token0Amount(1_010_000), Token1Amount(4_040_000))
+        );
+        assert_eq!(pool.lp_supply, LpAmount(2_020_000));
+
+        // emptied pools take a fresh bootstrap deposit
+        pool.apply_withdraw(pool.lp_supply).unwrap();
+        assert_eq!(pool.reserves, Reserves::default());
+        let result = pool
+            .apply_deposit_tokens(Reserves::new(Token0Amount(4), Token1Amount(9)))
+            .unwrap();
+        assert_eq!(result.lp_token_amount, 6);
+        assert_eq!(pool.lp_supply, LpAmount(6));
+    }
+
+    #[test]
+    fn swaps_undo_in_reverse_order() {
+        let mut pool = pool();
+        let (_, first) = pool.apply_swap(TradeDirection::ZeroForOne, 50_000).unwrap();
+        let after_first = pool;
+        let (_, second) = pool.apply_swap(TradeDirection::OneForZero, 80_000).unwrap();
+        let after_second = pool;
+        // the first swap cannot be undone while the second stands
+        assert_eq!(pool.undo(first), None);
+        assert_eq!(pool, after_second);
+        assert_eq!(pool.undo(second), Some(()));
+        assert_eq!(pool, after_first);
+        // nor can an undo be applied twice
+        assert_eq!(pool.undo(second), None);
+        assert_eq!(pool.undo(first), Some(()));
+        assert_eq!(pool, self::pool());
+    }
+
+    #[test]
+    fn failed_operations_leave_the_pool_untouched() {
+        let mut pool = pool();
+        assert_eq!(
+            pool.apply_swap_exact_out(TradeDirection::ZeroForOne, 4_000_000),
+            None
+        );
+        assert_eq!(pool.apply_withdraw(LpAmount(2_000_001)), None);
+        assert_eq!(pool, self::pool());
+        let mut empty = Pool::default();
+        assert_eq!(empty.apply_deposit(LpAmount(1)), None);
+        assert_eq!(
+            empty.apply_deposit_tokens(Reserves::new(Token0Amount(0), Token1Amount(1))),
+            None
+        );
+        assert_eq!(empty, Pool::default());
+    }
+
+    #[test]
+    fn pool_from_state() {
+        let state = PoolState {
+            token_0_reserve: 1,
+            token_1_reserve: 2,
+            lp_supply: 3,
+            protocol_fees_token_0: 4,
+            protocol_fees_token_1: 5,
+            ..PoolState::default()
+        };
+        let pool = Pool::from(state);
+        assert_eq!(
+            pool.reserves,
+            Reserves::new(Token0Amount(1), Token1Amount(2))
+        );
+        assert_eq!(pool.lp_supply, LpAmount(3));
+        assert_eq!(
+            pool.protocol_fees,
+            Reserves::new(Token0Amount(4), Token1Amount(5))
+        );
+    }
+
+    #[test]
+    fn replay_recorded_actions() {
+        let actions = [
+            Action::SwapExactIn {
+                trade_direction: TradeDirection::ZeroForOne,
+                source_amount: 10_000,
+            },
+            Action::Deposit {
+                lp_amount: LpAmount(1_000),
+            },
+            Action::SwapExactOut {
+                trade_direction: TradeDirection::OneForZero,
+                destination_amount: 5_000,
+            },
+            Action::Withdraw {
+                lp_amount: LpAmount(500_000),
+            },
+        ];
+        let mut recorded = pool();
+        let initial = PoolSnapshot::from(&recorded);
+        for action in actions {
+            assert!(recorded.apply(action));
+        }
+        let expected = PoolSnapshot::from(&recorded);
+        assert_eq!(replay(&initial, &actions), Ok(expected));
+        assert_eq!(verify_replay(&initial, &actions, &expected), Ok(()));
+        assert_eq!(
+            verify_replay(&initial, &actions[..3], &expected),
+            Err(ReplayError::StateMismatch)
+        );
+        let failing = [
+            actions[0],
+            Action::Withdraw {
+                lp_amount: LpAmount(u128::MAX),
+            },
+        ];
+        assert_eq!(
+            replay(&initial, &failing),
+            Err(ReplayError::ActionFailed(1))
+        );
+
+        // fixtures round trip through borsh
+        let bytes = borsh::to_vec(&(initial, actions.to_vec(), expected)).unwrap();
+        let (initial, actions, expected) =
+            <(PoolSnapshot, Vec<Action>, PoolSnapshot)>::try_from_slice(&bytes).unwrap();
+        assert_eq!(verify_replay(&initial, &actions, &expected), Ok(()));
+    }
+
+    fn split_pool() -> Pool {
+        let mut pool = pool();
+        pool.fee_config = pool
+            .fee_config
+            .with_protocol_fee_mode(ProtocolFeeMode::Split);
+        pool
+    }
+
+    // Vault balances: the reserves plus the uncollected protocol fees
+    fn balances(pool: &Pool) -> (u128, u128) {
+        (
+            pool.reserves.token_0.get() + pool.protocol_fees.token_0.get(),
+            pool.reserves.token_1.get() + pool.protocol_fees.token_1.get(),
+        )
+    }
+
+    #[test]
+    fn split_protocol_fee_accrues_both_tokens() {
+        let mut input = pool();
+        let mut split = split_pool();
+        let (expected, _) = input
+            .apply_swap(TradeDirection::OneForZero, 100_000)
+            .unwrap();
+        let (result, _) = split
+            .apply_swap(TradeDirection::OneForZero, 100_000)
+            .unwrap();
+        // the trader is charged the same either way
+        assert_eq!(result.source_amount_swapped, expected.source_amount_swapped);
+        assert_eq!(
+            result.destination_amount_swapped,
+            expected.destination_amount_swapped
+        );
+        assert_eq!(result.trade_fee, expected.trade_fee);
+        // 12% of a 250 token fee is 30, half of which is sold for token 0
+        assert_eq!(expected.protocol_fee, 30);
+        assert_eq!(result.protocol_fee, 15);
+        assert!(result.destination_protocol_fee > 0);
+        assert_eq!(
+            split.protocol_fees,
+            Reserves::new(
+                Token0Amount(result.destination_protocol_fee),
+                Token1Amount(15)
+            )
+        );
+        assert_eq!(
+            split.protocol_fees,
+            result.protocol_fees(TradeDirection::OneForZero)
+        );
+        // fees too small to halve stay in the input token
+        let (result, _) = split.apply_swap(TradeDirection::ZeroForOne, 10).unwrap();
+        assert_eq!(result.destination_protocol_fee, 0);
+    }
+
+    proptest! {
+        #[test]
+        fn undoing_every_swap_restores_the_pool(
+            swaps in prop::collection::vec((any::<bool>(), 1..500_000u128), 1..20),
+        ) {
+            let mut pool = pool();
+            let mut undos = Vec::new();
+            for (zero_for_one, amount) in swaps {
+                let trade_direction = if zero_for_one {
+                    TradeDirection::ZeroForOne
+                } else {
+                    TradeDirection::OneForZero
+                };
+                let before = pool;
+                match pool.apply_swap(trade_direction, amount) {
+                    Some((_, undo)) => undos.push((before, undo)),
+                    None => prop_assert_eq!(pool, before),
+                }
+            }
+            while let Some((before, undo)) = undos.pop() {
+                prop_assert_eq!(pool.undo(undo), Some(()));
+                prop_assert_eq!(pool, before);
+            }
+        }
+
+        #[test]
+        fn split_protocol_fee_conserves_tokens(
+            amount in 1..500_000u128,
+            zero_for_one: bool,
+            exact_in: bool,
+        ) {
+            let trade_direction = if zero_for_one {
+                TradeDirection::ZeroForOne
+            } else {
+                TradeDirection::OneForZero
+            };
+            let swap = |pool: &mut Pool| if exact_in {
+                pool.apply_swap(trade_direction, amount).map(|(result, _)| result)
+            } else {
+                pool.apply_swap_exact_out(trade_direction, amount)
+            };
+            let mut input = pool();
+            let mut split = split_pool();
+            let (before_0, before_1) = balances(&split);
+            let invariant_before = CurveCalculator::pool_value(split.reserves).invariant;
+            let expected = swap(&mut input).unwrap();
+            let result = swap(&mut split).unwrap();
+            let (after_0, after_1) = balances(&split);
+            let (paid_in, paid_out) = match trade_direction {
+                TradeDirection::ZeroForOne => (after_0 - before_0, before_1 - after_1),
+                TradeDirection::OneForZero => (after_1 - before_1, before_0 - after_0),
+            };
+            // the vaults only change by what the trader pays and receives
+            prop_assert_eq!(paid_in, result.source_amount_swapped);
+            prop_assert_eq!(paid_out, result.destination_amount_swapped);
+            prop_assert_eq!(result.source_amount_swapped, expected.source_amount_swapped);
+            prop_assert_eq!(
+                result.destination_amount_swapped,
+                expected.destination_amount_swapped
+            );
+            // the protocol keeps at least half its fee in the input token
+            prop_assert_eq!(
+                result.protocol_fee,
+                expected.protocol_fee - expected.protocol_fee / 2
+            );
+            prop_assert!(
+                CurveCalculator::pool_value(split.reserves).invariant
+                    >= CurveCalculator::pool_value(input.reserves).invariant
+            );
+            prop_assert!(CurveCalculator::pool_value(split.reserves).invariant >= invariant_before);
+        }
+
+        #[test]
+        fn deposit_then_withdraw_never_loses_pool_value(
+            lp_amount in 1..10_000_000u128,
+            source_amount in 1..1_000_000u128,
+        ) {
+            let mut pool = pool();
+            pool.apply_swap(TradeDirection::ZeroForOne, source_amount).unwrap();
+            let before = pool;
+            let deposit = pool.apply_deposit(LpAmount(lp_amount)).unwrap();
+            let withdrawal = pool.apply_withdraw(LpAmount(lp_amount)).unwrap();
+            prop_assert!(withdrawal.token_0_amount <= deposit.token_0_amount);
+            prop_assert!(withdrawal.token_1_amount <= deposit.token_1_amount);
+            prop_assert_eq!(pool.lp_supply, before.lp_supply);
+            prop_assert!(pool.reserves.token_0 >= before.reserves.token_0);
+            prop_assert!(pool.reserves.token_1 >= before.reserves.token_1);
+        }
+    }
+}
@@ -0,0 +1,250 @@
+//! Monte Carlo fee tuning
+//!
+//! Runs randomized trade flow against the same pool under several fee
+//! configurations and reports the distribution of LP returns, so pool
+//! creators can compare fee rates with the production math. Each step the
+//! external price takes a random walk, noise traders swap random amounts in
+//! random directions, and an arbitrageur trades the pool back to the
+//! external price. LP returns are measured against holding the initial
+//! reserves, both valued at the final external price.
+//!
+//! Every fee configuration sees the same random paths, so differences
+//! between them come from the fees alone, and a seed reproduces a run
+//! exactly.
+use crate::{
+    analytics::optimal_trade_to_price,
+    curve::{
+        amount::{LpAmount, Reserves},
+        calculator::{RoundDirection, TradeDirection},
+        fees::FeeConfig,
+    },
+    sim::Pool,
+    utils::PriceQ64,
+};
+
+/// Denominator for the basis point settings and results
+pub const MONTE_CARLO_BPS_DENOMINATOR: u128 = 10_000;
+
+/// Market model shared by every fee configuration of a run
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MonteCarloConfig {
+    /// Initial reserves; the initial external price is the pool's
+    pub reserves: Reserves,
+    /// Number of independent paths per fee configuration
+    pub paths: u32,
+    /// Steps per path
+    pub steps: u32,
+    /// Largest move of the external price per step, in basis points, at
+    /// most 10_000; each move is uniform in `±volatility_bps`
+    pub volatility_bps: u16,
+    /// Noise trades per step
+    pub noise_trades_per_step: u8,
+    /// Largest noise trade, in basis points of the source reserve; sizes
+    /// are uniform up to it
+    pub max_noise_trade_bps: u16,
+    /// Whether an arbitrageur aligns the pool with the external price after
+    /// each step
+    pub arbitrage: bool,
+    /// Seed of the random paths
+    pub seed: u64,
+}
+
+/// LP returns of one fee configuration over all paths, in basis points of
+/// the hold value, rounded toward zero
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReturnDistribution {
+    /// The fee configuration simulated
+    pub fee_config: FeeConfig,
+    /// Mean return
+    pub mean_bps: i128,
+    /// Worst return
+    pub min_bps: i128,
+    /// 5th percentile return
+    pub p5_bps: i128,
+    /// Median return
+    pub median_bps: i128,
+    /// 95th percentile return
+    pub p95_bps: i128,
+    /// Best return
+    pub max_bps: i128,
+}
+
+// SplitMix64: small, fast and good enough for simulation, and keeps runs
+// reproducible without pulling in a random number crate
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in `0..=max`, up to a negligible modulo bias
+    fn up_to(&mut self, max: u64) -> u64 {
+        match max.checked_add(1) {
+            Some(bound) => self.next_u64() % bound,
+            None => self.next_u64(),
+        }
+    }
+}
+
+// Value of `reserves` in token 1 at `price`
+fn value(reserves: Reserves, price: PriceQ64) -> Option<u128> {
+    price
+        .mul_amount(reserves.token_0.get(), RoundDirection::Floor)?
+        .checked_add(reserves.token_1.get())
+}
+
+fn run_path(config: &MonteCarloConfig, fee_config: FeeConfig, seed: u64) -> Option<i128> {
+    let mut rng = SplitMix64(seed);
+    let mut pool = Pool::new(config.reserves, LpAmount(1), fee_config);
+    let (reserve_0, reserve_1) = (config.reserves.token_0.get(), config.reserves.token_1.get());
+    let mut price = PriceQ64::from_reserves(reserve_0, reserve_1)?;
+    let volatility = u64::from(config.volatility_bps);
+    for _ in 0..config.steps {
+        let change = rng.up_to(2 * volatility) as u128;
+        let factor = PriceQ64::from_ratio(
+            MONTE_CARLO_BPS_DENOMINATOR + change - u128::from(config.volatility_bps),
+            MONTE_CARLO_BPS_DENOMINATOR,
+        )?;
+        // a price that would vanish or overflow stays where it is
+        if let Some(moved) = price
+            .checked_mul(factor)
+            .filter(|moved| *moved != PriceQ64::ZERO)
+        {
+            price = moved;
+        }
+
+        for _ in 0..config.noise_trades_per_step {
+            let trade_direction = if rng.next_u64() & 1 == 0 {
+                TradeDirection::ZeroForOne
+            } else {
+                TradeDirection::OneForZero
+            };
+            let (source, _) = pool.reserves.source_and_destination(trade_direction);
+            let size_bps = rng.up_to(config.max_noise_trade_bps.into());
+            let amount = source.checked_mul(size_bps.into())? / MONTE_CARLO_BPS_DENOMINATOR;
+            if amount > 0 {
+                // a trade the pool cannot fill is simply not made
+                let _ = pool.apply_swap(trade_direction, amount);
+            }
+        }
+        if config.arbitrage
+            && let Some(trade) = optimal_trade_to_price(pool.reserves, fee_config, price)
+        {
+            let _ = pool.apply_swap(trade.trade_direction, trade.source_amount);
+        }
+    }
+
+    let hold_value = value(config.reserves, price)?;
+    let pool_value = value(pool.reserves, price)?;
+    if hold_value == 0 {
+        return None;
+    }
+    let gain = pool_value as i128 - hold_value as i128;
+    Some(gain * MONTE_CARLO_BPS_DENOMINATOR as i128 / hold_value as i128)
+}
+
+/// Simulate `config` under each of `fee_configs`, in order.
+///
+/// Returns `None` if the pool is empty, there are no paths, the volatility
+/// exceeds 100%, or a value overflows.
+pub fn run(
+    config: &MonteCarloConfig,
+    fee_configs: &[FeeConfig],
+) -> Option<Vec<ReturnDistribution>> {
+    if config.paths == 0 || u128::from(config.volatility_bps) > MONTE_CARLO_BPS_DENOMINATOR {
+        return None;
+    }
+    let mut seeds = SplitMix64(config.seed);
+    let seeds: Vec<u64> = (0..config.paths).map(|_| seeds.next_u64()).collect();
+    fee_configs
+        .iter()
+        .map(|&fee_config| {
+            let mut returns = seeds
+                .iter()
+                .map(|&seed| run_path(config, fee_config, seed))
+                .collect::<Option<Vec<_>>>()?;
+            returns.sort_unstable();
+            let percentile = |percent: usize| returns[(returns.len() - 1) * percent / 100];
+            Some(ReturnDistribution {
+                fee_config,
+                mean_bps: returns.iter().sum::<i128>() / returns.len() as i128,
+                min_bps: percentile(0),
+                p5_bps: percentile(5),
+                median_bps: percentile(50),
+                p95_bps: percentile(95),
+                max_bps: percentile(100),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::curve::{
+            amount::{Token0Amount, Token1Amount},
+            fees::FeeRate,
+        },
+    };
+
+    fn config() -> MonteCarloConfig {
+        MonteCarloConfig {
+            reserves: Reserves::new(Token0Amount(1_000_000_000), Token1Amount(2_000_000_000)),
+            paths: 50,
+            steps: 100,
+            volatility_bps: 0,
+            noise_trades_per_step: 2,
+            max_noise_trade_bps: 50,
+            arbitrage: true,
+            seed: 7,
+        }
+    }
+
+    fn fee_tiers() -> Vec<FeeConfig> {
+        [0, 5, 30]
+            .into_iter()
+            .map(|bps| FeeConfig::new(FeeRate::from_bps(bps).unwrap(), FeeRate::ZERO))
+            .collect()
+    }
+
+    #[test]
+    fn fees_pay_lps_for_noise_flow() {
+        let results = run(&config(), &fee_tiers()).unwrap();
+        assert_eq!(results.len(), 3);
+        // without price moves, noise flow only ever pays the LPs their fees
+        assert!(results[0].mean_bps.abs() <= 1);
+        assert!(results[1].mean_bps > results[0].mean_bps);
+        assert!(results[2].mean_bps > results[1].mean_bps);
+        for result in &results {
+            assert!(result.min_bps <= result.p5_bps);
+            assert!(result.p5_bps <= result.median_bps);
+            assert!(result.median_bps <= result.p95_bps);
+            assert!(result.p95_bps <= result.max_bps);
+        }
+    }
+
+    #[test]
+    fn volatility_without_noise_costs_lps() {
+        let config = MonteCarloConfig {
+            volatility_bps: 200,
+            noise_trades_per_step: 0,
+            ..config()
+        };
+        let results = run(&config, &fee_tiers()).unwrap();
+        // arbitrage against a fee-less pool is pure impermanent loss
+        assert!(results[0].max_bps <= 0);
+        assert!(results[0].mean_bps < 0);
+        // the same seed reproduces the run
+        assert_eq!(run(&config, &fee_tiers()).unwrap(), results);
+        assert_eq!(
+            run(&MonteCarloConfig { paths: 0, ..config }, &fee_tiers()),
+            None
+        );
+    }
+}
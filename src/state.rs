@@ -0,0 +1,617 @@
+//! Pool state
+//!
+//! The account layout a program built on this crate persists for each pool.
+//! Everything is borsh encoded with a fixed size, so the layout tests below
+//! pin the wire format shared by the on-chain program and off-chain clients.
+use {
+    crate::{
+        curve::{
+            amount::{Reserves, Token0Amount, Token1Amount},
+            buyback::{BuybackConfig, FeeCollection},
+            calculator::{CurveCalculator, DepositResult, RoundDirection, TradingTokenResult},
+            fees::FeeConfig,
+            params::CurveParams,
+            registry::{CurveRegistry, SwapCurve},
+            sync::{SurplusPolicy, SyncResult},
+        },
+        fee_tier::FeeTier,
+        postconditions,
+    },
+    alloc::boxed::Box,
+    borsh::{BorshDeserialize, BorshSerialize},
+};
+
+/// The bonding curve a pool trades on, identified by its `CurveRegistry` id
+///
+/// Encoded as the id alone, so ids without a built-in curve decode as
+/// `Custom` and are resolved through a registry.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CurveType {
+    /// Uniswap-style constant product curve, `x * y = k`; takes no
+    /// parameters
+    #[default]
+    ConstantProduct,
+    /// StableSwap curve; takes `CurveParams::Stable`
+    Stable,
+    /// Balancer-style weighted curve; takes `CurveParams::Weighted`
+    Weighted,
+    /// Hard-bounded curve for pegged tokens; takes `CurveParams::Pegged`
+    Pegged,
+    /// Oracle-priced PMM curve; takes `CurveParams::Pmm`
+    Pmm,
+    /// Constant product curve over virtual reserves; takes
+    /// `CurveParams::Leveraged`
+    Leveraged,
+    /// Repegging curve; takes `CurveParams::CryptoSwap`
+    CryptoSwap,
+    /// A curve registered with `CurveRegistry` under an id no built-in curve
+    /// uses; its parameters are opaque to this crate
+    Custom(u8),
+}
+
+impl CurveType {
+    /// The curve type with registry id `id`
+    pub const fn from_id(id: u8) -> Self {
+        match id {
+            0 => Self::ConstantProduct,
+            1 => Self::Stable,
+            2 => Self::Weighted,
+            3 => Self::Pegged,
+            4 => Self::Pmm,
+            5 => Self::Leveraged,
+            6 => Self::CryptoSwap,
+            id => Self::Custom(id),
+        }
+    }
+
+    /// The id the curve is registered and encoded under
+    pub const fn id(self) -> u8 {
+        match self {
+            Self::ConstantProduct => 0,
+            Self::Stable => 1,
+            Self::Weighted => 2,
+            Self::Pegged => 3,
+            Self::Pmm => 4,
+            Self::Leveraged => 5,
+            Self::CryptoSwap => 6,
+            Self::Custom(id) => id,
+        }
+    }
+}
+
+impl BorshSerialize for CurveType {
+    fn serialize<W: borsh::io::Write>(&self, writer: &mut W) -> borsh::io::Result<()> {
+        self.id().serialize(writer)
+    }
+}
+
+impl BorshDeserialize for CurveType {
+    fn deserialize_reader<R: borsh::io::Read>(reader: &mut R) -> borsh::io::Result<Self> {
+        u8::deserialize_reader(reader).map(Self::from_id)
+    }
+}
+
+/// Reserves, LP supply and configuration of a pool
+#[derive(Clone, Copy, Debug, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct PoolState {
+    /// Curve used to price swaps
+    pub curve_type: CurveType,
+    /// Fee rates charged on swaps
+    pub fee_config: FeeConfig,
+    /// Amount of token 0 backing the pool, excluding protocol fees
+    pub token_0_reserve: u64,
+    /// Amount of token 1 backing the pool, excluding protocol fees
+    pub token_1_reserve: u64,
+    /// Outstanding LP tokens
+    pub lp_supply: u64,
+    /// Protocol fees accrued in token 0 and not yet collected
+    pub protocol_fees_token_0: u64,
+    /// Protocol fees accrued in token 1 and not yet collected
+    pub protocol_fees_token_1: u64,
+    /// Whether vault surplus found by `sync` goes to LPs or a fee vault
+    pub surplus_policy: SurplusPolicy,
+    /// Parameters configuring `curve_type`, packed in the `CurveParams`
+    /// layout
+    pub curve_params: [u8; CurveParams::LEN],
+    /// Share of protocol fees routed to buyback-and-burn on collection
+    pub buyback: BuybackConfig,
+}
+
+impl Default for PoolState {
+    /// An empty constant product pool without fees
+    fn default() -> Self {
+        Self {
+            curve_type: CurveType::default(),
+            fee_config: FeeConfig::default(),
+            token_0_reserve: 0,
+            token_1_reserve: 0,
+            lp_supply: 0,
+            protocol_fees_token_0: 0,
+            protocol_fees_token_1: 0,
+            surplus_policy: SurplusPolicy::default(),
+            curve_params: CurveParams::default().pack(),
+            buyback: BuybackConfig::default(),
+        }
+    }
+}
+
+impl PoolState {
+    /// Size of the borsh encoded state in bytes
+    pub const LEN: usize = 1 + (2 * 8 + 2) + 5 * 8 + 1 + CurveParams::LEN + (8 + 1);
+
+    /// The pool's reserves, excluding protocol fees
+    pub fn reserves(&self) -> Reserves {
+        Reserves::new(
+            Token0Amount::from(self.token_0_reserve),
+            Token1Amount::from(self.token_1_reserve),
+        )
+    }
+
+    /// Protocol fees accrued in each token and not yet collected
+    pub fn protocol_fees(&self) -> Reserves {
+        Reserves::new(
+            Token0Amount::from(self.protocol_fees_token_0),
+            Token1Amount::from(self.protocol_fees_token_1),
+        )
+    }
+
+    /// The configured built-in curve.
+    ///
+    /// Returns `None` if the packed parameters are invalid or configure a
+    /// different curve than `curve_type`.
+    pub fn curve_params(&self) -> Option<CurveParams> {
+        CurveParams::unpack(&self.curve_params)
+            .filter(|params| params.curve_type() == self.curve_type)
+    }
+
+    /// Configure the pool's curve, setting `curve_type` to match and
+    /// keeping the fee tier
+    pub fn set_curve_params(&mut self, params: CurveParams) {
+        self.curve_type = params.curve_type();
+        self.curve_params = params.pack_with_fee_tier(self.fee_tier());
+    }
+
+    /// The fee tier recorded with the curve parameters, if the pool is in
+    /// one and the parameters are in a known version
+    pub fn fee_tier(&self) -> Option<FeeTier> {
+        CurveParams::unpack_header(&self.curve_params)?.1
+    }
+
+    /// Place the pool in `fee_tier`, charging the tier's trade fee
+    pub fn set_fee_tier(&mut self, fee_tier: FeeTier) {
+        self.fee_config.trade_fee_rate = fee_tier.trade_fee_rate();
+        CurveParams::pack_fee_tier(&mut self.curve_params, Some(fee_tier));
+    }
+
+    /// The configured curve, built-in or custom, decoded through
+    /// `registry`.
+    ///
+    /// Returns `None` if `curve_type` is not registered, its decoder rejects
+    /// the parameters, or they are packed for a different curve.
+    pub fn curve(&self, registry: &CurveRegistry) -> Option<Box<dyn SwapCurve>> {
+        let (curve_type, _, _) = CurveParams::unpack_header(&self.curve_params)?;
+        if curve_type != self.curve_type {
+            return None;
+        }
+        registry.decode(&self.curve_params)
+    }
+
+    /// Configure the pool to trade on `curve_type` with parameter bytes
+    /// `params`, which its registered decoder parses, keeping the fee tier.
+    ///
+    /// Returns `None`, leaving the state unchanged, if `params` is longer
+    /// than `CurveParams::PARAMS_LEN`.
+    pub fn set_curve(&mut self, curve_type: CurveType, params: &[u8]) -> Option<()> {
+        let mut curve_params = CurveParams::pack_raw(curve_type, params)?;
+        CurveParams::pack_fee_tier(&mut curve_params, self.fee_tier());
+        self.curve_params = curve_params;
+        self.curve_type = curve_type;
+        Some(())
+    }
+
+    /// Collect all protocol fees at `now`, running the configured buyback
+    /// against the pool.
+    ///
+    /// The caller transfers `collected` to the treasury and burns `burned`.
+    /// Returns `None`, leaving the state unchanged, if the curve parameters
+    /// are invalid or the buyback fails.
+    pub fn collect_protocol_fees(&mut self, now: u64) -> Option<FeeCollection> {
+        let reserves = self.reserves();
+        let protocol_fees = self.protocol_fees();
+        let collection = self.curve_params()?.collect_protocol_fees(
+            now,
+            reserves,
+            protocol_fees,
+            self.fee_config,
+            self.buyback,
+        )?;
+        // the buyback only moves fees into the reserves, so they fit in u64
+        // as long as the vaults do
+        let token_0_reserve = u64::try_from(collection.reserves.token_0.get()).ok()?;
+        let token_1_reserve = u64::try_from(collection.reserves.token_1.get()).ok()?;
+        let paid_out = collection.paid_out()?;
+        self.token_0_reserve = token_0_reserve;
+        self.token_1_reserve = token_1_reserve;
+        self.protocol_fees_token_0 = 0;
+        self.protocol_fees_token_1 = 0;
+        postconditions::invariant_non_decreasing(reserves, self.reserves());
+        postconditions::reserves_match_vault_deltas(
+            reserves,
+            protocol_fees,
+            self.reserves(),
+            Reserves::default(),
+            Reserves::default(),
+            paid_out,
+        );
+        Some(collection)
+    }
+
+    /// Deposit up to `maximum_token_0_amount` and `maximum_token_1_amount`
+    /// in proportion to the reserves, as
+    /// `CurveCalculator::deposit_trading_tokens` does.
+    ///
+    /// A pool whose LP supply is zero, including one that was fully
+    /// withdrawn, is bootstrapped again with the initial-LP formula. The
+    /// caller transfers only the tokens taken and mints `lp_token_amount`.
+    /// Returns `None`, leaving the state unchanged, if the deposit mints
+    /// nothing or the new reserves or supply overflow u64.
+    pub fn deposit(
+        &mut self,
+        maximum_token_0_amount: u64,
+        maximum_token_1_amount: u64,
+    ) -> Option<DepositResult> {
+        let result = CurveCalculator::deposit_trading_tokens(
+            maximum_token_0_amount.into(),
+            maximum_token_1_amount.into(),
+            self.lp_supply.into(),
+            self.token_0_reserve.into(),
+            self.token_1_reserve.into(),
+        )?;
+        // the tokens taken never exceed the maximums, so they fit in u64
+        let tokens = &result.trading_tokens;
+        let token_0_reserve = self
+            .token_0_reserve
+            .checked_add(u64::try_from(tokens.token_0_amount).ok()?)?;
+        let token_1_reserve = self
+            .token_1_reserve
+            .checked_add(u64::try_from(tokens.token_1_amount).ok()?)?;
+        let lp_supply = self
+            .lp_supply
+            .checked_add(u64::try_from(result.lp_token_amount).ok()?)?;
+        self.token_0_reserve = token_0_reserve;
+        self.token_1_reserve = token_1_reserve;
+        self.lp_supply = lp_supply;
+        Some(result)
+    }
+
+    /// Burn `lp_token_amount` LP tokens for their share of the reserves,
+    /// rounded down in the pool's favor.
+    ///
+    /// Burning the whole supply pays out the reserves exactly and zeroes
+    /// them, leaving only uncollected protocol fees before the pool can be
+    /// closed. Returns `None`, leaving the state unchanged, if
+    /// `lp_token_amount` is zero or exceeds the LP supply.
+    pub fn withdraw(&mut self, lp_token_amount: u64) -> Option<TradingTokenResult> {
+        let lp_supply = self.lp_supply.checked_sub(lp_token_amount)?;
+        let result = CurveCalculator::lp_tokens_to_trading_tokens(
+            lp_token_amount.into(),
+            self.lp_supply.into(),
+            self.token_0_reserve.into(),
+            self.token_1_reserve.into(),
+            RoundDirection::Floor,
+        )?;
+        // the payout never exceeds the reserves, so it fits in u64
+        let token_0_reserve = self
+            .token_0_reserve
+            .checked_sub(u64::try_from(result.token_0_amount).ok()?)?;
+        let token_1_reserve = self
+            .token_1_reserve
+            .checked_sub(u64::try_from(result.token_1_amount).ok()?)?;
+        self.lp_supply = lp_supply;
+        self.token_0_reserve = token_0_reserve;
+        self.token_1_reserve = token_1_reserve;
+        Some(result)
+    }
+
+    /// Whether the pool holds nothing at all: no LP supply, reserves or
+    /// uncollected protocol fees, so its accounts can be closed
+    pub fn is_closable(&self) -> bool {
+        self.lp_supply == 0
+            && self.token_0_reserve == 0
+            && self.token_1_reserve == 0
+            && self.protocol_fees_token_0 == 0
+            && self.protocol_fees_token_1 == 0
+    }
+
+    /// Vault balances above the reserves and protocol fees, for the `Skim`
+    /// instruction to transfer to its recipient.
+    ///
+    /// The state is not modified. Returns `None` if a vault holds less than
+    /// it owes, which `sync_reserves` must reconcile first.
+    pub fn skim(&self, vault_0_balance: u64, vault_1_balance: u64) -> Option<Reserves> {
+        CurveCalculator::skim(
+            self.reserves(),
+            self.protocol_fees(),
+            Reserves::new(
+                Token0Amount::from(vault_0_balance),
+                Token1Amount::from(vault_1_balance),
+            ),
+        )
+    }
+
+    /// Reconcile the reserves with the actual vault balances after
+    /// out-of-band transfers, according to `surplus_policy`. The vaults also
+    /// hold the uncollected protocol fees.
+    ///
+    /// Any skimmed surplus is left in the vaults for the caller to transfer
+    /// to the fee vault. Returns `None`, leaving the state unchanged, if a
+    /// vault holds less than its protocol fees or the policy rejects a
+    /// surplus.
+    pub fn sync_reserves(
+        &mut self,
+        vault_0_balance: u64,
+        vault_1_balance: u64,
+    ) -> Option<SyncResult> {
+        let result = CurveCalculator::sync(
+            self.reserves(),
+            self.protocol_fees(),
+            Reserves::new(
+                Token0Amount::from(vault_0_balance),
+                Token1Amount::from(vault_1_balance),
+            ),
+            self.surplus_policy,
+        )?;
+        // the new reserves never exceed the balances, so they fit in u64
+        self.token_0_reserve = u64::try_from(result.reserves.token_0.get()).ok()?;
+        self.token_1_reserve = u64::try_from(result.reserves.token_1.get()).ok()?;
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::curve::{
+            buyback::BuybackToken,
+            fees::{FeeRate, ProtocolFeeMode, TradeFeeSide},
+            stable::AmpRamp,
+        },
+    };
+
+    #[test]
+    fn pool_state_layout() {
+        let state = PoolState {
+            curve_type: CurveType::ConstantProduct,
+            fee_config: FeeConfig::new(
+                FeeRate::from_bps(25).unwrap(),
+                FeeRate::from_percent(12).unwrap(),
+            )
+            .with_protocol_fee_mode(ProtocolFeeMode::Split)
+            .with_trade_fee_side(TradeFeeSide::Output),
+            token_0_reserve: 1,
+            token_1_reserve: 2,
+            lp_supply: 3,
+            protocol_fees_token_0: 4,
+            protocol_fees_token_1: u64::MAX,
+            surplus_policy: SurplusPolicy::SkimToFeeVault,
+            curve_params: CurveParams::ConstantProduct.pack(),
+            buyback: BuybackConfig {
+                share: FeeRate::from_percent(50).unwrap(),
+                token: BuybackToken::Token1,
+            },
+        };
+        let bytes = borsh::to_vec(&state).unwrap();
+        let mut expected = vec![0u8];
+        expected.extend_from_slice(&2_500u64.to_le_bytes());
+        expected.extend_from_slice(&120_000u64.to_le_bytes());
+        expected.extend_from_slice(&[1, 1]);
+        for value in [1u64, 2, 3, 4, u64::MAX] {
+            expected.extend_from_slice(&value.to_le_bytes());
+        }
+        expected.push(1);
+        // the constant product curve in version 1, outside any fee tier
+        expected.extend_from_slice(&[1, 0, 0]);
+        expected.extend_from_slice(&[0; CurveParams::PARAMS_LEN]);
+        expected.extend_from_slice(&500_000u64.to_le_bytes());
+        expected.push(1);
+        assert_eq!(bytes, expected);
+        assert_eq!(bytes.len(), PoolState::LEN);
+        assert_eq!(PoolState::try_from_slice(&bytes).unwrap(), state);
+    }
+
+    #[test]
+    fn malformed_state_is_rejected() {
+        // trailing bytes are not silently ignored
+        let mut bytes = borsh::to_vec(&PoolState::default()).unwrap();
+        bytes.push(0);
+        assert!(PoolState::try_from_slice(&bytes).is_err());
+        let mut bytes = borsh::to_vec(&PoolState::default()).unwrap();
+        // the surplus policy, before the curve parameters and buyback config
+        bytes[PoolState::LEN - (8 + 1) - CurveParams::LEN - 1] = 3;
+        assert!(PoolState::try_from_slice(&bytes).is_err());
+    }
+
+    #[test]
+    fn curve_params_match_curve_type() {
+        let mut state = PoolState::default();
+        assert_eq!(state.curve_params(), Some(CurveParams::ConstantProduct));
+        let stable = CurveParams::Stable(AmpRamp::new(100).unwrap());
+        state.set_curve_params(stable);
+        assert_eq!(state.curve_type, CurveType::Stable);
+        assert_eq!(state.curve_params(), Some(stable));
+        let bytes = borsh::to_vec(&state).unwrap();
+        assert_eq!(PoolState::try_from_slice(&bytes).unwrap().curve_params(), Some(stable));
+        // parameters for another curve are not used
+        state.curve_type = CurveType::ConstantProduct;
+        assert_eq!(state.curve_params(), None);
+
+        // ids without a built-in curve are kept for a registry to resolve
+        let mut bytes = borsh::to_vec(&PoolState::default()).unwrap();
+        bytes[0] = 7;
+        let custom = PoolState::try_from_slice(&bytes).unwrap();
+        assert_eq!(custom.curve_type, CurveType::Custom(7));
+        assert_eq!(borsh::to_vec(&custom).unwrap(), bytes);
+        assert_eq!(custom.curve_params(), None);
+    }
+
+    #[test]
+    fn fee_tier_is_kept_with_the_curve() {
+        let mut state = PoolState::default();
+        assert_eq!(state.fee_tier(), None);
+        state.set_fee_tier(FeeTier::Bps5);
+        assert_eq!(state.fee_tier(), Some(FeeTier::Bps5));
+        assert_eq!(state.fee_config.trade_fee_rate, FeeTier::Bps5.trade_fee_rate());
+        let stable = CurveParams::Stable(AmpRamp::new(100).unwrap());
+        state.set_curve_params(stable);
+        let bytes = borsh::to_vec(&state).unwrap();
+        let state = PoolState::try_from_slice(&bytes).unwrap();
+        assert_eq!(state.fee_tier(), Some(FeeTier::Bps5));
+        assert_eq!(state.curve_params(), Some(stable));
+    }
+
+    #[test]
+    fn curve_type_ids_round_trip() {
+        for id in 0..=u8::MAX {
+            assert_eq!(CurveType::from_id(id).id(), id);
+        }
+        assert_eq!(CurveType::from_id(1), CurveType::Stable);
+        assert_eq!(CurveType::from_id(6), CurveType::CryptoSwap);
+        assert_eq!(CurveType::from_id(7), CurveType::Custom(7));
+    }
+
+    #[test]
+    fn burning_the_whole_supply_empties_the_pool() {
+        let mut state = PoolState {
+            token_0_reserve: u64::MAX,
+            token_1_reserve: 7,
+            lp_supply: u64::MAX,
+            protocol_fees_token_0: 3,
+            ..PoolState::default()
+        };
+        assert_eq!(state.withdraw(0), None);
+        assert_eq!(state.withdraw(u64::MAX - 1).unwrap().token_1_amount, 6);
+        let result = state.withdraw(1).unwrap();
+        // the last LP token takes everything left, dust included
+        assert_eq!(result.token_0_amount, 1);
+        assert_eq!(result.token_1_amount, 1);
+        assert_eq!(state.reserves(), Reserves::default());
+        assert_eq!(state.lp_supply, 0);
+        assert_eq!(state.withdraw(1), None);
+        // the protocol fees are still owed
+        assert!(!state.is_closable());
+        state.protocol_fees_token_0 = 0;
+        assert!(state.is_closable());
+    }
+
+    #[test]
+    fn fully_withdrawn_pools_can_be_bootstrapped_again() {
+        let mut state = PoolState::default();
+        assert_eq!(state.deposit(0, 4_000), None);
+        assert_eq!(state, PoolState::default());
+        let result = state.deposit(1_000, 4_000).unwrap();
+        assert_eq!(result.lp_token_amount, 2_000);
+        assert_eq!(state.lp_supply, 2_000);
+        assert_eq!((state.token_0_reserve, state.token_1_reserve), (1_000, 4_000));
+
+        // proportional deposits refund the excess side
+        let result = state.deposit(100, 1_000).unwrap();
+        assert_eq!(result.lp_token_amount, 200);
+        assert_eq!((result.unused_token_0, result.unused_token_1), (0, 600));
+        assert_eq!((state.token_0_reserve, state.token_1_reserve), (1_100, 4_400));
+
+        state.withdraw(2_200).unwrap();
+        assert_eq!(state.lp_supply, 0);
+        assert_eq!(state.reserves(), Reserves::default());
+        // a new price is set by the next first deposit
+        let result = state.deposit(9, 1).unwrap();
+        assert_eq!(result.lp_token_amount, 3);
+        assert_eq!(state.lp_supply, 3);
+        assert_eq!((state.token_0_reserve, state.token_1_reserve), (9, 1));
+
+        // the supply would overflow u64
+        let mut full = PoolState {
+            token_0_reserve: 1,
+            token_1_reserve: 1,
+            lp_supply: u64::MAX,
+            ..PoolState::default()
+        };
+        assert_eq!(full.deposit(1, 1), None);
+    }
+
+    #[test]
+    fn sync_applies_surplus_policy() {
+        let mut state = PoolState {
+            token_0_reserve: 1_000,
+            token_1_reserve: 2_000,
+            protocol_fees_token_0: 10,
+            ..PoolState::default()
+        };
+        let mut skimming = PoolState {
+            surplus_policy: SurplusPolicy::SkimToFeeVault,
+            ..state
+        };
+        let mut rejecting = PoolState {
+            surplus_policy: SurplusPolicy::Reject,
+            ..state
+        };
+        let result = state.sync_reserves(1_060, 2_000).unwrap();
+        assert_eq!(result.skimmed, Reserves::default());
+        assert_eq!((state.token_0_reserve, state.token_1_reserve), (1_050, 2_000));
+
+        let result = skimming.sync_reserves(1_060, 2_000).unwrap();
+        assert_eq!(result.skimmed.token_0.get(), 50);
+        assert_eq!(skimming.token_0_reserve, 1_000);
+
+        // a vault short of its protocol fees leaves the state untouched
+        assert_eq!(skimming.sync_reserves(9, 2_000), None);
+        assert_eq!(skimming.token_0_reserve, 1_000);
+
+        assert_eq!(rejecting.sync_reserves(1_060, 2_000), None);
+        assert_eq!(rejecting.token_0_reserve, 1_000);
+        let result = rejecting.sync_reserves(1_000, 2_000).unwrap();
+        assert_eq!(result.shortfall.token_0.get(), 10);
+        assert_eq!(rejecting.token_0_reserve, 990);
+    }
+
+    #[test]
+    fn skim_leaves_the_state_untouched() {
+        let state = PoolState {
+            token_0_reserve: 1_000,
+            token_1_reserve: 2_000,
+            protocol_fees_token_0: 10,
+            ..PoolState::default()
+        };
+        let skimmed = state.skim(1_060, 2_001).unwrap();
+        assert_eq!((skimmed.token_0.get(), skimmed.token_1.get()), (50, 1));
+        assert_eq!(state.skim(1_010, 2_000), Some(Reserves::default()));
+        assert_eq!(state.skim(1_009, 2_001), None);
+        assert_eq!(state.reserves().token_0.get(), 1_000);
+    }
+
+    #[test]
+    fn collecting_fees_runs_the_buyback() {
+        let mut state = PoolState {
+            token_0_reserve: 1_000_000,
+            token_1_reserve: 1_000_000,
+            protocol_fees_token_0: 1_000,
+            protocol_fees_token_1: 300,
+            buyback: BuybackConfig {
+                share: FeeRate::MAX,
+                token: BuybackToken::Token1,
+            },
+            ..PoolState::default()
+        };
+        let collection = state.collect_protocol_fees(0).unwrap();
+        assert_eq!(collection.collected, Reserves::default());
+        assert_eq!(collection.burned.token_1.get(), 300 + 999);
+        assert_eq!((state.token_0_reserve, state.token_1_reserve), (1_001_000, 999_001));
+        assert_eq!((state.protocol_fees_token_0, state.protocol_fees_token_1), (0, 0));
+
+        // invalid curve parameters leave the fees uncollected
+        state.protocol_fees_token_0 = 5;
+        state.curve_type = CurveType::Stable;
+        assert_eq!(state.collect_protocol_fees(0), None);
+        assert_eq!(state.protocol_fees_token_0, 5);
+    }
+}
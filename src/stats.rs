@@ -0,0 +1,421 @@
+//! Cumulative pool statistics
+//!
+//! Running totals of swap volume and fees, updated from each `SwapResult`.
+//! The counters saturate instead of overflowing, so a long-lived pool can
+//! never fail a swap because its statistics are full. The layout is fixed
+//! size, so the accumulator can be embedded in pool state or kept by an
+//! indexer.
+//!
+//! `BuybackStats` totals the protocol fees routed to buyback-and-burn.
+//!
+//! `FeeGrowth` instead tracks the LP fees earned per LP token, so each LP's
+//! share can be computed from a checkpoint taken when they entered, without
+//! touching every position on each swap.
+use {
+    crate::{
+        curve::{
+            amount::{LpAmount, Token0Amount, Token1Amount, TokenAmounts},
+            buyback::FeeCollection,
+            calculator::{SwapResult, TradeDirection},
+        },
+        utils::{Q64_RESOLUTION, U256, price::to_u128},
+    },
+    borsh::{BorshDeserialize, BorshSerialize},
+};
+
+/// Cumulative volume and fees of a pool
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct PoolStats {
+    /// Token 0 paid into the pool by swaps, including fees
+    pub volume_in_token_0: u128,
+    /// Token 1 paid into the pool by swaps, including fees
+    pub volume_in_token_1: u128,
+    /// Token 0 paid out of the pool by swaps
+    pub volume_out_token_0: u128,
+    /// Token 1 paid out of the pool by swaps
+    pub volume_out_token_1: u128,
+    /// Trade fees charged in token 0, including the protocol's share.
+    ///
+    /// Fees deducted from the output of a swap, and under
+    /// `ProtocolFeeMode::Split` the protocol's share bought with the input
+    /// fee, count in the output token; see `fees_by_token`.
+    pub trade_fees_token_0: u128,
+    /// Trade fees charged in token 1, including the protocol's share
+    pub trade_fees_token_1: u128,
+    /// Protocol's share of the trade fees in token 0
+    pub protocol_fees_token_0: u128,
+    /// Protocol's share of the trade fees in token 1
+    pub protocol_fees_token_1: u128,
+    /// Number of swaps recorded
+    pub swap_count: u64,
+}
+
+impl PoolStats {
+    /// Size of the borsh encoded statistics in bytes
+    pub const LEN: usize = 8 * 16 + 8;
+
+    /// Add a swap in `trade_direction` to the totals
+    pub fn record_swap(&mut self, trade_direction: TradeDirection, result: &SwapResult) {
+        let (volume_in, volume_out) = match trade_direction {
+            TradeDirection::ZeroForOne => {
+                (&mut self.volume_in_token_0, &mut self.volume_out_token_1)
+            }
+            TradeDirection::OneForZero => {
+                (&mut self.volume_in_token_1, &mut self.volume_out_token_0)
+            }
+        };
+        *volume_in = volume_in.saturating_add(result.source_amount_swapped);
+        *volume_out = volume_out.saturating_add(result.destination_amount_swapped);
+        let [token_0, token_1] = fees_by_token(trade_direction, result);
+        let add = |total: &mut u128, amount: u128| *total = total.saturating_add(amount);
+        add(&mut self.trade_fees_token_0, token_0.trade_fee);
+        add(&mut self.protocol_fees_token_0, token_0.protocol_fee);
+        add(&mut self.trade_fees_token_1, token_1.trade_fee);
+        add(&mut self.protocol_fees_token_1, token_1.protocol_fee);
+        self.swap_count = self.swap_count.saturating_add(1);
+    }
+
+    /// Trade fees in token 0 kept by liquidity providers
+    pub fn lp_fees_token_0(&self) -> u128 {
+        self.trade_fees_token_0
+            .saturating_sub(self.protocol_fees_token_0)
+    }
+
+    /// Trade fees in token 1 kept by liquidity providers
+    pub fn lp_fees_token_1(&self) -> u128 {
+        self.trade_fees_token_1
+            .saturating_sub(self.protocol_fees_token_1)
+    }
+}
+
+/// Trade fee charged in one token, and the protocol's share of it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TokenFees {
+    pub trade_fee: u128,
+    pub protocol_fee: u128,
+}
+
+impl TokenFees {
+    /// The part of the trade fee kept by liquidity providers
+    pub const fn lp_fee(&self) -> u128 {
+        self.trade_fee.saturating_sub(self.protocol_fee)
+    }
+}
+
+/// Fees of a swap in `trade_direction`, per token.
+///
+/// Fees deducted from the input count in the input token and fees deducted
+/// from the output in the output token. Under `ProtocolFeeMode::Split` the
+/// pool sells part of the input fee, at most the `protocol_fee` it takes
+/// from the input, for `destination_protocol_fee`; that part counts as a
+/// fee in the output token, all of it the protocol's, so liquidity
+/// providers are never credited with fees they sold. The input fee is then
+/// understated by at most one token.
+pub fn fees_by_token(trade_direction: TradeDirection, result: &SwapResult) -> [TokenFees; 2] {
+    let split = result.destination_protocol_fee > result.destination_trade_fee;
+    let sold = if split { result.protocol_fee } else { 0 };
+    let source = TokenFees {
+        trade_fee: result.trade_fee.saturating_sub(sold),
+        protocol_fee: result.protocol_fee,
+    };
+    let destination = TokenFees {
+        trade_fee: result
+            .destination_trade_fee
+            .max(result.destination_protocol_fee),
+        protocol_fee: result.destination_protocol_fee,
+    };
+    match trade_direction {
+        TradeDirection::ZeroForOne => [source, destination],
+        TradeDirection::OneForZero => [destination, source],
+    }
+}
+
+/// Cumulative protocol fee collections and buybacks of a pool
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct BuybackStats {
+    /// Token 0 fees paid to the treasury
+    pub collected_token_0: u128,
+    /// Token 1 fees paid to the treasury
+    pub collected_token_1: u128,
+    /// Token 0 fees sold to the pool by buybacks
+    pub sold_token_0: u128,
+    /// Token 1 fees sold to the pool by buybacks
+    pub sold_token_1: u128,
+    /// Token 0 earmarked for burning
+    pub burned_token_0: u128,
+    /// Token 1 earmarked for burning
+    pub burned_token_1: u128,
+    /// Number of collections recorded
+    pub collection_count: u64,
+}
+
+impl BuybackStats {
+    /// Size of the borsh encoded statistics in bytes
+    pub const LEN: usize = 6 * 16 + 8;
+
+    /// Add a fee collection to the totals
+    pub fn record_collection(&mut self, collection: &FeeCollection) {
+        let add = |total: &mut u128, amount: u128| *total = total.saturating_add(amount);
+        add(&mut self.collected_token_0, collection.collected.token_0.get());
+        add(&mut self.collected_token_1, collection.collected.token_1.get());
+        add(&mut self.sold_token_0, collection.sold.token_0.get());
+        add(&mut self.sold_token_1, collection.sold.token_1.get());
+        add(&mut self.burned_token_0, collection.burned.token_0.get());
+        add(&mut self.burned_token_1, collection.burned.token_1.get());
+        self.collection_count = self.collection_count.saturating_add(1);
+    }
+}
+
+/// LP fees earned per LP token since the pool was created, in Q64.64.
+///
+/// Like Uniswap's fee growth, the accumulators wrap on overflow: only the
+/// difference between two readings is meaningful, and it stays correct
+/// across a wrap as long as less than 2^64 tokens of fees per LP token
+/// accrue between them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct FeeGrowth {
+    /// Token 0 fees per LP token
+    pub fee_growth_global_0: u128,
+    /// Token 1 fees per LP token
+    pub fee_growth_global_1: u128,
+}
+
+impl FeeGrowth {
+    /// Size of the borsh encoded accumulators in bytes
+    pub const LEN: usize = 2 * 16;
+
+    /// Credit the LP share of a swap's fees, in each token as
+    /// `fees_by_token` counts them, to the `lp_supply` outstanding LP
+    /// tokens, rounded down.
+    ///
+    /// With no LP supply there is no one to credit, and the fee is not
+    /// tracked.
+    pub fn record_swap(
+        &mut self,
+        trade_direction: TradeDirection,
+        result: &SwapResult,
+        lp_supply: LpAmount,
+    ) {
+        if lp_supply.get() == 0 {
+            return;
+        }
+        let [token_0, token_1] = fees_by_token(trade_direction, result);
+        let credit = |fee_growth: &mut u128, lp_fee: u128| {
+            let growth = (U256::from(lp_fee) << Q64_RESOLUTION) / U256::from(lp_supply.get());
+            // lp_fee < 2^128 and lp_supply >= 1, so the growth fits in 192
+            // bits; keeping the low 128 wraps like the accumulator itself
+            *fee_growth = fee_growth.wrapping_add(growth.low_u128());
+        };
+        credit(&mut self.fee_growth_global_0, token_0.lp_fee());
+        credit(&mut self.fee_growth_global_1, token_1.lp_fee());
+    }
+
+    /// Fees earned by `lp_amount` LP tokens held since `checkpoint`, a copy
+    /// of the accumulators taken when the position was opened or last
+    /// collected. Rounded down.
+    ///
+    /// Returns `None` if an amount does not fit in u128.
+    pub fn uncollected_fees(
+        &self,
+        checkpoint: &FeeGrowth,
+        lp_amount: LpAmount,
+    ) -> Option<TokenAmounts> {
+        let earned = |growth: u128, entry: u128| {
+            let growth = U256::from(growth.wrapping_sub(entry));
+            to_u128((growth * U256::from(lp_amount.get())) >> Q64_RESOLUTION)
+        };
+        Some(TokenAmounts {
+            token_0: Token0Amount(earned(
+                self.fee_growth_global_0,
+                checkpoint.fee_growth_global_0,
+            )?),
+            token_1: Token1Amount(earned(
+                self.fee_growth_global_1,
+                checkpoint.fee_growth_global_1,
+            )?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::curve::{
+            amount::Reserves,
+            buyback::{BuybackConfig, BuybackToken},
+            calculator::CurveCalculator,
+            fees::{FeeConfig, FeeRate, ProtocolFeeMode, TradeFeeSide},
+            params::CurveParams,
+            swap_params::SwapParams,
+        },
+    };
+
+    #[test]
+    fn record_swaps_in_both_directions() {
+        let mut stats = PoolStats::default();
+        let result = CurveCalculator::swap_base_input(
+            &SwapParams::from_raw_parts(1_000, 50_000, 80_000, 2_500, 120_000).unwrap(),
+        )
+        .unwrap();
+        stats.record_swap(TradeDirection::ZeroForOne, &result);
+        stats.record_swap(TradeDirection::OneForZero, &result);
+        stats.record_swap(TradeDirection::OneForZero, &result);
+        assert_eq!(stats.swap_count, 3);
+        assert_eq!(stats.volume_in_token_0, 1_000);
+        assert_eq!(stats.volume_in_token_1, 2_000);
+        assert_eq!(stats.volume_out_token_1, result.destination_amount_swapped);
+        assert_eq!(
+            stats.volume_out_token_0,
+            2 * result.destination_amount_swapped
+        );
+        assert_eq!(stats.trade_fees_token_1, 2 * result.trade_fee);
+        assert_eq!(stats.protocol_fees_token_0, result.protocol_fee);
+        assert_eq!(
+            stats.lp_fees_token_0(),
+            result.trade_fee - result.protocol_fee
+        );
+    }
+
+    #[test]
+    fn record_fees_per_side_and_mode() {
+        let swap = |fee_config: FeeConfig| {
+            let params = SwapParams::new(
+                TradeDirection::ZeroForOne,
+                1_000_000,
+                Reserves::new(Token0Amount(50_000_000), Token1Amount(80_000_000)),
+                fee_config,
+            );
+            CurveCalculator::swap_base_input(&params).unwrap()
+        };
+        let fee_config = FeeConfig::STANDARD_25BPS;
+
+        // fees deducted from the output are in token 1
+        let result = swap(fee_config.with_trade_fee_side(TradeFeeSide::Output));
+        let (mut stats, mut fee_growth) = (PoolStats::default(), FeeGrowth::default());
+        stats.record_swap(TradeDirection::ZeroForOne, &result);
+        fee_growth.record_swap(TradeDirection::ZeroForOne, &result, LpAmount(1));
+        assert_eq!(
+            (stats.trade_fees_token_0, stats.protocol_fees_token_0),
+            (0, 0)
+        );
+        assert_eq!(stats.trade_fees_token_1, result.destination_trade_fee);
+        assert_eq!(stats.protocol_fees_token_1, result.destination_protocol_fee);
+        let lp_fee = result.destination_trade_fee - result.destination_protocol_fee;
+        assert_eq!(stats.lp_fees_token_1(), lp_fee);
+        let fees = fee_growth
+            .uncollected_fees(&FeeGrowth::default(), LpAmount(1))
+            .unwrap();
+        assert_eq!(fees.token_0, Token0Amount(0));
+        assert_eq!(fees.token_1, Token1Amount(lp_fee));
+
+        // under `Split` the protocol's token 1 share is bought with part of
+        // the token 0 fee, which the LPs do not keep
+        let input = swap(fee_config);
+        let result = swap(fee_config.with_protocol_fee_mode(ProtocolFeeMode::Split));
+        assert!(result.destination_protocol_fee > 0);
+        let (mut stats, mut fee_growth) = (PoolStats::default(), FeeGrowth::default());
+        stats.record_swap(TradeDirection::ZeroForOne, &result);
+        fee_growth.record_swap(TradeDirection::ZeroForOne, &result, LpAmount(1));
+        assert_eq!(stats.protocol_fees_token_0, result.protocol_fee);
+        assert_eq!(stats.protocol_fees_token_1, result.destination_protocol_fee);
+        assert_eq!(stats.trade_fees_token_1, result.destination_protocol_fee);
+        assert_eq!(stats.lp_fees_token_1(), 0);
+        let lp_fee = input.trade_fee - input.protocol_fee;
+        assert!((lp_fee - 1..=lp_fee).contains(&stats.lp_fees_token_0()));
+        let fees = fee_growth
+            .uncollected_fees(&FeeGrowth::default(), LpAmount(1))
+            .unwrap();
+        assert_eq!(fees.token_0, Token0Amount(stats.lp_fees_token_0()));
+        assert_eq!(fees.token_1, Token1Amount(0));
+    }
+
+    #[test]
+    fn counters_saturate() {
+        let mut stats = PoolStats {
+            volume_in_token_0: u128::MAX - 1,
+            swap_count: u64::MAX,
+            ..PoolStats::default()
+        };
+        let result = CurveCalculator::swap_base_input(
+            &SwapParams::from_raw_parts(1_000, 50_000, 80_000, 2_500, 120_000).unwrap(),
+        )
+        .unwrap();
+        stats.record_swap(TradeDirection::ZeroForOne, &result);
+        assert_eq!(stats.volume_in_token_0, u128::MAX);
+        assert_eq!(stats.swap_count, u64::MAX);
+        assert_eq!(borsh::to_vec(&stats).unwrap().len(), PoolStats::LEN);
+    }
+
+    #[test]
+    fn record_buybacks() {
+        let mut stats = BuybackStats::default();
+        let collection = CurveParams::ConstantProduct
+            .collect_protocol_fees(
+                0,
+                Reserves::new(Token0Amount(1_000_000), Token1Amount(1_000_000)),
+                Reserves::new(Token0Amount(100), Token1Amount(1_000)),
+                FeeConfig::default(),
+                BuybackConfig {
+                    share: FeeRate::from_percent(50).unwrap(),
+                    token: BuybackToken::Token0,
+                },
+            )
+            .unwrap();
+        stats.record_collection(&collection);
+        stats.record_collection(&collection);
+        assert_eq!(stats.collection_count, 2);
+        assert_eq!((stats.collected_token_0, stats.collected_token_1), (100, 1_000));
+        assert_eq!((stats.sold_token_0, stats.sold_token_1), (0, 1_000));
+        // 50 fees plus 499 bought for 500 token 1, twice
+        assert_eq!((stats.burned_token_0, stats.burned_token_1), (1_098, 0));
+        assert_eq!(borsh::to_vec(&stats).unwrap().len(), BuybackStats::LEN);
+    }
+
+    #[test]
+    fn fee_growth_splits_fees_by_share() {
+        // a power of two supply keeps the Q64 growth exact
+        let mut fee_growth = FeeGrowth::default();
+        let result = CurveCalculator::swap_base_input(
+            &SwapParams::from_raw_parts(1_000_000, 50_000_000, 80_000_000, 2_500, 120_000).unwrap(),
+        )
+        .unwrap();
+        let lp_fee = result.trade_fee - result.protocol_fee;
+        fee_growth.record_swap(TradeDirection::ZeroForOne, &result, LpAmount(4_096));
+        let entry = fee_growth;
+        fee_growth.record_swap(TradeDirection::OneForZero, &result, LpAmount(4_096));
+
+        // a position held throughout earns its share of both fees
+        let early = fee_growth
+            .uncollected_fees(&FeeGrowth::default(), LpAmount(1_024))
+            .unwrap();
+        assert_eq!(early.token_0, Token0Amount(lp_fee / 4));
+        assert_eq!(early.token_1, Token1Amount(lp_fee / 4));
+        // one opened after the first swap only earns from the second
+        let late = fee_growth
+            .uncollected_fees(&entry, LpAmount(1_024))
+            .unwrap();
+        assert_eq!(late.token_0, Token0Amount(0));
+        assert_eq!(late.token_1, Token1Amount(lp_fee / 4));
+        assert_eq!(borsh::to_vec(&fee_growth).unwrap().len(), FeeGrowth::LEN);
+    }
+
+    #[test]
+    fn fee_growth_survives_wrapping() {
+        let result = CurveCalculator::swap_base_input(
+            &SwapParams::from_raw_parts(1_000, 50_000, 80_000, 2_500, 0).unwrap(),
+        )
+        .unwrap();
+        let entry = FeeGrowth {
+            fee_growth_global_0: u128::MAX,
+            fee_growth_global_1: 0,
+        };
+        let mut fee_growth = entry;
+        fee_growth.record_swap(TradeDirection::ZeroForOne, &result, LpAmount(1));
+        assert!(fee_growth.fee_growth_global_0 < entry.fee_growth_global_0);
+        let fees = fee_growth.uncollected_fees(&entry, LpAmount(1)).unwrap();
+        assert_eq!(fees.token_0, Token0Amount(result.trade_fee));
+        fee_growth.record_swap(TradeDirection::OneForZero, &result, LpAmount(0));
+        assert_eq!(fee_growth.fee_growth_global_1, 0);
+    }
+}
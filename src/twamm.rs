@@ -0,0 +1,557 @@
+//! Time-weighted average market making
+//!
+//! Long-term orders sell a token at a constant rate over many intervals.
+//! Instead of trading each interval's amount as a swap, every interval both
+//! directions' sales are executed virtually against the constant product
+//! pool at once, as if infinitely many infinitesimal trades had alternated
+//! through the interval. For reserves `x, y`, `k = x * y`, and amounts `a`
+//! of token 0 and `b` of token 1 sold over the interval, the reserves end at
+//!
+//! ```text
+//! x' = sqrt(k * a / b) * (e + c) / (e - c),  y' = k / x'
+//! e  = exp(2 * sqrt(a * b / k))
+//! c  = (sqrt(x * b) - sqrt(y * a)) / (sqrt(x * b) + sqrt(y * a))
+//! ```
+//!
+//! and an ordinary constant product trade when only one side sells. Sellers
+//! of token 0 share the token 1 paid out in proportion to their sale rates,
+//! and vice versa. Rounding always favours the pool, so `x' * y'` never
+//! falls below `k` and orders are never paid more than the pool released.
+use {
+    crate::{
+        curve::{
+            amount::{Reserves, Token0Amount, Token1Amount},
+            calculator::{SwapResult, TradeDirection},
+            fees::Fees,
+        },
+        sim::Pool,
+        utils::{
+            IntegerSquareRoot, U256,
+            fixed_math::exp,
+            price::{Q64, Q64_RESOLUTION, to_u128},
+        },
+    },
+    alloc::collections::BTreeMap,
+};
+
+/// Long-term orders selling one token
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OrderPool {
+    /// Tokens sold per second by the active orders
+    pub sale_rate: u128,
+    /// Tokens bought per unit of sale rate since the pool was created, in
+    /// Q64.64
+    pub earnings_per_rate: u128,
+    /// Sale rate of the orders expiring at each timestamp
+    pub rate_ending: BTreeMap<u64, u128>,
+    /// `earnings_per_rate` at each expiry that has passed
+    pub earnings_at_expiry: BTreeMap<u64, u128>,
+}
+
+/// An order selling a token at a constant rate until its expiry
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LongTermOrder {
+    /// Direction of the sale
+    pub trade_direction: TradeDirection,
+    /// Tokens sold per second
+    pub sale_rate: u128,
+    /// Timestamp the order was placed
+    pub start: u64,
+    /// Timestamp the order stops selling, on an interval boundary
+    pub expiry: u64,
+    /// `earnings_per_rate` of the order pool when the order was placed
+    pub earnings_per_rate_start: u128,
+    /// Proceeds already withdrawn
+    pub withdrawn: u128,
+}
+
+impl LongTermOrder {
+    /// Tokens the order sells in total
+    pub fn amount(&self) -> Option<u128> {
+        self.sale_rate
+            .checked_mul(u128::from(self.expiry - self.start))
+    }
+}
+
+/// A constant product pool with long-term orders
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Twamm {
+    /// The pool orders are executed against
+    pub pool: Pool,
+    /// Seconds between executions; orders expire on multiples of it
+    pub interval: u64,
+    /// Timestamp up to which orders have been executed
+    pub last_execution: u64,
+    /// Orders selling token 0, then orders selling token 1
+    pub order_pools: [OrderPool; 2],
+    /// Active orders by id
+    pub orders: BTreeMap<u64, LongTermOrder>,
+    /// Id of the next order placed
+    pub next_order_id: u64,
+}
+
+// `numerator / denominator`, rounded up
+fn ceil_div(numerator: U256, denominator: U256) -> Option<U256> {
+    if denominator.is_zero() {
+        return None;
+    }
+    let quotient = numerator / denominator;
+    Some(if (numerator % denominator).is_zero() {
+        quotient
+    } else {
+        quotient + 1
+    })
+}
+
+/// Reserves after selling `amount_0` of token 0 and `amount_1` of token 1
+/// into `reserves` continuously over one interval, fees excluded.
+///
+/// Returns `None` if a reserve is empty or a value overflows.
+pub fn virtual_reserves(reserves: Reserves, amount_0: u128, amount_1: u128) -> Option<Reserves> {
+    let (x, y) = (reserves.token_0.get(), reserves.token_1.get());
+    if x == 0 || y == 0 {
+        return None;
+    }
+    let (x_max, y_max) = (x.checked_add(amount_0)?, y.checked_add(amount_1)?);
+    let k = U256::from(x) * U256::from(y);
+    let (x_end, y_end) = if amount_1 == 0 {
+        (U256::from(x_max), ceil_div(k, U256::from(x_max))?)
+    } else if amount_0 == 0 {
+        (ceil_div(k, U256::from(y_max))?, U256::from(y_max))
+    } else {
+        let sqrt_x_b = (U256::from(x) * U256::from(amount_1)).sqrt_floor();
+        let sqrt_y_a = (U256::from(y) * U256::from(amount_0)).sqrt_floor();
+        // sqrt(k * a / b), where the price of the two flows balances
+        let x_balanced = U256::from(x) * sqrt_y_a / sqrt_x_b;
+        // c, in Q64.64, with its sign
+        let (c, c_negative) = if sqrt_x_b >= sqrt_y_a {
+            ((sqrt_x_b - sqrt_y_a) << Q64_RESOLUTION, false)
+        } else {
+            ((sqrt_y_a - sqrt_x_b) << Q64_RESOLUTION, true)
+        };
+        let c = c / (sqrt_x_b + sqrt_y_a);
+        let exponent = ((U256::from(amount_0) * U256::from(amount_1)).sqrt_floor()
+            << (Q64_RESOLUTION + 1))
+            / k.sqrt_floor();
+        // past what exp can represent, (e + c) / (e - c) is 1 to within a ulp
+        let e = to_u128(exponent)
+            .and_then(|exponent| i128::try_from(exponent).ok())
+            .and_then(exp);
+        let ratio = match e {
+            Some(e) => {
+                let e = U256::from(e);
+                let (numerator, denominator) = if c_negative {
+                    (e - c, e + c)
+                } else {
+                    (e + c, e - c)
+                };
+                (numerator << Q64_RESOLUTION).checked_div(denominator)?
+            }
+            None => U256::from(Q64),
+        };
+        let x_end = (x_balanced.checked_mul(ratio)? >> Q64_RESOLUTION)
+            .clamp(U256::one(), U256::from(x_max));
+        let y_end = ceil_div(k, x_end)?;
+        if y_end > U256::from(y_max) {
+            (ceil_div(k, U256::from(y_max))?, U256::from(y_max))
+        } else {
+            (x_end, y_end)
+        }
+    };
+    Some(Reserves::new(
+        Token0Amount(to_u128(x_end)?),
+        Token1Amount(to_u128(y_end)?),
+    ))
+}
+
+impl Twamm {
+    /// A pool with no orders, executed up to `timestamp`.
+    ///
+    /// Returns `None` if `interval` is zero or the pool does not take every
+    /// fee from the input, the only configuration virtual trades implement.
+    pub fn new(pool: Pool, interval: u64, timestamp: u64) -> Option<Self> {
+        (interval > 0 && pool.fee_config.is_input_only()).then(|| Self {
+            pool,
+            interval,
+            last_execution: timestamp,
+            order_pools: Default::default(),
+            orders: BTreeMap::new(),
+            next_order_id: 0,
+        })
+    }
+
+    // Sell `amount_0` and `amount_1` virtually against the pool, returning
+    // the token 1 bought by token 0 sellers and the token 0 bought by token
+    // 1 sellers
+    fn execute_interval(&mut self, amount_0: u128, amount_1: u128) -> Option<(u128, u128)> {
+        let fee_config = self.pool.fee_config;
+        if !fee_config.is_input_only() {
+            return None;
+        }
+        let trade_fee_rate = fee_config.trade_fee_rate.get();
+        let protocol_fee_rate = fee_config.protocol_fee_rate.get();
+        let trade_fee_0 = Fees::trading_fee(amount_0, trade_fee_rate)?;
+        let trade_fee_1 = Fees::trading_fee(amount_1, trade_fee_rate)?;
+        let protocol_fee_0 = Fees::protocol_fee(trade_fee_0, protocol_fee_rate)?;
+        let protocol_fee_1 = Fees::protocol_fee(trade_fee_1, protocol_fee_rate)?;
+
+        // the LPs' share of the fees joins the reserves before the trade
+        let reserves = self.pool.reserves;
+        let x = reserves
+            .token_0
+            .get()
+            .checked_add(trade_fee_0 - protocol_fee_0)?;
+        let y = reserves
+            .token_1
+            .get()
+            .checked_add(trade_fee_1 - protocol_fee_1)?;
+        let (sold_0, sold_1) = (amount_0 - trade_fee_0, amount_1 - trade_fee_1);
+        let end = virtual_reserves(
+            Reserves::new(Token0Amount(x), Token1Amount(y)),
+            sold_0,
+            sold_1,
+        )?;
+        let bought_1 = (y + sold_1).checked_sub(end.token_1.get())?;
+        let bought_0 = (x + sold_0).checked_sub(end.token_0.get())?;
+
+        let protocol_fees = self.pool.protocol_fees;
+        self.pool.protocol_fees = Reserves::new(
+            Token0Amount(protocol_fees.token_0.get().checked_add(protocol_fee_0)?),
+            Token1Amount(protocol_fees.token_1.get().checked_add(protocol_fee_1)?),
+        );
+        self.pool.reserves = end;
+        Some((bought_1, bought_0))
+    }
+
+    /// Execute the long-term orders up to `now`, one interval at a time.
+    ///
+    /// Returns `None`, leaving the pool unchanged, if `now` is before the
+    /// last execution or a value overflows.
+    pub fn execute(&mut self, now: u64) -> Option<()> {
+        if now < self.last_execution {
+            return None;
+        }
+        let mut twamm = self.clone();
+        while twamm.last_execution < now {
+            let boundary =
+                (twamm.last_execution / twamm.interval + 1).checked_mul(twamm.interval)?;
+            let until = boundary.min(now);
+            let elapsed = u128::from(until - twamm.last_execution);
+            let amount_0 = twamm.order_pools[0].sale_rate.checked_mul(elapsed)?;
+            let amount_1 = twamm.order_pools[1].sale_rate.checked_mul(elapsed)?;
+            if amount_0 > 0 || amount_1 > 0 {
+                let bought = twamm.execute_interval(amount_0, amount_1)?;
+                for (order_pool, bought) in twamm.order_pools.iter_mut().zip([bought.0, bought.1]) {
+                    if order_pool.sale_rate > 0 {
+                        let earnings = (U256::from(bought) << Q64_RESOLUTION)
+                            / U256::from(order_pool.sale_rate);
+                        order_pool.earnings_per_rate = order_pool
+                            .earnings_per_rate
+                            .checked_add(to_u128(earnings)?)?;
+                    }
+                }
+            }
+            twamm.last_execution = until;
+            if until == boundary {
+                for order_pool in &mut twamm.order_pools {
+                    if let Some(rate) = order_pool.rate_ending.remove(&boundary) {
+                        order_pool.sale_rate = order_pool.sale_rate.checked_sub(rate)?;
+                        order_pool
+                            .earnings_at_expiry
+                            .insert(boundary, order_pool.earnings_per_rate);
+                    }
+                }
+            }
+        }
+        *self = twamm;
+        Some(())
+    }
+
+    /// Place an order selling about `amount` of the source token of
+    /// `trade_direction` from `now` until the end of the `intervals`-th
+    /// interval boundary after it, returning its id.
+    ///
+    /// The order sells `amount / duration` per second, rounded down, so only
+    /// `LongTermOrder::amount` is taken. Returns `None`, leaving the pool
+    /// unchanged, if the sale rate would be zero or orders cannot be
+    /// executed up to `now`.
+    pub fn submit_order(
+        &mut self,
+        trade_direction: TradeDirection,
+        amount: u128,
+        intervals: u64,
+        now: u64,
+    ) -> Option<u64> {
+        let mut twamm = self.clone();
+        twamm.execute(now)?;
+        let expiry = (now / twamm.interval)
+            .checked_add(intervals)?
+            .checked_mul(twamm.interval)?;
+        if expiry <= now {
+            return None;
+        }
+        let sale_rate = amount / u128::from(expiry - now);
+        if sale_rate == 0 {
+            return None;
+        }
+        let order_pool = &mut twamm.order_pools[trade_direction.input_index()];
+        order_pool.sale_rate = order_pool.sale_rate.checked_add(sale_rate)?;
+        let ending = order_pool.rate_ending.entry(expiry).or_default();
+        *ending = ending.checked_add(sale_rate)?;
+        let id = twamm.next_order_id;
+        twamm.orders.insert(
+            id,
+            LongTermOrder {
+                trade_direction,
+                sale_rate,
+                start: now,
+                expiry,
+                earnings_per_rate_start: order_pool.earnings_per_rate,
+                withdrawn: 0,
+            },
+        );
+        twamm.next_order_id = id.checked_add(1)?;
+        *self = twamm;
+        Some(id)
+    }
+
+    // Proceeds of an order not yet withdrawn, rounded down
+    fn unclaimed_proceeds(&self, order: &LongTermOrder) -> Option<u128> {
+        let order_pool = &self.order_pools[order.trade_direction.input_index()];
+        let earnings_per_rate = if self.last_execution >= order.expiry {
+            *order_pool.earnings_at_expiry.get(&order.expiry)?
+        } else {
+            order_pool.earnings_per_rate
+        };
+        let earned = (U256::from(earnings_per_rate - order.earnings_per_rate_start)
+            * U256::from(order.sale_rate))
+            >> Q64_RESOLUTION;
+        to_u128(earned)?.checked_sub(order.withdrawn)
+    }
+
+    /// Withdraw the proceeds of order `id` up to `now`, closing it if it
+    /// has expired.
+    ///
+    /// Returns `None`, leaving the pool unchanged, if there is no such
+    /// order or orders cannot be executed up to `now`.
+    pub fn withdraw_proceeds(&mut self, id: u64, now: u64) -> Option<u128> {
+        let mut twamm = self.clone();
+        twamm.execute(now)?;
+        let mut order = *twamm.orders.get(&id)?;
+        let proceeds = twamm.unclaimed_proceeds(&order)?;
+        order.withdrawn = order.withdrawn.checked_add(proceeds)?;
+        if now >= order.expiry {
+            twamm.orders.remove(&id);
+        } else {
+            twamm.orders.insert(id, order);
+        }
+        *self = twamm;
+        Some(proceeds)
+    }
+
+    /// Cancel order `id` at `now`, returning the source tokens it has not
+    /// sold and its unwithdrawn proceeds.
+    ///
+    /// Returns `None`, leaving the pool unchanged, if there is no such
+    /// order, it has expired, or orders cannot be executed up to `now`.
+    pub fn cancel_order(&mut self, id: u64, now: u64) -> Option<(u128, u128)> {
+        let mut twamm = self.clone();
+        twamm.execute(now)?;
+        let order = twamm.orders.remove(&id)?;
+        if now >= order.expiry {
+            return None;
+        }
+        let proceeds = twamm.unclaimed_proceeds(&order)?;
+        let unsold = order
+            .sale_rate
+            .checked_mul(u128::from(order.expiry - now))?;
+        let order_pool = &mut twamm.order_pools[order.trade_direction.input_index()];
+        order_pool.sale_rate = order_pool.sale_rate.checked_sub(order.sale_rate)?;
+        let ending = order_pool.rate_ending.get_mut(&order.expiry)?;
+        *ending = ending.checked_sub(order.sale_rate)?;
+        if *ending == 0 {
+            order_pool.rate_ending.remove(&order.expiry);
+        }
+        *self = twamm;
+        Some((unsold, proceeds))
+    }
+
+    /// Swap exactly `source_amount` in `trade_direction` at `now`, after
+    /// executing the long-term orders up to it
+    pub fn swap_exact_in(
+        &mut self,
+        trade_direction: TradeDirection,
+        source_amount: u128,
+        now: u64,
+    ) -> Option<SwapResult> {
+        let mut twamm = self.clone();
+        twamm.execute(now)?;
+        let (result, _) = twamm.pool.apply_swap(trade_direction, source_amount)?;
+        *self = twamm;
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::curve::{
+            amount::LpAmount,
+            calculator::CurveCalculator,
+            fees::{FeeConfig, FeeRate, ProtocolFeeMode, TradeFeeSide},
+        },
+        alloc::vec::Vec,
+        proptest::prelude::*,
+    };
+
+    fn twamm(trade_fee_bps: u16) -> Twamm {
+        let pool = Pool::new(
+            Reserves::new(
+                Token0Amount(1_000_000_000_000),
+                Token1Amount(2_000_000_000_000),
+            ),
+            LpAmount(1),
+            FeeConfig::new(
+                FeeRate::from_bps(trade_fee_bps).unwrap(),
+                FeeRate::from_percent(10).unwrap(),
+            ),
+        );
+        Twamm::new(pool, 100, 0).unwrap()
+    }
+
+    #[test]
+    fn one_sided_order_trades_like_a_swap() {
+        let mut twamm = twamm(0);
+        let id = twamm
+            .submit_order(TradeDirection::ZeroForOne, 10_000_000_000, 1, 0)
+            .unwrap();
+        assert_eq!(twamm.orders[&id].amount(), Some(10_000_000_000));
+        let swap = CurveCalculator::swap_exact_in(
+            TradeDirection::ZeroForOne,
+            10_000_000_000,
+            twamm.pool.reserves,
+            twamm.pool.fee_config,
+        )
+        .unwrap();
+        let proceeds = twamm.withdraw_proceeds(id, 100).unwrap();
+        assert!(proceeds <= swap.destination_amount_swapped);
+        assert!(swap.destination_amount_swapped - proceeds <= 1);
+        assert!(twamm.orders.is_empty());
+        assert_eq!(twamm.order_pools[0].sale_rate, 0);
+    }
+
+    #[test]
+    fn opposite_orders_net_out() {
+        let mut twamm = twamm(30);
+        let price_before = twamm.pool.reserves;
+        // equal value in each direction, at the pool price
+        let sell_0 = twamm
+            .submit_order(TradeDirection::ZeroForOne, 1_000_000_000, 10, 0)
+            .unwrap();
+        let sell_1 = twamm
+            .submit_order(TradeDirection::OneForZero, 2_000_000_000, 10, 0)
+            .unwrap();
+        let bought_1 = twamm.withdraw_proceeds(sell_0, 1_000).unwrap();
+        let bought_0 = twamm.withdraw_proceeds(sell_1, 1_000).unwrap();
+        // each side gets the other's tokens at the pool price, less the fee,
+        // without moving the price
+        assert!(bought_1.abs_diff(1_994_000_000) < 100_000);
+        assert!(bought_0.abs_diff(997_000_000) < 100_000);
+        let reserves = twamm.pool.reserves;
+        let ratio_before = price_before.token_1.get() * 1_000 / price_before.token_0.get();
+        assert_eq!(
+            reserves.token_1.get() * 1_000 / reserves.token_0.get(),
+            ratio_before
+        );
+        assert_eq!(twamm.pool.protocol_fees.token_0.get(), 300_000);
+    }
+
+    #[test]
+    fn cancel_and_reject() {
+        let mut twamm = twamm(0);
+        let id = twamm
+            .submit_order(TradeDirection::OneForZero, 1_000_000, 10, 50)
+            .unwrap();
+        // the order runs from 50 to the 10th boundary after it, at 1_000
+        assert_eq!(twamm.orders[&id].expiry, 1_000);
+        assert_eq!(twamm.orders[&id].sale_rate, 1_052);
+        let (unsold, proceeds) = twamm.cancel_order(id, 525).unwrap();
+        assert_eq!(unsold, 1_052 * 475);
+        assert!(proceeds > 0);
+        assert_eq!(twamm.order_pools[1].sale_rate, 0);
+        assert!(twamm.order_pools[1].rate_ending.is_empty());
+        assert_eq!(twamm.cancel_order(id, 525), None);
+        assert_eq!(twamm.execute(524), None);
+        assert_eq!(
+            twamm.submit_order(TradeDirection::OneForZero, 10, 10, 600),
+            None
+        );
+        assert_eq!(Twamm::new(Pool::default(), 0, 0), None);
+    }
+
+    #[test]
+    fn fees_are_only_charged_on_the_input() {
+        let fee_config = twamm(30).pool.fee_config;
+        for unsupported in [
+            fee_config.with_trade_fee_side(TradeFeeSide::Output),
+            fee_config.with_protocol_fee_mode(ProtocolFeeMode::Split),
+        ] {
+            let mut pool = twamm(30).pool;
+            pool.fee_config = unsupported;
+            assert_eq!(Twamm::new(pool, 100, 0), None);
+
+            let mut twamm = twamm(30);
+            twamm
+                .submit_order(TradeDirection::ZeroForOne, 1_000_000, 1, 0)
+                .unwrap();
+            twamm.pool.fee_config = unsupported;
+            let before = twamm.clone();
+            assert_eq!(twamm.execute(100), None);
+            assert_eq!(twamm, before);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn value_never_leaks(
+            orders in prop::collection::vec(
+                (any::<bool>(), 1u128..1 << 40, 1u64..20, 0u64..2_000),
+                1..8,
+            ),
+            trade_fee_bps in 0u16..100,
+        ) {
+            let mut twamm = twamm(trade_fee_bps);
+            let initial = twamm.pool.reserves;
+            let k = |reserves: Reserves| {
+                U256::from(reserves.token_0.get()) * U256::from(reserves.token_1.get())
+            };
+            let mut orders = orders;
+            orders.sort_by_key(|order| order.3);
+            let mut vault = [initial.token_0.get(), initial.token_1.get()];
+            let mut ids = Vec::new();
+            for (zero_for_one, amount, intervals, start) in orders {
+                let trade_direction = if zero_for_one {
+                    TradeDirection::ZeroForOne
+                } else {
+                    TradeDirection::OneForZero
+                };
+                let k_before = k(twamm.pool.reserves);
+                if let Some(id) = twamm.submit_order(trade_direction, amount, intervals, start) {
+                    vault[trade_direction.input_index()] += twamm.orders[&id].amount().unwrap();
+                    ids.push((id, trade_direction));
+                }
+                prop_assert!(k(twamm.pool.reserves) >= k_before);
+            }
+            for (id, trade_direction) in ids {
+                let proceeds = twamm.withdraw_proceeds(id, 5_000).unwrap();
+                vault[trade_direction.output_index()] -= proceeds;
+            }
+            prop_assert!(k(twamm.pool.reserves) >= k(initial));
+            let reserves = twamm.pool.reserves;
+            let protocol_fees = twamm.pool.protocol_fees;
+            prop_assert!(vault[0] >= reserves.token_0.get() + protocol_fees.token_0.get());
+            prop_assert!(vault[1] >= reserves.token_1.get() + protocol_fees.token_1.get());
+        }
+    }
+}
@@ -0,0 +1,266 @@
+//! Conversions between UI amounts and raw token amounts
+//!
+//! Wallets and user interfaces show amounts in whole tokens, as in `1.5`,
+//! while the curve works in raw base units, `1_500_000` for a mint with 6
+//! decimals. Scaling a float by `10^decimals` drifts: `4.35 * 100.0` is
+//! `434.99999999999994`, which truncates to 434. These helpers convert through exact decimal
+//! arithmetic instead, and make the rounding of digits beyond the mint's
+//! precision explicit.
+use {
+    crate::curve::calculator::RoundDirection,
+    core::fmt::{self, Write},
+};
+
+/// Most decimals a mint can have for `10^decimals` to fit in u128
+pub const MAX_DECIMALS: u8 = 38;
+
+/// Raw amount of the decimal UI amount `ui_amount`, as in `"1.5"`, for a
+/// mint with `decimals` decimals.
+///
+/// Digits beyond `decimals` are rounded in `round_direction`. Returns `None`
+/// if `ui_amount` is not a plain decimal number, `decimals` exceeds
+/// `MAX_DECIMALS`, or the raw amount does not fit in `T`.
+pub fn parse_ui_amount<T: TryFrom<u128>>(
+    ui_amount: &str,
+    decimals: u8,
+    round_direction: RoundDirection,
+) -> Option<T> {
+    if decimals > MAX_DECIMALS {
+        return None;
+    }
+    let (integer, fraction) = ui_amount.split_once('.').unwrap_or((ui_amount, ""));
+    let all_digits = |digits: &str| digits.bytes().all(|digit| digit.is_ascii_digit());
+    if (integer.is_empty() && fraction.is_empty()) || !all_digits(integer) || !all_digits(fraction)
+    {
+        return None;
+    }
+    let (kept, dropped) = fraction.split_at(fraction.len().min(usize::from(decimals)));
+    let mut raw = 0u128;
+    for digit in integer.bytes().chain(kept.bytes()) {
+        raw = raw.checked_mul(10)?.checked_add(u128::from(digit - b'0'))?;
+    }
+    raw = raw.checked_mul(10u128.pow(u32::from(decimals) - kept.len() as u32))?;
+    if round_direction == RoundDirection::Ceiling && dropped.bytes().any(|digit| digit != b'0') {
+        raw = raw.checked_add(1)?;
+    }
+    T::try_from(raw).ok()
+}
+
+/// Raw amount of the UI amount `ui_amount` for a mint with `decimals`
+/// decimals.
+///
+/// The float is read as the shortest decimal that converts back to it,
+/// `4.35` rather than `4.3499999999999996447...`, then converted as
+/// `parse_ui_amount` does. Returns `None` for negative, infinite and NaN
+/// amounts, and where `parse_ui_amount` would.
+pub fn ui_amount_from_f64<T: TryFrom<u128>>(
+    ui_amount: f64,
+    decimals: u8,
+    round_direction: RoundDirection,
+) -> Option<T> {
+    if !ui_amount.is_finite() || (ui_amount.is_sign_negative() && ui_amount != 0.0) {
+        return None;
+    }
+    let mut buffer = Buffer::new();
+    // `{}` never uses an exponent and writes at most 326 bytes for an f64
+    write!(buffer, "{}", ui_amount.abs()).ok()?;
+    parse_ui_amount(buffer.as_str()?, decimals, round_direction)
+}
+
+/// A raw amount of a mint with `decimals` decimals, displayed as its exact
+/// UI amount without trailing zeros, as in `1.5`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UiAmount {
+    raw: u128,
+    decimals: u8,
+}
+
+impl UiAmount {
+    /// Returns `None` if `decimals` exceeds `MAX_DECIMALS`
+    pub fn new(raw: impl Into<u128>, decimals: u8) -> Option<Self> {
+        (decimals <= MAX_DECIMALS).then(|| Self {
+            raw: raw.into(),
+            decimals,
+        })
+    }
+
+    /// The raw amount
+    pub const fn raw(&self) -> u128 {
+        self.raw
+    }
+
+    /// The UI amount as the nearest f64
+    pub fn to_f64(&self) -> f64 {
+        let mut buffer = Buffer::new();
+        // parsing the exact decimal rounds once, to nearest
+        match write!(buffer, "{self}").ok().and_then(|_| buffer.as_str()) {
+            Some(ui_amount) => ui_amount.parse().unwrap_or(f64::NAN),
+            None => f64::NAN,
+        }
+    }
+}
+
+impl fmt::Display for UiAmount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scale = 10u128.pow(u32::from(self.decimals));
+        let (integer, fraction) = (self.raw / scale, self.raw % scale);
+        if fraction == 0 {
+            return write!(f, "{integer}");
+        }
+        let mut decimals = usize::from(self.decimals);
+        let mut fraction = fraction;
+        while fraction % 10 == 0 {
+            fraction /= 10;
+            decimals -= 1;
+        }
+        write!(f, "{integer}.{fraction:0decimals$}")
+    }
+}
+
+// Stack buffer for formatting numbers without allocating
+struct Buffer {
+    bytes: [u8; 384],
+    len: usize,
+}
+
+impl Buffer {
+    const fn new() -> Self {
+        Self {
+            bytes: [0; 384],
+            len: 0,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        core::str::from_utf8(&self.bytes[..self.len]).ok()
+    }
+}
+
+impl Write for Buffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len.checked_add(s.len()).ok_or(fmt::Error)?;
+        self.bytes
+            .get_mut(self.len..end)
+            .ok_or(fmt::Error)?
+            .copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, proptest::prelude::*};
+
+    #[test]
+    fn parse_rounds_excess_digits() {
+        let parse =
+            |ui_amount, round_direction| parse_ui_amount::<u128>(ui_amount, 6, round_direction);
+        assert_eq!(parse("1.5", RoundDirection::Floor), Some(1_500_000));
+        assert_eq!(parse("1", RoundDirection::Floor), Some(1_000_000));
+        assert_eq!(parse(".25", RoundDirection::Floor), Some(250_000));
+        assert_eq!(parse("7.", RoundDirection::Floor), Some(7_000_000));
+        assert_eq!(parse("0.0000015", RoundDirection::Floor), Some(1));
+        assert_eq!(parse("0.0000015", RoundDirection::Ceiling), Some(2));
+        assert_eq!(parse("0.0000010000", RoundDirection::Ceiling), Some(1));
+        for invalid in ["", ".", "-1", "+1", "1e6", "1.2.3", "1,000", " 1"] {
+            assert_eq!(parse(invalid, RoundDirection::Floor), None, "{invalid}");
+        }
+        assert_eq!(
+            parse_ui_amount::<u64>("18446744073709.551615", 6, RoundDirection::Floor),
+            Some(u64::MAX)
+        );
+        assert_eq!(
+            parse_ui_amount::<u64>("18446744073709.551616", 6, RoundDirection::Floor),
+            None
+        );
+        assert_eq!(
+            parse_ui_amount::<u128>("1", MAX_DECIMALS + 1, RoundDirection::Floor),
+            None
+        );
+    }
+
+    #[test]
+    fn floats_convert_without_drift() {
+        assert_eq!(
+            ui_amount_from_f64::<u64>(4.35, 2, RoundDirection::Floor),
+            Some(435)
+        );
+        assert_eq!(
+            ui_amount_from_f64::<u64>(1.1, 9, RoundDirection::Ceiling),
+            Some(1_100_000_000)
+        );
+        assert_eq!(
+            ui_amount_from_f64::<u64>(0.0, 6, RoundDirection::Ceiling),
+            Some(0)
+        );
+        assert_eq!(
+            ui_amount_from_f64::<u64>(-0.0, 6, RoundDirection::Ceiling),
+            Some(0)
+        );
+        assert_eq!(
+            ui_amount_from_f64::<u64>(1e-7, 6, RoundDirection::Floor),
+            Some(0)
+        );
+        assert_eq!(
+            ui_amount_from_f64::<u64>(1e-7, 6, RoundDirection::Ceiling),
+            Some(1)
+        );
+        assert_eq!(
+            ui_amount_from_f64::<u64>(f64::MIN_POSITIVE, 6, RoundDirection::Ceiling),
+            Some(1)
+        );
+        assert_eq!(
+            ui_amount_from_f64::<u128>(f64::MAX, 6, RoundDirection::Floor),
+            None
+        );
+        for invalid in [-1.0, f64::NAN, f64::INFINITY] {
+            assert_eq!(
+                ui_amount_from_f64::<u64>(invalid, 6, RoundDirection::Floor),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn display_is_exact_and_trimmed() {
+        let ui = |raw: u64, decimals| UiAmount::new(raw, decimals).unwrap().to_string();
+        assert_eq!(ui(1_500_000, 6), "1.5");
+        assert_eq!(ui(1, 6), "0.000001");
+        assert_eq!(ui(12_000_000, 6), "12");
+        assert_eq!(ui(0, 6), "0");
+        assert_eq!(ui(123, 0), "123");
+        assert_eq!(
+            UiAmount::new(u128::MAX, MAX_DECIMALS).unwrap().to_string(),
+            "3.40282366920938463463374607431768211455"
+        );
+        assert_eq!(UiAmount::new(1u64, MAX_DECIMALS + 1), None);
+        assert_eq!(UiAmount::new(300_000u64, 6).unwrap().to_f64(), 0.3);
+    }
+
+    proptest! {
+        #[test]
+        fn ui_amounts_round_trip(raw: u128, decimals in 0..=MAX_DECIMALS) {
+            let ui_amount = UiAmount::new(raw, decimals).unwrap().to_string();
+            for round_direction in [RoundDirection::Floor, RoundDirection::Ceiling] {
+                prop_assert_eq!(
+                    parse_ui_amount::<u128>(&ui_amount, decimals, round_direction),
+                    Some(raw)
+                );
+            }
+        }
+
+        #[test]
+        fn floats_round_trip(raw: u64, decimals in 0..=12u8) {
+            let float = UiAmount::new(raw, decimals).unwrap().to_f64();
+            let floor = ui_amount_from_f64::<u128>(float, decimals, RoundDirection::Floor).unwrap();
+            let ceiling = ui_amount_from_f64::<u128>(float, decimals, RoundDirection::Ceiling).unwrap();
+            prop_assert!(floor <= ceiling && ceiling <= floor + 1);
+            // amounts of up to 15 significant digits survive the float
+            if raw < 10u64.pow(15) {
+                prop_assert_eq!(floor, u128::from(raw));
+                prop_assert_eq!(ceiling, u128::from(raw));
+            }
+        }
+    }
+}
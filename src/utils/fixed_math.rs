@@ -0,0 +1,368 @@
+//! Deterministic fixed-point transcendental functions
+//!
+//! All values are Q64.64: unsigned inputs and outputs are `u128`, signed ones
+//! (logarithms and exponents) are `i128`, each with 64 fractional bits. The
+//! implementations use only integer arithmetic with U256 intermediates, so the
+//! results are bit-for-bit identical on every platform.
+//!
+//! Error bounds, where an ulp is 2^-64:
+//! * [`ln`] and [`log2`]: absolute error of at most 1 ulp.
+//! * [`exp`]: relative error below 2^-63, plus 1 ulp from the final
+//!   truncation.
+//! * [`pow`]: relative error below `(|exponent| + 2) * 2^-63`, plus 1 ulp,
+//!   since the logarithm error is scaled by the exponent.
+//!
+//! Every result is truncated towards zero.
+use crate::utils::{Q64, Q64_RESOLUTION, U256};
+
+/// Fractional bits used internally by the logarithm
+const LOG_FRACTION_BITS: u32 = 96;
+
+/// Mantissa precision used while iterating on values in [1, 2)
+const MANTISSA_BITS: u32 = 127;
+
+/// ln(2) in Q0.128
+const LN2_Q128: u128 = 0xb17217f7_d1cf79ab_c9e3b398_03f2f6af;
+
+/// log2(e) in Q1.127
+const LOG2_E_Q127: u128 = 0xb8aa3b29_5c17f0bb_be87fed0_691d3e88;
+
+/// Largest input accepted by [`exp`]; its result is just below 2^64.
+pub const MAX_EXP_INPUT: i128 = 0x2c_5c85fdf4_73de6af2;
+
+/// 2^(2^-i) in Q1.127 for i in 1..=64, rounded to nearest
+const EXP2_BITS_Q127: [u128; 64] = [
+    0xb504f333_f9de6484_597d89b3_754abe9f, // 2^(2^-1)
+    0x9837f051_8db8a96f_46ad2318_2e42f6f6, // 2^(2^-2)
+    0x8b95c1e3_ea8bd6e6_fbe46287_58a53c90, // 2^(2^-3)
+    0x85aac367_cc487b14_c5c95b8c_2154c1b2, // 2^(2^-4)
+    0x82cd8698_ac2ba1d7_3e2a475b_46520bff, // 2^(2^-5)
+    0x8164d1f3_bc030773_7be56527_bd14def5, // 2^(2^-6)
+    0x80b1ed4f_d999ab6c_25335719_b6e6fd20, // 2^(2^-7)
+    0x8058d7d2_d5e5f6b0_94d589f6_08ee4aa2, // 2^(2^-8)
+    0x802c6436_d0e04f50_ff8ce94a_6797b3ce, // 2^(2^-9)
+    0x8016302f_17467628_3690dfe4_4d11d008, // 2^(2^-10)
+    0x800b179c_82028fd0_945e54e2_ae18f2f0, // 2^(2^-11)
+    0x80058baf_7fee3b5d_1c718b38_e549cb93, // 2^(2^-12)
+    0x8002c5d0_0fdcfcb6_b6566a58_c048be1f, // 2^(2^-13)
+    0x800162e6_1bed4a48_e84c2e1a_463473da, // 2^(2^-14)
+    0x8000b172_92f702a3_aa22beac_ca949013, // 2^(2^-15)
+    0x800058b9_2abbae02_030c5fa5_256f41fe, // 2^(2^-16)
+    0x80002c5c_8dade4d7_1776c0f4_dbea67d6, // 2^(2^-17)
+    0x8000162e_44eaf636_526be456_600bdbe5, // 2^(2^-18)
+    0x80000b17_21fa7c18_8307016c_1cd4e8b7, // 2^(2^-19)
+    0x8000058b_90de7e4c_ecfc4875_03488bb2, // 2^(2^-20)
+    0x800002c5_c8678f36_cbfce50a_6de60b14, // 2^(2^-21)
+    0x80000162_e431db9f_80b2347b_5d62e516, // 2^(2^-22)
+    0x800000b1_721872d0_c7b08cf1_e0114153, // 2^(2^-23)
+    0x80000058_b90c1aa8_a5c3736c_b77e8e00, // 2^(2^-24)
+    0x8000002c_5c8605a4_635f2efc_2362d978, // 2^(2^-25)
+    0x80000016_2e4300e6_35cf4a10_9e3939bd, // 2^(2^-26)
+    0x8000000b_17217ff8_1bef9c55_1590cf83, // 2^(2^-27)
+    0x80000005_8b90bfdd_4e39cd52_c0cfa27d, // 2^(2^-28)
+    0x80000002_c5c85fe6_f72d669e_0e76e412, // 2^(2^-29)
+    0x80000001_62e42ff1_8f9ad351_86d0df28, // 2^(2^-30)
+    0x80000000_b17217f8_4cce71aa_0dcfffe8, // 2^(2^-31)
+    0x80000000_58b90bfc_07a77ad5_6ed22aaa, // 2^(2^-32)
+    0x80000000_2c5c85fd_fc23cdea_d40da8d7, // 2^(2^-33)
+    0x80000000_162e42fe_fc25eb15_71853a66, // 2^(2^-34)
+    0x80000000_0b17217f_7d97f692_baacded5, // 2^(2^-35)
+    0x80000000_058b90bf_bead3b8b_5dd254d8, // 2^(2^-36)
+    0x80000000_02c5c85f_df4eedd6_2f084e68, // 2^(2^-37)
+    0x80000000_0162e42f_efa58aef_378bf587, // 2^(2^-38)
+    0x80000000_00b17217_f7d24a78_a3c7ef03, // 2^(2^-39)
+    0x80000000_0058b90b_fbe9067c_93e474a6, // 2^(2^-40)
+    0x80000000_002c5c85_fdf47b8e_5a72599f, // 2^(2^-41)
+    0x80000000_00162e42_fefa3bdb_315934a3, // 2^(2^-42)
+    0x80000000_000b1721_7f7d1d72_99b49c46, // 2^(2^-43)
+    0x80000000_00058b90_bfbe8e9a_8d1c4ea0, // 2^(2^-44)
+    0x80000000_0002c5c8_5fdf4745_969ea76f, // 2^(2^-45)
+    0x80000000_000162e4_2fefa3a0_df5373c0, // 2^(2^-46)
+    0x80000000_0000b172_17f7d1cf_f4aac1e2, // 2^(2^-47)
+    0x80000000_000058b9_0bfbe8e7_db95a2f1, // 2^(2^-48)
+    0x80000000_00002c5c_85fdf473_e61ae1f9, // 2^(2^-49)
+    0x80000000_0000162e_42fefa39_f121751c, // 2^(2^-50)
+    0x80000000_00000b17_217f7d1c_f815bb96, // 2^(2^-51)
+    0x80000000_0000058b_90bfbe8e_7bec1e0d, // 2^(2^-52)
+    0x80000000_000002c5_c85fdf47_3dee5f17, // 2^(2^-53)
+    0x80000000_00000162_e42fefa3_9ef54390, // 2^(2^-54)
+    0x80000000_000000b1_7217f7d1_cf7a26c9, // 2^(2^-55)
+    0x80000000_00000058_b90bfbe8_e7bcf4a5, // 2^(2^-56)
+    0x80000000_0000002c_5c85fdf4_73de72a2, // 2^(2^-57)
+    0x80000000_00000016_2e42fefa_39ef3765, // 2^(2^-58)
+    0x80000000_0000000b_17217f7d_1cf79b38, // 2^(2^-59)
+    0x80000000_00000005_8b90bfbe_8e7bcd7d, // 2^(2^-60)
+    0x80000000_00000002_c5c85fdf_473de6b7, // 2^(2^-61)
+    0x80000000_00000001_62e42fef_a39ef359, // 2^(2^-62)
+    0x80000000_00000000_b17217f7_d1cf79ac, // 2^(2^-63)
+    0x80000000_00000000_58b90bfb_e8e7bcd6, // 2^(2^-64)
+];
+
+/// Base-2 logarithm of a positive Q64.64 value, as signed Q64.64.
+///
+/// Returns `None` for zero.
+pub fn log2(x: u128) -> Option<i128> {
+    let log2 = log2_extended(x)?;
+    Some(shift_right_signed(log2, LOG_FRACTION_BITS - Q64_RESOLUTION))
+}
+
+/// Natural logarithm of a positive Q64.64 value, as signed Q64.64.
+///
+/// Returns `None` for zero.
+pub fn ln(x: u128) -> Option<i128> {
+    let log2 = log2_extended(x)?;
+    // |log2| < 2^(7 + 96) and LN2 < 2^128, so the product fits in U256
+    let magnitude = (U256::from(log2.unsigned_abs()) * U256::from(LN2_Q128))
+        >> (LOG_FRACTION_BITS + 128 - Q64_RESOLUTION);
+    let magnitude = magnitude.as_u128() as i128;
+    Some(if log2 < 0 { -magnitude } else { magnitude })
+}
+
+/// e raised to a signed Q64.64 power, as Q64.64.
+///
+/// Returns `None` if the result does not fit, ie. `x > MAX_EXP_INPUT`.
+/// Results smaller than 2^-64 truncate to zero.
+pub fn exp(x: i128) -> Option<u128> {
+    // y = x * log2(e), kept at 64 fractional bits
+    let magnitude = (U256::from(x.unsigned_abs()) * U256::from(LOG2_E_Q127)) >> MANTISSA_BITS;
+    if magnitude > U256::from(i128::MAX as u128) {
+        return if x < 0 { Some(0) } else { None };
+    }
+    let magnitude = magnitude.as_u128() as i128;
+    exp2(if x < 0 { -magnitude } else { magnitude })
+}
+
+/// `base` raised to the power `exponent`, both Q64.64, as Q64.64.
+///
+/// Computed as `exp(exponent * ln(base))`, so fractional exponents are
+/// supported. `0^0` is 1 and zero raised to a negative power is `None`.
+pub fn pow(base: u128, exponent: i128) -> Option<u128> {
+    if exponent == 0 {
+        return Some(Q64);
+    }
+    if base == 0 {
+        return if exponent > 0 { Some(0) } else { None };
+    }
+    let ln_base = ln(base)?;
+    let magnitude = (U256::from(ln_base.unsigned_abs()) * U256::from(exponent.unsigned_abs()))
+        >> Q64_RESOLUTION;
+    let negative = (ln_base < 0) != (exponent < 0);
+    if magnitude > U256::from(i128::MAX as u128) {
+        return if negative { Some(0) } else { None };
+    }
+    let magnitude = magnitude.as_u128() as i128;
+    exp(if negative { -magnitude } else { magnitude })
+}
+
+/// 2 raised to a signed Q64.64 power, as Q64.64
+fn exp2(y: i128) -> Option<u128> {
+    // arithmetic shift floors, so the fraction is always in [0, 1)
+    let integer = y >> Q64_RESOLUTION;
+    let fraction = (y as u128) & (Q64 - 1);
+    if integer >= 64 {
+        return None;
+    }
+    if integer < -(Q64_RESOLUTION as i128) - 1 {
+        return Some(0);
+    }
+    // 2^fraction = product of 2^(2^-i) over the set fraction bits
+    let mut mantissa = U256::one() << MANTISSA_BITS;
+    for (i, factor) in EXP2_BITS_Q127.iter().enumerate() {
+        if fraction & (1 << (Q64_RESOLUTION as usize - 1 - i)) != 0 {
+            mantissa = (mantissa * U256::from(*factor)) >> MANTISSA_BITS;
+        }
+    }
+    // mantissa * 2^integer, converted from Q1.127 to Q64.64
+    let shift = (MANTISSA_BITS - Q64_RESOLUTION) as i128 - integer;
+    Some((mantissa >> shift as usize).as_u128())
+}
+
+/// log2 of a Q64.64 value with `LOG_FRACTION_BITS` fractional bits.
+///
+/// Uses the binary digit-by-digit method: the value is normalized into
+/// [1, 2) and repeatedly squared, each square >= 2 contributing one bit.
+fn log2_extended(x: u128) -> Option<i128> {
+    if x == 0 {
+        return None;
+    }
+    let msb = 127 - x.leading_zeros();
+    let integer = msb as i128 - Q64_RESOLUTION as i128;
+    // normalize so the leading bit sits at MANTISSA_BITS, ie. a value in [1, 2)
+    let mut mantissa = U256::from(x) << (MANTISSA_BITS - msb);
+    let two = U256::one() << (MANTISSA_BITS + 1);
+    let mut fraction: i128 = 0;
+    for bit in (0..LOG_FRACTION_BITS).rev() {
+        mantissa = (mantissa * mantissa) >> MANTISSA_BITS;
+        if mantissa >= two {
+            mantissa >>= 1;
+            fraction |= 1 << bit;
+        }
+    }
+    Some((integer << LOG_FRACTION_BITS) + fraction)
+}
+
+/// Shift a signed value right, truncating towards zero
+fn shift_right_signed(value: i128, shift: u32) -> i128 {
+    let magnitude = (value.unsigned_abs() >> shift) as i128;
+    if value < 0 { -magnitude } else { magnitude }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, proptest::prelude::*};
+
+    /// Asserts `actual` is within `factor * 2^-63` relative error plus 1 ulp
+    fn assert_within_relative(actual: u128, expected: u128, factor: u128) {
+        let tolerance = (U256::from(expected) * U256::from(factor)) >> 63;
+        let error = U256::from(actual.abs_diff(expected));
+        assert!(
+            error <= tolerance + 1,
+            "actual {actual} expected {expected} error {error}"
+        );
+    }
+
+    #[test]
+    fn ln_reference_values() {
+        // expected values are truncated, computed with 100-digit decimals
+        let tests: &[(u128, i128)] = &[
+            (9223372036854775808, -12786308645202655659),  // ln(0.5)
+            (55340232221128654848, 20265819725292939638),  // ln(3)
+            (184467440737095516160, 42475197918399869019), // ln(10)
+            (18446744073709551616000000, 254851187510399214117), // ln(1000000)
+            (18446744073709, -254851187510399765733),      // ln(0.000001)
+        ];
+        for (x, expected) in tests.iter() {
+            assert!(ln(*x).unwrap().abs_diff(*expected) <= 1);
+        }
+        assert_eq!(ln(Q64), Some(0));
+        assert_eq!(ln(0), None);
+        assert_eq!(log2(0), None);
+    }
+
+    #[test]
+    fn log_powers_of_two_are_exact() {
+        // every representable power of two, from 2^-64 up to 2^63
+        for exponent in -64i128..64 {
+            let x = 1u128 << (exponent + 64);
+            assert_eq!(log2(x), Some(exponent << 64));
+            let expected = exponent * (LN2_Q128 >> 64) as i128;
+            assert!(ln(x).unwrap().abs_diff(expected) <= 64);
+        }
+    }
+
+    #[test]
+    fn log_range_boundaries() {
+        // smallest and largest representable inputs
+        assert!(ln(1).unwrap().abs_diff(-64 * (LN2_Q128 >> 64) as i128) <= 64);
+        let max = ln(u128::MAX).unwrap();
+        assert!(max < 64 * (LN2_Q128 >> 64) as i128 + 64);
+        assert!(max > 63 * (LN2_Q128 >> 64) as i128);
+        assert!(ln(Q64 - 1).unwrap() < 0);
+        assert_eq!(ln(Q64 + 1), Some(0));
+        assert!(ln(Q64 + (1 << 20)).unwrap() > 0);
+    }
+
+    #[test]
+    fn exp_reference_values() {
+        let tests: &[(i128, u128)] = &[
+            (18446744073709551616, 50143449209799256682), // exp(1)
+            (-18446744073709551616, 6786177901268885274), // exp(-1)
+            (184467440737095516160, 406316577365116946489258), // exp(10)
+            (-368934881474191032320, 38021573369),        // exp(-20)
+            (9223372036854775808, 30413539329486470295),  // exp(0.5)
+            (
+                793209995169510719488,
+                87213244692009816622412593697761324251,
+            ), // exp(43)
+        ];
+        for (x, expected) in tests.iter() {
+            assert_within_relative(exp(*x).unwrap(), *expected, 1);
+        }
+    }
+
+    #[test]
+    fn exp_range_boundaries() {
+        assert_eq!(exp(0), Some(Q64));
+        assert!(exp(MAX_EXP_INPUT).unwrap() > u128::MAX - (u128::MAX >> 60));
+        assert_eq!(exp(MAX_EXP_INPUT + 1), None);
+        assert_eq!(exp(i128::MAX), None);
+        assert_eq!(exp(i128::MIN), Some(0));
+        // e^-45 < 2^-64 truncates to zero, e^-44 does not
+        assert_eq!(exp(-45 * Q64 as i128), Some(0));
+        assert!(exp(-44 * Q64 as i128).unwrap() > 0);
+        assert_eq!(exp(1), Some(Q64));
+        assert_eq!(exp(-1), Some(Q64 - 1));
+    }
+
+    #[test]
+    fn exp_of_multiples_of_ln2() {
+        for exponent in -64i128..64 {
+            let x = exponent * (LN2_Q128 >> 64) as i128;
+            let expected = 1u128 << (exponent + 64);
+            assert_within_relative(exp(x).unwrap(), expected, 64);
+        }
+    }
+
+    #[test]
+    fn pow_reference_values() {
+        let tests: &[(u128, i128, u128)] = &[
+            (
+                36893488147419103232,
+                9223372036854775808,
+                26087635650665564424,
+            ), // 2^0.5
+            (
+                14757395258967641292,
+                27670116110564327424,
+                13199415587953903547,
+            ), // 0.8^1.5
+            (
+                184467440737095516160,
+                -41505174165846491136,
+                103733665078570476,
+            ), // 10^-2.25
+            (
+                27670116110564327424,
+                368934881474191032320,
+                61340159879159601954815,
+            ), // 1.5^20
+        ];
+        for (base, exponent, expected) in tests.iter() {
+            let factor = (exponent.unsigned_abs() >> 64) + 3;
+            assert_within_relative(pow(*base, *exponent).unwrap(), *expected, factor);
+        }
+    }
+
+    #[test]
+    fn pow_edge_cases() {
+        let two = 2 * Q64;
+        assert_eq!(pow(two, 0), Some(Q64));
+        assert_eq!(pow(0, 0), Some(Q64));
+        assert_eq!(pow(0, Q64 as i128), Some(0));
+        assert_eq!(pow(0, -(Q64 as i128)), None);
+        assert_eq!(pow(Q64, i128::MAX), Some(Q64));
+        assert_eq!(pow(two, 65 * Q64 as i128), None);
+        assert_eq!(pow(two, i128::MIN), Some(0));
+        assert_within_relative(pow(two, -(Q64 as i128)).unwrap(), Q64 / 2, 3);
+        assert_within_relative(pow(4 * Q64, (Q64 / 2) as i128).unwrap(), two, 3);
+    }
+
+    proptest! {
+        #[test]
+        fn ln_matches_f64(x in 1..u128::MAX) {
+            let expected = (x as f64 / Q64 as f64).ln();
+            let actual = ln(x).unwrap() as f64 / Q64 as f64;
+            prop_assert!((actual - expected).abs() < 1e-9);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn exp_inverts_ln(x in Q64 / 1_000_000..1_000_000 * Q64) {
+            let round_trip = exp(ln(x).unwrap()).unwrap();
+            // ln loses at most 1 ulp, which exp scales by up to 1e6
+            let error = round_trip.abs_diff(x);
+            prop_assert!(error <= x / (1 << 40) + 1);
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! Human-readable formatting
+//!
+//! Result types implement `Display` twice over: `{}` writes a compact
+//! single line of `key=value` pairs for logs, and the alternate `{:#}` writes
+//! one labelled row per value, with thousands separators, for operators.
+use {
+    crate::{curve::calculator::TradeDirection, utils::PriceQ64},
+    core::fmt,
+};
+
+/// Formats an amount with thousands separators, as in `1,234,567`.
+///
+/// Honors the formatter's width and alignment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Grouped(pub u128);
+
+impl fmt::Display for Grouped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // 39 digits and 12 separators for u128::MAX
+        let mut buffer = [0u8; 51];
+        let mut start = buffer.len();
+        let mut amount = self.0;
+        let mut digits = 0;
+        loop {
+            if digits > 0 && digits % 3 == 0 {
+                start -= 1;
+                buffer[start] = b',';
+            }
+            start -= 1;
+            buffer[start] = b'0' + (amount % 10) as u8;
+            amount /= 10;
+            digits += 1;
+            if amount == 0 {
+                break;
+            }
+        }
+        // only ASCII digits and commas were written
+        f.pad(core::str::from_utf8(&buffer[start..]).map_err(|_| fmt::Error)?)
+    }
+}
+
+// A value in a row of `write_rows`
+pub(crate) enum Value {
+    Amount(u128),
+    Price(PriceQ64),
+    Direction(TradeDirection),
+}
+
+// Writes `rows` of `(key, value)` pairs in the format the formatter asks
+// for. The alternate format spells keys with spaces, pads them to line up
+// the values, groups amounts and follows approximate prices with their
+// exact Q64.64 value.
+pub(crate) fn write_rows(f: &mut fmt::Formatter<'_>, rows: &[(&str, Value)]) -> fmt::Result {
+    let alternate = f.alternate();
+    let width = rows.iter().map(|(key, _)| key.len()).max().unwrap_or(0);
+    for (index, (key, value)) in rows.iter().enumerate() {
+        if alternate {
+            if index > 0 {
+                f.write_str("\n")?;
+            }
+            for word in key.split('_').enumerate() {
+                match word {
+                    (0, word) => f.write_str(word)?,
+                    (_, word) => write!(f, " {word}")?,
+                }
+            }
+            write!(f, ":{:padding$} ", "", padding = width - key.len())?;
+        } else {
+            if index > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{key}=")?;
+        }
+        match *value {
+            Value::Amount(amount) if alternate => write!(f, "{}", Grouped(amount))?,
+            Value::Amount(amount) => write!(f, "{amount}")?,
+            Value::Price(price) => {
+                let approximate = price.raw() as f64 / 18_446_744_073_709_551_616.0;
+                write!(f, "{approximate:.9}")?;
+                if alternate {
+                    write!(f, " (Q64 {})", price.raw())?;
+                }
+            }
+            Value::Direction(trade_direction) => write!(f, "{trade_direction:?}")?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Rows;
+
+    impl fmt::Display for Rows {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write_rows(
+                f,
+                &[
+                    (
+                        "trade_direction",
+                        Value::Direction(TradeDirection::ZeroForOne),
+                    ),
+                    ("amount_in", Value::Amount(1_234_567)),
+                    ("price", Value::Price(PriceQ64::from_integer(4))),
+                ],
+            )
+        }
+    }
+
+    #[test]
+    fn grouped_separates_thousands() {
+        assert_eq!(Grouped(0).to_string(), "0");
+        assert_eq!(Grouped(999).to_string(), "999");
+        assert_eq!(Grouped(1_000).to_string(), "1,000");
+        assert_eq!(Grouped(1_234_567).to_string(), "1,234,567");
+        assert_eq!(
+            Grouped(u128::MAX).to_string(),
+            "340,282,366,920,938,463,463,374,607,431,768,211,455"
+        );
+        assert_eq!(format!("{:>8}", Grouped(12_345)), "  12,345");
+    }
+
+    #[test]
+    fn rows_format_compact_and_alternate() {
+        assert_eq!(
+            Rows.to_string(),
+            "trade_direction=ZeroForOne amount_in=1234567 price=4.000000000"
+        );
+        assert_eq!(
+            format!("{Rows:#}"),
+            "trade direction: ZeroForOne\n\
+             amount in:       1,234,567\n\
+             price:           4.000000000 (Q64 73786976294838206464)"
+        );
+    }
+}
@@ -1,5 +1,11 @@
-///! 128 and 256 bit numbers
-///! U128 is more efficient that u128
+//! 128, 256 and 512 bit numbers
+//! U128 is more efficient that u128
+
+// construct_uint! expands to code that trips these lints
+#![allow(clippy::assign_op_pattern)]
+#![allow(clippy::manual_div_ceil)]
+#![allow(clippy::reversed_empty_ranges)]
+
 use uint::construct_uint;
 construct_uint! {
     pub struct U128(2);
@@ -9,6 +15,10 @@ construct_uint! {
     pub struct U256(4);
 }
 
+construct_uint! {
+    pub struct U512(8);
+}
+
 pub trait CheckedCeilDiv: Sized {
     /// Perform ceiling division
     fn checked_ceil_div(&self, rhs: Self) -> Option<(Self, Self)>;
@@ -21,7 +31,7 @@ impl CheckedCeilDiv for u128 {
         // fail.
         if quotient == 0 {
             // return None;
-            if self.checked_mul(2 as u128)? >= rhs {
+            if self.checked_mul(2)? >= rhs {
                 return Some((1, 0));
             } else {
                 return Some((0, 0));
@@ -44,3 +54,62 @@ impl CheckedCeilDiv for u128 {
         Some((quotient, rhs))
     }
 }
+
+/// Unsigned integer arithmetic the curve formulas are generic over.
+///
+/// On-chain callers instantiate the formulas with `u64` or `u128` to stay
+/// within their compute budget; off-chain analytics use `U256` so
+/// intermediate products of large reserves do not overflow.
+pub trait MathUint: Copy + Ord + core::fmt::Debug {
+    const ZERO: Self;
+    const ONE: Self;
+
+    fn checked_add(self, rhs: Self) -> Option<Self>;
+    fn checked_sub(self, rhs: Self) -> Option<Self>;
+    fn checked_mul(self, rhs: Self) -> Option<Self>;
+    fn checked_div(self, rhs: Self) -> Option<Self>;
+    fn checked_rem(self, rhs: Self) -> Option<Self>;
+
+    /// Division rounded up. Returns `None` if `rhs` is zero.
+    fn checked_div_ceil(self, rhs: Self) -> Option<Self> {
+        let quotient = self.checked_div(rhs)?;
+        if self.checked_rem(rhs)? == Self::ZERO {
+            Some(quotient)
+        } else {
+            quotient.checked_add(Self::ONE)
+        }
+    }
+}
+
+macro_rules! impl_math_uint {
+    ($type:ty, $zero:expr, $one:expr) => {
+        impl MathUint for $type {
+            const ZERO: Self = $zero;
+            const ONE: Self = $one;
+
+            fn checked_add(self, rhs: Self) -> Option<Self> {
+                <$type>::checked_add(self, rhs)
+            }
+
+            fn checked_sub(self, rhs: Self) -> Option<Self> {
+                <$type>::checked_sub(self, rhs)
+            }
+
+            fn checked_mul(self, rhs: Self) -> Option<Self> {
+                <$type>::checked_mul(self, rhs)
+            }
+
+            fn checked_div(self, rhs: Self) -> Option<Self> {
+                <$type>::checked_div(self, rhs)
+            }
+
+            fn checked_rem(self, rhs: Self) -> Option<Self> {
+                <$type>::checked_rem(self, rhs)
+            }
+        }
+    };
+}
+
+impl_math_uint!(u64, 0, 1);
+impl_math_uint!(u128, 0, 1);
+impl_math_uint!(U256, U256([0; 4]), U256([1, 0, 0, 0]));
@@ -1,2 +1,9 @@
+pub mod fixed_math;
+pub mod format;
 pub mod math;
+pub mod price;
+pub mod sqrt;
+pub use format::Grouped;
 pub use math::*;
+pub use price::*;
+pub use sqrt::*;
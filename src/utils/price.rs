@@ -0,0 +1,168 @@
+//! Q64.64 fixed-point price
+//!
+//! Prices are stored as an unsigned 128-bit integer whose low 64 bits hold the
+//! fractional part. Intermediate products are computed in U256 so that
+//! multiplication and division are exact up to the final truncation.
+use crate::{curve::calculator::RoundDirection, utils::U256};
+
+/// Number of fractional bits in a Q64.64 value
+pub const Q64_RESOLUTION: u32 = 64;
+
+/// The fixed-point representation of 1.0
+pub const Q64: u128 = 1 << Q64_RESOLUTION;
+
+/// A non-negative price in Q64.64 fixed-point format.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PriceQ64(u128);
+
+impl PriceQ64 {
+    /// The price 0
+    pub const ZERO: Self = Self(0);
+    /// The price 1
+    pub const ONE: Self = Self(Q64);
+
+    /// Wrap a raw Q64.64 value
+    pub const fn from_raw(raw: u128) -> Self {
+        Self(raw)
+    }
+
+    /// The underlying Q64.64 value
+    pub const fn raw(self) -> u128 {
+        self.0
+    }
+
+    /// Convert a whole number into a price
+    pub const fn from_integer(value: u64) -> Self {
+        Self((value as u128) << Q64_RESOLUTION)
+    }
+
+    /// Price of `numerator / denominator`, rounded down.
+    ///
+    /// Returns `None` if the denominator is zero or the result does not fit in
+    /// 64 integer bits.
+    pub fn from_ratio(numerator: u128, denominator: u128) -> Option<Self> {
+        if denominator == 0 {
+            return None;
+        }
+        let raw = (U256::from(numerator) << Q64_RESOLUTION) / U256::from(denominator);
+        to_u128(raw).map(Self)
+    }
+
+    /// Spot price of token 0 quoted in token 1, ie. `reserve_1 / reserve_0`
+    pub fn from_reserves(reserve_0: u128, reserve_1: u128) -> Option<Self> {
+        Self::from_ratio(reserve_1, reserve_0)
+    }
+
+    /// Multiply two prices, rounding down
+    pub fn checked_mul(self, rhs: Self) -> Option<Self> {
+        let raw = (U256::from(self.0) * U256::from(rhs.0)) >> Q64_RESOLUTION;
+        to_u128(raw).map(Self)
+    }
+
+    /// Divide two prices, rounding down
+    pub fn checked_div(self, rhs: Self) -> Option<Self> {
+        Self::from_ratio(self.0, rhs.0)
+    }
+
+    /// The inverse price `1 / self`, rounding down.
+    ///
+    /// Converts a token 0 price in token 1 into a token 1 price in token 0.
+    pub fn reciprocal(self) -> Option<Self> {
+        Self::ONE.checked_div(self)
+    }
+
+    /// Convert an amount of the base token into the quote token at this price
+    pub fn mul_amount(self, amount: u128, round_direction: RoundDirection) -> Option<u128> {
+        let product = U256::from(amount) * U256::from(self.0);
+        let mut quotient = product >> Q64_RESOLUTION;
+        if round_direction == RoundDirection::Ceiling && !(product % U256::from(Q64)).is_zero() {
+            quotient = quotient + 1;
+        }
+        to_u128(quotient)
+    }
+
+    /// Convert an amount of the quote token into the base token at this price
+    pub fn div_amount(self, amount: u128, round_direction: RoundDirection) -> Option<u128> {
+        if self.0 == 0 {
+            return None;
+        }
+        let numerator = U256::from(amount) << Q64_RESOLUTION;
+        let denominator = U256::from(self.0);
+        let mut quotient = numerator / denominator;
+        if round_direction == RoundDirection::Ceiling && !(numerator % denominator).is_zero() {
+            quotient = quotient + 1;
+        }
+        to_u128(quotient)
+    }
+
+    /// The integer part of the price, rounded down
+    pub const fn to_integer_floor(self) -> u128 {
+        self.0 >> Q64_RESOLUTION
+    }
+}
+
+pub(crate) fn to_u128(value: U256) -> Option<u128> {
+    if value > U256::from(u128::MAX) {
+        None
+    } else {
+        Some(value.as_u128())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, proptest::prelude::*};
+
+    #[test]
+    fn price_from_reserves() {
+        let price = PriceQ64::from_reserves(100, 250).unwrap();
+        assert_eq!(price.raw(), 5 * Q64 / 2);
+        assert_eq!(price.to_integer_floor(), 2);
+        assert_eq!(PriceQ64::from_reserves(0, 250), None);
+        assert_eq!(PriceQ64::from_reserves(1, u128::MAX), None);
+        assert_eq!(PriceQ64::from_reserves(10, 0), Some(PriceQ64::ZERO));
+    }
+
+    #[test]
+    fn price_arithmetic() {
+        let two = PriceQ64::from_integer(2);
+        let three = PriceQ64::from_integer(3);
+        assert_eq!(two.checked_mul(three), Some(PriceQ64::from_integer(6)));
+        assert_eq!(three.checked_div(two), Some(PriceQ64::from_raw(3 * Q64 / 2)));
+        assert_eq!(two.reciprocal(), Some(PriceQ64::from_raw(Q64 / 2)));
+        assert_eq!(PriceQ64::ZERO.reciprocal(), None);
+        let max = PriceQ64::from_raw(u128::MAX);
+        assert_eq!(max.checked_mul(two), None);
+    }
+
+    #[test]
+    fn price_amount_rounding() {
+        let price = PriceQ64::from_reserves(3, 1).unwrap();
+        assert_eq!(price.mul_amount(10, RoundDirection::Floor), Some(3));
+        assert_eq!(price.mul_amount(10, RoundDirection::Ceiling), Some(4));
+        let price = PriceQ64::from_integer(3);
+        assert_eq!(price.div_amount(10, RoundDirection::Floor), Some(3));
+        assert_eq!(price.div_amount(10, RoundDirection::Ceiling), Some(4));
+        assert_eq!(price.div_amount(9, RoundDirection::Ceiling), Some(3));
+        assert_eq!(PriceQ64::ZERO.div_amount(9, RoundDirection::Floor), None);
+    }
+
+    proptest! {
+        #[test]
+        fn reserve_price_brackets_ratio(
+            reserve_0 in 1..u64::MAX,
+            reserve_1 in 1..u64::MAX,
+            amount in 0..u64::MAX,
+        ) {
+            let price = PriceQ64::from_reserves(reserve_0 as u128, reserve_1 as u128).unwrap();
+            let exact = (amount as u128) * (reserve_1 as u128) / (reserve_0 as u128);
+            let floor = price.mul_amount(amount as u128, RoundDirection::Floor).unwrap();
+            let ceil = price.mul_amount(amount as u128, RoundDirection::Ceiling).unwrap();
+            // the price itself is truncated, so converting never overshoots
+            // the exact ratio and is off by at most one unit
+            prop_assert!(floor <= exact);
+            prop_assert!(exact - floor <= 1);
+            prop_assert!(ceil >= floor && ceil - floor <= 1);
+        }
+    }
+}
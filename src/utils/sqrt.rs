@@ -0,0 +1,173 @@
+//! Integer square roots for u128, U256 and U512
+//!
+//! Uses Newton's (Babylonian) method, which converges quadratically and only
+//! needs integer division, so results are exact and deterministic on-chain.
+use crate::utils::{U256, U512};
+
+pub trait IntegerSquareRoot: Sized {
+    /// Largest integer `r` such that `r * r <= self`
+    fn sqrt_floor(&self) -> Self;
+    /// Smallest integer `r` such that `r * r >= self`
+    fn sqrt_ceil(&self) -> Self;
+}
+
+impl IntegerSquareRoot for u128 {
+    fn sqrt_floor(&self) -> Self {
+        let value = *self;
+        if value < 2 {
+            return value;
+        }
+        // Start from a power of two that is guaranteed to be >= sqrt(value),
+        // so the sequence decreases monotonically towards the floor.
+        let bits = 128 - value.leading_zeros();
+        let mut x = 1u128 << bits.div_ceil(2);
+        loop {
+            let y = (x + value / x) >> 1;
+            if y >= x {
+                return x;
+            }
+            x = y;
+        }
+    }
+
+    fn sqrt_ceil(&self) -> Self {
+        let root = self.sqrt_floor();
+        // root * root <= self, so this cannot overflow
+        if root * root < *self {
+            root + 1
+        } else {
+            root
+        }
+    }
+}
+
+macro_rules! impl_integer_square_root {
+    ($($wide:ty),*) => {$(
+        impl IntegerSquareRoot for $wide {
+            fn sqrt_floor(&self) -> Self {
+                let value = *self;
+                if value < <$wide>::from(2) {
+                    return value;
+                }
+                let bits = value.bits();
+                let mut x = <$wide>::one() << bits.div_ceil(2);
+                loop {
+                    let y = (x + value / x) >> 1;
+                    if y >= x {
+                        return x;
+                    }
+                    x = y;
+                }
+            }
+
+            fn sqrt_ceil(&self) -> Self {
+                let root = self.sqrt_floor();
+                if root * root < *self {
+                    root + <$wide>::one()
+                } else {
+                    root
+                }
+            }
+        }
+    )*};
+}
+
+impl_integer_square_root!(U256, U512);
+
+#[cfg(test)]
+mod tests {
+    use {super::*, proptest::prelude::*, spl_math::precise_number::PreciseNumber};
+
+    fn check_u128_bounds(value: u128) {
+        let floor = value.sqrt_floor();
+        let ceil = value.sqrt_ceil();
+        assert!(floor * floor <= value);
+        // (floor + 1)^2 may not fit in u128 near the top of the range
+        let next = U256::from(floor + 1);
+        assert!(next * next > U256::from(value));
+        assert!(U256::from(ceil) * U256::from(ceil) >= U256::from(value));
+        assert!(ceil - floor <= 1);
+    }
+
+    #[test]
+    fn sqrt_small_values() {
+        let expected: &[(u128, u128, u128)] = &[
+            (0, 0, 0),
+            (1, 1, 1),
+            (2, 1, 2),
+            (3, 1, 2),
+            (4, 2, 2),
+            (5, 2, 3),
+            (8, 2, 3),
+            (9, 3, 3),
+            (10, 3, 4),
+        ];
+        for (value, floor, ceil) in expected.iter() {
+            assert_eq!(value.sqrt_floor(), *floor);
+            assert_eq!(value.sqrt_ceil(), *ceil);
+        }
+        for value in 0..10_000u128 {
+            check_u128_bounds(value);
+        }
+    }
+
+    #[test]
+    fn sqrt_u128_boundaries() {
+        assert_eq!(u128::MAX.sqrt_floor(), u64::MAX as u128);
+        assert_eq!(u128::MAX.sqrt_ceil(), 1u128 << 64);
+        let square = (u64::MAX as u128) * (u64::MAX as u128);
+        assert_eq!(square.sqrt_floor(), u64::MAX as u128);
+        assert_eq!(square.sqrt_ceil(), u64::MAX as u128);
+        check_u128_bounds(square - 1);
+        check_u128_bounds(square + 1);
+        check_u128_bounds(u128::MAX);
+    }
+
+    #[test]
+    fn sqrt_u256_boundaries() {
+        assert_eq!(U256::MAX.sqrt_floor(), U256::from(u128::MAX));
+        assert_eq!(U256::MAX.sqrt_ceil(), U256::one() << 128);
+        let square = U256::from(u128::MAX) * U256::from(u128::MAX);
+        assert_eq!(square.sqrt_floor(), U256::from(u128::MAX));
+        assert_eq!((square + 1).sqrt_ceil(), U256::one() << 128);
+        assert_eq!(U256::zero().sqrt_ceil(), U256::zero());
+    }
+
+    proptest! {
+        #[test]
+        fn sqrt_u128_is_exact(value in any::<u128>()) {
+            check_u128_bounds(value);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn sqrt_u256_matches_u128(value in any::<u128>()) {
+            prop_assert_eq!(U256::from(value).sqrt_floor(), U256::from(value.sqrt_floor()));
+            prop_assert_eq!(U256::from(value).sqrt_ceil(), U256::from(value.sqrt_ceil()));
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn sqrt_u512_matches_u256(high in any::<u128>(), low in any::<u128>()) {
+            let value = (U256::from(high) << 128) | U256::from(low);
+            let wide = (U512::from(high) << 128) | U512::from(low);
+            prop_assert_eq!(wide.sqrt_floor(), U512::from(value.sqrt_floor().as_u128()));
+            prop_assert_eq!(wide.sqrt_ceil(), U512::from(value.sqrt_ceil().as_u128()));
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn sqrt_matches_precise_number(value in 0..u64::MAX) {
+            let value = value as u128;
+            let expected = PreciseNumber::new(value).unwrap().sqrt().unwrap();
+            // PreciseNumber::sqrt is an approximation rounded to the nearest
+            // integer, so it may land on either side of the exact floor
+            let approximation = expected.to_imprecise().unwrap();
+            prop_assert!(approximation.abs_diff(value.sqrt_floor()) <= 1);
+            prop_assert!(approximation.abs_diff(value.sqrt_ceil()) <= 1);
+        }
+    }
+}
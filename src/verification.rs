@@ -0,0 +1,90 @@
+//! Formal verification harnesses
+//!
+//! The properties auditors care about most, written as plain functions that
+//! panic when violated. Under `cargo kani --features verification` the
+//! harnesses below check them for every input in the bounded domain; the same
+//! functions can be called from tests with concrete values.
+//!
+//! Inputs are bounded to u64, the size of on-chain token amounts, which keeps
+//! the proofs tractable while covering every amount a pool can hold.
+use crate::{
+    curve::{
+        constant_product::ConstantProductCurve,
+        fees::{FEE_RATE_DENOMINATOR_VALUE, Fees},
+    },
+    utils::U256,
+};
+
+/// `try_swap_base_input_without_fees` never pays out more than the invariant
+/// allows, ie. `(x + dx) * (y - dy) >= x * y`.
+pub fn check_swap_base_input_preserves_invariant(
+    source_amount: u64,
+    swap_source_amount: u64,
+    swap_destination_amount: u64,
+) {
+    let (dx, x, y) = (
+        u128::from(source_amount),
+        u128::from(swap_source_amount),
+        u128::from(swap_destination_amount),
+    );
+    if x + dx == 0 {
+        return;
+    }
+    let dy = ConstantProductCurve::try_swap_base_input_without_fees(dx, x, y).unwrap();
+    assert!(dy <= y);
+    let before = U256::from(x) * U256::from(y);
+    let after = U256::from(x + dx) * U256::from(y - dy);
+    assert!(after >= before);
+}
+
+/// The ceiling-divided trading fee never exceeds the amount it is charged
+/// on, and the protocol's share never exceeds the trading fee.
+pub fn check_fees_bounded_by_principal(amount: u64, trade_fee_rate: u64, protocol_fee_rate: u64) {
+    if trade_fee_rate > FEE_RATE_DENOMINATOR_VALUE || protocol_fee_rate > FEE_RATE_DENOMINATOR_VALUE
+    {
+        return;
+    }
+    let amount = u128::from(amount);
+    let trade_fee = Fees::trading_fee(amount, trade_fee_rate).unwrap();
+    assert!(trade_fee <= amount);
+    let protocol_fee = Fees::protocol_fee(trade_fee, protocol_fee_rate).unwrap();
+    assert!(protocol_fee <= trade_fee);
+}
+
+#[cfg(kani)]
+mod harnesses {
+    use super::*;
+
+    #[kani::proof]
+    fn swap_base_input_preserves_invariant() {
+        check_swap_base_input_preserves_invariant(kani::any(), kani::any(), kani::any());
+    }
+
+    #[kani::proof]
+    fn fees_bounded_by_principal() {
+        check_fees_bounded_by_principal(kani::any(), kani::any(), kani::any());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, proptest::prelude::*};
+
+    proptest! {
+        #[test]
+        fn properties_hold_on_samples(
+            amount in any::<u64>(),
+            swap_source_amount in any::<u64>(),
+            swap_destination_amount in any::<u64>(),
+            trade_fee_rate in 0..=FEE_RATE_DENOMINATOR_VALUE,
+            protocol_fee_rate in 0..=FEE_RATE_DENOMINATOR_VALUE,
+        ) {
+            check_swap_base_input_preserves_invariant(
+                amount,
+                swap_source_amount,
+                swap_destination_amount,
+            );
+            check_fees_bounded_by_principal(amount, trade_fee_rate, protocol_fee_rate);
+        }
+    }
+}
@@ -0,0 +1,286 @@
+//! WebAssembly bindings for browser quoting
+//!
+//! Token amounts cross the boundary as decimal strings, since JavaScript
+//! numbers cannot represent every u64 exactly. Inputs must fit in a u64, like
+//! on-chain token amounts; outputs are decimal strings of the exact results.
+//! Fee rates use the same `FEE_RATE_DENOMINATOR_VALUE` scale as the Rust API.
+use {
+    crate::curve::{
+        calculator::{CurveCalculator, RoundDirection, SwapResult, TradingTokenResult},
+        slippage,
+        swap_params::SwapParams,
+    },
+    wasm_bindgen::prelude::*,
+};
+
+/// Results of a swap quote
+#[wasm_bindgen(getter_with_clone)]
+pub struct WasmSwapResult {
+    /// New amount of source token in the pool
+    #[wasm_bindgen(js_name = newSwapSourceAmount)]
+    pub new_swap_source_amount: String,
+    /// New amount of destination token in the pool
+    #[wasm_bindgen(js_name = newSwapDestinationAmount)]
+    pub new_swap_destination_amount: String,
+    /// Amount of source token swapped (includes fees)
+    #[wasm_bindgen(js_name = sourceAmountSwapped)]
+    pub source_amount_swapped: String,
+    /// Amount of destination token swapped
+    #[wasm_bindgen(js_name = destinationAmountSwapped)]
+    pub destination_amount_swapped: String,
+    /// Amount of source tokens going to pool holders
+    #[wasm_bindgen(js_name = tradeFee)]
+    pub trade_fee: String,
+    /// Amount of source tokens going to protocol
+    #[wasm_bindgen(js_name = protocolFee)]
+    pub protocol_fee: String,
+    /// Amount of destination tokens withheld from the output as fees
+    #[wasm_bindgen(js_name = destinationTradeFee)]
+    pub destination_trade_fee: String,
+    /// Amount of destination tokens going to protocol
+    #[wasm_bindgen(js_name = destinationProtocolFee)]
+    pub destination_protocol_fee: String,
+}
+
+impl From<SwapResult> for WasmSwapResult {
+    fn from(result: SwapResult) -> Self {
+        Self {
+            new_swap_source_amount: result.new_swap_source_amount.to_string(),
+            new_swap_destination_amount: result.new_swap_destination_amount.to_string(),
+            source_amount_swapped: result.source_amount_swapped.to_string(),
+            destination_amount_swapped: result.destination_amount_swapped.to_string(),
+            trade_fee: result.trade_fee.to_string(),
+            protocol_fee: result.protocol_fee.to_string(),
+            destination_trade_fee: result.destination_trade_fee.to_string(),
+            destination_protocol_fee: result.destination_protocol_fee.to_string(),
+        }
+    }
+}
+
+/// Token amounts for a given amount of LP tokens
+#[wasm_bindgen(getter_with_clone)]
+pub struct WasmTradingTokenResult {
+    /// Amount of token 0
+    #[wasm_bindgen(js_name = token0Amount)]
+    pub token_0_amount: String,
+    /// Amount of token 1
+    #[wasm_bindgen(js_name = token1Amount)]
+    pub token_1_amount: String,
+}
+
+impl From<TradingTokenResult> for WasmTradingTokenResult {
+    fn from(result: TradingTokenResult) -> Self {
+        Self {
+            token_0_amount: result.token_0_amount.to_string(),
+            token_1_amount: result.token_1_amount.to_string(),
+        }
+    }
+}
+
+/// Quote an exact-input swap
+#[wasm_bindgen(js_name = swapBaseInput)]
+pub fn swap_base_input(
+    source_amount: &str,
+    swap_source_amount: &str,
+    swap_destination_amount: &str,
+    trade_fee_rate: u32,
+    protocol_fee_rate: u32,
+) -> Result<WasmSwapResult, JsError> {
+    quote_base_input(
+        source_amount,
+        swap_source_amount,
+        swap_destination_amount,
+        trade_fee_rate,
+        protocol_fee_rate,
+    )
+    .map_err(|e| JsError::new(&e))
+}
+
+/// Quote an exact-output swap
+#[wasm_bindgen(js_name = swapBaseOutput)]
+pub fn swap_base_output(
+    destination_amount: &str,
+    swap_source_amount: &str,
+    swap_destination_amount: &str,
+    trade_fee_rate: u32,
+    protocol_fee_rate: u32,
+) -> Result<WasmSwapResult, JsError> {
+    quote_base_output(
+        destination_amount,
+        swap_source_amount,
+        swap_destination_amount,
+        trade_fee_rate,
+        protocol_fee_rate,
+    )
+    .map_err(|e| JsError::new(&e))
+}
+
+/// Token amounts for depositing (`round_up = true`) or withdrawing
+/// (`round_up = false`) the given amount of LP tokens
+#[wasm_bindgen(js_name = lpTokensToTradingTokens)]
+pub fn lp_tokens_to_trading_tokens(
+    lp_token_amount: &str,
+    lp_token_supply: &str,
+    swap_token_0_amount: &str,
+    swap_token_1_amount: &str,
+    round_up: bool,
+) -> Result<WasmTradingTokenResult, JsError> {
+    convert_lp_tokens(
+        lp_token_amount,
+        lp_token_supply,
+        swap_token_0_amount,
+        swap_token_1_amount,
+        round_up,
+    )
+    .map_err(|e| JsError::new(&e))
+}
+
+/// Smallest acceptable output for an exact-input quote
+#[wasm_bindgen(js_name = minimumAmountOut)]
+pub fn minimum_amount_out(amount_out: &str, slippage_bps: u16) -> Result<String, JsError> {
+    let amount_out = parse_amount("amount_out", amount_out).map_err(|e| JsError::new(&e))?;
+    slippage::minimum_amount_out(amount_out, slippage_bps)
+        .map(|amount| amount.to_string())
+        .ok_or_else(|| JsError::new("slippage_bps exceeds 10000"))
+}
+
+/// Largest acceptable input for an exact-output quote
+#[wasm_bindgen(js_name = maximumAmountIn)]
+pub fn maximum_amount_in(amount_in: &str, slippage_bps: u16) -> Result<String, JsError> {
+    let amount_in = parse_amount("amount_in", amount_in).map_err(|e| JsError::new(&e))?;
+    slippage::maximum_amount_in(amount_in, slippage_bps)
+        .map(|amount| amount.to_string())
+        .ok_or_else(|| JsError::new("maximum amount in overflows"))
+}
+
+// The functions below hold the logic behind the bindings with plain string
+// errors, since `JsError` can only be constructed on wasm targets.
+
+fn parse_amount(name: &str, value: &str) -> Result<u128, String> {
+    value
+        .parse::<u64>()
+        .map(u128::from)
+        .map_err(|_| format!("{name} is not a u64 amount: {value:?}"))
+}
+
+fn quote_base_input(
+    source_amount: &str,
+    swap_source_amount: &str,
+    swap_destination_amount: &str,
+    trade_fee_rate: u32,
+    protocol_fee_rate: u32,
+) -> Result<WasmSwapResult, String> {
+    let source_amount = parse_amount("source_amount", source_amount)?;
+    let swap_source_amount = parse_amount("swap_source_amount", swap_source_amount)?;
+    let swap_destination_amount = parse_amount("swap_destination_amount", swap_destination_amount)?;
+    if swap_source_amount == 0 && source_amount == 0 {
+        return Err("pool has no source liquidity".to_string());
+    }
+    SwapParams::from_raw_parts(
+        source_amount,
+        swap_source_amount,
+        swap_destination_amount,
+        u64::from(trade_fee_rate),
+        u64::from(protocol_fee_rate),
+    )
+    .and_then(|params| CurveCalculator::swap_base_input(&params))
+    .map(WasmSwapResult::from)
+    .ok_or_else(|| "swap cannot be computed".to_string())
+}
+
+fn quote_base_output(
+    destination_amount: &str,
+    swap_source_amount: &str,
+    swap_destination_amount: &str,
+    trade_fee_rate: u32,
+    protocol_fee_rate: u32,
+) -> Result<WasmSwapResult, String> {
+    let destination_amount = parse_amount("destination_amount", destination_amount)?;
+    let swap_source_amount = parse_amount("swap_source_amount", swap_source_amount)?;
+    let swap_destination_amount = parse_amount("swap_destination_amount", swap_destination_amount)?;
+    // the strict calculator panics on these, which would abort the wasm
+    // instance instead of surfacing an error to JavaScript
+    if destination_amount >= swap_destination_amount {
+        return Err("destination_amount must be less than the pool reserve".to_string());
+    }
+    if u64::from(trade_fee_rate) >= crate::curve::fees::FEE_RATE_DENOMINATOR_VALUE {
+        return Err("trade_fee_rate must be below 100%".to_string());
+    }
+    SwapParams::from_raw_parts(
+        destination_amount,
+        swap_source_amount,
+        swap_destination_amount,
+        u64::from(trade_fee_rate),
+        u64::from(protocol_fee_rate),
+    )
+    .and_then(|params| CurveCalculator::swap_base_output(&params))
+    .map(WasmSwapResult::from)
+    .ok_or_else(|| "swap cannot be computed".to_string())
+}
+
+fn convert_lp_tokens(
+    lp_token_amount: &str,
+    lp_token_supply: &str,
+    swap_token_0_amount: &str,
+    swap_token_1_amount: &str,
+    round_up: bool,
+) -> Result<WasmTradingTokenResult, String> {
+    let round_direction = if round_up {
+        RoundDirection::Ceiling
+    } else {
+        RoundDirection::Floor
+    };
+    CurveCalculator::lp_tokens_to_trading_tokens(
+        parse_amount("lp_token_amount", lp_token_amount)?,
+        parse_amount("lp_token_supply", lp_token_supply)?,
+        parse_amount("swap_token_0_amount", swap_token_0_amount)?,
+        parse_amount("swap_token_1_amount", swap_token_1_amount)?,
+        round_direction,
+    )
+    .map(WasmTradingTokenResult::from)
+    .ok_or_else(|| "LP token conversion cannot be computed".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn string_amounts_round_trip() {
+        let result = quote_base_input("1000", "50000", "80000", 2_500, 120_000).unwrap();
+        let expected = CurveCalculator::swap_base_input(
+            &SwapParams::from_raw_parts(1_000, 50_000, 80_000, 2_500, 120_000).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            result.destination_amount_swapped,
+            expected.destination_amount_swapped.to_string()
+        );
+        assert_eq!(result.trade_fee, expected.trade_fee.to_string());
+
+        let max = u64::MAX.to_string();
+        let result = quote_base_input(&max, &max, &max, 0, 0).unwrap();
+        assert_eq!(
+            result.new_swap_source_amount,
+            (2 * u64::MAX as u128).to_string()
+        );
+    }
+
+    #[test]
+    fn invalid_inputs_are_errors() {
+        assert!(quote_base_input("-1", "1", "1", 0, 0).is_err());
+        assert!(quote_base_input("18446744073709551616", "1", "1", 0, 0).is_err());
+        assert!(quote_base_input("1.5", "1", "1", 0, 0).is_err());
+        assert!(quote_base_output("100", "100", "100", 0, 0).is_err());
+        assert!(quote_base_output("10", "100", "100", 1_000_000, 0).is_err());
+        assert!(convert_lp_tokens("1", "0", "1", "1", false).is_err());
+    }
+
+    #[test]
+    fn lp_conversion_rounding() {
+        let floor = convert_lp_tokens("5", "10", "2", "49", false).unwrap();
+        assert_eq!(floor.token_1_amount, "24");
+        let ceiling = convert_lp_tokens("5", "10", "2", "49", true).unwrap();
+        assert_eq!(ceiling.token_1_amount, "25");
+    }
+}